@@ -22,6 +22,8 @@
 //! - Upperlayer stacks (fs, net, display)
 //!     - `fs`: Enable file system support.
 //!     - `myfs`: Allow users to define their custom filesystems to override the default.
+//!     - `fs-writeback`: Write back dirty file pages and disk buffers periodically
+//!       in a background task (implies `fs`, `multitask` and `irq`).
 //!     - `net`: Enable networking support.
 //!     - `display`: Enable graphics support.
 //! - Device drivers
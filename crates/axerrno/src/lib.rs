@@ -89,6 +89,29 @@ pub enum AxError {
     Interrupted,
     /// Syscall timed out
     Timeout,
+    /// The operation is not permitted on this object, e.g. writing to an
+    /// immutable file.
+    OperationNotPermitted,
+    /// The filesystem is mounted read-only.
+    ReadOnlyFilesystem,
+    /// The device or filesystem type does not exist.
+    NoSuchDevice,
+    /// The device exists but is not ready, e.g. an unbound loop device.
+    NoDeviceOrAddress,
+    /// The operation would move a node to another mounted filesystem.
+    CrossesDevices,
+    /// Too many symbolic links were encountered while resolving a path.
+    FilesystemLoop,
+    /// The node already has the maximum number of hard links.
+    TooManyLinks,
+    /// The file is not a terminal, or not the controlling terminal of the
+    /// caller.
+    NotATty,
+    /// Waiting for the resource would deadlock.
+    Deadlock,
+    /// The file cannot be locked, e.g. on a filesystem without stable file
+    /// identities.
+    NoLocksAvailable,
 }
 
 /// A specialized [`Result`] type with [`AxError`] as the error type.
@@ -216,6 +239,16 @@ impl AxError {
             WriteZero => "Write zero",
             Interrupted => "Interrupted",
             Timeout => "Timeout",
+            OperationNotPermitted => "Operation not permitted",
+            ReadOnlyFilesystem => "Read-only filesystem",
+            NoSuchDevice => "No such device",
+            NoDeviceOrAddress => "No such device or address",
+            CrossesDevices => "Cross-device link",
+            FilesystemLoop => "Too many levels of symbolic links",
+            TooManyLinks => "Too many links",
+            NotATty => "Inappropriate ioctl for device",
+            Deadlock => "Resource deadlock avoided",
+            NoLocksAvailable => "No locks available",
         }
     }
 
@@ -269,6 +302,16 @@ impl From<AxError> for LinuxError {
             WouldBlock => LinuxError::EAGAIN,
             Interrupted => LinuxError::EINTR,
             Timeout => LinuxError::ETIME,
+            OperationNotPermitted => LinuxError::EPERM,
+            ReadOnlyFilesystem => LinuxError::EROFS,
+            NoSuchDevice => LinuxError::ENODEV,
+            NoDeviceOrAddress => LinuxError::ENXIO,
+            CrossesDevices => LinuxError::EXDEV,
+            FilesystemLoop => LinuxError::ELOOP,
+            TooManyLinks => LinuxError::EMLINK,
+            NotATty => LinuxError::ENOTTY,
+            Deadlock => LinuxError::EDEADLK,
+            NoLocksAvailable => LinuxError::ENOLCK,
         }
     }
 }
@@ -1,4 +1,5 @@
 use alloc::collections::BTreeMap;
+use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
 use axfs_vfs::{VfsError, VfsResult};
@@ -8,14 +9,16 @@ use spin::RwLock;
 ///
 /// It implements [`axfs_vfs::VfsNodeOps`].
 pub struct DirNode {
+    this: Weak<DirNode>,
     parent: RwLock<Weak<dyn VfsNodeOps>>,
-    children: RwLock<BTreeMap<&'static str, VfsNodeRef>>,
+    children: RwLock<BTreeMap<String, VfsNodeRef>>,
 }
 
 impl DirNode {
     pub(super) fn new(parent: Option<&VfsNodeRef>) -> Arc<Self> {
         let parent = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
-        Arc::new(Self {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
             parent: RwLock::new(parent),
             children: RwLock::new(BTreeMap::new()),
         })
@@ -26,16 +29,34 @@ impl DirNode {
     }
 
     /// Create a subdirectory at this directory.
-    pub fn mkdir(self: &Arc<Self>, name: &'static str) -> Arc<Self> {
+    pub fn mkdir(self: &Arc<Self>, name: &str) -> Arc<Self> {
         let parent = self.clone() as VfsNodeRef;
         let node = Self::new(Some(&parent));
-        self.children.write().insert(name, node.clone());
+        self.children.write().insert(name.into(), node.clone());
         node
     }
 
     /// Add a node to this directory.
-    pub fn add(&self, name: &'static str, node: VfsNodeRef) {
-        self.children.write().insert(name, node);
+    pub fn add(&self, name: &str, node: VfsNodeRef) {
+        self.children.write().insert(name.into(), node);
+    }
+
+    /// Returns the subdirectory `name`, creating it if it does not exist.
+    pub(super) fn get_or_mkdir(self: &Arc<Self>, name: &str) -> VfsResult<Arc<Self>> {
+        let child = self.children.read().get(name).cloned();
+        match child {
+            Some(node) => node
+                .as_any()
+                .downcast_ref::<Self>()
+                .and_then(|dir| dir.this.upgrade())
+                .ok_or(VfsError::NotADirectory),
+            None => Ok(self.mkdir(name)),
+        }
+    }
+
+    /// Removes the entry `name` from this directory.
+    pub(super) fn remove_entry(&self, name: &str) -> VfsResult<VfsNodeRef> {
+        self.children.write().remove(name).ok_or(VfsError::NotFound)
     }
 }
 
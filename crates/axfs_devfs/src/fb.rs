@@ -0,0 +1,64 @@
+use axfs_vfs::{DeviceId, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+
+/// A frame buffer device behaves like `/dev/fb0`.
+///
+/// Reads and writes access the pixels of the frame buffer directly.
+pub struct FrameBufferDev {
+    base: usize,
+    size: usize,
+}
+
+impl FrameBufferDev {
+    /// Create a frame buffer device over the `size` bytes at `base`.
+    ///
+    /// # Safety
+    ///
+    /// The memory must stay mapped and valid for reads and writes as long
+    /// as the device exists.
+    pub const unsafe fn new(base: usize, size: usize) -> Self {
+        Self { base, size }
+    }
+
+    fn range(&self, offset: u64, len: usize) -> (usize, usize) {
+        let start = (offset as usize).min(self.size);
+        let end = start.saturating_add(len).min(self.size);
+        (start, end - start)
+    }
+}
+
+impl VfsNodeOps for FrameBufferDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::CharDevice,
+            self.size as _,
+            0,
+        );
+        attr.set_rdev(DeviceId::new(29, 0));
+        Ok(attr)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let (start, len) = self.range(offset, buf.len());
+        // SAFETY: `start + len` does not exceed the size given in `new`.
+        unsafe {
+            core::ptr::copy_nonoverlapping((self.base + start) as *const u8, buf.as_mut_ptr(), len)
+        };
+        Ok(len)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let (start, len) = self.range(offset, buf.len());
+        // SAFETY: `start + len` does not exceed the size given in `new`.
+        unsafe {
+            core::ptr::copy_nonoverlapping(buf.as_ptr(), (self.base + start) as *mut u8, len)
+        };
+        Ok(len)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
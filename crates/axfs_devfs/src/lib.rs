@@ -7,24 +7,36 @@
 extern crate alloc;
 
 mod dir;
+mod fb;
+mod full;
 mod null;
 mod random;
+mod rtc;
 #[cfg(test)]
 mod tests;
 mod zero;
 pub use self::dir::DirNode;
 
+pub use self::fb::FrameBufferDev;
+pub use self::full::FullDev;
 pub use self::null::NullDev;
 pub use self::random::RandomDev;
+pub use self::rtc::{RtcDev, RtcTime};
 pub use self::zero::ZeroDev;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
-use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult};
-use spin::once::Once;
+use axfs_vfs::{DeviceId, VfsError, VfsNodeOps, VfsNodeRef, VfsOps, VfsResult};
+use spin::{once::Once, RwLock};
 
 /// A device filesystem that implements [`axfs_vfs::VfsOps`].
+///
+/// Besides the directory tree, it keeps a registry of the character and
+/// block devices indexed by their device numbers, so that device nodes
+/// created on other filesystems can be resolved with [`find`](Self::find).
 pub struct DeviceFileSystem {
     parent: Once<VfsNodeRef>,
     root: Arc<DirNode>,
+    devices: RwLock<BTreeMap<DeviceId, VfsNodeRef>>,
 }
 
 impl DeviceFileSystem {
@@ -33,20 +45,77 @@ impl DeviceFileSystem {
         Self {
             parent: Once::new(),
             root: DirNode::new(None),
+            devices: RwLock::new(BTreeMap::new()),
         }
     }
 
     /// Create a subdirectory at the root directory.
-    pub fn mkdir(&self, name: &'static str) -> Arc<DirNode> {
+    pub fn mkdir(&self, name: &str) -> Arc<DirNode> {
         self.root.mkdir(name)
     }
 
     /// Add a node to the root directory.
     ///
     /// The node must implement [`axfs_vfs::VfsNodeOps`], and be wrapped in [`Arc`].
-    pub fn add(&self, name: &'static str, node: VfsNodeRef) {
+    pub fn add(&self, name: &str, node: VfsNodeRef) {
         self.root.add(name, node);
     }
+
+    /// Publish `node` at `path` relative to the root directory, creating
+    /// the missing intermediate directories.
+    ///
+    /// A character or block device with a non-zero device number is also
+    /// added to the registry, replacing the previous device with the same
+    /// number.
+    pub fn register(&self, path: &str, node: VfsNodeRef) -> VfsResult {
+        let (dir, name) = self.parent_dir(path)?;
+        if dir.clone().lookup(name).is_ok() {
+            return Err(VfsError::AlreadyExists);
+        }
+        let attr = node.get_attr()?;
+        let ty = attr.file_type();
+        if (ty.is_char_device() || ty.is_block_device()) && attr.rdev() != DeviceId::default() {
+            self.devices.write().insert(attr.rdev(), node.clone());
+        }
+        dir.add(name, node);
+        Ok(())
+    }
+
+    /// Remove the node at `path` published by [`register`](Self::register),
+    /// and the device it refers to from the registry.
+    pub fn unregister(&self, path: &str) -> VfsResult {
+        let (dir, name) = self.parent_dir(path)?;
+        let node = dir.remove_entry(name)?;
+        let rdev = node.get_attr()?.rdev();
+        let mut devices = self.devices.write();
+        if devices
+            .get(&rdev)
+            .is_some_and(|dev| Arc::ptr_eq(dev, &node))
+        {
+            devices.remove(&rdev);
+        }
+        Ok(())
+    }
+
+    /// Find the registered device with the device number `rdev`.
+    pub fn find(&self, rdev: DeviceId) -> Option<VfsNodeRef> {
+        self.devices.read().get(&rdev).cloned()
+    }
+
+    /// Split `path` into its parent directory, created if missing, and the
+    /// last component.
+    fn parent_dir<'a>(&self, path: &'a str) -> VfsResult<(Arc<DirNode>, &'a str)> {
+        let path = path.trim_matches('/');
+        let (parents, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        let mut dir = self.root.clone();
+        for component in parents.split('/').filter(|c| !c.is_empty() && *c != ".") {
+            dir = dir.get_or_mkdir(component)?;
+        }
+        Ok((dir, name))
+    }
 }
 
 impl VfsOps for DeviceFileSystem {
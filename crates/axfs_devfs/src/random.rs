@@ -1,6 +1,6 @@
 use core::ops::DerefMut;
 
-use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axfs_vfs::{DeviceId, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
 use rand::{rngs::SmallRng, Fill, SeedableRng};
 use spin::Mutex;
 
@@ -9,26 +9,37 @@ use spin::Mutex;
 /// It always returns a chunk of random bytes when read, and all writes are discarded.
 ///
 /// TODO: update entropy pool with data written.
-pub struct RandomDev(Mutex<SmallRng>);
+pub struct RandomDev {
+    rng: Mutex<SmallRng>,
+    rdev: DeviceId,
+}
+
+impl RandomDev {
+    /// Create a random device with the device number `rdev`, which is 1:8
+    /// for `/dev/random` and 1:9 for `/dev/urandom`.
+    pub fn new(rdev: DeviceId) -> Self {
+        Self {
+            rng: Mutex::new(SmallRng::from_seed([0; 32])),
+            rdev,
+        }
+    }
+}
 
 impl Default for RandomDev {
     fn default() -> Self {
-        Self(Mutex::new(SmallRng::from_seed([0; 32])))
+        Self::new(DeviceId::new(1, 9))
     }
 }
 
 impl VfsNodeOps for RandomDev {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new(
-            VfsNodePerm::default_file(),
-            VfsNodeType::CharDevice,
-            0,
-            0,
-        ))
+        let mut attr = VfsNodeAttr::new(VfsNodePerm::default_file(), VfsNodeType::CharDevice, 0, 0);
+        attr.set_rdev(self.rdev);
+        Ok(attr)
     }
 
     fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
-        buf.try_fill(self.0.lock().deref_mut()).unwrap();
+        buf.try_fill(self.rng.lock().deref_mut()).unwrap();
         Ok(buf.len())
     }
 
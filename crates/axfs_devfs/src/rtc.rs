@@ -0,0 +1,109 @@
+use core::time::Duration;
+
+use axfs_vfs::{DeviceId, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+
+/// The broken-down time of an RTC, laid out as the `struct rtc_time`
+/// returned by the `RTC_RD_TIME` ioctl.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RtcTime {
+    /// Seconds, 0 to 59.
+    pub tm_sec: i32,
+    /// Minutes, 0 to 59.
+    pub tm_min: i32,
+    /// Hours, 0 to 23.
+    pub tm_hour: i32,
+    /// Day of the month, 1 to 31.
+    pub tm_mday: i32,
+    /// Month, 0 to 11.
+    pub tm_mon: i32,
+    /// Years since 1900.
+    pub tm_year: i32,
+    /// Day of the week, 0 to 6 from Sunday.
+    pub tm_wday: i32,
+    /// Day of the year, 0 to 365.
+    pub tm_yday: i32,
+    /// Always 0, the RTC keeps UTC.
+    pub tm_isdst: i32,
+}
+
+impl RtcTime {
+    /// Converts the time elapsed since the Unix epoch to UTC.
+    pub fn from_unix(time: Duration) -> Self {
+        let secs = time.as_secs();
+        let days = (secs / 86400) as i64;
+        let rem = (secs % 86400) as i32;
+
+        // civil date from the days since 1970-01-01, in 400-year eras
+        // starting on March 1st
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let mday = doy - (153 * mp + 2) / 5 + 1;
+        let mon = if mp < 10 { mp + 2 } else { mp - 10 };
+        let year = yoe + era * 400 + i64::from(mon < 2);
+
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        const DAYS_BEFORE: [i32; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+        let yday = DAYS_BEFORE[mon as usize] + mday as i32 - 1 + i32::from(leap && mon >= 2);
+
+        Self {
+            tm_sec: rem % 60,
+            tm_min: rem / 60 % 60,
+            tm_hour: rem / 3600,
+            tm_mday: mday as i32,
+            tm_mon: mon as i32,
+            tm_year: (year - 1900) as i32,
+            // 1970-01-01 was a Thursday
+            tm_wday: (days + 4).rem_euclid(7) as i32,
+            tm_yday: yday,
+            tm_isdst: 0,
+        }
+    }
+}
+
+/// A real-time clock behaves like `/dev/rtc0`.
+///
+/// The time is read with [`RtcDev::now`], which backs the `RTC_RD_TIME`
+/// ioctl. Reading and writing the node itself is not supported.
+pub struct RtcDev {
+    clock: fn() -> Duration,
+}
+
+impl RtcDev {
+    /// Create an RTC reading the time elapsed since the Unix epoch from
+    /// `clock`.
+    pub const fn new(clock: fn() -> Duration) -> Self {
+        Self { clock }
+    }
+
+    /// Returns the current time of the RTC.
+    pub fn now(&self) -> RtcTime {
+        RtcTime::from_unix((self.clock)())
+    }
+}
+
+impl VfsNodeOps for RtcDev {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = VfsNodeAttr::new(VfsNodePerm::default_file(), VfsNodeType::CharDevice, 0, 0);
+        attr.set_rdev(DeviceId::new(253, 0));
+        Ok(attr)
+    }
+
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::Unsupported)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::Unsupported)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
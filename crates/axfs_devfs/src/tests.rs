@@ -1,6 +1,8 @@
 use std::sync::Arc;
 
-use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+use std::time::Duration;
+
+use axfs_vfs::{DeviceId, VfsError, VfsNodeType, VfsResult};
 
 use crate::*;
 
@@ -113,3 +115,82 @@ fn test_devfs() {
     test_devfs_ops(&devfs).unwrap();
     test_get_parent(&devfs).unwrap();
 }
+
+#[test]
+fn test_device_id() {
+    let rdev = DeviceId::new(8, 1);
+    assert_eq!(rdev.raw(), 0x801);
+    assert_eq!((rdev.major(), rdev.minor()), (8, 1));
+
+    let rdev = DeviceId::new(0x12345, 0x678_9abc);
+    assert_eq!(DeviceId::from_raw(rdev.raw()), rdev);
+    assert_eq!((rdev.major(), rdev.minor()), (0x12345, 0x678_9abc));
+}
+
+#[test]
+fn test_registry() {
+    let devfs = DeviceFileSystem::new();
+    devfs.register("null", Arc::new(NullDev)).unwrap();
+    devfs.register("/misc/full", Arc::new(FullDev)).unwrap();
+    devfs
+        .register("random", Arc::new(RandomDev::new(DeviceId::new(1, 8))))
+        .unwrap();
+    assert_eq!(
+        devfs.register("null", Arc::new(ZeroDev)).err(),
+        Some(VfsError::AlreadyExists)
+    );
+    assert_eq!(
+        devfs.register("misc/..", Arc::new(ZeroDev)).err(),
+        Some(VfsError::InvalidInput)
+    );
+    assert_eq!(
+        devfs.register("null/zero", Arc::new(ZeroDev)).err(),
+        Some(VfsError::NotADirectory)
+    );
+
+    let root = devfs.root_dir();
+    let full = root.clone().lookup("misc/full").unwrap();
+    assert_eq!(full.get_attr().unwrap().rdev(), DeviceId::new(1, 7));
+    assert_eq!(full.write_at(0, &[1]).err(), Some(VfsError::StorageFull));
+    assert!(Arc::ptr_eq(
+        &devfs.find(DeviceId::new(1, 7)).unwrap(),
+        &full
+    ));
+    assert!(Arc::ptr_eq(
+        &devfs.find(DeviceId::new(1, 3)).unwrap(),
+        &root.clone().lookup("null").unwrap()
+    ));
+    assert!(devfs.find(DeviceId::new(1, 8)).is_some());
+    assert!(devfs.find(DeviceId::new(1, 9)).is_none());
+
+    devfs.unregister("misc/full").unwrap();
+    assert!(devfs.find(DeviceId::new(1, 7)).is_none());
+    assert_eq!(
+        root.clone().lookup("misc/full").err(),
+        Some(VfsError::NotFound)
+    );
+    assert!(root.lookup("misc").unwrap().get_attr().unwrap().is_dir());
+    assert_eq!(devfs.unregister("misc/full"), Err(VfsError::NotFound));
+}
+
+#[test]
+fn test_rtc() {
+    // 2024-02-29 13:45:30 UTC, a Thursday
+    let rtc = RtcDev::new(|| Duration::from_secs(1_709_214_330));
+    assert_eq!(
+        rtc.now(),
+        RtcTime {
+            tm_sec: 30,
+            tm_min: 45,
+            tm_hour: 13,
+            tm_mday: 29,
+            tm_mon: 1,
+            tm_year: 124,
+            tm_wday: 4,
+            tm_yday: 59,
+            tm_isdst: 0,
+        }
+    );
+    assert_eq!(RtcTime::from_unix(Duration::ZERO).tm_wday, 4);
+    assert_eq!(RtcTime::from_unix(Duration::ZERO).tm_year, 70);
+}
@@ -1,30 +1,55 @@
 use alloc::collections::BTreeMap;
 use alloc::sync::{Arc, Weak};
 use alloc::{string::String, vec::Vec};
+use core::time::Duration;
 
-use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
 use axfs_vfs::{VfsError, VfsResult};
 use spin::RwLock;
 
 use crate::file::FileNode;
+use crate::info::{FsInfo, Meta};
+use crate::symlink::SymlinkNode;
 use crate::Interrupts;
 
+type Children = BTreeMap<String, VfsNodeRef>;
+
 /// The directory node in the RAM filesystem.
 ///
 /// It implements [`axfs_vfs::VfsNodeOps`].
 pub struct DirNode {
     this: Weak<DirNode>,
+    fs: Arc<FsInfo>,
     parent: RwLock<Weak<dyn VfsNodeOps>>,
-    children: RwLock<BTreeMap<String, VfsNodeRef>>,
+    children: RwLock<Children>,
+    meta: RwLock<Meta>,
 }
 
 impl DirNode {
-    pub(super) fn new(parent: Option<Weak<dyn VfsNodeOps>>) -> Arc<Self> {
-        Arc::new_cyclic(|this| Self {
+    pub(super) fn new(
+        fs: Arc<FsInfo>,
+        parent: Option<Weak<dyn VfsNodeOps>>,
+        perm: VfsNodePerm,
+    ) -> VfsResult<Arc<Self>> {
+        fs.alloc_inode()?;
+        let meta = Meta::new(perm, fs.now());
+        Ok(Arc::new_cyclic(|this| Self {
             this: this.clone(),
+            fs,
             parent: RwLock::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
             children: RwLock::new(BTreeMap::new()),
-        })
+            meta: RwLock::new(meta),
+        }))
+    }
+
+    pub(super) fn set_owner_ids(&self, uid: u32, gid: u32) {
+        let mut meta = self.meta.write();
+        meta.uid = uid;
+        meta.gid = gid;
+    }
+
+    fn modified(&self) {
+        self.meta.write().modified(self.fs.now());
     }
 
     pub(super) fn set_parent(&self, parent: Option<&VfsNodeRef>) {
@@ -43,7 +68,8 @@ impl DirNode {
 
     /// Creates a new node with the given name and type in this directory.
     pub fn create_node(&self, name: &str, ty: VfsNodeType) -> VfsResult {
-        if self.exist(name) {
+        let mut children = self.children.write();
+        if children.contains_key(name) {
             log::error!("AlreadyExists {}", name);
             return Err(VfsError::AlreadyExists);
         }
@@ -53,13 +79,55 @@ impl DirNode {
                 if name == "interrupts" {
                     Arc::new(Interrupts::default())
                 } else {
-                    Arc::new(FileNode::new())
+                    Arc::new(FileNode::new_in(self.fs.clone())?)
                 }
             }
-            VfsNodeType::Dir => Self::new(Some(self.this.clone())),
+            VfsNodeType::Dir => Self::new(
+                self.fs.clone(),
+                Some(self.this.clone()),
+                VfsNodePerm::default_dir(),
+            )?,
             _ => return Err(VfsError::Unsupported),
         };
-        self.children.write().insert(name.into(), node);
+        children.insert(name.into(), node);
+        drop(children);
+        self.modified();
+        Ok(())
+    }
+
+    /// Creates a symbolic link with the given name in this directory,
+    /// pointing to `target`.
+    pub fn symlink_node(&self, name: &str, target: &str) -> VfsResult {
+        let mut children = self.children.write();
+        if children.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        let node = Arc::new(SymlinkNode::new_in(self.fs.clone(), target)?);
+        children.insert(name.into(), node);
+        drop(children);
+        self.modified();
+        Ok(())
+    }
+
+    /// Creates a hard link with the given name in this directory to `node`,
+    /// which must be a file of the same RAM filesystem.
+    pub fn link_node(&self, name: &str, node: &VfsNodeRef) -> VfsResult {
+        let any = node.as_any();
+        if any.is::<DirNode>() {
+            return Err(VfsError::OperationNotPermitted);
+        }
+        let file = any
+            .downcast_ref::<FileNode>()
+            .filter(|file| Arc::ptr_eq(file.fs(), &self.fs))
+            .ok_or(VfsError::CrossesDevices)?;
+        let mut children = self.children.write();
+        if children.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        file.inc_nlink();
+        children.insert(name.into(), node.clone());
+        drop(children);
+        self.modified();
         Ok(())
     }
 
@@ -72,14 +140,164 @@ impl DirNode {
                 return Err(VfsError::DirectoryNotEmpty);
             }
         }
+        if let Some(file) = node.as_any().downcast_ref::<FileNode>() {
+            file.dec_nlink();
+        }
         children.remove(name);
+        drop(children);
+        self.modified();
+        Ok(())
+    }
+
+    /// Looks up the directory holding the last component of `path`, returns
+    /// it and the last component.
+    fn split_parent<'a>(&self, path: &'a str) -> VfsResult<(Arc<DirNode>, &'a str)> {
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let path = path.trim_end_matches('/');
+        let (dir, name) = match path.rsplit_once('/') {
+            Some((dir, name)) => (this.lookup(dir)?, name),
+            None => (this as VfsNodeRef, path),
+        };
+        match dir.as_any().downcast_ref::<DirNode>() {
+            Some(dir) => Ok((dir.this.upgrade().ok_or(VfsError::NotFound)?, name)),
+            None if dir.get_attr()?.is_dir() => Err(VfsError::CrossesDevices),
+            None => Err(VfsError::NotADirectory),
+        }
+    }
+
+    /// Whether `self` is `dir` or a directory inside it.
+    fn is_inside(&self, dir: &DirNode) -> bool {
+        let mut cur = self.this.upgrade();
+        while let Some(node) = cur {
+            if core::ptr::eq(Arc::as_ptr(&node), dir) {
+                return true;
+            }
+            cur = node
+                .parent()
+                .and_then(|p| p.as_any().downcast_ref::<DirNode>()?.this.upgrade());
+        }
+        false
+    }
+
+    /// Moves the entry `src_name` of `src_dir` to `dst_name` in `dst_dir`,
+    /// replacing the node there if any.
+    fn move_node(
+        src_dir: &Arc<Self>,
+        src_name: &str,
+        dst_dir: &Arc<Self>,
+        dst_name: &str,
+    ) -> VfsResult {
+        if [src_name, dst_name]
+            .iter()
+            .any(|name| name.is_empty() || *name == "." || *name == "..")
+        {
+            return Err(VfsError::InvalidInput);
+        }
+        let node = src_dir
+            .children
+            .read()
+            .get(src_name)
+            .cloned()
+            .ok_or(VfsError::NotFound)?;
+        let moved_dir = node.as_any().downcast_ref::<DirNode>();
+        if moved_dir.is_some_and(|dir| dst_dir.is_inside(dir)) {
+            return Err(VfsError::InvalidInput); // into itself
+        }
+        // lock both directories in a fixed order
+        let src_ptr = Arc::as_ptr(src_dir);
+        if Arc::ptr_eq(src_dir, dst_dir) {
+            let mut children = src_dir.children.write();
+            Self::move_entry(src_ptr, &mut children, None, src_name, dst_name, &node)?;
+        } else if src_ptr < Arc::as_ptr(dst_dir) {
+            let mut src = src_dir.children.write();
+            let mut dst = dst_dir.children.write();
+            Self::move_entry(src_ptr, &mut src, Some(&mut dst), src_name, dst_name, &node)?;
+        } else {
+            let mut dst = dst_dir.children.write();
+            let mut src = src_dir.children.write();
+            Self::move_entry(src_ptr, &mut src, Some(&mut dst), src_name, dst_name, &node)?;
+        }
+        if let Some(dir) = moved_dir {
+            dir.set_parent(Some(&(dst_dir.clone() as VfsNodeRef)));
+        }
+        src_dir.modified();
+        dst_dir.modified();
+        Ok(())
+    }
+
+    /// Moves `node` from `src_name` in `src` to `dst_name` in `dst`, which is
+    /// `src` itself if `None`.
+    fn move_entry(
+        src_dir: *const DirNode,
+        src: &mut Children,
+        mut dst: Option<&mut Children>,
+        src_name: &str,
+        dst_name: &str,
+        node: &VfsNodeRef,
+    ) -> VfsResult {
+        if !src.get(src_name).is_some_and(|n| Arc::ptr_eq(n, node)) {
+            return Err(VfsError::NotFound); // renamed meanwhile
+        }
+        let old = match &dst {
+            Some(dst) => dst.get(dst_name),
+            None => src.get(dst_name),
+        };
+        if let Some(old) = old {
+            if Arc::ptr_eq(old, node) {
+                return Ok(()); // hard links of the same file
+            }
+            let old_dir = old.as_any().downcast_ref::<DirNode>();
+            match (node.as_any().is::<DirNode>(), old_dir) {
+                (true, Some(old_dir)) => {
+                    // the source directory is locked already, and not empty
+                    if core::ptr::eq(old_dir, src_dir) || !old_dir.children.read().is_empty() {
+                        return Err(VfsError::DirectoryNotEmpty);
+                    }
+                }
+                (true, None) => return Err(VfsError::NotADirectory),
+                (false, Some(_)) => return Err(VfsError::IsADirectory),
+                (false, None) => {}
+            }
+            if let Some(file) = old.as_any().downcast_ref::<FileNode>() {
+                file.dec_nlink();
+            }
+        }
+        src.remove(src_name);
+        match dst.as_mut() {
+            Some(dst) => dst.insert(dst_name.into(), node.clone()),
+            None => src.insert(dst_name.into(), node.clone()),
+        };
         Ok(())
     }
 }
 
 impl VfsNodeOps for DirNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_dir(4096, 0))
+        let mut attr = VfsNodeAttr::new_dir(4096, 0);
+        let subdirs = self
+            .children
+            .read()
+            .values()
+            .filter(|node| node.as_any().is::<DirNode>())
+            .count();
+        attr.set_nlink(2 + subdirs as u64);
+        self.meta.read().fill(&mut attr);
+        Ok(attr)
+    }
+
+    fn set_perm(&self, perm: VfsNodePerm) -> VfsResult {
+        self.meta.write().set_perm(perm, self.fs.now());
+        Ok(())
+    }
+
+    fn set_owner(&self, uid: Option<u32>, gid: Option<u32>) -> VfsResult {
+        self.meta.write().set_owner(uid, gid, self.fs.now());
+        Ok(())
+    }
+
+    fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> VfsResult {
+        self.meta.write().set_times(atime, mtime, self.fs.now());
+        Ok(())
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
@@ -148,6 +366,57 @@ impl VfsNodeOps for DirNode {
         }
     }
 
+    fn link(&self, path: &str, node: &VfsNodeRef) -> VfsResult {
+        log::debug!("link at ramfs: {}", path);
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.link(rest, node),
+                ".." => self.parent().ok_or(VfsError::NotFound)?.link(rest, node),
+                _ => {
+                    let subdir = self
+                        .children
+                        .read()
+                        .get(name)
+                        .ok_or(VfsError::NotFound)?
+                        .clone();
+                    subdir.link(rest, node)
+                }
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Err(VfsError::AlreadyExists)
+        } else {
+            self.link_node(name, node)
+        }
+    }
+
+    fn symlink(&self, path: &str, target: &str) -> VfsResult {
+        log::debug!("symlink at ramfs: {} -> {}", path, target);
+        let (name, rest) = split_path(path);
+        if let Some(rest) = rest {
+            match name {
+                "" | "." => self.symlink(rest, target),
+                ".." => self
+                    .parent()
+                    .ok_or(VfsError::NotFound)?
+                    .symlink(rest, target),
+                _ => {
+                    let subdir = self
+                        .children
+                        .read()
+                        .get(name)
+                        .ok_or(VfsError::NotFound)?
+                        .clone();
+                    subdir.symlink(rest, target)
+                }
+            }
+        } else if name.is_empty() || name == "." || name == ".." {
+            Err(VfsError::AlreadyExists)
+        } else {
+            self.symlink_node(name, target)
+        }
+    }
+
     fn remove(&self, path: &str) -> VfsResult {
         log::debug!("remove at ramfs: {}", path);
         let (name, rest) = split_path(path);
@@ -172,9 +441,22 @@ impl VfsNodeOps for DirNode {
         }
     }
 
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        log::debug!("rename at ramfs: {} -> {}", src_path, dst_path);
+        let (src_dir, src_name) = self.split_parent(src_path)?;
+        let (dst_dir, dst_name) = self.split_parent(dst_path)?;
+        Self::move_node(&src_dir, src_name, &dst_dir, dst_name)
+    }
+
     axfs_vfs::impl_vfs_dir_default! {}
 }
 
+impl Drop for DirNode {
+    fn drop(&mut self) {
+        self.fs.free_inode();
+    }
+}
+
 fn split_path(path: &str) -> (&str, Option<&str>) {
     let trimmed_path = path.trim_start_matches('/');
     trimmed_path.find('/').map_or((trimmed_path, None), |n| {
@@ -1,55 +1,181 @@
-use alloc::vec::Vec;
-use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
+use alloc::boxed::Box;
+use alloc::collections::{btree_map::Entry, BTreeMap};
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsResult};
 use spin::RwLock;
 
+use crate::info::{FsInfo, Meta, PAGE_SIZE};
+
 /// The file node in the RAM filesystem.
 ///
+/// The data is kept in pages allocated on the first write, so the holes of
+/// a sparse file take no memory.
+///
 /// It implements [`axfs_vfs::VfsNodeOps`].
 pub struct FileNode {
-    content: RwLock<Vec<u8>>,
+    fs: Arc<FsInfo>,
+    content: RwLock<Content>,
+    /// The number of directory entries pointing to this file.
+    nlink: AtomicU64,
+    meta: RwLock<Meta>,
+}
+
+#[derive(Default)]
+struct Content {
+    size: u64,
+    /// The allocated pages, by page index.
+    pages: BTreeMap<u64, Box<[u8]>>,
 }
 
 impl FileNode {
-    pub const fn new() -> Self {
-        Self {
-            content: RwLock::new(Vec::new()),
-        }
+    /// Creates a file that is not in any RAM filesystem, with no limits.
+    pub fn new() -> Self {
+        Self::new_in(Arc::new(FsInfo::new(|| Duration::ZERO)))
+            .expect("a filesystem without limits is never full")
+    }
+
+    pub(crate) fn new_in(fs: Arc<FsInfo>) -> VfsResult<Self> {
+        fs.alloc_inode()?;
+        let meta = Meta::new(VfsNodePerm::default_file(), fs.now());
+        Ok(Self {
+            fs,
+            content: RwLock::new(Content::default()),
+            nlink: AtomicU64::new(1),
+            meta: RwLock::new(meta),
+        })
+    }
+
+    pub(crate) fn fs(&self) -> &Arc<FsInfo> {
+        &self.fs
+    }
+
+    pub(crate) fn inc_nlink(&self) {
+        self.nlink.fetch_add(1, Ordering::Relaxed);
+        self.meta.write().ctime = self.fs.now();
+    }
+
+    pub(crate) fn dec_nlink(&self) {
+        self.nlink.fetch_sub(1, Ordering::Relaxed);
+        self.meta.write().ctime = self.fs.now();
+    }
+}
+
+impl Default for FileNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for FileNode {
+    fn drop(&mut self) {
+        self.fs
+            .free_pages(self.content.get_mut().pages.len() as u64);
+        self.fs.free_inode();
     }
 }
 
 impl VfsNodeOps for FileNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_file(self.content.read().len() as _, 0))
+        let content = self.content.read();
+        let blocks = content.pages.len() * (PAGE_SIZE / 512);
+        let mut attr = VfsNodeAttr::new_file(content.size, blocks as _);
+        attr.set_nlink(self.nlink.load(Ordering::Relaxed));
+        self.meta.read().fill(&mut attr);
+        Ok(attr)
+    }
+
+    fn set_perm(&self, perm: VfsNodePerm) -> VfsResult {
+        self.meta.write().set_perm(perm, self.fs.now());
+        Ok(())
+    }
+
+    fn set_owner(&self, uid: Option<u32>, gid: Option<u32>) -> VfsResult {
+        self.meta.write().set_owner(uid, gid, self.fs.now());
+        Ok(())
+    }
+
+    fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> VfsResult {
+        self.meta.write().set_times(atime, mtime, self.fs.now());
+        Ok(())
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
         let mut content = self.content.write();
-        if size < content.len() as u64 {
-            content.truncate(size as _);
-        } else {
-            content.resize(size as _, 0);
+        if size < content.size {
+            let dropped = content.pages.split_off(&size.div_ceil(PAGE_SIZE as u64));
+            self.fs.free_pages(dropped.len() as u64);
+            let tail = (size % PAGE_SIZE as u64) as usize;
+            if let Some(page) = content.pages.get_mut(&(size / PAGE_SIZE as u64)) {
+                page[tail..].fill(0);
+            }
         }
+        content.size = size;
+        self.meta.write().modified(self.fs.now());
         Ok(())
     }
 
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
         let content = self.content.read();
-        let start = content.len().min(offset as usize);
-        let end = content.len().min(offset as usize + buf.len());
-        let src = &content[start..end];
-        buf[..src.len()].copy_from_slice(src);
-        Ok(src.len())
+        if offset >= content.size {
+            return Ok(0);
+        }
+        let len = buf.len().min((content.size - offset) as usize);
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let start = (pos % PAGE_SIZE as u64) as usize;
+            let n = (PAGE_SIZE - start).min(len - done);
+            match content.pages.get(&(pos / PAGE_SIZE as u64)) {
+                Some(page) => buf[done..done + n].copy_from_slice(&page[start..start + n]),
+                None => buf[done..done + n].fill(0), // a hole
+            }
+            done += n;
+        }
+        drop(content);
+        self.meta.write().atime = self.fs.now();
+        Ok(len)
     }
 
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
-        let offset = offset as usize;
         let mut content = self.content.write();
-        if offset + buf.len() > content.len() {
-            content.resize(offset + buf.len(), 0);
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let index = pos / PAGE_SIZE as u64;
+            let start = (pos % PAGE_SIZE as u64) as usize;
+            let n = (PAGE_SIZE - start).min(buf.len() - done);
+            let page = match content.pages.entry(index) {
+                Entry::Occupied(page) => page.into_mut(),
+                Entry::Vacant(entry) => {
+                    if let Err(e) = self.fs.alloc_page() {
+                        if done == 0 {
+                            return Err(e);
+                        }
+                        break; // a short write
+                    }
+                    entry.insert(alloc::vec![0; PAGE_SIZE].into_boxed_slice())
+                }
+            };
+            page[start..start + n].copy_from_slice(&buf[done..done + n]);
+            done += n;
         }
-        let dst = &mut content[offset..offset + buf.len()];
-        dst.copy_from_slice(&buf[..dst.len()]);
-        Ok(buf.len())
+        content.size = content.size.max(offset + done as u64);
+        drop(content);
+        self.meta.write().modified(self.fs.now());
+        Ok(done)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        // The content lives in memory, nothing to write back.
+        Ok(())
+    }
+
+    fn cache_key(&self) -> Option<(usize, u64)> {
+        // Every lookup of the file returns this same node.
+        Some((self as *const Self as usize, 0))
     }
 
     impl_vfs_non_dir_default! {}
@@ -0,0 +1,214 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use axfs_vfs::{VfsError, VfsNodeAttr, VfsNodePerm, VfsResult};
+
+/// The size of the pages holding file data.
+pub(crate) const PAGE_SIZE: usize = 4096;
+
+/// The state shared by all nodes of a RAM filesystem: the limits, the usage
+/// and the clock.
+pub(crate) struct FsInfo {
+    /// The most pages of file data.
+    max_pages: AtomicU64,
+    /// The most nodes.
+    max_inodes: AtomicU64,
+    pages: AtomicU64,
+    inodes: AtomicU64,
+    clock: fn() -> Duration,
+}
+
+impl FsInfo {
+    pub(crate) fn new(clock: fn() -> Duration) -> Self {
+        Self {
+            max_pages: AtomicU64::new(u64::MAX),
+            max_inodes: AtomicU64::new(u64::MAX),
+            pages: AtomicU64::new(0),
+            inodes: AtomicU64::new(0),
+            clock,
+        }
+    }
+
+    /// The current time, for the timestamps of the nodes.
+    pub(crate) fn now(&self) -> Duration {
+        (self.clock)()
+    }
+
+    /// Accounts for a new node, fails with `StorageFull` if there are too
+    /// many.
+    pub(crate) fn alloc_inode(&self) -> VfsResult {
+        charge(&self.inodes, &self.max_inodes)
+    }
+
+    pub(crate) fn free_inode(&self) {
+        self.inodes.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Accounts for a new page of file data, fails with `StorageFull` if the
+    /// filesystem is full.
+    pub(crate) fn alloc_page(&self) -> VfsResult {
+        charge(&self.pages, &self.max_pages)
+    }
+
+    pub(crate) fn free_pages(&self, num: u64) {
+        self.pages.fetch_sub(num, Ordering::Relaxed);
+    }
+
+    /// Changes the limits given in `options`. Fails if more is already in use.
+    pub(crate) fn set_limits(&self, options: &RamFsOptions) -> VfsResult {
+        let max_pages = options.size.map(|size| match size {
+            0 => u64::MAX,
+            size => size.div_ceil(PAGE_SIZE as u64),
+        });
+        let max_inodes = options.nr_inodes.map(|n| if n == 0 { u64::MAX } else { n });
+        if max_pages.is_some_and(|max| self.pages.load(Ordering::Relaxed) > max)
+            || max_inodes.is_some_and(|max| self.inodes.load(Ordering::Relaxed) > max)
+        {
+            return Err(VfsError::InvalidInput);
+        }
+        if let Some(max) = max_pages {
+            self.max_pages.store(max, Ordering::Relaxed);
+        }
+        if let Some(max) = max_inodes {
+            self.max_inodes.store(max, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+fn charge(used: &AtomicU64, max: &AtomicU64) -> VfsResult {
+    let max = max.load(Ordering::Relaxed);
+    used.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+        (n < max).then_some(n + 1)
+    })
+    .map(|_| ())
+    .map_err(|_| VfsError::StorageFull)
+}
+
+/// The owner, permission and timestamps of a node.
+#[derive(Clone, Copy)]
+pub(crate) struct Meta {
+    pub perm: VfsNodePerm,
+    pub uid: u32,
+    pub gid: u32,
+    pub atime: Duration,
+    pub mtime: Duration,
+    pub ctime: Duration,
+}
+
+impl Meta {
+    pub(crate) fn new(perm: VfsNodePerm, now: Duration) -> Self {
+        Self {
+            perm,
+            uid: 0,
+            gid: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+        }
+    }
+
+    /// Copies the owner, permission and timestamps into `attr`.
+    pub(crate) fn fill(&self, attr: &mut VfsNodeAttr) {
+        attr.set_perm(self.perm);
+        attr.set_owner(self.uid, self.gid);
+        attr.set_times(self.atime, self.mtime, self.ctime);
+    }
+
+    pub(crate) fn set_perm(&mut self, perm: VfsNodePerm, now: Duration) {
+        self.perm = perm;
+        self.ctime = now;
+    }
+
+    pub(crate) fn set_owner(&mut self, uid: Option<u32>, gid: Option<u32>, now: Duration) {
+        self.uid = uid.unwrap_or(self.uid);
+        self.gid = gid.unwrap_or(self.gid);
+        self.ctime = now;
+    }
+
+    pub(crate) fn set_times(
+        &mut self,
+        atime: Option<Duration>,
+        mtime: Option<Duration>,
+        now: Duration,
+    ) {
+        self.atime = atime.unwrap_or(self.atime);
+        self.mtime = mtime.unwrap_or(self.mtime);
+        self.ctime = now;
+    }
+
+    /// The data or the entries were modified.
+    pub(crate) fn modified(&mut self, now: Duration) {
+        self.mtime = now;
+        self.ctime = now;
+    }
+}
+
+/// The mount options of a [`RamFileSystem`](crate::RamFileSystem), those of
+/// tmpfs.
+#[derive(Debug, Clone, Copy)]
+pub struct RamFsOptions {
+    /// The most bytes of file data, rounded up to pages (`size=`). `Some(0)`
+    /// means no limit, `None` is no limit on mount and unchanged on remount.
+    pub size: Option<u64>,
+    /// The most nodes (`nr_inodes=`), with zero or `None` like `size`.
+    pub nr_inodes: Option<u64>,
+    /// The permission of the root directory (`mode=`), `0o1777` by default.
+    pub mode: VfsNodePerm,
+    /// The owner of the root directory (`uid=`).
+    pub uid: u32,
+    /// The group of the root directory (`gid=`).
+    pub gid: u32,
+}
+
+impl RamFsOptions {
+    /// Parses comma-separated tmpfs mount options, e.g. `size=16m,mode=755`.
+    ///
+    /// Sizes take a `k`, `m` or `g` suffix. Options without a value, such as
+    /// `rw` or `noatime`, are left to the VFS and ignored here. Unknown
+    /// options with a value are rejected with `InvalidInput`.
+    pub fn parse(data: &str) -> VfsResult<Self> {
+        let mut options = Self::default();
+        for opt in data.split(',') {
+            let Some((key, value)) = opt.split_once('=') else {
+                continue;
+            };
+            match key {
+                "size" => options.size = Some(parse_size(value)?),
+                "nr_inodes" => options.nr_inodes = Some(parse_size(value)?),
+                "mode" => {
+                    let mode = u16::from_str_radix(value, 8).map_err(|_| VfsError::InvalidInput)?;
+                    options.mode = VfsNodePerm::from_bits(mode).ok_or(VfsError::InvalidInput)?;
+                }
+                "uid" => options.uid = value.parse().map_err(|_| VfsError::InvalidInput)?,
+                "gid" => options.gid = value.parse().map_err(|_| VfsError::InvalidInput)?,
+                _ => return Err(VfsError::InvalidInput),
+            }
+        }
+        Ok(options)
+    }
+}
+
+impl Default for RamFsOptions {
+    fn default() -> Self {
+        Self {
+            size: None,
+            nr_inodes: None,
+            mode: VfsNodePerm::from_bits_truncate(0o1777),
+            uid: 0,
+            gid: 0,
+        }
+    }
+}
+
+/// Parses a number with an optional `k`, `m` or `g` suffix.
+fn parse_size(value: &str) -> VfsResult<u64> {
+    let (num, shift) = match value.as_bytes().last() {
+        Some(b'k' | b'K') => (&value[..value.len() - 1], 10),
+        Some(b'm' | b'M') => (&value[..value.len() - 1], 20),
+        Some(b'g' | b'G') => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    let num: u64 = num.parse().map_err(|_| VfsError::InvalidInput)?;
+    num.checked_mul(1 << shift).ok_or(VfsError::InvalidInput)
+}
@@ -1,6 +1,11 @@
 //! RAM filesystem used by [ArceOS](https://github.com/rcore-os/arceos).
 //!
-//! The implementation is based on [`axfs_vfs`].
+//! The implementation is based on [`axfs_vfs`]. It also serves as tmpfs:
+//! [`RamFileSystem::with_options`] takes the tmpfs mount options
+//! ([`RamFsOptions`]) to limit the size and the number of nodes, and gives
+//! every node an owner, a permission and timestamps. Files are sparse, and
+//! [`rename`](axfs_vfs::VfsNodeOps::rename) replaces an existing target
+//! atomically.
 
 #![cfg_attr(not(test), no_std)]
 
@@ -8,29 +13,52 @@ extern crate alloc;
 
 mod dir;
 mod file;
+mod info;
 mod interrupts;
+mod symlink;
 #[cfg(test)]
 mod tests;
 
 pub use self::dir::DirNode;
 pub use self::file::FileNode;
+pub use self::info::RamFsOptions;
 pub use self::interrupts::{Interrupts, INTERRUPT};
+pub use self::symlink::SymlinkNode;
 use alloc::sync::Arc;
-use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult};
+use axfs_vfs::{VfsNodePerm, VfsNodeRef, VfsOps, VfsResult};
+use core::time::Duration;
+use info::FsInfo;
 use spin::once::Once;
 
 /// A RAM filesystem that implements [`axfs_vfs::VfsOps`].
 pub struct RamFileSystem {
     parent: Once<VfsNodeRef>,
     root: Arc<DirNode>,
+    fs: Arc<FsInfo>,
 }
 
 impl RamFileSystem {
-    /// Create a new instance.
+    /// Create a new instance, without limits and timestamps.
     pub fn new() -> Self {
+        Self::with_root(FsInfo::new(|| Duration::ZERO), VfsNodePerm::default_dir())
+    }
+
+    /// Create a new instance with the tmpfs mount `options`. The timestamps
+    /// of the nodes are taken from `clock`.
+    pub fn with_options(options: &RamFsOptions, clock: fn() -> Duration) -> VfsResult<Self> {
+        let fs = FsInfo::new(clock);
+        fs.set_limits(options)?;
+        let ramfs = Self::with_root(fs, options.mode);
+        ramfs.root.set_owner_ids(options.uid, options.gid);
+        Ok(ramfs)
+    }
+
+    fn with_root(fs: FsInfo, perm: VfsNodePerm) -> Self {
+        let fs = Arc::new(fs);
         Self {
             parent: Once::new(),
-            root: DirNode::new(None),
+            root: DirNode::new(fs.clone(), None, perm).expect("the root is the first node"),
+            fs,
         }
     }
 
@@ -53,6 +81,10 @@ impl VfsOps for RamFileSystem {
     fn root_dir(&self) -> VfsNodeRef {
         self.root.clone()
     }
+
+    fn remount(&self, data: &str) -> VfsResult {
+        self.fs.set_limits(&RamFsOptions::parse(data)?)
+    }
 }
 
 impl Default for RamFileSystem {
@@ -0,0 +1,79 @@
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::time::Duration;
+
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType};
+use axfs_vfs::{VfsError, VfsResult};
+use spin::RwLock;
+
+use crate::info::{FsInfo, Meta};
+
+/// The longest target of a symbolic link, `PATH_MAX - 1` as in Linux.
+const MAX_TARGET_LEN: usize = 4095;
+
+/// The symbolic link node in the RAM filesystem.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct SymlinkNode {
+    fs: Arc<FsInfo>,
+    target: String,
+    meta: RwLock<Meta>,
+}
+
+impl SymlinkNode {
+    pub(crate) fn new_in(fs: Arc<FsInfo>, target: &str) -> VfsResult<Self> {
+        if target.is_empty() || target.len() > MAX_TARGET_LEN {
+            return Err(VfsError::InvalidInput);
+        }
+        fs.alloc_inode()?;
+        let meta = Meta::new(VfsNodePerm::from_bits_truncate(0o777), fs.now());
+        Ok(Self {
+            fs,
+            target: target.into(),
+            meta: RwLock::new(meta),
+        })
+    }
+
+    /// The path the link points to.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+}
+
+impl Drop for SymlinkNode {
+    fn drop(&mut self) {
+        self.fs.free_inode();
+    }
+}
+
+impl VfsNodeOps for SymlinkNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = VfsNodeAttr::new(
+            VfsNodePerm::empty(),
+            VfsNodeType::SymLink,
+            self.target.len() as _,
+            0,
+        );
+        self.meta.read().fill(&mut attr);
+        Ok(attr)
+    }
+
+    fn set_owner(&self, uid: Option<u32>, gid: Option<u32>) -> VfsResult {
+        self.meta.write().set_owner(uid, gid, self.fs.now());
+        Ok(())
+    }
+
+    fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> VfsResult {
+        self.meta.write().set_times(atime, mtime, self.fs.now());
+        Ok(())
+    }
+
+    fn readlink(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let len = buf.len().min(self.target.len());
+        buf[..len].copy_from_slice(&self.target.as_bytes()[..len]);
+        self.meta.write().atime = self.fs.now();
+        Ok(len)
+    }
+
+    impl_vfs_non_dir_default! {}
+}
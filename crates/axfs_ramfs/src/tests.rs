@@ -1,6 +1,7 @@
 use std::sync::Arc;
+use std::time::Duration;
 
-use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+use axfs_vfs::{VfsError, VfsNodePerm, VfsNodeType, VfsResult};
 
 use crate::*;
 
@@ -88,6 +89,42 @@ fn test_get_parent(devfs: &RamFileSystem) -> VfsResult {
     Ok(())
 }
 
+fn test_link(devfs: &RamFileSystem) -> VfsResult {
+    let root = devfs.root_dir();
+    let f1 = root.clone().lookup("f1")?;
+    assert_eq!(f1.get_attr()?.nlink(), 1);
+    assert_eq!(root.get_attr()?.nlink(), 3);
+
+    root.link("foo/bar/l1", &f1)?;
+    assert_eq!(f1.get_attr()?.nlink(), 2);
+    assert!(Arc::ptr_eq(&root.clone().lookup("foo/bar/l1")?, &f1));
+    let mut buf = [0; 4];
+    f1.write_at(0, b"link")?;
+    root.clone().lookup("foo/bar/l1")?.read_at(0, &mut buf)?;
+    assert_eq!(&buf, b"link");
+    // both names share the cached pages of the file
+    assert_eq!(
+        root.clone().lookup("foo/bar/l1")?.cache_key(),
+        f1.cache_key()
+    );
+    assert_ne!(root.clone().lookup("f2")?.cache_key(), f1.cache_key());
+    assert_eq!(root.cache_key(), None);
+
+    assert_eq!(
+        root.link("foo/f3", &f1).err(),
+        Some(VfsError::AlreadyExists)
+    );
+    assert_eq!(
+        root.link("l2", &root.clone().lookup("foo")?).err(),
+        Some(VfsError::OperationNotPermitted)
+    );
+    assert_eq!(root.link("nope/l2", &f1).err(), Some(VfsError::NotFound));
+
+    root.remove("foo/bar/l1")?;
+    assert_eq!(f1.get_attr()?.nlink(), 1);
+    Ok(())
+}
+
 #[test]
 fn test_ramfs() {
     // .
@@ -118,6 +155,8 @@ fn test_ramfs() {
     test_ramfs_ops(&ramfs).unwrap();
     test_get_parent(&ramfs).unwrap();
 
+    test_link(&ramfs).unwrap();
+
     let root = ramfs.root_dir();
     assert_eq!(root.remove("f1"), Ok(()));
     assert_eq!(root.remove("//f2"), Ok(()));
@@ -134,3 +173,141 @@ fn test_ramfs() {
     assert_eq!(root.remove("./foo"), Ok(()));
     assert!(ramfs.root_dir_node().get_entries().is_empty());
 }
+
+fn test_rename(fs: &RamFileSystem) -> VfsResult {
+    let root = fs.root_dir();
+    root.create("d1", VfsNodeType::Dir)?;
+    root.create("d1/f1", VfsNodeType::File)?;
+    root.create("d1/f2", VfsNodeType::File)?;
+    let f1 = root.clone().lookup("d1/f1")?;
+
+    // replaces the target atomically
+    root.rename("d1/f1", "d1/f2")?;
+    assert!(Arc::ptr_eq(&root.clone().lookup("d1/f2")?, &f1));
+    assert_eq!(root.clone().lookup("d1/f1").err(), Some(VfsError::NotFound));
+    assert_eq!(root.rename("d1/f1", "f3").err(), Some(VfsError::NotFound));
+
+    root.create("d2", VfsNodeType::Dir)?;
+    root.rename("d1/f2", "d2/f3")?;
+    assert!(Arc::ptr_eq(&root.clone().lookup("d2/f3")?, &f1));
+    assert_eq!(
+        root.rename("d1", "d2/f3").err(),
+        Some(VfsError::NotADirectory)
+    );
+    assert_eq!(
+        root.rename("d2/f3", "d1").err(),
+        Some(VfsError::IsADirectory)
+    );
+    assert_eq!(
+        root.rename("d1", "d2").err(),
+        Some(VfsError::DirectoryNotEmpty)
+    );
+    assert_eq!(
+        root.rename("d2", "d2/d3").err(),
+        Some(VfsError::InvalidInput)
+    );
+
+    // a directory over an empty one, and its parent follows
+    root.rename("d2", "d1")?;
+    let d1 = root.clone().lookup("d1")?;
+    assert!(Arc::ptr_eq(&d1.clone().lookup("..")?, &root));
+    root.create("d1/d3", VfsNodeType::Dir)?;
+    root.rename("d1/d3", "d3")?;
+    assert!(Arc::ptr_eq(&root.clone().lookup("d3/..")?, &root));
+    assert_eq!(
+        root.rename("d3", "d1/f3/x").err(),
+        Some(VfsError::NotADirectory)
+    );
+    root.remove("d3")?;
+    root.remove("d1/f3")?;
+    root.remove("d1")?;
+    Ok(())
+}
+
+fn clock() -> Duration {
+    Duration::from_secs(100)
+}
+
+#[test]
+fn test_tmpfs() {
+    let options = RamFsOptions::parse("rw,size=8k,nr_inodes=4,mode=700,uid=1000,gid=100").unwrap();
+    assert_eq!(options.size, Some(8192));
+    assert_eq!(options.nr_inodes, Some(4));
+    assert_eq!(RamFsOptions::parse("size=1m").unwrap().size, Some(1 << 20));
+    assert!(RamFsOptions::parse("size=1x").is_err());
+    assert!(RamFsOptions::parse("bogus=1").is_err());
+
+    let tmpfs = RamFileSystem::with_options(&options, clock).unwrap();
+    let root = tmpfs.root_dir();
+    let attr = root.get_attr().unwrap();
+    assert_eq!(attr.perm().bits(), 0o700);
+    assert_eq!((attr.uid(), attr.gid()), (1000, 100));
+    assert_eq!(attr.mtime(), Duration::from_secs(100));
+
+    // sparse files only take the pages written
+    root.create("f1", VfsNodeType::File).unwrap();
+    let f1 = root.clone().lookup("f1").unwrap();
+    f1.truncate(1 << 20).unwrap();
+    assert_eq!(f1.get_attr().unwrap().blocks(), 0);
+    assert_eq!(f1.write_at(4096 * 10, &[1; 4096]).unwrap(), 4096);
+    assert_eq!(f1.get_attr().unwrap().blocks(), 8);
+    let mut buf = [1; 16];
+    f1.read_at(100, &mut buf).unwrap();
+    assert_eq!(buf, [0; 16]);
+
+    // two pages in total, then the filesystem is full
+    assert_eq!(f1.write_at(0, &[2; 8192]).unwrap(), 4096);
+    assert_eq!(
+        f1.write_at(8192, &[2; 1]).err(),
+        Some(VfsError::StorageFull)
+    );
+    f1.truncate(4096).unwrap();
+    assert_eq!(f1.write_at(8192, &[2; 1]).unwrap(), 1);
+
+    // the root, f1, a symlink and one more node
+    root.symlink("l1", "f1").unwrap();
+    let l1 = root.clone().lookup("l1").unwrap();
+    assert_eq!(l1.get_attr().unwrap().file_type(), VfsNodeType::SymLink);
+    assert_eq!(l1.get_attr().unwrap().size(), 2);
+    let mut target = [0; 8];
+    assert_eq!(l1.readlink(&mut target).unwrap(), 2);
+    assert_eq!(&target[..2], b"f1");
+    assert_eq!(
+        root.symlink("l1", "f1").err(),
+        Some(VfsError::AlreadyExists)
+    );
+    root.create("d1", VfsNodeType::Dir).unwrap();
+    assert_eq!(
+        root.create("f2", VfsNodeType::File).err(),
+        Some(VfsError::StorageFull)
+    );
+    drop(l1);
+    root.remove("l1").unwrap();
+    root.create("f2", VfsNodeType::File).unwrap();
+    root.remove("f2").unwrap();
+    root.remove("d1").unwrap();
+
+    // the limits change on remount, but not below the usage
+    assert_eq!(tmpfs.remount("size=4k").err(), Some(VfsError::InvalidInput));
+    tmpfs.remount("size=0,nr_inodes=0").unwrap();
+    assert_eq!(f1.write_at(1 << 16, &[3; 8192]).unwrap(), 8192);
+
+    let perm = VfsNodePerm::from_bits_truncate(0o4750);
+    f1.set_perm(perm).unwrap();
+    f1.set_owner(Some(5), None).unwrap();
+    f1.set_times(Some(Duration::from_secs(1)), None).unwrap();
+    let attr = f1.get_attr().unwrap();
+    assert_eq!(attr.perm().bits(), 0o4750);
+    assert_eq!((attr.uid(), attr.gid()), (5, 0));
+    assert_eq!(attr.atime(), Duration::from_secs(1));
+    assert_eq!(attr.mtime(), Duration::from_secs(100));
+
+    test_rename(&tmpfs).unwrap();
+
+    // files of another filesystem can't be linked
+    let other = RamFileSystem::new();
+    other.root_dir().create("f", VfsNodeType::File).unwrap();
+    let f = other.root_dir().lookup("f").unwrap();
+    assert_eq!(root.link("f3", &f).err(), Some(VfsError::CrossesDevices));
+    assert_eq!(root.rename("f1", "../f1").err(), Some(VfsError::NotFound));
+}
@@ -1,7 +1,7 @@
 //! Virtual filesystem interfaces used by [ArceOS](https://github.com/rcore-os/arceos).
 //!
-//! A filesystem is a set of files and directories (symbol links are not
-//! supported currently), collectively referred to as **nodes**, which are
+//! A filesystem is a set of files, directories and symbolic links,
+//! collectively referred to as **nodes**, which are
 //! conceptually similar to [inodes] in Linux. A file system needs to implement
 //! the [`VfsOps`] trait, its files and directories need to implement the
 //! [`VfsNodeOps`] trait.
@@ -10,6 +10,9 @@
 //!
 //! - [`mount()`](VfsOps::mount): Do something when the filesystem is mounted.
 //! - [`umount()`](VfsOps::umount): Do something when the filesystem is unmounted.
+//! - [`remount()`](VfsOps::remount): Change the mount options of a mounted filesystem.
+//! - [`sync()`](VfsOps::sync): Write all cached data of the filesystem to disk.
+//! - [`is_busy()`](VfsOps::is_busy): Whether any node of the filesystem is in use.
 //! - [`format()`](VfsOps::format): Format the filesystem.
 //! - [`statfs()`](VfsOps::statfs): Get the attributes of the filesystem.
 //! - [`root_dir()`](VfsOps::root_dir): Get root directory of the filesystem.
@@ -26,11 +29,21 @@
 //! | [`write_at()`](VfsNodeOps::write_at) | Write data to the file | file |
 //! | [`fsync()`](VfsNodeOps::fsync) | Synchronize the file data to disk | file |
 //! | [`truncate()`](VfsNodeOps::truncate) | Truncate the file | file |
+//! | [`set_perm()`](VfsNodeOps::set_perm) | Change the permission | both |
+//! | [`set_owner()`](VfsNodeOps::set_owner) | Change the owner and group | both |
+//! | [`set_times()`](VfsNodeOps::set_times) | Change the access and modification times | both |
+//! | [`get_flags()`](VfsNodeOps::get_flags) | Get the node flags | both |
+//! | [`set_flags()`](VfsNodeOps::set_flags) | Set the node flags | both |
+//! | [`fiemap()`](VfsNodeOps::fiemap) | Get the extent layout of the file | file |
+//! | [`cache_key()`](VfsNodeOps::cache_key) | Identify the file in the page cache | file |
 //! | [`parent()`](VfsNodeOps::parent) | Get the parent directory | directory |
 //! | [`lookup()`](VfsNodeOps::lookup) | Lookup the node with the given path | directory |
 //! | [`create()`](VfsNodeOps::create) | Create a new node with the given path | directory |
+//! | [`symlink()`](VfsNodeOps::symlink) | Create a symbolic link with the given path | directory |
+//! | [`mknod()`](VfsNodeOps::mknod) | Create a device node with the given path | directory |
 //! | [`remove()`](VfsNodeOps::remove) | Remove the node with the given path | directory |
 //! | [`read_dir()`](VfsNodeOps::read_dir) | Read directory entries | directory |
+//! | [`read_dir_at()`](VfsNodeOps::read_dir_at) | Read directory entries from a position | directory |
 //!
 //! [inodes]: https://en.wikipedia.org/wiki/Inode
 
@@ -43,10 +56,14 @@ mod structs;
 
 pub mod path;
 
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
 use axerrno::{ax_err, AxError, AxResult};
+use core::time::Duration;
 
-pub use self::structs::{FileSystemInfo, VfsDirEntry, VfsNodeAttr, VfsNodePerm, VfsNodeType};
+pub use self::structs::{
+    DeviceId, FileSystemInfo, VfsDirEntry, VfsExtent, VfsExtentFlags, VfsNodeAttr, VfsNodeFlags,
+    VfsNodePerm, VfsNodeType,
+};
 
 /// A wrapper of [`Arc<dyn VfsNodeOps>`].
 pub type VfsNodeRef = Arc<dyn VfsNodeOps>;
@@ -69,6 +86,25 @@ pub trait VfsOps: Send + Sync {
         Ok(())
     }
 
+    /// Change the mount options of the mounted filesystem (`MS_REMOUNT`).
+    ///
+    /// `data` is a comma-separated option string such as `"ro,noatime"`.
+    fn remount(&self, _data: &str) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// Write all cached data and metadata of the filesystem to disk (`sync`).
+    fn sync(&self) -> VfsResult {
+        Ok(())
+    }
+
+    /// Whether any node of the filesystem is still in use, e.g. an open file
+    /// or a working directory. A busy filesystem can only be unmounted lazily
+    /// or forcibly.
+    fn is_busy(&self) -> bool {
+        false
+    }
+
     /// Format the filesystem.
     fn format(&self) -> VfsResult {
         ax_err!(Unsupported)
@@ -100,6 +136,34 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(Unsupported)
     }
 
+    /// Change the permission of the node (`chmod`).
+    fn set_perm(&self, _perm: VfsNodePerm) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// Change the owner and group of the node (`chown`). `None` leaves the ID
+    /// unchanged.
+    fn set_owner(&self, _uid: Option<u32>, _gid: Option<u32>) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// Change the access and modification times of the node (`utimensat`).
+    /// `None` leaves the time unchanged. The status change time is set to the
+    /// current time.
+    fn set_times(&self, _atime: Option<Duration>, _mtime: Option<Duration>) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// Get the flags of the node (`FS_IOC_GETFLAGS`).
+    fn get_flags(&self) -> VfsResult<VfsNodeFlags> {
+        Ok(VfsNodeFlags::empty())
+    }
+
+    /// Set the flags of the node (`FS_IOC_SETFLAGS`).
+    fn set_flags(&self, _flags: VfsNodeFlags) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
     // file operations:
 
     /// Read data from the file at the given offset.
@@ -122,6 +186,28 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(InvalidInput)
     }
 
+    /// Get the extents of the file that overlap `[start, start + len)`
+    /// (`FS_IOC_FIEMAP`).
+    fn fiemap(&self, _start: u64, _len: u64) -> VfsResult<Vec<VfsExtent>> {
+        ax_err!(Unsupported)
+    }
+
+    /// Get the key identifying the file data in the page cache.
+    ///
+    /// Nodes of the same file, even if obtained by separate lookups, must
+    /// return the same key, and the keys of different files must differ while
+    /// the nodes are alive. Return `None` (the default) to bypass the page
+    /// cache, e.g. for devices.
+    fn cache_key(&self) -> Option<(usize, u64)> {
+        None
+    }
+
+    /// Read the target of the symbolic link into `buf`, truncated if `buf` is
+    /// too small. Returns the number of bytes read.
+    fn readlink(&self, _buf: &mut [u8]) -> VfsResult<usize> {
+        ax_err!(InvalidInput)
+    }
+
     // directory operations:
 
     /// Get the parent directory of this directory.
@@ -145,6 +231,24 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(Unsupported)
     }
 
+    /// Create a hard link named `path` in the directory to the existing
+    /// `node`, which must belong to the same filesystem.
+    fn link(&self, _path: &str, _node: &VfsNodeRef) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// Create a symbolic link named `path` in the directory, pointing to
+    /// `target`.
+    fn symlink(&self, _path: &str, _target: &str) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
+    /// Create a character or block device node named `path` in the
+    /// directory, referring to the device `rdev`.
+    fn mknod(&self, _path: &str, _ty: VfsNodeType, _rdev: DeviceId) -> VfsResult {
+        ax_err!(Unsupported)
+    }
+
     /// Remove the node with the given `path` in the directory.
     fn remove(&self, _path: &str) -> VfsResult {
         ax_err!(Unsupported)
@@ -155,7 +259,27 @@ pub trait VfsNodeOps: Send + Sync {
         ax_err!(Unsupported)
     }
 
+    /// Read directory entries into `dirents`, starting from the opaque
+    /// position `pos`. Returns the number of entries filled.
+    ///
+    /// Each filled entry records the position to resume from in
+    /// [`VfsDirEntry::next_offset`]. Filesystems whose entries have stable
+    /// positions (e.g. hashes or on-disk offsets) should override this, so
+    /// that `getdents`, `telldir` and `seekdir` survive concurrent inserts and
+    /// deletes. The default implementation treats `pos` as an entry index.
+    fn read_dir_at(&self, pos: u64, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let n = self.read_dir(pos as usize, dirents)?;
+        for (i, ent) in dirents[..n].iter_mut().enumerate() {
+            ent.set_next_offset(pos + i as u64 + 1);
+        }
+        Ok(n)
+    }
+
     /// Renames or moves existing file or directory.
+    ///
+    /// An existing `dst_path` is replaced, if it is not a directory or is an
+    /// empty one, atomically. Filesystems that cannot replace it return
+    /// [`AlreadyExists`](VfsError::AlreadyExists) instead.
     fn rename(&self, _src_path: &str, _dst_path: &str) -> VfsResult {
         ax_err!(Unsupported)
     }
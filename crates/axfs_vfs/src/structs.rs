@@ -1,3 +1,5 @@
+use core::time::Duration;
+
 /// Filesystem attributes.
 ///
 /// Currently not used.
@@ -16,6 +18,60 @@ pub struct VfsNodeAttr {
     size: u64,
     /// Number of 512B blocks allocated.
     blocks: u64,
+    /// Number of hard links.
+    nlink: u64,
+    /// Owner user ID.
+    uid: u32,
+    /// Owner group ID.
+    gid: u32,
+    /// Time of the last access.
+    atime: Duration,
+    /// Time of the last data modification.
+    mtime: Duration,
+    /// Time of the last status change.
+    ctime: Duration,
+    /// Device number of a character or block device.
+    rdev: DeviceId,
+}
+
+/// A device number, made of a major number identifying the driver and a
+/// minor number identifying the device among those of the driver.
+///
+/// It is encoded as the 64-bit `dev_t` of glibc and musl.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeviceId(u64);
+
+impl DeviceId {
+    /// Creates a device number from its major and minor numbers.
+    pub const fn new(major: u32, minor: u32) -> Self {
+        let (major, minor) = (major as u64, minor as u64);
+        Self(
+            (major & 0xffff_f000) << 32
+                | (major & 0xfff) << 8
+                | (minor & 0xffff_ff00) << 12
+                | (minor & 0xff),
+        )
+    }
+
+    /// Creates a device number from its `dev_t` encoding.
+    pub const fn from_raw(dev: u64) -> Self {
+        Self(dev)
+    }
+
+    /// Returns the `dev_t` encoding of the device number.
+    pub const fn raw(self) -> u64 {
+        self.0
+    }
+
+    /// Returns the major number.
+    pub const fn major(self) -> u32 {
+        ((self.0 >> 32) & 0xffff_f000 | (self.0 >> 8) & 0xfff) as u32
+    }
+
+    /// Returns the minor number.
+    pub const fn minor(self) -> u32 {
+        ((self.0 >> 12) & 0xffff_ff00 | self.0 & 0xff) as u32
+    }
 }
 
 bitflags::bitflags! {
@@ -42,9 +98,63 @@ bitflags::bitflags! {
         const OTHER_WRITE = 0o2;
         /// Others have execute permission.
         const OTHER_EXEC = 0o1;
+
+        /// Set user ID on execution.
+        const SET_UID = 0o4000;
+        /// Set group ID on execution, or inherit the group in a directory.
+        const SET_GID = 0o2000;
+        /// Only the owners may remove or rename the entries of the directory.
+        const STICKY = 0o1000;
+    }
+}
+
+bitflags::bitflags! {
+    /// Node (file/directory) flags, the `FS_*_FL` values used by
+    /// `FS_IOC_GETFLAGS` and `FS_IOC_SETFLAGS`.
+    ///
+    /// Filesystems may report bits not listed here; they are kept as is.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct VfsNodeFlags: u32 {
+        /// Writes are synchronous.
+        const SYNC = 0x0000_0008;
+        /// The node cannot be modified, removed or renamed.
+        const IMMUTABLE = 0x0000_0010;
+        /// The node can only be appended to, and cannot be removed or renamed.
+        const APPEND = 0x0000_0020;
+        /// The node is skipped by `dump`.
+        const NODUMP = 0x0000_0040;
+        /// The access time is not updated.
+        const NOATIME = 0x0000_0080;
+        /// Directory updates are synchronous.
+        const DIRSYNC = 0x0001_0000;
+    }
+}
+
+bitflags::bitflags! {
+    /// Flags of a [`VfsExtent`], the `FIEMAP_EXTENT_*` values.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct VfsExtentFlags: u32 {
+        /// The last extent of the file.
+        const LAST = 0x0000_0001;
+        /// Space is allocated but not yet written.
+        const UNWRITTEN = 0x0000_0800;
     }
 }
 
+/// A range of a file mapped to the underlying device, as reported by
+/// `FS_IOC_FIEMAP`.
+#[derive(Debug, Clone, Copy)]
+pub struct VfsExtent {
+    /// Offset in the file, in bytes.
+    pub logical: u64,
+    /// Offset on the device, in bytes.
+    pub physical: u64,
+    /// Length of the extent, in bytes.
+    pub length: u64,
+    /// Extent flags.
+    pub flags: VfsExtentFlags,
+}
+
 /// Node (file/directory) type.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -67,8 +177,10 @@ pub enum VfsNodeType {
 
 /// Directory entry.
 pub struct VfsDirEntry {
+    d_ino: u64,
     d_type: VfsNodeType,
-    d_name: [u8; 63],
+    d_off: u64,
+    d_name: [u8; 255],
 }
 
 impl VfsNodePerm {
@@ -205,6 +317,13 @@ impl VfsNodeAttr {
             ty,
             size,
             blocks,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            atime: Duration::ZERO,
+            mtime: Duration::ZERO,
+            ctime: Duration::ZERO,
+            rdev: DeviceId(0),
         }
     }
 
@@ -215,6 +334,13 @@ impl VfsNodeAttr {
             ty: VfsNodeType::File,
             size,
             blocks,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            atime: Duration::ZERO,
+            mtime: Duration::ZERO,
+            ctime: Duration::ZERO,
+            rdev: DeviceId(0),
         }
     }
 
@@ -226,6 +352,13 @@ impl VfsNodeAttr {
             ty: VfsNodeType::Dir,
             size,
             blocks,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            atime: Duration::ZERO,
+            mtime: Duration::ZERO,
+            ctime: Duration::ZERO,
+            rdev: DeviceId(0),
         }
     }
 
@@ -239,6 +372,65 @@ impl VfsNodeAttr {
         self.blocks
     }
 
+    /// Returns the number of hard links to the node.
+    pub const fn nlink(&self) -> u64 {
+        self.nlink
+    }
+
+    /// Sets the number of hard links to the node.
+    pub fn set_nlink(&mut self, nlink: u64) {
+        self.nlink = nlink
+    }
+
+    /// Returns the owner user ID of the node.
+    pub const fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// Returns the owner group ID of the node.
+    pub const fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// Sets the owner user and group IDs of the node.
+    pub fn set_owner(&mut self, uid: u32, gid: u32) {
+        self.uid = uid;
+        self.gid = gid;
+    }
+
+    /// Returns the time of the last access.
+    pub const fn atime(&self) -> Duration {
+        self.atime
+    }
+
+    /// Returns the time of the last data modification.
+    pub const fn mtime(&self) -> Duration {
+        self.mtime
+    }
+
+    /// Returns the time of the last status change.
+    pub const fn ctime(&self) -> Duration {
+        self.ctime
+    }
+
+    /// Sets the access, modification and status change times of the node.
+    pub fn set_times(&mut self, atime: Duration, mtime: Duration, ctime: Duration) {
+        self.atime = atime;
+        self.mtime = mtime;
+        self.ctime = ctime;
+    }
+
+    /// Returns the device number of a character or block device, zero for
+    /// the other nodes.
+    pub const fn rdev(&self) -> DeviceId {
+        self.rdev
+    }
+
+    /// Sets the device number of a character or block device.
+    pub fn set_rdev(&mut self, rdev: DeviceId) {
+        self.rdev = rdev
+    }
+
     /// Returns the permission of the node.
     pub const fn perm(&self) -> VfsNodePerm {
         self.mode
@@ -269,14 +461,16 @@ impl VfsDirEntry {
     /// Creates an empty `VfsDirEntry`.
     pub const fn default() -> Self {
         Self {
+            d_ino: 0,
             d_type: VfsNodeType::File,
-            d_name: [0; 63],
+            d_off: 0,
+            d_name: [0; 255],
         }
     }
 
     /// Creates a new `VfsDirEntry` with the given name and type.
     pub fn new(name: &str, ty: VfsNodeType) -> Self {
-        let mut d_name = [0; 63];
+        let mut d_name = [0; 255];
         if name.len() > d_name.len() {
             log::warn!(
                 "directory entry name too long: {} > {}",
@@ -284,8 +478,14 @@ impl VfsDirEntry {
                 d_name.len()
             );
         }
-        d_name[..name.len()].copy_from_slice(name.as_bytes());
-        Self { d_type: ty, d_name }
+        let len = name.len().min(d_name.len());
+        d_name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        Self {
+            d_ino: 0,
+            d_type: ty,
+            d_off: 0,
+            d_name,
+        }
     }
 
     /// Returns the type of the entry.
@@ -293,6 +493,30 @@ impl VfsDirEntry {
         self.d_type
     }
 
+    /// Returns the inode number of the entry, or 0 if the filesystem does not
+    /// report one.
+    pub fn ino(&self) -> u64 {
+        self.d_ino
+    }
+
+    /// Sets the inode number of the entry.
+    pub fn set_ino(&mut self, ino: u64) {
+        self.d_ino = ino;
+    }
+
+    /// Returns the opaque position of the entry that follows this one.
+    ///
+    /// Passing it to [`read_dir_at`](crate::VfsNodeOps::read_dir_at) resumes
+    /// the iteration right after this entry.
+    pub fn next_offset(&self) -> u64 {
+        self.d_off
+    }
+
+    /// Sets the opaque position of the entry that follows this one.
+    pub fn set_next_offset(&mut self, off: u64) {
+        self.d_off = off;
+    }
+
     /// Converts the name of the entry to a byte slice.
     pub fn name_as_bytes(&self) -> &[u8] {
         let len = self
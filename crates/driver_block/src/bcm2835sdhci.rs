@@ -47,31 +47,58 @@ impl BaseDriverOps for SDHCIDriver {
 }
 
 impl BlockDriverOps for SDHCIDriver {
+    /// Reads the blocks one by one, the controller transfers a single block
+    /// per command.
     fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
-        if buf.len() < BLOCK_SIZE {
+        if buf.is_empty() || buf.len() % BLOCK_SIZE != 0 {
             return Err(DevError::InvalidParam);
         }
-        let (prefix, aligned_buf, suffix) = unsafe { buf.align_to_mut::<u32>() };
-        if !prefix.is_empty() || !suffix.is_empty() {
-            return Err(DevError::InvalidParam);
+        let mut bounce = [0u32; BLOCK_SIZE / 4];
+        for (i, block) in buf.chunks_exact_mut(BLOCK_SIZE).enumerate() {
+            let id = block_id as u32 + i as u32;
+            let (prefix, aligned_buf, suffix) = unsafe { block.align_to_mut::<u32>() };
+            if prefix.is_empty() && suffix.is_empty() {
+                self.0
+                    .read_block(id, 1, aligned_buf)
+                    .map_err(deal_sdhci_err)?;
+            } else {
+                // read through an aligned buffer
+                self.0
+                    .read_block(id, 1, &mut bounce)
+                    .map_err(deal_sdhci_err)?;
+                for (dst, src) in block.chunks_exact_mut(4).zip(bounce.iter()) {
+                    dst.copy_from_slice(&src.to_ne_bytes());
+                }
+            }
         }
-        self.0
-            .read_block(block_id as u32, 1, aligned_buf)
-            .map_err(deal_sdhci_err)
+        Ok(())
     }
 
+    /// Writes the blocks one by one, the controller transfers a single block
+    /// per command.
     fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
-        if buf.len() < BLOCK_SIZE {
-            return Err(DevError::Io);
-        }
-        let (prefix, aligned_buf, suffix) = unsafe { buf.align_to::<u32>() };
-        if !prefix.is_empty() || !suffix.is_empty() {
+        if buf.is_empty() || buf.len() % BLOCK_SIZE != 0 {
             return Err(DevError::InvalidParam);
         }
-        self.0
-            .write_block(block_id as u32, 1, aligned_buf)
-            .map_err(deal_sdhci_err)
+        let mut bounce = [0u32; BLOCK_SIZE / 4];
+        for (i, block) in buf.chunks_exact(BLOCK_SIZE).enumerate() {
+            let id = block_id as u32 + i as u32;
+            let (prefix, aligned_buf, suffix) = unsafe { block.align_to::<u32>() };
+            if prefix.is_empty() && suffix.is_empty() {
+                self.0
+                    .write_block(id, 1, aligned_buf)
+                    .map_err(deal_sdhci_err)?;
+            } else {
+                // write through an aligned buffer
+                for (dst, src) in bounce.iter_mut().zip(block.chunks_exact(4)) {
+                    *dst = u32::from_ne_bytes(src.try_into().unwrap());
+                }
+                self.0.write_block(id, 1, &bounce).map_err(deal_sdhci_err)?;
+            }
+        }
+        Ok(())
     }
+
     fn flush(&mut self) -> DevResult {
         Ok(())
     }
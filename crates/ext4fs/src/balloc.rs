@@ -0,0 +1,429 @@
+//! 块的分配与释放
+//!
+//! 与 inode 的分配相同，从目标块所在的块组开始依次查找有空闲块的块组，在块位图中分配，
+//! 并更新块组描述符和超级块中的空闲块数以及位图的校验和。块位图尚未初始化
+//! （`BLOCK_UNINIT`）的块组需要先算出其中的元数据块，分配时直接跳过。
+//!
+//! 释放 inode 时按它的格式找出所有块：extent 树的数据块和索引块，旧格式的直接块和
+//! 间接块，以及扩展属性块。
+
+use alloc::vec::Vec;
+
+use crate::crc::ext4_crc32c;
+use crate::defs::*;
+use crate::dir::{read_u16, read_u32};
+use crate::ialloc::EXT4_FEATURE_RO_COMPAT_GDT_CSUM;
+use crate::inode::EXT4_EXT_MAGIC;
+use crate::link::Ext4LinkError;
+use crate::{Ext4Fs, Ext4Traits};
+
+/// 组描述符中包含 `bg_block_bitmap_csum_hi` 所需的最小长度
+const EXT4_BG_BLOCK_BITMAP_CSUM_HI_END: usize = 0x3A;
+/// 长度大于该值的 extent 是未初始化的
+const EXT_INIT_MAX_LEN: u16 = 32768;
+/// extent 树节点头部和每一项的长度
+const EXT4_EXT_ENTRY_SIZE: usize = 12;
+/// 扩展属性块的魔数
+const EXT4_XATTR_MAGIC: u32 = 0xEA02_0000;
+/// 扩展属性块头部中 `h_refcount` 的偏移
+const EXT4_XATTR_REFCOUNT_OFFSET: usize = 4;
+/// 扩展属性块头部中 `h_checksum` 的偏移
+const EXT4_XATTR_CSUM_OFFSET: usize = 0x10;
+/// 直接块的个数，之后依次是一级、二级和三级间接块
+const EXT4_NDIR_BLOCKS: usize = 12;
+
+fn ext4_bg_free_blocks(desc: &GroupDesc) -> u32 {
+    desc.bg_free_blocks_count_lo as u32 | (desc.bg_free_blocks_count_hi as u32) << 16
+}
+
+fn ext4_bg_set_free_blocks(desc: &mut GroupDesc, count: u32) {
+    desc.bg_free_blocks_count_lo = count as u16;
+    desc.bg_free_blocks_count_hi = (count >> 16) as u16;
+}
+
+fn ext4_bg_block_bitmap(desc: &GroupDesc) -> u64 {
+    desc.bg_block_bitmap_lo as u64 | (desc.bg_block_bitmap_hi as u64) << 32
+}
+
+fn ext4_sb_free_blocks(sb: &Ext4SuperBlock) -> u64 {
+    sb.free_blocks_count as u64 | (sb.free_blocks_count_hi as u64) << 32
+}
+
+fn ext4_sb_set_free_blocks(sb: &mut Ext4SuperBlock, count: u64) {
+    sb.free_blocks_count = count as u32;
+    sb.free_blocks_count_hi = (count >> 32) as u32;
+}
+
+fn write_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// 把 `i_block` 转换为字节，extent 树的根节点保存在其中
+fn ext4_iblock_bytes(block: &[u32; 15]) -> [u8; 60] {
+    let mut bytes = [0u8; 60];
+    for (dst, word) in bytes.chunks_exact_mut(4).zip(block) {
+        dst.copy_from_slice(&word.to_le_bytes());
+    }
+    bytes
+}
+
+impl Ext4Fs {
+    /// 文件系统的总块数
+    fn ext4_blocks_count(&self) -> u64 {
+        self.super_block.blocks_count as u64 | (self.super_block.blocks_count_hi as u64) << 32
+    }
+
+    /// 块组 `group` 中的块数，最后一个块组可能不满
+    fn ext4_blocks_in_group(&self, group: u64) -> u32 {
+        let per_group = self.super_block.blocks_per_group as u64;
+        let start = self.super_block.first_data_block as u64 + group * per_group;
+        self.ext4_blocks_count()
+            .saturating_sub(start)
+            .min(per_group) as u32
+    }
+
+    /// 写回块组的块位图，同时更新描述符中位图的校验和
+    fn ext4_write_block_bitmap(&self, desc: &mut GroupDesc, bitmap: &[u8]) {
+        if self.ext4_has_metadata_csum() {
+            let len = self.super_block.blocks_per_group as usize / 8;
+            let csum = ext4_crc32c(self.ext4_csum_seed(), &bitmap[..len]);
+            desc.bg_block_bitmap_csum_lo = csum as u16;
+            if self.ext4_desc_size() >= EXT4_BG_BLOCK_BITMAP_CSUM_HI_END {
+                desc.bg_block_bitmap_csum_hi = (csum >> 16) as u16;
+            }
+        }
+        self.block_device
+            .write_block(ext4_bg_block_bitmap(desc) as usize, bitmap);
+    }
+
+    /// 在块组 `group` 的块位图中分配一个块，从组内下标 `first` 开始查找
+    fn ext4_alloc_block_in_group(&self, group: u64, first: u32) -> Option<u32> {
+        let mut desc = self.ext4_read_group_desc(group);
+        if ext4_bg_free_blocks(&desc) == 0 || desc.bg_flags.contains(GroupFlags::BLOCK_UNINIT) {
+            return None;
+        }
+        let mut bitmap = self.read_block(ext4_bg_block_bitmap(&desc) * BLOCK_SIZE);
+        let len = self.ext4_blocks_in_group(group);
+        let first = first.min(len);
+        let index = (first..len)
+            .chain(0..first)
+            .find(|&i| bitmap[i as usize / 8] & (1 << (i % 8)) == 0)?;
+        bitmap[index as usize / 8] |= 1 << (index % 8);
+
+        let free = ext4_bg_free_blocks(&desc) - 1;
+        ext4_bg_set_free_blocks(&mut desc, free);
+        self.ext4_write_block_bitmap(&mut desc, &bitmap);
+        self.ext4_write_group_desc(group, &desc);
+        Some(index)
+    }
+
+    /// 分配一个块，尽量靠近 `goal`，返回块号
+    pub fn ext4_alloc_block(&self, goal: u64) -> Result<u64, Ext4LinkError> {
+        let sb = &self.super_block;
+        if sb.feature_ro_compat & EXT4_FEATURE_RO_COMPAT_GDT_CSUM != 0
+            && !self.ext4_has_metadata_csum()
+        {
+            return Err(Ext4LinkError::Unsupported);
+        }
+        let first_data = sb.first_data_block as u64;
+        let per_group = sb.blocks_per_group as u64;
+        let goal = goal.clamp(first_data, self.ext4_blocks_count() - 1) - first_data;
+        let groups = self.ext4_group_count();
+        let start = goal / per_group;
+        let block = (0..groups)
+            .find_map(|i| {
+                let group = (start + i) % groups;
+                let first = if i == 0 { (goal % per_group) as u32 } else { 0 };
+                let index = self.ext4_alloc_block_in_group(group, first)?;
+                Some(first_data + group * per_group + index as u64)
+            })
+            .ok_or(Ext4LinkError::NoSpace)?;
+        self.ext4_update_super_block(|sb| {
+            let free = ext4_sb_free_blocks(sb).saturating_sub(1);
+            ext4_sb_set_free_blocks(sb, free);
+        });
+        log::debug!("ext4: allocate block {}", block);
+        Ok(block)
+    }
+
+    /// 释放从 `start` 开始的 `count` 个块，可以跨越块组
+    pub fn ext4_free_blocks(&self, start: u64, count: u64) {
+        let first_data = self.super_block.first_data_block as u64;
+        let per_group = self.super_block.blocks_per_group as u64;
+        let end = start.saturating_add(count);
+        if start < first_data || end > self.ext4_blocks_count() {
+            self.ext4_error(
+                0,
+                start,
+                "ext4_free_blocks",
+                line!(),
+                "freeing blocks not in datazone",
+            );
+            return;
+        }
+        let mut freed_total = 0;
+        let mut block = start;
+        while block < end {
+            let group = (block - first_data) / per_group;
+            let index = (block - first_data) % per_group;
+            let n = (per_group - index).min(end - block);
+            let mut desc = self.ext4_read_group_desc(group);
+            let mut bitmap = self.read_block(ext4_bg_block_bitmap(&desc) * BLOCK_SIZE);
+            let mut freed = 0;
+            for i in index as usize..(index + n) as usize {
+                if bitmap[i / 8] & (1 << (i % 8)) != 0 {
+                    bitmap[i / 8] &= !(1 << (i % 8));
+                    freed += 1;
+                }
+            }
+            if freed as u64 != n {
+                self.ext4_error(0, block, "ext4_free_blocks", line!(), "bit already cleared");
+            }
+            let free = ext4_bg_free_blocks(&desc) + freed;
+            ext4_bg_set_free_blocks(&mut desc, free);
+            self.ext4_write_block_bitmap(&mut desc, &bitmap);
+            self.ext4_write_group_desc(group, &desc);
+            freed_total += freed as u64;
+            block += n;
+        }
+        self.ext4_update_super_block(|sb| {
+            let free = ext4_sb_free_blocks(sb) + freed_total;
+            ext4_sb_set_free_blocks(sb, free);
+        });
+    }
+
+    /// inode 自己的校验和种子，用于 extent 块、目录块等属于它的元数据
+    pub(crate) fn ext4_inode_csum_seed(&self, ino: u32, generation: u32) -> u32 {
+        let csum = ext4_crc32c(self.ext4_csum_seed(), &ino.to_le_bytes());
+        ext4_crc32c(csum, &generation.to_le_bytes())
+    }
+
+    /// 在 inode 的末尾追加一个块，返回它的物理块号
+    ///
+    /// 新块尽量紧跟最后一个 extent，这样只需增加它的长度；否则在最右侧的叶子节点中新增
+    /// 一个 extent，叶子节点已满时需要分裂 extent 树，暂不支持。`inode_data` 中的
+    /// extent 树根节点和块数会被更新，由调用者修改 `i_size` 后写回。
+    pub(crate) fn ext4_append_block(
+        &self,
+        ino: u32,
+        inode_data: &mut Ext4Inode,
+    ) -> Result<u64, Ext4LinkError> {
+        let mut root = ext4_iblock_bytes(&inode_data.block);
+        if read_u16(&root, 0) != EXT4_EXT_MAGIC {
+            return Err(Ext4LinkError::Unsupported);
+        }
+        let lblk = (inode_data.size as u64).div_ceil(BLOCK_SIZE) as u32;
+
+        // 沿最后一个索引找到最右侧的叶子节点
+        let mut leaf_pblk = None;
+        let mut leaf = Vec::new();
+        let mut depth = read_u16(&root, 6);
+        while depth > 0 {
+            let node = leaf_pblk.map_or(&root[..], |_| &leaf[..]);
+            let entries = read_u16(node, 2) as usize;
+            if entries == 0 {
+                return Err(Ext4LinkError::Unsupported);
+            }
+            let last = EXT4_EXT_ENTRY_SIZE * entries;
+            let pblk = read_u32(node, last + 4) as u64 | (read_u16(node, last + 8) as u64) << 32;
+            leaf = self.read_block(pblk * BLOCK_SIZE);
+            if read_u16(&leaf, 0) != EXT4_EXT_MAGIC || read_u16(&leaf, 6) != depth - 1 {
+                self.ext4_error(
+                    ino,
+                    pblk,
+                    "ext4_append_block",
+                    line!(),
+                    "invalid extent header",
+                );
+                return Err(Ext4LinkError::Unsupported);
+            }
+            leaf_pblk = Some(pblk);
+            depth -= 1;
+        }
+        let node = match leaf_pblk {
+            Some(_) => &mut leaf[..],
+            None => &mut root[..],
+        };
+        let entries = read_u16(node, 2) as usize;
+        let max = read_u16(node, 4) as usize;
+
+        // 紧跟在最后一个 extent 之后的位置
+        let last = (entries > 0).then(|| {
+            let offset = EXT4_EXT_ENTRY_SIZE * entries;
+            let len = read_u16(node, offset + 4);
+            let start =
+                read_u32(node, offset + 8) as u64 | (read_u16(node, offset + 6) as u64) << 32;
+            (offset, read_u32(node, offset), len, start)
+        });
+        let goal = match last {
+            Some((_, _, len, start)) => start + (len % EXT_INIT_MAX_LEN) as u64,
+            None => self.ext4_inode_group_start(ino),
+        };
+        let pblk = self.ext4_alloc_block(goal)?;
+        match last {
+            Some((offset, first, len, start))
+                if len < EXT_INIT_MAX_LEN
+                    && first + len as u32 == lblk
+                    && start + len as u64 == pblk =>
+            {
+                write_u16(node, offset + 4, len + 1);
+            }
+            _ if entries < max => {
+                let offset = EXT4_EXT_ENTRY_SIZE * (entries + 1);
+                write_u32(node, offset, lblk);
+                write_u16(node, offset + 4, 1);
+                write_u16(node, offset + 6, (pblk >> 32) as u16);
+                write_u32(node, offset + 8, pblk as u32);
+                write_u16(node, 2, entries as u16 + 1);
+            }
+            _ => {
+                self.ext4_free_blocks(pblk, 1);
+                return Err(Ext4LinkError::NoSpace);
+            }
+        }
+
+        match leaf_pblk {
+            Some(leaf_pblk) => {
+                if self.ext4_has_metadata_csum() {
+                    let tail = EXT4_EXT_ENTRY_SIZE * (max + 1);
+                    let seed = self.ext4_inode_csum_seed(ino, inode_data.generation);
+                    let csum = ext4_crc32c(seed, &leaf[..tail]);
+                    write_u32(&mut leaf, tail, csum);
+                }
+                self.block_device.write_block(leaf_pblk as usize, &leaf);
+            }
+            None => {
+                for (word, src) in inode_data.block.iter_mut().zip(root.chunks_exact(4)) {
+                    *word = u32::from_le_bytes(src.try_into().unwrap());
+                }
+            }
+        }
+        inode_data.blocks += (BLOCK_SIZE / 512) as u32;
+        Ok(pblk)
+    }
+
+    /// inode 所在块组的第一个块，作为分配它的第一个块时的目标
+    fn ext4_inode_group_start(&self, ino: u32) -> u64 {
+        let group = (ino - 1) as u64 / self.super_block.inodes_per_group as u64;
+        self.super_block.first_data_block as u64 + group * self.super_block.blocks_per_group as u64
+    }
+
+    /// 释放 inode 占用的所有块，包括扩展属性块
+    ///
+    /// 设备文件、FIFO、套接字、快速符号链接和内联数据没有数据块。
+    pub(crate) fn ext4_free_inode_blocks(&self, ino: u32, inode_data: &Ext4Inode) {
+        let ty = FileMode::from_bits_truncate(inode_data.mode & FileMode::S_IFMT.bits());
+        let flags = IFlags::from_bits_truncate(inode_data.flags);
+        let xattr_block = inode_data.file_acl as u64 | (read_u16(&inode_data.osd2, 2) as u64) << 32;
+        let has_data = match ty {
+            FileMode::S_IFREG | FileMode::S_IFDIR => true,
+            // 快速符号链接的目标保存在 i_block 中
+            FileMode::S_IFLNK => {
+                let xattr_blocks = if xattr_block != 0 {
+                    BLOCK_SIZE / 512
+                } else {
+                    0
+                };
+                inode_data.blocks as u64 > xattr_blocks
+            }
+            _ => false,
+        };
+        if has_data && !flags.contains(IFlags::EXT4_INLINE_DATA_FL) {
+            if flags.contains(IFlags::EXT4_EXTENTS_FL) {
+                self.ext4_free_extent_node(ino, &ext4_iblock_bytes(&inode_data.block));
+            } else {
+                for (i, &block) in inode_data.block.iter().enumerate() {
+                    let level = i.saturating_sub(EXT4_NDIR_BLOCKS - 1) as u32;
+                    self.ext4_free_indirect(block as u64, level);
+                }
+            }
+        }
+        if xattr_block != 0 {
+            self.ext4_release_xattr_block(xattr_block);
+        }
+    }
+
+    /// 释放 extent 树节点 `node` 所指向的所有块，包括下层的索引块
+    fn ext4_free_extent_node(&self, ino: u32, node: &[u8]) {
+        if read_u16(node, 0) != EXT4_EXT_MAGIC {
+            self.ext4_error(
+                ino,
+                0,
+                "ext4_free_extent_node",
+                line!(),
+                "invalid extent header",
+            );
+            return;
+        }
+        let entries = read_u16(node, 2) as usize;
+        let depth = read_u16(node, 6);
+        for i in 1..=entries {
+            let offset = EXT4_EXT_ENTRY_SIZE * i;
+            if offset + EXT4_EXT_ENTRY_SIZE > node.len() {
+                break;
+            }
+            if depth == 0 {
+                let len = read_u16(node, offset + 4);
+                let len = if len > EXT_INIT_MAX_LEN {
+                    len - EXT_INIT_MAX_LEN
+                } else {
+                    len
+                };
+                let start =
+                    read_u32(node, offset + 8) as u64 | (read_u16(node, offset + 6) as u64) << 32;
+                self.ext4_free_blocks(start, len as u64);
+            } else {
+                let child =
+                    read_u32(node, offset + 4) as u64 | (read_u16(node, offset + 8) as u64) << 32;
+                self.ext4_free_extent_node(ino, &self.read_block(child * BLOCK_SIZE));
+                self.ext4_free_blocks(child, 1);
+            }
+        }
+    }
+
+    /// 释放旧格式的块 `block`，`level` 为它作为间接块的层数，0 表示数据块
+    fn ext4_free_indirect(&self, block: u64, level: u32) {
+        if block == 0 {
+            return;
+        }
+        if level > 0 {
+            let data = self.read_block(block * BLOCK_SIZE);
+            for offset in (0..data.len()).step_by(4) {
+                self.ext4_free_indirect(read_u32(&data, offset) as u64, level - 1);
+            }
+        }
+        self.ext4_free_blocks(block, 1);
+    }
+
+    /// 减少扩展属性块的引用计数，不再被引用时释放它
+    fn ext4_release_xattr_block(&self, block: u64) {
+        let mut data = self.read_block(block * BLOCK_SIZE);
+        if read_u32(&data, 0) != EXT4_XATTR_MAGIC {
+            self.ext4_error(
+                0,
+                block,
+                "ext4_release_xattr_block",
+                line!(),
+                "invalid xattr block",
+            );
+            return;
+        }
+        let refcount = read_u32(&data, EXT4_XATTR_REFCOUNT_OFFSET);
+        if refcount <= 1 {
+            self.ext4_free_blocks(block, 1);
+            return;
+        }
+        write_u32(&mut data, EXT4_XATTR_REFCOUNT_OFFSET, refcount - 1);
+        if self.ext4_has_metadata_csum() {
+            write_u32(&mut data, EXT4_XATTR_CSUM_OFFSET, 0);
+            let csum = ext4_crc32c(self.ext4_csum_seed(), &block.to_le_bytes());
+            let csum = ext4_crc32c(csum, &data);
+            write_u32(&mut data, EXT4_XATTR_CSUM_OFFSET, csum);
+        }
+        self.block_device.write_block(block as usize, &data);
+    }
+}
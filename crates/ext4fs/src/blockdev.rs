@@ -10,4 +10,6 @@ pub trait BlockDevice: Send + Any {
     fn block_size(&self) -> usize;
     /// Get block num
     fn block_num(&self) -> usize;
+    /// Flush the data cached by the device to the storage
+    fn flush(&self) {}
 }
\ No newline at end of file
@@ -0,0 +1,36 @@
+//! 元数据校验使用的 crc32c（Castagnoli）
+
+const CRC32C_POLY: u32 = 0x82F6_3B78;
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32C_POLY
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+/// 在 `crc` 的基础上继续计算 `data` 的 crc32c
+///
+/// 与 Linux 的 `ext4_chksum` 一致，不做首尾取反。
+pub fn ext4_crc32c(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = crc;
+    for &b in data {
+        crc = CRC32C_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
@@ -101,7 +101,8 @@ pub struct Ext4SuperBlock {
     pub lpf_ino: u32,
     pub prj_quota_inum: u32,
     pub checksum_seed: u32,
-    pub padding2: [u8; 100],
+    /// 从 `s_wtime_hi` 到 `s_reserved` 的字段，`checksum` 位于偏移 0x3FC
+    pub padding2: [u8; 392],
     pub checksum: u32,
 }
 
@@ -437,12 +438,30 @@ bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
     pub struct IFlags: u32 {
         // 定义每个flag的名字和值，使用16进制字面量
+        // EXT4_SECRM_FL是安全删除的标志
+        const EXT4_SECRM_FL = 0x0000_0001; // 安全删除
+        // EXT4_UNRM_FL是可恢复删除的标志
+        const EXT4_UNRM_FL = 0x0000_0002; // 可恢复删除
+        // EXT4_COMPR_FL是压缩文件的标志
+        const EXT4_COMPR_FL = 0x0000_0004; // 压缩文件
+        // EXT4_SYNC_FL是同步写入的标志
+        const EXT4_SYNC_FL = 0x0000_0008; // 同步写入
+        // EXT4_IMMUTABLE_FL是不可修改的标志
+        const EXT4_IMMUTABLE_FL = 0x0000_0010; // 不可修改、删除和重命名
+        // EXT4_APPEND_FL是只能追加写入的标志
+        const EXT4_APPEND_FL = 0x0000_0020; // 只能追加写入
+        // EXT4_NODUMP_FL是不参与dump的标志
+        const EXT4_NODUMP_FL = 0x0000_0040; // 不参与dump
+        // EXT4_NOATIME_FL是不更新访问时间的标志
+        const EXT4_NOATIME_FL = 0x0000_0080; // 不更新访问时间
+        // EXT4_INDEX_FL是目录使用htree索引的标志
+        const EXT4_INDEX_FL = 0x0000_1000; // htree索引目录
         // EXT4_EA_INODE_FL是扩展属性的inode标志
         const EXT4_EA_INODE_FL = 0x0020_0000; // 扩展属性的inode
         // EXT4_HUGE_FILE_FL是大文件标志
         const EXT4_HUGE_FILE_FL = 0x0040_0000; // 大文件
         // EXT4_EXTENTS_FL是使用extents的标志
-        const EXT4_EXTENTS_FL = 0x0080_0000; // 使用extents
+        const EXT4_EXTENTS_FL = 0x0008_0000; // 使用extents
         // EXT4_EOFBLOCKS_FL是有额外的块的标志
         const EXT4_EOFBLOCKS_FL = 0x0200_0000; // 有额外的块
         // EXT4_SNAPFILE_FL是快照文件的标志
@@ -0,0 +1,316 @@
+//! 目录遍历
+//!
+//! 每个目录项都有一个不透明的位置，遍历可以从任意位置恢复：
+//! - 线性目录的位置是目录项在目录文件中的字节偏移（逻辑块号 * 块大小 + 块内偏移）；
+//! - htree 目录的位置由文件名哈希构成：`(major >> 1) << 32 | minor`。
+//!
+//! 位置只依赖目录项本身，因此在两次遍历之间插入或删除其他目录项不会造成重复或遗漏。
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::defs::*;
+use crate::hash::*;
+use crate::{Ext4Fs, Ext4Traits};
+
+/// 超级块 `flags` 中表示使用无符号字符计算哈希的标志
+pub const EXT2_FLAGS_UNSIGNED_HASH: u32 = 0x0002;
+
+/// htree 支持的最大索引层数（开启 largedir 时为 3）
+const EXT4_HTREE_LEVEL: usize = 3;
+
+/// 遍历目录得到的一个目录项
+#[derive(Debug, Clone)]
+pub struct Ext4DirIterEntry {
+    /// 目录项指向的 inode
+    pub inode: u32,
+    /// 文件名
+    pub name: String,
+    /// 目录项中记录的文件类型，见 [`DirEntryType`]
+    pub file_type: u8,
+    /// 该目录项的位置
+    pub pos: u64,
+}
+
+impl Ext4DirIterEntry {
+    /// 紧跟在该目录项之后的位置，用作下一次遍历的起点
+    pub fn next_pos(&self) -> u64 {
+        self.pos + 1
+    }
+}
+
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// 目录块中解析出的目录项：`(块内偏移, inode, 文件类型, 文件名)`
+pub(crate) type DirBlockEntry<'a> = (usize, u32, u8, &'a [u8]);
+
+/// 解析一个目录块，跳过空闲的目录项
+///
+/// 遇到损坏的目录项时停止，并返回它的块内偏移。
+pub(crate) fn ext4_dir_block_entries(block: &[u8]) -> (Vec<DirBlockEntry<'_>>, Option<usize>) {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset + 8 <= block.len() {
+        let inode = read_u32(block, offset);
+        let rec_len = read_u16(block, offset + 4) as usize;
+        let name_len = block[offset + 6] as usize;
+        let file_type = block[offset + 7];
+        if rec_len < 8 || offset + rec_len > block.len() || 8 + name_len > rec_len {
+            return (entries, Some(offset));
+        }
+        if inode != 0 {
+            entries.push((offset, inode, file_type, &block[offset + 8..offset + 8 + name_len]));
+        }
+        offset += rec_len;
+    }
+    (entries, None)
+}
+
+/// 把 htree 哈希转换为目录位置
+fn ext4_hash2pos(major: u32, minor: u32) -> u64 {
+    (((major >> 1) as u64) << 32) | minor as u64
+}
+
+/// 在 `max` 处截断，但不拆开位置相同的一组目录项，以免下次从 `next_pos` 恢复时遗漏
+fn ext4_truncate_entries(entries: &mut Vec<Ext4DirIterEntry>, max: usize) {
+    if entries.len() <= max {
+        return;
+    }
+    let mut n = max;
+    while n > 0 && entries[n].pos == entries[n - 1].pos {
+        n -= 1;
+    }
+    entries.truncate(if n == 0 { max } else { n });
+}
+
+impl Ext4Fs {
+    /// 获取目录每个逻辑块对应的物理块号
+    pub fn ext4_dir_blocks(&self, inode: &Ext4Inode) -> Vec<u64> {
+        let total_blocks = (inode.size as u64).div_ceil(BLOCK_SIZE);
+        let mut extents: Vec<Ext4Extent> = Vec::new();
+        self.ext4_find_extent(inode, &mut extents);
+
+        let mut blocks = Vec::new();
+        for lblk in 0..total_blocks as u32 {
+            let pblk = extents.iter().find_map(|e| {
+                // 长度大于 32768 的是未初始化的 extent
+                let len = if e.ee_len > 32768 {
+                    e.ee_len - 32768
+                } else {
+                    e.ee_len
+                } as u32;
+                if lblk >= e.ee_block && lblk < e.ee_block + len {
+                    let start = ((e.ee_start_hi as u64) << 32) | e.ee_start_lo as u64;
+                    Some(start + (lblk - e.ee_block) as u64)
+                } else {
+                    None
+                }
+            });
+            match pblk {
+                Some(pblk) => blocks.push(pblk),
+                None => {
+                    log::warn!("directory hole at logical block {}", lblk);
+                    break;
+                }
+            }
+        }
+        blocks
+    }
+
+    /// 从位置 `pos` 开始读取目录 `inode` 中至多 `max` 个目录项
+    ///
+    /// 返回的目录项按位置递增排列，下一次遍历应从最后一项的
+    /// [`next_pos`](Ext4DirIterEntry::next_pos) 开始。返回空表示已经读完。
+    pub fn ext4_dir_read_from(&self, inode: u32, pos: u64, max: usize) -> Vec<Ext4DirIterEntry> {
+        let inode_data = self.ext4_read_inode(inode as u64, &self.super_block);
+        let blocks = self.ext4_dir_blocks(&inode_data);
+        if max == 0 || blocks.is_empty() {
+            return Vec::new();
+        }
+
+        let flags = IFlags::from_bits_truncate(inode_data.flags);
+        if flags.contains(IFlags::EXT4_INDEX_FL) {
+            if let Some(entries) = self.ext4_htree_read_from(inode, &blocks, pos, max) {
+                return entries;
+            }
+            // 与 Linux 一致，索引损坏时按线性目录读取
+            self.ext4_error(inode, blocks[0], "ext4_dir_read_from", line!(), "invalid htree index");
+        }
+        self.ext4_linear_read_from(inode, &blocks, pos, max)
+    }
+
+    /// 解析目录 `dir` 的物理块 `pblk`，记录发现的损坏
+    fn ext4_dir_block_checked<'a>(&self, dir: u32, pblk: u64, data: &'a [u8]) -> Vec<DirBlockEntry<'a>> {
+        let (entries, corrupted) = ext4_dir_block_entries(data);
+        if let Some(offset) = corrupted {
+            let msg = format!("corrupted directory entry at offset {}", offset);
+            self.ext4_error(dir, pblk, "ext4_dir_read_from", line!(), &msg);
+        }
+        entries
+    }
+
+    fn ext4_linear_read_from(
+        &self,
+        dir: u32,
+        blocks: &[u64],
+        pos: u64,
+        max: usize,
+    ) -> Vec<Ext4DirIterEntry> {
+        let mut entries = Vec::new();
+        let first = (pos / BLOCK_SIZE) as usize;
+        for (lblk, &pblk) in blocks.iter().enumerate().skip(first) {
+            let data = self.read_block(pblk * BLOCK_SIZE);
+            let base = lblk as u64 * BLOCK_SIZE;
+            for (offset, inode, file_type, name) in self.ext4_dir_block_checked(dir, pblk, &data) {
+                let entry_pos = base + offset as u64;
+                if entry_pos < pos {
+                    continue;
+                }
+                entries.push(Ext4DirIterEntry {
+                    inode,
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    file_type,
+                    pos: entry_pos,
+                });
+                if entries.len() == max {
+                    return entries;
+                }
+            }
+        }
+        entries
+    }
+
+    /// 收集 htree 所有叶子块，返回 `(起始哈希, 逻辑块号)`，按哈希递增排列
+    fn ext4_htree_collect_leaves(
+        &self,
+        blocks: &[u64],
+        node: &[u8],
+        offset: usize,
+        start_hash: u32,
+        levels: usize,
+        leaves: &mut Vec<(u32, u32)>,
+    ) -> Option<()> {
+        if offset + 8 > node.len() {
+            return None;
+        }
+        let limit = read_u16(node, offset) as usize;
+        let count = read_u16(node, offset + 2) as usize;
+        if count == 0 || count > limit || offset + count * 8 > node.len() {
+            return None;
+        }
+        for i in 0..count {
+            let hash = if i == 0 {
+                start_hash
+            } else {
+                read_u32(node, offset + i * 8)
+            };
+            let block = read_u32(node, offset + i * 8 + 4);
+            if block as usize >= blocks.len() {
+                return None;
+            }
+            if levels == 0 {
+                leaves.push((hash, block));
+            } else {
+                let child = self.read_block(blocks[block as usize] * BLOCK_SIZE);
+                // 中间节点以一个空的目录项开头，索引紧随其后
+                self.ext4_htree_collect_leaves(blocks, &child, 8, hash, levels - 1, leaves)?;
+            }
+        }
+        Some(())
+    }
+
+    /// 解析 htree 的根块，返回哈希算法和所有叶子块，叶子块的格式见
+    /// [`ext4_htree_collect_leaves`](Self::ext4_htree_collect_leaves)
+    pub(crate) fn ext4_htree_root(&self, blocks: &[u64]) -> Option<(u8, Vec<(u32, u32)>)> {
+        let root = self.read_block(blocks[0] * BLOCK_SIZE);
+        // dx_root_info 位于 "." 和 ".." 两个目录项之后
+        if read_u32(&root, 24) != 0 {
+            return None;
+        }
+        let mut hash_version = root[28];
+        let info_length = root[29] as usize;
+        let levels = root[30] as usize;
+        if levels >= EXT4_HTREE_LEVEL {
+            return None;
+        }
+        if hash_version <= DX_HASH_TEA && self.super_block.flags & EXT2_FLAGS_UNSIGNED_HASH != 0 {
+            hash_version += 3;
+        }
+        let mut leaves = Vec::new();
+        self.ext4_htree_collect_leaves(blocks, &root, 24 + info_length, 0, levels, &mut leaves)?;
+        Some((hash_version, leaves))
+    }
+
+    fn ext4_htree_read_from(
+        &self,
+        dir: u32,
+        blocks: &[u64],
+        pos: u64,
+        max: usize,
+    ) -> Option<Vec<Ext4DirIterEntry>> {
+        let (hash_version, leaves) = self.ext4_htree_root(blocks)?;
+        let seed = self.super_block.hash_seed;
+        let root = self.read_block(blocks[0] * BLOCK_SIZE);
+
+        // "." 和 ".." 只出现在根块中，约定它们的哈希分别为 0 和 2
+        let mut pending: Vec<Ext4DirIterEntry> = ext4_dir_block_entries(&root)
+            .0
+            .into_iter()
+            .take(2)
+            .zip([0u64, ext4_hash2pos(2, 0)])
+            .filter(|(_, p)| *p >= pos)
+            .map(|((_, inode, file_type, name), p)| Ext4DirIterEntry {
+                inode,
+                name: String::from_utf8_lossy(name).into_owned(),
+                file_type,
+                pos: p,
+            })
+            .collect();
+
+        let major = ((pos >> 32) as u32) << 1;
+        let start = leaves.partition_point(|&(h, _)| h <= major).saturating_sub(1);
+        for j in start..leaves.len() {
+            let pblk = blocks[leaves[j].1 as usize];
+            let data = self.read_block(pblk * BLOCK_SIZE);
+            for (_, inode, file_type, name) in self.ext4_dir_block_checked(dir, pblk, &data) {
+                let (h, m) = ext4_dirhash(name, hash_version, &seed);
+                let entry_pos = ext4_hash2pos(h, m);
+                if entry_pos < pos {
+                    continue;
+                }
+                pending.push(Ext4DirIterEntry {
+                    inode,
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    file_type,
+                    pos: entry_pos,
+                });
+            }
+            pending.sort_by(|a, b| a.pos.cmp(&b.pos).then_with(|| a.name.cmp(&b.name)));
+
+            // 后续叶子块中的哈希都不小于下一个叶子块的起始哈希，
+            // 在此之前的目录项已经全部收集完毕
+            if let Some(&(next_hash, _)) = leaves.get(j + 1) {
+                let bound = ext4_hash2pos(next_hash & !1, 0);
+                let complete = pending.partition_point(|e| e.pos < bound);
+                if complete >= max {
+                    pending.truncate(complete);
+                    break;
+                }
+            }
+        }
+        ext4_truncate_entries(&mut pending, max);
+        Some(pending)
+    }
+}
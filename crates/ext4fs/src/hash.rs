@@ -0,0 +1,208 @@
+//! htree 目录使用的文件名哈希，与 Linux `fs/ext4/hash.c` 保持一致
+
+/// legacy 哈希（有符号字符）
+pub const DX_HASH_LEGACY: u8 = 0;
+/// half MD4 哈希（有符号字符）
+pub const DX_HASH_HALF_MD4: u8 = 1;
+/// TEA 哈希（有符号字符）
+pub const DX_HASH_TEA: u8 = 2;
+/// legacy 哈希（无符号字符）
+pub const DX_HASH_LEGACY_UNSIGNED: u8 = 3;
+/// half MD4 哈希（无符号字符）
+pub const DX_HASH_HALF_MD4_UNSIGNED: u8 = 4;
+/// TEA 哈希（无符号字符）
+pub const DX_HASH_TEA_UNSIGNED: u8 = 5;
+
+/// 32 位哈希空间的结束标记
+pub const EXT4_HTREE_EOF_32BIT: u32 = 0x7fff_ffff;
+
+const DELTA: u32 = 0x9E37_79B9;
+
+fn tea_transform(buf: &mut [u32; 4], input: &[u32]) {
+    let mut sum: u32 = 0;
+    let (mut b0, mut b1) = (buf[0], buf[1]);
+    let (a, b, c, d) = (input[0], input[1], input[2], input[3]);
+
+    for _ in 0..16 {
+        sum = sum.wrapping_add(DELTA);
+        b0 = b0.wrapping_add(
+            (b1 << 4).wrapping_add(a) ^ b1.wrapping_add(sum) ^ (b1 >> 5).wrapping_add(b),
+        );
+        b1 = b1.wrapping_add(
+            (b0 << 4).wrapping_add(c) ^ b0.wrapping_add(sum) ^ (b0 >> 5).wrapping_add(d),
+        );
+    }
+    buf[0] = buf[0].wrapping_add(b0);
+    buf[1] = buf[1].wrapping_add(b1);
+}
+
+fn md4_f(x: u32, y: u32, z: u32) -> u32 {
+    z ^ (x & (y ^ z))
+}
+
+fn md4_g(x: u32, y: u32, z: u32) -> u32 {
+    (x & y).wrapping_add((x ^ y) & z)
+}
+
+fn md4_h(x: u32, y: u32, z: u32) -> u32 {
+    x ^ y ^ z
+}
+
+fn half_md4_transform(buf: &mut [u32; 4], input: &[u32]) {
+    const K1: u32 = 0;
+    const K2: u32 = 0o13240474631;
+    const K3: u32 = 0o15666365641;
+
+    let (mut a, mut b, mut c, mut d) = (buf[0], buf[1], buf[2], buf[3]);
+
+    macro_rules! round {
+        ($f:ident, $a:ident, $b:ident, $c:ident, $d:ident, $x:expr, $s:expr) => {
+            $a = $a.wrapping_add($f($b, $c, $d)).wrapping_add($x);
+            $a = $a.rotate_left($s);
+        };
+    }
+
+    // Round 1
+    round!(md4_f, a, b, c, d, input[0].wrapping_add(K1), 3);
+    round!(md4_f, d, a, b, c, input[1].wrapping_add(K1), 7);
+    round!(md4_f, c, d, a, b, input[2].wrapping_add(K1), 11);
+    round!(md4_f, b, c, d, a, input[3].wrapping_add(K1), 19);
+    round!(md4_f, a, b, c, d, input[4].wrapping_add(K1), 3);
+    round!(md4_f, d, a, b, c, input[5].wrapping_add(K1), 7);
+    round!(md4_f, c, d, a, b, input[6].wrapping_add(K1), 11);
+    round!(md4_f, b, c, d, a, input[7].wrapping_add(K1), 19);
+
+    // Round 2
+    round!(md4_g, a, b, c, d, input[1].wrapping_add(K2), 3);
+    round!(md4_g, d, a, b, c, input[3].wrapping_add(K2), 5);
+    round!(md4_g, c, d, a, b, input[5].wrapping_add(K2), 9);
+    round!(md4_g, b, c, d, a, input[7].wrapping_add(K2), 13);
+    round!(md4_g, a, b, c, d, input[0].wrapping_add(K2), 3);
+    round!(md4_g, d, a, b, c, input[2].wrapping_add(K2), 5);
+    round!(md4_g, c, d, a, b, input[4].wrapping_add(K2), 9);
+    round!(md4_g, b, c, d, a, input[6].wrapping_add(K2), 13);
+
+    // Round 3
+    round!(md4_h, a, b, c, d, input[3].wrapping_add(K3), 3);
+    round!(md4_h, d, a, b, c, input[7].wrapping_add(K3), 9);
+    round!(md4_h, c, d, a, b, input[2].wrapping_add(K3), 11);
+    round!(md4_h, b, c, d, a, input[6].wrapping_add(K3), 15);
+    round!(md4_h, a, b, c, d, input[1].wrapping_add(K3), 3);
+    round!(md4_h, d, a, b, c, input[5].wrapping_add(K3), 9);
+    round!(md4_h, c, d, a, b, input[0].wrapping_add(K3), 11);
+    round!(md4_h, b, c, d, a, input[4].wrapping_add(K3), 15);
+
+    buf[0] = buf[0].wrapping_add(a);
+    buf[1] = buf[1].wrapping_add(b);
+    buf[2] = buf[2].wrapping_add(c);
+    buf[3] = buf[3].wrapping_add(d);
+}
+
+/// 按有符号或无符号字符读取文件名中的一个字节
+fn char_value(c: u8, unsigned: bool) -> u32 {
+    if unsigned {
+        c as u32
+    } else {
+        c as i8 as i32 as u32
+    }
+}
+
+fn dx_hack_hash(name: &[u8], unsigned: bool) -> u32 {
+    let (mut hash0, mut hash1): (u32, u32) = (0x12a3_fe2d, 0x37ab_e8f9);
+    for &c in name {
+        let v = (char_value(c, unsigned) as i32).wrapping_mul(7152373) as u32;
+        let mut hash = hash1.wrapping_add(hash0 ^ v);
+        if hash & 0x8000_0000 != 0 {
+            hash = hash.wrapping_sub(0x7fff_ffff);
+        }
+        hash1 = hash0;
+        hash0 = hash;
+    }
+    hash0 << 1
+}
+
+fn str2hashbuf(msg: &[u8], buf: &mut [u32], num: usize, unsigned: bool) {
+    let len = msg.len() as u32;
+    let mut pad = len | (len << 8);
+    pad |= pad << 16;
+
+    let mut val = pad;
+    let mut num = num as isize;
+    let mut idx = 0;
+    let len = msg.len().min(num as usize * 4);
+    for (i, &c) in msg[..len].iter().enumerate() {
+        val = char_value(c, unsigned).wrapping_add(val << 8);
+        if i % 4 == 3 {
+            buf[idx] = val;
+            idx += 1;
+            val = pad;
+            num -= 1;
+        }
+    }
+    num -= 1;
+    if num >= 0 {
+        buf[idx] = val;
+        idx += 1;
+    }
+    while num > 0 {
+        buf[idx] = pad;
+        idx += 1;
+        num -= 1;
+    }
+}
+
+/// 计算文件名的 htree 哈希，返回 `(major, minor)`
+///
+/// `seed` 为超级块中的 `hash_seed`，全零时使用默认种子。
+pub fn ext4_dirhash(name: &[u8], hash_version: u8, seed: &[u32; 4]) -> (u32, u32) {
+    let mut buf: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+    if seed.iter().any(|&s| s != 0) {
+        buf = *seed;
+    }
+
+    let mut input = [0u32; 8];
+    let mut minor_hash = 0;
+    let hash = match hash_version {
+        DX_HASH_LEGACY | DX_HASH_LEGACY_UNSIGNED => {
+            dx_hack_hash(name, hash_version == DX_HASH_LEGACY_UNSIGNED)
+        }
+        DX_HASH_HALF_MD4 | DX_HASH_HALF_MD4_UNSIGNED => {
+            let unsigned = hash_version == DX_HASH_HALF_MD4_UNSIGNED;
+            let mut p = name;
+            loop {
+                str2hashbuf(p, &mut input, 8, unsigned);
+                half_md4_transform(&mut buf, &input);
+                if p.len() <= 32 {
+                    break;
+                }
+                p = &p[32..];
+            }
+            minor_hash = buf[2];
+            buf[1]
+        }
+        DX_HASH_TEA | DX_HASH_TEA_UNSIGNED => {
+            let unsigned = hash_version == DX_HASH_TEA_UNSIGNED;
+            let mut p = name;
+            loop {
+                str2hashbuf(p, &mut input, 4, unsigned);
+                tea_transform(&mut buf, &input);
+                if p.len() <= 16 {
+                    break;
+                }
+                p = &p[16..];
+            }
+            minor_hash = buf[1];
+            buf[0]
+        }
+        _ => {
+            log::warn!("unknown htree hash version {}", hash_version);
+            0
+        }
+    };
+
+    let mut hash = hash & !1;
+    if hash == EXT4_HTREE_EOF_32BIT << 1 {
+        hash = (EXT4_HTREE_EOF_32BIT - 1) << 1;
+    }
+    (hash, minor_hash)
+}
@@ -0,0 +1,418 @@
+//! inode 的分配与释放，以及设备文件等特殊文件的创建
+//!
+//! 与 Linux 的 `ext4_new_inode` 一致，从父目录所在的块组开始依次查找有空闲 inode 的
+//! 块组，在 inode 位图中分配，并更新块组描述符和超级块中的空闲 inode 数以及相应的
+//! 校验和。组描述符使用旧的 crc16 校验和（gdt_csum）的文件系统暂不支持分配。
+//!
+//! 删除最后一个链接后，inode 可能仍被打开，此时先把它放入超级块的孤儿链表，不再使用后
+//! 再释放。异常关机后残留在链表中的 inode 在下次读写挂载时释放。
+
+use core::mem::size_of;
+
+use crate::crc::ext4_crc32c;
+use crate::defs::*;
+use crate::inode::EXT4_EXT_MAGIC;
+use crate::link::Ext4LinkError;
+use crate::{Ext4Fs, Ext4Traits};
+
+/// 超级块 `feature_ro_compat` 中表示组描述符带 crc16 校验和的特性
+pub const EXT4_FEATURE_RO_COMPAT_GDT_CSUM: u32 = 0x0010;
+/// 超级块 `feature_incompat` 中表示 64 位块号的特性，组描述符的大小由 `desc_size` 给出
+pub const EXT4_FEATURE_INCOMPAT_64BIT: u32 = 0x0080;
+
+/// 没有 64 位特性时组描述符的大小
+const EXT4_MIN_DESC_SIZE: usize = 32;
+/// 组描述符中 `bg_checksum` 的偏移
+const EXT4_BG_CHECKSUM_OFFSET: usize = 0x1E;
+/// 组描述符中包含 `bg_inode_bitmap_csum_hi` 所需的最小长度
+const EXT4_BG_INODE_BITMAP_CSUM_HI_END: usize = 0x3C;
+/// inode 扩展部分的大小，与 mkfs 和 Linux 的默认值一致
+const EXT4_EXTRA_ISIZE: u16 = 32;
+/// 旧版本文件系统的第一个非保留 inode
+const EXT4_GOOD_OLD_FIRST_INO: u32 = 11;
+/// 扩展部分中 `i_extra_isize` 的偏移
+const EXT4_INODE_EXTRA_ISIZE_OFFSET: usize = 0x80;
+/// `i_block` 中 extent 树根节点最多的项数
+const EXT4_EXT_ROOT_MAX: u16 = 4;
+
+/// 按 ext4 的规则把设备号编码到 `i_block` 中
+///
+/// 主次设备号都小于 256 时使用旧格式，保存在 `i_block[0]`；否则使用新格式，保存在
+/// `i_block[1]`，与 Linux 的 `old_encode_dev` 和 `new_encode_dev` 一致。
+pub fn ext4_encode_rdev(block: &mut [u32; 15], major: u32, minor: u32) {
+    if major < 256 && minor < 256 {
+        block[0] = (major << 8) | minor;
+        block[1] = 0;
+    } else {
+        block[0] = 0;
+        block[1] = (minor & 0xff) | (major << 8) | ((minor & !0xff) << 12);
+        block[2] = 0;
+    }
+}
+
+/// 从 `i_block` 中解出设备号，返回 `(主设备号, 次设备号)`
+pub fn ext4_decode_rdev(block: &[u32; 15]) -> (u32, u32) {
+    if block[0] != 0 {
+        ((block[0] >> 8) & 0xff, block[0] & 0xff)
+    } else {
+        let dev = block[1];
+        ((dev & 0xfff00) >> 8, (dev & 0xff) | ((dev >> 12) & 0xfff00))
+    }
+}
+
+fn ext4_bg_free_inodes(desc: &GroupDesc) -> u32 {
+    desc.bg_free_inodes_count_lo as u32 | (desc.bg_free_inodes_count_hi as u32) << 16
+}
+
+fn ext4_bg_set_free_inodes(desc: &mut GroupDesc, count: u32) {
+    desc.bg_free_inodes_count_lo = count as u16;
+    desc.bg_free_inodes_count_hi = (count >> 16) as u16;
+}
+
+fn ext4_bg_itable_unused(desc: &GroupDesc) -> u32 {
+    desc.bg_itable_unused_lo as u32 | (desc.bg_itable_unused_hi as u32) << 16
+}
+
+fn ext4_bg_set_itable_unused(desc: &mut GroupDesc, count: u32) {
+    desc.bg_itable_unused_lo = count as u16;
+    desc.bg_itable_unused_hi = (count >> 16) as u16;
+}
+
+fn ext4_bg_used_dirs(desc: &GroupDesc) -> u32 {
+    desc.bg_used_dirs_count_lo as u32 | (desc.bg_used_dirs_count_hi as u32) << 16
+}
+
+fn ext4_bg_set_used_dirs(desc: &mut GroupDesc, count: u32) {
+    desc.bg_used_dirs_count_lo = count as u16;
+    desc.bg_used_dirs_count_hi = (count >> 16) as u16;
+}
+
+fn ext4_bg_inode_bitmap(desc: &GroupDesc) -> u64 {
+    desc.bg_inode_bitmap_lo as u64 | (desc.bg_inode_bitmap_hi as u64) << 32
+}
+
+impl Ext4Fs {
+    /// 组描述符的大小
+    pub(crate) fn ext4_desc_size(&self) -> usize {
+        if self.super_block.feature_incompat & EXT4_FEATURE_INCOMPAT_64BIT != 0 {
+            self.super_block.desc_size as usize
+        } else {
+            EXT4_MIN_DESC_SIZE
+        }
+    }
+
+    /// 块组的个数
+    pub(crate) fn ext4_group_count(&self) -> u64 {
+        let inodes_per_group = self.super_block.inodes_per_group as u64;
+        (self.super_block.inodes_count as u64).div_ceil(inodes_per_group)
+    }
+
+    /// 块组 `group` 的描述符所在的块号和块内偏移
+    fn ext4_desc_location(&self, group: u64) -> (u64, usize) {
+        let desc_size = self.ext4_desc_size();
+        let per_block = BLOCK_SIZE / desc_size as u64;
+        let block = self.super_block.first_data_block as u64 + 1 + group / per_block;
+        (block, (group % per_block) as usize * desc_size)
+    }
+
+    /// 读取块组 `group` 的描述符，32 字节的描述符中高位的字段为 0
+    pub(crate) fn ext4_read_group_desc(&self, group: u64) -> GroupDesc {
+        let (block_id, offset) = self.ext4_desc_location(group);
+        let block = self.read_block(block_id * BLOCK_SIZE);
+        let mut desc = GroupDesc::default();
+        let len = self.ext4_desc_size().min(size_of::<GroupDesc>());
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                block[offset..offset + len].as_ptr(),
+                &mut desc as *mut GroupDesc as *mut u8,
+                len,
+            );
+        }
+        desc
+    }
+
+    /// 写回块组 `group` 的描述符，同时更新它的校验和
+    pub(crate) fn ext4_write_group_desc(&self, group: u64, desc: &GroupDesc) {
+        let (block_id, offset) = self.ext4_desc_location(group);
+        let desc_size = self.ext4_desc_size();
+        let mut block = self.read_block(block_id * BLOCK_SIZE);
+        let raw = &mut block[offset..offset + desc_size];
+        let len = desc_size.min(size_of::<GroupDesc>());
+        let bytes = unsafe {
+            core::slice::from_raw_parts(desc as *const GroupDesc as *const u8, len)
+        };
+        raw[..len].copy_from_slice(bytes);
+
+        if self.ext4_has_metadata_csum() {
+            let csum_field = EXT4_BG_CHECKSUM_OFFSET..EXT4_BG_CHECKSUM_OFFSET + 2;
+            let mut csum = ext4_crc32c(self.ext4_csum_seed(), &(group as u32).to_le_bytes());
+            csum = ext4_crc32c(csum, &raw[..csum_field.start]);
+            csum = ext4_crc32c(csum, &[0; 2]);
+            csum = ext4_crc32c(csum, &raw[csum_field.end..]);
+            raw[csum_field].copy_from_slice(&(csum as u16).to_le_bytes());
+        }
+        self.block_device.write_block(block_id as usize, &block);
+    }
+
+    /// 写回块组的 inode 位图，同时更新描述符中位图的校验和
+    fn ext4_write_inode_bitmap(&self, desc: &mut GroupDesc, bitmap: &[u8]) {
+        if self.ext4_has_metadata_csum() {
+            let len = self.super_block.inodes_per_group as usize / 8;
+            let csum = ext4_crc32c(self.ext4_csum_seed(), &bitmap[..len]);
+            desc.bg_inode_bitmap_csum_lo = csum as u16;
+            if self.ext4_desc_size() >= EXT4_BG_INODE_BITMAP_CSUM_HI_END {
+                desc.bg_inode_bitmap_csum_hi = (csum >> 16) as u16;
+            }
+        }
+        self.block_device
+            .write_block(ext4_bg_inode_bitmap(desc) as usize, bitmap);
+    }
+
+    /// 在块组 `group` 的 inode 位图中分配一个 inode，返回它在组内的下标
+    fn ext4_alloc_in_group(&self, group: u64) -> Option<u32> {
+        let mut desc = self.ext4_read_group_desc(group);
+        if ext4_bg_free_inodes(&desc) == 0 {
+            return None;
+        }
+        let inodes_per_group = self.super_block.inodes_per_group;
+        let mut bitmap = if desc.bg_flags.contains(GroupFlags::INODE_UNINIT) {
+            // 未初始化的位图视为全 0，组内 inode 之后的填充位为 1
+            let mut bitmap = alloc::vec![0u8; BLOCK_SIZE as usize];
+            for bit in inodes_per_group as usize..bitmap.len() * 8 {
+                bitmap[bit / 8] |= 1 << (bit % 8);
+            }
+            bitmap
+        } else {
+            self.read_block(ext4_bg_inode_bitmap(&desc) * BLOCK_SIZE)
+        };
+        // 0 号块组中 first_ino 之前的 inode 是保留的，旧版本的文件系统固定为 11
+        let first = if group == 0 {
+            self.super_block.first_ino.max(EXT4_GOOD_OLD_FIRST_INO) - 1
+        } else {
+            0
+        };
+        let index = (first..inodes_per_group)
+            .find(|&i| bitmap[i as usize / 8] & (1 << (i % 8)) == 0)?;
+        bitmap[index as usize / 8] |= 1 << (index % 8);
+
+        desc.bg_flags.remove(GroupFlags::INODE_UNINIT);
+        let free = ext4_bg_free_inodes(&desc) - 1;
+        ext4_bg_set_free_inodes(&mut desc, free);
+        let used = inodes_per_group - ext4_bg_itable_unused(&desc);
+        if index >= used {
+            ext4_bg_set_itable_unused(&mut desc, inodes_per_group - index - 1);
+        }
+        self.ext4_write_inode_bitmap(&mut desc, &bitmap);
+        self.ext4_write_group_desc(group, &desc);
+        Some(index)
+    }
+
+    /// 分配一个新的 inode，从目录 `dir` 所在的块组开始查找，返回 inode 号
+    ///
+    /// 新 inode 的内容全部清零后设置类型和权限 `mode` 以及时间，链接数为 0，由调用者
+    /// 通过 [`ext4_link`](Ext4Fs::ext4_link) 放入目录。
+    pub fn ext4_alloc_inode(&self, dir: u32, mode: u16) -> Result<u32, Ext4LinkError> {
+        let sb = &self.super_block;
+        if sb.feature_ro_compat & EXT4_FEATURE_RO_COMPAT_GDT_CSUM != 0
+            && !self.ext4_has_metadata_csum()
+        {
+            return Err(Ext4LinkError::Unsupported);
+        }
+        let groups = self.ext4_group_count();
+        let start = (dir as u64 - 1) / sb.inodes_per_group as u64;
+        let (group, index) = (0..groups)
+            .map(|i| (start + i) % groups)
+            .find_map(|group| Some((group, self.ext4_alloc_in_group(group)?)))
+            .ok_or(Ext4LinkError::NoFreeInode)?;
+        self.ext4_update_super_block(|sb| {
+            sb.free_inodes_count = sb.free_inodes_count.saturating_sub(1)
+        });
+        let inode = (group * sb.inodes_per_group as u64 + index as u64 + 1) as u32;
+        log::debug!("ext4: allocate inode {} in group {}", inode, group);
+
+        // 先清零磁盘上的整个 inode，包括扩展部分
+        let offset = self.ext4_inode_offset(inode as u64);
+        let block_id = offset / BLOCK_SIZE;
+        let start = (offset % BLOCK_SIZE) as usize;
+        let inode_size = sb.inode_size as usize;
+        let mut block = self.read_block(block_id * BLOCK_SIZE);
+        let raw = &mut block[start..start + inode_size];
+        raw.fill(0);
+        if inode_size > size_of::<Ext4Inode>() {
+            raw[EXT4_INODE_EXTRA_ISIZE_OFFSET..EXT4_INODE_EXTRA_ISIZE_OFFSET + 2]
+                .copy_from_slice(&EXT4_EXTRA_ISIZE.to_le_bytes());
+        }
+        self.block_device.write_block(block_id as usize, &block);
+
+        let now = self.ext4_now();
+        let mut inode_data = self.ext4_read_inode(inode as u64, sb);
+        inode_data.mode = mode;
+        inode_data.atime = now;
+        inode_data.ctime = now;
+        inode_data.mtime = now;
+        inode_data.generation = now.rotate_left(16) ^ inode;
+        self.ext4_write_inode(inode as u64, &inode_data);
+        Ok(inode)
+    }
+
+    /// 在 inode 位图中释放 `inode` 并记录删除时间，它的块已经释放
+    fn ext4_free_inode(&self, inode: u32) {
+        let inodes_per_group = self.super_block.inodes_per_group;
+        let group = ((inode - 1) / inodes_per_group) as u64;
+        let index = (inode - 1) % inodes_per_group;
+        let mut inode_data = self.ext4_read_inode(inode as u64, &self.super_block);
+        let mut desc = self.ext4_read_group_desc(group);
+        let mut bitmap = self.read_block(ext4_bg_inode_bitmap(&desc) * BLOCK_SIZE);
+        bitmap[index as usize / 8] &= !(1 << (index % 8));
+        let free = ext4_bg_free_inodes(&desc) + 1;
+        ext4_bg_set_free_inodes(&mut desc, free);
+        if inode_data.mode & FileMode::S_IFMT.bits() == FileMode::S_IFDIR.bits() {
+            let dirs = ext4_bg_used_dirs(&desc).saturating_sub(1);
+            ext4_bg_set_used_dirs(&mut desc, dirs);
+        }
+        self.ext4_write_inode_bitmap(&mut desc, &bitmap);
+        self.ext4_write_group_desc(group, &desc);
+        self.ext4_update_super_block(|sb| sb.free_inodes_count += 1);
+
+        inode_data.dtime = self.ext4_now();
+        self.ext4_write_inode(inode as u64, &inode_data);
+    }
+
+    /// 把没有链接的 `inode` 放入孤儿链表的头部，链表中的下一个 inode 保存在 `i_dtime` 中
+    pub(crate) fn ext4_orphan_add(&self, inode: u32) {
+        let mut inode_data = self.ext4_read_inode(inode as u64, &self.super_block);
+        inode_data.dtime = self.read_super_block().last_orphan;
+        self.ext4_write_inode(inode as u64, &inode_data);
+        self.ext4_update_super_block(|sb| sb.last_orphan = inode);
+    }
+
+    /// 从孤儿链表中删除 `inode`，它不在链表中时什么也不做
+    fn ext4_orphan_del(&self, inode: u32) {
+        let next = self.ext4_read_inode(inode as u64, &self.super_block).dtime;
+        let mut prev = self.read_super_block().last_orphan;
+        if prev == inode {
+            self.ext4_update_super_block(|sb| sb.last_orphan = next);
+            return;
+        }
+        // 链表可能已损坏，最多遍历 inode 总数次
+        for _ in 0..self.super_block.inodes_count {
+            if prev == 0 || prev > self.super_block.inodes_count {
+                return;
+            }
+            let mut prev_data = self.ext4_read_inode(prev as u64, &self.super_block);
+            if prev_data.dtime == inode {
+                prev_data.dtime = next;
+                self.ext4_write_inode(prev as u64, &prev_data);
+                return;
+            }
+            prev = prev_data.dtime;
+        }
+    }
+
+    /// 释放没有链接的 `inode` 和它的所有块，并把它从孤儿链表中删除
+    ///
+    /// 由 [`ext4_unlink`](Ext4Fs::ext4_unlink) 删除最后一个链接后、inode 不再被使用时调用。
+    pub fn ext4_evict_inode(&self, inode: u32) {
+        let mut inode_data = self.ext4_read_inode(inode as u64, &self.super_block);
+        if inode_data.links_count != 0 {
+            self.ext4_error(
+                inode,
+                0,
+                "ext4_evict_inode",
+                line!(),
+                "evicting inode with links",
+            );
+            return;
+        }
+        log::debug!("ext4: evict inode {}", inode);
+        self.ext4_orphan_del(inode);
+        self.ext4_free_inode_blocks(inode, &inode_data);
+
+        // 与 Linux 截断后的 inode 一致：大小和块数为 0，extent 树只剩根节点
+        inode_data = self.ext4_read_inode(inode as u64, &self.super_block);
+        inode_data.size = 0;
+        inode_data.dir_acl = 0;
+        inode_data.blocks = 0;
+        inode_data.file_acl = 0;
+        inode_data.osd2[..4].fill(0);
+        if IFlags::from_bits_truncate(inode_data.flags).contains(IFlags::EXT4_EXTENTS_FL) {
+            inode_data.block = [0; 15];
+            inode_data.block[0] = EXT4_EXT_MAGIC as u32;
+            inode_data.block[1] = EXT4_EXT_ROOT_MAX as u32;
+        }
+        self.ext4_write_inode(inode as u64, &inode_data);
+        self.ext4_free_inode(inode);
+    }
+
+    /// 释放孤儿链表中的所有 inode，它们在上次卸载前被删除时仍被打开
+    pub(crate) fn ext4_orphan_cleanup(&self) {
+        let inodes_count = self.super_block.inodes_count;
+        for _ in 0..inodes_count {
+            let inode = self.read_super_block().last_orphan;
+            if inode == 0 {
+                return;
+            }
+            let sb = &self.super_block;
+            if inode > inodes_count || self.ext4_read_inode(inode as u64, sb).links_count != 0 {
+                self.ext4_error(inode, 0, "ext4_orphan_cleanup", line!(), "bad orphan inode");
+                self.ext4_update_super_block(|sb| sb.last_orphan = 0);
+                return;
+            }
+            log::info!("ext4: cleaning up orphan inode {}", inode);
+            self.ext4_evict_inode(inode);
+        }
+    }
+
+    /// 在目录 `dir` 中创建名为 `name` 的特殊文件，返回它的 inode 号
+    ///
+    /// `mode` 的类型必须是字符设备、块设备、FIFO 或套接字，设备文件的设备号为
+    /// `major:minor`，其余类型忽略设备号。
+    pub fn ext4_mknod(
+        &self,
+        dir: u32,
+        name: &str,
+        mode: u16,
+        major: u32,
+        minor: u32,
+    ) -> Result<u32, Ext4LinkError> {
+        let ty = FileMode::from_bits_truncate(mode & FileMode::S_IFMT.bits());
+        if ![
+            FileMode::S_IFCHR,
+            FileMode::S_IFBLK,
+            FileMode::S_IFIFO,
+            FileMode::S_IFSOCK,
+        ]
+        .contains(&ty)
+        {
+            return Err(Ext4LinkError::Unsupported);
+        }
+        if name.is_empty() || name.len() > 255 {
+            return Err(Ext4LinkError::InvalidName);
+        }
+        let dir_data = self.ext4_read_inode(dir as u64, &self.super_block);
+        if dir_data.mode & FileMode::S_IFMT.bits() != FileMode::S_IFDIR.bits() {
+            return Err(Ext4LinkError::NotADirectory);
+        }
+        if self.ext4_dir_lookup(dir, name.as_bytes()).is_some() {
+            return Err(Ext4LinkError::Exists);
+        }
+
+        let inode = self.ext4_alloc_inode(dir, mode)?;
+        if ty == FileMode::S_IFCHR || ty == FileMode::S_IFBLK {
+            let mut inode_data = self.ext4_read_inode(inode as u64, &self.super_block);
+            ext4_encode_rdev(&mut inode_data.block, major, minor);
+            self.ext4_write_inode(inode as u64, &inode_data);
+        }
+        if let Err(e) = self.ext4_link(dir, name, inode) {
+            self.ext4_free_inode(inode);
+            return Err(e);
+        }
+        Ok(inode)
+    }
+
+    /// 读取设备文件的设备号，返回 `(主设备号, 次设备号)`
+    pub fn ext4_get_rdev(&self, inode: u64) -> (u32, u32) {
+        let inode_data = self.ext4_read_inode(inode, &self.super_block);
+        ext4_decode_rdev(&inode_data.block)
+    }
+}
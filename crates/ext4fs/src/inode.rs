@@ -0,0 +1,275 @@
+//! inode 的写回、inode 标志和 extent 布局查询
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use crate::crc::ext4_crc32c;
+use crate::defs::*;
+use crate::{Ext4AtimeMode, Ext4Fs, Ext4Traits};
+
+/// 超级块 `feature_ro_compat` 中表示元数据带校验和的特性
+pub const EXT4_FEATURE_RO_COMPAT_METADATA_CSUM: u32 = 0x0400;
+/// 超级块 `feature_incompat` 中表示校验和种子保存在超级块中的特性
+pub const EXT4_FEATURE_INCOMPAT_CSUM_SEED: u32 = 0x2000;
+
+/// 用户可以看到的 inode 标志，与 Linux 的 `EXT4_FL_USER_VISIBLE` 一致
+pub const EXT4_FL_USER_VISIBLE: u32 = 0x705B_DFFF;
+/// 用户可以修改的 inode 标志，与 Linux 的 `EXT4_FL_USER_MODIFIABLE` 一致
+pub const EXT4_FL_USER_MODIFIABLE: u32 = 0x604B_C0FF;
+
+/// [`Ext4Fs::ext4_read_at`] 一次向块设备读取的最大块数 (128 KiB)
+const MAX_READ_BLOCKS: u64 = 32;
+
+/// 128 字节的基本 inode 大小
+const EXT4_GOOD_OLD_INODE_SIZE: usize = 128;
+/// `osd2` 中 `l_i_checksum_lo` 的偏移
+const EXT4_INODE_CSUM_LO_OFFSET: usize = 0x7C;
+/// 扩展部分中 `i_extra_isize` 的偏移
+const EXT4_INODE_EXTRA_ISIZE_OFFSET: usize = 0x80;
+/// 扩展部分中 `i_checksum_hi` 的偏移
+const EXT4_INODE_CSUM_HI_OFFSET: usize = 0x82;
+/// `i_generation` 的偏移
+const EXT4_INODE_GENERATION_OFFSET: usize = 0x64;
+
+/// 长度大于该值的 extent 是未初始化的
+const EXT_INIT_MAX_LEN: u16 = 32768;
+/// extent 树节点头部的魔数
+pub(crate) const EXT4_EXT_MAGIC: u16 = 0xF30A;
+
+/// 文件中一段连续的数据在磁盘上的位置，均以字节为单位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ext4ExtentMapping {
+    /// 在文件中的偏移
+    pub logical: u64,
+    /// 在磁盘上的偏移
+    pub physical: u64,
+    /// 长度
+    pub length: u64,
+    /// 空间已分配但尚未写入
+    pub unwritten: bool,
+    /// 是文件的最后一个 extent
+    pub last: bool,
+}
+
+impl Ext4Fs {
+    /// inode 在磁盘上的字节偏移
+    pub fn ext4_inode_offset(&self, inode: u64) -> u64 {
+        let super_block = &self.super_block;
+        let inodes_per_group = super_block.inodes_per_group as u64;
+        let group = (inode - 1) / inodes_per_group;
+        let index = (inode - 1) % inodes_per_group;
+        let inode_table_blk_num = self.ext4_get_block_group(group, super_block);
+        inode_table_blk_num * BLOCK_SIZE + index * super_block.inode_size as u64
+    }
+
+    pub(crate) fn ext4_has_metadata_csum(&self) -> bool {
+        self.super_block.feature_ro_compat & EXT4_FEATURE_RO_COMPAT_METADATA_CSUM != 0
+    }
+
+    /// 文件系统的校验和种子
+    pub(crate) fn ext4_csum_seed(&self) -> u32 {
+        if self.super_block.feature_incompat & EXT4_FEATURE_INCOMPAT_CSUM_SEED != 0 {
+            self.super_block.checksum_seed
+        } else {
+            ext4_crc32c(!0, &self.super_block.uuid)
+        }
+    }
+
+    /// 计算磁盘上 inode 原始数据的校验和，校验和字段本身按 0 计算
+    fn ext4_inode_csum(&self, inode: u64, raw: &[u8]) -> u32 {
+        let generation = &raw[EXT4_INODE_GENERATION_OFFSET..EXT4_INODE_GENERATION_OFFSET + 4];
+        let mut csum = ext4_crc32c(self.ext4_csum_seed(), &(inode as u32).to_le_bytes());
+        csum = ext4_crc32c(csum, generation);
+
+        csum = ext4_crc32c(csum, &raw[..EXT4_INODE_CSUM_LO_OFFSET]);
+        csum = ext4_crc32c(csum, &[0; 2]);
+        csum = ext4_crc32c(csum, &raw[EXT4_INODE_CSUM_LO_OFFSET + 2..EXT4_GOOD_OLD_INODE_SIZE]);
+        if raw.len() > EXT4_GOOD_OLD_INODE_SIZE {
+            csum = ext4_crc32c(csum, &raw[EXT4_GOOD_OLD_INODE_SIZE..EXT4_INODE_CSUM_HI_OFFSET]);
+            if Self::ext4_inode_has_csum_hi(raw) {
+                csum = ext4_crc32c(csum, &[0; 2]);
+                csum = ext4_crc32c(csum, &raw[EXT4_INODE_CSUM_HI_OFFSET + 2..]);
+            } else {
+                csum = ext4_crc32c(csum, &raw[EXT4_INODE_CSUM_HI_OFFSET..]);
+            }
+        }
+        csum
+    }
+
+    fn ext4_inode_has_csum_hi(raw: &[u8]) -> bool {
+        raw.len() > EXT4_GOOD_OLD_INODE_SIZE
+            && u16::from_le_bytes([
+                raw[EXT4_INODE_EXTRA_ISIZE_OFFSET],
+                raw[EXT4_INODE_EXTRA_ISIZE_OFFSET + 1],
+            ]) >= 4
+    }
+
+    /// 把 inode 写回磁盘，超出 [`Ext4Inode`] 的扩展部分保持不变
+    pub fn ext4_write_inode(&self, inode: u64, inode_data: &Ext4Inode) {
+        let offset = self.ext4_inode_offset(inode);
+        let block_id = offset / BLOCK_SIZE;
+        let start = (offset % BLOCK_SIZE) as usize;
+        let inode_size = self.super_block.inode_size as usize;
+
+        let mut block = self.read_block(block_id * BLOCK_SIZE);
+        let raw = unsafe {
+            core::slice::from_raw_parts(
+                inode_data as *const Ext4Inode as *const u8,
+                size_of::<Ext4Inode>(),
+            )
+        };
+        block[start..start + size_of::<Ext4Inode>()].copy_from_slice(raw);
+
+        if self.ext4_has_metadata_csum() {
+            let raw = &mut block[start..start + inode_size];
+            let csum = self.ext4_inode_csum(inode, raw);
+            raw[EXT4_INODE_CSUM_LO_OFFSET..EXT4_INODE_CSUM_LO_OFFSET + 2]
+                .copy_from_slice(&(csum as u16).to_le_bytes());
+            if Self::ext4_inode_has_csum_hi(raw) {
+                raw[EXT4_INODE_CSUM_HI_OFFSET..EXT4_INODE_CSUM_HI_OFFSET + 2]
+                    .copy_from_slice(&((csum >> 16) as u16).to_le_bytes());
+            }
+        }
+
+        self.block_device.write_block(block_id as usize, &block);
+    }
+
+    /// 读取用户可见的 inode 标志
+    pub fn ext4_get_flags(&self, inode: u64) -> u32 {
+        let inode_data = self.ext4_read_inode(inode, &self.super_block);
+        inode_data.flags & EXT4_FL_USER_VISIBLE
+    }
+
+    /// 设置 inode 标志，只有 [`EXT4_FL_USER_MODIFIABLE`] 中的标志会被修改
+    ///
+    /// 切换 extent 格式需要迁移数据，暂不支持，`EXT4_EXTENTS_FL` 保持不变。
+    pub fn ext4_set_flags(&self, inode: u64, flags: u32) {
+        let mut inode_data = self.ext4_read_inode(inode, &self.super_block);
+        let mask = EXT4_FL_USER_MODIFIABLE & !IFlags::EXT4_EXTENTS_FL.bits();
+        inode_data.flags = (inode_data.flags & !mask) | (flags & mask);
+        self.ext4_write_inode(inode, &inode_data);
+    }
+
+    /// 按 `mode` 更新访问 inode 后的访问时间，只读时不修改磁盘
+    pub fn ext4_touch_atime(&self, inode: u64, mode: Ext4AtimeMode) {
+        if mode == Ext4AtimeMode::None || self.ext4_is_read_only() {
+            return;
+        }
+        let mut inode_data = self.ext4_read_inode(inode, &self.super_block);
+        let now = self.ext4_now();
+        if mode.need_update(inode_data.atime, inode_data.mtime, inode_data.ctime, now) {
+            inode_data.atime = now;
+            self.ext4_write_inode(inode, &inode_data);
+        }
+    }
+
+    /// 获取文件中与 `[start, start + len)` 重叠的 extent，按文件偏移递增排列
+    pub fn ext4_fiemap(&self, inode: u64, start: u64, len: u64) -> Vec<Ext4ExtentMapping> {
+        let inode_data = self.ext4_read_inode(inode, &self.super_block);
+        // 没有使用 extent 的 inode（如内联数据、快速符号链接）没有磁盘布局可以报告
+        if Ext4ExtentHeader::from_bytes_u32(&inode_data.block).eh_magic != EXT4_EXT_MAGIC {
+            return Vec::new();
+        }
+        let mut extents: Vec<Ext4Extent> = Vec::new();
+        self.ext4_find_extent(&inode_data, &mut extents);
+        extents.sort_by_key(|e| e.ee_block);
+
+        let end = start.saturating_add(len);
+        let count = extents.len();
+        extents
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| {
+                let (blocks, unwritten) = if e.ee_len > EXT_INIT_MAX_LEN {
+                    (e.ee_len - EXT_INIT_MAX_LEN, true)
+                } else {
+                    (e.ee_len, false)
+                };
+                let logical = e.ee_block as u64 * BLOCK_SIZE;
+                let length = blocks as u64 * BLOCK_SIZE;
+                if logical >= end || logical + length <= start {
+                    return None;
+                }
+                let pblk = ((e.ee_start_hi as u64) << 32) | e.ee_start_lo as u64;
+                Some(Ext4ExtentMapping {
+                    logical,
+                    physical: pblk * BLOCK_SIZE,
+                    length,
+                    unwritten,
+                    last: i + 1 == count,
+                })
+            })
+            .collect()
+    }
+
+    /// 从文件偏移 `offset` 处读取数据到 `buf`，返回读取的字节数
+    ///
+    /// 读取范围不超过文件大小，空洞和未写入的 extent 读出为 0
+    pub fn ext4_read_at(&self, inode: u64, offset: u64, buf: &mut [u8]) -> usize {
+        let inode_data = self.ext4_read_inode(inode, &self.super_block);
+        // 普通文件的 i_dir_acl 字段即 i_size_high
+        let size = inode_data.size as u64 | ((inode_data.dir_acl as u64) << 32);
+        if offset >= size {
+            return 0;
+        }
+        let len = buf.len().min((size - offset) as usize);
+        let buf = &mut buf[..len];
+        buf.fill(0);
+        for extent in self.ext4_fiemap(inode, offset, len as u64) {
+            if extent.unwritten {
+                continue;
+            }
+            let start = offset.max(extent.logical);
+            let end = (offset + len as u64).min(extent.logical + extent.length);
+            // extent 内的块物理上连续，一次最多读出 MAX_READ_BLOCKS 块
+            let mut pos = start;
+            while pos < end {
+                let first = pos - pos % BLOCK_SIZE;
+                let stop = end.min(first + MAX_READ_BLOCKS * BLOCK_SIZE);
+                let mut data =
+                    vec![0u8; ((stop - first).div_ceil(BLOCK_SIZE) * BLOCK_SIZE) as usize];
+                self.block_device.read_block(
+                    (extent.physical + first - extent.logical) as usize,
+                    &mut data,
+                );
+                let dst = (pos - offset) as usize;
+                let src = (pos - first) as usize;
+                let n = (stop - pos) as usize;
+                buf[dst..dst + n].copy_from_slice(&data[src..src + n]);
+                pos = stop;
+            }
+        }
+        len
+    }
+
+    /// 读取符号链接的目标
+    ///
+    /// 目标短于 60 字节的快速符号链接直接保存在 `i_block` 中，其余的保存在数据块中
+    pub fn ext4_readlink(&self, inode: u64) -> Vec<u8> {
+        let inode_data = self.ext4_read_inode(inode, &self.super_block);
+        let size = inode_data.size as usize;
+        if Ext4ExtentHeader::from_bytes_u32(&inode_data.block).eh_magic != EXT4_EXT_MAGIC {
+            let mut target: Vec<u8> = inode_data
+                .block
+                .iter()
+                .flat_map(|word| word.to_le_bytes())
+                .collect();
+            target.truncate(size);
+            return target;
+        }
+        let mut target = Vec::with_capacity(size);
+        for extent in self.ext4_fiemap(inode, 0, size as u64) {
+            let blocks = extent.length / BLOCK_SIZE;
+            for i in 0..blocks {
+                if extent.unwritten {
+                    target.resize(target.len() + BLOCK_SIZE as usize, 0);
+                } else {
+                    target.extend(self.read_block(extent.physical + i * BLOCK_SIZE));
+                }
+            }
+        }
+        target.truncate(size);
+        target
+    }
+}
@@ -1,4 +1,4 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(allocator_api)]
 #![feature(new_uninit)]
 extern crate alloc;
@@ -12,15 +12,35 @@ use core::cell::{RefCell, UnsafeCell};
 use core::marker::PhantomData;
 use core::mem::size_of;
 use core::str;
+use core::sync::atomic::{AtomicBool, AtomicU8};
 
 
+mod balloc;
 mod blockdev;
+mod crc;
 mod defs;
+mod dir;
 mod ext4;
+mod hash;
+mod ialloc;
+mod inode;
+mod link;
+mod options;
+mod superblock;
+#[cfg(test)]
+mod tests;
 
 pub use blockdev::*;
+pub use crc::*;
 pub use defs::*;
+pub use dir::*;
 pub use ext4::*;
+pub use hash::*;
+pub use ialloc::*;
+pub use inode::*;
+pub use link::*;
+pub use options::*;
+pub use superblock::*;
 
 struct Ext4TraitsImpl {
     pub block_device: Arc<dyn BlockDevice>,
@@ -38,8 +58,17 @@ impl Ext4Traits for Ext4TraitsImpl {
 }
 
 pub struct Ext4Fs {
+    /// 打开时读到的超级块，挂载期间变化的字段以磁盘上的为准
     pub super_block: Ext4SuperBlock,
     block_device: Arc<dyn BlockDevice>,
+    /// 所使用的超级块的字节偏移，从备份加载时不是 [`BASE_OFFSET`]
+    sb_offset: u64,
+    /// 只读挂载，或因错误变为只读
+    read_only: AtomicBool,
+    /// 发现错误后的处理方式，见 [`Ext4ErrorsBehavior`]
+    errors: AtomicU8,
+    /// 获取当前时间（秒）
+    clock: fn() -> u32,
     // phantomdata: PhantomData<A>,
 }
 
@@ -58,18 +87,17 @@ impl Ext4Fs {
     pub fn open(block_device: Arc<dyn BlockDevice>) -> Self {
         log::info!("---------------open-------------------");
 
-        let mut data = [0u8; 4096];
-        // block_device block_size=512  super_block in offset 1024
-        block_device.read_block(BASE_OFFSET as usize, &mut data);
-        let mut buf = [0u8; size_of::<Ext4SuperBlock>()];
-        buf.copy_from_slice(&data[..size_of::<Ext4SuperBlock>()]);
-
-        let super_block = unsafe { core::ptr::read(buf.as_ptr() as *const _) };
+        // 主超级块位于偏移 1024 处，损坏时使用备份
+        let (sb_offset, super_block) = ext4_load_super_block(&*block_device);
         // log::info!("super_block {:x?}", super_block);
 
         Self {
             super_block: super_block,
             block_device: block_device,
+            sb_offset,
+            read_only: AtomicBool::new(true),
+            errors: AtomicU8::new(0),
+            clock: || 0,
         }
     }
 
@@ -80,7 +108,7 @@ impl Ext4Fs {
     }
 
     pub fn read_super_block(&self) -> Ext4SuperBlock {
-        let data = self.read_block(BASE_OFFSET);
+        let data = self.read_block(self.sb_offset);
         let mut buf = [0u8; size_of::<Ext4SuperBlock>()];
         buf.copy_from_slice(&data[..size_of::<Ext4SuperBlock>()]);
         unsafe { core::ptr::read(buf.as_ptr() as *const _) }
@@ -139,17 +167,23 @@ impl Ext4Fs {
 
         for en in 0..extent_entries {
             let idx = (3 + en * 3) as usize;
-            if idx == 12 {
+            if idx + 3 > data.len() {
                 break;
             }
             let extent_index = Ext4ExtentIndex::from_bytes_u32(&data[idx..]);
             log::info!("extent_index {:x?}", extent_index);
             let ei_leaf_lo = extent_index.ei_leaf_lo;
             let ei_leaf_hi = extent_index.ei_leaf_hi;
-            let mut block = ei_leaf_lo;
-            block |= ((ei_leaf_hi as u32) << 31) << 1;
-            let data = self.read_block(block as u64 * BLOCK_SIZE);
-            let data: Vec<u32> = unsafe { core::mem::transmute(data) };
+            let block = ((ei_leaf_hi as u64) << 32) | ei_leaf_lo as u64;
+            let data = self.read_block(block * BLOCK_SIZE);
+            let data: Vec<u32> = data
+                .chunks_exact(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            if Ext4ExtentHeader::from_bytes_u32(&data).eh_magic != inode::EXT4_EXT_MAGIC {
+                self.ext4_error(0, block, "ext4_add_extent", line!(), "invalid extent header");
+                continue;
+            }
             self.ext4_add_extent(inode, depth - 1, &data, extents, false);
         }
     }
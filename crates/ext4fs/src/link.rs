@@ -0,0 +1,574 @@
+//! 硬链接：目录项的查找、添加和删除，以及 inode 链接数的维护
+//!
+//! 新的目录项优先放入目录已有的块中，都放不下时为目录追加一个块。htree 目录的新目录项
+//! 放入文件名哈希对应的叶子块，叶子块已满时与 Linux 一样按哈希把它分裂为两块，并在
+//! 索引中加入新块；索引节点本身已满时需要增加索引层数，暂不支持。
+//!
+//! 删除最后一个链接后 inode 进入孤儿链表，由调用者在它不再被使用时通过
+//! [`ext4_evict_inode`](Ext4Fs::ext4_evict_inode) 释放。
+
+use alloc::vec::Vec;
+
+use crate::crc::ext4_crc32c;
+use crate::defs::*;
+use crate::dir::{ext4_dir_block_entries, read_u16, read_u32, DirBlockEntry};
+use crate::hash::ext4_dirhash;
+use crate::{Ext4Fs, Ext4Traits};
+
+/// 一个 inode 最多的链接数，与 Linux 的 `EXT4_LINK_MAX` 一致
+pub const EXT4_LINK_MAX: u16 = 65000;
+/// 超级块 `feature_incompat` 中表示目录项记录文件类型的特性
+pub const EXT4_FEATURE_INCOMPAT_FILETYPE: u32 = 0x0002;
+
+/// 目录块末尾校验和目录项的长度
+const EXT4_DIR_TAIL_SIZE: usize = 12;
+/// 校验和目录项的 `file_type`
+const EXT4_DIR_TAIL_FT: u8 = 0xDE;
+/// 文件名的最大长度
+const EXT4_NAME_LEN: usize = 255;
+
+/// 创建或删除链接时的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ext4LinkError {
+    /// 路径或目录项不存在
+    NotFound,
+    /// 路径中间的分量不是目录
+    NotADirectory,
+    /// 目录中已有同名的目录项
+    Exists,
+    /// 不能为目录创建或删除链接
+    IsDirectory,
+    /// 文件名为空或过长
+    InvalidName,
+    /// 链接数已达到 [`EXT4_LINK_MAX`]
+    TooManyLinks,
+    /// 没有空闲的块，或 htree 目录的索引已满
+    NoSpace,
+    /// 要删除的目录不为空
+    NotEmpty,
+    /// 没有空闲的 inode
+    NoFreeInode,
+    /// 文件系统的特性或文件类型不支持该操作
+    Unsupported,
+}
+
+/// 长度为 `name_len` 的目录项占用的空间，按 4 字节对齐
+fn ext4_dir_rec_len(name_len: usize) -> usize {
+    (8 + name_len + 3) & !3
+}
+
+fn ext4_is_dir(inode: &Ext4Inode) -> bool {
+    inode.mode & FileMode::S_IFMT.bits() == FileMode::S_IFDIR.bits()
+}
+
+/// 由 inode 的类型得到目录项中的文件类型
+fn ext4_dir_file_type(mode: u16) -> DirEntryType {
+    match FileMode::from_bits_truncate(mode & FileMode::S_IFMT.bits()) {
+        FileMode::S_IFREG => DirEntryType::REG_FILE,
+        FileMode::S_IFDIR => DirEntryType::DIR,
+        FileMode::S_IFCHR => DirEntryType::CHRDEV,
+        FileMode::S_IFBLK => DirEntryType::BLKDEV,
+        FileMode::S_IFIFO => DirEntryType::FIFO,
+        FileMode::S_IFSOCK => DirEntryType::SOCK,
+        FileMode::S_IFLNK => DirEntryType::SYMLINK,
+        _ => DirEntryType::UNKNOWN,
+    }
+}
+
+/// 目录块中目录项可以使用的长度，不包括末尾的校验和目录项
+fn ext4_dir_block_limit(block: &[u8]) -> usize {
+    let tail = block.len() - EXT4_DIR_TAIL_SIZE;
+    if read_u32(block, tail) == 0
+        && read_u16(block, tail + 4) as usize == EXT4_DIR_TAIL_SIZE
+        && block[tail + 6] == 0
+        && block[tail + 7] == EXT4_DIR_TAIL_FT
+    {
+        tail
+    } else {
+        block.len()
+    }
+}
+
+/// 在目录块中查找名为 `name` 的目录项，返回 `(块内偏移, 前一个目录项的偏移, inode)`
+fn ext4_dir_block_find(block: &[u8], name: &[u8]) -> Option<(usize, Option<usize>, u32)> {
+    let limit = ext4_dir_block_limit(block);
+    let mut prev = None;
+    let mut offset = 0;
+    while offset + 8 <= limit {
+        let inode = read_u32(block, offset);
+        let rec_len = read_u16(block, offset + 4) as usize;
+        let name_len = block[offset + 6] as usize;
+        if rec_len < 8 || offset + rec_len > limit || 8 + name_len > rec_len {
+            return None;
+        }
+        if inode != 0 && &block[offset + 8..offset + 8 + name_len] == name {
+            return Some((offset, prev, inode));
+        }
+        prev = Some(offset);
+        offset += rec_len;
+    }
+    None
+}
+
+/// 在目录块中放入一个新目录项，空间不足时返回 `false`
+fn ext4_dir_block_insert(block: &mut [u8], name: &[u8], inode: u32, file_type: u8) -> bool {
+    let limit = ext4_dir_block_limit(block);
+    let needed = ext4_dir_rec_len(name.len());
+    let mut offset = 0;
+    while offset + 8 <= limit {
+        let old_inode = read_u32(block, offset);
+        let rec_len = read_u16(block, offset + 4) as usize;
+        let name_len = block[offset + 6] as usize;
+        if rec_len < 8 || offset + rec_len > limit || 8 + name_len > rec_len {
+            return false;
+        }
+        // 已删除的目录项可以整个复用，否则使用它末尾的空闲空间
+        let used = if old_inode == 0 {
+            0
+        } else {
+            ext4_dir_rec_len(name_len)
+        };
+        if rec_len - used >= needed {
+            if used != 0 {
+                block[offset + 4..offset + 6].copy_from_slice(&(used as u16).to_le_bytes());
+            }
+            let new = offset + used;
+            block[new..new + 4].copy_from_slice(&inode.to_le_bytes());
+            block[new + 4..new + 6].copy_from_slice(&((rec_len - used) as u16).to_le_bytes());
+            block[new + 6] = name.len() as u8;
+            block[new + 7] = file_type;
+            block[new + 8..new + 8 + name.len()].copy_from_slice(name);
+            return true;
+        }
+        offset += rec_len;
+    }
+    false
+}
+
+/// 新建一个空的目录块，`tail` 表示块末尾是否有校验和目录项
+fn ext4_dir_block_init(tail: bool) -> Vec<u8> {
+    let mut block = alloc::vec![0u8; BLOCK_SIZE as usize];
+    let limit = if tail {
+        block.len() - EXT4_DIR_TAIL_SIZE
+    } else {
+        block.len()
+    };
+    block[4..6].copy_from_slice(&(limit as u16).to_le_bytes());
+    if tail {
+        block[limit + 4..limit + 6].copy_from_slice(&(EXT4_DIR_TAIL_SIZE as u16).to_le_bytes());
+        block[limit + 7] = EXT4_DIR_TAIL_FT;
+    }
+    block
+}
+
+/// 删除目录块中位于 `offset` 的目录项，`prev` 为前一个目录项的偏移
+///
+/// 与前一个目录项合并；块中的第一个目录项只能标记为空闲。
+fn ext4_dir_block_remove(block: &mut [u8], offset: usize, prev: Option<usize>) {
+    let rec_len = read_u16(block, offset + 4);
+    match prev {
+        Some(prev) => {
+            let prev_len = read_u16(block, prev + 4) + rec_len;
+            block[prev + 4..prev + 6].copy_from_slice(&prev_len.to_le_bytes());
+        }
+        None => block[offset..offset + 4].copy_from_slice(&0u32.to_le_bytes()),
+    }
+}
+
+/// 从根节点到叶子块的路径上的一个 htree 索引节点
+struct HtreeFrame {
+    /// 索引节点的逻辑块号
+    lblk: u32,
+    /// 块内 `dx_countlimit` 的偏移
+    offset: usize,
+    /// 路径经过的索引项
+    index: usize,
+}
+
+impl Ext4Fs {
+    /// 在目录 `dir` 中查找名为 `name` 的目录项，返回它指向的 inode
+    pub fn ext4_dir_lookup(&self, dir: u32, name: &[u8]) -> Option<u32> {
+        let dir_data = self.ext4_read_inode(dir as u64, &self.super_block);
+        self.ext4_dir_blocks(&dir_data)
+            .into_iter()
+            .find_map(|pblk| {
+                let block = self.read_block(pblk * BLOCK_SIZE);
+                ext4_dir_block_find(&block, name).map(|(_, _, inode)| inode)
+            })
+    }
+
+    /// 从根目录开始逐个分量地查找 `path`，返回它的 inode
+    ///
+    /// `.` 和 `..` 按目录中实际的目录项处理，不跟随符号链接。
+    pub fn ext4_path_lookup(&self, path: &str) -> Result<u32, Ext4LinkError> {
+        let mut inode = ROOT_INODE as u32;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let inode_data = self.ext4_read_inode(inode as u64, &self.super_block);
+            if !ext4_is_dir(&inode_data) {
+                return Err(Ext4LinkError::NotADirectory);
+            }
+            inode = self
+                .ext4_dir_lookup(inode, name.as_bytes())
+                .ok_or(Ext4LinkError::NotFound)?;
+        }
+        Ok(inode)
+    }
+
+    /// 在目录 `dir` 中创建指向 `inode` 的目录项 `name`，并把 inode 的链接数加一
+    pub fn ext4_link(&self, dir: u32, name: &str, inode: u32) -> Result<(), Ext4LinkError> {
+        let name = name.as_bytes();
+        if name.is_empty() || name.len() > EXT4_NAME_LEN {
+            return Err(Ext4LinkError::InvalidName);
+        }
+        let mut inode_data = self.ext4_read_inode(inode as u64, &self.super_block);
+        if ext4_is_dir(&inode_data) {
+            return Err(Ext4LinkError::IsDirectory);
+        }
+        if inode_data.links_count >= EXT4_LINK_MAX {
+            return Err(Ext4LinkError::TooManyLinks);
+        }
+        let mut dir_data = self.ext4_read_inode(dir as u64, &self.super_block);
+        if !ext4_is_dir(&dir_data) {
+            return Err(Ext4LinkError::NotADirectory);
+        }
+        if self.ext4_dir_lookup(dir, name).is_some() {
+            return Err(Ext4LinkError::Exists);
+        }
+
+        let file_type = if self.super_block.feature_incompat & EXT4_FEATURE_INCOMPAT_FILETYPE != 0 {
+            ext4_dir_file_type(inode_data.mode).bits()
+        } else {
+            0
+        };
+        let blocks = self.ext4_dir_blocks(&dir_data);
+        let flags = IFlags::from_bits_truncate(dir_data.flags);
+        // htree 目录只能放入文件名哈希对应的叶子块，否则按哈希查找时会找不到
+        let htree = match flags.contains(IFlags::EXT4_INDEX_FL) {
+            true => self.ext4_htree_root(&blocks).map(|(hash_version, _)| {
+                let (hash, _) = ext4_dirhash(name, hash_version, &self.super_block.hash_seed);
+                (hash_version, hash)
+            }),
+            false => None,
+        };
+        let htree_path = match htree {
+            Some((_, hash)) => Some(
+                self.ext4_htree_path(&blocks, hash)
+                    .ok_or(Ext4LinkError::NoSpace)?,
+            ),
+            None => None,
+        };
+        let candidates = match &htree_path {
+            Some((_, leaf)) => alloc::vec![blocks[*leaf as usize]],
+            None => blocks.clone(),
+        };
+        let found = candidates.into_iter().find(|&pblk| {
+            let mut block = self.read_block(pblk * BLOCK_SIZE);
+            if !ext4_dir_block_insert(&mut block, name, inode, file_type) {
+                return false;
+            }
+            self.ext4_write_dir_block(dir, &dir_data, pblk, &mut block);
+            true
+        });
+        match (found, htree, htree_path) {
+            (Some(pblk), _, _) => {
+                log::debug!("ext4: link {} in dir {} (block {})", inode, dir, pblk);
+            }
+            (None, Some((hash_version, hash)), Some((path, leaf))) => {
+                let entry = (name, inode, file_type);
+                self.ext4_htree_split(
+                    dir,
+                    &mut dir_data,
+                    &blocks,
+                    &path,
+                    leaf,
+                    hash_version,
+                    hash,
+                    entry,
+                )?;
+            }
+            _ => {
+                // 所有块都已满，追加一个新块
+                let tail = ext4_dir_block_limit(&self.read_block(blocks[0] * BLOCK_SIZE))
+                    != BLOCK_SIZE as usize;
+                let mut block = ext4_dir_block_init(tail);
+                if !ext4_dir_block_insert(&mut block, name, inode, file_type) {
+                    return Err(Ext4LinkError::InvalidName);
+                }
+                let pblk = self.ext4_append_block(dir, &mut dir_data)?;
+                dir_data.size += BLOCK_SIZE as u32;
+                self.ext4_write_dir_block(dir, &dir_data, pblk, &mut block);
+                log::debug!("ext4: link {} in dir {} (new block {})", inode, dir, pblk);
+            }
+        }
+
+        let now = self.ext4_now();
+        dir_data.mtime = now;
+        dir_data.ctime = now;
+        self.ext4_write_inode(dir as u64, &dir_data);
+        // 目录 inode 和目标 inode 可能位于同一块中，需要重新读取
+        inode_data = self.ext4_read_inode(inode as u64, &self.super_block);
+        inode_data.links_count += 1;
+        inode_data.ctime = now;
+        self.ext4_write_inode(inode as u64, &inode_data);
+        Ok(())
+    }
+
+    /// 从 htree 的根节点开始按哈希 `hash` 查找叶子块，返回经过的索引节点和叶子块的逻辑块号
+    fn ext4_htree_path(&self, blocks: &[u64], hash: u32) -> Option<(Vec<HtreeFrame>, u32)> {
+        let root = self.read_block(blocks[0] * BLOCK_SIZE);
+        let levels = root[30] as usize;
+        let mut path = Vec::new();
+        let mut lblk = 0;
+        let mut offset = 24 + root[29] as usize;
+        for _ in 0..=levels {
+            let node = self.read_block(blocks[lblk as usize] * BLOCK_SIZE);
+            if offset + 8 > node.len() {
+                return None;
+            }
+            let limit = read_u16(&node, offset) as usize;
+            let count = read_u16(&node, offset + 2) as usize;
+            if count == 0 || count > limit || offset + count * 8 > node.len() {
+                return None;
+            }
+            // 第一项没有哈希，覆盖所有小于第二项的哈希
+            let index = (1..count)
+                .take_while(|&i| read_u32(&node, offset + i * 8) <= hash)
+                .last()
+                .unwrap_or(0);
+            path.push(HtreeFrame {
+                lblk,
+                offset,
+                index,
+            });
+            lblk = read_u32(&node, offset + index * 8 + 4);
+            if lblk as usize >= blocks.len() {
+                return None;
+            }
+            // 中间节点以一个空的目录项开头，索引紧随其后
+            offset = 8;
+        }
+        Some((path, lblk))
+    }
+
+    /// 把已满的 htree 叶子块 `leaf` 按哈希分裂为两块，再放入新目录项 `entry`
+    ///
+    /// 较大的一半哈希移入追加到目录末尾的新块，并在最下层的索引节点中紧随原叶子块的
+    /// 索引项之后加入新块的索引项。哈希相同的目录项尽量留在同一块中，否则与 Linux
+    /// 一样设置新索引项哈希的最低位，表示哈希延续到了新块。
+    #[allow(clippy::too_many_arguments)]
+    fn ext4_htree_split(
+        &self,
+        dir: u32,
+        dir_data: &mut Ext4Inode,
+        blocks: &[u64],
+        path: &[HtreeFrame],
+        leaf: u32,
+        hash_version: u8,
+        hash: u32,
+        entry: (&[u8], u32, u8),
+    ) -> Result<(), Ext4LinkError> {
+        let frame = path.last().unwrap();
+        let node_pblk = blocks[frame.lblk as usize];
+        let mut node = self.read_block(node_pblk * BLOCK_SIZE);
+        let limit = read_u16(&node, frame.offset) as usize;
+        let count = read_u16(&node, frame.offset + 2) as usize;
+        if count >= limit {
+            return Err(Ext4LinkError::NoSpace);
+        }
+
+        let leaf_pblk = blocks[leaf as usize];
+        let old = self.read_block(leaf_pblk * BLOCK_SIZE);
+        let tail = ext4_dir_block_limit(&old) != old.len();
+        let seed = self.super_block.hash_seed;
+        let mut entries: Vec<(u32, u32, DirBlockEntry)> = ext4_dir_block_entries(&old)
+            .0
+            .into_iter()
+            .map(|e| {
+                let (major, minor) = ext4_dirhash(e.3, hash_version, &seed);
+                (major & !1, minor, e)
+            })
+            .collect();
+        entries.sort_by_key(|&(major, minor, _)| (major, minor));
+        if entries.len() < 2 {
+            return Err(Ext4LinkError::NoSpace);
+        }
+        // 从中间开始找哈希变化的位置
+        let mid = entries.len() / 2;
+        let differs = |i: &usize| entries[*i].0 != entries[*i - 1].0;
+        let split = (mid..entries.len())
+            .find(differs)
+            .or_else(|| (1..mid).rev().find(differs));
+        let (split, continued) = match split {
+            Some(split) => (split, 0),
+            None => (mid, 1),
+        };
+        let new_hash = entries[split].0 | continued;
+
+        let mut lower = ext4_dir_block_init(tail);
+        let mut upper = ext4_dir_block_init(tail);
+        for (i, (_, _, (_, inode, file_type, name))) in entries.iter().enumerate() {
+            let block = if i < split { &mut lower } else { &mut upper };
+            ext4_dir_block_insert(block, name, *inode, *file_type);
+        }
+        let (name, inode, file_type) = entry;
+        let target = if hash & !1 >= new_hash & !1 && continued == 0 {
+            &mut upper
+        } else {
+            &mut lower
+        };
+        if !ext4_dir_block_insert(target, name, inode, file_type) {
+            return Err(Ext4LinkError::NoSpace);
+        }
+
+        let new_lblk = blocks.len() as u32;
+        let new_pblk = self.ext4_append_block(dir, dir_data)?;
+        dir_data.size += BLOCK_SIZE as u32;
+        self.ext4_write_dir_block(dir, dir_data, new_pblk, &mut upper);
+        self.ext4_write_dir_block(dir, dir_data, leaf_pblk, &mut lower);
+
+        // 在原叶子块的索引项之后插入新块的索引项
+        let at = frame.offset + (frame.index + 1) * 8;
+        let end = frame.offset + count * 8;
+        node.copy_within(at..end, at + 8);
+        node[at..at + 4].copy_from_slice(&new_hash.to_le_bytes());
+        node[at + 4..at + 8].copy_from_slice(&new_lblk.to_le_bytes());
+        node[frame.offset + 2..frame.offset + 4].copy_from_slice(&(count as u16 + 1).to_le_bytes());
+        self.ext4_write_dx_node(dir, dir_data, node_pblk, &mut node, frame.offset);
+        log::debug!(
+            "ext4: split htree leaf {} of dir {} at hash {:#x}, new leaf {}",
+            leaf,
+            dir,
+            new_hash,
+            new_lblk
+        );
+        Ok(())
+    }
+
+    /// 删除目录 `dir` 中的目录项 `name`，并把它指向的 inode 的链接数减一
+    ///
+    /// 删除的是最后一个链接时返回该 inode，它已进入孤儿链表，不再使用后由调用者通过
+    /// [`ext4_evict_inode`](Ext4Fs::ext4_evict_inode) 释放。
+    pub fn ext4_unlink(&self, dir: u32, name: &str) -> Result<Option<u32>, Ext4LinkError> {
+        let mut dir_data = self.ext4_read_inode(dir as u64, &self.super_block);
+        if !ext4_is_dir(&dir_data) {
+            return Err(Ext4LinkError::NotADirectory);
+        }
+        let (pblk, mut block, (offset, prev, inode)) = self
+            .ext4_dir_find_block(&dir_data, name.as_bytes())
+            .ok_or(Ext4LinkError::NotFound)?;
+        let inode_data = self.ext4_read_inode(inode as u64, &self.super_block);
+        if ext4_is_dir(&inode_data) {
+            return Err(Ext4LinkError::IsDirectory);
+        }
+        ext4_dir_block_remove(&mut block, offset, prev);
+        self.ext4_write_dir_block(dir, &dir_data, pblk, &mut block);
+
+        let now = self.ext4_now();
+        dir_data.mtime = now;
+        dir_data.ctime = now;
+        self.ext4_write_inode(dir as u64, &dir_data);
+        let mut inode_data = self.ext4_read_inode(inode as u64, &self.super_block);
+        inode_data.links_count = inode_data.links_count.saturating_sub(1);
+        inode_data.ctime = now;
+        self.ext4_write_inode(inode as u64, &inode_data);
+        if inode_data.links_count > 0 {
+            return Ok(None);
+        }
+        self.ext4_orphan_add(inode);
+        Ok(Some(inode))
+    }
+
+    /// 删除目录 `dir` 中的空目录 `name`，返回它的 inode
+    ///
+    /// 与 [`ext4_unlink`](Ext4Fs::ext4_unlink) 删除最后一个链接时相同，该 inode 已进入
+    /// 孤儿链表，由调用者释放。
+    pub fn ext4_rmdir(&self, dir: u32, name: &str) -> Result<u32, Ext4LinkError> {
+        if name == "." || name == ".." {
+            return Err(Ext4LinkError::InvalidName);
+        }
+        let mut dir_data = self.ext4_read_inode(dir as u64, &self.super_block);
+        if !ext4_is_dir(&dir_data) {
+            return Err(Ext4LinkError::NotADirectory);
+        }
+        let (pblk, mut block, (offset, prev, inode)) = self
+            .ext4_dir_find_block(&dir_data, name.as_bytes())
+            .ok_or(Ext4LinkError::NotFound)?;
+        let inode_data = self.ext4_read_inode(inode as u64, &self.super_block);
+        if !ext4_is_dir(&inode_data) {
+            return Err(Ext4LinkError::NotADirectory);
+        }
+        if self
+            .ext4_dir_read_from(inode, 0, 3)
+            .iter()
+            .any(|e| e.name != "." && e.name != "..")
+        {
+            return Err(Ext4LinkError::NotEmpty);
+        }
+        ext4_dir_block_remove(&mut block, offset, prev);
+        self.ext4_write_dir_block(dir, &dir_data, pblk, &mut block);
+
+        // 子目录的 ".." 不再指向父目录；与 Linux 一致，链接数为 1 表示超过了上限，不再减少
+        let now = self.ext4_now();
+        if dir_data.links_count > 2 {
+            dir_data.links_count -= 1;
+        }
+        dir_data.mtime = now;
+        dir_data.ctime = now;
+        self.ext4_write_inode(dir as u64, &dir_data);
+        let mut inode_data = self.ext4_read_inode(inode as u64, &self.super_block);
+        inode_data.links_count = 0;
+        inode_data.ctime = now;
+        self.ext4_write_inode(inode as u64, &inode_data);
+        self.ext4_orphan_add(inode);
+        Ok(inode)
+    }
+
+    /// 在目录中查找名为 `name` 的目录项，返回 `(物理块号, 块的内容, 块内的查找结果)`
+    #[allow(clippy::type_complexity)]
+    fn ext4_dir_find_block(
+        &self,
+        dir_data: &Ext4Inode,
+        name: &[u8],
+    ) -> Option<(u64, Vec<u8>, (usize, Option<usize>, u32))> {
+        self.ext4_dir_blocks(dir_data).into_iter().find_map(|pblk| {
+            let block = self.read_block(pblk * BLOCK_SIZE);
+            let found = ext4_dir_block_find(&block, name)?;
+            Some((pblk, block, found))
+        })
+    }
+
+    /// 更新目录块末尾的校验和（如果有）并写回
+    fn ext4_write_dir_block(&self, dir: u32, dir_data: &Ext4Inode, pblk: u64, block: &mut [u8]) {
+        let limit = ext4_dir_block_limit(block);
+        if self.ext4_has_metadata_csum() && limit != block.len() {
+            let seed = self.ext4_inode_csum_seed(dir, dir_data.generation);
+            let csum = ext4_crc32c(seed, &block[..limit]);
+            block[limit + 8..limit + 12].copy_from_slice(&csum.to_le_bytes());
+        }
+        self.block_device.write_block(pblk as usize, block);
+    }
+
+    /// 更新 htree 索引节点的校验和（如果有）并写回，`offset` 为 `dx_countlimit` 的偏移
+    ///
+    /// 校验和覆盖到最后一个索引项为止，以及 `dx_tail` 中校验和之前的部分。
+    fn ext4_write_dx_node(
+        &self,
+        dir: u32,
+        dir_data: &Ext4Inode,
+        pblk: u64,
+        node: &mut [u8],
+        offset: usize,
+    ) {
+        let limit = read_u16(node, offset) as usize;
+        let count = read_u16(node, offset + 2) as usize;
+        let tail = offset + limit * 8;
+        if self.ext4_has_metadata_csum() && tail + 8 <= node.len() {
+            let seed = self.ext4_inode_csum_seed(dir, dir_data.generation);
+            let mut csum = ext4_crc32c(seed, &node[..offset + count * 8]);
+            csum = ext4_crc32c(csum, &node[tail..tail + 4]);
+            csum = ext4_crc32c(csum, &[0; 4]);
+            node[tail + 4..tail + 8].copy_from_slice(&csum.to_le_bytes());
+        }
+        self.block_device.write_block(pblk as usize, node);
+    }
+}
@@ -0,0 +1,175 @@
+//! 挂载选项的解析
+
+/// 超级块 `errors` 字段：出错后重新挂载为只读
+const EXT4_ERRORS_RO: u16 = 2;
+/// 超级块 `errors` 字段：出错后 panic
+const EXT4_ERRORS_PANIC: u16 = 3;
+
+/// 默认的提交间隔，单位为秒
+pub const EXT4_DEF_COMMIT_INTERVAL: u32 = 5;
+
+/// 访问时间的更新策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ext4AtimeMode {
+    /// 每次访问都更新（`strictatime`）
+    Strict,
+    /// 只在 atime 早于 mtime/ctime 或超过一天时更新（`relatime`）
+    Relative,
+    /// 从不更新（`noatime`）
+    None,
+}
+
+impl Ext4AtimeMode {
+    /// 在 `now` 访问文件时是否需要更新访问时间
+    pub fn need_update(self, atime: u32, mtime: u32, ctime: u32, now: u32) -> bool {
+        match self {
+            Self::Strict => atime != now,
+            // 与 Linux 一致，atime 不晚于 mtime/ctime 或已经过了一天时才更新
+            Self::Relative => {
+                atime <= mtime || atime <= ctime || now.saturating_sub(atime) >= 24 * 60 * 60
+            }
+            Self::None => false,
+        }
+    }
+}
+
+/// 数据的写入模式（`data=`）
+///
+/// 驱动没有日志，数据和元数据都直接写入块设备，两种模式的行为相同。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ext4DataMode {
+    /// 先写数据，再写元数据
+    Ordered,
+    /// 数据和元数据的写入顺序不做保证
+    Writeback,
+}
+
+/// 发现文件系统错误后的处理方式（`errors=`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ext4ErrorsBehavior {
+    /// 记录错误后继续
+    Continue,
+    /// 重新挂载为只读
+    RemountRo,
+    /// panic
+    Panic,
+}
+
+impl Ext4ErrorsBehavior {
+    /// 从超级块的 `errors` 字段得到默认的处理方式
+    pub fn from_super_block(errors: u16) -> Self {
+        match errors {
+            EXT4_ERRORS_RO => Self::RemountRo,
+            EXT4_ERRORS_PANIC => Self::Panic,
+            // EXT4_ERRORS_CONTINUE 以及未知的值
+            _ => Self::Continue,
+        }
+    }
+}
+
+/// 挂载选项解析失败的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ext4OptionError {
+    /// 不认识的选项
+    Unknown(alloc::string::String),
+    /// 选项的值不合法
+    InvalidValue(alloc::string::String),
+    /// 驱动不支持的选项，如 `data=journal`
+    Unsupported(alloc::string::String),
+}
+
+/// ext4 的挂载选项
+///
+/// 驱动不支持日志，所有修改都直接写入块设备，因此不接受 `data=journal`，
+/// `data=ordered`、`data=writeback` 和 `commit=` 只记录下来。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ext4MountOptions {
+    /// 只读挂载
+    pub read_only: bool,
+    /// 访问时间的更新策略
+    pub atime: Ext4AtimeMode,
+    /// 数据的写入模式
+    pub data: Ext4DataMode,
+    /// 提交间隔，单位为秒
+    pub commit: u32,
+    /// 出错后的处理方式
+    pub errors: Ext4ErrorsBehavior,
+    /// 每次修改后都让块设备落盘
+    pub sync: bool,
+}
+
+impl Default for Ext4MountOptions {
+    fn default() -> Self {
+        Self {
+            read_only: false,
+            atime: Ext4AtimeMode::Relative,
+            data: Ext4DataMode::Ordered,
+            commit: EXT4_DEF_COMMIT_INTERVAL,
+            errors: Ext4ErrorsBehavior::Continue,
+            sync: false,
+        }
+    }
+}
+
+impl Ext4MountOptions {
+    /// 以超级块中记录的默认值为基础解析 `data` 中逗号分隔的选项
+    pub fn parse(data: &str, super_block_errors: u16) -> Result<Self, Ext4OptionError> {
+        let mut options = Self {
+            errors: Ext4ErrorsBehavior::from_super_block(super_block_errors),
+            ..Self::default()
+        };
+        options.apply(data)?;
+        Ok(options)
+    }
+
+    /// 重新挂载时在当前选项的基础上应用 `data` 中的选项
+    pub fn remount(&self, data: &str) -> Result<Self, Ext4OptionError> {
+        let mut options = *self;
+        options.apply(data)?;
+        Ok(options)
+    }
+
+    fn apply(&mut self, data: &str) -> Result<(), Ext4OptionError> {
+        use alloc::string::ToString;
+        for opt in data.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = match opt.split_once('=') {
+                Some((k, v)) => (k, Some(v)),
+                None => (opt, None),
+            };
+            let invalid = || Ext4OptionError::InvalidValue(opt.to_string());
+            match (key, value) {
+                ("ro", None) => self.read_only = true,
+                ("rw", None) => self.read_only = false,
+                ("noatime", None) => self.atime = Ext4AtimeMode::None,
+                ("relatime", None) => self.atime = Ext4AtimeMode::Relative,
+                ("strictatime", None) => self.atime = Ext4AtimeMode::Strict,
+                ("sync", None) => self.sync = true,
+                ("async", None) => self.sync = false,
+                ("defaults", None) => {}
+                ("data", Some("ordered")) => self.data = Ext4DataMode::Ordered,
+                ("data", Some("writeback")) => self.data = Ext4DataMode::Writeback,
+                ("data", Some("journal")) => {
+                    return Err(Ext4OptionError::Unsupported(opt.to_string()))
+                }
+                ("data", Some(_)) => return Err(invalid()),
+                // 与 Linux 一致，0 表示默认的间隔
+                ("commit", Some(v)) => {
+                    self.commit = match v.parse().map_err(|_| invalid())? {
+                        0 => EXT4_DEF_COMMIT_INTERVAL,
+                        n => n,
+                    }
+                }
+                ("errors", Some(v)) => {
+                    self.errors = match v {
+                        "continue" => Ext4ErrorsBehavior::Continue,
+                        "remount-ro" => Ext4ErrorsBehavior::RemountRo,
+                        "panic" => Ext4ErrorsBehavior::Panic,
+                        _ => return Err(invalid()),
+                    }
+                }
+                _ => return Err(Ext4OptionError::Unknown(opt.to_string())),
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,317 @@
+//! 超级块的加载、挂载状态和错误记录
+//!
+//! 与 Linux 一致：
+//! - 读写挂载时增加 `mnt_count`，更新 `mtime`，并清除 `state` 中的
+//!   [`EXT4_VALID_FS`]，卸载或重新挂载为只读时再设置回来，因此异常断电后
+//!   e2fsck 能发现文件系统没有正常卸载；
+//! - 发现元数据损坏时在 `state` 中设置 [`EXT4_ERROR_FS`]，并记录出错的次数、
+//!   时间、inode、块号和位置，之后按挂载选项 `errors=` 处理；
+//! - 主超级块损坏时依次尝试组 1, 3, 5, 7, 9, 25, 27, ... 中的备份超级块。
+
+use core::mem::size_of;
+use core::sync::atomic::Ordering;
+
+use crate::crc::ext4_crc32c;
+use crate::defs::*;
+use crate::inode::EXT4_FEATURE_RO_COMPAT_METADATA_CSUM;
+use crate::options::Ext4ErrorsBehavior;
+use crate::{BlockDevice, Ext4Fs, Ext4Traits};
+
+/// 超级块的魔数
+pub const EXT4_SUPER_MAGIC: u16 = 0xEF53;
+
+/// `state`：文件系统已正常卸载
+pub const EXT4_VALID_FS: u16 = 0x0001;
+/// `state`：检测到了错误
+pub const EXT4_ERROR_FS: u16 = 0x0002;
+
+/// 超级块在磁盘上占用的大小
+const EXT4_SUPER_BLOCK_SIZE: usize = 1024;
+/// `checksum` 字段在超级块中的偏移
+const EXT4_SUPER_CSUM_OFFSET: usize = 0x3FC;
+/// `padding2` 中 `first_error_errcode` 的下标（偏移 0x27A）
+const EXT4_FIRST_ERRCODE_IDX: usize = 6;
+/// `padding2` 中 `last_error_errcode` 的下标（偏移 0x27B）
+const EXT4_LAST_ERRCODE_IDX: usize = 7;
+/// 错误码：元数据损坏
+const EXT4_ERR_EFSCORRUPTED: u8 = 5;
+
+/// `feature_ro_compat`：只有部分块组保存超级块备份
+pub const EXT4_FEATURE_RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
+
+/// 主超级块损坏时无从得知块组大小，与 e2fsck 一样按 mkfs 的默认值猜测
+const EXT4_DEFAULT_BLOCKS_PER_GROUP: u64 = 8 * BLOCK_SIZE;
+
+/// 开启 sparse_super 时保存超级块备份的块组：1 以及 3、5、7 的幂，按递增顺序
+pub fn ext4_sparse_super_groups() -> impl Iterator<Item = u64> {
+    let mut powers = [3u64, 5, 7];
+    let mut first = true;
+    core::iter::from_fn(move || {
+        if first {
+            first = false;
+            return Some(1);
+        }
+        let next = *powers.iter().min().unwrap();
+        for (p, base) in powers.iter_mut().zip([3, 5, 7]) {
+            if *p == next {
+                *p = p.checked_mul(base)?;
+            }
+        }
+        Some(next)
+    })
+}
+
+/// 计算超级块的校验和
+fn ext4_super_csum(raw: &[u8]) -> u32 {
+    ext4_crc32c(!0, &raw[..EXT4_SUPER_CSUM_OFFSET])
+}
+
+fn ext4_super_block_from_bytes(raw: &[u8]) -> Ext4SuperBlock {
+    let mut buf = [0u8; size_of::<Ext4SuperBlock>()];
+    buf.copy_from_slice(&raw[..size_of::<Ext4SuperBlock>()]);
+    unsafe { core::ptr::read(buf.as_ptr() as *const _) }
+}
+
+fn ext4_super_block_to_bytes(super_block: &Ext4SuperBlock, raw: &mut [u8]) {
+    let bytes = unsafe {
+        core::slice::from_raw_parts(
+            super_block as *const Ext4SuperBlock as *const u8,
+            size_of::<Ext4SuperBlock>(),
+        )
+    };
+    raw[..bytes.len()].copy_from_slice(bytes);
+}
+
+/// 检查超级块是否可用：魔数、基本的几何参数以及校验和
+pub fn ext4_super_block_valid(raw: &[u8]) -> bool {
+    let super_block = ext4_super_block_from_bytes(raw);
+    if super_block.magic != EXT4_SUPER_MAGIC
+        || super_block.log_block_size > 6
+        || super_block.blocks_per_group == 0
+        || super_block.inodes_per_group == 0
+    {
+        return false;
+    }
+    if super_block.feature_ro_compat & EXT4_FEATURE_RO_COMPAT_METADATA_CSUM != 0 {
+        return ext4_super_csum(raw) == super_block.checksum;
+    }
+    true
+}
+
+/// 读取位于字节偏移 `offset` 处的超级块原始数据
+fn ext4_read_super_raw(block_device: &dyn BlockDevice, offset: u64) -> [u8; EXT4_SUPER_BLOCK_SIZE] {
+    let mut data = [0u8; BLOCK_SIZE as usize];
+    block_device.read_block(offset as usize, &mut data);
+    let mut raw = [0u8; EXT4_SUPER_BLOCK_SIZE];
+    raw.copy_from_slice(&data[..EXT4_SUPER_BLOCK_SIZE]);
+    raw
+}
+
+/// 找到一个可用的超级块，返回它的字节偏移和内容
+///
+/// 所有副本都不可用时返回主超级块，由调用者根据魔数拒绝挂载。
+pub(crate) fn ext4_load_super_block(block_device: &dyn BlockDevice) -> (u64, Ext4SuperBlock) {
+    let raw = ext4_read_super_raw(block_device, BASE_OFFSET);
+    if ext4_super_block_valid(&raw) {
+        return (BASE_OFFSET, ext4_super_block_from_bytes(&raw));
+    }
+    log::warn!("ext4: primary superblock is corrupted, trying backups");
+
+    let device_size = block_device.block_num() as u64 * BLOCK_SIZE;
+    for group in ext4_sparse_super_groups() {
+        // 4K 的块时 first_data_block 为 0，备份位于块组的第一个块的开头
+        let offset = group * EXT4_DEFAULT_BLOCKS_PER_GROUP * BLOCK_SIZE;
+        if offset + EXT4_SUPER_BLOCK_SIZE as u64 > device_size {
+            break;
+        }
+        let backup = ext4_read_super_raw(block_device, offset);
+        if ext4_super_block_valid(&backup) {
+            let super_block = ext4_super_block_from_bytes(&backup);
+            if super_block.block_group_nr as u64 == group {
+                log::warn!("ext4: using backup superblock in group {}", group);
+                return (offset, super_block);
+            }
+        }
+    }
+    (BASE_OFFSET, ext4_super_block_from_bytes(&raw))
+}
+
+impl Ext4Fs {
+    /// 当前时间，单位为秒
+    pub(crate) fn ext4_now(&self) -> u32 {
+        (self.clock)()
+    }
+
+    /// 设置获取当前时间（秒）的函数，用于超级块中的各个时间戳
+    pub fn set_clock(&mut self, clock: fn() -> u32) {
+        self.clock = clock;
+    }
+
+    /// 是否从备份超级块加载
+    pub fn ext4_from_backup_super_block(&self) -> bool {
+        self.sb_offset != BASE_OFFSET
+    }
+
+    /// 是否只读挂载（包括因错误而变为只读）
+    pub fn ext4_is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Acquire)
+    }
+
+    /// 设置发现错误后的处理方式
+    pub fn ext4_set_errors_behavior(&self, errors: Ext4ErrorsBehavior) {
+        let v = match errors {
+            Ext4ErrorsBehavior::Continue => 0,
+            Ext4ErrorsBehavior::RemountRo => 1,
+            Ext4ErrorsBehavior::Panic => 2,
+        };
+        self.errors.store(v, Ordering::Release);
+    }
+
+    fn ext4_errors_behavior(&self) -> Ext4ErrorsBehavior {
+        match self.errors.load(Ordering::Acquire) {
+            1 => Ext4ErrorsBehavior::RemountRo,
+            2 => Ext4ErrorsBehavior::Panic,
+            _ => Ext4ErrorsBehavior::Continue,
+        }
+    }
+
+    /// 读出磁盘上的超级块，交给 `f` 修改后写回，同时更新 `wtime` 和校验和
+    pub fn ext4_update_super_block(&self, f: impl FnOnce(&mut Ext4SuperBlock)) {
+        let block_id = self.sb_offset / BLOCK_SIZE;
+        let start = (self.sb_offset % BLOCK_SIZE) as usize;
+        let mut block = self.read_block(block_id * BLOCK_SIZE);
+        let raw = &mut block[start..start + EXT4_SUPER_BLOCK_SIZE];
+
+        let mut super_block = ext4_super_block_from_bytes(raw);
+        f(&mut super_block);
+        super_block.wtime = self.ext4_now();
+        ext4_super_block_to_bytes(&super_block, raw);
+        if super_block.feature_ro_compat & EXT4_FEATURE_RO_COMPAT_METADATA_CSUM != 0 {
+            let csum = ext4_super_csum(raw);
+            raw[EXT4_SUPER_CSUM_OFFSET..].copy_from_slice(&csum.to_le_bytes());
+        }
+        self.block_device.write_block(block_id as usize, &block);
+    }
+
+    /// 开始以读写方式使用文件系统：增加挂载次数并标记为未正常卸载
+    fn ext4_setup_super(&self) {
+        let now = self.ext4_now();
+        let from_backup = self.ext4_from_backup_super_block();
+        self.ext4_update_super_block(|sb| {
+            if sb.state & EXT4_VALID_FS == 0 {
+                log::warn!("ext4: mounting unchecked fs, running e2fsck is recommended");
+            } else if sb.state & EXT4_ERROR_FS != 0 {
+                log::warn!("ext4: mounting fs with errors, running e2fsck is recommended");
+            } else if (sb.max_mnt_count as i16) > 0 && sb.mnt_count >= sb.max_mnt_count {
+                log::warn!("ext4: maximal mount count reached, running e2fsck is recommended");
+            }
+            sb.mnt_count = sb.mnt_count.wrapping_add(1);
+            sb.mtime = now;
+            sb.state &= !EXT4_VALID_FS;
+            // 主超级块已损坏，需要 e2fsck 修复
+            if from_backup {
+                sb.state |= EXT4_ERROR_FS;
+            }
+        });
+    }
+
+    /// 结束读写：标记为已正常卸载，保留检测到的错误
+    fn ext4_commit_clean(&self) {
+        self.ext4_update_super_block(|sb| sb.state |= EXT4_VALID_FS);
+    }
+
+    /// 挂载文件系统，只读挂载时不修改磁盘
+    pub fn ext4_mount(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Release);
+        if !read_only {
+            self.ext4_setup_super();
+            // 上次卸载前未释放的 inode 仍在孤儿链表中
+            self.ext4_orphan_cleanup();
+        }
+    }
+
+    /// 在只读和读写之间切换
+    pub fn ext4_remount(&self, read_only: bool) {
+        let was_read_only = self.read_only.swap(read_only, Ordering::AcqRel);
+        match (was_read_only, read_only) {
+            (true, false) => self.ext4_setup_super(),
+            (false, true) => self.ext4_commit_clean(),
+            _ => {}
+        }
+    }
+
+    /// 卸载文件系统，读写挂载时标记为已正常卸载
+    ///
+    /// 驱动的所有修改都直接写入块设备，也不写日志，因此没有需要提交的事务，
+    /// 只需标记超级块后让设备落盘。
+    pub fn ext4_umount(&self) {
+        if !self.read_only.swap(true, Ordering::AcqRel) {
+            self.ext4_commit_clean();
+        }
+        self.block_device.flush();
+    }
+
+    /// 把整个文件系统写回磁盘（`sync`）
+    pub fn ext4_sync(&self) {
+        if !self.ext4_is_read_only() {
+            // 只更新 wtime
+            self.ext4_update_super_block(|_| {});
+        }
+        self.block_device.flush();
+    }
+
+    /// 把一个 inode 的数据和元数据写回磁盘（`fsync`）
+    ///
+    /// inode 的修改在 [`ext4_write_inode`](Ext4Fs::ext4_write_inode) 时已经写入
+    /// 块设备，只需让设备落盘。
+    pub fn ext4_fsync(&self, _inode: u64) {
+        self.block_device.flush();
+    }
+
+    /// 记录检测到的元数据损坏，然后按 `errors=` 的设置处理
+    ///
+    /// `ino` 和 `block` 为 0 表示与具体的 inode 或块无关。
+    pub fn ext4_error(&self, ino: u32, block: u64, func: &str, line: u32, msg: &str) {
+        log::error!(
+            "EXT4-fs error: {}:{}: inode #{}: block {}: {}",
+            func,
+            line,
+            ino,
+            block,
+            msg
+        );
+        let now = self.ext4_now();
+        let mut func_name = [0u8; 32];
+        let len = func.len().min(func_name.len());
+        func_name[..len].copy_from_slice(&func.as_bytes()[..len]);
+
+        self.ext4_update_super_block(|sb| {
+            sb.state |= EXT4_ERROR_FS;
+            if sb.error_count == 0 || sb.first_error_time == 0 {
+                sb.first_error_time = now;
+                sb.first_error_ino = ino;
+                sb.first_error_block = block;
+                sb.first_error_func = func_name;
+                sb.first_error_line = line;
+                sb.padding2[EXT4_FIRST_ERRCODE_IDX] = EXT4_ERR_EFSCORRUPTED;
+            }
+            sb.last_error_time = now;
+            sb.last_error_ino = ino;
+            sb.last_error_block = block;
+            sb.last_error_func = func_name;
+            sb.last_error_line = line;
+            sb.padding2[EXT4_LAST_ERRCODE_IDX] = EXT4_ERR_EFSCORRUPTED;
+            sb.error_count = sb.error_count.saturating_add(1);
+        });
+
+        match self.ext4_errors_behavior() {
+            Ext4ErrorsBehavior::Continue => {}
+            Ext4ErrorsBehavior::RemountRo => {
+                if !self.read_only.swap(true, Ordering::AcqRel) {
+                    log::error!("EXT4-fs: remounting filesystem read-only");
+                }
+            }
+            Ext4ErrorsBehavior::Panic => panic!("EXT4-fs: panic forced after error"),
+        }
+    }
+}
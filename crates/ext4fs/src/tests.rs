@@ -0,0 +1,629 @@
+use crate::*;
+
+#[test]
+fn test_dirhash() {
+    let seed = [0u32; 4];
+    let cases: [(&[u8], u8, (u32, u32)); 7] = [
+        (b"hello", DX_HASH_LEGACY, (0x32252546, 0)),
+        (b"lost+found", DX_HASH_LEGACY, (0x5e2aba24, 0)),
+        (b"hello", DX_HASH_HALF_MD4, (0x1746da32, 0x420013b5)),
+        (b"lost+found", DX_HASH_HALF_MD4, (0x591de422, 0x6ffc56e0)),
+        (
+            b"a_rather_long_file_name_that_exceeds_thirty_two_bytes.txt",
+            DX_HASH_HALF_MD4,
+            (0x6749f6ac, 0x02110160),
+        ),
+        (b"hello", DX_HASH_TEA, (0x6f5bb1a8, 0x231917c2)),
+        (
+            b"a_rather_long_file_name_that_exceeds_thirty_two_bytes.txt",
+            DX_HASH_TEA,
+            (0x9cadf6a4, 0xcbf9a2af),
+        ),
+    ];
+    for (name, version, expected) in cases {
+        assert_eq!(ext4_dirhash(name, version, &seed), expected);
+    }
+}
+
+#[test]
+fn test_dirhash_seed() {
+    let seed = [0x67452301, 0xefcdab89, 0x67452301, 0xefcdab89];
+    assert_eq!(
+        ext4_dirhash(b"hello", DX_HASH_HALF_MD4, &seed),
+        (0xa26e4a80, 0x97e5b7f7)
+    );
+    // 非 ASCII 字符按有符号字符参与计算
+    assert_eq!(
+        ext4_dirhash("café".as_bytes(), DX_HASH_TEA, &[0; 4]),
+        (0x105842ea, 0xfb9165ca)
+    );
+}
+
+#[test]
+fn test_crc32c() {
+    assert_eq!(!ext4_crc32c(!0, b"123456789"), 0xe306_9283);
+    assert_eq!(ext4_crc32c(!0, b""), !0);
+    // 分段计算与一次计算的结果相同
+    let crc = ext4_crc32c(!0, b"1234");
+    assert_eq!(ext4_crc32c(crc, b"56789"), ext4_crc32c(!0, b"123456789"));
+}
+
+#[test]
+fn test_mount_options() {
+    let opts = Ext4MountOptions::parse("", 2).unwrap();
+    assert_eq!(opts.errors, Ext4ErrorsBehavior::RemountRo);
+    assert_eq!(opts.atime, Ext4AtimeMode::Relative);
+    assert!(!opts.read_only && !opts.sync);
+
+    let opts = Ext4MountOptions::parse("ro,noatime,errors=panic,sync", 1).unwrap();
+    assert!(opts.read_only && opts.sync);
+    assert_eq!(opts.atime, Ext4AtimeMode::None);
+    assert_eq!(opts.errors, Ext4ErrorsBehavior::Panic);
+
+    // 后出现的选项覆盖前面的
+    let opts = Ext4MountOptions::parse("ro,rw,sync,async,noatime,strictatime", 1).unwrap();
+    assert!(!opts.read_only && !opts.sync);
+    assert_eq!(opts.atime, Ext4AtimeMode::Strict);
+
+    assert!(matches!(
+        Ext4MountOptions::parse("errors=ignore", 1),
+        Err(Ext4OptionError::InvalidValue(_))
+    ));
+    assert!(matches!(
+        Ext4MountOptions::parse("nodelalloc", 1),
+        Err(Ext4OptionError::Unknown(_))
+    ));
+
+    // 没有日志，data= 和 commit= 只记录下来
+    let opts = Ext4MountOptions::parse("", 1).unwrap();
+    assert_eq!(opts.data, Ext4DataMode::Ordered);
+    assert_eq!(opts.commit, EXT4_DEF_COMMIT_INTERVAL);
+    let opts = Ext4MountOptions::parse("data=writeback,commit=30", 1).unwrap();
+    assert_eq!(opts.data, Ext4DataMode::Writeback);
+    assert_eq!(opts.commit, 30);
+    let opts = Ext4MountOptions::parse("data=ordered,commit=0", 1).unwrap();
+    assert_eq!(opts.data, Ext4DataMode::Ordered);
+    assert_eq!(opts.commit, EXT4_DEF_COMMIT_INTERVAL);
+    assert!(matches!(
+        Ext4MountOptions::parse("data=journal", 1),
+        Err(Ext4OptionError::Unsupported(_))
+    ));
+    for opt in ["data=fast", "commit=soon", "commit=-1"] {
+        assert!(matches!(
+            Ext4MountOptions::parse(opt, 1),
+            Err(Ext4OptionError::InvalidValue(_))
+        ));
+    }
+}
+
+#[test]
+fn test_remount_options() {
+    let opts = Ext4MountOptions::parse("noatime", 1).unwrap();
+    let ro = opts.remount("ro").unwrap();
+    assert!(ro.read_only);
+    assert_eq!(ro.atime, Ext4AtimeMode::None);
+    assert!(!ro.remount("rw").unwrap().read_only);
+    assert!(ro.remount("sync").unwrap().sync);
+    assert_eq!(
+        ro.remount("data=writeback").unwrap().data,
+        Ext4DataMode::Writeback
+    );
+    assert!(ro.remount("data=journal").is_err());
+}
+
+#[test]
+fn test_atime_mode() {
+    const DAY: u32 = 24 * 60 * 60;
+    assert!(!Ext4AtimeMode::None.need_update(0, 100, 100, 200));
+    assert!(Ext4AtimeMode::Strict.need_update(150, 100, 100, 200));
+    assert!(!Ext4AtimeMode::Strict.need_update(200, 100, 100, 200));
+    // relatime 只在 atime 不晚于 mtime/ctime 或过了一天时更新
+    assert!(Ext4AtimeMode::Relative.need_update(100, 100, 50, 200));
+    assert!(Ext4AtimeMode::Relative.need_update(100, 50, 150, 200));
+    assert!(!Ext4AtimeMode::Relative.need_update(150, 100, 100, 200));
+    assert!(Ext4AtimeMode::Relative.need_update(150, 100, 100, 150 + DAY));
+}
+
+#[test]
+fn test_sparse_super_groups() {
+    let groups: Vec<u64> = ext4_sparse_super_groups().take(12).collect();
+    assert_eq!(groups, [1, 3, 5, 7, 9, 25, 27, 49, 81, 125, 243, 343]);
+    assert_eq!(core::mem::size_of::<Ext4SuperBlock>(), 1024);
+}
+
+#[test]
+fn test_rdev_encoding() {
+    let mut block = [0xffff_ffffu32; 15];
+    ext4_encode_rdev(&mut block, 1, 3);
+    assert_eq!((block[0], block[1]), (0x0103, 0));
+    assert_eq!(ext4_decode_rdev(&block), (1, 3));
+    // 超出旧格式范围时使用新格式
+    ext4_encode_rdev(&mut block, 259, 0x12345);
+    assert_eq!((block[0], block[1], block[2]), (0, 0x1231_0345, 0));
+    assert_eq!(ext4_decode_rdev(&block), (259, 0x12345));
+    ext4_encode_rdev(&mut block, 4, 300);
+    assert_eq!(ext4_decode_rdev(&block), (4, 300));
+}
+
+/// 以内存作为块设备
+struct MemDevice(std::sync::Mutex<Vec<u8>>);
+
+impl BlockDevice for MemDevice {
+    fn read_block(&self, offset: usize, buf: &mut [u8]) {
+        let data = self.0.lock().unwrap();
+        let end = (offset + buf.len()).min(data.len());
+        let len = end.saturating_sub(offset);
+        buf[..len].copy_from_slice(&data[offset..offset + len]);
+        buf[len..].fill(0);
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let offset = block_id * BLOCK_SIZE as usize;
+        self.0.lock().unwrap()[offset..offset + buf.len()].copy_from_slice(buf);
+    }
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE as usize
+    }
+    fn block_num(&self) -> usize {
+        self.0.lock().unwrap().len() / BLOCK_SIZE as usize
+    }
+}
+
+const TEST_BLOCKS: usize = 256;
+const TEST_INODES: u32 = 64;
+/// 普通文件 `/file` 的 inode，数据位于块 9~11
+const TEST_FILE_INO: u32 = 12;
+/// 空目录 `/sub` 的 inode，目录块为块 12
+const TEST_SUB_INO: u32 = 13;
+/// htree 目录 `/hdir` 的 inode，根块为块 13，唯一的叶子块为块 14
+const TEST_HTREE_INO: u32 = 14;
+
+fn put(image: &mut [u8], offset: usize, bytes: &[u8]) {
+    image[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+/// 写入一个目录项
+fn put_dirent(
+    image: &mut [u8],
+    offset: usize,
+    inode: u32,
+    rec_len: u16,
+    file_type: u8,
+    name: &[u8],
+) {
+    put(image, offset, &inode.to_le_bytes());
+    put(image, offset + 4, &rec_len.to_le_bytes());
+    image[offset + 6] = name.len() as u8;
+    image[offset + 7] = file_type;
+    put(image, offset + 8, name);
+}
+
+/// 写入一个只有一个 extent 的 inode
+fn put_inode(image: &mut [u8], ino: u32, mode: u16, links: u16, flags: u32, start: u32, len: u16) {
+    let base = 4 * BLOCK_SIZE as usize + (ino as usize - 1) * 256;
+    let size = len as u32 * BLOCK_SIZE as u32;
+    put(image, base, &mode.to_le_bytes());
+    put(image, base + 0x4, &size.to_le_bytes());
+    put(image, base + 0x1A, &links.to_le_bytes());
+    put(image, base + 0x1C, &(len as u32 * 8).to_le_bytes());
+    put(
+        image,
+        base + 0x20,
+        &(flags | IFlags::EXT4_EXTENTS_FL.bits()).to_le_bytes(),
+    );
+    // extent 树的根节点：一个叶子 extent
+    put(
+        image,
+        base + 0x28,
+        &[0x0a, 0xf3, 1, 0, 4, 0, 0, 0, 0, 0, 0, 0],
+    );
+    put(image, base + 0x34, &0u32.to_le_bytes());
+    put(image, base + 0x38, &len.to_le_bytes());
+    put(image, base + 0x3C, &start.to_le_bytes());
+    put(image, base + 0x80, &32u16.to_le_bytes());
+}
+
+/// 构造一个只有一个块组的 ext4 镜像，不启用元数据校验和
+///
+/// 块 0 为超级块，块 1 为组描述符，块 2、3 为位图，块 4~7 为 inode 表，块 8 为根目录。
+/// 根目录下有普通文件 `file`、空目录 `sub` 和只有一个叶子块的 htree 目录 `hdir`。
+fn test_image() -> Arc<MemDevice> {
+    let mut image = alloc::vec![0u8; TEST_BLOCKS * BLOCK_SIZE as usize];
+    let used_blocks = 15;
+    let used_inodes = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 12, 13, 14];
+
+    let sb = 1024;
+    put(&mut image, sb, &TEST_INODES.to_le_bytes());
+    put(&mut image, sb + 0x4, &(TEST_BLOCKS as u32).to_le_bytes());
+    put(
+        &mut image,
+        sb + 0xC,
+        &(TEST_BLOCKS as u32 - used_blocks).to_le_bytes(),
+    );
+    put(
+        &mut image,
+        sb + 0x10,
+        &(TEST_INODES - used_inodes.len() as u32).to_le_bytes(),
+    );
+    put(&mut image, sb + 0x18, &2u32.to_le_bytes());
+    put(&mut image, sb + 0x1C, &2u32.to_le_bytes());
+    put(&mut image, sb + 0x20, &32768u32.to_le_bytes());
+    put(&mut image, sb + 0x24, &32768u32.to_le_bytes());
+    put(&mut image, sb + 0x28, &TEST_INODES.to_le_bytes());
+    put(&mut image, sb + 0x38, &EXT4_SUPER_MAGIC.to_le_bytes());
+    put(&mut image, sb + 0x3A, &1u16.to_le_bytes());
+    put(&mut image, sb + 0x3C, &1u16.to_le_bytes());
+    put(&mut image, sb + 0x4C, &1u32.to_le_bytes());
+    put(&mut image, sb + 0x54, &11u32.to_le_bytes());
+    put(&mut image, sb + 0x58, &256u16.to_le_bytes());
+    // dir_index；filetype、extents 和 64bit
+    put(&mut image, sb + 0x5C, &0x20u32.to_le_bytes());
+    put(&mut image, sb + 0x60, &0xC2u32.to_le_bytes());
+    image[sb + 0xFC] = DX_HASH_HALF_MD4;
+    put(&mut image, sb + 0xFE, &64u16.to_le_bytes());
+    put(&mut image, sb + 0x15C, &32u16.to_le_bytes());
+    put(&mut image, sb + 0x15E, &32u16.to_le_bytes());
+
+    let gd = BLOCK_SIZE as usize;
+    put(&mut image, gd, &2u32.to_le_bytes());
+    put(&mut image, gd + 0x4, &3u32.to_le_bytes());
+    put(&mut image, gd + 0x8, &4u32.to_le_bytes());
+    put(
+        &mut image,
+        gd + 0xC,
+        &(TEST_BLOCKS as u16 - used_blocks as u16).to_le_bytes(),
+    );
+    put(
+        &mut image,
+        gd + 0xE,
+        &(TEST_INODES as u16 - used_inodes.len() as u16).to_le_bytes(),
+    );
+    put(&mut image, gd + 0x10, &3u16.to_le_bytes());
+
+    // 与 mke2fs 一样，位图中超出块组范围的位都标记为已使用
+    let block_bitmap = 2 * BLOCK_SIZE as usize;
+    for bit in (0..used_blocks as usize).chain(TEST_BLOCKS..BLOCK_SIZE as usize * 8) {
+        image[block_bitmap + bit / 8] |= 1 << (bit % 8);
+    }
+    let inode_bitmap = 3 * BLOCK_SIZE as usize;
+    let unused = TEST_INODES as usize..BLOCK_SIZE as usize * 8;
+    for bit in used_inodes
+        .iter()
+        .map(|&ino| ino as usize - 1)
+        .chain(unused)
+    {
+        image[inode_bitmap + bit / 8] |= 1 << (bit % 8);
+    }
+
+    let dir_mode = FileMode::S_IFDIR.bits() | 0o755;
+    put_inode(&mut image, 2, dir_mode, 4, 0, 8, 1);
+    put_inode(
+        &mut image,
+        TEST_FILE_INO,
+        FileMode::S_IFREG.bits() | 0o644,
+        1,
+        0,
+        9,
+        3,
+    );
+    put_inode(&mut image, TEST_SUB_INO, dir_mode, 2, 0, 12, 1);
+    let index = IFlags::EXT4_INDEX_FL.bits();
+    put_inode(&mut image, TEST_HTREE_INO, dir_mode, 2, index, 13, 2);
+
+    let dir_type = DirEntryType::DIR.bits();
+    let root = 8 * BLOCK_SIZE as usize;
+    put_dirent(&mut image, root, 2, 12, dir_type, b".");
+    put_dirent(&mut image, root + 12, 2, 12, dir_type, b"..");
+    let reg_type = DirEntryType::REG_FILE.bits();
+    put_dirent(&mut image, root + 24, TEST_FILE_INO, 12, reg_type, b"file");
+    put_dirent(&mut image, root + 36, TEST_SUB_INO, 12, dir_type, b"sub");
+    put_dirent(
+        &mut image,
+        root + 48,
+        TEST_HTREE_INO,
+        4048,
+        dir_type,
+        b"hdir",
+    );
+    let sub = 12 * BLOCK_SIZE as usize;
+    put_dirent(&mut image, sub, TEST_SUB_INO, 12, dir_type, b".");
+    put_dirent(&mut image, sub + 12, 2, 4084, dir_type, b"..");
+
+    // htree 的根块：dx_root_info 之后只有指向逻辑块 1 的一项
+    let hroot = 13 * BLOCK_SIZE as usize;
+    put_dirent(&mut image, hroot, TEST_HTREE_INO, 12, dir_type, b".");
+    put_dirent(&mut image, hroot + 12, 2, 4084, dir_type, b"..");
+    image[hroot + 28] = DX_HASH_HALF_MD4;
+    image[hroot + 29] = 8;
+    put(&mut image, hroot + 32, &508u16.to_le_bytes());
+    put(&mut image, hroot + 34, &1u16.to_le_bytes());
+    put(&mut image, hroot + 36, &1u32.to_le_bytes());
+    put_dirent(&mut image, 14 * BLOCK_SIZE as usize, 0, 4096, 0, b"");
+
+    Arc::new(MemDevice(std::sync::Mutex::new(image)))
+}
+
+fn test_fs(device: &Arc<MemDevice>) -> Ext4Fs {
+    let mut fs = Ext4Fs::open(device.clone());
+    fs.set_clock(|| 1_700_000_000);
+    fs.ext4_mount(false);
+    fs
+}
+
+/// 空闲块数和空闲 inode 数
+fn free_counts(fs: &Ext4Fs) -> (u64, u32) {
+    let sb = fs.read_super_block();
+    let blocks = sb.free_blocks_count as u64 | (sb.free_blocks_count_hi as u64) << 32;
+    (blocks, sb.free_inodes_count)
+}
+
+/// 从头到尾分批读取目录的所有文件名
+fn dir_names(fs: &Ext4Fs, dir: u32, batch: usize) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut pos = 0;
+    loop {
+        let entries = fs.ext4_dir_read_from(dir, pos, batch);
+        let Some(last) = entries.last() else {
+            return names;
+        };
+        pos = last.next_pos();
+        names.extend(entries.into_iter().map(|e| e.name));
+    }
+}
+
+#[test]
+fn test_link_grows_directory() {
+    let device = test_image();
+    let fs = test_fs(&device);
+    let (free_blocks, free_inodes) = free_counts(&fs);
+    let fifo = FileMode::S_IFIFO.bits() | 0o644;
+    let inode = fs.ext4_mknod(2, "fifo", fifo, 0, 0).unwrap();
+
+    // 每个目录项 40 字节，300 个目录项需要为根目录追加几个块
+    let name = |i: usize| format!("a-rather-long-link-name-{:03}", i);
+    for i in 0..300 {
+        fs.ext4_link(2, &name(i), inode).unwrap();
+    }
+    let root = fs.ext4_read_inode(2, &fs.super_block);
+    let dir_blocks = root.size as u64 / BLOCK_SIZE;
+    assert!(dir_blocks >= 3);
+    assert_eq!(root.blocks as u64, dir_blocks * 8);
+    assert_eq!(
+        fs.ext4_read_inode(inode as u64, &fs.super_block)
+            .links_count,
+        301
+    );
+    assert_eq!(fs.ext4_path_lookup(&name(299)), Ok(inode));
+    assert_eq!(dir_names(&fs, 2, 64).len(), 306);
+    assert_eq!(
+        free_counts(&fs),
+        (free_blocks - (dir_blocks - 1), free_inodes - 1)
+    );
+
+    for i in 0..300 {
+        assert_eq!(fs.ext4_unlink(2, &name(i)), Ok(None));
+    }
+    assert_eq!(fs.ext4_unlink(2, "fifo"), Ok(Some(inode)));
+    fs.ext4_evict_inode(inode);
+    assert_eq!(dir_names(&fs, 2, 64), [".", "..", "file", "sub", "hdir"]);
+    assert_eq!(
+        free_counts(&fs),
+        (free_blocks - (dir_blocks - 1), free_inodes)
+    );
+}
+
+#[test]
+fn test_touch_atime() {
+    let device = test_image();
+    let mut fs = test_fs(&device);
+    let atime = |fs: &Ext4Fs| {
+        fs.ext4_read_inode(TEST_FILE_INO as u64, &fs.super_block)
+            .atime
+    };
+
+    fs.ext4_touch_atime(TEST_FILE_INO as u64, Ext4AtimeMode::None);
+    assert_eq!(atime(&fs), 0);
+    fs.ext4_touch_atime(TEST_FILE_INO as u64, Ext4AtimeMode::Relative);
+    assert_eq!(atime(&fs), 1_700_000_000);
+
+    fs.set_clock(|| 1_700_000_100);
+    fs.ext4_touch_atime(TEST_FILE_INO as u64, Ext4AtimeMode::Relative);
+    assert_eq!(atime(&fs), 1_700_000_000);
+    fs.ext4_touch_atime(TEST_FILE_INO as u64, Ext4AtimeMode::Strict);
+    assert_eq!(atime(&fs), 1_700_000_100);
+
+    // 只读挂载时不修改磁盘
+    fs.set_clock(|| 1_700_000_200);
+    fs.ext4_remount(true);
+    fs.ext4_touch_atime(TEST_FILE_INO as u64, Ext4AtimeMode::Strict);
+    assert_eq!(atime(&fs), 1_700_000_100);
+}
+
+#[test]
+fn test_unlink_last_link() {
+    let device = test_image();
+    let fs = test_fs(&device);
+    let (free_blocks, free_inodes) = free_counts(&fs);
+
+    fs.ext4_link(TEST_SUB_INO, "again", TEST_FILE_INO).unwrap();
+    assert_eq!(fs.ext4_unlink(2, "file"), Ok(None));
+    assert_eq!(
+        fs.ext4_unlink(TEST_SUB_INO, "again"),
+        Ok(Some(TEST_FILE_INO))
+    );
+    assert_eq!(
+        fs.ext4_path_lookup("sub/again"),
+        Err(Ext4LinkError::NotFound)
+    );
+    // 释放前 inode 位于孤儿链表中，数据块仍被占用
+    assert_eq!(fs.read_super_block().last_orphan, TEST_FILE_INO);
+    assert_eq!(free_counts(&fs), (free_blocks, free_inodes));
+
+    fs.ext4_evict_inode(TEST_FILE_INO);
+    assert_eq!(fs.read_super_block().last_orphan, 0);
+    assert_eq!(free_counts(&fs), (free_blocks + 3, free_inodes + 1));
+    let inode = fs.ext4_read_inode(TEST_FILE_INO as u64, &fs.super_block);
+    assert_eq!((inode.size, inode.blocks), (0, 0));
+    assert_ne!(inode.dtime, 0);
+    // 释放的块和 inode 可以重新分配
+    assert_eq!(fs.ext4_alloc_block(9), Ok(9));
+    assert_eq!(fs.ext4_alloc_inode(2, FileMode::S_IFIFO.bits()), Ok(11));
+    assert_eq!(
+        fs.ext4_alloc_inode(2, FileMode::S_IFIFO.bits()),
+        Ok(TEST_FILE_INO)
+    );
+}
+
+#[test]
+fn test_orphan_cleanup_on_mount() {
+    let device = test_image();
+    let fs = test_fs(&device);
+    let (free_blocks, free_inodes) = free_counts(&fs);
+    let fifo = FileMode::S_IFIFO.bits() | 0o644;
+    let inode = fs.ext4_mknod(2, "fifo", fifo, 0, 0).unwrap();
+    assert_eq!(fs.ext4_unlink(2, "fifo"), Ok(Some(inode)));
+    assert_eq!(fs.ext4_unlink(2, "file"), Ok(Some(TEST_FILE_INO)));
+    assert_eq!(fs.read_super_block().last_orphan, TEST_FILE_INO);
+
+    // 没有释放就重新挂载，孤儿链表中的两个 inode 都被释放
+    let fs = test_fs(&device);
+    assert_eq!(fs.read_super_block().last_orphan, 0);
+    assert_eq!(free_counts(&fs), (free_blocks + 3, free_inodes + 1));
+}
+
+#[test]
+fn test_rmdir() {
+    let device = test_image();
+    let fs = test_fs(&device);
+    let (free_blocks, free_inodes) = free_counts(&fs);
+
+    fs.ext4_link(TEST_SUB_INO, "file", TEST_FILE_INO).unwrap();
+    assert_eq!(fs.ext4_unlink(2, "sub"), Err(Ext4LinkError::IsDirectory));
+    assert_eq!(fs.ext4_rmdir(2, "sub"), Err(Ext4LinkError::NotEmpty));
+    assert_eq!(fs.ext4_rmdir(2, "file"), Err(Ext4LinkError::NotADirectory));
+    assert_eq!(
+        fs.ext4_rmdir(TEST_SUB_INO, ".."),
+        Err(Ext4LinkError::InvalidName)
+    );
+    assert_eq!(fs.ext4_unlink(TEST_SUB_INO, "file"), Ok(None));
+
+    assert_eq!(fs.ext4_rmdir(2, "sub"), Ok(TEST_SUB_INO));
+    assert_eq!(fs.ext4_read_inode(2, &fs.super_block).links_count, 3);
+    fs.ext4_evict_inode(TEST_SUB_INO);
+    assert_eq!(free_counts(&fs), (free_blocks + 1, free_inodes + 1));
+    assert_eq!(dir_names(&fs, 2, 8), [".", "..", "file", "hdir"]);
+}
+
+#[test]
+fn test_htree_leaf_split() {
+    let device = test_image();
+    let fs = test_fs(&device);
+    let fifo = FileMode::S_IFIFO.bits() | 0o644;
+    let inode = fs.ext4_mknod(2, "fifo", fifo, 0, 0).unwrap();
+
+    let name = |i: usize| format!("htree-entry-{}", i);
+    for i in 0..400 {
+        fs.ext4_link(TEST_HTREE_INO, &name(i), inode).unwrap();
+    }
+    let dir = fs.ext4_read_inode(TEST_HTREE_INO as u64, &fs.super_block);
+    assert!(IFlags::from_bits_truncate(dir.flags).contains(IFlags::EXT4_INDEX_FL));
+    let blocks = fs.ext4_dir_blocks(&dir);
+    assert!(blocks.len() > 3);
+
+    // 每个叶子块中的文件名哈希都落在索引给出的范围内
+    let (hash_version, leaves) = fs.ext4_htree_root(&blocks).unwrap();
+    assert_eq!(leaves.len(), blocks.len() - 1);
+    let mut count = 0;
+    for (i, &(start, lblk)) in leaves.iter().enumerate() {
+        let end = leaves.get(i + 1).map_or(u32::MAX, |&(hash, _)| hash & !1);
+        let block = fs.read_block(blocks[lblk as usize] * BLOCK_SIZE);
+        for (_, _, _, name) in crate::dir::ext4_dir_block_entries(&block).0 {
+            let (hash, _) = ext4_dirhash(name, hash_version, &[0; 4]);
+            assert!(hash >= start & !1 && hash <= end, "{:?}", name);
+            count += 1;
+        }
+    }
+    assert_eq!(count, 400);
+    let mut names = dir_names(&fs, TEST_HTREE_INO, 7);
+    assert_eq!(names.len(), 402);
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), 402);
+}
+
+/// 分批遍历目录，每读完一批调用一次 `between`，返回读到的所有文件名
+fn dir_names_with(
+    fs: &Ext4Fs,
+    dir: u32,
+    batch: usize,
+    mut between: impl FnMut(usize),
+) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut pos = 0;
+    for round in 0.. {
+        let entries = fs.ext4_dir_read_from(dir, pos, batch);
+        let Some(last) = entries.last() else {
+            break;
+        };
+        assert!(entries.windows(2).all(|w| w[0].pos <= w[1].pos));
+        assert!(entries.iter().all(|e| e.inode != 0));
+        pos = last.next_pos();
+        names.extend(entries.into_iter().map(|e| e.name));
+        between(round);
+    }
+    names
+}
+
+#[test]
+fn test_dir_resume_across_changes() {
+    let device = test_image();
+    let fs = test_fs(&device);
+    let fifo = FileMode::S_IFIFO.bits() | 0o644;
+    let inode = fs.ext4_mknod(2, "fifo", fifo, 0, 0).unwrap();
+
+    for dir in [2, TEST_HTREE_INO] {
+        let old = |i: usize| format!("old-entry-{:03}", i);
+        let new = |i: usize| format!("new-entry-{:03}", i);
+        for i in 0..300 {
+            fs.ext4_link(dir, &old(i), inode).unwrap();
+        }
+        // 完整读一遍：每个目录项恰好出现一次
+        let mut all = dir_names(&fs, dir, 5);
+        all.sort();
+        let len = all.len();
+        all.dedup();
+        assert_eq!(all.len(), len);
+
+        // 遍历过程中删除一半旧目录项并加入新目录项，htree 目录的叶子块会因此分裂
+        let names = dir_names_with(&fs, dir, 7, |round| {
+            if round == 3 {
+                for i in (0..300).step_by(2) {
+                    fs.ext4_unlink(dir, &old(i)).unwrap();
+                }
+                for i in 0..200 {
+                    fs.ext4_link(dir, &new(i), inode).unwrap();
+                }
+            }
+        });
+        let mut sorted = names.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted.len(), names.len(), "duplicated entries");
+        // 整个遍历期间都存在的目录项一个也不能少
+        for i in (1..300).step_by(2) {
+            assert!(names.contains(&old(i)), "{} missing in dir {}", old(i), dir);
+        }
+        assert!(names.iter().any(|name| name == "."));
+
+        for i in (1..300).step_by(2) {
+            fs.ext4_unlink(dir, &old(i)).unwrap();
+        }
+        for i in 0..200 {
+            fs.ext4_unlink(dir, &new(i)).unwrap();
+        }
+    }
+    assert_eq!(
+        dir_names(&fs, 2, 3),
+        [".", "..", "file", "sub", "hdir", "fifo"]
+    );
+    assert_eq!(dir_names(&fs, TEST_HTREE_INO, 3).len(), 2);
+}
@@ -4,6 +4,10 @@
 //! [`core::alloc::GlobalAlloc`]. A static global variable of type
 //! [`GlobalAllocator`] is defined with the `#[global_allocator]` attribute, to
 //! be registered as the standard library’s default allocator.
+//!
+//! Caches holding memory that can be dropped at will (e.g. the page cache of
+//! the filesystem) can register a [`ReclaimFn`] with [`register_reclaimer`].
+//! It is called to free memory when an allocation runs out of pages.
 
 #![no_std]
 
@@ -12,7 +16,9 @@ extern crate log;
 extern crate alloc;
 
 mod page;
-use allocator::{AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator};
+use allocator::{
+    AllocError, AllocResult, BaseAllocator, BitmapPageAllocator, ByteAllocator, PageAllocator,
+};
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::NonNull;
 pub use page::PhysPage;
@@ -20,6 +26,18 @@ use spinlock::SpinNoIrq;
 
 const PAGE_SIZE: usize = 0x1000;
 const MIN_HEAP_SIZE: usize = 0x8000; // 32 K
+const MAX_RECLAIMERS: usize = 4;
+
+/// A callback that frees cached memory under memory pressure.
+///
+/// It is asked to free `num_pages` pages, and returns the number of pages
+/// actually freed. It is called without any lock of the allocator held, but
+/// may be called in the middle of an allocation, so it must not block on
+/// locks that may be held by the allocating task.
+pub type ReclaimFn = fn(num_pages: usize) -> usize;
+
+static RECLAIMERS: SpinNoIrq<[Option<ReclaimFn>; MAX_RECLAIMERS]> =
+    SpinNoIrq::new([None; MAX_RECLAIMERS]);
 
 pub use page::GlobalPage;
 
@@ -114,7 +132,21 @@ impl GlobalAllocator {
                     .max(layout.size())
                     .next_power_of_two()
                     .max(PAGE_SIZE);
-                let heap_ptr = self.alloc_pages(expand_size / PAGE_SIZE, PAGE_SIZE)?;
+                let num_pages = expand_size / PAGE_SIZE;
+                let heap_ptr = match self.palloc.lock().alloc_pages(num_pages, PAGE_SIZE) {
+                    Ok(ptr) => ptr,
+                    Err(AllocError::NoMemory) => {
+                        // reclaimers free heap memory too, so the byte
+                        // allocator must be unlocked while they run
+                        drop(balloc);
+                        if reclaim(num_pages) == 0 {
+                            return Err(AllocError::NoMemory);
+                        }
+                        balloc = self.balloc.lock();
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
                 debug!(
                     "expand heap memory: [{:#x}, {:#x})",
                     heap_ptr,
@@ -142,8 +174,17 @@ impl GlobalAllocator {
     ///
     /// `align_pow2` must be a power of 2, and the returned region bound will be
     /// aligned to it.
+    ///
+    /// If there is no memory, the registered reclaimers are asked to free
+    /// some, and the allocation is retried.
     pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
-        self.palloc.lock().alloc_pages(num_pages, align_pow2)
+        loop {
+            let res = self.palloc.lock().alloc_pages(num_pages, align_pow2);
+            match res {
+                Err(AllocError::NoMemory) if reclaim(num_pages) > 0 => continue,
+                res => return res,
+            }
+        }
     }
 
     /// Gives back the allocated pages starts from `pos` to the page allocator.
@@ -200,6 +241,39 @@ pub fn global_allocator() -> &'static GlobalAllocator {
     &GLOBAL_ALLOCATOR
 }
 
+/// Registers a callback to free cached memory when the page allocator runs out
+/// of memory.
+///
+/// Returns `false` if too many reclaimers have been registered.
+pub fn register_reclaimer(f: ReclaimFn) -> bool {
+    let mut reclaimers = RECLAIMERS.lock();
+    match reclaimers.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(f);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Asks the registered reclaimers to free `num_pages` pages. Returns the
+/// number of pages freed.
+fn reclaim(num_pages: usize) -> usize {
+    // copy the callbacks out, they may allocate or free memory
+    let reclaimers = *RECLAIMERS.lock();
+    let mut freed = 0;
+    for f in reclaimers.into_iter().flatten() {
+        if freed >= num_pages {
+            break;
+        }
+        freed += f(num_pages - freed);
+    }
+    if freed > 0 {
+        debug!("reclaimed {} pages", freed);
+    }
+    freed
+}
+
 /// Initializes the global allocator with the given memory region.
 ///
 /// Note that the memory region bounds are just numbers, and the allocator
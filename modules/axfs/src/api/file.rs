@@ -1,9 +1,14 @@
+use alloc::{sync::Arc, vec::Vec};
 use axio::{prelude::*, Result, SeekFrom};
-use core::fmt;
+use core::{fmt, time::Duration};
 
+use super::DeviceId;
 #[cfg(feature = "monolithic")]
 use super::FileExt;
 use crate::fops;
+use crate::locks::{LockOwner, LockType, RecordLock};
+use crate::namei::ResolvedPath;
+use crate::page_cache::PageCache;
 
 /// A structure representing a type of file with accessors for each file type.
 /// It is returned by [`Metadata::file_type`] method.
@@ -12,6 +17,12 @@ pub type FileType = fops::FileType;
 /// Representation of the various permissions on a file.
 pub type Permissions = fops::FilePerm;
 
+/// Inode flags of a file, as used by `FS_IOC_GETFLAGS`/`FS_IOC_SETFLAGS`.
+pub type FileFlags = fops::FileFlags;
+
+/// A range of a file mapped to the underlying device.
+pub type FileExtent = fops::FileExtent;
+
 /// An object providing access to an open file on the filesystem.
 #[derive(Clone)]
 pub struct File {
@@ -19,7 +30,7 @@ pub struct File {
 }
 
 /// Metadata information about a file.
-pub struct Metadata(fops::FileAttr);
+pub struct Metadata(pub(super) fops::FileAttr);
 
 /// Options and flags which can be used to configure how a file is opened.
 #[derive(Clone, Debug)]
@@ -71,6 +82,12 @@ impl OpenOptions {
     pub fn open(&self, path: &str) -> Result<File> {
         fops::File::open(path, &self.0).map(|inner| File { inner })
     }
+
+    /// Opens the file reached by [`resolve_path`](super::resolve_path) with
+    /// the options specified by `self`, without resolving its path again.
+    pub fn open_resolved(&self, resolved: &ResolvedPath) -> Result<File> {
+        fops::File::open_resolved(resolved, &self.0).map(|inner| File { inner })
+    }
 }
 
 impl Metadata {
@@ -118,6 +135,42 @@ impl Metadata {
     pub const fn blocks(&self) -> u64 {
         self.0.blocks()
     }
+
+    /// Returns the number of hard links to the file.
+    pub const fn nlink(&self) -> u64 {
+        self.0.nlink()
+    }
+
+    /// Returns the device number, if the file is a character or block
+    /// device.
+    pub const fn rdev(&self) -> DeviceId {
+        self.0.rdev()
+    }
+
+    /// Returns the user ID of the owner of the file.
+    pub const fn uid(&self) -> u32 {
+        self.0.uid()
+    }
+
+    /// Returns the group ID of the owner of the file.
+    pub const fn gid(&self) -> u32 {
+        self.0.gid()
+    }
+
+    /// Returns the last access time of the file.
+    pub const fn accessed(&self) -> Duration {
+        self.0.atime()
+    }
+
+    /// Returns the last modification time of the file.
+    pub const fn modified(&self) -> Duration {
+        self.0.mtime()
+    }
+
+    /// Returns the last status change time of the file.
+    pub const fn changed(&self) -> Duration {
+        self.0.ctime()
+    }
 }
 
 impl fmt::Debug for Metadata {
@@ -178,6 +231,107 @@ impl File {
     pub fn truncate(&mut self, len: usize) -> Result<()> {
         self.inner.truncate(len as u64)
     }
+
+    /// Queries the inode flags of the underlying file.
+    pub fn flags(&self) -> Result<FileFlags> {
+        self.inner.get_flags()
+    }
+
+    /// Changes the inode flags of the underlying file.
+    pub fn set_flags(&self, flags: FileFlags) -> Result<()> {
+        self.inner.set_flags(flags)
+    }
+
+    /// Changes the permissions of the underlying file.
+    pub fn set_permissions(&self, perm: Permissions) -> Result<()> {
+        self.inner.set_perm(perm)
+    }
+
+    /// Changes the owner and group of the underlying file. `None` leaves the
+    /// ID unchanged.
+    pub fn set_owner(&self, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        self.inner.set_owner(uid, gid)
+    }
+
+    /// Changes the access and modification times of the underlying file.
+    /// `None` leaves the time unchanged.
+    pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> Result<()> {
+        self.inner.set_times(atime, mtime)
+    }
+
+    /// Queries the extents of the underlying file that overlap
+    /// `[start, start + len)`.
+    pub fn fiemap(&self, start: u64, len: u64) -> Result<Vec<FileExtent>> {
+        self.inner.fiemap(start, len)
+    }
+
+    /// Returns the first record lock on the file conflicting with `lock`
+    /// (`F_GETLK`), `None` if `lock` can be placed.
+    pub fn test_lock(&self, lock: &RecordLock) -> Result<Option<RecordLock>> {
+        self.inner.test_lock(lock)
+    }
+
+    /// Places the record lock `lock` on the file, or removes the locks of
+    /// its owner in its range if it is [`LockType::Unlock`] (`F_SETLK`).
+    /// Fails with `EAGAIN` if another owner holds a conflicting lock.
+    ///
+    /// To wait for the lock (`F_SETLKW`), retry with `wait` set until it is
+    /// placed; it fails with `EDEADLK` if the owner of the conflicting lock
+    /// waits for the caller. Call [`cancel_lock_wait`](super::cancel_lock_wait)
+    /// when giving up.
+    pub fn set_lock(&self, lock: &RecordLock, wait: bool) -> Result<()> {
+        self.inner.set_lock(lock, wait)
+    }
+
+    /// Places the whole-file lock of type `ty` owned by `owner` on the file,
+    /// or removes it if `ty` is [`LockType::Unlock`] (`flock`). Fails with
+    /// `EAGAIN` if another owner holds a conflicting lock.
+    pub fn flock(&self, owner: LockOwner, ty: LockType) -> Result<()> {
+        self.inner.flock(owner, ty)
+    }
+
+    /// Removes all the locks of `owner` on the file, when a process closes
+    /// it or the last reference to an opened file is dropped.
+    pub fn release_locks(&self, owner: LockOwner) {
+        self.inner.release_locks(owner)
+    }
+
+    /// Returns the page cache of the file, shared by everyone accessing the
+    /// file. `None` if the file is not cached, e.g. a device.
+    pub fn page_cache(&self) -> Option<Arc<PageCache>> {
+        self.inner.page_cache().cloned()
+    }
+
+    /// Returns the number of the loop device if this file is `/dev/loopN`.
+    pub fn loop_number(&self) -> Option<usize> {
+        crate::loopdev::number_of(self.inner.node().ok()?)
+    }
+
+    /// Returns whether this file is `/dev/loop-control`.
+    pub fn is_loop_control(&self) -> bool {
+        self.inner.node().map_or(false, |node| {
+            node.as_any().is::<crate::loopdev::LoopControl>()
+        })
+    }
+
+    /// Reads the current time if this file is a real-time clock, e.g.
+    /// `/dev/rtc0`.
+    #[cfg(feature = "devfs")]
+    pub fn rtc_time(&self) -> Option<crate::fs::devfs::RtcTime> {
+        let node = self.inner.node().ok()?;
+        node.as_any()
+            .downcast_ref::<crate::fs::devfs::RtcDev>()
+            .map(|rtc| rtc.now())
+    }
+
+    /// Binds loop device `number` to this file (`LOOP_SET_FD`). `name` is
+    /// reported as the file name in the status of the device.
+    ///
+    /// The device is read-only if this file is not opened for writing.
+    pub fn attach_loop(&self, number: usize, name: &str) -> Result<()> {
+        let node = self.inner.node()?.clone();
+        crate::loopdev::attach(number, node, name, !self.inner.writable())
+    }
 }
 
 impl Read for File {
@@ -12,10 +12,36 @@ use axfs_vfs::VfsNodeRef;
 pub use port::*;
 
 pub use self::dir::{DirBuilder, DirEntry, ReadDir};
-pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
+pub use self::file::{
+    File, FileExtent, FileFlags, FileType, Metadata, OpenOptions, Permissions,
+};
+#[cfg(feature = "fuse")]
+pub use crate::fuse::{FuseConnection, FUSE_DEVICE};
+pub use crate::inotify::{Inotify, InotifyMask, MAX_QUEUED_EVENTS, MAX_WATCHES};
+pub use crate::locks::{cancel_lock_wait, release_locks, LockOwner, LockType, RecordLock};
+pub use crate::loopdev::{LoopStatus, LOOP_DEVICE_COUNT};
+pub use crate::namei::{LookupFlags, ResolvedPath, MAX_SYMLINKS};
+pub use crate::page_cache::{CachedPage, PageCache};
+pub use crate::perm::{
+    current_credentials, generic_permission, permission, register_credentials,
+    register_permission_hook, Access, Credentials, CredentialsFn, PermissionHook,
+};
+pub use crate::root::MountInfo;
+pub use crate::signal::{register_signal_pending, signal_pending, SignalPendingFn};
+pub use axfs_vfs::DeviceId;
+
+#[cfg(feature = "procfs")]
+pub use crate::fs::procfs::{
+    register_process_entries, ProcDir, ProcEntries, ProcFile, ProcFileSystem, ProcSymlink,
+};
+#[cfg(feature = "devfs")]
+pub use crate::fs::devpts::{register_pts_entries, PtsEntries};
+#[cfg(feature = "sysfs")]
+pub use crate::fs::sysfs::{add_sysfs_node, SysAttr, SysDir, SysFileSystem, SysLink};
 
 use alloc::{string::String, vec::Vec};
 use axio::{self as io, prelude::*};
+use core::time::Duration;
 
 /// Returns an iterator over the entries within a directory.
 pub fn read_dir(path: &str) -> io::Result<ReadDir> {
@@ -63,8 +89,33 @@ pub fn write<C: AsRef<[u8]>>(path: &str, contents: C) -> io::Result<()> {
 
 /// Given a path, query the file system to get information about a file,
 /// directory, etc.
+///
+/// The file is not opened, so it needs no read permission.
 pub fn metadata(path: &str) -> io::Result<Metadata> {
-    File::open(path)?.metadata()
+    crate::root::lookup(None, path)?.get_attr().map(Metadata)
+}
+
+/// Checks that the caller may execute the file at `path`, which must be a
+/// regular file, and returns its metadata, with the set-user-ID and
+/// set-group-ID bits that change the credentials of the new program.
+pub fn check_exec(path: &str) -> AxResult<Metadata> {
+    let node = crate::root::lookup(None, path)?;
+    let attr = node.get_attr()?;
+    if !attr.is_file() {
+        return axerrno::ax_err!(PermissionDenied);
+    }
+    permission(&current_credentials(), &attr, Access::EXEC)?;
+    Ok(Metadata(attr))
+}
+
+/// Checks that `cred` may access the file at `path` as requested by
+/// `access(2)`: an empty `mode` only checks that the file exists.
+pub fn access(path: &str, mode: Access, cred: &Credentials) -> AxResult {
+    let attr = crate::root::lookup(None, path)?.get_attr()?;
+    if mode.is_empty() {
+        return Ok(());
+    }
+    permission(cred, &attr, mode)
 }
 
 /// Creates a new, empty directory at the provided path.
@@ -88,8 +139,30 @@ pub fn remove_file(path: &str) -> io::Result<()> {
     crate::root::remove_file(None, path)
 }
 
-/// Rename a file or directory to a new name.
-/// Delete the original file if `old` already exists.
+/// Creates a new hard link `new` to the file at `old`.
+///
+/// A symbolic link at `old` is linked itself. Both paths must be on the same
+/// mounted fs.
+pub fn hard_link(old: &str, new: &str) -> io::Result<()> {
+    crate::root::hard_link(old, new)
+}
+
+/// Creates a new symbolic link `path` pointing to `target`.
+pub fn symlink(target: &str, path: &str) -> AxResult {
+    crate::root::symlink(None, target, path)
+}
+
+/// Creates the character or block device node `path`, of type `ty`,
+/// referring to the device `rdev`.
+///
+/// Opening it opens the device registered with [`register_device`] under
+/// the same number.
+pub fn mknod(path: &str, ty: FileType, rdev: DeviceId) -> AxResult {
+    crate::root::mknod(None, path, ty, rdev)
+}
+
+/// Rename a file or directory to a new name, replacing `new` if it already
+/// exists.
 ///
 /// This only works then the new path is in the same mounted fs.
 pub fn rename(old: &str, new: &str) -> io::Result<()> {
@@ -105,3 +178,213 @@ pub fn path_exists(path: &str) -> bool {
 pub fn lookup(path: &str) -> AxResult<VfsNodeRef> {
     crate::root::lookup(None, path)
 }
+
+/// Resolves `path` relative to the directory `dir`, an absolute path, or
+/// to the current directory if `dir` is `None`.
+///
+/// Symbolic links are followed, except in the last component with
+/// [`LookupFlags::NOFOLLOW`]. Fails with `ENOTDIR` if a component used as a
+/// directory is not one, `EACCES` if a directory cannot be searched, and
+/// `ELOOP` after [`MAX_SYMLINKS`] links.
+pub fn resolve_path(dir: Option<&str>, path: &str, flags: LookupFlags) -> AxResult<ResolvedPath> {
+    crate::root::resolve_path(dir, path, flags)
+}
+
+/// Reads the target of the symbolic link at `path`, resolved as in
+/// [`resolve_path`]. Fails with `EINVAL` if it is not a symbolic link.
+pub fn read_link(dir: Option<&str>, path: &str) -> AxResult<String> {
+    crate::root::read_link(dir, path)
+}
+
+/// Changes the permissions of the file at `path`, following symbolic links.
+pub fn set_permissions(path: &str, perm: Permissions) -> AxResult {
+    let resolved = resolve_path(None, path, LookupFlags::empty())?;
+    resolved_node(&resolved)?.set_perm(perm)?;
+    crate::inotify::notify(&resolved.path, attrib_mask(&resolved));
+    Ok(())
+}
+
+/// Changes the owner and group of the file at `path`, resolved with `flags`
+/// as in [`resolve_path`]. `None` leaves the ID unchanged.
+pub fn set_owner(path: &str, uid: Option<u32>, gid: Option<u32>, flags: LookupFlags) -> AxResult {
+    let resolved = resolve_path(None, path, flags)?;
+    resolved_node(&resolved)?.set_owner(uid, gid)?;
+    crate::inotify::notify(&resolved.path, attrib_mask(&resolved));
+    Ok(())
+}
+
+/// Changes the access and modification times of the file at `path`,
+/// resolved with `flags` as in [`resolve_path`]. `None` leaves the time
+/// unchanged.
+pub fn set_times(
+    path: &str,
+    atime: Option<Duration>,
+    mtime: Option<Duration>,
+    flags: LookupFlags,
+) -> AxResult {
+    let resolved = resolve_path(None, path, flags)?;
+    resolved_node(&resolved)?.set_times(atime, mtime)?;
+    crate::inotify::notify(&resolved.path, attrib_mask(&resolved));
+    Ok(())
+}
+
+fn resolved_node(resolved: &ResolvedPath) -> AxResult<&VfsNodeRef> {
+    resolved.node.as_ref().ok_or(axerrno::AxError::NotFound)
+}
+
+/// The inotify event of changing the attributes of the resolved file.
+fn attrib_mask(resolved: &ResolvedPath) -> InotifyMask {
+    match resolved.file_type() {
+        Some(ty) if ty.is_dir() => InotifyMask::ATTRIB | InotifyMask::ISDIR,
+        _ => InotifyMask::ATTRIB,
+    }
+}
+
+/// Mounts a filesystem of type `fs_type` at the directory `target`.
+///
+/// `ext4` and `vfat` are opened on the block device (or image file) `source`,
+/// and detected from it if `fs_type` is empty or `"auto"`. `tmpfs`, `proc`,
+/// `sysfs` and `devtmpfs` ignore `source`.
+///
+/// `data` holds the comma-separated mount options, e.g.
+/// `"ro,noatime,errors=remount-ro,sync"` for ext4.
+pub fn mount(source: &str, target: &str, fs_type: &str, data: &str) -> AxResult {
+    let (fs, fs_type) = crate::mounts::new_fs(fs_type, || lookup_source(source), data)?;
+    crate::root::mount(target, fs, source, &fs_type, data)
+}
+
+/// Mounts at the directory `target` the filesystem served by a user-space
+/// daemon through the FUSE connection `conn`.
+///
+/// `fs_type` is `"fuse"` or `"fuse.<subtype>"`, and `data` holds the mount
+/// options of the daemon, e.g. `"fd=3,rootmode=40000,user_id=0,group_id=0"`.
+/// The connection is aborted if the mount fails.
+#[cfg(feature = "fuse")]
+pub fn mount_fuse(
+    conn: &alloc::sync::Arc<FuseConnection>,
+    source: &str,
+    target: &str,
+    fs_type: &str,
+    data: &str,
+) -> AxResult {
+    if fs_type != "fuse" && !fs_type.starts_with("fuse.") {
+        return axerrno::ax_err!(InvalidInput);
+    }
+    let fs = crate::mounts::fuse(conn.clone(), data)?;
+    crate::root::mount(target, fs, source, fs_type, data).map_err(|e| {
+        conn.abort();
+        e
+    })
+}
+
+/// Makes the directory `source` also visible at `target`, like
+/// `mount --bind`. Mounts below `source` are not carried over.
+pub fn bind_mount(source: &str, target: &str) -> AxResult {
+    crate::root::bind_mount(source, target)
+}
+
+/// Returns the mount table, in the order the filesystems were mounted.
+pub fn mounts() -> Vec<MountInfo> {
+    crate::root::mount_table()
+}
+
+/// Formats the mount table as in `/proc/mounts`.
+pub fn proc_mounts() -> String {
+    crate::root::proc_mounts()
+}
+
+/// Formats the mount table as in `/proc/self/mountinfo`.
+pub fn proc_mountinfo() -> String {
+    crate::root::proc_mountinfo()
+}
+
+/// Returns the names of all block devices and partitions found at boot, e.g.
+/// `vda`, `vda1`, `vdb`.
+pub fn block_devices() -> Vec<String> {
+    crate::dev::BlockDevNode::all()
+        .iter()
+        .map(|dev| dev.name().into())
+        .collect()
+}
+
+/// Looks up a block device or partition found at boot, or a loop device, by
+/// its name, e.g. `vdb`, `vda1` or `loop0`.
+pub fn block_device(name: &str) -> Option<VfsNodeRef> {
+    crate::dev::BlockDevNode::find(name)
+        .map(|dev| dev as VfsNodeRef)
+        .or_else(|| crate::loopdev::find(name).map(|dev| dev as VfsNodeRef))
+}
+
+/// Publishes the character or block device `node` at `path` relative to
+/// `/dev`, e.g. `console` or `input/event0`, creating the missing
+/// directories. It is also found by its device number, read from its
+/// attributes, when a device node with that number is opened.
+#[cfg(feature = "devfs")]
+pub fn register_device(path: &str, node: VfsNodeRef) -> AxResult {
+    crate::devices::register(path, node)
+}
+
+/// Removes the device published at `path` relative to `/dev`.
+#[cfg(feature = "devfs")]
+pub fn unregister_device(path: &str) -> AxResult {
+    crate::devices::unregister(path)
+}
+
+/// Finds the device published with the device number `rdev`.
+#[cfg(feature = "devfs")]
+pub fn find_device(rdev: DeviceId) -> Option<VfsNodeRef> {
+    crate::devices::find(rdev)
+}
+
+/// Resolves the source of a mount: `/dev/<name>` refers to a block device
+/// found at boot or a loop device, anything else to a node in the filesystem.
+fn lookup_source(source: &str) -> AxResult<VfsNodeRef> {
+    source
+        .strip_prefix("/dev/")
+        .and_then(block_device)
+        .map_or_else(|| crate::root::lookup(None, source), Ok)
+}
+
+/// Returns the number of the first loop device not bound to a file
+/// (`LOOP_CTL_GET_FREE`).
+pub fn loop_get_free() -> AxResult<usize> {
+    crate::loopdev::get_free()
+}
+
+/// Unbinds loop device `number` from its file (`LOOP_CLR_FD`).
+///
+/// A device with a filesystem mounted on it is unbound after the filesystem
+/// is unmounted.
+pub fn loop_detach(number: usize) -> AxResult {
+    crate::loopdev::detach(number)
+}
+
+/// Returns the status of loop device `number` (`LOOP_GET_STATUS64`).
+pub fn loop_status(number: usize) -> AxResult<LoopStatus> {
+    crate::loopdev::status(number)
+}
+
+/// Changes the offset and size limit in the backing file, and the autoclear
+/// flag, of loop device `number` (`LOOP_SET_STATUS64`).
+pub fn loop_set_status(number: usize, offset: u64, size_limit: u64, autoclear: bool) -> AxResult {
+    crate::loopdev::set_status(number, offset, size_limit, autoclear)
+}
+
+/// Changes the mount options of the filesystem mounted at `target`, e.g.
+/// switching between `"ro"` and `"rw"`.
+pub fn remount(target: &str, data: &str) -> AxResult {
+    crate::root::remount(target, data)
+}
+
+/// Detaches the filesystem mounted at `target`.
+///
+/// Fails with `EBUSY` if the filesystem is in use, unless `flags` contains
+/// [`UmountFlags::DETACH`] or [`UmountFlags::FORCE`].
+pub fn umount(target: &str, flags: UmountFlags) -> AxResult {
+    crate::root::umount(target, flags)
+}
+
+/// Writes back the cached data of all mounted filesystems.
+pub fn sync() -> AxResult {
+    crate::root::sync()
+}
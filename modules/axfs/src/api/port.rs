@@ -173,6 +173,8 @@ pub enum FileIOType {
     Link,
     /// Socket
     Socket,
+    /// 终端
+    Tty,
     /// 其他
     Other,
 }
@@ -310,7 +312,8 @@ pub trait FileIO: AsAny + Send + Sync {
         false
     }
 
-    fn ioctl(&self, _request: usize, _arg1: usize) -> AxResult<()> {
+    /// 设备相关的控制操作，返回值作为 ioctl 的返回值
+    fn ioctl(&self, _request: usize, _arg1: usize) -> AxResult<isize> {
         Err(AxError::Unsupported)
     }
 }
@@ -332,6 +335,17 @@ impl<T: Any> AsAny for T {
         self
     }
 }
+bitflags! {
+    /// umount2 的参数
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct UmountFlags: u32 {
+        /// 即使文件系统正在使用也立即卸载
+        const FORCE = 1;
+        /// 从目录树中摘下，在不再使用后再卸载
+        const DETACH = 2;
+    }
+}
+
 bitflags! {
     /// 指定文件打开时的权限
     #[derive(Clone, Copy)]
@@ -365,9 +379,36 @@ impl From<usize> for AccessMode {
 
 /// IOCTL系统调用支持
 pub const TCGETS: usize = 0x5401;
+pub const TCSETS: usize = 0x5402;
+pub const TCSETSW: usize = 0x5403;
+pub const TCSETSF: usize = 0x5404;
+pub const TCSBRK: usize = 0x5409;
+pub const TCXONC: usize = 0x540A;
+pub const TCFLSH: usize = 0x540B;
+pub const TIOCSCTTY: usize = 0x540E;
 pub const TIOCGPGRP: usize = 0x540F;
 pub const TIOCSPGRP: usize = 0x5410;
+pub const TIOCOUTQ: usize = 0x5411;
 pub const TIOCGWINSZ: usize = 0x5413;
+pub const TIOCSWINSZ: usize = 0x5414;
+pub const FIONREAD: usize = 0x541B;
+pub const TIOCNOTTY: usize = 0x5422;
+pub const TIOCGSID: usize = 0x5429;
+pub const TIOCGPTN: usize = 0x8004_5430;
+pub const TIOCSPTLCK: usize = 0x4004_5431;
+pub const TIOCGPTLCK: usize = 0x8004_5439;
+pub const FS_IOC_GETFLAGS: usize = 0x8008_6601;
+pub const FS_IOC_SETFLAGS: usize = 0x4008_6602;
+pub const FS_IOC_FIEMAP: usize = 0xC020_660B;
+pub const RTC_RD_TIME: usize = 0x8024_7009;
+pub const LOOP_SET_FD: usize = 0x4C00;
+pub const LOOP_CLR_FD: usize = 0x4C01;
+pub const LOOP_SET_STATUS64: usize = 0x4C04;
+pub const LOOP_GET_STATUS64: usize = 0x4C05;
+pub const LOOP_CONFIGURE: usize = 0x4C0A;
+pub const LOOP_CTL_ADD: usize = 0x4C80;
+pub const LOOP_CTL_REMOVE: usize = 0x4C81;
+pub const LOOP_CTL_GET_FREE: usize = 0x4C82;
 #[repr(C)]
 #[derive(Clone, Copy, Default)]
 pub struct ConsoleWinSize {
@@ -376,3 +417,89 @@ pub struct ConsoleWinSize {
     pub ws_xpixel: u16,
     pub ws_ypixel: u16,
 }
+
+/// FS_IOC_FIEMAP 的请求头，之后紧跟 `fm_extent_count` 个 [`FiemapExtent`]
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Fiemap {
+    /// 查询范围的起始偏移
+    pub fm_start: u64,
+    /// 查询范围的长度
+    pub fm_length: u64,
+    /// 请求标志，返回时为内核支持的标志
+    pub fm_flags: u32,
+    /// 找到的 extent 数量
+    pub fm_mapped_extents: u32,
+    /// 用户提供的 extent 数组大小，为 0 时只统计数量
+    pub fm_extent_count: u32,
+    pub fm_reserved: u32,
+}
+
+/// FS_IOC_FIEMAP 返回的一个 extent
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct FiemapExtent {
+    /// 在文件中的偏移
+    pub fe_logical: u64,
+    /// 在设备上的偏移
+    pub fe_physical: u64,
+    /// 长度
+    pub fe_length: u64,
+    pub fe_reserved64: [u64; 2],
+    /// FIEMAP_EXTENT_* 标志
+    pub fe_flags: u32,
+    pub fe_reserved: [u32; 3],
+}
+
+/// 在查询前先把文件数据同步到磁盘
+pub const FIEMAP_FLAG_SYNC: u32 = 0x0000_0001;
+
+/// LOOP_GET_STATUS64/LOOP_SET_STATUS64 使用的 loop 设备信息
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct LoopInfo64 {
+    pub lo_device: u64,
+    pub lo_inode: u64,
+    pub lo_rdevice: u64,
+    /// 设备在后备文件中的起始偏移
+    pub lo_offset: u64,
+    /// 设备的最大长度，为 0 时到文件末尾
+    pub lo_sizelimit: u64,
+    /// 设备编号，即 loopN 中的 N
+    pub lo_number: u32,
+    pub lo_encrypt_type: u32,
+    pub lo_encrypt_key_size: u32,
+    /// LO_FLAGS_* 标志
+    pub lo_flags: u32,
+    /// 后备文件的路径
+    pub lo_file_name: [u8; LO_NAME_SIZE],
+    pub lo_crypt_name: [u8; LO_NAME_SIZE],
+    pub lo_encrypt_key: [u8; LO_KEY_SIZE],
+    pub lo_init: [u64; 2],
+}
+
+impl Default for LoopInfo64 {
+    fn default() -> Self {
+        // 数组长度超过 32，无法 derive
+        unsafe { core::mem::zeroed() }
+    }
+}
+
+/// LOOP_CONFIGURE 的参数，相当于 LOOP_SET_FD 加上 LOOP_SET_STATUS64
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct LoopConfig {
+    /// 后备文件的文件描述符
+    pub fd: u32,
+    /// 逻辑块大小，为 0 时使用默认值
+    pub block_size: u32,
+    pub info: LoopInfo64,
+    pub reserved: [u64; 8],
+}
+
+pub const LO_NAME_SIZE: usize = 64;
+pub const LO_KEY_SIZE: usize = 32;
+/// 设备只读，后备文件以只读方式打开时设置
+pub const LO_FLAGS_READ_ONLY: u32 = 1;
+/// 设备不再被使用时自动解除绑定
+pub const LO_FLAGS_AUTOCLEAR: u32 = 4;
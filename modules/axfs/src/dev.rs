@@ -1,12 +1,37 @@
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec};
 use axdriver::prelude::*;
+use axfs_vfs::{DeviceId, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axsync::Mutex;
 
 const BLOCK_SIZE: usize = 512;
+/// The most blocks transferred by one device request (64 KiB).
+const MAX_REQUEST_BLOCKS: usize = 128;
+/// Buffered writes are kept in chunks of this size.
+const CHUNK_SIZE: u64 = 4096;
+/// Buffered writes are written back once this many chunks (1 MiB) are dirty.
+const MAX_DIRTY_CHUNKS: usize = 256;
+/// The major number of the virtio block devices. Each disk has 16 minor
+/// numbers, the first for the whole disk and the others for partitions 1 to
+/// 15.
+const VIRTBLK_MAJOR: u32 = 254;
+/// The major number of the partitions numbered 16 and above, whose minor
+/// numbers are allocated in order.
+const BLOCK_EXT_MAJOR: u32 = 259;
+
+/// All block devices found at boot, in the order they were probed.
+static BLOCK_DEVICES: Mutex<Vec<Arc<BlockDevNode>>> = Mutex::new(Vec::new());
 
 /// A disk device with a cursor.
+///
+/// [`Disk::read_one`] and [`Disk::write_one`] access the device directly.
+/// [`Disk::write_at`] buffers the writes instead, and [`Disk::write_back`]
+/// merges the adjacent ones into large requests.
 pub struct Disk {
     block_id: u64,
     offset: usize,
     dev: AxBlockDevice,
+    /// Buffered writes not yet written back, by chunk index.
+    dirty: BTreeMap<u64, Box<[u8]>>,
 }
 
 impl Disk {
@@ -17,6 +42,7 @@ impl Disk {
             block_id: 0,
             offset: 0,
             dev,
+            dirty: BTreeMap::new(),
         }
     }
     #[allow(dead_code)]
@@ -41,15 +67,18 @@ impl Disk {
         self.offset = pos as usize % BLOCK_SIZE;
     }
 
-    /// Read within one block, returns the number of bytes read.
+    /// Read within one block, or as many whole blocks as one request allows
+    /// if the cursor is at the start of a block, returns the number of bytes
+    /// read.
     pub fn read_one(&mut self, buf: &mut [u8]) -> DevResult<usize> {
         // info!("block id: {}", self.block_id);
         let read_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
-            // whole block
-            self.dev
-                .read_block(self.block_id, &mut buf[0..BLOCK_SIZE])?;
-            self.block_id += 1;
-            BLOCK_SIZE
+            // whole blocks
+            let blocks = (buf.len() / BLOCK_SIZE).min(MAX_REQUEST_BLOCKS);
+            let len = blocks * BLOCK_SIZE;
+            self.dev.read_block(self.block_id, &mut buf[..len])?;
+            self.block_id += blocks as u64;
+            len
         } else {
             // partial block
             let mut data = [0u8; BLOCK_SIZE];
@@ -72,13 +101,17 @@ impl Disk {
         Ok(read_size)
     }
 
-    /// Write within one block, returns the number of bytes written.
+    /// Write within one block, or as many whole blocks as one request allows
+    /// if the cursor is at the start of a block, returns the number of bytes
+    /// written.
     pub fn write_one(&mut self, buf: &[u8]) -> DevResult<usize> {
         let write_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
-            // whole block
-            self.dev.write_block(self.block_id, &buf[0..BLOCK_SIZE])?;
-            self.block_id += 1;
-            BLOCK_SIZE
+            // whole blocks
+            let blocks = (buf.len() / BLOCK_SIZE).min(MAX_REQUEST_BLOCKS);
+            let len = blocks * BLOCK_SIZE;
+            self.dev.write_block(self.block_id, &buf[..len])?;
+            self.block_id += blocks as u64;
+            len
         } else {
             // partial block
             let mut data = [0u8; BLOCK_SIZE];
@@ -98,4 +131,287 @@ impl Disk {
         };
         Ok(write_size)
     }
+
+    /// Reads the disk at `pos`, including the buffered writes, returns the
+    /// number of bytes read.
+    pub fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> DevResult<usize> {
+        let len = buf.len().min(self.size().saturating_sub(pos) as usize);
+        let mut done = 0;
+        while done < len {
+            let cur = pos + done as u64;
+            let chunk = cur / CHUNK_SIZE;
+            if let Some(data) = self.dirty.get(&chunk) {
+                let start = (cur % CHUNK_SIZE) as usize;
+                let n = (data.len() - start).min(len - done);
+                buf[done..done + n].copy_from_slice(&data[start..start + n]);
+                done += n;
+                continue;
+            }
+            // read from the device up to the next buffered chunk
+            let end = match self.dirty.range(chunk..).next() {
+                Some((&next, _)) => (next * CHUNK_SIZE).min(pos + len as u64),
+                None => pos + len as u64,
+            };
+            let stop = done + (end - cur) as usize;
+            self.set_position(cur);
+            while done < stop {
+                done += self.read_one(&mut buf[done..stop])?;
+            }
+        }
+        Ok(len)
+    }
+
+    /// Writes the disk at `pos` into the write buffer, returns the number of
+    /// bytes written. The buffer is written back when it grows too large.
+    pub fn write_at(&mut self, pos: u64, buf: &[u8]) -> DevResult<usize> {
+        let size = self.size();
+        let len = buf.len().min(size.saturating_sub(pos) as usize);
+        let mut done = 0;
+        while done < len {
+            let cur = pos + done as u64;
+            let chunk = cur / CHUNK_SIZE;
+            let chunk_start = chunk * CHUNK_SIZE;
+            let chunk_len = (size - chunk_start).min(CHUNK_SIZE) as usize;
+            let start = (cur - chunk_start) as usize;
+            let n = (chunk_len - start).min(len - done);
+            if !self.dirty.contains_key(&chunk) {
+                let mut data = alloc::vec![0; chunk_len].into_boxed_slice();
+                if n < chunk_len {
+                    // keep the rest of a partially written chunk
+                    self.read_at(chunk_start, &mut data)?;
+                }
+                self.dirty.insert(chunk, data);
+            }
+            let data = self.dirty.get_mut(&chunk).unwrap();
+            data[start..start + n].copy_from_slice(&buf[done..done + n]);
+            done += n;
+        }
+        if self.dirty.len() >= MAX_DIRTY_CHUNKS {
+            self.write_back()?;
+        }
+        Ok(len)
+    }
+
+    /// Writes the buffered writes back to the device, merging adjacent chunks
+    /// into requests of up to 64 KiB.
+    pub fn write_back(&mut self) -> DevResult {
+        let max_len = MAX_REQUEST_BLOCKS * BLOCK_SIZE;
+        let mut dirty = core::mem::take(&mut self.dirty).into_iter().peekable();
+        let mut run = Vec::new();
+        while let Some((chunk, data)) = dirty.next() {
+            let run_start = chunk * CHUNK_SIZE;
+            run.clear();
+            run.extend_from_slice(&data);
+            let mut chunks = alloc::vec![(chunk, data)];
+            while let Some((next, _)) = dirty.peek() {
+                if next * CHUNK_SIZE != run_start + run.len() as u64 || run.len() >= max_len {
+                    break;
+                }
+                let (next, data) = dirty.next().unwrap();
+                run.extend_from_slice(&data);
+                chunks.push((next, data));
+            }
+            if let Err(e) = self.write_run(run_start, &run) {
+                // keep the chunks not written back for the next attempt
+                self.dirty.extend(chunks);
+                self.dirty.extend(dirty);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn write_run(&mut self, pos: u64, data: &[u8]) -> DevResult {
+        self.set_position(pos);
+        let mut written = 0;
+        while written < data.len() {
+            written += self.write_one(&data[written..])?;
+        }
+        Ok(())
+    }
+
+    /// Whether there are buffered writes not yet written back.
+    pub fn has_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Write back the buffered writes, then flush the data cached by the
+    /// device to the storage.
+    pub fn flush(&mut self) -> DevResult {
+        self.write_back()?;
+        self.dev.flush()
+    }
+}
+
+/// Returns the name of the `idx`-th block device, i.e. `vda`, `vdb`, ...
+pub fn block_device_name(idx: usize) -> String {
+    let mut name = String::from("vd");
+    if idx >= 26 {
+        name.push((b'a' + (idx / 26 - 1) as u8) as char);
+    }
+    name.push((b'a' + (idx % 26) as u8) as char);
+    name
+}
+
+/// A disk or a partition on it registered under a name like `vda` or `vda1`,
+/// so that filesystems can be mounted on it at boot or at runtime.
+pub struct BlockDevNode {
+    name: String,
+    disk: Arc<Mutex<Disk>>,
+    /// The offset of the first byte on the disk, non-zero for a partition.
+    start: u64,
+    size: u64,
+    /// The partition number, `None` for the whole disk.
+    partition: Option<usize>,
+    rdev: DeviceId,
+}
+
+impl BlockDevNode {
+    /// Registers `disk` under `name`, and each partition on it under `name`
+    /// followed by the partition number. Returns the registered devices,
+    /// the whole disk first.
+    pub fn register(name: String, disk: Disk) -> Vec<Arc<Self>> {
+        let size = disk.size();
+        let disk = Arc::new(Mutex::new(disk));
+        let first_minor = disks().len() as u32 * 16;
+        let whole = Arc::new(Self {
+            name,
+            disk: disk.clone(),
+            start: 0,
+            size,
+            partition: None,
+            rdev: DeviceId::new(VIRTBLK_MAJOR, first_minor),
+        });
+        let parts = crate::partition::read_partitions(size, |offset, buf| {
+            whole.read_at(offset, buf).map_or(false, |n| n == buf.len())
+        });
+        let mut devices = alloc::vec![whole.clone()];
+        let mut ext_minor = BLOCK_DEVICES
+            .lock()
+            .iter()
+            .filter(|dev| dev.rdev.major() == BLOCK_EXT_MAJOR)
+            .count() as u32;
+        for part in parts {
+            let name = alloc::format!("{}{}", whole.name, part.number);
+            info!("  partition {}: {:#x} bytes at {:#x}", name, part.size, part.start);
+            let rdev = if part.number < 16 {
+                DeviceId::new(VIRTBLK_MAJOR, first_minor + part.number as u32)
+            } else {
+                ext_minor += 1;
+                DeviceId::new(BLOCK_EXT_MAJOR, ext_minor - 1)
+            };
+            devices.push(Arc::new(Self {
+                name,
+                disk: disk.clone(),
+                start: part.start,
+                size: part.size,
+                partition: Some(part.number),
+                rdev,
+            }));
+        }
+        BLOCK_DEVICES.lock().extend(devices.iter().cloned());
+        devices
+    }
+
+    /// The name of the device, e.g. `vda` or `vda1`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The offset of the device on its disk, in bytes.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// The size of the device, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The partition number, `None` for a whole disk.
+    pub fn partition(&self) -> Option<usize> {
+        self.partition
+    }
+
+    /// Whether `self` and `other` are on the same disk.
+    pub fn same_disk(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.disk, &other.disk)
+    }
+
+    /// Looks up a registered block device by name.
+    pub fn find(name: &str) -> Option<Arc<Self>> {
+        BLOCK_DEVICES.lock().iter().find(|dev| dev.name == name).cloned()
+    }
+
+    /// Returns all registered block devices.
+    pub fn all() -> Vec<Arc<Self>> {
+        BLOCK_DEVICES.lock().clone()
+    }
+}
+
+/// Returns all disks, once each however many partitions they have.
+fn disks() -> Vec<Arc<Mutex<Disk>>> {
+    let mut disks: Vec<Arc<Mutex<Disk>>> = Vec::new();
+    for dev in BLOCK_DEVICES.lock().iter() {
+        if !disks.iter().any(|disk| Arc::ptr_eq(disk, &dev.disk)) {
+            disks.push(dev.disk.clone());
+        }
+    }
+    disks
+}
+
+/// Writes back the buffered writes of all disks, without flushing the
+/// devices.
+#[cfg(feature = "writeback")]
+pub(crate) fn write_back_all() -> DevResult {
+    for disk in disks() {
+        let mut disk = disk.lock();
+        if disk.has_dirty() {
+            disk.write_back()?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes back the buffered writes of all disks and flushes them (`sync`).
+pub(crate) fn sync_all() -> DevResult {
+    for disk in disks() {
+        disk.lock().flush()?;
+    }
+    Ok(())
+}
+
+impl VfsNodeOps for BlockDevNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o660),
+            VfsNodeType::BlockDevice,
+            self.size,
+            self.size / BLOCK_SIZE as u64,
+        );
+        attr.set_rdev(self.rdev);
+        Ok(attr)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let len = buf.len().min(self.size.saturating_sub(offset) as usize);
+        self.disk
+            .lock()
+            .read_at(self.start + offset, &mut buf[..len])
+            .map_err(|_| VfsError::Io)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let len = buf.len().min(self.size.saturating_sub(offset) as usize);
+        self.disk
+            .lock()
+            .write_at(self.start + offset, &buf[..len])
+            .map_err(|_| VfsError::Io)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        self.disk.lock().flush().map_err(|_| VfsError::Io)
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
 }
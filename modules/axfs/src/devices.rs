@@ -0,0 +1,103 @@
+//! The device registry behind devtmpfs.
+//!
+//! There is one device tree, shared by all mounts of devtmpfs. The memory
+//! devices, the RTC, the loop devices and the block devices found at boot
+//! are published here. Other modules publish their devices with
+//! [`register`], e.g. `console` or `fb0`.
+//!
+//! Each character or block device is also indexed by its device number, so
+//! that a device node created with `mknod` on another filesystem opens the
+//! same device as its devtmpfs counterpart.
+
+use alloc::sync::Arc;
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::{DeviceId, VfsNodeAttr, VfsNodeRef};
+use axsync::Mutex;
+
+use crate::dev::BlockDevNode;
+use crate::fs::devfs::{DeviceFileSystem, FullDev, NullDev, RandomDev, RtcDev, ZeroDev};
+use crate::loopdev::{LoopControl, LoopNode, LOOP_DEVICE_NAMES};
+
+/// The device tree, created on first use.
+static DEVFS: Mutex<Option<Arc<DeviceFileSystem>>> = Mutex::new(None);
+
+/// Returns the device tree, creating it with the built-in devices on first
+/// use.
+pub(crate) fn devfs() -> Arc<DeviceFileSystem> {
+    let mut devfs = DEVFS.lock();
+    devfs
+        .get_or_insert_with(|| {
+            let devfs = Arc::new(DeviceFileSystem::new());
+            if let Err(e) = add_builtin(&devfs) {
+                warn!("failed to create devtmpfs: {:?}", e);
+            }
+            devfs
+        })
+        .clone()
+}
+
+/// The devices that exist on every system.
+fn add_builtin(devfs: &DeviceFileSystem) -> AxResult {
+    devfs.register("null", Arc::new(NullDev))?;
+    devfs.register("zero", Arc::new(ZeroDev))?;
+    devfs.register("full", Arc::new(FullDev))?;
+    devfs.register("random", Arc::new(RandomDev::new(DeviceId::new(1, 8))))?;
+    devfs.register("urandom", Arc::new(RandomDev::new(DeviceId::new(1, 9))))?;
+    let rtc = Arc::new(RtcDev::new(axhal::time::current_time));
+    devfs.register("rtc0", rtc.clone())?;
+    // the old name of the RTC, still opened by busybox `hwclock`
+    devfs.mkdir("misc").add("rtc", rtc);
+    devfs.register("loop-control", Arc::new(LoopControl))?;
+    #[cfg(feature = "fuse")]
+    devfs.register("fuse", Arc::new(crate::fuse::FuseDevNode))?;
+    for (number, name) in LOOP_DEVICE_NAMES.iter().enumerate() {
+        devfs.register(name, Arc::new(LoopNode(number)))?;
+    }
+    // a device in a subdirectory, looked up by the filesystem tests
+    devfs.mkdir("foo").add("bar", Arc::new(ZeroDev));
+    // the mount point of the POSIX shared memory tmpfs
+    devfs.mkdir("shm");
+    // the mount point of devpts
+    devfs.mkdir("pts");
+    Ok(())
+}
+
+/// Publishes the block devices and partitions found at boot.
+pub(crate) fn add_block_devices() -> AxResult {
+    let devfs = devfs();
+    for dev in BlockDevNode::all() {
+        devfs.register(dev.name(), dev.clone())?;
+    }
+    Ok(())
+}
+
+/// Publishes `node` at `path` relative to `/dev`, see
+/// [`DeviceFileSystem::register`].
+pub(crate) fn register(path: &str, node: VfsNodeRef) -> AxResult {
+    devfs().register(path, node)
+}
+
+/// Removes the node at `path` relative to `/dev`, see
+/// [`DeviceFileSystem::unregister`].
+pub(crate) fn unregister(path: &str) -> AxResult {
+    devfs().unregister(path)
+}
+
+/// Finds the registered device with the device number `rdev`.
+pub(crate) fn find(rdev: DeviceId) -> Option<VfsNodeRef> {
+    devfs().find(rdev)
+}
+
+/// Replaces a device node being opened by the device it refers to. Fails
+/// with `ENODEV` if no device of the same type is registered with its
+/// number.
+pub(crate) fn resolve(node: VfsNodeRef, attr: &VfsNodeAttr) -> AxResult<VfsNodeRef> {
+    let ty = attr.file_type();
+    if !(ty.is_char_device() || ty.is_block_device()) || attr.rdev() == DeviceId::default() {
+        return Ok(node);
+    }
+    match find(attr.rdev()) {
+        Some(dev) if dev.get_attr()?.file_type() == ty => Ok(dev),
+        _ => ax_err!(NoSuchDevice),
+    }
+}
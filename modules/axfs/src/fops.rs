@@ -1,10 +1,17 @@
 //! Low-level filesystem operations.
 
 use axerrno::{ax_err, ax_err_type, AxResult};
+use alloc::{sync::Arc, vec::Vec};
 use axfs_vfs::{VfsError, VfsNodeRef};
 use axio::SeekFrom;
 use capability::{Cap, WithCap};
-use core::fmt;
+use core::{fmt, time::Duration};
+
+use crate::inotify::{FileEvents, InotifyMask};
+use crate::locks::{self, LockOwner, LockType, RecordLock};
+use crate::namei::ResolvedPath;
+use crate::page_cache::{PageCache, MAX_READ_AHEAD, PAGE_SIZE};
+use crate::perm::{self, Access};
 
 #[cfg(feature = "myfs")]
 pub use crate::dev::Disk;
@@ -19,20 +26,67 @@ pub type DirEntry = axfs_vfs::VfsDirEntry;
 pub type FileAttr = axfs_vfs::VfsNodeAttr;
 /// Alias of [`axfs_vfs::VfsNodePerm`].
 pub type FilePerm = axfs_vfs::VfsNodePerm;
+/// Alias of [`axfs_vfs::VfsNodeFlags`].
+pub type FileFlags = axfs_vfs::VfsNodeFlags;
+/// Alias of [`axfs_vfs::VfsExtent`].
+pub type FileExtent = axfs_vfs::VfsExtent;
 
 /// An opened file object, with open permissions and a cursor.
 #[derive(Clone)]
 pub struct File {
     node: WithCap<VfsNodeRef>,
+    /// The page cache of regular files, shared by all opened instances.
+    cache: Option<Arc<PageCache>>,
+    read_ahead: ReadAhead,
     is_append: bool,
     offset: u64,
+    /// The inotify events of the file, `None` if it was opened relative to a
+    /// directory and cannot be named.
+    events: Option<Arc<FileEvents>>,
+}
+
+/// Detection of sequential reads through the cursor of an opened file.
+#[derive(Clone, Default)]
+struct ReadAhead {
+    /// Where the last read ended.
+    prev_end: u64,
+    /// The read-ahead window in pages, zero after a random read.
+    window: u64,
+}
+
+impl ReadAhead {
+    /// The smallest read-ahead window in pages, after the first sequential
+    /// read.
+    const MIN_WINDOW: u64 = 4;
+
+    /// Updates the window for a read of `len` bytes at `offset`. Returns the
+    /// first page and the number of pages to load ahead, covering the read
+    /// and the window after it, or `None` for a random read.
+    ///
+    /// The window doubles on every sequential read, up to
+    /// [`MAX_READ_AHEAD`] pages.
+    fn next(&mut self, offset: u64, len: usize) -> Option<(u64, u64)> {
+        self.window = if offset == self.prev_end {
+            (self.window * 2).clamp(Self::MIN_WINDOW, MAX_READ_AHEAD)
+        } else {
+            0
+        };
+        self.prev_end = offset + len as u64;
+        if self.window == 0 || len == 0 {
+            return None;
+        }
+        let first = offset / PAGE_SIZE;
+        let last = self.prev_end.div_ceil(PAGE_SIZE) + self.window;
+        Some((first, last - first))
+    }
 }
 
 /// An opened directory object, with open permissions and a cursor for
 /// [`read_dir`](Directory::read_dir).
 pub struct Directory {
     node: WithCap<VfsNodeRef>,
-    entry_idx: usize,
+    pos: u64,
+    events: Option<Arc<FileEvents>>,
 }
 
 /// Options and flags which can be used to configure how a file is opened.
@@ -120,6 +174,7 @@ impl File {
         }
 
         let node_option = crate::root::lookup(dir, path);
+        let mut created = false;
         let node = if opts.create || opts.create_new {
             match node_option {
                 Ok(node) => {
@@ -130,13 +185,48 @@ impl File {
                     node
                 }
                 // not exists, create new
-                Err(VfsError::NotFound) => crate::root::create_file(dir, path)?,
+                Err(VfsError::NotFound) => {
+                    created = true;
+                    crate::root::create_file(dir, path)?
+                }
                 Err(e) => return Err(e),
             }
         } else {
             // just open the existing
             node_option?
         };
+        Self::open_node(dir, path, node, created, opts)
+    }
+
+    /// Opens the file reached by
+    /// [`resolve_path`](crate::api::resolve_path), without resolving its
+    /// path again. A missing file is created at the resolved path if `opts`
+    /// allow it.
+    pub fn open_resolved(resolved: &ResolvedPath, opts: &OpenOptions) -> AxResult<Self> {
+        debug!("open resolved file: {} {:?}", resolved.path, opts);
+        if !opts.is_valid() {
+            return ax_err!(InvalidInput);
+        }
+        let (node, created) = match &resolved.node {
+            Some(_) if opts.create_new => return ax_err!(AlreadyExists),
+            Some(node) => (node.clone(), false),
+            None if opts.create || opts.create_new => {
+                (crate::root::create_file(None, &resolved.path)?, true)
+            }
+            None => return ax_err!(NotFound),
+        };
+        Self::open_node(None, &resolved.path, node, created, opts)
+    }
+
+    /// Opens `node`, found at `path` relative to `dir`, after checking the
+    /// permissions unless it was just `created`.
+    fn open_node(
+        dir: Option<&VfsNodeRef>,
+        path: &str,
+        node: VfsNodeRef,
+        created: bool,
+        opts: &OpenOptions,
+    ) -> AxResult<Self> {
         let attr = node.get_attr()?;
         if attr.is_dir()
             && (opts.create || opts.create_new || opts.write || opts.append || opts.truncate)
@@ -144,17 +234,40 @@ impl File {
             return ax_err!(IsADirectory);
         }
         let access_cap = opts.into();
-        if !perm_to_cap(attr.perm()).contains(access_cap) {
-            return ax_err!(PermissionDenied);
+        // the creator may open the new file whatever its mode
+        if !created {
+            perm::check(&node, cap_to_access(access_cap))?;
+        }
+        if opts.write || opts.append || opts.truncate {
+            let flags = node.get_flags()?;
+            if flags.contains(FileFlags::IMMUTABLE)
+                || (flags.contains(FileFlags::APPEND) && (!opts.append || opts.truncate))
+            {
+                return ax_err!(OperationNotPermitted);
+            }
         }
+        // a device node opens the registered device with the same number
+        #[cfg(feature = "devfs")]
+        let node = crate::devices::resolve(node, &attr)?;
         node.open()?;
+        let cache = PageCache::get(&node);
         if opts.truncate {
-            node.truncate(0)?;
+            match &cache {
+                Some(cache) => cache.truncate(0)?,
+                None => node.truncate(0)?,
+            }
+        }
+        let events = opened_events(dir, path, false, access_cap.contains(Cap::WRITE));
+        if opts.truncate {
+            raise(&events, InotifyMask::MODIFY);
         }
         Ok(Self {
             node: WithCap::new(node, access_cap),
+            cache,
+            read_ahead: ReadAhead::default(),
             is_append: opts.append,
             offset: 0,
+            events,
         })
     }
 
@@ -166,7 +279,30 @@ impl File {
 
     /// Truncates the file to the specified size.
     pub fn truncate(&self, size: u64) -> AxResult {
-        self.node.access(Cap::WRITE)?.truncate(size)?;
+        let node = self.node.access(Cap::WRITE)?;
+        if node
+            .get_flags()?
+            .intersects(FileFlags::IMMUTABLE | FileFlags::APPEND)
+        {
+            return ax_err!(OperationNotPermitted);
+        }
+        match &self.cache {
+            Some(cache) => cache.truncate(size)?,
+            None => node.truncate(size)?,
+        }
+        raise(&self.events, InotifyMask::MODIFY);
+        Ok(())
+    }
+
+    /// Checks the node flags before writing at `offset`: immutable files
+    /// cannot be written, append-only files can only be written at the end.
+    fn check_write_flags(&self, node: &VfsNodeRef, offset: u64) -> AxResult {
+        let flags = node.get_flags()?;
+        if flags.contains(FileFlags::IMMUTABLE)
+            || (flags.contains(FileFlags::APPEND) && offset != self.get_attr()?.size())
+        {
+            return ax_err!(OperationNotPermitted);
+        }
         Ok(())
     }
 
@@ -174,9 +310,18 @@ impl File {
     /// read.
     ///
     /// After the read, the cursor will be advanced by the number of bytes read.
+    ///
+    /// Sequential reads of a regular file load the following pages ahead of
+    /// time, in a window that grows with every sequential read.
     pub fn read(&mut self, buf: &mut [u8]) -> AxResult<usize> {
-        let node = self.node.access(Cap::READ)?;
-        let read_len = node.read_at(self.offset, buf)?;
+        self.node.access(Cap::READ)?;
+        if let Some(cache) = &self.cache {
+            if let Some((first, count)) = self.read_ahead.next(self.offset, buf.len()) {
+                // the pages not loaded here are read by `read_at`
+                cache.read_ahead(first, count).ok();
+            }
+        }
+        let read_len = self.read_at(self.offset, buf)?;
         self.offset += read_len as u64;
         Ok(read_len)
     }
@@ -186,7 +331,13 @@ impl File {
     /// It does not update the file cursor.
     pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
         let node = self.node.access(Cap::READ)?;
-        let read_len = node.read_at(offset, buf)?;
+        let read_len = match &self.cache {
+            Some(cache) => cache.read_at(offset, buf)?,
+            None => node.read_at(offset, buf)?,
+        };
+        if read_len > 0 {
+            raise(&self.events, InotifyMask::ACCESS);
+        }
         Ok(read_len)
     }
 
@@ -200,7 +351,8 @@ impl File {
         if self.is_append {
             self.offset = self.get_attr()?.size();
         };
-        let write_len = node.write_at(self.offset, buf)?;
+        self.check_write_flags(node, self.offset)?;
+        let write_len = self.write_node(node, self.offset, buf)?;
         self.offset += write_len as u64;
         Ok(write_len)
     }
@@ -211,14 +363,30 @@ impl File {
     /// It does not update the file cursor.
     pub fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
         let node = self.node.access(Cap::WRITE)?;
-        let write_len = node.write_at(offset, buf)?;
+        self.check_write_flags(node, offset)?;
+        self.write_node(node, offset, buf)
+    }
+
+    fn write_node(&self, node: &VfsNodeRef, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        let write_len = match &self.cache {
+            Some(cache) => cache.write_at(offset, buf)?,
+            None => node.write_at(offset, buf)?,
+        };
+        if write_len > 0 {
+            raise(&self.events, InotifyMask::MODIFY);
+        }
         Ok(write_len)
     }
 
     /// Flushes the file, writes all buffered data to the underlying device.
+    ///
+    /// Like `fsync(2)`, this is allowed on files opened read-only.
     pub fn flush(&self) -> AxResult {
-        self.node.access(Cap::WRITE)?.fsync()?;
-        Ok(())
+        let node = self.node.access(Cap::empty())?;
+        match &self.cache {
+            Some(cache) => cache.sync(),
+            None => node.fsync(),
+        }
     }
 
     /// Sets the cursor of the file to the specified offset. Returns the new
@@ -240,6 +408,90 @@ impl File {
         self.node.access(Cap::empty())?.get_attr()
     }
 
+    /// Gets the file flags (`FS_IOC_GETFLAGS`).
+    pub fn get_flags(&self) -> AxResult<FileFlags> {
+        self.node.access(Cap::empty())?.get_flags()
+    }
+
+    /// Sets the file flags (`FS_IOC_SETFLAGS`).
+    pub fn set_flags(&self, flags: FileFlags) -> AxResult {
+        self.node.access(Cap::empty())?.set_flags(flags)?;
+        raise(&self.events, InotifyMask::ATTRIB);
+        Ok(())
+    }
+
+    /// Changes the permission of the file (`fchmod`).
+    pub fn set_perm(&self, perm: FilePerm) -> AxResult {
+        self.node.access(Cap::empty())?.set_perm(perm)?;
+        raise(&self.events, InotifyMask::ATTRIB);
+        Ok(())
+    }
+
+    /// Changes the owner and group of the file (`fchown`). `None` leaves the
+    /// ID unchanged.
+    pub fn set_owner(&self, uid: Option<u32>, gid: Option<u32>) -> AxResult {
+        self.node.access(Cap::empty())?.set_owner(uid, gid)?;
+        raise(&self.events, InotifyMask::ATTRIB);
+        Ok(())
+    }
+
+    /// Changes the access and modification times of the file (`futimens`).
+    /// `None` leaves the time unchanged.
+    pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> AxResult {
+        self.node.access(Cap::empty())?.set_times(atime, mtime)?;
+        raise(&self.events, InotifyMask::ATTRIB);
+        Ok(())
+    }
+
+    /// Gets the extents of the file that overlap `[start, start + len)`
+    /// (`FS_IOC_FIEMAP`).
+    pub fn fiemap(&self, start: u64, len: u64) -> AxResult<Vec<FileExtent>> {
+        self.node.access(Cap::empty())?.fiemap(start, len)
+    }
+
+    /// Returns the first record lock on the file conflicting with `lock`
+    /// (`F_GETLK`), `None` if `lock` can be placed.
+    pub fn test_lock(&self, lock: &RecordLock) -> AxResult<Option<RecordLock>> {
+        let node = self.node.access(Cap::empty())?;
+        locks::test_record_lock(node, lock)
+    }
+
+    /// Places the record lock `lock` on the file, or removes the locks of
+    /// its owner in its range if it is [`LockType::Unlock`] (`F_SETLK`).
+    ///
+    /// Fails with `EAGAIN` if another owner holds a conflicting lock. The
+    /// caller may retry until the lock is placed (`F_SETLKW`) if `wait`,
+    /// which fails with `EDEADLK` if waiting would deadlock.
+    pub fn set_lock(&self, lock: &RecordLock, wait: bool) -> AxResult {
+        let node = self.node.access(Cap::empty())?;
+        locks::set_record_lock(node, lock, wait)
+    }
+
+    /// Places the whole-file lock of type `ty` owned by `owner` on the file,
+    /// or removes it if `ty` is [`LockType::Unlock`] (`flock`). Fails with
+    /// `EAGAIN` if another owner holds a conflicting lock.
+    pub fn flock(&self, owner: LockOwner, ty: LockType) -> AxResult {
+        let node = self.node.access(Cap::empty())?;
+        locks::set_flock(node, owner, ty)
+    }
+
+    /// Removes all the locks of `owner` on the file.
+    pub fn release_locks(&self, owner: LockOwner) {
+        if let Ok(node) = self.node.access(Cap::empty()) {
+            locks::release_file_locks(node, owner);
+        }
+    }
+
+    /// Gets the page cache of the file, `None` if it is not a regular file.
+    pub fn page_cache(&self) -> Option<&Arc<PageCache>> {
+        self.cache.as_ref()
+    }
+
+    /// Gets the node of the file.
+    pub(crate) fn node(&self) -> AxResult<&VfsNodeRef> {
+        Ok(self.node.access(Cap::empty())?)
+    }
+
     #[allow(unused)]
     pub fn readable(&self) -> bool {
         self.node.can_access(Cap::READ)
@@ -272,14 +524,13 @@ impl Directory {
             return ax_err!(NotADirectory);
         }
         let access_cap = opts.into();
-        if !perm_to_cap(attr.perm()).contains(access_cap) {
-            return ax_err!(PermissionDenied);
-        }
+        perm::check(&node, cap_to_access(access_cap))?;
 
         node.open()?;
         Ok(Self {
             node: WithCap::new(node, access_cap),
-            entry_idx: 0,
+            pos: 0,
+            events: opened_events(dir, path, true, false),
         })
     }
 
@@ -332,17 +583,30 @@ impl Directory {
     /// Reads directory entries starts from the current position into the
     /// given buffer. Returns the number of entries read.
     ///
-    /// After the read, the cursor will be advanced by the number of entries
-    /// read.
+    /// After the read, the cursor will be moved past the last entry read.
     pub fn read_dir(&mut self, dirents: &mut [DirEntry]) -> AxResult<usize> {
         let n = self
             .node
             .access(Cap::READ)?
-            .read_dir(self.entry_idx, dirents)?;
-        self.entry_idx += n;
+            .read_dir_at(self.pos, dirents)?;
+        if n > 0 {
+            self.pos = dirents[n - 1].next_offset();
+        }
         Ok(n)
     }
 
+    /// Returns the opaque position of the cursor, as used by `telldir`.
+    pub fn tell(&self) -> u64 {
+        self.pos
+    }
+
+    /// Moves the cursor to a position previously returned by
+    /// [`tell`](Directory::tell) or [`DirEntry::next_offset`], as used by
+    /// `seekdir`. Seeking to 0 rewinds the directory.
+    pub fn seek(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
     /// Rename a file or directory to a new name.
     /// Delete the original file if `old` already exists.
     ///
@@ -402,16 +666,38 @@ impl From<&OpenOptions> for Cap {
     }
 }
 
-fn perm_to_cap(perm: FilePerm) -> Cap {
-    let mut cap = Cap::empty();
-    if perm.owner_readable() {
-        cap |= Cap::READ;
+/// Raises [`InotifyMask::OPEN`] for the file or directory opened at `path`,
+/// and returns its events. A path relative to an opened directory cannot be
+/// named, and has no events.
+fn opened_events(
+    dir: Option<&VfsNodeRef>,
+    path: &str,
+    is_dir: bool,
+    writable: bool,
+) -> Option<Arc<FileEvents>> {
+    if dir.is_some() && !path.starts_with('/') {
+        return None;
+    }
+    FileEvents::opened(path, is_dir, writable)
+}
+
+/// Raises `mask` for an opened file, if it has events.
+fn raise(events: &Option<Arc<FileEvents>>, mask: InotifyMask) {
+    if let Some(events) = events {
+        events.raise(mask);
+    }
+}
+
+fn cap_to_access(cap: Cap) -> Access {
+    let mut access = Access::empty();
+    if cap.contains(Cap::READ) {
+        access |= Access::READ;
     }
-    if perm.owner_writable() {
-        cap |= Cap::WRITE;
+    if cap.contains(Cap::WRITE) {
+        access |= Access::WRITE;
     }
-    if perm.owner_executable() {
-        cap |= Cap::EXECUTE;
+    if cap.contains(Cap::EXECUTE) {
+        access |= Access::EXEC;
     }
-    cap
+    access
 }
@@ -0,0 +1,122 @@
+//! The devpts filesystem, holding the slave sides of the pseudo-terminals.
+//!
+//! The slaves are created when `/dev/ptmx` is opened and removed when their
+//! master is closed, by the terminal module, which lists them with the
+//! function registered with [`register_pts_entries`]. Every mount shows the
+//! same slaves.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axfs_vfs::impl_vfs_dir_default;
+use axfs_vfs::{DeviceId, VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef};
+use axfs_vfs::{VfsError, VfsNodeType, VfsOps, VfsResult};
+use axsync::Mutex;
+
+/// The device number of `/dev/ptmx`.
+pub const PTMX_DEVICE: DeviceId = DeviceId::new(5, 2);
+
+/// Lists the slave sides of the pseudo-terminals, by index.
+pub type PtsEntries = fn() -> Vec<(u32, VfsNodeRef)>;
+
+/// The function listing the slaves, registered by the terminal module.
+static PTS_ENTRIES: Mutex<Option<PtsEntries>> = Mutex::new(None);
+
+/// Registers the function listing the slaves shown in devpts. A later
+/// registration replaces the earlier one.
+pub fn register_pts_entries(f: PtsEntries) {
+    *PTS_ENTRIES.lock() = Some(f);
+}
+
+/// The slaves, and `ptmx` if the multiplexer is registered as a device.
+fn entries() -> Vec<(String, VfsNodeRef)> {
+    let mut entries = Vec::new();
+    if let Some(ptmx) = crate::devices::find(PTMX_DEVICE) {
+        entries.push((String::from("ptmx"), ptmx));
+    }
+    // copied out, the listing takes the locks of the terminal module
+    let pts_entries = *PTS_ENTRIES.lock();
+    if let Some(f) = pts_entries {
+        entries.extend(
+            f().into_iter()
+                .map(|(index, node)| (format!("{}", index), node)),
+        );
+    }
+    entries
+}
+
+/// The root directory of devpts.
+struct PtsDir;
+
+impl VfsNodeOps for PtsDir {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let perm = VfsNodePerm::from_bits_truncate(0o755);
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::Dir, 0, 0))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let path = path.trim_matches('/');
+        match path {
+            "" | "." => Ok(self as VfsNodeRef),
+            _ if path.contains('/') => Err(VfsError::NotADirectory),
+            _ => entries()
+                .into_iter()
+                .find(|(name, _)| name == path)
+                .map(|(_, node)| node)
+                .ok_or(VfsError::NotFound),
+        }
+    }
+
+    fn create(&self, _path: &str, _ty: VfsNodeType) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn remove(&self, _path: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let entries = entries();
+        let dots = [".", ".."].map(|name| (name, VfsNodeType::Dir));
+        let all = dots.into_iter().chain(
+            entries
+                .iter()
+                .map(|(name, _)| (name.as_str(), VfsNodeType::CharDevice)),
+        );
+        let mut n = 0;
+        for ((name, ty), ent) in all.skip(start_idx).zip(dirents.iter_mut()) {
+            *ent = VfsDirEntry::new(name, ty);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    impl_vfs_dir_default! {}
+}
+
+/// The devpts filesystem, mounted on `/dev/pts`.
+pub struct DevPtsFileSystem {
+    root: Arc<PtsDir>,
+}
+
+impl DevPtsFileSystem {
+    /// Creates a devpts showing the registered slaves.
+    pub fn new() -> Self {
+        Self {
+            root: Arc::new(PtsDir),
+        }
+    }
+}
+
+impl Default for DevPtsFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VfsOps for DevPtsFileSystem {
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
@@ -1,11 +1,14 @@
-use crate::dev::Disk;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
-use core::cell::{RefCell, UnsafeCell};
+use core::cell::UnsafeCell;
 use core::num;
 use core::ptr::NonNull;
 
-use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
+use alloc::vec::Vec;
+use axfs_vfs::{DeviceId, VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
+use axfs_vfs::{VfsExtent, VfsExtentFlags, VfsNodeFlags};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
+use axerrno::ax_err;
 use axsync::Mutex;
 
 use ext4fs::{BlockDevice, Ext4Fs, BLOCK_SIZE, *};
@@ -17,76 +20,138 @@ unsafe impl Sync for Ext4DirWrapper {}
 unsafe impl Send for Ext4FileWrapper {}
 unsafe impl Sync for Ext4FileWrapper {}
 
-pub struct DiskAdapter {
-    inner: RefCell<Disk>,
+/// 以文件或块设备节点作为 ext4 的块设备，用于挂载 `/dev/vdb`、镜像文件等
+pub struct NodeBlockDevice {
+    node: VfsNodeRef,
 }
 
-impl BlockDevice for DiskAdapter {
+impl NodeBlockDevice {
+    pub fn new(node: VfsNodeRef) -> Self {
+        Self { node }
+    }
+}
+
+impl BlockDevice for NodeBlockDevice {
     fn block_num(&self) -> usize {
-        self.inner.borrow_mut().size() as usize / BLOCK_SIZE as usize
+        self.node.get_attr().map_or(0, |attr| attr.size() / BLOCK_SIZE) as usize
     }
     fn block_size(&self) -> usize {
         BLOCK_SIZE as usize
     }
     fn read_block(&self, offset: usize, buf: &mut [u8]) {
-        assert_eq!(buf.len(), self.block_size());
-
-        let disk_block_id = offset / 512 as usize;
-        let disk_offset = offset % 512 as usize;
-
-        let mut disk = self.inner.borrow_mut();
-
-        disk.set_position(disk_block_id as u64 * 512 as u64 + disk_offset as u64);
-
         let mut filled = 0;
-
         while filled < buf.len() {
-            let mut tmp = [0u8; 512];
-
-            disk.read_one(&mut tmp).unwrap();
-
-            let count = buf.len().min(filled + 512 as usize) - filled;
-
-            buf[filled..filled + count].copy_from_slice(&tmp[..count]);
-
-            filled += count;
+            match self.node.read_at((offset + filled) as u64, &mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) => {
+                    log::error!("ext4 read at {:#x} failed: {:?}", offset + filled, e);
+                    break;
+                }
+            }
         }
+        // 超出设备末尾的部分按 0 处理
+        buf[filled..].fill(0);
     }
     fn write_block(&self, block_id: usize, buf: &[u8]) {
         assert!(buf.len() == (BLOCK_SIZE as usize));
-        let mut inner = self.inner.borrow_mut();
-        let true_block_size = inner.true_block_size();
-        let num_block = (BLOCK_SIZE as usize) / true_block_size;
-
-        for i in 0..num_block {
-            let pos = block_id * (BLOCK_SIZE as usize) + i * true_block_size;
-            inner.set_position(pos as _);
-            let res = inner.write_one(&buf[i * true_block_size..(i + 1) * true_block_size]);
-            assert_eq!(res.unwrap(), true_block_size);
+        let offset = (block_id * BLOCK_SIZE as usize) as u64;
+        let mut written = 0;
+        while written < buf.len() {
+            match self.node.write_at(offset + written as u64, &buf[written..]) {
+                Ok(0) => break,
+                Ok(n) => written += n,
+                Err(e) => {
+                    log::error!("ext4 write block {} failed: {:?}", block_id, e);
+                    break;
+                }
+            }
+        }
+    }
+    fn flush(&self) {
+        if let Err(e) = self.node.fsync() {
+            log::error!("ext4 flush device failed: {:?}", e);
         }
     }
 }
 
+/// 超级块中时间戳使用的时钟
+fn ext4_clock() -> u32 {
+    axhal::time::current_time().as_secs() as u32
+}
+
 pub struct Ext4FileSystem {
     inner: Arc<ext4fs::Ext4Fs>,
     root_dir: UnsafeCell<Option<VfsNodeRef>>,
+    options: Mutex<Ext4MountOptions>,
+    /// 仍然存活的文件节点，按 inode 记录，不为空时不能直接卸载
+    open_inodes: Mutex<BTreeMap<u32, Ext4OpenInode>>,
+}
+
+/// 同一个 inode 的所有文件节点
+#[derive(Default)]
+struct Ext4OpenInode {
+    /// 存活的文件节点个数
+    count: usize,
+    /// 最后一个链接已被删除，最后一个文件节点释放时释放 inode
+    unlinked: bool,
 }
 
 impl Ext4FileSystem {
-    pub fn new(disk: Disk) -> Self {
-        log::info!("-----------------ext4fs init-----------------");
+    /// 在任意块设备上打开 ext4 文件系统，`data` 为 mount(2) 传入的挂载选项
+    pub fn with_options(block_device: Arc<dyn BlockDevice>, data: &str) -> VfsResult<Self> {
+        let mut inner = ext4fs::Ext4Fs::open(block_device);
+        if inner.super_block.magic != EXT4_SUPER_MAGIC {
+            return ax_err!(InvalidData, "not an ext4 filesystem");
+        }
+        // 驱动只支持 4K 的块
+        if 1024u64 << inner.super_block.log_block_size != BLOCK_SIZE {
+            return ax_err!(Unsupported, "unsupported ext4 block size");
+        }
+        let options = Ext4MountOptions::parse(data, inner.super_block.errors).map_err(|e| {
+            log::warn!("invalid ext4 mount options {:?}: {:?}", data, e);
+            VfsError::InvalidInput
+        })?;
+        log::info!("ext4 mounted with {:?}", options);
+        inner.set_clock(ext4_clock);
+        inner.ext4_set_errors_behavior(options.errors);
+        inner.ext4_mount(options.read_only);
+        Ok(Self {
+            inner: Arc::new(inner),
+            root_dir: UnsafeCell::new(None),
+            options: Mutex::new(options),
+            open_inodes: Mutex::new(BTreeMap::new()),
+        })
+    }
 
-        let block_device = Arc::new(DiskAdapter {
-            inner: RefCell::new(disk),
-        });
+    /// 当前的挂载选项，`read_only` 包括因错误而变为只读的情况
+    pub fn options(&self) -> Ext4MountOptions {
+        let mut options = *self.options.lock();
+        options.read_only = self.inner.ext4_is_read_only();
+        options
+    }
 
-        let inner = Arc::new(ext4fs::Ext4Fs::open(block_device));
-        Self {
-            inner,
-            root_dir: UnsafeCell::new(None),
+    /// 按挂载选项更新读取 `inode` 后的访问时间
+    fn touch_atime(&self, inode: u32) {
+        let atime = self.options.lock().atime;
+        self.inner.ext4_touch_atime(inode as u64, atime);
+    }
+
+    /// 以 `sync` 挂载时，修改完成后让块设备落盘
+    fn sync_if_needed(&self) {
+        if self.options.lock().sync {
+            self.inner.ext4_sync();
         }
     }
 
+    /// 只读挂载时拒绝修改文件系统
+    fn check_writable(&self) -> VfsResult {
+        if self.inner.ext4_is_read_only() {
+            return ax_err!(ReadOnlyFilesystem);
+        }
+        Ok(())
+    }
+
     pub fn init(&self) {
         let root_inode = self.inner.root_inode();
         let fs_ptr: NonNull<Ext4FileSystem> =
@@ -97,6 +162,15 @@ impl Ext4FileSystem {
     fn new_dir(inode: ext4fs::Ext4Inode, fs_ptr: NonNull<Ext4FileSystem>) -> Arc<Ext4DirWrapper> {
         Arc::new(Ext4DirWrapper(inode, fs_ptr))
     }
+
+    /// 释放已删除最后一个链接的 `inode`，仍有文件节点时推迟到最后一个节点释放时
+    fn release_inode(&self, inode: u32) {
+        if let Some(open) = self.open_inodes.lock().get_mut(&inode) {
+            open.unlinked = true;
+            return;
+        }
+        self.inner.ext4_evict_inode(inode);
+    }
 }
 
 impl VfsOps for Ext4FileSystem {
@@ -106,7 +180,39 @@ impl VfsOps for Ext4FileSystem {
     }
 
     fn umount(&self) -> VfsResult {
-        todo!()
+        // 所有修改都直接写入块设备，只需把超级块标记为已正常卸载并让设备落盘
+        self.inner.ext4_umount();
+        Ok(())
+    }
+
+    fn sync(&self) -> VfsResult {
+        self.inner.ext4_sync();
+        Ok(())
+    }
+
+    fn is_busy(&self) -> bool {
+        // 根目录节点由文件系统持有一份，其余的引用来自打开的目录或进行中的操作
+        let root_dir = unsafe { (*self.root_dir.get()).as_ref() };
+        !self.open_inodes.lock().is_empty() || root_dir.is_some_and(|d| Arc::strong_count(d) > 1)
+    }
+
+    fn remount(&self, data: &str) -> VfsResult {
+        let mut options = self.options.lock();
+        options.read_only = self.inner.ext4_is_read_only();
+        let new_options = options.remount(data).map_err(|e| {
+            log::warn!("invalid ext4 remount options {:?}: {:?}", data, e);
+            VfsError::InvalidInput
+        })?;
+        if new_options.read_only != options.read_only {
+            log::info!(
+                "ext4 remounted {}",
+                if new_options.read_only { "read-only" } else { "read-write" }
+            );
+        }
+        self.inner.ext4_set_errors_behavior(new_options.errors);
+        self.inner.ext4_remount(new_options.read_only);
+        *options = new_options;
+        Ok(())
     }
 }
 
@@ -119,21 +225,111 @@ impl VfsNodeOps for Ext4DirWrapper {
 
         unsafe {
             let fs = self.1.as_ref();
+            // ext4_generic_open 找不到路径时不会报错，先检查路径是否存在
+            fs.inner.ext4_path_lookup(path).map_err(ext4_link_error)?;
             fs.inner.ext4_generic_open(&mut ext4_file, path);
             fs.inner.ext4_file_inode_read(&mut ext4_file);
             fs.inner.ext4_find_all_disk_blocks(&mut ext4_file);
         }
 
-        let fs_ptr: NonNull<Ext4FileSystem> = self.1;
-
-        let file_wrapepr = Arc::new(Ext4FileWrapper(Mutex::new(ext4_file), fs_ptr));
+        let file_wrapepr = Arc::new(Ext4FileWrapper::new(ext4_file, self.1));
 
         Ok(file_wrapepr)
     }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let (ty, perm) = map_imode(self.0.mode);
+        let mut attr = VfsNodeAttr::new(perm, ty, self.0.size as _, self.0.blocks as _);
+        attr.set_nlink(unsafe { ext4_nlink(self.1.as_ref(), EXT4_ROOT_INO) });
+        Ok(attr)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        unsafe { ext4_read_dir(self.1.as_ref(), EXT4_ROOT_INO, start_idx, dirents) }
+    }
+
+    fn read_dir_at(&self, pos: u64, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        unsafe { ext4_read_dir_at(self.1.as_ref(), EXT4_ROOT_INO, pos, dirents) }
+    }
+
+    fn get_flags(&self) -> VfsResult<VfsNodeFlags> {
+        let fs = unsafe { self.1.as_ref() };
+        let flags = fs.inner.ext4_get_flags(EXT4_ROOT_INO as u64);
+        Ok(VfsNodeFlags::from_bits_retain(flags))
+    }
+
+    fn set_flags(&self, flags: VfsNodeFlags) -> VfsResult {
+        let fs = unsafe { self.1.as_ref() };
+        fs.check_writable()?;
+        fs.inner.ext4_set_flags(EXT4_ROOT_INO as u64, flags.bits());
+        fs.sync_if_needed();
+        Ok(())
+    }
+
+    fn create(&self, _path: &str, _ty: VfsNodeType) -> VfsResult {
+        unsafe { self.1.as_ref() }.check_writable()?;
+        ax_err!(Unsupported)
+    }
+
+    fn link(&self, path: &str, node: &VfsNodeRef) -> VfsResult {
+        unsafe { ext4_link_at(self.1.as_ref(), path, node) }
+    }
+
+    fn mknod(&self, path: &str, ty: VfsNodeType, rdev: DeviceId) -> VfsResult {
+        unsafe { ext4_mknod_at(self.1.as_ref(), path, ty, rdev) }
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        unsafe { ext4_unlink_at(self.1.as_ref(), path) }
+    }
+
+    fn rename(&self, _src_path: &str, _dst_path: &str) -> VfsResult {
+        unsafe { self.1.as_ref() }.check_writable()?;
+        ax_err!(Unsupported)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        unsafe { self.1.as_ref() }.inner.ext4_fsync(EXT4_ROOT_INO as u64);
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
 }
 
 pub struct Ext4FileWrapper(Mutex<ext4fs::Ext4File>, NonNull<Ext4FileSystem>);
 
+impl Ext4FileWrapper {
+    fn new(ext4_file: ext4fs::Ext4File, fs_ptr: NonNull<Ext4FileSystem>) -> Self {
+        let fs = unsafe { fs_ptr.as_ref() };
+        fs.open_inodes.lock().entry(ext4_file.inode).or_default().count += 1;
+        Self(Mutex::new(ext4_file), fs_ptr)
+    }
+}
+
+impl Drop for Ext4FileWrapper {
+    fn drop(&mut self) {
+        let fs = unsafe { self.1.as_ref() };
+        let inode = self.0.get_mut().inode;
+        let mut open_inodes = fs.open_inodes.lock();
+        let Some(open) = open_inodes.get_mut(&inode) else {
+            return;
+        };
+        open.count -= 1;
+        if open.count > 0 {
+            return;
+        }
+        let unlinked = open_inodes.remove(&inode).is_some_and(|open| open.unlinked);
+        drop(open_inodes);
+        // 只读时留在孤儿链表中，下次读写挂载时释放
+        if unlinked && !fs.inner.ext4_is_read_only() {
+            fs.inner.ext4_evict_inode(inode);
+            fs.sync_if_needed();
+        }
+    }
+}
+
 impl VfsNodeOps for Ext4FileWrapper {
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
         log::info!("-------Ext4FileWrapper-----lookup path {:?}", path);
@@ -142,13 +338,12 @@ impl VfsNodeOps for Ext4FileWrapper {
 
         unsafe {
             let fs = self.1.as_ref();
+            fs.inner.ext4_path_lookup(path).map_err(ext4_link_error)?;
             fs.inner.ext4_generic_open(&mut ext4_file, path);
             fs.inner.ext4_file_inode_read(&mut ext4_file);
         }
 
-        let fs_ptr: NonNull<Ext4FileSystem> = self.1;
-
-        let file_wrapepr = Arc::new(Ext4FileWrapper(Mutex::new(ext4_file), fs_ptr));
+        let file_wrapepr = Arc::new(Ext4FileWrapper::new(ext4_file, self.1));
 
         Ok(file_wrapepr)
     }
@@ -156,6 +351,7 @@ impl VfsNodeOps for Ext4FileWrapper {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
         let ext4_file = self.0.lock();
 
+        let inode = ext4_file.inode;
         let inode_mode = ext4_file.inode_mode;
         let flags = ext4_file.flags;
         let size = ext4_file.fsize;
@@ -163,60 +359,271 @@ impl VfsNodeOps for Ext4FileWrapper {
         let (ty, perm) = map_imode(inode_mode as u16);
 
         drop(ext4_file);
-        Ok(VfsNodeAttr::new(perm, ty, size as _, blocks as _))
+        let fs = unsafe { self.1.as_ref() };
+        let mut attr = VfsNodeAttr::new(perm, ty, size as _, blocks as _);
+        attr.set_nlink(ext4_nlink(fs, inode));
+        if ty.is_char_device() || ty.is_block_device() {
+            let (major, minor) = fs.inner.ext4_get_rdev(inode as u64);
+            attr.set_rdev(DeviceId::new(major, minor));
+        }
+        Ok(attr)
     }
 
     fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let inode = self.0.lock().inode;
+        unsafe { ext4_read_dir(self.1.as_ref(), inode, start_idx, dirents) }
+    }
+
+    fn read_dir_at(&self, pos: u64, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let inode = self.0.lock().inode;
+        unsafe { ext4_read_dir_at(self.1.as_ref(), inode, pos, dirents) }
+    }
+
+    fn get_flags(&self) -> VfsResult<VfsNodeFlags> {
+        let inode = self.0.lock().inode;
+        let fs = unsafe { self.1.as_ref() };
+        let flags = fs.inner.ext4_get_flags(inode as u64);
+        Ok(VfsNodeFlags::from_bits_retain(flags))
+    }
+
+    fn set_flags(&self, flags: VfsNodeFlags) -> VfsResult {
+        let mut ext4_file = self.0.lock();
+        let fs = unsafe { self.1.as_ref() };
+        fs.check_writable()?;
+        fs.inner.ext4_set_flags(ext4_file.inode as u64, flags.bits());
+        fs.inner.ext4_file_inode_read(&mut ext4_file);
+        fs.sync_if_needed();
+        Ok(())
+    }
+
+    fn fiemap(&self, start: u64, len: u64) -> VfsResult<Vec<VfsExtent>> {
+        let inode = self.0.lock().inode;
+        let fs = unsafe { self.1.as_ref() };
+        let extents = fs
+            .inner
+            .ext4_fiemap(inode as u64, start, len)
+            .into_iter()
+            .map(|e| {
+                let mut flags = VfsExtentFlags::empty();
+                if e.unwritten {
+                    flags |= VfsExtentFlags::UNWRITTEN;
+                }
+                if e.last {
+                    flags |= VfsExtentFlags::LAST;
+                }
+                VfsExtent {
+                    logical: e.logical,
+                    physical: e.physical,
+                    length: e.length,
+                    flags,
+                }
+            })
+            .collect();
+        Ok(extents)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let inode = self.0.lock().inode;
+        let fs = unsafe { self.1.as_ref() };
+        let len = fs.inner.ext4_read_at(inode as u64, offset, buf);
+        fs.touch_atime(inode);
+        Ok(len)
+    }
+
+    fn cache_key(&self) -> Option<(usize, u64)> {
+        // 每次 lookup 都会创建新的 wrapper，用文件系统和 inode 号标识同一个文件
         let ext4_file = self.0.lock();
+        if !map_imode(ext4_file.inode_mode).0.is_file() {
+            return None;
+        }
+        Some((self.1.as_ptr() as usize, ext4_file.inode as u64))
+    }
 
-        let mut len = 0;
+    fn readlink(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let ext4_file = self.0.lock();
+        if !map_imode(ext4_file.inode_mode).0.is_symlink() {
+            return ax_err!(InvalidInput);
+        }
+        let target = unsafe { self.1.as_ref() }
+            .inner
+            .ext4_readlink(ext4_file.inode as u64);
+        let len = target.len().min(buf.len());
+        buf[..len].copy_from_slice(&target[..len]);
+        Ok(len)
+    }
 
-        unsafe {
-            let fs = self.1.as_ref();
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        unsafe { self.1.as_ref() }.check_writable()?;
+        ax_err!(InvalidInput)
+    }
 
-            let entries = fs
-                .inner
-                .read_dir_entry(ext4_file.inode as u64, &fs.inner.super_block);
-            len = entries.len();
+    fn truncate(&self, _size: u64) -> VfsResult {
+        unsafe { self.1.as_ref() }.check_writable()?;
+        ax_err!(InvalidInput)
+    }
 
-            let mut iter = entries.into_iter().skip(2).skip(start_idx);
-            for (i, out_entry) in dirents.iter_mut().enumerate() {
-                let x: Option<Ext4DirEntry> = iter.next();
+    fn fsync(&self) -> VfsResult {
+        let inode = self.0.lock().inode;
+        unsafe { self.1.as_ref() }.inner.ext4_fsync(inode as u64);
+        Ok(())
+    }
 
-                match x {
-                    Some(ext4direntry) => {
-                        let name = ext4direntry.name;
-                        let name_len = ext4direntry.name_len;
-                        let file_type = ext4direntry.file_type;
+    fn create(&self, _path: &str, _ty: VfsNodeType) -> VfsResult {
+        unsafe { self.1.as_ref() }.check_writable()?;
+        ax_err!(Unsupported)
+    }
 
-                        let (ty, _) = map_dir_imode(file_type as u16);
-                        let name = get_name(name, name_len as usize).unwrap();
+    fn link(&self, path: &str, node: &VfsNodeRef) -> VfsResult {
+        unsafe { ext4_link_at(self.1.as_ref(), path, node) }
+    }
 
-                        *out_entry = VfsDirEntry::new(name.as_str(), ty);
-                    }
-                    _ => return Ok(i),
-                }
-            }
-        }
+    fn mknod(&self, path: &str, ty: VfsNodeType, rdev: DeviceId) -> VfsResult {
+        unsafe { ext4_mknod_at(self.1.as_ref(), path, ty, rdev) }
+    }
 
-        drop(ext4_file);
+    fn remove(&self, path: &str) -> VfsResult {
+        unsafe { ext4_unlink_at(self.1.as_ref(), path) }
+    }
 
-        return Ok(len);
+    fn rename(&self, _src_path: &str, _dst_path: &str) -> VfsResult {
+        unsafe { self.1.as_ref() }.check_writable()?;
+        ax_err!(Unsupported)
     }
 
-    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
-        let mut ext4_file = self.0.lock();
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
 
-        unsafe {
-            let fs = self.1.as_ref();
-            if offset < ext4_file.fsize {
-                let len: usize = fs.inner.ext4_file_iter(&mut ext4_file, buf);
-                Ok(len)
-            } else {
-                return Ok(0);
-            }
-        }
+/// 根目录的 inode 号
+const EXT4_ROOT_INO: u32 = 2;
+
+/// inode 的硬链接数
+fn ext4_nlink(fs: &Ext4FileSystem, inode: u32) -> u64 {
+    let inode = fs
+        .inner
+        .ext4_read_inode(inode as u64, &fs.inner.super_block);
+    inode.links_count as u64
+}
+
+/// 在 `path`（相对于文件系统的根目录）处创建指向 `node` 的硬链接
+fn ext4_link_at(fs: &Ext4FileSystem, path: &str, node: &VfsNodeRef) -> VfsResult {
+    fs.check_writable()?;
+    let any = node.as_any();
+    if any.is::<Ext4DirWrapper>() {
+        return ax_err!(OperationNotPermitted);
     }
+    let file = match any.downcast_ref::<Ext4FileWrapper>() {
+        Some(file) if core::ptr::eq(file.1.as_ptr(), fs) => file,
+        _ => return ax_err!(CrossesDevices),
+    };
+    let inode = file.0.lock().inode;
+    let (parent, name) = ext4_split_path(path);
+    let dir = fs.inner.ext4_path_lookup(parent).map_err(ext4_link_error)?;
+    fs.inner
+        .ext4_link(dir, name, inode)
+        .map_err(ext4_link_error)?;
+    fs.sync_if_needed();
+    Ok(())
+}
+
+/// 在 `path`（相对于文件系统的根目录）处创建指向设备 `rdev` 的设备文件
+///
+/// 权限与新建的普通文件相同，由调用者再行修改。
+fn ext4_mknod_at(fs: &Ext4FileSystem, path: &str, ty: VfsNodeType, rdev: DeviceId) -> VfsResult {
+    fs.check_writable()?;
+    let file_type = match ty {
+        VfsNodeType::CharDevice => ext4fs::FileMode::S_IFCHR,
+        VfsNodeType::BlockDevice => ext4fs::FileMode::S_IFBLK,
+        _ => return ax_err!(InvalidInput),
+    };
+    let mode = file_type.bits() | VfsNodePerm::default_file().bits();
+    let (parent, name) = ext4_split_path(path);
+    let dir = fs.inner.ext4_path_lookup(parent).map_err(ext4_link_error)?;
+    fs.inner
+        .ext4_mknod(dir, name, mode, rdev.major(), rdev.minor())
+        .map_err(ext4_link_error)?;
+    fs.sync_if_needed();
+    Ok(())
+}
+
+/// 删除 `path`（相对于文件系统的根目录）处的目录项，是目录时删除空目录
+///
+/// 删除最后一个链接后释放 inode，它仍被打开时推迟到关闭后。
+fn ext4_unlink_at(fs: &Ext4FileSystem, path: &str) -> VfsResult {
+    fs.check_writable()?;
+    let (parent, name) = ext4_split_path(path);
+    let dir = fs.inner.ext4_path_lookup(parent).map_err(ext4_link_error)?;
+    let res = match fs.inner.ext4_unlink(dir, name) {
+        Err(Ext4LinkError::IsDirectory) => fs.inner.ext4_rmdir(dir, name).map(Some),
+        res => res,
+    };
+    if let Some(inode) = res.map_err(ext4_link_error)? {
+        fs.release_inode(inode);
+    }
+    fs.sync_if_needed();
+    Ok(())
+}
+
+/// 把路径拆分为父目录和最后一个分量
+fn ext4_split_path(path: &str) -> (&str, &str) {
+    let path = path.trim_matches('/');
+    path.rsplit_once('/').unwrap_or(("", path))
+}
+
+fn ext4_link_error(e: Ext4LinkError) -> VfsError {
+    match e {
+        Ext4LinkError::NotFound => VfsError::NotFound,
+        Ext4LinkError::NotADirectory => VfsError::NotADirectory,
+        Ext4LinkError::Exists => VfsError::AlreadyExists,
+        Ext4LinkError::IsDirectory => VfsError::OperationNotPermitted,
+        Ext4LinkError::InvalidName => VfsError::InvalidInput,
+        Ext4LinkError::TooManyLinks => VfsError::TooManyLinks,
+        Ext4LinkError::NoSpace => VfsError::StorageFull,
+        Ext4LinkError::NotEmpty => VfsError::DirectoryNotEmpty,
+        Ext4LinkError::NoFreeInode => VfsError::StorageFull,
+        Ext4LinkError::Unsupported => VfsError::Unsupported,
+    }
+}
+
+/// 从位置 `pos` 开始读取目录项，每一项的 `next_offset` 为下一项的位置
+fn ext4_read_dir_at(
+    fs: &Ext4FileSystem,
+    inode: u32,
+    pos: u64,
+    dirents: &mut [VfsDirEntry],
+) -> VfsResult<usize> {
+    let entries = fs.inner.ext4_dir_read_from(inode, pos, dirents.len());
+    for (entry, out_entry) in entries.iter().zip(dirents.iter_mut()) {
+        let (ty, _) = map_dir_imode(entry.file_type as u16);
+        *out_entry = VfsDirEntry::new(entry.name.as_str(), ty);
+        out_entry.set_ino(entry.inode as u64);
+        out_entry.set_next_offset(entry.next_pos());
+    }
+    fs.touch_atime(inode);
+    Ok(entries.len().min(dirents.len()))
+}
+
+/// 按下标读取目录项，下标从 "." 开始计数
+fn ext4_read_dir(
+    fs: &Ext4FileSystem,
+    inode: u32,
+    start_idx: usize,
+    dirents: &mut [VfsDirEntry],
+) -> VfsResult<usize> {
+    let entries = fs
+        .inner
+        .ext4_dir_read_from(inode, 0, start_idx + dirents.len());
+    let mut len = 0;
+    for (entry, out_entry) in entries.iter().skip(start_idx).zip(dirents.iter_mut()) {
+        let (ty, _) = map_dir_imode(entry.file_type as u16);
+        *out_entry = VfsDirEntry::new(entry.name.as_str(), ty);
+        out_entry.set_ino(entry.inode as u64);
+        out_entry.set_next_offset(entry.next_pos());
+        len += 1;
+    }
+    fs.touch_atime(inode);
+    Ok(len)
 }
 
 fn map_dir_imode(imode: u16) -> (VfsNodeType, VfsNodePerm) {
@@ -6,17 +6,27 @@ use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
 use axsync::Mutex;
 use fatfs::{Dir, File, LossyOemCpConverter, NullTimeProvider, Read, Seek, SeekFrom, Write};
 
-use crate::dev::Disk;
-
 const BLOCK_SIZE: usize = 512;
 
+/// The storage of a FAT filesystem: a block device node or an image file,
+/// with a cursor.
+pub struct FatStorage {
+    node: VfsNodeRef,
+    pos: u64,
+}
+
 pub struct FatFileSystem {
-    inner: fatfs::FileSystem<Disk, NullTimeProvider, LossyOemCpConverter>,
+    inner: fatfs::FileSystem<FatStorage, NullTimeProvider, LossyOemCpConverter>,
     root_dir: UnsafeCell<Option<VfsNodeRef>>,
+    /// Held by every node of the filesystem, to know whether it is in use.
+    token: Arc<()>,
 }
 
-pub struct FileWrapper<'a>(Mutex<File<'a, Disk, NullTimeProvider, LossyOemCpConverter>>);
-pub struct DirWrapper<'a>(Dir<'a, Disk, NullTimeProvider, LossyOemCpConverter>);
+pub struct FileWrapper<'a>(
+    Mutex<File<'a, FatStorage, NullTimeProvider, LossyOemCpConverter>>,
+    Arc<()>,
+);
+pub struct DirWrapper<'a>(Dir<'a, FatStorage, NullTimeProvider, LossyOemCpConverter>, Arc<()>);
 
 unsafe impl Sync for FatFileSystem {}
 unsafe impl Send for FatFileSystem {}
@@ -26,39 +36,49 @@ unsafe impl<'a> Send for DirWrapper<'a> {}
 unsafe impl<'a> Sync for DirWrapper<'a> {}
 
 impl FatFileSystem {
+    /// Formats `node` with a new FAT filesystem.
     #[cfg(feature = "use-ramdisk")]
-    pub fn new(mut disk: Disk) -> Self {
+    pub fn format(node: VfsNodeRef) -> VfsResult {
         let opts = fatfs::FormatVolumeOptions::new();
-        fatfs::format_volume(&mut disk, opts).expect("failed to format volume");
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
-            .expect("failed to initialize FAT filesystem");
-        Self {
-            inner,
-            root_dir: UnsafeCell::new(None),
-        }
+        fatfs::format_volume(&mut FatStorage { node, pos: 0 }, opts).map_err(as_vfs_err)
     }
 
-    #[cfg(not(feature = "use-ramdisk"))]
-    pub fn new(disk: Disk) -> Self {
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
-            .expect("failed to initialize FAT filesystem");
-        Self {
+    /// Opens the FAT filesystem stored on a block device node or an image
+    /// file.
+    pub fn from_node(node: VfsNodeRef) -> VfsResult<Arc<Self>> {
+        let storage = FatStorage { node, pos: 0 };
+        let inner =
+            fatfs::FileSystem::new(storage, fatfs::FsOptions::new()).map_err(as_vfs_err)?;
+        let fs = Arc::new(Self {
             inner,
             root_dir: UnsafeCell::new(None),
-        }
+            token: Arc::new(()),
+        });
+        // SAFETY: the nodes borrowing the filesystem hold `token`, and the
+        // mount tree keeps the `Arc` until none of them is left (`is_busy`).
+        let static_fs: &'static Self = unsafe { &*Arc::as_ptr(&fs) };
+        static_fs.init();
+        Ok(fs)
     }
 
     pub fn init(&'static self) {
         // must be called before later operations
-        unsafe { *self.root_dir.get() = Some(Self::new_dir(self.inner.root_dir())) }
+        let root_dir = Self::new_dir(self.inner.root_dir(), self.token.clone());
+        unsafe { *self.root_dir.get() = Some(root_dir) }
     }
 
-    fn new_file(file: File<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<FileWrapper> {
-        Arc::new(FileWrapper(Mutex::new(file)))
+    fn new_file(
+        file: File<'_, FatStorage, NullTimeProvider, LossyOemCpConverter>,
+        token: Arc<()>,
+    ) -> Arc<FileWrapper> {
+        Arc::new(FileWrapper(Mutex::new(file), token))
     }
 
-    fn new_dir(dir: Dir<'_, Disk, NullTimeProvider, LossyOemCpConverter>) -> Arc<DirWrapper> {
-        Arc::new(DirWrapper(dir))
+    fn new_dir(
+        dir: Dir<'_, FatStorage, NullTimeProvider, LossyOemCpConverter>,
+        token: Arc<()>,
+    ) -> Arc<DirWrapper> {
+        Arc::new(DirWrapper(dir, token))
     }
 }
 
@@ -149,7 +169,7 @@ impl VfsNodeOps for DirWrapper<'static> {
     fn parent(&self) -> Option<VfsNodeRef> {
         self.0
             .open_dir("..")
-            .map_or(None, |dir| Some(FatFileSystem::new_dir(dir)))
+            .map_or(None, |dir| Some(FatFileSystem::new_dir(dir, self.1.clone())))
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
@@ -164,9 +184,9 @@ impl VfsNodeOps for DirWrapper<'static> {
 
         // TODO: use `fatfs::Dir::find_entry`, but it's not public.
         if let Ok(file) = self.0.open_file(path) {
-            Ok(FatFileSystem::new_file(file))
+            Ok(FatFileSystem::new_file(file, self.1.clone()))
         } else if let Ok(dir) = self.0.open_dir(path) {
-            Ok(FatFileSystem::new_dir(dir))
+            Ok(FatFileSystem::new_dir(dir, self.1.clone()))
         } else {
             Err(VfsError::NotFound)
         }
@@ -244,63 +264,49 @@ impl VfsOps for FatFileSystem {
         let root_dir = unsafe { (*self.root_dir.get()).as_ref().unwrap() };
         root_dir.clone()
     }
+
+    fn is_busy(&self) -> bool {
+        // the filesystem itself and its root directory hold a token each
+        Arc::strong_count(&self.token) > 2
+    }
 }
 
-impl fatfs::IoBase for Disk {
+impl fatfs::IoBase for FatStorage {
     type Error = ();
 }
 
-impl Read for Disk {
-    fn read(&mut self, mut buf: &mut [u8]) -> Result<usize, Self::Error> {
-        let mut read_len = 0;
-        while !buf.is_empty() {
-            match self.read_one(buf) {
-                Ok(0) => break,
-                Ok(n) => {
-                    let tmp = buf;
-                    buf = &mut tmp[n..];
-                    read_len += n;
-                }
-                Err(_) => return Err(()),
-            }
-        }
-        Ok(read_len)
+impl Read for FatStorage {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let n = self.node.read_at(self.pos, buf).map_err(|_| ())?;
+        self.pos += n as u64;
+        Ok(n)
     }
 }
 
-impl Write for Disk {
-    fn write(&mut self, mut buf: &[u8]) -> Result<usize, Self::Error> {
-        let mut write_len = 0;
-        while !buf.is_empty() {
-            match self.write_one(buf) {
-                Ok(0) => break,
-                Ok(n) => {
-                    buf = &buf[n..];
-                    write_len += n;
-                }
-                Err(_) => return Err(()),
-            }
-        }
-        Ok(write_len)
+impl Write for FatStorage {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let n = self.node.write_at(self.pos, buf).map_err(|_| ())?;
+        self.pos += n as u64;
+        Ok(n)
     }
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Ok(())
+        self.node.fsync().map_err(|_| ())
     }
 }
 
-impl Seek for Disk {
+impl Seek for FatStorage {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
-        let size = self.size();
+        let size = self.node.get_attr().map_err(|_| ())?.size();
         let new_pos = match pos {
             SeekFrom::Start(pos) => Some(pos),
-            SeekFrom::Current(off) => self.position().checked_add_signed(off),
+            SeekFrom::Current(off) => self.pos.checked_add_signed(off),
             SeekFrom::End(off) => size.checked_add_signed(off),
         }
         .ok_or(())?;
         if new_pos > size {
             warn!("Seek beyond the end of the block device");
         }
-        self.set_position(new_pos);
+        self.pos = new_pos;
         Ok(new_pos)
     }
 }
@@ -0,0 +1,547 @@
+//! The filesystem of a FUSE connection, whose nodes forward their operations
+//! to the user-space daemon.
+//!
+//! Nothing is cached in the kernel: the attributes are fetched with
+//! `GETATTR` and the names looked up with `LOOKUP` every time, and file data
+//! bypasses the page cache as with the `direct_io` option of Linux, since
+//! the daemon may change the files at any time.
+//!
+//! Every lookup of a node ID returns the same node while it is alive, and
+//! the node sends `FORGET` for its lookups once dropped. The opened
+//! instances of a node share one handle of the daemon, opened read-write if
+//! the daemon allows it, else read-only.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axerrno::ax_err;
+use axfs_vfs::{DeviceId, VfsNodeType, VfsOps, VfsResult};
+use axfs_vfs::{VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef};
+use axsync::Mutex;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use crate::fuse::{opcode, FuseConnection, Message};
+use crate::fuse::{Attr, AttrOut, CreateIn, Dirent, EntryOut, GetattrIn, MkdirIn, OpenIn};
+use crate::fuse::{OpenOut, ReadIn, ReleaseIn, SetattrIn, WriteIn, WriteOut, FATTR_SIZE};
+
+/// The node ID of the root directory.
+const ROOT_ID: u64 = 1;
+
+/// The size of the replies asked for with `READDIR`.
+const READDIR_SIZE: u32 = 4096;
+
+/// The `st_mode` bits of the file type.
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+
+const O_RDONLY: u32 = 0;
+const O_RDWR: u32 = 2;
+const O_CREAT: u32 = 0o100;
+const O_EXCL: u32 = 0o200;
+
+/// The state shared by the nodes of a mounted connection.
+struct FuseMount {
+    conn: Arc<FuseConnection>,
+    /// The live nodes, by node ID.
+    nodes: Mutex<BTreeMap<u64, Weak<FuseNode>>>,
+    /// The number of opened nodes.
+    open_files: AtomicUsize,
+}
+
+/// A handle opened by the daemon, shared by the opened instances of a node.
+struct Handle {
+    fh: u64,
+    count: usize,
+}
+
+/// A file or directory of a FUSE filesystem.
+pub struct FuseNode {
+    mount: Arc<FuseMount>,
+    this: Weak<FuseNode>,
+    nodeid: u64,
+    ty: VfsNodeType,
+    /// The directory the node was last looked up in, `None` for the root.
+    parent: Mutex<Option<Arc<FuseNode>>>,
+    /// The number of lookups not forgotten yet.
+    nlookup: AtomicU64,
+    handle: Mutex<Option<Handle>>,
+}
+
+impl FuseNode {
+    fn arc(&self) -> Arc<Self> {
+        self.this.upgrade().unwrap()
+    }
+
+    fn conn(&self) -> &FuseConnection {
+        &self.mount.conn
+    }
+
+    /// Returns the node of the entry replied to a lookup, or to the creation
+    /// of a node, in this directory.
+    fn child(&self, entry: &EntryOut) -> VfsResult<Arc<Self>> {
+        // a node ID of 0 is a negative entry
+        if entry.nodeid == 0 {
+            return ax_err!(NotFound);
+        }
+        let mut nodes = self.mount.nodes.lock();
+        if let Some(node) = nodes.get(&entry.nodeid).and_then(Weak::upgrade) {
+            node.nlookup.fetch_add(1, Ordering::AcqRel);
+            let old_parent = node.parent.lock().replace(self.arc());
+            // dropping the old parent may forget it, which needs `nodes`
+            drop(nodes);
+            drop(old_parent);
+            return Ok(node);
+        }
+        let node = Arc::new_cyclic(|this| Self {
+            mount: self.mount.clone(),
+            this: this.clone(),
+            nodeid: entry.nodeid,
+            ty: node_type(entry.attr.mode),
+            parent: Mutex::new(Some(self.arc())),
+            nlookup: AtomicU64::new(1),
+            handle: Mutex::new(None),
+        });
+        nodes.insert(entry.nodeid, Arc::downgrade(&node));
+        Ok(node)
+    }
+
+    /// Looks up the entry `name` of this directory.
+    fn lookup_child(&self, name: &str) -> VfsResult<Arc<Self>> {
+        if !self.ty.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        match name {
+            "" | "." => Ok(self.arc()),
+            ".." => Ok(self.parent.lock().clone().unwrap_or_else(|| self.arc())),
+            _ => {
+                let reply = self
+                    .conn()
+                    .request(opcode::LOOKUP, self.nodeid, &[&c_name(name)])?;
+                self.child(&EntryOut::parse(&reply)?)
+            }
+        }
+    }
+
+    /// Looks up `path`, relative to this directory.
+    fn walk(&self, path: &str) -> VfsResult<Arc<Self>> {
+        let mut node = self.arc();
+        for name in path.split('/') {
+            node = node.lookup_child(name)?;
+        }
+        Ok(node)
+    }
+
+    /// Splits `path`, relative to this directory, into the directory holding
+    /// its last component and the name of the component.
+    fn split<'a>(&self, path: &'a str) -> VfsResult<(Arc<Self>, &'a str)> {
+        let path = path.trim_end_matches('/');
+        let (dir, name) = match path.rsplit_once('/') {
+            Some((dir, name)) => (self.walk(dir)?, name),
+            None => (self.arc(), path),
+        };
+        if matches!(name, "" | "." | "..") {
+            return ax_err!(InvalidInput);
+        }
+        if !dir.ty.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        Ok((dir, name))
+    }
+
+    /// Opens a handle of the daemon on the node.
+    fn open_handle(&self) -> VfsResult<u64> {
+        let open = |flags| {
+            let op = if self.ty.is_dir() {
+                opcode::OPENDIR
+            } else {
+                opcode::OPEN
+            };
+            let open_in = OpenIn {
+                flags,
+                ..Default::default()
+            };
+            let reply = self
+                .conn()
+                .request(op, self.nodeid, &[open_in.as_bytes()])?;
+            Ok(OpenOut::parse(&reply)?.fh)
+        };
+        if self.ty.is_dir() {
+            return open(O_RDONLY);
+        }
+        match open(O_RDWR) {
+            Err(VfsError::PermissionDenied | VfsError::ReadOnlyFilesystem) => open(O_RDONLY),
+            res => res,
+        }
+    }
+
+    fn release_handle(&self, fh: u64) -> VfsResult {
+        let op = if self.ty.is_dir() {
+            opcode::RELEASEDIR
+        } else {
+            opcode::RELEASE
+        };
+        let release = ReleaseIn {
+            fh,
+            ..Default::default()
+        };
+        self.conn()
+            .request(op, self.nodeid, &[release.as_bytes()])?;
+        Ok(())
+    }
+
+    /// Calls `f` with the handle of the node, opening a handle for the call
+    /// if the node is not opened.
+    fn with_handle<T>(&self, f: impl FnOnce(u64) -> VfsResult<T>) -> VfsResult<T> {
+        let fh = self.handle.lock().as_ref().map(|handle| handle.fh);
+        if let Some(fh) = fh {
+            return f(fh);
+        }
+        let fh = self.open_handle()?;
+        let res = f(fh);
+        self.release_handle(fh)?;
+        res
+    }
+
+    /// Reads the entries of the directory from the position `pos`, passing
+    /// their names, types and next positions to `f` until it returns false.
+    fn read_entries<F>(&self, pos: u64, mut f: F) -> VfsResult
+    where
+        F: FnMut(&str, VfsNodeType, u64) -> bool,
+    {
+        if !self.ty.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        self.with_handle(|fh| {
+            let mut pos = pos;
+            loop {
+                let read = ReadIn {
+                    fh,
+                    offset: pos,
+                    size: READDIR_SIZE,
+                    ..Default::default()
+                };
+                let reply =
+                    self.conn()
+                        .request(opcode::READDIR, self.nodeid, &[read.as_bytes()])?;
+                if reply.is_empty() {
+                    return Ok(());
+                }
+                let mut buf = &reply[..];
+                while !buf.is_empty() {
+                    let dirent = Dirent::parse(buf)?;
+                    let name_end = size_of::<Dirent>() + dirent.namelen as usize;
+                    let name = buf.get(size_of::<Dirent>()..name_end).ok_or(VfsError::Io)?;
+                    let name = core::str::from_utf8(name).map_err(|_| VfsError::InvalidData)?;
+                    if !f(name, node_type(dirent.ty << 12), dirent.off) {
+                        return Ok(());
+                    }
+                    pos = dirent.off;
+                    buf = buf.get(name_end.next_multiple_of(8)..).unwrap_or_default();
+                }
+            }
+        })
+    }
+}
+
+impl VfsNodeOps for FuseNode {
+    fn open(&self) -> VfsResult {
+        let mut handle = self.handle.lock();
+        match handle.as_mut() {
+            Some(handle) => handle.count += 1,
+            None => {
+                let fh = self.open_handle()?;
+                *handle = Some(Handle { fh, count: 1 });
+            }
+        }
+        self.mount.open_files.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    fn release(&self) -> VfsResult {
+        let mut handle = self.handle.lock();
+        let Some(opened) = handle.as_mut() else {
+            return Ok(());
+        };
+        self.mount.open_files.fetch_sub(1, Ordering::AcqRel);
+        opened.count -= 1;
+        if opened.count == 0 {
+            let fh = opened.fh;
+            *handle = None;
+            self.release_handle(fh)?;
+        }
+        Ok(())
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let getattr = GetattrIn::default();
+        let reply = self
+            .conn()
+            .request(opcode::GETATTR, self.nodeid, &[getattr.as_bytes()])?;
+        Ok(node_attr(&AttrOut::parse(&reply)?.attr))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let chunk_size = self.conn().max_write();
+        self.with_handle(|fh| {
+            let mut read_len = 0;
+            for chunk in buf.chunks_mut(chunk_size) {
+                let read = ReadIn {
+                    fh,
+                    offset: offset + read_len as u64,
+                    size: chunk.len() as u32,
+                    ..Default::default()
+                };
+                let reply = self
+                    .conn()
+                    .request(opcode::READ, self.nodeid, &[read.as_bytes()])?;
+                let len = reply.len().min(chunk.len());
+                chunk[..len].copy_from_slice(&reply[..len]);
+                read_len += len;
+                if len < chunk.len() {
+                    break;
+                }
+            }
+            Ok(read_len)
+        })
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let chunk_size = self.conn().max_write();
+        self.with_handle(|fh| {
+            let mut written = 0;
+            for chunk in buf.chunks(chunk_size) {
+                let write = WriteIn {
+                    fh,
+                    offset: offset + written as u64,
+                    size: chunk.len() as u32,
+                    ..Default::default()
+                };
+                let args = [write.as_bytes(), chunk];
+                let reply = self.conn().request(opcode::WRITE, self.nodeid, &args)?;
+                let len = (WriteOut::parse(&reply)?.size as usize).min(chunk.len());
+                written += len;
+                if len < chunk.len() {
+                    break;
+                }
+            }
+            Ok(written)
+        })
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        let setattr = SetattrIn {
+            valid: FATTR_SIZE,
+            size,
+            ..Default::default()
+        };
+        self.conn()
+            .request(opcode::SETATTR, self.nodeid, &[setattr.as_bytes()])?;
+        Ok(())
+    }
+
+    fn readlink(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        if !self.ty.is_symlink() {
+            return ax_err!(InvalidInput);
+        }
+        let target = self.conn().request(opcode::READLINK, self.nodeid, &[])?;
+        let len = target.len().min(buf.len());
+        buf[..len].copy_from_slice(&target[..len]);
+        Ok(len)
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent
+            .lock()
+            .clone()
+            .map(|parent| parent as VfsNodeRef)
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        Ok(self.walk(path)?)
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        let (dir, name) = self.split(path)?;
+        let name = c_name(name);
+        let reply = match ty {
+            VfsNodeType::File => {
+                let create = CreateIn {
+                    flags: O_RDWR | O_CREAT | O_EXCL,
+                    mode: S_IFREG | VfsNodePerm::default_file().mode(),
+                    ..Default::default()
+                };
+                let args = [create.as_bytes(), &name];
+                dir.conn().request(opcode::CREATE, dir.nodeid, &args)
+            }
+            VfsNodeType::Dir => {
+                let mkdir = MkdirIn {
+                    mode: VfsNodePerm::default_dir().mode(),
+                    ..Default::default()
+                };
+                let args = [mkdir.as_bytes(), &name];
+                dir.conn().request(opcode::MKDIR, dir.nodeid, &args)
+            }
+            _ => return ax_err!(Unsupported),
+        };
+        let reply = match reply {
+            Err(VfsError::AlreadyExists) => return Ok(()),
+            reply => reply?,
+        };
+        // the new node is looked up, and forgotten when dropped
+        dir.child(&EntryOut::parse(&reply)?)?;
+        if ty.is_file() {
+            let open = OpenOut::parse(&reply[size_of::<EntryOut>()..])?;
+            let release = ReleaseIn {
+                fh: open.fh,
+                ..Default::default()
+            };
+            let nodeid = EntryOut::parse(&reply)?.nodeid;
+            dir.conn()
+                .request(opcode::RELEASE, nodeid, &[release.as_bytes()])?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        let (dir, name) = self.split(path)?;
+        let op = if dir.lookup_child(name)?.ty.is_dir() {
+            opcode::RMDIR
+        } else {
+            opcode::UNLINK
+        };
+        dir.conn().request(op, dir.nodeid, &[&c_name(name)])?;
+        Ok(())
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let (mut idx, mut n) = (0, 0);
+        self.read_entries(0, |name, ty, _| {
+            if idx >= start_idx {
+                dirents[n] = VfsDirEntry::new(name, ty);
+                n += 1;
+            }
+            idx += 1;
+            n < dirents.len()
+        })?;
+        Ok(n)
+    }
+
+    fn read_dir_at(&self, pos: u64, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let mut n = 0;
+        if dirents.is_empty() {
+            return Ok(0);
+        }
+        self.read_entries(pos, |name, ty, next| {
+            dirents[n] = VfsDirEntry::new(name, ty);
+            dirents[n].set_next_offset(next);
+            n += 1;
+            n < dirents.len()
+        })?;
+        Ok(n)
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
+
+impl Drop for FuseNode {
+    fn drop(&mut self) {
+        if self.nodeid == ROOT_ID {
+            return;
+        }
+        let mut nodes = self.mount.nodes.lock();
+        // the node ID may have been looked up again since the last reference
+        // was dropped
+        if nodes
+            .get(&self.nodeid)
+            .is_some_and(|node| node.strong_count() == 0)
+        {
+            nodes.remove(&self.nodeid);
+        }
+        drop(nodes);
+        self.conn()
+            .forget(self.nodeid, self.nlookup.load(Ordering::Acquire));
+    }
+}
+
+/// A filesystem served by a user-space daemon.
+pub struct FuseFileSystem {
+    mount: Arc<FuseMount>,
+    root: Arc<FuseNode>,
+}
+
+impl FuseFileSystem {
+    /// Creates the filesystem of the connection `conn`, sending `INIT` to
+    /// the daemon. Fails with `EINVAL` if the connection is already mounted.
+    pub fn new(conn: Arc<FuseConnection>) -> VfsResult<Self> {
+        conn.mount()?;
+        let mount = Arc::new(FuseMount {
+            conn,
+            nodes: Mutex::new(BTreeMap::new()),
+            open_files: AtomicUsize::new(0),
+        });
+        let root = Arc::new_cyclic(|this| FuseNode {
+            mount: mount.clone(),
+            this: this.clone(),
+            nodeid: ROOT_ID,
+            ty: VfsNodeType::Dir,
+            parent: Mutex::new(None),
+            nlookup: AtomicU64::new(0),
+            handle: Mutex::new(None),
+        });
+        Ok(Self { mount, root })
+    }
+}
+
+impl VfsOps for FuseFileSystem {
+    fn umount(&self) -> VfsResult {
+        self.mount.conn.abort();
+        Ok(())
+    }
+
+    fn is_busy(&self) -> bool {
+        self.mount.open_files.load(Ordering::Acquire) > 0
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+/// A name as sent to the daemon, terminated by a null byte.
+fn c_name(name: &str) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(name.len() + 1);
+    buf.extend_from_slice(name.as_bytes());
+    buf.push(0);
+    buf
+}
+
+/// The node type of the `st_mode` bits `mode`.
+fn node_type(mode: u32) -> VfsNodeType {
+    match (mode & S_IFMT) >> 12 {
+        0o1 => VfsNodeType::Fifo,
+        0o2 => VfsNodeType::CharDevice,
+        0o4 => VfsNodeType::Dir,
+        0o6 => VfsNodeType::BlockDevice,
+        0o12 => VfsNodeType::SymLink,
+        0o14 => VfsNodeType::Socket,
+        _ => VfsNodeType::File,
+    }
+}
+
+fn node_attr(attr: &Attr) -> VfsNodeAttr {
+    let perm = VfsNodePerm::from_bits_truncate(attr.mode as u16);
+    let mut node_attr = VfsNodeAttr::new(perm, node_type(attr.mode), attr.size, attr.blocks);
+    node_attr.set_nlink(attr.nlink as u64);
+    node_attr.set_owner(attr.uid, attr.gid);
+    node_attr.set_times(
+        Duration::new(attr.atime, attr.atimensec),
+        Duration::new(attr.mtime, attr.mtimensec),
+        Duration::new(attr.ctime, attr.ctimensec),
+    );
+    node_attr.set_rdev(DeviceId::from_raw(attr.rdev as u64));
+    node_attr
+}
@@ -9,8 +9,23 @@ cfg_if::cfg_if! {
 #[cfg(feature = "ext4fs")]
 pub mod ext4fs;
 
+#[cfg(feature = "procfs")]
+pub mod procfs;
+
+#[cfg(feature = "sysfs")]
+pub mod sysfs;
+
+#[cfg(feature = "overlayfs")]
+pub mod overlayfs;
+
+#[cfg(feature = "fuse")]
+pub mod fusefs;
+
 #[cfg(feature = "devfs")]
 pub use axfs_devfs as devfs;
 
+#[cfg(feature = "devfs")]
+pub mod devpts;
+
 #[cfg(feature = "ramfs")]
 pub use axfs_ramfs as ramfs;
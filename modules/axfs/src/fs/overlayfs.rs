@@ -0,0 +1,706 @@
+//! The overlay filesystem, merging a read-only lower layer with a writable
+//! upper layer.
+//!
+//! A name in a directory of the overlay refers to the node of the upper
+//! layer if there is one, else to the node of the lower layer, and the
+//! directories found in both layers list the entries of both. The lower
+//! layer is never modified:
+//!
+//! - A node of the lower layer is copied up, with its parent directories, to
+//!   the upper layer the first time its data or attributes change.
+//! - Removing a name that exists in the lower layer leaves a whiteout in the
+//!   upper layer, an empty file named `.wh.<name>` hiding it.
+//! - A directory created in place of a removed one is made opaque with a
+//!   `.wh..wh..opq` file, so that the entries of the removed lower directory
+//!   stay hidden.
+//!
+//! These are the conventions of AUFS and of OCI image layers, which only
+//! need regular files from the upper layer. The names starting with `.wh.`
+//! are reserved.
+//!
+//! As in Linux without `redirect_dir`, directories of the lower layer cannot
+//! be renamed (`EXDEV`), and `mv` falls back to copying them. Changing the
+//! layers behind the back of a mounted overlay gives undefined results.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::sync::{Arc, Weak};
+use alloc::{format, vec, vec::Vec};
+use axerrno::ax_err;
+use axfs_vfs::{DeviceId, FileSystemInfo, VfsDirEntry, VfsExtent, VfsNodeAttr, VfsNodeFlags};
+use axfs_vfs::{VfsError, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
+use axsync::Mutex;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+
+/// The prefix of the names of the whiteouts, reserved in the overlay.
+const WHITEOUT_PREFIX: &str = ".wh.";
+
+/// The file marking a directory of the upper layer as opaque.
+const OPAQUE_MARKER: &str = ".wh..wh..opq";
+
+/// The size of the chunks in which file data is copied up.
+const COPY_CHUNK_SIZE: usize = 0x4000;
+
+/// The number of entries read at once from the directories of the layers.
+const DIRENT_BATCH: usize = 32;
+
+/// The longest target of a symbolic link copied up.
+const MAX_SYMLINK_LEN: usize = 4096;
+
+/// A file or directory of the overlay.
+///
+/// Every lookup of a name returns the same node while it is alive, which
+/// keeps track of the copy-up of the file.
+pub struct OverlayNode {
+    this: Weak<OverlayNode>,
+    /// The number of opened nodes of the overlay.
+    open_files: Arc<AtomicUsize>,
+    ty: VfsNodeType,
+    /// The parent directory and the name in it, `None` for the root.
+    loc: Mutex<Option<(Arc<OverlayNode>, String)>>,
+    /// The node of the upper layer, once there is one.
+    upper: Mutex<Option<VfsNodeRef>>,
+    /// The node of the lower layer visible under the name: the data of a
+    /// file not copied up yet, or the merged part of a directory.
+    lower: Mutex<Option<VfsNodeRef>>,
+    /// The children looked up in a directory.
+    children: Mutex<BTreeMap<String, Weak<OverlayNode>>>,
+}
+
+impl OverlayNode {
+    fn new(
+        open_files: Arc<AtomicUsize>,
+        ty: VfsNodeType,
+        loc: Option<(Arc<OverlayNode>, String)>,
+        upper: Option<VfsNodeRef>,
+        lower: Option<VfsNodeRef>,
+    ) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            open_files,
+            ty,
+            loc: Mutex::new(loc),
+            upper: Mutex::new(upper),
+            lower: Mutex::new(lower),
+            children: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    fn arc(&self) -> Arc<Self> {
+        self.this.upgrade().unwrap()
+    }
+
+    fn upper(&self) -> Option<VfsNodeRef> {
+        self.upper.lock().clone()
+    }
+
+    fn lower(&self) -> Option<VfsNodeRef> {
+        self.lower.lock().clone()
+    }
+
+    /// The node holding the data and attributes: the upper one if any.
+    fn real(&self) -> VfsNodeRef {
+        self.upper()
+            .or_else(|| self.lower())
+            .expect("overlay node in no layer")
+    }
+
+    fn parent_node(&self) -> Option<Arc<Self>> {
+        self.loc.lock().as_ref().map(|(parent, _)| parent.clone())
+    }
+
+    fn root_node(&self) -> Arc<Self> {
+        match self.parent_node() {
+            Some(parent) => parent.root_node(),
+            None => self.arc(),
+        }
+    }
+
+    /// The path of the node from the root of the overlay, which is also its
+    /// path in the upper layer.
+    fn path(&self) -> String {
+        match self.loc.lock().clone() {
+            Some((parent, name)) => join_path(&parent.path(), &name),
+            None => String::new(),
+        }
+    }
+
+    /// Looks up the entry `name` of this directory.
+    fn lookup_child(&self, name: &str) -> VfsResult<Arc<Self>> {
+        if !self.ty.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        match name {
+            "" | "." => return Ok(self.arc()),
+            ".." => return self.parent_node().ok_or(VfsError::NotFound),
+            _ if name.starts_with(WHITEOUT_PREFIX) => return ax_err!(NotFound),
+            _ => {}
+        }
+        let mut children = self.children.lock();
+        if let Some(node) = children.get(name).and_then(Weak::upgrade) {
+            return Ok(node);
+        }
+        let upper_dir = self.upper();
+        let upper = match &upper_dir {
+            Some(dir) => lookup_opt(dir, name)?,
+            None => None,
+        };
+        let upper_ty = match &upper {
+            Some(node) => Some(node.get_attr()?.file_type()),
+            None => None,
+        };
+        // a file of the upper layer or a whiteout hides the lower entry
+        let mut lower = None;
+        if let Some(dir) = self.lower() {
+            let whiteout = match &upper_dir {
+                Some(upper_dir) => lookup_opt(upper_dir, &whiteout_name(name))?.is_some(),
+                None => false,
+            };
+            if !matches!(upper_ty, Some(ty) if !ty.is_dir()) && !whiteout {
+                lower = lookup_opt(&dir, name)?;
+            }
+        }
+        // a directory of the upper layer hides a lower file, and all of the
+        // lower directory if it is opaque
+        if let (Some(upper), Some(node)) = (&upper, &lower) {
+            if !node.get_attr()?.is_dir() || is_opaque(upper)? {
+                lower = None;
+            }
+        }
+        let ty = match (upper_ty, &lower) {
+            (Some(ty), _) => ty,
+            (None, Some(node)) => node.get_attr()?.file_type(),
+            (None, None) => return ax_err!(NotFound),
+        };
+        let node = Self::new(
+            self.open_files.clone(),
+            ty,
+            Some((self.arc(), name.into())),
+            upper,
+            lower,
+        );
+        children.retain(|_, child| child.strong_count() > 0);
+        children.insert(name.into(), Arc::downgrade(&node));
+        Ok(node)
+    }
+
+    /// Whether the lower layer has an entry `name` merged in this directory,
+    /// which needs a whiteout once the name is removed.
+    fn in_lower(&self, name: &str) -> VfsResult<bool> {
+        match self.lower() {
+            Some(dir) => Ok(lookup_opt(&dir, name)?.is_some()),
+            None => Ok(false),
+        }
+    }
+
+    /// Looks up `path`, relative to this directory.
+    fn walk(&self, path: &str) -> VfsResult<Arc<Self>> {
+        let mut node = self.arc();
+        for name in path.split('/') {
+            node = node.lookup_child(name)?;
+        }
+        Ok(node)
+    }
+
+    /// Splits `path`, relative to this directory, into the directory holding
+    /// its last component and the name of the component, which must not be
+    /// reserved.
+    fn split<'a>(&self, path: &'a str) -> VfsResult<(Arc<Self>, &'a str)> {
+        let path = path.trim_end_matches('/');
+        let (dir, name) = match path.rsplit_once('/') {
+            Some((dir, name)) => (self.walk(dir)?, name),
+            None => (self.arc(), path),
+        };
+        if matches!(name, "" | "." | "..") || name.starts_with(WHITEOUT_PREFIX) {
+            return ax_err!(InvalidInput);
+        }
+        Ok((dir, name))
+    }
+
+    /// Returns the node of the upper layer, copying this node up with its
+    /// parents first if it is only in the lower layer.
+    fn copy_up(&self) -> VfsResult<VfsNodeRef> {
+        let mut upper = self.upper.lock();
+        if let Some(node) = upper.as_ref() {
+            return Ok(node.clone());
+        }
+        // the root always has an upper node
+        let (parent, name) = self.loc.lock().clone().ok_or(VfsError::NotFound)?;
+        let dir = parent.copy_up()?;
+        let lower = self.lower().ok_or(VfsError::NotFound)?;
+        let attr = lower.get_attr()?;
+        match attr.file_type() {
+            VfsNodeType::SymLink => {
+                let mut buf = vec![0; MAX_SYMLINK_LEN];
+                let len = lower.readlink(&mut buf)?;
+                let target =
+                    core::str::from_utf8(&buf[..len]).map_err(|_| VfsError::InvalidData)?;
+                dir.symlink(&name, target)?;
+            }
+            ty @ (VfsNodeType::CharDevice | VfsNodeType::BlockDevice) => {
+                dir.mknod(&name, ty, attr.rdev())?
+            }
+            ty => dir.create(&name, ty)?,
+        }
+        let node = dir.lookup(&name)?;
+        if attr.is_file() {
+            copy_data(&lower, &node, attr.size())?;
+        }
+        copy_attr(&node, &attr)?;
+        *upper = Some(node.clone());
+        Ok(node)
+    }
+
+    /// Lists the entries of this directory, but `.` and `..`, merging the
+    /// layers.
+    fn entries(&self) -> VfsResult<Vec<(String, VfsNodeType)>> {
+        let mut entries = Vec::new();
+        let mut hidden = BTreeSet::new();
+        if let Some(upper) = self.upper() {
+            for (name, ty) in read_all(&upper)? {
+                match name.strip_prefix(WHITEOUT_PREFIX) {
+                    Some(_) if name == OPAQUE_MARKER => {}
+                    Some(whited_out) => {
+                        hidden.insert(whited_out.to_string());
+                    }
+                    None => {
+                        hidden.insert(name.clone());
+                        entries.push((name, ty));
+                    }
+                }
+            }
+        }
+        if let Some(lower) = self.lower() {
+            for (name, ty) in read_all(&lower)? {
+                if !hidden.contains(&name) && !name.starts_with(WHITEOUT_PREFIX) {
+                    entries.push((name, ty));
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Creates the entry `name` in this directory with `create`, in the
+    /// upper layer, where it replaces a whiteout.
+    fn create_child<F>(&self, name: &str, ty: VfsNodeType, create: F) -> VfsResult
+    where
+        F: FnOnce(&VfsNodeRef) -> VfsResult,
+    {
+        match self.lookup_child(name) {
+            Ok(_) => return ax_err!(AlreadyExists),
+            Err(VfsError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        let dir = self.copy_up()?;
+        create(&dir)?;
+        let whiteout = whiteout_name(name);
+        if lookup_opt(&dir, &whiteout)?.is_some() {
+            if ty.is_dir() {
+                // the removed lower directory stays hidden
+                dir.clone()
+                    .lookup(name)?
+                    .create(OPAQUE_MARKER, VfsNodeType::File)?;
+            }
+            dir.remove(&whiteout)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the entry `name` of this directory, leaving a whiteout if it
+    /// exists in the lower layer.
+    fn remove_child(&self, name: &str) -> VfsResult {
+        let node = self.lookup_child(name)?;
+        let upper = node.upper();
+        if node.ty.is_dir() {
+            if !node.entries()?.is_empty() {
+                return ax_err!(DirectoryNotEmpty);
+            }
+            // only whiteouts are left in the upper directory
+            if let Some(upper) = &upper {
+                clear_dir(upper)?;
+            }
+        }
+        if self.in_lower(name)? {
+            self.copy_up()?
+                .create(&whiteout_name(name), VfsNodeType::File)?;
+        }
+        if upper.is_some() {
+            self.copy_up()?.remove(name)?;
+        }
+        self.children.lock().remove(name);
+        Ok(())
+    }
+}
+
+impl VfsNodeOps for OverlayNode {
+    fn open(&self) -> VfsResult {
+        self.open_files.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    fn release(&self) -> VfsResult {
+        self.open_files.fetch_sub(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.real().get_attr()
+    }
+
+    fn set_perm(&self, perm: VfsNodePerm) -> VfsResult {
+        self.copy_up()?.set_perm(perm)
+    }
+
+    fn set_owner(&self, uid: Option<u32>, gid: Option<u32>) -> VfsResult {
+        self.copy_up()?.set_owner(uid, gid)
+    }
+
+    fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> VfsResult {
+        self.copy_up()?.set_times(atime, mtime)
+    }
+
+    fn get_flags(&self) -> VfsResult<VfsNodeFlags> {
+        self.real().get_flags()
+    }
+
+    fn set_flags(&self, flags: VfsNodeFlags) -> VfsResult {
+        self.copy_up()?.set_flags(flags)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if self.ty.is_dir() {
+            return ax_err!(IsADirectory);
+        }
+        self.real().read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        if self.ty.is_dir() {
+            return ax_err!(IsADirectory);
+        }
+        self.copy_up()?.write_at(offset, buf)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        match self.upper() {
+            Some(upper) => upper.fsync(),
+            // nothing was written
+            None => Ok(()),
+        }
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        if self.ty.is_dir() {
+            return ax_err!(IsADirectory);
+        }
+        self.copy_up()?.truncate(size)
+    }
+
+    fn fiemap(&self, start: u64, len: u64) -> VfsResult<Vec<VfsExtent>> {
+        self.real().fiemap(start, len)
+    }
+
+    fn cache_key(&self) -> Option<(usize, u64)> {
+        // The node stays the same across the copy-up.
+        self.ty
+            .is_file()
+            .then_some((self as *const Self as usize, 0))
+    }
+
+    fn readlink(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        self.real().readlink(buf)
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.parent_node().map(|parent| parent as VfsNodeRef)
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        Ok(self.walk(path)?)
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        let (dir, name) = self.split(path)?;
+        match dir.create_child(name, ty, |upper| upper.create(name, ty)) {
+            Err(VfsError::AlreadyExists) => Ok(()),
+            res => res,
+        }
+    }
+
+    fn link(&self, path: &str, node: &VfsNodeRef) -> VfsResult {
+        let (dir, name) = self.split(path)?;
+        let target = node
+            .as_any()
+            .downcast_ref::<OverlayNode>()
+            .ok_or(VfsError::CrossesDevices)?;
+        if target.ty.is_dir() {
+            return ax_err!(OperationNotPermitted);
+        }
+        let upper = target.copy_up()?;
+        dir.create_child(name, target.ty, |dir| dir.link(name, &upper))
+    }
+
+    fn symlink(&self, path: &str, target: &str) -> VfsResult {
+        let (dir, name) = self.split(path)?;
+        dir.create_child(name, VfsNodeType::SymLink, |dir| dir.symlink(name, target))
+    }
+
+    fn mknod(&self, path: &str, ty: VfsNodeType, rdev: DeviceId) -> VfsResult {
+        let (dir, name) = self.split(path)?;
+        dir.create_child(name, ty, |dir| dir.mknod(name, ty, rdev))
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        let (dir, name) = self.split(path)?;
+        dir.remove_child(name)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        if !self.ty.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        let dots = [".", ".."].map(|name| (name.to_string(), VfsNodeType::Dir));
+        let all = dots.into_iter().chain(self.entries()?);
+        let mut n = 0;
+        for ((name, ty), ent) in all.skip(start_idx).zip(dirents.iter_mut()) {
+            *ent = VfsDirEntry::new(&name, ty);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
+        let (src_dir, src_name) = self.split(src_path)?;
+        let (dst_dir, dst_name) = self.split(dst_path)?;
+        let node = src_dir.lookup_child(src_name)?;
+        let dst = match dst_dir.lookup_child(dst_name) {
+            Ok(dst) => Some(dst),
+            Err(VfsError::NotFound) => None,
+            Err(e) => return Err(e),
+        };
+        if let Some(dst) = &dst {
+            if Arc::ptr_eq(dst, &node) {
+                return Ok(());
+            }
+            match (node.ty.is_dir(), dst.ty.is_dir()) {
+                (true, false) => return ax_err!(NotADirectory),
+                (false, true) => return ax_err!(IsADirectory),
+                (true, true) if !dst.entries()?.is_empty() => return ax_err!(DirectoryNotEmpty),
+                _ => {}
+            }
+        }
+        if node.ty.is_dir() && node.lower().is_some() {
+            // the lower part of a merged directory cannot move
+            return ax_err!(CrossesDevices);
+        }
+        node.copy_up()?;
+        let src_upper_dir = src_dir.copy_up()?;
+        let dst_upper_dir = dst_dir.copy_up()?;
+        if let Some(upper) = dst
+            .as_ref()
+            .filter(|dst| dst.ty.is_dir())
+            .and_then(|dst| dst.upper())
+        {
+            // only whiteouts are left in the replaced directory
+            clear_dir(&upper)?;
+        }
+        let src_upper_path = join_path(&src_dir.path(), src_name);
+        let dst_upper_path = join_path(&dst_dir.path(), dst_name);
+        let upper_root = self.root_node().copy_up()?;
+        upper_root.rename(&src_upper_path, &dst_upper_path)?;
+
+        if node.ty.is_dir() && dst_dir.in_lower(dst_name)? {
+            dst_upper_dir
+                .clone()
+                .lookup(dst_name)?
+                .create(OPAQUE_MARKER, VfsNodeType::File)?;
+        }
+        let dst_whiteout = whiteout_name(dst_name);
+        if lookup_opt(&dst_upper_dir, &dst_whiteout)?.is_some() {
+            dst_upper_dir.remove(&dst_whiteout)?;
+        }
+        if src_dir.in_lower(src_name)? {
+            src_upper_dir.create(&whiteout_name(src_name), VfsNodeType::File)?;
+        }
+
+        src_dir.children.lock().remove(src_name);
+        // the data is in the upper layer
+        *node.lower.lock() = None;
+        *node.loc.lock() = Some((dst_dir.clone(), dst_name.into()));
+        dst_dir
+            .children
+            .lock()
+            .insert(dst_name.into(), Arc::downgrade(&node));
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
+}
+
+/// The overlay filesystem.
+pub struct OverlayFileSystem {
+    root: Arc<OverlayNode>,
+    /// The filesystems of the layers if the overlay owns them, lower first.
+    layers: Vec<Arc<dyn VfsOps>>,
+}
+
+impl OverlayFileSystem {
+    /// Creates an overlay of the directory `upper` over the directory
+    /// `lower`, which is never modified.
+    pub fn new(lower: VfsNodeRef, upper: VfsNodeRef) -> VfsResult<Self> {
+        if !lower.get_attr()?.is_dir() || !upper.get_attr()?.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        let lower = if is_opaque(&upper)? {
+            None
+        } else {
+            Some(lower)
+        };
+        let open_files = Arc::new(AtomicUsize::new(0));
+        Ok(Self {
+            root: OverlayNode::new(open_files, VfsNodeType::Dir, None, Some(upper), lower),
+            layers: Vec::new(),
+        })
+    }
+
+    /// Creates an overlay of the filesystem `upper` over the filesystem
+    /// `lower`, which is never modified. The overlay owns the filesystems:
+    /// it syncs and unmounts them.
+    pub fn with_layers(lower: Arc<dyn VfsOps>, upper: Arc<dyn VfsOps>) -> VfsResult<Self> {
+        let mut fs = Self::new(lower.root_dir(), upper.root_dir())?;
+        fs.layers = vec![lower, upper];
+        Ok(fs)
+    }
+}
+
+impl VfsOps for OverlayFileSystem {
+    fn umount(&self) -> VfsResult {
+        for layer in self.layers.iter().rev() {
+            layer.umount()?;
+        }
+        Ok(())
+    }
+
+    fn sync(&self) -> VfsResult {
+        match self.layers.last() {
+            Some(upper) => upper.sync(),
+            None => Ok(()),
+        }
+    }
+
+    fn is_busy(&self) -> bool {
+        self.root.open_files.load(Ordering::Acquire) > 0
+    }
+
+    fn statfs(&self) -> VfsResult<FileSystemInfo> {
+        // the space left is that of the upper layer
+        match self.layers.last() {
+            Some(upper) => upper.statfs(),
+            None => ax_err!(Unsupported),
+        }
+    }
+
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+fn join_path(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.into()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+fn whiteout_name(name: &str) -> String {
+    format!("{}{}", WHITEOUT_PREFIX, name)
+}
+
+/// Looks up `name` in the directory `dir` of a layer, `None` if it does not
+/// exist.
+fn lookup_opt(dir: &VfsNodeRef, name: &str) -> VfsResult<Option<VfsNodeRef>> {
+    match dir.clone().lookup(name) {
+        Ok(node) => Ok(Some(node)),
+        Err(VfsError::NotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether the directory `dir` of the upper layer hides the lower one.
+fn is_opaque(dir: &VfsNodeRef) -> VfsResult<bool> {
+    Ok(lookup_opt(dir, OPAQUE_MARKER)?.is_some())
+}
+
+/// Reads all the entries of the directory `dir` of a layer, but `.` and
+/// `..`.
+fn read_all(dir: &VfsNodeRef) -> VfsResult<Vec<(String, VfsNodeType)>> {
+    let mut entries = Vec::new();
+    let mut buf: Vec<_> = (0..DIRENT_BATCH).map(|_| VfsDirEntry::default()).collect();
+    let mut idx = 0;
+    loop {
+        let n = dir.read_dir(idx, &mut buf)?;
+        for ent in &buf[..n] {
+            let name = String::from_utf8_lossy(ent.name_as_bytes());
+            if name != "." && name != ".." {
+                entries.push((name.into_owned(), ent.entry_type()));
+            }
+        }
+        if n < buf.len() {
+            return Ok(entries);
+        }
+        idx += n;
+    }
+}
+
+/// Removes the whiteouts and the opaque marker left in the directory `dir`
+/// of the upper layer, before removing it.
+fn clear_dir(dir: &VfsNodeRef) -> VfsResult {
+    for (name, _) in read_all(dir)? {
+        if name.starts_with(WHITEOUT_PREFIX) {
+            dir.remove(&name)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies the `size` bytes of data of the file `src` to `dst`.
+fn copy_data(src: &VfsNodeRef, dst: &VfsNodeRef, size: u64) -> VfsResult {
+    let mut buf = vec![0; COPY_CHUNK_SIZE];
+    let mut offset = 0;
+    while offset < size {
+        let n = src.read_at(offset, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        let mut done = 0;
+        while done < n {
+            match dst.write_at(offset + done as u64, &buf[done..n])? {
+                0 => return ax_err!(WriteZero),
+                written => done += written,
+            }
+        }
+        offset += n as u64;
+    }
+    Ok(())
+}
+
+/// Copies the permission, owner and times in `attr` to `node`, as far as
+/// its filesystem supports them.
+fn copy_attr(node: &VfsNodeRef, attr: &VfsNodeAttr) -> VfsResult {
+    let results = [
+        node.set_perm(attr.perm()),
+        node.set_owner(Some(attr.uid()), Some(attr.gid())),
+        node.set_times(Some(attr.atime()), Some(attr.mtime())),
+    ];
+    for res in results {
+        match res {
+            Ok(()) | Err(VfsError::Unsupported) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
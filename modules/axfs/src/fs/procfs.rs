@@ -0,0 +1,295 @@
+//! A synthetic procfs, whose files are generated from the kernel state every
+//! time they are read.
+//!
+//! The system-wide files (`meminfo`, `uptime`, `mounts`, `cpuinfo`, ...) are
+//! generated here. The process directories `/proc/<pid>`, `/proc/self` and
+//! the other files that need the process table are provided by the process
+//! module, registered with [`register_process_entries`].
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use axfs_vfs::{impl_vfs_dir_default, impl_vfs_non_dir_default};
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsError, VfsOps, VfsResult};
+use axsync::Mutex;
+
+/// Lists the entries of a procfs directory, by name.
+pub type ProcEntries = fn() -> Vec<(String, VfsNodeRef)>;
+
+/// The entries added to the root directory by the process module.
+static PROCESS_ENTRIES: Mutex<Option<ProcEntries>> = Mutex::new(None);
+
+/// Registers the function listing the process entries of `/proc`, such as
+/// `self` and the `<pid>` directories. A later registration replaces the
+/// earlier one.
+pub fn register_process_entries(f: ProcEntries) {
+    *PROCESS_ENTRIES.lock() = Some(f);
+}
+
+/// A read-only file whose content is generated when it is read.
+///
+/// It reports a size of zero, as in Linux, so it must be read until the end.
+pub struct ProcFile {
+    render: Box<dyn Fn() -> VfsResult<String> + Send + Sync>,
+}
+
+impl ProcFile {
+    /// Creates a file whose content is returned by `render`.
+    pub fn new(render: impl Fn() -> VfsResult<String> + Send + Sync + 'static) -> Self {
+        Self {
+            render: Box::new(render),
+        }
+    }
+}
+
+impl VfsNodeOps for ProcFile {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let perm = VfsNodePerm::from_bits_truncate(0o444);
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::File, 0, 0))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = (self.render)()?;
+        let bytes = content.as_bytes();
+        let start = bytes.len().min(offset as usize);
+        let len = buf.len().min(bytes.len() - start);
+        buf[..len].copy_from_slice(&bytes[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    impl_vfs_non_dir_default! {}
+}
+
+/// A symbolic link whose target is found when it is read.
+pub struct ProcSymlink {
+    target: Box<dyn Fn() -> VfsResult<String> + Send + Sync>,
+}
+
+impl ProcSymlink {
+    /// Creates a symbolic link to the path returned by `target`.
+    pub fn new(target: impl Fn() -> VfsResult<String> + Send + Sync + 'static) -> Self {
+        Self {
+            target: Box::new(target),
+        }
+    }
+}
+
+impl VfsNodeOps for ProcSymlink {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let perm = VfsNodePerm::from_bits_truncate(0o777);
+        let size = (self.target)().map_or(0, |target| target.len());
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::SymLink, size as _, 0))
+    }
+
+    fn readlink(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let target = (self.target)()?;
+        let len = buf.len().min(target.len());
+        buf[..len].copy_from_slice(&target.as_bytes()[..len]);
+        Ok(len)
+    }
+
+    impl_vfs_non_dir_default! {}
+}
+
+/// A read-only directory whose entries are listed when it is looked up or
+/// read.
+pub struct ProcDir {
+    entries: Box<dyn Fn() -> Vec<(String, VfsNodeRef)> + Send + Sync>,
+}
+
+impl ProcDir {
+    /// Creates a directory whose entries are returned by `entries`.
+    pub fn new(entries: impl Fn() -> Vec<(String, VfsNodeRef)> + Send + Sync + 'static) -> Self {
+        Self {
+            entries: Box::new(entries),
+        }
+    }
+
+    /// Creates a directory with the fixed `entries`.
+    pub fn with_entries(entries: Vec<(String, VfsNodeRef)>) -> Self {
+        Self::new(move || entries.clone())
+    }
+}
+
+impl VfsNodeOps for ProcDir {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let perm = VfsNodePerm::from_bits_truncate(0o555);
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::Dir, 0, 0))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let path = path.trim_start_matches('/');
+        let (name, rest) = match path.split_once('/') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (path, None),
+        };
+        let node = match name {
+            "" | "." => self.clone() as VfsNodeRef,
+            _ => (self.entries)()
+                .into_iter()
+                .find(|(entry, _)| entry == name)
+                .map(|(_, node)| node)
+                .ok_or(VfsError::NotFound)?,
+        };
+        match rest {
+            Some(rest) if !rest.is_empty() => node.lookup(rest),
+            _ => Ok(node),
+        }
+    }
+
+    fn create(&self, _path: &str, _ty: VfsNodeType) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn remove(&self, _path: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let entries = (self.entries)();
+        let dots = [".", ".."].map(|name| (name, VfsNodeType::Dir));
+        let all = dots.into_iter().chain(entries.iter().map(|(name, node)| {
+            let ty = node
+                .get_attr()
+                .map_or(VfsNodeType::File, |attr| attr.file_type());
+            (name.as_str(), ty)
+        }));
+        let mut n = 0;
+        for ((name, ty), ent) in all.skip(start_idx).zip(dirents.iter_mut()) {
+            *ent = VfsDirEntry::new(name, ty);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    impl_vfs_dir_default! {}
+}
+
+/// The procfs, mounted on `/proc`.
+pub struct ProcFileSystem {
+    root: Arc<ProcDir>,
+}
+
+impl ProcFileSystem {
+    /// Creates the procfs, with the system-wide files and the registered
+    /// process entries in its root directory.
+    pub fn new() -> Self {
+        let fixed = system_entries();
+        let root = ProcDir::new(move || {
+            let mut entries = fixed.clone();
+            // copied out, the process entries may take other locks
+            let process_entries = *PROCESS_ENTRIES.lock();
+            if let Some(f) = process_entries {
+                entries.extend(f());
+            }
+            entries
+        });
+        Self {
+            root: Arc::new(root),
+        }
+    }
+}
+
+impl Default for ProcFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VfsOps for ProcFileSystem {
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+/// A procfs entry named `name`.
+fn entry(name: &str, node: impl VfsNodeOps + 'static) -> (String, VfsNodeRef) {
+    (name.into(), Arc::new(node))
+}
+
+/// A writable file holding `content`, for the tunables in `/proc/sys`.
+fn tunable(content: &[u8]) -> axfs_ramfs::FileNode {
+    let file = axfs_ramfs::FileNode::new();
+    file.write_at(0, content)
+        .expect("a file without limits is never full");
+    file
+}
+
+/// The files of the root directory that do not depend on the processes.
+fn system_entries() -> Vec<(String, VfsNodeRef)> {
+    let net_core = ProcDir::with_entries(vec![entry("somaxconn", tunable(b"4096\n"))]);
+    let vm = ProcDir::with_entries(vec![entry("overcommit_memory", tunable(b"0\n"))]);
+    let net = ProcDir::with_entries(vec![entry("core", net_core)]);
+    let sys = ProcDir::with_entries(vec![entry("net", net), entry("vm", vm)]);
+    #[allow(unused_mut)]
+    let mut entries = vec![
+        entry("meminfo", ProcFile::new(|| Ok(meminfo()))),
+        entry("uptime", ProcFile::new(|| Ok(uptime()))),
+        entry("mounts", ProcFile::new(|| Ok(crate::root::proc_mounts()))),
+        entry("cpuinfo", ProcFile::new(|| Ok(cpuinfo()))),
+        entry("sys", sys),
+    ];
+    #[cfg(feature = "monolithic")]
+    entries.push(entry("interrupts", axfs_ramfs::Interrupts));
+    entries
+}
+
+/// `/proc/meminfo`, in kB as in Linux. Clean cached file pages are counted
+/// as available, since they are dropped when memory runs out.
+fn meminfo() -> String {
+    let allocator = axalloc::global_allocator();
+    let page_kb = axhal::mem::PAGE_SIZE_4K / 1024;
+    let free = allocator.available_pages() * page_kb;
+    let total = allocator.used_pages() * page_kb + free;
+    let cached = crate::page_cache::cached_pages() * page_kb;
+    [
+        ("MemTotal", total),
+        ("MemFree", free),
+        ("MemAvailable", free + cached),
+        ("Buffers", 0),
+        ("Cached", cached),
+        ("SwapCached", 0),
+        ("Shmem", 0),
+        ("SReclaimable", 0),
+        ("SwapTotal", 0),
+        ("SwapFree", 0),
+    ]
+    .iter()
+    .map(|(name, kb)| format!("{:<16}{:>8} kB\n", format!("{}:", name), kb))
+    .collect()
+}
+
+/// `/proc/uptime`: the seconds since boot and the idle seconds, which are
+/// not accounted.
+fn uptime() -> String {
+    let now = axhal::time::current_time();
+    format!("{}.{:02} 0.00\n", now.as_secs(), now.subsec_millis() / 10)
+}
+
+/// `/proc/cpuinfo`, one paragraph per CPU.
+fn cpuinfo() -> String {
+    (0..axconfig::SMP)
+        .map(|cpu| {
+            let mut info = format!("processor\t: {}\n", cpu);
+            if cfg!(target_arch = "riscv64") {
+                info += &format!("hart\t\t: {}\nisa\t\t: rv64imafdc\nmmu\t\t: sv39\n", cpu);
+            } else if cfg!(target_arch = "x86_64") {
+                info += "model name\t: x86_64\n";
+            } else if cfg!(target_arch = "aarch64") {
+                info += "model name\t: aarch64\n";
+            }
+            info + "\n"
+        })
+        .collect()
+}
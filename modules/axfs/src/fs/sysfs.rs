@@ -0,0 +1,403 @@
+//! A sysfs showing the devices found at boot and the kernel tunables.
+//!
+//! There is one tree, shared by all mounts of sysfs. The block devices and
+//! the tunables of the filesystem are added here. Other modules add their
+//! devices and parameters with [`add_sysfs_node`], e.g.
+//! `class/net/eth0/address` or `module/axtask/parameters/scheduler`.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::{format, vec::Vec};
+use axfs_vfs::{impl_vfs_dir_default, impl_vfs_non_dir_default};
+use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType};
+use axfs_vfs::{VfsError, VfsOps, VfsResult};
+use axsync::Mutex;
+
+use crate::dev::BlockDevNode;
+
+/// The root directory of sysfs, created on first use.
+static SYSFS_ROOT: Mutex<Option<Arc<SysDir>>> = Mutex::new(None);
+
+/// The size of a sector, the unit of the block device sizes.
+const SECTOR_SIZE: u64 = 512;
+
+type ShowFn = Box<dyn Fn() -> String + Send + Sync>;
+type StoreFn = Box<dyn Fn(&str) -> VfsResult + Send + Sync>;
+
+/// An attribute file, which shows a value on one line and optionally takes
+/// a new one.
+pub struct SysAttr {
+    show: ShowFn,
+    store: Option<StoreFn>,
+}
+
+impl SysAttr {
+    /// Creates a read-only attribute showing the value returned by `show`.
+    pub fn read_only(show: impl Fn() -> String + Send + Sync + 'static) -> Self {
+        Self {
+            show: Box::new(show),
+            store: None,
+        }
+    }
+
+    /// Creates a writable attribute. The value written, without the
+    /// trailing newline, is passed to `store`, which fails with
+    /// `InvalidInput` if it is not valid.
+    pub fn read_write(
+        show: impl Fn() -> String + Send + Sync + 'static,
+        store: impl Fn(&str) -> VfsResult + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            show: Box::new(show),
+            store: Some(Box::new(store)),
+        }
+    }
+}
+
+impl VfsNodeOps for SysAttr {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mode = if self.store.is_some() { 0o644 } else { 0o444 };
+        let perm = VfsNodePerm::from_bits_truncate(mode);
+        // Linux reports the size of a page for every attribute
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::File, 4096, 0))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = (self.show)() + "\n";
+        let bytes = content.as_bytes();
+        let start = bytes.len().min(offset as usize);
+        let len = buf.len().min(bytes.len() - start);
+        buf[..len].copy_from_slice(&bytes[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let store = self.store.as_ref().ok_or(VfsError::PermissionDenied)?;
+        let value = core::str::from_utf8(buf).map_err(|_| VfsError::InvalidInput)?;
+        store(value.trim_end_matches('\n'))?;
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        // opened with `O_TRUNC` by the shell before writing
+        match self.store {
+            Some(_) => Ok(()),
+            None => Err(VfsError::PermissionDenied),
+        }
+    }
+
+    impl_vfs_non_dir_default! {}
+}
+
+/// A symbolic link, e.g. from `class/block/vda` to `../../block/vda`.
+pub struct SysLink {
+    target: String,
+}
+
+impl SysLink {
+    /// Creates a symbolic link to `target`.
+    pub fn new(target: &str) -> Self {
+        Self {
+            target: target.into(),
+        }
+    }
+}
+
+impl VfsNodeOps for SysLink {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let perm = VfsNodePerm::from_bits_truncate(0o777);
+        let size = self.target.len() as u64;
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::SymLink, size, 0))
+    }
+
+    fn readlink(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let len = buf.len().min(self.target.len());
+        buf[..len].copy_from_slice(&self.target.as_bytes()[..len]);
+        Ok(len)
+    }
+
+    impl_vfs_non_dir_default! {}
+}
+
+/// A directory of sysfs. Its entries are added by the kernel, never by
+/// users.
+pub struct SysDir {
+    entries: Mutex<BTreeMap<String, VfsNodeRef>>,
+}
+
+impl SysDir {
+    fn new() -> Self {
+        Self {
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Adds `node` at `path` relative to this directory, creating the
+    /// missing directories on the way.
+    fn add(&self, path: &str, node: VfsNodeRef) -> VfsResult {
+        let path = path.trim_matches('/');
+        let (name, rest) = match path.split_once('/') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (path, None),
+        };
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        let mut entries = self.entries.lock();
+        let Some(rest) = rest else {
+            if entries.contains_key(name) {
+                return Err(VfsError::AlreadyExists);
+            }
+            entries.insert(name.into(), node);
+            return Ok(());
+        };
+        let dir = entries
+            .entry(name.into())
+            .or_insert_with(|| Arc::new(SysDir::new()))
+            .clone();
+        drop(entries);
+        match dir.as_any().downcast_ref::<SysDir>() {
+            Some(dir) => dir.add(rest, node),
+            None => Err(VfsError::NotADirectory),
+        }
+    }
+}
+
+impl VfsNodeOps for SysDir {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let perm = VfsNodePerm::from_bits_truncate(0o755);
+        Ok(VfsNodeAttr::new(perm, VfsNodeType::Dir, 0, 0))
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        let path = path.trim_start_matches('/');
+        let (name, rest) = match path.split_once('/') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (path, None),
+        };
+        let node = match name {
+            "" | "." => self.clone() as VfsNodeRef,
+            _ => self
+                .entries
+                .lock()
+                .get(name)
+                .cloned()
+                .ok_or(VfsError::NotFound)?,
+        };
+        match rest {
+            Some(rest) if !rest.is_empty() => node.lookup(rest),
+            _ => Ok(node),
+        }
+    }
+
+    fn create(&self, _path: &str, _ty: VfsNodeType) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn remove(&self, _path: &str) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        let entries: Vec<(String, VfsNodeRef)> = self
+            .entries
+            .lock()
+            .iter()
+            .map(|(name, node)| (name.clone(), node.clone()))
+            .collect();
+        let dots = [".", ".."].map(|name| (name, VfsNodeType::Dir));
+        let all = dots.into_iter().chain(entries.iter().map(|(name, node)| {
+            let ty = node
+                .get_attr()
+                .map_or(VfsNodeType::File, |attr| attr.file_type());
+            (name.as_str(), ty)
+        }));
+        let mut n = 0;
+        for ((name, ty), ent) in all.skip(start_idx).zip(dirents.iter_mut()) {
+            *ent = VfsDirEntry::new(name, ty);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    impl_vfs_dir_default! {}
+}
+
+/// The sysfs, mounted on `/sys`.
+pub struct SysFileSystem {
+    root: Arc<SysDir>,
+}
+
+impl SysFileSystem {
+    /// Opens the sysfs tree.
+    pub fn new() -> Self {
+        Self { root: root() }
+    }
+}
+
+impl Default for SysFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VfsOps for SysFileSystem {
+    fn root_dir(&self) -> VfsNodeRef {
+        self.root.clone()
+    }
+}
+
+/// Adds `node` to sysfs at `path`, relative to its root, creating the
+/// missing directories. Fails with `AlreadyExists` if `path` exists.
+pub fn add_sysfs_node(path: &str, node: impl VfsNodeOps + 'static) -> VfsResult {
+    root().add(path, Arc::new(node))
+}
+
+/// Returns the root directory, creating the tree with the tunables of the
+/// filesystem on first use.
+fn root() -> Arc<SysDir> {
+    let mut root = SYSFS_ROOT.lock();
+    root.get_or_insert_with(|| {
+        let root = Arc::new(SysDir::new());
+        if let Err(e) = add_tunables(&root) {
+            warn!("failed to create sysfs: {:?}", e);
+        }
+        root
+    })
+    .clone()
+}
+
+fn add_attr(dir: &SysDir, path: &str, attr: SysAttr) -> VfsResult {
+    dir.add(path, Arc::new(attr))
+}
+
+/// The system information and the tunables of the filesystem and the
+/// logger.
+fn add_tunables(root: &SysDir) -> VfsResult {
+    add_attr(
+        root,
+        "kernel/mm/transparent_hugepage/enabled",
+        SysAttr::read_only(|| "always madvise [never]".into()),
+    )?;
+    add_attr(
+        root,
+        "devices/system/clocksource/clocksource0/current_clocksource",
+        SysAttr::read_only(|| clocksource().into()),
+    )?;
+    for name in ["online", "possible", "present"] {
+        let path = format!("devices/system/cpu/{}", name);
+        add_attr(root, &path, SysAttr::read_only(cpu_range))?;
+    }
+    add_attr(
+        root,
+        "module/axfs/parameters/page_cache_max_pages",
+        SysAttr::read_write(
+            || crate::page_cache::max_pages().to_string(),
+            |value| {
+                let max = value.parse().map_err(|_| VfsError::InvalidInput)?;
+                crate::page_cache::set_max_pages(max);
+                Ok(())
+            },
+        ),
+    )?;
+    add_attr(
+        root,
+        "module/axfs/parameters/page_cache_pages",
+        SysAttr::read_only(|| crate::page_cache::cached_pages().to_string()),
+    )?;
+    add_attr(
+        root,
+        "module/axlog/parameters/level",
+        SysAttr::read_write(
+            || log::max_level().as_str().to_ascii_lowercase(),
+            |value| {
+                let level = value.parse().map_err(|_| VfsError::InvalidInput)?;
+                log::set_max_level(level);
+                Ok(())
+            },
+        ),
+    )
+}
+
+/// The name Linux gives to the timer used as the clock source.
+fn clocksource() -> &'static str {
+    if cfg!(target_arch = "x86_64") {
+        "tsc"
+    } else if cfg!(target_arch = "aarch64") {
+        "arch_sys_counter"
+    } else {
+        "riscv_clocksource"
+    }
+}
+
+/// The CPUs, as a range list like `0-3`.
+fn cpu_range() -> String {
+    match axconfig::SMP {
+        1 => "0".into(),
+        n => format!("0-{}", n - 1),
+    }
+}
+
+/// Adds the block devices found at boot to `block` and `class/block`, the
+/// partitions in the directory of their disk, and the loop devices.
+pub(crate) fn add_block_devices() -> VfsResult {
+    let root = root();
+    let devices = BlockDevNode::all();
+    for dev in &devices {
+        let dir = match dev.partition() {
+            None => format!("block/{}", dev.name()),
+            Some(_) => {
+                let Some(disk) = devices
+                    .iter()
+                    .find(|disk| disk.partition().is_none() && disk.same_disk(dev))
+                else {
+                    continue;
+                };
+                format!("block/{}/{}", disk.name(), dev.name())
+            }
+        };
+        let sectors = dev.size() / SECTOR_SIZE;
+        add_attr(&root, &format!("{}/size", dir), value(sectors))?;
+        add_attr(&root, &format!("{}/ro", dir), value(0))?;
+        match dev.partition() {
+            None => {
+                add_attr(&root, &format!("{}/removable", dir), value(0))?;
+                for name in ["logical_block_size", "hw_sector_size"] {
+                    let path = format!("{}/queue/{}", dir, name);
+                    add_attr(&root, &path, value(SECTOR_SIZE))?;
+                }
+            }
+            Some(number) => {
+                let start = dev.start() / SECTOR_SIZE;
+                add_attr(&root, &format!("{}/start", dir), value(start))?;
+                add_attr(&root, &format!("{}/partition", dir), value(number))?;
+            }
+        }
+        let link = SysLink::new(&format!("../../{}", dir));
+        root.add(&format!("class/block/{}", dev.name()), Arc::new(link))?;
+    }
+    for (number, name) in crate::loopdev::LOOP_DEVICE_NAMES.iter().enumerate() {
+        let size = SysAttr::read_only(move || {
+            let node = crate::loopdev::LoopNode(number);
+            let size = node.get_attr().map_or(0, |attr| attr.size());
+            (size / SECTOR_SIZE).to_string()
+        });
+        let ro = SysAttr::read_only(move || {
+            let ro = crate::loopdev::status(number).is_ok_and(|status| status.read_only);
+            (ro as u8).to_string()
+        });
+        add_attr(&root, &format!("block/{}/size", name), size)?;
+        add_attr(&root, &format!("block/{}/ro", name), ro)?;
+        let link = SysLink::new(&format!("../../block/{}", name));
+        root.add(&format!("class/block/{}", name), Arc::new(link))?;
+    }
+    Ok(())
+}
+
+/// A read-only attribute with a fixed value.
+fn value(value: impl ToString) -> SysAttr {
+    let value = value.to_string();
+    SysAttr::read_only(move || value.clone())
+}
@@ -0,0 +1,605 @@
+//! The kernel side of FUSE, filesystems implemented by user-space daemons.
+//!
+//! Opening `/dev/fuse` creates a [`FuseConnection`]. The daemon mounts it
+//! with [`api::mount_fuse`](crate::api::mount_fuse), given the file
+//! descriptor in the `fd=` mount option as with Linux, then reads the
+//! requests of the filesystem from the device and writes back the replies.
+//! The messages are those of the Linux FUSE protocol 7.31: a request starts
+//! with a `fuse_in_header`, and its reply with a `fuse_out_header` carrying
+//! the same `unique`.
+//!
+//! The kernel sends `INIT` right after the mount, without waiting for the
+//! reply, since the daemon only starts reading once `mount` has returned.
+//! The other requests wait for the connection to be initialized.
+//!
+//! A process waiting for a reply is blocked until the daemon answers, or
+//! gives up with `EINTR` when a signal is pending. The daemon's late reply
+//! is then refused with `ENOENT`, as with Linux. The connection is aborted when the filesystem is unmounted or the daemon
+//! closes the device, failing the pending and later requests with
+//! `ENOTCONN`.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::{ax_err, AxError, AxResult, LinuxError};
+use axfs_vfs::{DeviceId, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType, VfsResult};
+use axsync::Mutex;
+use axtask::WaitQueue;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+
+/// The device number of `/dev/fuse`, the misc device `FUSE_MINOR`.
+pub const FUSE_DEVICE: DeviceId = DeviceId::new(10, 229);
+
+/// The version of the protocol spoken by the kernel.
+const KERNEL_VERSION: u32 = 7;
+const KERNEL_MINOR_VERSION: u32 = 31;
+
+/// The smallest buffer the daemon may read the requests into, as in Linux.
+const MIN_READ_BUFFER: usize = 8192;
+
+/// The largest `max_write` accepted from the daemon.
+const MAX_WRITE: u32 = 0x2_0000;
+
+/// The request opcodes.
+pub(crate) mod opcode {
+    pub const LOOKUP: u32 = 1;
+    pub const FORGET: u32 = 2;
+    pub const GETATTR: u32 = 3;
+    pub const SETATTR: u32 = 4;
+    pub const READLINK: u32 = 5;
+    pub const MKDIR: u32 = 9;
+    pub const UNLINK: u32 = 10;
+    pub const RMDIR: u32 = 11;
+    pub const OPEN: u32 = 14;
+    pub const READ: u32 = 15;
+    pub const WRITE: u32 = 16;
+    pub const RELEASE: u32 = 18;
+    pub const INIT: u32 = 26;
+    pub const OPENDIR: u32 = 27;
+    pub const READDIR: u32 = 28;
+    pub const RELEASEDIR: u32 = 29;
+    pub const CREATE: u32 = 35;
+}
+
+/// The structures of the protocol, sent and received as their bytes. They
+/// are `#[repr(C)]` with explicit padding, so that all their bytes are
+/// initialized.
+pub(crate) trait Message: Copy + Default {
+    fn as_bytes(&self) -> &[u8] {
+        // SAFETY: the structure has no uninitialized padding
+        unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }
+    }
+
+    /// Reads the structure from the start of `buf`, failing with `EIO` if
+    /// `buf` is too short.
+    fn parse(buf: &[u8]) -> VfsResult<Self> {
+        if buf.len() < size_of::<Self>() {
+            return ax_err!(Io, "FUSE reply too short");
+        }
+        Ok(Self::parse_prefix(buf))
+    }
+
+    /// Reads the structure from the start of `buf`, the missing fields being
+    /// zero if `buf` is shorter, as for the replies of older daemons.
+    fn parse_prefix(buf: &[u8]) -> Self {
+        let mut msg = Self::default();
+        let len = buf.len().min(size_of::<Self>());
+        // SAFETY: any bytes are a valid value of the structure
+        unsafe {
+            core::ptr::copy_nonoverlapping(buf.as_ptr(), &mut msg as *mut Self as *mut u8, len)
+        };
+        msg
+    }
+}
+
+macro_rules! messages {
+    ($($(#[$attr:meta])* struct $name:ident { $($field:ident: $ty:ty,)* })*) => {
+        $(
+            $(#[$attr])*
+            #[repr(C)]
+            #[derive(Clone, Copy, Debug, Default)]
+            pub(crate) struct $name {
+                $(pub $field: $ty,)*
+            }
+
+            impl Message for $name {}
+        )*
+    };
+}
+
+messages! {
+    struct InHeader {
+        len: u32,
+        opcode: u32,
+        unique: u64,
+        nodeid: u64,
+        uid: u32,
+        gid: u32,
+        pid: u32,
+        padding: u32,
+    }
+
+    struct OutHeader {
+        len: u32,
+        error: i32,
+        unique: u64,
+    }
+
+    struct InitIn {
+        major: u32,
+        minor: u32,
+        max_readahead: u32,
+        flags: u32,
+    }
+
+    struct InitOut {
+        major: u32,
+        minor: u32,
+        max_readahead: u32,
+        flags: u32,
+        max_background: u16,
+        congestion_threshold: u16,
+        max_write: u32,
+        time_gran: u32,
+        max_pages: u16,
+        map_alignment: u16,
+        unused: [u32; 8],
+    }
+
+    /// The attributes of a node, `struct fuse_attr`.
+    struct Attr {
+        ino: u64,
+        size: u64,
+        blocks: u64,
+        atime: u64,
+        mtime: u64,
+        ctime: u64,
+        atimensec: u32,
+        mtimensec: u32,
+        ctimensec: u32,
+        mode: u32,
+        nlink: u32,
+        uid: u32,
+        gid: u32,
+        rdev: u32,
+        blksize: u32,
+        padding: u32,
+    }
+
+    struct EntryOut {
+        nodeid: u64,
+        generation: u64,
+        entry_valid: u64,
+        attr_valid: u64,
+        entry_valid_nsec: u32,
+        attr_valid_nsec: u32,
+        attr: Attr,
+    }
+
+    struct ForgetIn {
+        nlookup: u64,
+    }
+
+    struct GetattrIn {
+        getattr_flags: u32,
+        dummy: u32,
+        fh: u64,
+    }
+
+    struct AttrOut {
+        attr_valid: u64,
+        attr_valid_nsec: u32,
+        dummy: u32,
+        attr: Attr,
+    }
+
+    struct SetattrIn {
+        valid: u32,
+        padding: u32,
+        fh: u64,
+        size: u64,
+        lock_owner: u64,
+        atime: u64,
+        mtime: u64,
+        ctime: u64,
+        atimensec: u32,
+        mtimensec: u32,
+        ctimensec: u32,
+        mode: u32,
+        unused4: u32,
+        uid: u32,
+        gid: u32,
+        unused5: u32,
+    }
+
+    struct MkdirIn {
+        mode: u32,
+        umask: u32,
+    }
+
+    struct OpenIn {
+        flags: u32,
+        unused: u32,
+    }
+
+    struct OpenOut {
+        fh: u64,
+        open_flags: u32,
+        padding: u32,
+    }
+
+    struct CreateIn {
+        flags: u32,
+        mode: u32,
+        umask: u32,
+        padding: u32,
+    }
+
+    struct ReleaseIn {
+        fh: u64,
+        flags: u32,
+        release_flags: u32,
+        lock_owner: u64,
+    }
+
+    /// The request of `READ` and `READDIR`.
+    struct ReadIn {
+        fh: u64,
+        offset: u64,
+        size: u32,
+        read_flags: u32,
+        lock_owner: u64,
+        flags: u32,
+        padding: u32,
+    }
+
+    struct WriteIn {
+        fh: u64,
+        offset: u64,
+        size: u32,
+        write_flags: u32,
+        lock_owner: u64,
+        flags: u32,
+        padding: u32,
+    }
+
+    struct WriteOut {
+        size: u32,
+        padding: u32,
+    }
+
+    /// The fixed part of an entry in the reply of `READDIR`, followed by the
+    /// name padded to 8 bytes.
+    struct Dirent {
+        ino: u64,
+        off: u64,
+        namelen: u32,
+        ty: u32,
+    }
+}
+
+/// The `valid` bit of [`SetattrIn`] for the size.
+pub(crate) const FATTR_SIZE: u32 = 1 << 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Opened, not mounted yet.
+    Unmounted,
+    /// Mounted, waiting for the reply to `INIT`.
+    Initializing,
+    Ready,
+    Aborted,
+}
+
+struct ConnectionInner {
+    state: State,
+    /// The requests not read by the daemon yet.
+    queue: VecDeque<Vec<u8>>,
+    /// The requests waiting for a reply, by `unique`, with the reply once it
+    /// has arrived.
+    waiting: BTreeMap<u64, Option<VfsResult<Vec<u8>>>>,
+    next_unique: u64,
+    /// The `unique` of the `INIT` request.
+    init_unique: u64,
+    max_write: u32,
+}
+
+/// How often a process waiting for the daemon checks for signals.
+const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A connection between a FUSE filesystem and its daemon, created by
+/// opening `/dev/fuse`.
+pub struct FuseConnection {
+    inner: Mutex<ConnectionInner>,
+    /// The processes waiting for the connection to be initialized or for a
+    /// reply.
+    wait: WaitQueue,
+    /// Counts the changes the waiting processes are woken for, so that they
+    /// can wait without holding `inner`.
+    events: AtomicUsize,
+}
+
+impl FuseConnection {
+    /// Creates a connection, to be mounted.
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            inner: Mutex::new(ConnectionInner {
+                state: State::Unmounted,
+                queue: VecDeque::new(),
+                waiting: BTreeMap::new(),
+                next_unique: 1,
+                init_unique: 0,
+                max_write: 4096,
+            }),
+            wait: WaitQueue::new(),
+            events: AtomicUsize::new(0),
+        })
+    }
+
+    /// Returns whether a request is waiting to be read.
+    pub fn has_requests(&self) -> bool {
+        !self.inner.lock().queue.is_empty()
+    }
+
+    /// Reads the next request into `buf`, which must hold at least 8192
+    /// bytes. Returns the size of the request.
+    ///
+    /// Fails with `EAGAIN` if no request is queued, with `EPERM` before the
+    /// connection is mounted and with `ENODEV` once it is aborted.
+    pub fn read_request(&self, buf: &mut [u8]) -> AxResult<usize> {
+        let mut inner = self.inner.lock();
+        match inner.state {
+            State::Unmounted => return ax_err!(OperationNotPermitted),
+            State::Aborted => return ax_err!(NoSuchDevice),
+            _ => {}
+        }
+        if buf.len() < MIN_READ_BUFFER {
+            return ax_err!(InvalidInput);
+        }
+        let Some(request) = inner.queue.pop_front() else {
+            return ax_err!(WouldBlock);
+        };
+        if request.len() > buf.len() {
+            // fail the request rather than have the daemon loop on it
+            let unique = InHeader::parse_prefix(&request).unique;
+            if let Some(reply) = inner.waiting.get_mut(&unique) {
+                *reply = Some(ax_err!(Io));
+            }
+            drop(inner);
+            self.wake();
+            return ax_err!(InvalidInput);
+        }
+        buf[..request.len()].copy_from_slice(&request);
+        Ok(request.len())
+    }
+
+    /// Writes the reply `buf` to a request read before. Returns the size of
+    /// the reply.
+    ///
+    /// Fails with `EINVAL` if the reply is malformed, with `ENOENT` if no
+    /// request is waiting for it and with `ENODEV` once the connection is
+    /// aborted.
+    pub fn write_reply(&self, buf: &[u8]) -> AxResult<usize> {
+        let header = OutHeader::parse(buf).map_err(|_| AxError::InvalidInput)?;
+        if header.len as usize != buf.len()
+            || header.unique == 0
+            || header.error > 0
+            || header.error < -4095
+            || (header.error < 0 && buf.len() != size_of::<OutHeader>())
+        {
+            return ax_err!(InvalidInput);
+        }
+        let payload = &buf[size_of::<OutHeader>()..];
+        let reply = if header.error < 0 {
+            Err(error_from_errno(-header.error))
+        } else {
+            Ok(payload.to_vec())
+        };
+
+        let mut inner = self.inner.lock();
+        if inner.state == State::Aborted {
+            return ax_err!(NoSuchDevice);
+        }
+        if header.unique == inner.init_unique && inner.state == State::Initializing {
+            inner.init_unique = 0;
+            match reply.map(|payload| InitOut::parse_prefix(&payload)) {
+                Ok(init) if init.major == KERNEL_VERSION && init.minor >= 1 => {
+                    inner.max_write = init.max_write.clamp(4096, MAX_WRITE);
+                    inner.state = State::Ready;
+                }
+                _ => {
+                    warn!(
+                        "FUSE daemon refused the protocol {}.{}",
+                        KERNEL_VERSION, KERNEL_MINOR_VERSION
+                    );
+                    inner.abort();
+                }
+            }
+            drop(inner);
+            self.wake();
+            return Ok(buf.len());
+        }
+        match inner.waiting.get_mut(&header.unique) {
+            Some(slot @ None) => *slot = Some(reply),
+            _ => return ax_err!(NotFound),
+        }
+        drop(inner);
+        self.wake();
+        Ok(buf.len())
+    }
+
+    /// Aborts the connection: the daemon can no longer read requests, and
+    /// the requests fail with `ENOTCONN`.
+    pub fn abort(&self) {
+        self.inner.lock().abort();
+        self.wake();
+    }
+
+    /// Starts using the connection for a mounted filesystem, sending `INIT`.
+    /// Fails with `EINVAL` if it is already mounted or aborted.
+    pub(crate) fn mount(&self) -> VfsResult {
+        let mut inner = self.inner.lock();
+        if inner.state != State::Unmounted {
+            return ax_err!(InvalidInput, "FUSE connection already used");
+        }
+        let init = InitIn {
+            major: KERNEL_VERSION,
+            minor: KERNEL_MINOR_VERSION,
+            ..Default::default()
+        };
+        inner.init_unique = inner.queue_request(opcode::INIT, 0, &[init.as_bytes()]);
+        inner.state = State::Initializing;
+        Ok(())
+    }
+
+    /// The largest amount of data the daemon accepts in one `WRITE`.
+    pub(crate) fn max_write(&self) -> usize {
+        self.inner.lock().max_write as usize
+    }
+
+    /// Sends a request on the node `nodeid`, made of the parts `args`, and
+    /// waits for the reply. Returns the payload of the reply.
+    ///
+    /// Fails with `EINTR` if a signal arrives first.
+    pub(crate) fn request(&self, opcode: u32, nodeid: u64, args: &[&[u8]]) -> VfsResult<Vec<u8>> {
+        let unique = self.wait_for(|inner| match inner.state {
+            State::Ready => {
+                let unique = inner.queue_request(opcode, nodeid, args);
+                inner.waiting.insert(unique, None);
+                Some(Ok(unique))
+            }
+            State::Aborted => Some(ax_err!(NotConnected)),
+            _ => None,
+        })??;
+        let reply = self.wait_for(|inner| match inner.waiting.get(&unique) {
+            Some(Some(_)) => inner.waiting.remove(&unique).flatten(),
+            _ => None,
+        });
+        reply.unwrap_or_else(|e| match self.inner.lock().waiting.remove(&unique) {
+            // answered right before giving up
+            Some(Some(reply)) => reply,
+            _ => Err(e),
+        })
+    }
+
+    /// Waits until `f` returns `Some` when called on the locked state of the
+    /// connection, or fails with `EINTR` if a signal is pending first.
+    fn wait_for<T>(&self, mut f: impl FnMut(&mut ConnectionInner) -> Option<T>) -> VfsResult<T> {
+        loop {
+            let events = self.events.load(Ordering::Acquire);
+            if let Some(res) = f(&mut *self.inner.lock()) {
+                return Ok(res);
+            }
+            if crate::signal::signal_pending() {
+                return ax_err!(Interrupted);
+            }
+            // the signals don't wake the wait queue, check them from time to time
+            self.wait.wait_timeout_until(SIGNAL_CHECK_INTERVAL, || {
+                self.events.load(Ordering::Acquire) != events
+            });
+        }
+    }
+
+    /// Wakes the processes waiting for a reply or for the initialization,
+    /// after the state changed.
+    fn wake(&self) {
+        self.events.fetch_add(1, Ordering::Release);
+        self.wait.notify_all(false);
+    }
+
+    /// Tells the daemon that the kernel dropped `nlookup` references to the
+    /// node `nodeid`. There is no reply.
+    pub(crate) fn forget(&self, nodeid: u64, nlookup: u64) {
+        let mut inner = self.inner.lock();
+        if inner.state == State::Ready {
+            let forget = ForgetIn { nlookup };
+            inner.queue_request(opcode::FORGET, nodeid, &[forget.as_bytes()]);
+        }
+    }
+}
+
+impl ConnectionInner {
+    /// Queues a request for the daemon, returning its `unique`.
+    fn queue_request(&mut self, opcode: u32, nodeid: u64, args: &[&[u8]]) -> u64 {
+        let unique = self.next_unique;
+        self.next_unique += 1;
+        let len = size_of::<InHeader>() + args.iter().map(|arg| arg.len()).sum::<usize>();
+        let header = InHeader {
+            len: len as u32,
+            opcode,
+            unique,
+            nodeid,
+            ..Default::default()
+        };
+        let mut request = Vec::with_capacity(len);
+        request.extend_from_slice(header.as_bytes());
+        for arg in args {
+            request.extend_from_slice(arg);
+        }
+        self.queue.push_back(request);
+        unique
+    }
+
+    fn abort(&mut self) {
+        self.state = State::Aborted;
+        self.queue.clear();
+        for reply in self.waiting.values_mut().filter(|reply| reply.is_none()) {
+            *reply = Some(ax_err!(NotConnected));
+        }
+    }
+}
+
+/// Converts the `errno` of a reply to an error.
+fn error_from_errno(errno: i32) -> AxError {
+    use LinuxError::*;
+    match LinuxError::try_from(errno) {
+        Ok(EPERM) => AxError::OperationNotPermitted,
+        Ok(ENOENT) => AxError::NotFound,
+        Ok(EINTR) => AxError::Interrupted,
+        Ok(ENXIO) => AxError::NoDeviceOrAddress,
+        Ok(EAGAIN) => AxError::WouldBlock,
+        Ok(ENOMEM) => AxError::NoMemory,
+        Ok(EACCES) => AxError::PermissionDenied,
+        Ok(EFAULT) => AxError::BadAddress,
+        Ok(EBUSY) => AxError::ResourceBusy,
+        Ok(EEXIST) => AxError::AlreadyExists,
+        Ok(EXDEV) => AxError::CrossesDevices,
+        Ok(ENODEV) => AxError::NoSuchDevice,
+        Ok(ENOTDIR) => AxError::NotADirectory,
+        Ok(EISDIR) => AxError::IsADirectory,
+        Ok(EINVAL | EBADF | ENAMETOOLONG) => AxError::InvalidInput,
+        Ok(ENOTTY) => AxError::NotATty,
+        Ok(ENOSPC | EDQUOT) => AxError::StorageFull,
+        Ok(EROFS) => AxError::ReadOnlyFilesystem,
+        Ok(EMLINK) => AxError::TooManyLinks,
+        Ok(EDEADLK) => AxError::Deadlock,
+        Ok(ENOSYS | EOPNOTSUPP) => AxError::Unsupported,
+        Ok(ENOTEMPTY) => AxError::DirectoryNotEmpty,
+        Ok(ELOOP) => AxError::FilesystemLoop,
+        Ok(ETIME | ETIMEDOUT) => AxError::Timeout,
+        Ok(ENOTCONN) => AxError::NotConnected,
+        Ok(ENOLCK) => AxError::NoLocksAvailable,
+        _ => AxError::Io,
+    }
+}
+
+/// The node of `/dev/fuse` in devtmpfs. Opening it creates a connection, see
+/// [`FuseConnection`].
+pub(crate) struct FuseDevNode;
+
+impl VfsNodeOps for FuseDevNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o666),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+        );
+        attr.set_rdev(FUSE_DEVICE);
+        Ok(attr)
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
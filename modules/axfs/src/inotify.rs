@@ -0,0 +1,535 @@
+//! File change notification, as the inotify API of Linux.
+//!
+//! The events are raised by the path-based operations of this crate and by
+//! the opened files, whatever filesystem the files are on. Watches are kept
+//! by path: the canonical path of the file when the watch was added, kept
+//! up to date when the file or one of its parents is renamed. An event on a
+//! file is reported to the watches on the file itself and, with the name of
+//! the file, to the watches on its parent directory.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axerrno::{ax_err, AxResult};
+use axsync::Mutex;
+use bitflags::bitflags;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use crate::namei::LookupFlags;
+
+bitflags! {
+    /// The events of inotify, and the flags of the watches.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct InotifyMask: u32 {
+        /// The file was read.
+        const ACCESS = 0x1;
+        /// The file was written or truncated.
+        const MODIFY = 0x2;
+        /// The permissions, owner, timestamps or link count changed.
+        const ATTRIB = 0x4;
+        /// A file opened for writing was closed.
+        const CLOSE_WRITE = 0x8;
+        /// A file not opened for writing was closed.
+        const CLOSE_NOWRITE = 0x10;
+        /// The file was opened.
+        const OPEN = 0x20;
+        /// A file was moved out of the watched directory.
+        const MOVED_FROM = 0x40;
+        /// A file was moved into the watched directory.
+        const MOVED_TO = 0x80;
+        /// A file was created in the watched directory.
+        const CREATE = 0x100;
+        /// A file was deleted from the watched directory.
+        const DELETE = 0x200;
+        /// The watched file was deleted.
+        const DELETE_SELF = 0x400;
+        /// The watched file was moved.
+        const MOVE_SELF = 0x800;
+        /// Events were lost because the queue was full.
+        const Q_OVERFLOW = 0x4000;
+        /// The watch was removed.
+        const IGNORED = 0x8000;
+        /// Only watch the path if it is a directory.
+        const ONLYDIR = 0x0100_0000;
+        /// Do not follow a symbolic link in the last component of the path.
+        const DONT_FOLLOW = 0x0200_0000;
+        /// Do not report the events of the children after they are unlinked.
+        const EXCL_UNLINK = 0x0400_0000;
+        /// Fail if the path is already watched.
+        const MASK_CREATE = 0x1000_0000;
+        /// Add the events to the watch of the path instead of replacing them.
+        const MASK_ADD = 0x2000_0000;
+        /// The subject of the event is a directory.
+        const ISDIR = 0x4000_0000;
+        /// Remove the watch after its first event.
+        const ONESHOT = 0x8000_0000;
+
+        /// Both close events.
+        const CLOSE = Self::CLOSE_WRITE.bits() | Self::CLOSE_NOWRITE.bits();
+        /// Both move events.
+        const MOVE = Self::MOVED_FROM.bits() | Self::MOVED_TO.bits();
+        /// All the events a watch can ask for.
+        const ALL_EVENTS = 0xfff;
+    }
+}
+
+/// The maximum number of queued events of an instance, as the default
+/// `max_queued_events` of Linux. Further events are dropped and reported
+/// with a single [`InotifyMask::Q_OVERFLOW`] event.
+pub const MAX_QUEUED_EVENTS: usize = 16384;
+
+/// The maximum number of watches of an instance, as the default
+/// `max_user_watches` of Linux.
+pub const MAX_WATCHES: usize = 8192;
+
+/// The size of `struct inotify_event` without the name.
+const EVENT_HEADER_SIZE: usize = 16;
+
+/// The number of watches of all instances, to skip the work of raising an
+/// event when nobody watches.
+static WATCH_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The cookie relating the two events of the next rename.
+static NEXT_COOKIE: AtomicU32 = AtomicU32::new(1);
+
+/// All the instances, dropped ones pruned when the events are raised.
+static INSTANCES: Mutex<Vec<Weak<Inotify>>> = Mutex::new(Vec::new());
+
+/// A queued event.
+#[derive(Clone, PartialEq, Eq)]
+struct Event {
+    wd: i32,
+    mask: InotifyMask,
+    cookie: u32,
+    name: String,
+}
+
+impl Event {
+    /// The length of the name field, the name with its terminating NUL
+    /// padded like Linux does.
+    fn name_len(&self) -> usize {
+        if self.name.is_empty() {
+            0
+        } else {
+            (self.name.len() + 1).next_multiple_of(EVENT_HEADER_SIZE)
+        }
+    }
+
+    /// The size of the event as read, a `struct inotify_event`.
+    fn size(&self) -> usize {
+        EVENT_HEADER_SIZE + self.name_len()
+    }
+
+    /// Writes the event at the beginning of `buf`, which holds at least
+    /// [`size`](Self::size) bytes.
+    fn write_to(&self, buf: &mut [u8]) {
+        let name_len = self.name_len();
+        buf[0..4].copy_from_slice(&self.wd.to_ne_bytes());
+        buf[4..8].copy_from_slice(&self.mask.bits().to_ne_bytes());
+        buf[8..12].copy_from_slice(&self.cookie.to_ne_bytes());
+        buf[12..16].copy_from_slice(&(name_len as u32).to_ne_bytes());
+        let name = &mut buf[EVENT_HEADER_SIZE..EVENT_HEADER_SIZE + name_len];
+        name.fill(0);
+        name[..self.name.len()].copy_from_slice(self.name.as_bytes());
+    }
+}
+
+/// A watch of an instance.
+struct Watch {
+    /// The canonical path of the watched file.
+    path: String,
+    mask: InotifyMask,
+}
+
+struct InotifyInner {
+    watches: BTreeMap<i32, Watch>,
+    next_wd: i32,
+    events: VecDeque<Event>,
+}
+
+impl InotifyInner {
+    /// Queues `event`, unless it repeats the last queued one or the queue
+    /// is full.
+    fn push(&mut self, event: Event) {
+        if self.events.back() == Some(&event) {
+            return;
+        }
+        match self.events.len() {
+            n if n < MAX_QUEUED_EVENTS => self.events.push_back(event),
+            MAX_QUEUED_EVENTS => self.events.push_back(Event {
+                wd: -1,
+                mask: InotifyMask::Q_OVERFLOW,
+                cookie: 0,
+                name: String::new(),
+            }),
+            _ => {}
+        }
+    }
+
+    /// Removes the watch `wd`, queueing [`InotifyMask::IGNORED`].
+    fn remove_watch(&mut self, wd: i32) -> bool {
+        if self.watches.remove(&wd).is_none() {
+            return false;
+        }
+        WATCH_COUNT.fetch_sub(1, Ordering::Relaxed);
+        self.push(Event {
+            wd,
+            mask: InotifyMask::IGNORED,
+            cookie: 0,
+            name: String::new(),
+        });
+        true
+    }
+}
+
+/// An inotify instance, holding watches and the queue of their events.
+pub struct Inotify {
+    inner: Mutex<InotifyInner>,
+}
+
+impl Inotify {
+    /// Creates an instance without watches.
+    pub fn new() -> Arc<Self> {
+        let inotify = Arc::new(Self {
+            inner: Mutex::new(InotifyInner {
+                watches: BTreeMap::new(),
+                next_wd: 1,
+                events: VecDeque::new(),
+            }),
+        });
+        INSTANCES.lock().push(Arc::downgrade(&inotify));
+        inotify
+    }
+
+    /// Watches the file at `path` for the events in `mask`, which also
+    /// holds the flags of the watch. Returns the watch descriptor, the same
+    /// one if the file is already watched.
+    pub fn add_watch(&self, path: &str, mask: InotifyMask) -> AxResult<i32> {
+        let events = mask & InotifyMask::ALL_EVENTS;
+        if events.is_empty() || mask.contains(InotifyMask::MASK_ADD | InotifyMask::MASK_CREATE) {
+            return ax_err!(InvalidInput);
+        }
+        let mut flags = LookupFlags::empty();
+        if mask.contains(InotifyMask::DONT_FOLLOW) {
+            flags |= LookupFlags::NOFOLLOW;
+        }
+        if mask.contains(InotifyMask::ONLYDIR) {
+            flags |= LookupFlags::DIRECTORY;
+        }
+        let path = crate::root::resolve_path(None, path, flags)?.path;
+        let stored = mask & (InotifyMask::ALL_EVENTS | InotifyMask::ONESHOT);
+        let mut inner = self.inner.lock();
+        if let Some((&wd, watch)) = inner.watches.iter_mut().find(|(_, w)| w.path == path) {
+            if mask.contains(InotifyMask::MASK_CREATE) {
+                return ax_err!(AlreadyExists);
+            }
+            if mask.contains(InotifyMask::MASK_ADD) {
+                watch.mask |= stored;
+            } else {
+                watch.mask = stored;
+            }
+            return Ok(wd);
+        }
+        if inner.watches.len() >= MAX_WATCHES {
+            return ax_err!(StorageFull);
+        }
+        let wd = inner.next_wd;
+        inner.next_wd += 1;
+        inner.watches.insert(wd, Watch { path, mask: stored });
+        WATCH_COUNT.fetch_add(1, Ordering::Relaxed);
+        Ok(wd)
+    }
+
+    /// Removes the watch `wd`. Fails with `EINVAL` if there is no such
+    /// watch.
+    pub fn rm_watch(&self, wd: i32) -> AxResult {
+        if self.inner.lock().remove_watch(wd) {
+            Ok(())
+        } else {
+            ax_err!(InvalidInput)
+        }
+    }
+
+    /// Returns whether events are queued.
+    pub fn has_events(&self) -> bool {
+        !self.inner.lock().events.is_empty()
+    }
+
+    /// Returns the number of bytes the queued events take when read
+    /// (`FIONREAD`).
+    pub fn pending_bytes(&self) -> usize {
+        self.inner.lock().events.iter().map(Event::size).sum()
+    }
+
+    /// Reads the queued events that fit in `buf`, as `struct inotify_event`
+    /// records. Returns the number of bytes read.
+    ///
+    /// Fails with `EAGAIN` if no event is queued, and with `EINVAL` if
+    /// `buf` is too small for the first one.
+    pub fn read_events(&self, buf: &mut [u8]) -> AxResult<usize> {
+        let mut inner = self.inner.lock();
+        match inner.events.front() {
+            None => return ax_err!(WouldBlock),
+            Some(event) if event.size() > buf.len() => return ax_err!(InvalidInput),
+            Some(_) => {}
+        }
+        let mut len = 0;
+        while let Some(event) = inner.events.front() {
+            let size = event.size();
+            if len + size > buf.len() {
+                break;
+            }
+            event.write_to(&mut buf[len..]);
+            len += size;
+            inner.events.pop_front();
+        }
+        Ok(len)
+    }
+
+    /// Queues the event `mask` for the watches on `path` and, with the name
+    /// `name`, for those on its parent `parent`.
+    fn queue(&self, path: &str, parent: Option<(&str, &str)>, mask: InotifyMask, cookie: u32) {
+        let mut inner = self.inner.lock();
+        let mut oneshot = Vec::new();
+        let watches = inner
+            .watches
+            .iter()
+            .filter_map(|(&wd, watch)| {
+                let name = if watch.path == path {
+                    ""
+                } else {
+                    match parent {
+                        Some((parent, name)) if watch.path == parent => name,
+                        _ => return None,
+                    }
+                };
+                if !watch.mask.intersects(mask & InotifyMask::ALL_EVENTS) {
+                    return None;
+                }
+                if watch.mask.contains(InotifyMask::ONESHOT) {
+                    oneshot.push(wd);
+                }
+                Some((wd, String::from(name)))
+            })
+            .collect::<Vec<_>>();
+        for (wd, name) in watches {
+            inner.push(Event {
+                wd,
+                mask,
+                cookie,
+                name,
+            });
+        }
+        for wd in oneshot {
+            inner.remove_watch(wd);
+        }
+    }
+
+    /// Removes the watches on `path`, after queueing `mask` for them.
+    fn remove_watches_on(&self, path: &str, mask: InotifyMask) {
+        let mut inner = self.inner.lock();
+        let wds = inner
+            .watches
+            .iter()
+            .filter(|(_, watch)| watch.path == path)
+            .map(|(&wd, watch)| (wd, watch.mask))
+            .collect::<Vec<_>>();
+        for (wd, watch_mask) in wds {
+            if watch_mask.contains(mask) {
+                inner.push(Event {
+                    wd,
+                    mask,
+                    cookie: 0,
+                    name: String::new(),
+                });
+            }
+            inner.remove_watch(wd);
+        }
+    }
+
+    /// Updates the paths of the watches on `old` and below after it is
+    /// renamed to `new`.
+    fn rename_watches(&self, old: &str, new: &str) {
+        for watch in self.inner.lock().watches.values_mut() {
+            if watch.path == old {
+                watch.path = String::from(new);
+            } else if let Some(rest) = watch.path.strip_prefix(old) {
+                if rest.starts_with('/') || old == "/" {
+                    watch.path = String::from(new) + rest;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Inotify {
+    fn drop(&mut self) {
+        let count = self.inner.lock().watches.len();
+        WATCH_COUNT.fetch_sub(count, Ordering::Relaxed);
+    }
+}
+
+/// Returns whether any file is watched.
+fn watching() -> bool {
+    WATCH_COUNT.load(Ordering::Relaxed) > 0
+}
+
+/// The live instances.
+fn instances() -> Vec<Arc<Inotify>> {
+    let mut instances = INSTANCES.lock();
+    instances.retain(|inotify| inotify.strong_count() > 0);
+    instances.iter().filter_map(Weak::upgrade).collect()
+}
+
+/// Returns the canonical path of `path` as used by the watches, following a
+/// symbolic link in the last component if `follow`. The file itself may be
+/// missing, e.g. after it is deleted.
+fn canonical(path: &str, follow: bool) -> Option<String> {
+    let flags = if follow {
+        LookupFlags::CREATE
+    } else {
+        LookupFlags::CREATE | LookupFlags::NOFOLLOW
+    };
+    crate::root::resolve_path(None, path, flags)
+        .ok()
+        .map(|resolved| resolved.path)
+}
+
+/// Splits the canonical `path` into its parent directory and name.
+fn split_parent(path: &str) -> Option<(&str, &str)> {
+    match path.rsplit_once('/')? {
+        (_, "") => None,
+        ("", name) => Some(("/", name)),
+        (parent, name) => Some((parent, name)),
+    }
+}
+
+/// Raises `mask` for the file `path` and its parent directory, following a
+/// symbolic link in the last component if `follow`.
+fn raise(path: &str, mask: InotifyMask, follow: bool) {
+    if !watching() {
+        return;
+    }
+    let Some(path) = canonical(path, follow) else {
+        return;
+    };
+    for inotify in instances() {
+        inotify.queue(&path, split_parent(&path), mask, 0);
+    }
+}
+
+/// Raises `mask` for the file `path` and its parent directory.
+pub(crate) fn notify(path: &str, mask: InotifyMask) {
+    raise(path, mask, false);
+}
+
+/// Raises [`InotifyMask::CREATE`] for the directory holding the new file
+/// `path`.
+pub(crate) fn notify_create(path: &str, is_dir: bool) {
+    if !watching() {
+        return;
+    }
+    let Some(path) = canonical(path, false) else {
+        return;
+    };
+    let Some(parent) = split_parent(&path) else {
+        return;
+    };
+    let mask = InotifyMask::CREATE | isdir(is_dir);
+    for inotify in instances() {
+        inotify.queue("", Some(parent), mask, 0);
+    }
+}
+
+/// Raises [`InotifyMask::DELETE`] for the directory that held the deleted
+/// file `path`, and [`InotifyMask::DELETE_SELF`] for the file, whose
+/// watches are removed.
+pub(crate) fn notify_delete(path: &str, is_dir: bool) {
+    if !watching() {
+        return;
+    }
+    let Some(path) = canonical(path, false) else {
+        return;
+    };
+    let mask = InotifyMask::DELETE | isdir(is_dir);
+    for inotify in instances() {
+        if let Some(parent) = split_parent(&path) {
+            inotify.queue("", Some(parent), mask, 0);
+        }
+        inotify.remove_watches_on(&path, InotifyMask::DELETE_SELF);
+    }
+}
+
+/// Raises the events of renaming `old` to `new`, which must be called
+/// after the rename: [`InotifyMask::MOVED_FROM`] and
+/// [`InotifyMask::MOVED_TO`] with the same cookie for the two directories,
+/// and [`InotifyMask::MOVE_SELF`] for the file. The watches follow the
+/// file, and those on a replaced file are removed.
+pub(crate) fn notify_rename(old: &str, new: &str, is_dir: bool) {
+    if !watching() {
+        return;
+    }
+    let (Some(old), Some(new)) = (canonical(old, false), canonical(new, false)) else {
+        return;
+    };
+    let cookie = NEXT_COOKIE.fetch_add(1, Ordering::Relaxed);
+    let isdir = isdir(is_dir);
+    for inotify in instances() {
+        if let Some(parent) = split_parent(&old) {
+            inotify.queue("", Some(parent), InotifyMask::MOVED_FROM | isdir, cookie);
+        }
+        if let Some(parent) = split_parent(&new) {
+            inotify.queue("", Some(parent), InotifyMask::MOVED_TO | isdir, cookie);
+        }
+        inotify.remove_watches_on(&new, InotifyMask::DELETE_SELF);
+        inotify.queue(&old, None, InotifyMask::MOVE_SELF | isdir, 0);
+        inotify.rename_watches(&old, &new);
+    }
+}
+
+fn isdir(is_dir: bool) -> InotifyMask {
+    if is_dir {
+        InotifyMask::ISDIR
+    } else {
+        InotifyMask::empty()
+    }
+}
+
+/// The events of an opened file or directory, shared by the clones of the
+/// opened object: the close event is raised when the last one is dropped.
+pub(crate) struct FileEvents {
+    /// The absolute path the file was opened with.
+    path: String,
+    is_dir: bool,
+    writable: bool,
+}
+
+impl FileEvents {
+    /// Raises [`InotifyMask::OPEN`] for the file opened at `path`, relative
+    /// to the current directory.
+    pub(crate) fn opened(path: &str, is_dir: bool, writable: bool) -> Option<Arc<Self>> {
+        let events = Self {
+            path: crate::root::absolute_path(path).ok()?,
+            is_dir,
+            writable,
+        };
+        events.raise(InotifyMask::OPEN);
+        Some(Arc::new(events))
+    }
+
+    /// Raises `mask` for the file.
+    pub(crate) fn raise(&self, mask: InotifyMask) {
+        raise(&self.path, mask | isdir(self.is_dir), true);
+    }
+}
+
+impl Drop for FileEvents {
+    fn drop(&mut self) {
+        if self.writable {
+            self.raise(InotifyMask::CLOSE_WRITE);
+        } else {
+            self.raise(InotifyMask::CLOSE_NOWRITE);
+        }
+    }
+}
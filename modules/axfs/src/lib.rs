@@ -4,17 +4,128 @@
 //!
 //! # Cargo Features
 //!
-//! - `fatfs`: Use [FAT] as the main filesystem and mount it on `/`. This feature
-//!    is **enabled** by default.
-//! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`. This feature is
-//!    **enabled** by default.
-//! - `ramfs`: Mount [`axfs_ramfs::RamFileSystem`] on `/tmp`. This feature is
+//! - `fatfs`: Support [FAT] filesystems, for the root filesystem or mounted
+//!    at runtime.
+//! - `ext4fs`: Support ext4 filesystems, for the root filesystem or mounted
+//!    at runtime. This feature is **enabled** by default.
+//! - `devfs`: Mount [`axfs_devfs::DeviceFileSystem`] on `/dev`, holding the
+//!    devices published with [`api::register_device`], and a tmpfs on
+//!    `/dev/shm` if `ramfs` is enabled. Device nodes created with
+//!    [`api::mknod`] open the registered device with the same number.
+//!    A devpts is mounted on `/dev/pts`, listing the slave sides of the
+//!    pseudo-terminals.
+//!    This feature is **enabled** by default.
+//! - `ramfs`: Mount a tmpfs ([`axfs_ramfs::RamFileSystem`]) on `/tmp`, and
+//!    allow mounting more at runtime. This feature is **enabled** by default.
+//! - `procfs`: Mount a procfs ([`api::ProcFileSystem`]) on `/proc`, whose
+//!    files are generated from the kernel state when they are read.
+//!    This feature is **enabled** by default.
+//! - `sysfs`: Mount a sysfs ([`api::SysFileSystem`]) on `/sys`, listing the
+//!    probed devices and the writable kernel tunables. This feature is
 //!    **enabled** by default.
+//! - `overlayfs`: Support overlays of a writable filesystem over a read-only
+//!    one, mounted with the `overlay` type or over the root filesystem. This
+//!    feature is **disabled** by default.
+//! - `fuse`: Support filesystems served by user-space daemons through
+//!    `/dev/fuse`. It requires multitasking. This feature is **disabled** by
+//!    default.
 //! - `myfs`: Allow users to define their custom filesystems to override the
 //!    default. In this case, [`MyFileSystemIf`] is required to be implemented
 //!    to create and initialize other filesystems. This feature is **disabled** by
 //!    by default, but it will override other filesystem selection features if
 //!    both are enabled.
+//! - `writeback`: Write back dirty file pages and buffered disk writes every
+//!    few seconds in a background task. It requires the `multitask` and `irq`
+//!    features of `axtask`. Without it, they are only written back by `fsync`,
+//!    `sync`, and when the buffers fill up.
+//!
+//! # Block devices and the root filesystem
+//!
+//! Every block device found at boot is registered as `vda`, `vdb`, ..., and
+//! each partition in its MBR or GPT partition table as `vda1`, `vda2`, ...
+//! The root filesystem is mounted from the first one holding a supported
+//! filesystem, unless `root=<dev>` or `rootfstype=<type>` is given in the
+//! boot parameters (the `AX_BOOTARGS` environment variable at build time).
+//! The other devices can be mounted at runtime with [`api::mount`].
+//!
+//! With `overlayroot=tmpfs` (or `overlayroot=<dev>`) in the boot parameters,
+//! the root filesystem is the read-only lower layer of an overlay whose
+//! changes go to a tmpfs (or to the filesystem on `<dev>`), so that every
+//! boot starts from the pristine image.
+//!
+//! Filesystem images stored in files are mounted through the loop devices
+//! `loop0` to `loop7`, bound to files with [`api::File::attach_loop`].
+//!
+//! # Path resolution
+//!
+//! Paths are resolved one component at a time across the mount points,
+//! following symbolic links (at most [`api::MAX_SYMLINKS`] per path) and
+//! checking that every directory on the way can be searched.
+//! [`api::resolve_path`] exposes the walk, with the `*at` system call
+//! semantics selected by [`api::LookupFlags`].
+//!
+//! # Mounts
+//!
+//! Filesystems can be mounted on any directory, including one inside another
+//! mounted filesystem, and several can be stacked on the same directory (the
+//! last one is visible). [`api::bind_mount`] shows a directory at a second
+//! place. The mount table is listed by [`api::mounts`], and formatted as
+//! `/proc/mounts` and `/proc/self/mountinfo` by [`api::proc_mounts`] and
+//! [`api::proc_mountinfo`].
+//!
+//! A `tmpfs` keeps its files in memory. It takes the `size=`, `nr_inodes=`
+//! and `mode=` (of its root) mount options, which can be changed by
+//! [`api::remount`], and fails with `ENOSPC` once a limit is reached.
+//!
+//! An `overlay` merges the directory `upperdir=` over `lowerdir=`: files of
+//! the lower directory are copied up on their first change, and removed
+//! ones are hidden by whiteouts, leaving the lower directory untouched.
+//!
+//! # Page cache
+//!
+//! Regular files are read and written through a page cache, with one cache
+//! per file however many times it is opened. Shared memory mappings of a
+//! file map the cached pages directly ([`api::PageCache::get_page`]), so all
+//! processes see the same data. Pages written through mappings are written
+//! back by `msync`, `fsync` and [`api::sync`]. Clean pages are dropped when
+//! the page allocator runs out of memory.
+//!
+//! Sequential reads through an opened file are detected, and the following
+//! pages are read ahead in a window that doubles with every sequential read,
+//! up to 128 KiB. Disk writes are buffered, and adjacent dirty pages and
+//! buffered blocks are written back in large contiguous requests.
+//!
+//! # File locks
+//!
+//! Files carry advisory locks shared by all their opened instances: POSIX
+//! byte-range locks owned by processes, open file description locks owned
+//! by opened files ([`api::File::set_lock`]), and whole-file `flock` locks
+//! ([`api::File::flock`]).
+//!
+//! # File change notification
+//!
+//! [`api::Inotify`] instances watch files and directories for the events
+//! raised by the operations of this crate: creating, deleting, renaming,
+//! opening, reading, writing and closing files, and changing their
+//! attributes. The events are raised above the filesystems, so they are
+//! the same on all of them.
+//!
+//! # Permissions
+//!
+//! Opening, executing, creating, removing and renaming files check the
+//! permission bits against the credentials of the caller, registered by the
+//! process module with [`api::register_credentials`]. Files in sticky
+//! directories can only be removed by their owner, and new files belong to
+//! their creator. All the checks go through [`api::permission`], whose hook
+//! can be replaced to add ACLs or a security module.
+//!
+//! # Filesystems in user space
+//!
+//! Opening `/dev/fuse` creates an [`api::FuseConnection`], through which a
+//! user-space daemon serves the filesystem mounted with
+//! [`api::mount_fuse`], speaking the wire protocol of Linux FUSE. A process
+//! waiting for the daemon gives up with `EINTR` when a signal is pending, as
+//! told by the function registered with [`api::register_signal_pending`].
 //!
 //! [FAT]: https://en.wikipedia.org/wiki/File_Allocation_Table
 //! [`MyFileSystemIf`]: fops::MyFileSystemIf
@@ -27,9 +138,23 @@ extern crate log;
 extern crate alloc;
 
 mod dev;
+#[cfg(feature = "devfs")]
+mod devices;
 mod fs;
+#[cfg(feature = "fuse")]
+mod fuse;
+mod inotify;
+mod locks;
+mod loopdev;
 mod mounts;
+mod namei;
+mod page_cache;
+mod partition;
+mod perm;
 mod root;
+mod signal;
+#[cfg(feature = "writeback")]
+mod writeback;
 
 pub mod api;
 pub mod fops;
@@ -45,7 +170,23 @@ use axdriver::{prelude::*, AxDeviceContainer};
 pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>) {
     info!("Initialize filesystems...");
 
-    let dev = blk_devs.take_one().expect("No block device found!");
-    info!("  use block device 0: {:?}", dev.device_name());
-    self::root::init_rootfs(self::dev::Disk::new(dev));
+    let mut disks = alloc::vec::Vec::new();
+    while let Some(dev) = blk_devs.take_one() {
+        let name = self::dev::block_device_name(disks.len());
+        info!("  block device {}: {:?}", name, dev.device_name());
+        disks.push((name, self::dev::Disk::new(dev)));
+    }
+    assert!(!disks.is_empty(), "No block device found!");
+    axalloc::register_reclaimer(self::page_cache::reclaim);
+    self::root::init_rootfs(disks);
+    #[cfg(feature = "devfs")]
+    if let Err(e) = self::devices::add_block_devices() {
+        warn!("failed to add the block devices to devtmpfs: {:?}", e);
+    }
+    #[cfg(feature = "sysfs")]
+    if let Err(e) = self::fs::sysfs::add_block_devices() {
+        warn!("failed to add the block devices to sysfs: {:?}", e);
+    }
+    #[cfg(feature = "writeback")]
+    self::writeback::start();
 }
@@ -0,0 +1,263 @@
+//! Advisory file locks.
+//!
+//! Every file has a table of byte-range record locks, shared by the POSIX
+//! locks owned by processes and the open file description locks owned by
+//! opened files, and a separate list of BSD `flock` locks on the whole file,
+//! owned by opened files. The locks are advisory: reads and writes ignore
+//! them.
+//!
+//! The tables are kept by the identity of the file, so all the opened
+//! instances of a file share them. Files of filesystems without stable
+//! identities cannot be locked (`ENOLCK`). Waiting for a lock is left to the
+//! caller, which retries while placing it fails with `EAGAIN`, so that it can
+//! be interrupted; the processes waiting for a POSIX lock are recorded to
+//! detect deadlocks.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use axerrno::{ax_err, AxError, AxResult};
+use axfs_vfs::VfsNodeRef;
+use axsync::Mutex;
+
+/// The type of a lock, as the `l_type` of `struct flock`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockType {
+    /// A shared lock, for reading.
+    Read,
+    /// An exclusive lock, for writing.
+    Write,
+    /// No lock: removes the locks of the range.
+    Unlock,
+}
+
+/// The owner of a lock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockOwner {
+    /// A process, by its ID, owning POSIX record locks.
+    Process(u64),
+    /// An opened file, by an identifier unique while it is open, owning
+    /// open file description and `flock` locks.
+    File(usize),
+}
+
+/// A byte-range lock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordLock {
+    /// The type of the lock.
+    pub ty: LockType,
+    /// The first byte locked.
+    pub start: u64,
+    /// The byte after the last one locked, `u64::MAX` for a lock up to the
+    /// end of the file however it grows.
+    pub end: u64,
+    /// The owner of the lock.
+    pub owner: LockOwner,
+}
+
+impl RecordLock {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end
+    }
+
+    /// Returns whether this lock and `other`, of a different owner, cannot
+    /// be held at the same time.
+    fn conflicts(&self, other: &RecordLock) -> bool {
+        self.owner != other.owner
+            && self.overlaps(other.start, other.end)
+            && (self.ty == LockType::Write || other.ty == LockType::Write)
+    }
+}
+
+/// The key identifying a file in the lock tables.
+type LockKey = (usize, u64);
+
+/// The locks of a file.
+#[derive(Default)]
+struct LockTable {
+    records: Vec<RecordLock>,
+    flocks: Vec<(LockOwner, LockType)>,
+}
+
+impl LockTable {
+    fn is_empty(&self) -> bool {
+        self.records.is_empty() && self.flocks.is_empty()
+    }
+}
+
+/// The lock tables of the files holding locks.
+static LOCKS: Mutex<BTreeMap<LockKey, LockTable>> = Mutex::new(BTreeMap::new());
+
+/// The processes waiting for a POSIX lock, and the owner of the lock they
+/// wait for.
+static WAITING: Mutex<BTreeMap<LockOwner, LockOwner>> = Mutex::new(BTreeMap::new());
+
+/// Returns the key of the file `node` in the lock tables: the key of its
+/// page cache, which is the same for all the nodes of a file.
+///
+/// Fails with `ENOLCK` if the filesystem has no such key, e.g. FAT or devfs,
+/// since separate lookups of a file may give unrelated nodes there.
+fn key_of(node: &VfsNodeRef) -> AxResult<LockKey> {
+    node.cache_key().ok_or(AxError::NoLocksAvailable)
+}
+
+/// Returns the first lock on `node` conflicting with `lock`, as `F_GETLK`.
+pub(crate) fn test_record_lock(
+    node: &VfsNodeRef,
+    lock: &RecordLock,
+) -> AxResult<Option<RecordLock>> {
+    let key = key_of(node)?;
+    if lock.ty == LockType::Unlock {
+        return Ok(None);
+    }
+    let locks = LOCKS.lock();
+    Ok(locks
+        .get(&key)
+        .and_then(|table| table.records.iter().find(|l| l.conflicts(lock)).copied()))
+}
+
+/// Places `lock` on `node`, replacing the locks of its owner in its range,
+/// or removes them if it is [`LockType::Unlock`]. Adjacent locks of the same
+/// owner and type are merged.
+///
+/// Fails with `EAGAIN` if another owner holds a conflicting lock. If `wait`,
+/// a process owner is then recorded as waiting for it, and the call fails
+/// with `EDEADLK` instead if the holder waits, directly or not, for the
+/// caller.
+pub(crate) fn set_record_lock(node: &VfsNodeRef, lock: &RecordLock, wait: bool) -> AxResult {
+    let key = key_of(node)?;
+    let mut locks = LOCKS.lock();
+    let table = locks.entry(key).or_default();
+    if lock.ty != LockType::Unlock {
+        if let Some(holder) = table.records.iter().find(|l| l.conflicts(lock)) {
+            let holder = holder.owner;
+            if wait && matches!(lock.owner, LockOwner::Process(_)) {
+                wait_for(lock.owner, holder)?;
+            }
+            return ax_err!(WouldBlock);
+        }
+    }
+    // the parts of the locks of the owner out of the range remain
+    let mut records = Vec::with_capacity(table.records.len() + 2);
+    for l in table.records.drain(..) {
+        if l.owner != lock.owner || !l.overlaps(lock.start, lock.end) {
+            records.push(l);
+            continue;
+        }
+        if l.start < lock.start {
+            records.push(RecordLock {
+                end: lock.start,
+                ..l
+            });
+        }
+        if l.end > lock.end {
+            records.push(RecordLock {
+                start: lock.end,
+                ..l
+            });
+        }
+    }
+    if lock.ty != LockType::Unlock {
+        let mut new = *lock;
+        records.retain(|l| {
+            let adjacent = l.end == new.start || (new.end != u64::MAX && l.start == new.end);
+            if l.owner == new.owner && l.ty == new.ty && adjacent {
+                new.start = new.start.min(l.start);
+                new.end = new.end.max(l.end);
+                false
+            } else {
+                true
+            }
+        });
+        records.push(new);
+    }
+    table.records = records;
+    if table.is_empty() {
+        locks.remove(&key);
+    }
+    drop(locks);
+    WAITING.lock().remove(&lock.owner);
+    Ok(())
+}
+
+/// Records `owner` as waiting for a lock of `holder`, unless it would
+/// deadlock.
+fn wait_for(owner: LockOwner, holder: LockOwner) -> AxResult {
+    let mut waiting = WAITING.lock();
+    let mut next = holder;
+    // the chain is at most as long as the number of waiting owners
+    for _ in 0..=waiting.len() {
+        if next == owner {
+            return ax_err!(Deadlock);
+        }
+        match waiting.get(&next) {
+            Some(&owner) => next = owner,
+            None => break,
+        }
+    }
+    waiting.insert(owner, holder);
+    Ok(())
+}
+
+/// Forgets that `owner` waits for a lock, after it gives up waiting, e.g.
+/// when interrupted by a signal.
+pub fn cancel_lock_wait(owner: LockOwner) {
+    WAITING.lock().remove(&owner);
+}
+
+/// Places a `flock` lock of type `ty` held by `owner` on `node`, replacing
+/// its previous one, or removes it if `ty` is [`LockType::Unlock`].
+///
+/// Fails with `EAGAIN` if another owner holds a conflicting lock, in which
+/// case the previous lock of `owner` is removed, as Linux does when
+/// converting a lock.
+pub(crate) fn set_flock(node: &VfsNodeRef, owner: LockOwner, ty: LockType) -> AxResult {
+    let key = key_of(node)?;
+    let mut locks = LOCKS.lock();
+    let table = locks.entry(key).or_default();
+    if table.flocks.contains(&(owner, ty)) {
+        return Ok(());
+    }
+    table.flocks.retain(|(o, _)| *o != owner);
+    let conflict = ty != LockType::Unlock
+        && table
+            .flocks
+            .iter()
+            .any(|&(_, t)| t == LockType::Write || ty == LockType::Write);
+    if !conflict && ty != LockType::Unlock {
+        table.flocks.push((owner, ty));
+    }
+    if table.is_empty() {
+        locks.remove(&key);
+    }
+    if conflict {
+        ax_err!(WouldBlock)
+    } else {
+        Ok(())
+    }
+}
+
+/// Removes all the locks of `owner` on `node`, when a process closes the
+/// file, or the last reference to an opened file is dropped.
+pub(crate) fn release_file_locks(node: &VfsNodeRef, owner: LockOwner) {
+    let Ok(key) = key_of(node) else {
+        return;
+    };
+    let mut locks = LOCKS.lock();
+    if let Some(table) = locks.get_mut(&key) {
+        table.records.retain(|l| l.owner != owner);
+        table.flocks.retain(|(o, _)| *o != owner);
+        if table.is_empty() {
+            locks.remove(&key);
+        }
+    }
+}
+
+/// Removes all the locks of `owner` on all files, when a process exits.
+pub fn release_locks(owner: LockOwner) {
+    LOCKS.lock().retain(|_, table| {
+        table.records.retain(|l| l.owner != owner);
+        table.flocks.retain(|(o, _)| *o != owner);
+        !table.is_empty()
+    });
+    WAITING.lock().remove(&owner);
+}
@@ -0,0 +1,311 @@
+//! Loop devices: block devices backed by regular files, so that filesystem
+//! images stored in files can be mounted.
+//!
+//! Like Linux booted with `max_loop=8`, there is a fixed set of devices
+//! `loop0` to `loop7`. They appear in devtmpfs together with `loop-control`,
+//! and are bound to files with the `LOOP_*` ioctls.
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::{
+    DeviceId, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult,
+};
+use axsync::{Mutex, MutexGuard};
+
+use crate::page_cache::PageCache;
+
+const BLOCK_SIZE: u64 = 512;
+/// The major number of the loop devices. The minor number is the number of
+/// the device, e.g. 0 for `loop0`.
+const LOOP_MAJOR: u32 = 7;
+
+/// The number of loop devices.
+pub const LOOP_DEVICE_COUNT: usize = 8;
+
+/// The names of the loop devices in devtmpfs.
+#[cfg(feature = "devfs")]
+pub(crate) const LOOP_DEVICE_NAMES: [&str; LOOP_DEVICE_COUNT] = [
+    "loop0", "loop1", "loop2", "loop3", "loop4", "loop5", "loop6", "loop7",
+];
+
+static LOOP_DEVICES: Mutex<Vec<Arc<LoopDevice>>> = Mutex::new(Vec::new());
+
+/// The configuration of a bound loop device, as reported by
+/// `LOOP_GET_STATUS64`.
+#[derive(Debug, Clone, Default)]
+pub struct LoopStatus {
+    /// The device number, e.g. 0 for `loop0`.
+    pub number: usize,
+    /// The offset in the backing file where the device starts.
+    pub offset: u64,
+    /// The maximum size of the device, 0 for the whole file.
+    pub size_limit: u64,
+    /// Whether the device is read-only, because the backing file was opened
+    /// read-only.
+    pub read_only: bool,
+    /// Whether the device is unbound once it is no longer used.
+    pub autoclear: bool,
+    /// The path of the backing file when it was bound.
+    pub file_name: String,
+}
+
+struct Backing {
+    file: VfsNodeRef,
+    /// The page cache of a regular backing file, so that the device and the
+    /// opened file see the same data.
+    cache: Option<Arc<PageCache>>,
+    status: LoopStatus,
+}
+
+impl Backing {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        match &self.cache {
+            Some(cache) => cache.read_at(offset, buf),
+            None => self.file.read_at(offset, buf),
+        }
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        match &self.cache {
+            Some(cache) => cache.write_at(offset, buf),
+            None => self.file.write_at(offset, buf),
+        }
+    }
+
+    fn fsync(&self) -> VfsResult {
+        match &self.cache {
+            Some(cache) => cache.sync(),
+            None => self.file.fsync(),
+        }
+    }
+}
+
+/// A loop device, forwarding block I/O to its backing file.
+///
+/// A device is in use while a filesystem mounted on it holds a reference
+/// besides the one in [`LOOP_DEVICES`].
+pub struct LoopDevice {
+    number: usize,
+    backing: Mutex<Option<Backing>>,
+}
+
+impl LoopDevice {
+    fn size(backing: &Backing) -> u64 {
+        let file_size = backing.file.get_attr().map_or(0, |attr| attr.size());
+        let mut size = file_size.saturating_sub(backing.status.offset);
+        if backing.status.size_limit != 0 {
+            size = size.min(backing.status.size_limit);
+        }
+        size / BLOCK_SIZE * BLOCK_SIZE
+    }
+}
+
+impl VfsNodeOps for LoopDevice {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let size = self.backing.lock().as_ref().map_or(0, Self::size);
+        let mut attr = VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o660),
+            VfsNodeType::BlockDevice,
+            size,
+            size / BLOCK_SIZE,
+        );
+        attr.set_rdev(DeviceId::new(LOOP_MAJOR, self.number as u32));
+        Ok(attr)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let backing = self.backing.lock();
+        let backing = backing.as_ref().ok_or(VfsError::NoDeviceOrAddress)?;
+        let len = buf
+            .len()
+            .min(Self::size(backing).saturating_sub(offset) as usize);
+        backing.read_at(backing.status.offset + offset, &mut buf[..len])
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let backing = self.backing.lock();
+        let backing = backing.as_ref().ok_or(VfsError::NoDeviceOrAddress)?;
+        if backing.status.read_only {
+            return ax_err!(ReadOnlyFilesystem);
+        }
+        let len = buf
+            .len()
+            .min(Self::size(backing).saturating_sub(offset) as usize);
+        backing.write_at(backing.status.offset + offset, &buf[..len])
+    }
+
+    fn fsync(&self) -> VfsResult {
+        let backing = self.backing.lock();
+        let backing = backing.as_ref().ok_or(VfsError::NoDeviceOrAddress)?;
+        backing.fsync()
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// Locks the loop devices, creating them on first use. Devices marked
+/// autoclear are unbound here once no filesystem uses them.
+fn devices() -> MutexGuard<'static, Vec<Arc<LoopDevice>>> {
+    let mut devices = LOOP_DEVICES.lock();
+    if devices.is_empty() {
+        devices.extend((0..LOOP_DEVICE_COUNT).map(|number| {
+            Arc::new(LoopDevice {
+                number,
+                backing: Mutex::new(None),
+            })
+        }));
+    }
+    for dev in devices.iter() {
+        let mut backing = dev.backing.lock();
+        if backing.as_ref().map_or(false, |b| b.status.autoclear) && Arc::strong_count(dev) == 1 {
+            debug!("loop{}: autoclear", dev.number);
+            *backing = None;
+        }
+    }
+    devices
+}
+
+fn device(number: usize) -> AxResult<Arc<LoopDevice>> {
+    devices()
+        .get(number)
+        .cloned()
+        .ok_or(VfsError::NoDeviceOrAddress)
+}
+
+/// Looks up a loop device by its name, e.g. `loop0`.
+pub(crate) fn find(name: &str) -> Option<Arc<LoopDevice>> {
+    let number = name.strip_prefix("loop")?.parse().ok()?;
+    device(number).ok()
+}
+
+/// Returns the number of the first unbound loop device.
+pub(crate) fn get_free() -> AxResult<usize> {
+    devices()
+        .iter()
+        .find(|dev| dev.backing.lock().is_none())
+        .map(|dev| dev.number)
+        .ok_or(VfsError::ResourceBusy)
+}
+
+/// Binds loop device `number` to `file`, named `name` in its status.
+pub(crate) fn attach(number: usize, file: VfsNodeRef, name: &str, read_only: bool) -> AxResult {
+    if file.as_any().is::<LoopDevice>() || file.as_any().is::<LoopNode>() {
+        return ax_err!(InvalidInput, "a loop device cannot back another");
+    }
+    let ty = file.get_attr()?.file_type();
+    if !ty.is_file() && !ty.is_block_device() {
+        return ax_err!(InvalidInput);
+    }
+    let dev = device(number)?;
+    let mut backing = dev.backing.lock();
+    if backing.is_some() {
+        return ax_err!(ResourceBusy);
+    }
+    info!("loop{}: bound to {}", number, name);
+    *backing = Some(Backing {
+        cache: PageCache::get(&file),
+        file,
+        status: LoopStatus {
+            number,
+            read_only,
+            file_name: name.to_string(),
+            ..Default::default()
+        },
+    });
+    Ok(())
+}
+
+/// Unbinds loop device `number`. A device still in use is marked autoclear
+/// instead, and is unbound after the filesystem on it is unmounted.
+pub(crate) fn detach(number: usize) -> AxResult {
+    let devices = devices();
+    let dev = devices.get(number).ok_or(VfsError::NoDeviceOrAddress)?;
+    let mut backing = dev.backing.lock();
+    let bound = backing.as_mut().ok_or(VfsError::NoDeviceOrAddress)?;
+    if Arc::strong_count(dev) > 1 {
+        bound.status.autoclear = true;
+    } else {
+        info!("loop{}: unbound", number);
+        *backing = None;
+    }
+    Ok(())
+}
+
+/// Returns the status of loop device `number`.
+pub(crate) fn status(number: usize) -> AxResult<LoopStatus> {
+    let dev = device(number)?;
+    let backing = dev.backing.lock();
+    let backing = backing.as_ref().ok_or(VfsError::NoDeviceOrAddress)?;
+    Ok(backing.status.clone())
+}
+
+/// Changes the offset, size limit and autoclear flag of loop device `number`.
+pub(crate) fn set_status(number: usize, offset: u64, size_limit: u64, autoclear: bool) -> AxResult {
+    let dev = device(number)?;
+    let mut backing = dev.backing.lock();
+    let backing = backing.as_mut().ok_or(VfsError::NoDeviceOrAddress)?;
+    if offset % BLOCK_SIZE != 0 || size_limit % BLOCK_SIZE != 0 {
+        return ax_err!(InvalidInput);
+    }
+    backing.status.offset = offset;
+    backing.status.size_limit = size_limit;
+    backing.status.autoclear = autoclear;
+    Ok(())
+}
+
+/// Returns the number of the loop device if `node` is one.
+pub(crate) fn number_of(node: &VfsNodeRef) -> Option<usize> {
+    let any = node.as_any();
+    any.downcast_ref::<LoopNode>()
+        .map(|node| node.0)
+        .or_else(|| any.downcast_ref::<LoopDevice>().map(|dev| dev.number))
+}
+
+/// The node of a loop device in devtmpfs.
+///
+/// It refers to the device by number, so that open files and devtmpfs
+/// instances do not count as users of the device.
+pub(crate) struct LoopNode(pub usize);
+
+impl VfsNodeOps for LoopNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        device(self.0)?.get_attr()
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        device(self.0)?.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        device(self.0)?.write_at(offset, buf)
+    }
+
+    fn fsync(&self) -> VfsResult {
+        device(self.0)?.fsync()
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// `/dev/loop-control`, which only serves the `LOOP_CTL_*` ioctls.
+pub(crate) struct LoopControl;
+
+impl VfsNodeOps for LoopControl {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o660),
+            VfsNodeType::CharDevice,
+            0,
+            0,
+        );
+        // the misc device LOOP_CTRL_MINOR
+        attr.set_rdev(DeviceId::new(10, 237));
+        Ok(attr)
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
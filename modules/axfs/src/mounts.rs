@@ -1,36 +1,19 @@
 use alloc::sync::Arc;
-use axfs_vfs::{VfsNodeType, VfsOps, VfsResult};
+use axerrno::ax_err;
+use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult};
 
 use crate::fs;
 
+/// The device tree, which is the same for every mount of devtmpfs.
 #[cfg(feature = "devfs")]
 pub(crate) fn devfs() -> Arc<fs::devfs::DeviceFileSystem> {
-    let null = fs::devfs::NullDev;
-    let zero = fs::devfs::ZeroDev;
-    let bar = fs::devfs::ZeroDev;
-    let random = fs::devfs::RandomDev::default();
-    let urandom = fs::devfs::RandomDev::default();
-
-    let devfs = fs::devfs::DeviceFileSystem::new();
-    let foo_dir = devfs.mkdir("foo");
-    devfs.add("null", Arc::new(null));
-    devfs.add("zero", Arc::new(zero));
-    foo_dir.add("bar", Arc::new(bar));
-    devfs.add("random", Arc::new(random));
-    devfs.add("urandom", Arc::new(urandom));
-    #[cfg(feature = "monolithic")]
-    {
-        // 添加dev文件系统下的配置文件
-        // busybox的时候要用到
-        // devfs不支持可修改的file，因此取巧直接用了ramfs提供的file实现
-        let testshm = fs::ramfs::FileNode::new();
-        let testrtc = fs::ramfs::FileNode::new();
-        let shm_dir = devfs.mkdir("shm");
-        shm_dir.add("testshm", Arc::new(testshm));
-        let rtc_dir = devfs.mkdir("misc");
-        rtc_dir.add("rtc", Arc::new(testrtc));
-    }
-    Arc::new(devfs)
+    crate::devices::devfs()
+}
+
+/// The devpts, which shows the same pseudo-terminals on every mount.
+#[cfg(feature = "devfs")]
+pub(crate) fn devpts() -> Arc<fs::devpts::DevPtsFileSystem> {
+    Arc::new(fs::devpts::DevPtsFileSystem::new())
 }
 
 #[cfg(feature = "ramfs")]
@@ -38,68 +21,203 @@ pub(crate) fn ramfs() -> Arc<fs::ramfs::RamFileSystem> {
     Arc::new(fs::ramfs::RamFileSystem::new())
 }
 
+/// A RAM filesystem with the tmpfs mount options in `data`, such as
+/// `size=`, `nr_inodes=` and `mode=`.
+#[cfg(feature = "ramfs")]
+pub(crate) fn tmpfs(data: &str) -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
+    let options = fs::ramfs::RamFsOptions::parse(data)?;
+    let fs = fs::ramfs::RamFileSystem::with_options(&options, axhal::time::current_time)?;
+    Ok(Arc::new(fs))
+}
+
 #[cfg(feature = "procfs")]
-pub(crate) fn procfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
-    let procfs = fs::ramfs::RamFileSystem::new();
-    let proc_root = procfs.root_dir();
-
-    // Create /proc/sys/net/core/somaxconn
-    proc_root.create("sys", VfsNodeType::Dir)?;
-    proc_root.create("sys/net", VfsNodeType::Dir)?;
-    proc_root.create("sys/net/core", VfsNodeType::Dir)?;
-    proc_root.create("sys/net/core/somaxconn", VfsNodeType::File)?;
-    let file_somaxconn = proc_root.clone().lookup("./sys/net/core/somaxconn")?;
-    file_somaxconn.write_at(0, b"4096\n")?;
-
-    // Create /proc/sys/vm/overcommit_memory
-    proc_root.create("sys/vm", VfsNodeType::Dir)?;
-    proc_root.create("sys/vm/overcommit_memory", VfsNodeType::File)?;
-    let file_over = proc_root.clone().lookup("./sys/vm/overcommit_memory")?;
-    file_over.write_at(0, b"0\n")?;
-
-    // Create /proc/self/stat
-    proc_root.create("self", VfsNodeType::Dir)?;
-    proc_root.create("self/stat", VfsNodeType::File)?;
-
-    #[cfg(feature = "monolithic")]
+pub(crate) fn procfs() -> Arc<fs::procfs::ProcFileSystem> {
+    Arc::new(fs::procfs::ProcFileSystem::new())
+}
+
+#[cfg(feature = "sysfs")]
+pub(crate) fn sysfs() -> Arc<fs::sysfs::SysFileSystem> {
+    Arc::new(fs::sysfs::SysFileSystem::new())
+}
+
+/// An overlay of the directory `upperdir` over the directory `lowerdir`,
+/// given in `data` as in Linux, e.g. `lowerdir=/lower,upperdir=/upper`. The
+/// `workdir` option is accepted but not needed.
+#[cfg(feature = "overlayfs")]
+pub(crate) fn overlay(data: &str) -> VfsResult<Arc<fs::overlayfs::OverlayFileSystem>> {
+    let (mut lower, mut upper) = (None, None);
+    for opt in data.split(',') {
+        let Some((key, value)) = opt.split_once('=') else {
+            continue;
+        };
+        match key {
+            // a single lower layer
+            "lowerdir" if value.contains(':') => return ax_err!(Unsupported),
+            "lowerdir" => lower = Some(value),
+            "upperdir" => upper = Some(value),
+            "workdir" => {}
+            _ => return ax_err!(InvalidInput, "unknown overlay option"),
+        }
+    }
+    let (Some(lower), Some(upper)) = (lower, upper) else {
+        return ax_err!(InvalidInput, "overlay needs lowerdir and upperdir");
+    };
+    let lower = crate::root::lookup(None, lower)?;
+    let upper = crate::root::lookup(None, upper)?;
+    Ok(Arc::new(fs::overlayfs::OverlayFileSystem::new(lower, upper)?))
+}
+
+/// An overlay of the filesystem `upper` over the filesystem `lower`, which
+/// it owns.
+#[cfg(feature = "overlayfs")]
+pub(crate) fn overlay_layers(
+    lower: Arc<dyn VfsOps>,
+    upper: Arc<dyn VfsOps>,
+) -> VfsResult<Arc<fs::overlayfs::OverlayFileSystem>> {
+    Ok(Arc::new(fs::overlayfs::OverlayFileSystem::with_layers(lower, upper)?))
+}
+
+/// The filesystem served through the FUSE connection `conn`, with the mount
+/// options in `data` as passed by the daemon, e.g.
+/// `fd=3,rootmode=40000,user_id=0,group_id=0`. The file descriptor is
+/// resolved to `conn` by the caller.
+#[cfg(feature = "fuse")]
+pub(crate) fn fuse(
+    conn: Arc<crate::fuse::FuseConnection>,
+    data: &str,
+) -> VfsResult<Arc<fs::fusefs::FuseFileSystem>> {
+    for opt in data.split(',') {
+        let Some((key, value)) = opt.split_once('=') else {
+            continue;
+        };
+        match key {
+            "rootmode" => {
+                let mode =
+                    u32::from_str_radix(value, 8).map_err(|_| axfs_vfs::VfsError::InvalidInput)?;
+                if mode & 0o170000 != 0o040000 {
+                    return ax_err!(InvalidInput, "FUSE root is not a directory");
+                }
+            }
+            "fd" | "user_id" | "group_id" | "max_read" | "blksize" => {}
+            _ => return ax_err!(InvalidInput, "unknown FUSE option"),
+        }
+    }
+    Ok(Arc::new(fs::fusefs::FuseFileSystem::new(conn)?))
+}
+
+/// Opens the ext4 filesystem stored on `device`, which can be a block device
+/// node or a regular file holding an image.
+#[cfg(feature = "ext4fs")]
+pub(crate) fn ext4fs(
+    device: VfsNodeRef,
+    data: &str,
+) -> VfsResult<Arc<fs::ext4fs::Ext4FileSystem>> {
+    let block_device = Arc::new(fs::ext4fs::NodeBlockDevice::new(device));
+    let fs = Arc::new(fs::ext4fs::Ext4FileSystem::with_options(block_device, data)?);
+    fs.init();
+    Ok(fs)
+}
+
+/// Opens the FAT filesystem stored on `device`.
+#[cfg(all(feature = "fatfs", not(feature = "myfs")))]
+pub(crate) fn fatfs(device: VfsNodeRef) -> VfsResult<Arc<fs::fatfs::FatFileSystem>> {
+    fs::fatfs::FatFileSystem::from_node(device)
+}
+
+/// Creates a filesystem of type `fs_type` to be mounted at runtime.
+///
+/// `source` resolves the block device node or image file for the disk-based
+/// filesystems, and is not called for the others. An empty or `"auto"` type
+/// is detected from the magic numbers on the source.
+///
+/// Returns the filesystem and its type as shown in the mount table.
+pub(crate) fn new_fs<F>(
+    fs_type: &str,
+    source: F,
+    data: &str,
+) -> VfsResult<(Arc<dyn VfsOps>, String)>
+where
+    F: FnOnce() -> VfsResult<VfsNodeRef>,
+{
+    let fs_type = canonical_fs_type(fs_type);
+    let fs: Arc<dyn VfsOps> = match fs_type {
+        #[cfg(feature = "ramfs")]
+        "tmpfs" => tmpfs(data)?,
+        #[cfg(feature = "ramfs")]
+        "ramfs" => ramfs(),
+        #[cfg(feature = "procfs")]
+        "proc" => procfs(),
+        #[cfg(feature = "sysfs")]
+        "sysfs" => sysfs(),
+        #[cfg(feature = "devfs")]
+        "devtmpfs" => devfs(),
+        #[cfg(feature = "devfs")]
+        "devpts" => devpts(),
+        #[cfg(feature = "overlayfs")]
+        "overlay" => overlay(data)?,
+        "" | "auto" => {
+            let device = block_source(source()?)?;
+            let fs_type = probe(&device)?;
+            return Ok((block_fs(fs_type, device, data)?, fs_type.into()));
+        }
+        "ext4" | "vfat" if !block_fs_supported(fs_type) => return ax_err!(Unsupported),
+        "ext4" | "vfat" => block_fs(fs_type, block_source(source()?)?, data)?,
+        _ => return ax_err!(NoSuchDevice, "unknown filesystem type"),
+    };
+    Ok((fs, fs_type.into()))
+}
+
+/// Maps the aliases of a filesystem type to the name used by [`new_fs`].
+pub(crate) fn canonical_fs_type(fs_type: &str) -> &str {
+    match fs_type {
+        "ext2" | "ext3" | "ext4" => "ext4",
+        "vfat" | "fat" | "msdos" => "vfat",
+        ty => ty,
+    }
+}
+
+/// Checks that `node` can hold a disk-based filesystem.
+fn block_source(node: VfsNodeRef) -> VfsResult<VfsNodeRef> {
+    let ty = node.get_attr()?.file_type();
+    if !ty.is_block_device() && !ty.is_file() {
+        return ax_err!(InvalidInput, "mount source is not a block device");
+    }
+    Ok(node)
+}
+
+/// Identifies the filesystem stored on `device` by its magic numbers.
+pub(crate) fn probe(device: &VfsNodeRef) -> VfsResult<&'static str> {
+    let mut buf = [0u8; 2048];
+    let len = device.read_at(0, &mut buf)?;
+    let buf = &buf[..len];
+    // ext2/3/4: the magic number 0xEF53 in the superblock at offset 1024
+    if buf.get(0x438..0x43a) == Some(&[0x53, 0xef][..]) {
+        return Ok("ext4");
+    }
+    // FAT: the boot signature, and the type string of FAT12/16 or FAT32
+    if buf.get(510..512) == Some(&[0x55, 0xaa][..])
+        && (buf.get(54..57) == Some(&b"FAT"[..]) || buf.get(82..87) == Some(&b"FAT32"[..]))
     {
-        // Create other file to pass the testcases
-        proc_root.create("meminfo", VfsNodeType::File)?;
-        proc_root.create("mounts", VfsNodeType::File)?;
-        proc_root.create("interrupts", VfsNodeType::File)?;
-        // procfs.mount("interrupts", Arc::new(fs::devfs::Interrupts::default()))?;
+        return Ok("vfat");
     }
-    Ok(Arc::new(procfs))
+    ax_err!(InvalidData, "unknown filesystem on the device")
 }
 
-#[cfg(feature = "sysfs")]
-pub(crate) fn sysfs() -> VfsResult<Arc<fs::ramfs::RamFileSystem>> {
-    let sysfs = fs::ramfs::RamFileSystem::new();
-    let sys_root = sysfs.root_dir();
-
-    // Create /sys/kernel/mm/transparent_hugepage/enabled
-    sys_root.create("kernel", VfsNodeType::Dir)?;
-    sys_root.create("kernel/mm", VfsNodeType::Dir)?;
-    sys_root.create("kernel/mm/transparent_hugepage", VfsNodeType::Dir)?;
-    sys_root.create("kernel/mm/transparent_hugepage/enabled", VfsNodeType::File)?;
-    let file_hp = sys_root
-        .clone()
-        .lookup("./kernel/mm/transparent_hugepage/enabled")?;
-    file_hp.write_at(0, b"always [madvise] never\n")?;
-
-    // Create /sys/devices/system/clocksource/clocksource0/current_clocksource
-    sys_root.create("devices", VfsNodeType::Dir)?;
-    sys_root.create("devices/system", VfsNodeType::Dir)?;
-    sys_root.create("devices/system/clocksource", VfsNodeType::Dir)?;
-    sys_root.create("devices/system/clocksource/clocksource0", VfsNodeType::Dir)?;
-    sys_root.create(
-        "devices/system/clocksource/clocksource0/current_clocksource",
-        VfsNodeType::File,
-    )?;
-    let file_cc = sys_root
-        .clone()
-        .lookup("devices/system/clocksource/clocksource0/current_clocksource")?;
-    file_cc.write_at(0, b"tsc\n")?;
-
-    Ok(Arc::new(sysfs))
+pub(crate) fn block_fs_supported(fs_type: &str) -> bool {
+    match fs_type {
+        "ext4" => cfg!(feature = "ext4fs"),
+        "vfat" => cfg!(all(feature = "fatfs", not(feature = "myfs"))),
+        _ => false,
+    }
+}
+
+#[allow(unused_variables)]
+fn block_fs(fs_type: &str, device: VfsNodeRef, data: &str) -> VfsResult<Arc<dyn VfsOps>> {
+    match fs_type {
+        #[cfg(feature = "ext4fs")]
+        "ext4" => Ok(ext4fs(device, data)?),
+        #[cfg(all(feature = "fatfs", not(feature = "myfs")))]
+        "vfat" => Ok(fatfs(device)?),
+        _ => ax_err!(Unsupported),
+    }
 }
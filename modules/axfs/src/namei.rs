@@ -0,0 +1,159 @@
+//! Path resolution (namei).
+//!
+//! A path is walked one component at a time from the root directory, the
+//! current directory or a given directory. The walk crosses mount points in
+//! both directions, follows symbolic links (at most [`MAX_SYMLINKS`] per
+//! path), and checks that every directory on the way can be searched by the
+//! caller.
+
+use alloc::{string::String, vec, vec::Vec};
+use axerrno::{ax_err, AxError, AxResult};
+use axfs_vfs::{VfsNodeRef, VfsNodeType};
+use bitflags::bitflags;
+
+use crate::perm::{self, Access, Credentials};
+use crate::root::{Location, RootDirectory};
+
+/// The maximum number of symbolic links followed while resolving a path, as
+/// `MAXSYMLINKS` in Linux.
+pub const MAX_SYMLINKS: usize = 40;
+
+/// The maximum length of the target of a symbolic link.
+const PATH_MAX: usize = 4096;
+
+bitflags! {
+    /// Flags controlling how the last component of a path is resolved.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct LookupFlags: u32 {
+        /// Do not follow a symbolic link in the last component (`O_NOFOLLOW`,
+        /// `AT_SYMLINK_NOFOLLOW`). A trailing slash still follows it.
+        const NOFOLLOW = 1;
+        /// The last component must be a directory (`O_DIRECTORY`).
+        const DIRECTORY = 2;
+        /// The last component may be missing, as when it is about to be
+        /// created. Its parent directory must exist.
+        const CREATE = 4;
+        /// The last component is about to be opened: with
+        /// [`NOFOLLOW`](Self::NOFOLLOW), a symbolic link there fails with
+        /// `ELOOP` rather than being returned, as `O_NOFOLLOW`.
+        const OPEN = 8;
+    }
+}
+
+/// A path resolved by [`resolve_path`](crate::api::resolve_path).
+pub struct ResolvedPath {
+    /// The absolute path, without `.`, `..` or symbolic links.
+    pub path: String,
+    /// The node at the path, `None` if it is missing and
+    /// [`LookupFlags::CREATE`] was given.
+    pub node: Option<VfsNodeRef>,
+}
+
+impl ResolvedPath {
+    /// Returns the type of the node, `None` if it is missing.
+    pub fn file_type(&self) -> Option<VfsNodeType> {
+        let attr = self.node.as_ref()?.get_attr().ok()?;
+        Some(attr.file_type())
+    }
+}
+
+/// Walks `path` in the tree of `root`, starting from `start` if the path is
+/// relative. Returns the location reached and the node there, which is
+/// `None` only if the last component is missing and
+/// [`LookupFlags::CREATE`] is given.
+pub(crate) fn walk(
+    root: &RootDirectory,
+    start: Location,
+    path: &str,
+    flags: LookupFlags,
+) -> AxResult<(Location, Option<VfsNodeRef>)> {
+    if path.is_empty() {
+        return ax_err!(NotFound);
+    }
+    let mut loc = if path.starts_with('/') {
+        root.root_location()
+    } else {
+        start
+    };
+    let mut node = loc.node()?;
+    // the components still to walk, the next one last
+    let mut pending = components(path);
+    let must_be_dir = flags.contains(LookupFlags::DIRECTORY) || path.ends_with('/');
+    let mut links = 0;
+    let cred = perm::current_credentials();
+    while let Some(name) = pending.pop() {
+        check_searchable(&node, &cred)?;
+        match name.as_str() {
+            "." => continue,
+            ".." => {
+                loc = root.parent(&loc);
+                node = loc.node()?;
+                continue;
+            }
+            _ => {}
+        }
+        let last = pending.is_empty();
+        let next = root.child(&loc, &name);
+        let child = match next.node() {
+            Ok(child) => child,
+            Err(AxError::NotFound) if last && flags.contains(LookupFlags::CREATE) => {
+                return Ok((next, None));
+            }
+            Err(e) => return Err(e),
+        };
+        let follow = !last || must_be_dir || !flags.contains(LookupFlags::NOFOLLOW);
+        let is_link = child.get_attr()?.file_type().is_symlink();
+        if is_link && !follow && flags.contains(LookupFlags::OPEN) {
+            return ax_err!(FilesystemLoop);
+        }
+        if is_link && follow {
+            links += 1;
+            if links > MAX_SYMLINKS {
+                return ax_err!(FilesystemLoop);
+            }
+            let target = read_link(&child)?;
+            if target.is_empty() {
+                return ax_err!(NotFound);
+            }
+            // a relative target is resolved from the directory of the link
+            if target.starts_with('/') {
+                loc = root.root_location();
+                node = loc.node()?;
+            }
+            pending.extend(components(&target));
+            continue;
+        }
+        loc = next;
+        node = child;
+    }
+    if must_be_dir && !node.get_attr()?.is_dir() {
+        return ax_err!(NotADirectory);
+    }
+    Ok((loc, Some(node)))
+}
+
+/// Reads the target of the symbolic link `node`.
+pub(crate) fn read_link(node: &VfsNodeRef) -> AxResult<String> {
+    let mut buf = vec![0; PATH_MAX];
+    let len = node.readlink(&mut buf)?;
+    buf.truncate(len);
+    String::from_utf8(buf).map_err(|_| AxError::InvalidData)
+}
+
+/// Splits `path` into its components, in reverse order.
+fn components(path: &str) -> Vec<String> {
+    path.rsplit('/')
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Checks that `dir` is a directory that can be searched with `cred`.
+fn check_searchable(dir: &VfsNodeRef, cred: &Credentials) -> AxResult {
+    let attr = dir.get_attr()?;
+    if !attr.is_dir() {
+        ax_err!(NotADirectory)
+    } else {
+        perm::permission(cred, &attr, Access::EXEC)
+    }
+}
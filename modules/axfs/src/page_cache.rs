@@ -0,0 +1,467 @@
+//! The page cache of file data, shared by file reads and writes and by shared
+//! memory mappings.
+//!
+//! There is at most one [`PageCache`] per file, found by the
+//! [`cache_key`](axfs_vfs::VfsNodeOps::cache_key) of its node. It lives as
+//! long as an opened file or a memory mapping holds it, and keeps the node
+//! alive meanwhile.
+//!
+//! Writes go through to the filesystem and update the cached pages, so the
+//! only dirty pages are the ones written by shared memory mappings. They are
+//! written back by [`PageCache::sync_range`] (`msync`, `fsync`), by
+//! [`sync_all`] (`sync`), periodically by the write-back task, and when the
+//! cache is dropped, adjacent dirty pages in one write. Clean pages that are
+//! not mapped are dropped by [`reclaim`] when memory runs out.
+//!
+//! Sequential reads load the following pages ahead of time with
+//! [`PageCache::read_ahead`], in requests of up to [`MAX_READ_AHEAD`] pages.
+//!
+//! The number of cached pages can be limited through sysfs, with
+//! `/sys/module/axfs/parameters/page_cache_max_pages`. The limit is soft:
+//! clean pages that are not mapped are dropped to make room for a new page,
+//! but dirty and mapped pages are never dropped for it.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axalloc::GlobalPage;
+use axerrno::{AxError, AxResult};
+use axfs_vfs::VfsNodeRef;
+use axhal::mem::{virt_to_phys, PhysAddr, PAGE_SIZE_4K};
+use axsync::Mutex;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+pub(crate) const PAGE_SIZE: u64 = PAGE_SIZE_4K as u64;
+
+/// The most pages read or written back by one request to the filesystem
+/// (128 KiB), also the largest read-ahead window.
+pub(crate) const MAX_READ_AHEAD: u64 = 32;
+
+type CacheKey = (usize, u64);
+
+/// All page caches, by the cache keys of their nodes.
+static PAGE_CACHES: Mutex<BTreeMap<CacheKey, Weak<PageCache>>> = Mutex::new(BTreeMap::new());
+
+/// The number of pages allocated for the page cache.
+static NR_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// The most pages kept in the page cache, zero for no limit.
+static MAX_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// A page of file data in the page cache.
+pub struct CachedPage {
+    frame: GlobalPage,
+    dirty: AtomicBool,
+}
+
+impl CachedPage {
+    fn alloc() -> AxResult<Self> {
+        let max = MAX_PAGES.load(Ordering::Relaxed);
+        let nr = NR_PAGES.load(Ordering::Relaxed);
+        if max != 0 && nr >= max {
+            reclaim(nr + 1 - max);
+        }
+        let frame = GlobalPage::alloc_zero()?;
+        NR_PAGES.fetch_add(1, Ordering::Relaxed);
+        Ok(Self {
+            frame,
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    /// Returns the physical address of the page, to map it into user space.
+    pub fn start_paddr(&self) -> PhysAddr {
+        self.frame.start_paddr(virt_to_phys)
+    }
+
+    /// Marks the page as modified, e.g. when it is mapped writable. It will
+    /// be written back to the file on the next sync.
+    pub fn set_dirty(&self) {
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    /// Whether the page has been modified since it was last written back.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+    }
+
+    fn data(&self) -> &[u8] {
+        self.frame.as_slice()
+    }
+
+    /// The page may also be written through user mappings, so the cache
+    /// never hands out Rust references to it for long.
+    #[allow(clippy::mut_from_ref)]
+    fn data_mut(&self) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(self.frame.as_ptr() as *mut u8, self.frame.size())
+        }
+    }
+}
+
+impl Drop for CachedPage {
+    fn drop(&mut self) {
+        NR_PAGES.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The cached pages of a file, by page index.
+pub struct PageCache {
+    key: CacheKey,
+    node: VfsNodeRef,
+    pages: Mutex<BTreeMap<u64, Arc<CachedPage>>>,
+}
+
+impl PageCache {
+    /// Gets the page cache of the file `node`, creating it if there is none.
+    ///
+    /// Returns `None` if the node is not cached, e.g. a device.
+    pub(crate) fn get(node: &VfsNodeRef) -> Option<Arc<Self>> {
+        let key = node.cache_key()?;
+        let mut caches = PAGE_CACHES.lock();
+        if let Some(cache) = caches.get(&key).and_then(Weak::upgrade) {
+            return Some(cache);
+        }
+        let cache = Arc::new(Self {
+            key,
+            node: node.clone(),
+            pages: Mutex::new(BTreeMap::new()),
+        });
+        caches.insert(key, Arc::downgrade(&cache));
+        Some(cache)
+    }
+
+    /// Gets the page at `index`, reading it from the file if it is not
+    /// cached. The part of the page past the end of the file is zero.
+    pub fn get_page(&self, index: u64) -> AxResult<Arc<CachedPage>> {
+        self.load(&mut self.pages.lock(), index)
+    }
+
+    fn load(
+        &self,
+        pages: &mut BTreeMap<u64, Arc<CachedPage>>,
+        index: u64,
+    ) -> AxResult<Arc<CachedPage>> {
+        if let Some(page) = pages.get(&index) {
+            return Ok(page.clone());
+        }
+        let page = CachedPage::alloc()?;
+        let buf = page.data_mut();
+        let mut filled = 0;
+        while filled < buf.len() {
+            let n = self
+                .node
+                .read_at(index * PAGE_SIZE + filled as u64, &mut buf[filled..])?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        let page = Arc::new(page);
+        pages.insert(index, page.clone());
+        Ok(page)
+    }
+
+    /// Loads the pages in `[first, first + count)` that are not cached yet,
+    /// reading each run of adjacent missing pages from the file at once.
+    ///
+    /// Pages past the end of the file are skipped. It gives up quietly if
+    /// there is no memory for new pages.
+    pub fn read_ahead(&self, first: u64, count: u64) -> AxResult {
+        let size = self.node.get_attr()?.size();
+        let last = (first + count).min(size.div_ceil(PAGE_SIZE));
+        let mut pages = self.pages.lock();
+        let mut index = first;
+        while index < last {
+            if pages.contains_key(&index) {
+                index += 1;
+                continue;
+            }
+            let mut end = index + 1;
+            while end < last && end - index < MAX_READ_AHEAD && !pages.contains_key(&end) {
+                end += 1;
+            }
+            let mut run = Vec::with_capacity((end - index) as usize);
+            for _ in index..end {
+                match CachedPage::alloc() {
+                    Ok(page) => run.push(page),
+                    Err(AxError::NoMemory) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            if run.is_empty() {
+                return Ok(());
+            }
+            let mut buf = alloc::vec![0; run.len() * PAGE_SIZE_4K];
+            let len = buf.len().min((size - index * PAGE_SIZE) as usize);
+            let mut filled = 0;
+            while filled < len {
+                let n = self
+                    .node
+                    .read_at(index * PAGE_SIZE + filled as u64, &mut buf[filled..len])?;
+                if n == 0 {
+                    break;
+                }
+                filled += n;
+            }
+            for (page, data) in run.into_iter().zip(buf.chunks(PAGE_SIZE_4K)) {
+                page.data_mut().copy_from_slice(data);
+                pages.insert(index, Arc::new(page));
+                index += 1;
+            }
+            if index < end {
+                // out of memory
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the file at `offset` through the cache.
+    ///
+    /// Falls back to reading the file directly if there is no memory for new
+    /// pages.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        let size = self.node.get_attr()?.size();
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+        let mut pages = self.pages.lock();
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let start = (pos % PAGE_SIZE) as usize;
+            let n = (PAGE_SIZE as usize - start).min(len - done);
+            match self.load(&mut pages, pos / PAGE_SIZE) {
+                Ok(page) => buf[done..done + n].copy_from_slice(&page.data()[start..start + n]),
+                Err(AxError::NoMemory) => {
+                    return Ok(done + self.node.read_at(pos, &mut buf[done..len])?);
+                }
+                Err(e) => return Err(e),
+            }
+            done += n;
+        }
+        Ok(len)
+    }
+
+    /// Writes the file at `offset`, and updates the cached pages.
+    pub fn write_at(&self, offset: u64, buf: &[u8]) -> AxResult<usize> {
+        let pages = self.pages.lock();
+        let written = self.node.write_at(offset, buf)?;
+        if written == 0 {
+            return Ok(0);
+        }
+        let end = offset + written as u64;
+        for (&index, page) in pages.range(offset / PAGE_SIZE..=(end - 1) / PAGE_SIZE) {
+            let page_start = index * PAGE_SIZE;
+            let start = offset.max(page_start);
+            let stop = end.min(page_start + PAGE_SIZE);
+            page.data_mut()[(start - page_start) as usize..(stop - page_start) as usize]
+                .copy_from_slice(&buf[(start - offset) as usize..(stop - offset) as usize]);
+        }
+        Ok(written)
+    }
+
+    /// Truncates the file to `size`, and drops the cached pages past it.
+    pub fn truncate(&self, size: u64) -> AxResult {
+        let mut pages = self.pages.lock();
+        self.node.truncate(size)?;
+        pages.retain(|&index, _| index * PAGE_SIZE < size);
+        if size % PAGE_SIZE != 0 {
+            if let Some(page) = pages.get(&(size / PAGE_SIZE)) {
+                page.data_mut()[(size % PAGE_SIZE) as usize..].fill(0);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes back the dirty pages overlapping `[start, end)` of the file,
+    /// then flushes the file (`msync`, `fsync`).
+    pub fn sync_range(&self, start: u64, end: u64) -> AxResult {
+        let pages = self.pages.lock();
+        self.write_back(&pages, start / PAGE_SIZE, end.div_ceil(PAGE_SIZE))?;
+        drop(pages);
+        self.node.fsync()
+    }
+
+    /// Writes back all dirty pages, then flushes the file.
+    pub fn sync(&self) -> AxResult {
+        self.sync_range(0, u64::MAX)
+    }
+
+    /// Writes back the dirty pages in `[first, last)`, each run of adjacent
+    /// dirty pages in one write. Only the part of a page inside the file is
+    /// written, the file is never extended.
+    fn write_back(
+        &self,
+        pages: &BTreeMap<u64, Arc<CachedPage>>,
+        first: u64,
+        last: u64,
+    ) -> AxResult {
+        if !pages.range(first..last).any(|(_, page)| page.is_dirty()) {
+            return Ok(());
+        }
+        let size = self.node.get_attr()?.size();
+        let mut run: Vec<&Arc<CachedPage>> = Vec::new();
+        let mut run_start = 0;
+        for (&index, page) in pages.range(first..last) {
+            let dirty = page.is_dirty();
+            let len = run.len() as u64;
+            if len > 0 && (!dirty || index != run_start + len || len >= MAX_READ_AHEAD) {
+                self.write_run(run_start, &run, size)?;
+                run.clear();
+            }
+            if dirty {
+                page.dirty.store(false, Ordering::Release);
+                if run.is_empty() {
+                    run_start = index;
+                }
+                run.push(page);
+            }
+        }
+        if !run.is_empty() {
+            self.write_run(run_start, &run, size)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the adjacent pages `run` starting at page `first`, whose dirty
+    /// flags have been cleared, back to the file of size `size`.
+    fn write_run(&self, first: u64, run: &[&Arc<CachedPage>], size: u64) -> AxResult {
+        let pos = first * PAGE_SIZE;
+        if pos < size {
+            let len = (run.len() as u64 * PAGE_SIZE).min(size - pos) as usize;
+            let res = if run.len() == 1 {
+                self.node.write_at(pos, &run[0].data()[..len])
+            } else {
+                let mut buf = Vec::with_capacity(run.len() * PAGE_SIZE_4K);
+                for page in run {
+                    buf.extend_from_slice(page.data());
+                }
+                self.node.write_at(pos, &buf[..len])
+            };
+            if let Err(e) = res {
+                run.iter().for_each(|page| page.set_dirty());
+                return Err(e);
+            }
+        }
+        // writes through a mapping are not tracked, so a page still mapped
+        // may be modified again
+        for page in run {
+            if Arc::strong_count(page) > 1 {
+                page.set_dirty();
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops at most `max` clean pages that are not mapped anywhere. Returns
+    /// the number of pages dropped.
+    fn evict(&self, max: usize) -> usize {
+        let Some(mut pages) = self.pages.try_lock() else {
+            return 0;
+        };
+        let mut dropped = 0;
+        pages.retain(|_, page| {
+            if dropped < max && Arc::strong_count(page) == 1 && !page.is_dirty() {
+                dropped += 1;
+                false
+            } else {
+                true
+            }
+        });
+        dropped
+    }
+}
+
+impl Drop for PageCache {
+    fn drop(&mut self) {
+        let pages = core::mem::take(self.pages.get_mut());
+        if let Err(e) = self.write_back(&pages, 0, u64::MAX) {
+            warn!("failed to write back cached pages: {:?}", e);
+        }
+        // may be dropped by `reclaim` with the table locked, then the stale
+        // entry is replaced by the next `get`
+        if let Some(mut caches) = PAGE_CACHES.try_lock() {
+            if caches
+                .get(&self.key)
+                .is_some_and(|cache| cache.strong_count() == 0)
+            {
+                caches.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Writes back the dirty pages of all files (`sync`).
+pub(crate) fn sync_all() -> AxResult {
+    let caches: Vec<Arc<PageCache>> = PAGE_CACHES
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect();
+    for cache in caches {
+        let pages = cache.pages.lock();
+        cache.write_back(&pages, 0, u64::MAX)?;
+    }
+    Ok(())
+}
+
+/// Writes back the dirty pages of all files, without flushing the files. It
+/// is run periodically by the write-back task, and keeps going on errors.
+#[cfg(feature = "writeback")]
+pub(crate) fn write_back_all() {
+    let caches: Vec<Arc<PageCache>> = PAGE_CACHES
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .collect();
+    for cache in caches {
+        let pages = cache.pages.lock();
+        if let Err(e) = cache.write_back(&pages, 0, u64::MAX) {
+            warn!("failed to write back cached pages: {:?}", e);
+        }
+    }
+}
+
+/// The number of pages in the page cache, including the pages dropped from
+/// it that are still mapped.
+#[cfg(any(feature = "procfs", feature = "sysfs"))]
+pub(crate) fn cached_pages() -> usize {
+    NR_PAGES.load(Ordering::Relaxed)
+}
+
+/// The most pages kept in the page cache, zero for no limit.
+#[cfg(feature = "sysfs")]
+pub(crate) fn max_pages() -> usize {
+    MAX_PAGES.load(Ordering::Relaxed)
+}
+
+/// Limits the page cache to `max` pages, zero for no limit. Clean pages are
+/// dropped at once if there are more.
+#[cfg(feature = "sysfs")]
+pub(crate) fn set_max_pages(max: usize) {
+    MAX_PAGES.store(max, Ordering::Relaxed);
+    let nr = NR_PAGES.load(Ordering::Relaxed);
+    if max != 0 && nr > max {
+        reclaim(nr - max);
+    }
+}
+
+/// Drops clean pages of the page cache to free memory, registered to the
+/// global allocator as an [`axalloc::ReclaimFn`].
+pub(crate) fn reclaim(num_pages: usize) -> usize {
+    // locks are only tried, the allocating task may hold them
+    let Some(caches) = PAGE_CACHES.try_lock() else {
+        return 0;
+    };
+    let mut freed = 0;
+    for cache in caches.values().filter_map(Weak::upgrade) {
+        if freed >= num_pages {
+            break;
+        }
+        freed += cache.evict(num_pages - freed);
+    }
+    freed
+}
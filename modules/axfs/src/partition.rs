@@ -0,0 +1,386 @@
+//! Partition tables: MBR (with extended and logical partitions) and GPT.
+//!
+//! Partitions are numbered like Linux: MBR primary partitions are 1-4 by
+//! their slot, logical partitions start from 5, and GPT partitions are
+//! numbered by their entry index starting from 1.
+
+use alloc::vec::Vec;
+
+/// The sector size assumed by the partition tables.
+pub const SECTOR_SIZE: u64 = 512;
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const MBR_ENTRIES_OFFSET: usize = 446;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xee;
+const MBR_TYPES_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+/// Guards against loops in a corrupted chain of logical partitions.
+const MAX_LOGICAL_PARTITIONS: usize = 128;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_HEADER_MIN_SIZE: usize = 92;
+const GPT_MAX_ENTRIES: u32 = 1024;
+const GPT_MAX_ENTRY_SIZE: usize = 4096;
+
+/// A partition on a disk, with its offset and length in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Partition {
+    /// The partition number, e.g. 1 for `vda1`.
+    pub number: usize,
+    /// The offset of the first byte.
+    pub start: u64,
+    /// The length in bytes.
+    pub size: u64,
+}
+
+/// Reads the partition table of a disk of `disk_size` bytes.
+///
+/// `read(offset, buf)` fills `buf` from the disk and returns `false` on error.
+/// Returns an empty list if the disk has no valid partition table.
+pub fn read_partitions<F>(disk_size: u64, mut read: F) -> Vec<Partition>
+where
+    F: FnMut(u64, &mut [u8]) -> bool,
+{
+    let mut mbr = [0u8; SECTOR_SIZE as usize];
+    if !read(0, &mut mbr) || mbr[510..512] != MBR_SIGNATURE {
+        return Vec::new();
+    }
+    let entries: Vec<MbrEntry> = (0..4).map(|i| MbrEntry::parse(&mbr, i)).collect();
+    if entries.iter().any(|e| e.ty == MBR_TYPE_GPT_PROTECTIVE) {
+        return read_gpt(disk_size, &mut read).unwrap_or_default();
+    }
+
+    let mut parts = Vec::new();
+    let mut logical_number = 5;
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.is_empty() {
+            continue;
+        }
+        if MBR_TYPES_EXTENDED.contains(&entry.ty) {
+            read_logical(entry.start_lba, disk_size, &mut read, &mut logical_number, &mut parts);
+        } else {
+            push_checked(&mut parts, i + 1, entry.start_lba, entry.num_sectors, disk_size);
+        }
+    }
+    parts.sort_by_key(|part| part.number);
+    parts
+}
+
+/// An entry of the MBR or an EBR.
+struct MbrEntry {
+    ty: u8,
+    start_lba: u64,
+    num_sectors: u64,
+}
+
+impl MbrEntry {
+    fn parse(sector: &[u8], idx: usize) -> Self {
+        let entry = &sector[MBR_ENTRIES_OFFSET + idx * 16..MBR_ENTRIES_OFFSET + (idx + 1) * 16];
+        Self {
+            ty: entry[4],
+            start_lba: read_u32(entry, 8) as u64,
+            num_sectors: read_u32(entry, 12) as u64,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ty == 0 || self.num_sectors == 0
+    }
+}
+
+/// Walks the chain of EBRs in the extended partition starting at
+/// `ext_start`. The logical partition of each EBR is relative to that EBR,
+/// and the link to the next EBR is relative to the extended partition.
+/// The chain ends at the first EBR that cannot be read.
+fn read_logical<F>(
+    ext_start: u64,
+    disk_size: u64,
+    read: &mut F,
+    number: &mut usize,
+    parts: &mut Vec<Partition>,
+) where
+    F: FnMut(u64, &mut [u8]) -> bool,
+{
+    let mut ebr_lba = ext_start;
+    for _ in 0..MAX_LOGICAL_PARTITIONS {
+        let mut ebr = [0u8; SECTOR_SIZE as usize];
+        let Some(offset) = ebr_lba.checked_mul(SECTOR_SIZE) else {
+            return;
+        };
+        if !read(offset, &mut ebr) || ebr[510..512] != MBR_SIGNATURE {
+            return;
+        }
+        let logical = MbrEntry::parse(&ebr, 0);
+        if !logical.is_empty() {
+            match ebr_lba.checked_add(logical.start_lba) {
+                Some(start) => {
+                    if push_checked(parts, *number, start, logical.num_sectors, disk_size) {
+                        *number += 1;
+                    }
+                }
+                None => warn!("partition {} is out of the disk, ignored", number),
+            }
+        }
+        let next = MbrEntry::parse(&ebr, 1);
+        if next.is_empty() || !MBR_TYPES_EXTENDED.contains(&next.ty) {
+            return;
+        }
+        let Some(next_lba) = ext_start.checked_add(next.start_lba) else {
+            return;
+        };
+        ebr_lba = next_lba;
+    }
+    warn!("too many logical partitions, the chain may be corrupted");
+}
+
+/// Reads the GPT from the primary header, or from the backup header at the
+/// end of the disk if the primary one is corrupted.
+fn read_gpt<F>(disk_size: u64, read: &mut F) -> Option<Vec<Partition>>
+where
+    F: FnMut(u64, &mut [u8]) -> bool,
+{
+    let last_lba = (disk_size / SECTOR_SIZE).checked_sub(1)?;
+    read_gpt_at(1, disk_size, read).or_else(|| {
+        warn!("the primary GPT header is corrupted, trying the backup");
+        read_gpt_at(last_lba, disk_size, read)
+    })
+}
+
+fn read_gpt_at<F>(header_lba: u64, disk_size: u64, read: &mut F) -> Option<Vec<Partition>>
+where
+    F: FnMut(u64, &mut [u8]) -> bool,
+{
+    let mut header = [0u8; SECTOR_SIZE as usize];
+    if !read(header_lba * SECTOR_SIZE, &mut header) || &header[..8] != GPT_SIGNATURE {
+        return None;
+    }
+    let header_size = read_u32(&header, 12) as usize;
+    if !(GPT_HEADER_MIN_SIZE..=header.len()).contains(&header_size) {
+        return None;
+    }
+    let header_crc = read_u32(&header, 16);
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != header_crc || read_u64(&header, 24) != header_lba {
+        return None;
+    }
+
+    let entries_lba = read_u64(&header, 72);
+    let num_entries = read_u32(&header, 80);
+    let entry_size = read_u32(&header, 84) as usize;
+    let entries_crc = read_u32(&header, 88);
+    if num_entries > GPT_MAX_ENTRIES
+        || !(128..=GPT_MAX_ENTRY_SIZE).contains(&entry_size)
+        || entry_size % 8 != 0
+    {
+        return None;
+    }
+    let entries_offset = entries_lba.checked_mul(SECTOR_SIZE)?;
+    let mut entries = alloc::vec![0u8; num_entries as usize * entry_size];
+    if !read(entries_offset, &mut entries) || crc32(&entries) != entries_crc {
+        return None;
+    }
+
+    let mut parts = Vec::new();
+    for (i, entry) in entries.chunks_exact(entry_size).enumerate() {
+        // an all-zero partition type GUID marks an unused entry
+        if entry[..16].iter().all(|&b| b == 0) {
+            continue;
+        }
+        let first_lba = read_u64(entry, 32);
+        let last_lba = read_u64(entry, 40);
+        let Some(num_sectors) = last_lba
+            .checked_sub(first_lba)
+            .and_then(|n| n.checked_add(1))
+        else {
+            continue;
+        };
+        push_checked(&mut parts, i + 1, first_lba, num_sectors, disk_size);
+    }
+    Some(parts)
+}
+
+/// Adds a partition if it lies within the disk. Returns whether it is added.
+fn push_checked(
+    parts: &mut Vec<Partition>,
+    number: usize,
+    start_lba: u64,
+    num_sectors: u64,
+    disk_size: u64,
+) -> bool {
+    let start = start_lba.saturating_mul(SECTOR_SIZE);
+    let size = num_sectors.saturating_mul(SECTOR_SIZE);
+    if start == 0 || start.saturating_add(size) > disk_size {
+        warn!("partition {} is out of the disk, ignored", number);
+        return false;
+    }
+    parts.push(Partition {
+        number,
+        start,
+        size,
+    });
+    true
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+/// The CRC-32 (IEEE 802.3) used by GPT.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISK_SECTORS: u64 = 256;
+
+    fn new_disk() -> Vec<u8> {
+        alloc::vec![0; (DISK_SECTORS * SECTOR_SIZE) as usize]
+    }
+
+    fn read_from(disk: &[u8]) -> impl FnMut(u64, &mut [u8]) -> bool + '_ {
+        |offset, buf| {
+            let Some(src) = usize::try_from(offset)
+                .ok()
+                .and_then(|start| disk.get(start..start.checked_add(buf.len())?))
+            else {
+                return false;
+            };
+            buf.copy_from_slice(src);
+            true
+        }
+    }
+
+    fn partitions(disk: &[u8]) -> Vec<(usize, u64, u64)> {
+        read_partitions(disk.len() as u64, read_from(disk))
+            .into_iter()
+            .map(|p| (p.number, p.start / SECTOR_SIZE, p.size / SECTOR_SIZE))
+            .collect()
+    }
+
+    /// Writes the `idx`-th entry of the MBR or EBR at `lba` and its signature.
+    fn set_mbr_entry(disk: &mut [u8], lba: u64, idx: usize, ty: u8, start: u32, len: u32) {
+        let sector = &mut disk[(lba * SECTOR_SIZE) as usize..][..SECTOR_SIZE as usize];
+        let entry = &mut sector[MBR_ENTRIES_OFFSET + idx * 16..][..16];
+        entry[4] = ty;
+        entry[8..12].copy_from_slice(&start.to_le_bytes());
+        entry[12..16].copy_from_slice(&len.to_le_bytes());
+        sector[510..512].copy_from_slice(&MBR_SIGNATURE);
+    }
+
+    /// Writes a GPT header at `header_lba` whose 4 entries at `entries_lba`
+    /// hold `parts` as `(index, first_lba, last_lba)`.
+    fn write_gpt(disk: &mut [u8], header_lba: u64, entries_lba: u64, parts: &[(usize, u64, u64)]) {
+        let mut entries = [0u8; 4 * 128];
+        for &(i, first, last) in parts {
+            let entry = &mut entries[i * 128..][..128];
+            entry[..16].fill(0xaf);
+            entry[32..40].copy_from_slice(&first.to_le_bytes());
+            entry[40..48].copy_from_slice(&last.to_le_bytes());
+        }
+        // the entries of a table out of the disk are left out
+        let offset = entries_lba.checked_mul(SECTOR_SIZE).map(|o| o as usize);
+        if let Some(dst) = offset.and_then(|o| disk.get_mut(o..o + entries.len())) {
+            dst.copy_from_slice(&entries);
+        }
+
+        let mut header = [0u8; GPT_HEADER_MIN_SIZE];
+        header[..8].copy_from_slice(GPT_SIGNATURE);
+        header[12..16].copy_from_slice(&(GPT_HEADER_MIN_SIZE as u32).to_le_bytes());
+        header[24..32].copy_from_slice(&header_lba.to_le_bytes());
+        header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+        header[80..84].copy_from_slice(&4u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+        header[88..92].copy_from_slice(&crc32(&entries).to_le_bytes());
+        let crc = crc32(&header);
+        header[16..20].copy_from_slice(&crc.to_le_bytes());
+        let offset = (header_lba * SECTOR_SIZE) as usize;
+        disk[offset..offset + header.len()].copy_from_slice(&header);
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_mbr() {
+        let mut disk = new_disk();
+        assert!(partitions(&disk).is_empty());
+
+        set_mbr_entry(&mut disk, 0, 0, 0x83, 1, 99);
+        set_mbr_entry(&mut disk, 0, 2, 0x83, 100, 50);
+        // past the end of the disk
+        set_mbr_entry(&mut disk, 0, 3, 0x83, 200, 100);
+        assert_eq!(partitions(&disk), [(1, 1, 99), (3, 100, 50)]);
+    }
+
+    #[test]
+    fn test_ebr_chain() {
+        let mut disk = new_disk();
+        set_mbr_entry(&mut disk, 0, 0, 0x83, 1, 63);
+        set_mbr_entry(&mut disk, 0, 1, 0x05, 64, 192);
+        // each logical partition is relative to its EBR, and each link to
+        // the next EBR is relative to the extended partition
+        set_mbr_entry(&mut disk, 64, 0, 0x83, 1, 31);
+        set_mbr_entry(&mut disk, 64, 1, 0x05, 32, 64);
+        set_mbr_entry(&mut disk, 96, 0, 0x83, 2, 60);
+        set_mbr_entry(&mut disk, 96, 1, 0x05, 100, 10);
+        set_mbr_entry(&mut disk, 164, 0, 0x83, 1, 9);
+        assert_eq!(
+            partitions(&disk),
+            [(1, 1, 63), (5, 65, 31), (6, 98, 60), (7, 165, 9)]
+        );
+
+        // a link out of the disk ends the chain
+        set_mbr_entry(&mut disk, 96, 1, 0x05, u32::MAX, 10);
+        assert_eq!(partitions(&disk), [(1, 1, 63), (5, 65, 31), (6, 98, 60)]);
+    }
+
+    #[test]
+    fn test_gpt() {
+        let mut disk = new_disk();
+        set_mbr_entry(&mut disk, 0, 0, MBR_TYPE_GPT_PROTECTIVE, 1, u32::MAX);
+        write_gpt(&mut disk, 1, 2, &[(0, 34, 99), (2, 100, 199), (3, 300, 399)]);
+        // the entry out of the disk is skipped
+        assert_eq!(partitions(&disk), [(1, 34, 66), (3, 100, 100)]);
+
+        // an entry whose size overflows is skipped too
+        write_gpt(&mut disk, 1, 2, &[(0, 0, u64::MAX), (1, 34, 99)]);
+        assert_eq!(partitions(&disk), [(2, 34, 66)]);
+
+        // so is a table whose offset overflows
+        write_gpt(&mut disk, 1, u64::MAX / 256, &[(0, 34, 99)]);
+        assert!(partitions(&disk).is_empty());
+    }
+
+    #[test]
+    fn test_gpt_crc_mismatch() {
+        let mut disk = new_disk();
+        set_mbr_entry(&mut disk, 0, 0, MBR_TYPE_GPT_PROTECTIVE, 1, u32::MAX);
+        write_gpt(&mut disk, 1, 2, &[(0, 34, 99)]);
+        // corrupt the entries after their CRC is computed
+        disk[2 * SECTOR_SIZE as usize + 32] ^= 1;
+        assert!(partitions(&disk).is_empty());
+
+        // fall back to the backup header at the last sector
+        write_gpt(&mut disk, DISK_SECTORS - 1, DISK_SECTORS - 5, &[(1, 40, 99)]);
+        assert_eq!(partitions(&disk), [(2, 40, 60)]);
+
+        // a corrupted header is rejected as well
+        disk[(DISK_SECTORS - 1) as usize * SECTOR_SIZE as usize + 72] ^= 1;
+        assert!(partitions(&disk).is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+//! Permission checks against the credentials of the calling process.
+//!
+//! The filesystem doesn't know about processes: the process module registers
+//! with [`register_credentials`] the function returning the credentials of
+//! the caller, and everything runs as root until it does.
+//!
+//! Every check goes through [`permission`], which calls the hook registered
+//! with [`register_permission_hook`], by default [`generic_permission`] that
+//! checks the owner, group and other bits of the mode. A hook adding ACLs or
+//! a security module wraps [`generic_permission`].
+
+use alloc::vec::Vec;
+use axerrno::{ax_err, AxResult};
+use axfs_vfs::{VfsNodeAttr, VfsNodePerm, VfsNodeRef};
+use axsync::Mutex;
+use bitflags::bitflags;
+
+bitflags! {
+    /// The kind of access checked by [`permission`], as the `MAY_*` masks of
+    /// Linux and the `mode` of `access(2)`.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Access: u32 {
+        /// Execute a file, or search a directory.
+        const EXEC = 1;
+        /// Write a file, or add and remove the entries of a directory.
+        const WRITE = 2;
+        /// Read a file, or list a directory.
+        const READ = 4;
+    }
+}
+
+/// The identity a process accesses the files with: the effective user and
+/// group IDs, and the supplementary groups.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Credentials {
+    /// The effective user ID.
+    pub uid: u32,
+    /// The effective group ID.
+    pub gid: u32,
+    /// The supplementary group IDs.
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    /// The credentials of the superuser.
+    pub const fn root() -> Self {
+        Self {
+            uid: 0,
+            gid: 0,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Whether these are the credentials of the superuser, which bypasses
+    /// the permission bits.
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+
+    /// Whether `gid` is the group or one of the supplementary groups.
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.gid == gid || self.groups.contains(&gid)
+    }
+}
+
+/// Returns the credentials of the calling process.
+pub type CredentialsFn = fn() -> Credentials;
+
+/// Decides whether `cred` may access the node with the attributes `attr`,
+/// failing with `PermissionDenied` if not.
+pub type PermissionHook = fn(&Credentials, &VfsNodeAttr, Access) -> AxResult;
+
+static CREDENTIALS: Mutex<Option<CredentialsFn>> = Mutex::new(None);
+
+static PERMISSION_HOOK: Mutex<PermissionHook> = Mutex::new(generic_permission);
+
+/// Registers the function returning the credentials of the calling process.
+/// A later registration replaces the earlier one.
+pub fn register_credentials(f: CredentialsFn) {
+    *CREDENTIALS.lock() = Some(f);
+}
+
+/// Registers the hook deciding all the accesses, replacing
+/// [`generic_permission`] or the hook registered earlier.
+pub fn register_permission_hook(hook: PermissionHook) {
+    *PERMISSION_HOOK.lock() = hook;
+}
+
+/// Returns the credentials of the calling process, those of root if no
+/// process module registered them.
+pub fn current_credentials() -> Credentials {
+    let f = *CREDENTIALS.lock();
+    f.map_or_else(Credentials::root, |f| f())
+}
+
+/// Checks that `cred` may access the node with the attributes `attr`, with
+/// the registered permission hook.
+pub fn permission(cred: &Credentials, attr: &VfsNodeAttr, access: Access) -> AxResult {
+    let hook = *PERMISSION_HOOK.lock();
+    hook(cred, attr, access)
+}
+
+/// Checks the permission bits of the owner, the group or the others, the
+/// first class `cred` belongs to. Root may read and write anything, and
+/// execute the files with an execute bit and all the directories.
+pub fn generic_permission(cred: &Credentials, attr: &VfsNodeAttr, access: Access) -> AxResult {
+    let mode = attr.perm().bits() as u32;
+    if cred.is_root() {
+        if !access.contains(Access::EXEC) || attr.is_dir() || mode & 0o111 != 0 {
+            return Ok(());
+        }
+        return ax_err!(PermissionDenied);
+    }
+    let granted = if cred.uid == attr.uid() {
+        mode >> 6
+    } else if cred.in_group(attr.gid()) {
+        mode >> 3
+    } else {
+        mode
+    };
+    if Access::from_bits_truncate(granted & 0o7).contains(access) {
+        Ok(())
+    } else {
+        ax_err!(PermissionDenied)
+    }
+}
+
+/// Checks that the caller may access `node`.
+pub(crate) fn check(node: &VfsNodeRef, access: Access) -> AxResult {
+    permission(&current_credentials(), &node.get_attr()?, access)
+}
+
+/// Checks that the caller may add an entry to the directory `dir`.
+pub(crate) fn may_create(dir: &VfsNodeRef) -> AxResult {
+    check(dir, Access::WRITE | Access::EXEC)
+}
+
+/// Checks that the caller may remove `victim` from the directory `dir`. In a
+/// sticky directory, only the owner of the file or of the directory may.
+pub(crate) fn may_delete(dir: &VfsNodeRef, victim: &VfsNodeRef) -> AxResult {
+    let cred = current_credentials();
+    let dir_attr = dir.get_attr()?;
+    permission(&cred, &dir_attr, Access::WRITE | Access::EXEC)?;
+    if dir_attr.perm().contains(VfsNodePerm::STICKY)
+        && !cred.is_root()
+        && cred.uid != dir_attr.uid()
+        && cred.uid != victim.get_attr()?.uid()
+    {
+        return ax_err!(OperationNotPermitted);
+    }
+    Ok(())
+}
+
+/// Makes the caller the owner of the new node `node` in the directory
+/// `dir`. The group is that of `dir` if it has the set-group-ID bit, which
+/// new directories inherit, as in Linux.
+pub(crate) fn init_owner(dir: &VfsNodeRef, node: &VfsNodeRef) -> AxResult {
+    let cred = current_credentials();
+    let dir_attr = dir.get_attr()?;
+    let setgid = dir_attr.perm().contains(VfsNodePerm::SET_GID);
+    if cred.is_root() && cred.gid == 0 && !setgid {
+        // already owned by root
+        return Ok(());
+    }
+    let gid = if setgid { dir_attr.gid() } else { cred.gid };
+    match node.set_owner(Some(cred.uid), Some(gid)) {
+        // the filesystem has no owners
+        Ok(()) | Err(axerrno::AxError::Unsupported) => {}
+        Err(e) => return Err(e),
+    }
+    let attr = node.get_attr()?;
+    if setgid && attr.is_dir() {
+        node.set_perm(attr.perm() | VfsNodePerm::SET_GID).ok();
+    }
+    Ok(())
+}
@@ -1,114 +1,452 @@
-//! Root directory of the filesystem
+//! Root directory of the filesystem, and the tree of mounted filesystems.
 //!
-//! TODO: it doesn't work very well if the mount points have containment relationships.
+//! Every mount is attached to a directory of the mount it is mounted on
+//! (its parent), and paths are resolved component by component across the
+//! mount points. This supports nested mounts, several mounts stacked on the
+//! same directory (the last one is visible), mounts hiding the contents of a
+//! non-empty directory, and bind mounts showing a directory of a mounted
+//! filesystem at another place.
 
-use alloc::{
-    string::{String, ToString},
-    sync::Arc,
-    vec::Vec,
-};
+use alloc::{format, string::String, sync::Arc, vec::Vec};
 use axerrno::{ax_err, AxError, AxResult};
-use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
+use axfs_vfs::{
+    DeviceId, VfsDirEntry, VfsNodeAttr, VfsNodeFlags, VfsNodeOps, VfsNodePerm, VfsNodeRef,
+    VfsNodeType, VfsOps, VfsResult,
+};
 use axsync::Mutex;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
 use lazy_init::LazyInit;
 
-use crate::{api::FileType, fs, mounts};
+use crate::{
+    api::{FileType, UmountFlags},
+    dev::BlockDevNode,
+    fs,
+    inotify::{self, InotifyMask},
+    mounts,
+    namei::{self, LookupFlags, ResolvedPath},
+    perm::{self, Access},
+};
 
 static CURRENT_DIR_PATH: Mutex<String> = Mutex::new(String::new());
-static CURRENT_DIR: LazyInit<Mutex<VfsNodeRef>> = LazyInit::new();
 
-struct MountPoint {
-    path: &'static str,
+/// Mounts detached lazily (or forcibly) while their filesystems are still in
+/// use. The filesystems are unmounted once their last node is released.
+static DETACHED: Mutex<Vec<Arc<Mount>>> = Mutex::new(Vec::new());
+
+static NEXT_MOUNT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// A filesystem (or a directory of it, for a bind mount) attached to a
+/// directory in the tree.
+struct Mount {
+    /// The unique ID shown in `/proc/self/mountinfo`.
+    id: usize,
+    /// The mount this one is attached to, `None` for the root mount.
+    parent: Option<Arc<Mount>>,
+    /// The directory this mount is attached to, relative to the root of the
+    /// parent mount. It is empty if the mount is stacked on the parent.
+    mountpoint: String,
+    /// The absolute path of the mount at the time it was mounted.
+    path: String,
     fs: Arc<dyn VfsOps>,
+    /// The directory of `fs` shown at the root of the mount, relative to
+    /// the root of `fs`. It is empty except for bind mounts.
+    root: String,
+    source: String,
+    fs_type: String,
+    options: Mutex<String>,
 }
 
-struct RootDirectory {
-    main_fs: Arc<dyn VfsOps>,
-    mounts: Vec<MountPoint>,
-}
+impl Mount {
+    fn new(
+        parent: Option<(Arc<Mount>, String)>,
+        path: String,
+        fs: Arc<dyn VfsOps>,
+        root: String,
+        source: &str,
+        fs_type: &str,
+        options: &str,
+    ) -> Arc<Self> {
+        let (parent, mountpoint) = parent.map_or((None, String::new()), |(p, m)| (Some(p), m));
+        Arc::new(Self {
+            id: NEXT_MOUNT_ID.fetch_add(1, Ordering::Relaxed),
+            parent,
+            mountpoint,
+            path,
+            fs,
+            root,
+            source: source.into(),
+            fs_type: fs_type.into(),
+            options: Mutex::new(options.into()),
+        })
+    }
 
-static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
+    /// Returns `rel` (relative to the root of the mount) as a path relative
+    /// to the root of the filesystem.
+    fn fs_path(&self, rel: &str) -> String {
+        match (self.root.is_empty(), rel.is_empty()) {
+            (_, true) => self.root.clone(),
+            (true, false) => rel.into(),
+            (false, false) => format!("{}/{}", self.root, rel),
+        }
+    }
 
-impl MountPoint {
-    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
-        Self { path, fs }
+    /// Looks up `rel`, relative to the root of the mount.
+    fn lookup(&self, rel: &str) -> VfsResult<VfsNodeRef> {
+        let path = self.fs_path(rel);
+        let root = self.fs.root_dir();
+        if path.is_empty() {
+            Ok(root)
+        } else {
+            root.lookup(&path)
+        }
     }
+
+    fn is_child_of(&self, parent: &Arc<Mount>) -> bool {
+        self.parent
+            .as_ref()
+            .map_or(false, |p| Arc::ptr_eq(p, parent))
+    }
+}
+
+/// An entry of the mount table.
+#[derive(Debug, Clone)]
+pub struct MountInfo {
+    /// The unique ID of the mount.
+    pub id: usize,
+    /// The ID of the mount it is attached to, or its own ID for the root.
+    pub parent_id: usize,
+    /// The device or other source it was mounted from, e.g. `/dev/vda`.
+    pub source: String,
+    /// The absolute path of the mount point.
+    pub target: String,
+    /// The filesystem type, e.g. `ext4`.
+    pub fs_type: String,
+    /// The comma-separated mount options.
+    pub options: String,
+    /// The directory of the filesystem shown at the mount point, `/` unless
+    /// it is a bind mount.
+    pub root: String,
 }
 
-impl Drop for MountPoint {
-    fn drop(&mut self) {
-        self.fs.umount().ok();
+/// A place in the directory tree: a path relative to the root of a mount.
+#[derive(Clone)]
+pub(crate) struct Location {
+    mount: Arc<Mount>,
+    rel: String,
+}
+
+impl Location {
+    /// Looks up the node at this location.
+    pub(crate) fn node(&self) -> VfsResult<VfsNodeRef> {
+        self.mount.lookup(&self.rel)
+    }
+
+    /// Returns the absolute path of this location.
+    pub(crate) fn path(&self) -> String {
+        let mut names = Vec::new();
+        let mut mount = &self.mount;
+        let mut rel = self.rel.as_str();
+        loop {
+            if !rel.is_empty() {
+                names.push(rel);
+            }
+            match &mount.parent {
+                Some(parent) => {
+                    rel = &mount.mountpoint;
+                    mount = parent;
+                }
+                None => break,
+            }
+        }
+        names.reverse();
+        format!("/{}", names.join("/"))
     }
 }
 
+pub(crate) struct RootDirectory {
+    main_fs: Arc<dyn VfsOps>,
+    /// All mounts in the order they were mounted, the root mount first.
+    mounts: Mutex<Vec<Arc<Mount>>>,
+}
+
+static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
+
 impl RootDirectory {
-    pub const fn new(main_fs: Arc<dyn VfsOps>) -> Self {
+    pub fn new(main_fs: Arc<dyn VfsOps>, source: &str, fs_type: &str) -> Self {
+        let root = Mount::new(
+            None,
+            "/".into(),
+            main_fs.clone(),
+            String::new(),
+            source,
+            fs_type,
+            "rw",
+        );
         Self {
             main_fs,
-            mounts: Vec::new(),
+            mounts: Mutex::new(alloc::vec![root]),
+        }
+    }
+
+    /// Returns the location of the root directory.
+    pub(crate) fn root_location(&self) -> Location {
+        Location {
+            mount: self.mounts.lock()[0].clone(),
+            rel: String::new(),
+        }
+    }
+
+    /// Returns the location of the entry `name` in the directory at `loc`.
+    /// If a filesystem is mounted there, it is the root of the topmost one.
+    pub(crate) fn child(&self, loc: &Location, name: &str) -> Location {
+        let mut mount = loc.mount.clone();
+        let mut rel = loc.rel.clone();
+        if !rel.is_empty() {
+            rel.push('/');
+        }
+        rel.push_str(name);
+        let mounts = self.mounts.lock();
+        while let Some(child) = mounts
+            .iter()
+            .find(|m| m.is_child_of(&mount) && m.mountpoint == rel)
+        {
+            mount = child.clone();
+            rel.clear();
+        }
+        Location { mount, rel }
+    }
+
+    /// Returns the location of the parent directory of `loc`. The parent of
+    /// the root of a mount is the parent of the directory it is mounted on,
+    /// and the parent of the root directory is itself.
+    pub(crate) fn parent(&self, loc: &Location) -> Location {
+        let mut mount = loc.mount.clone();
+        let mut rel = loc.rel.clone();
+        while rel.is_empty() {
+            match mount.parent.clone() {
+                Some(parent) => {
+                    rel = mount.mountpoint.clone();
+                    mount = parent;
+                }
+                None => break,
+            }
         }
+        rel.truncate(rel.rfind('/').unwrap_or(0));
+        Location { mount, rel }
     }
 
-    pub fn mount(&mut self, path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
+    /// Resolves `path` from the root directory, see [`namei::walk`].
+    fn walk(&self, path: &str, flags: LookupFlags) -> AxResult<(Location, Option<VfsNodeRef>)> {
+        namei::walk(self, self.root_location(), path, flags)
+    }
+
+    /// Mounts `fs` on the directory `path`, which is created if it does not
+    /// exist. `root` is the directory of `fs` to show, empty for the whole
+    /// filesystem.
+    fn mount(
+        &self,
+        path: &str,
+        fs: Arc<dyn VfsOps>,
+        root: String,
+        source: &str,
+        fs_type: &str,
+        options: &str,
+    ) -> AxResult {
         if path == "/" {
             return ax_err!(InvalidInput, "cannot mount root filesystem");
         }
         if !path.starts_with('/') {
             return ax_err!(InvalidInput, "mount path must start with '/'");
         }
-        if self.mounts.iter().any(|mp| mp.path == path) {
-            return ax_err!(InvalidInput, "mount point already exists");
+        let (loc, node) = self.walk(path, LookupFlags::CREATE)?;
+        // create the mount point if it does not exist
+        let mount_point = match node {
+            Some(node) => node,
+            None => {
+                let parent = &loc.mount;
+                parent
+                    .fs
+                    .root_dir()
+                    .create(&parent.fs_path(&loc.rel), FileType::Dir)?;
+                loc.node()?
+            }
+        };
+        if !mount_point.get_attr()?.is_dir() {
+            return ax_err!(NotADirectory);
+        }
+        let path = loc.path();
+        if root.is_empty() {
+            fs.mount(&path, mount_point)?;
         }
-        // create the mount point in the main filesystem if it does not exist
-        self.main_fs.root_dir().create(path, FileType::Dir)?;
-        fs.mount(path, self.main_fs.root_dir().lookup(path)?)?;
-        self.mounts.push(MountPoint::new(path, fs));
+        let mount = Mount::new(
+            Some((loc.mount, loc.rel)),
+            path,
+            fs,
+            root,
+            source,
+            fs_type,
+            options,
+        );
+        self.mounts.lock().push(mount);
         Ok(())
     }
 
-    pub fn _umount(&mut self, path: &str) {
-        self.mounts.retain(|mp| mp.path != path);
+    /// Mounts the directory `src` at `path`, like `mount --bind`. The mounts
+    /// below `src` are not included.
+    fn bind(&self, src: &str, path: &str) -> AxResult {
+        let (
+            Location {
+                mount: src_mnt,
+                rel,
+            },
+            _,
+        ) = self.walk(src, LookupFlags::DIRECTORY)?;
+        let options = src_mnt.options.lock().clone();
+        self.mount(
+            path,
+            src_mnt.fs.clone(),
+            src_mnt.fs_path(&rel),
+            &src_mnt.source,
+            &src_mnt.fs_type,
+            &options,
+        )
     }
 
-    pub fn contains(&self, path: &str) -> bool {
-        self.mounts.iter().any(|mp| mp.path == path)
+    /// Unmounts the topmost mount at `path`.
+    ///
+    /// A busy filesystem, a mount holding the current directory, or a mount
+    /// with other mounts below it, is refused with `EBUSY` unless
+    /// `MNT_DETACH` or `MNT_FORCE` is given:
+    ///
+    /// - `MNT_DETACH` removes it (and the mounts below it) from the tree now,
+    ///   and unmounts the filesystem after its last node is released.
+    /// - `MNT_FORCE` flushes and unmounts the filesystem now. The nodes still
+    ///   in use can only be read afterwards.
+    ///
+    /// A filesystem also mounted elsewhere, e.g. by a bind mount, is only
+    /// detached from `path`.
+    pub fn umount(&self, path: &str, flags: UmountFlags) -> AxResult {
+        let (Location { mount: target, rel }, _) = self.walk(path, LookupFlags::empty())?;
+        if !rel.is_empty() || target.parent.is_none() {
+            return ax_err!(InvalidInput, "not a mount point");
+        }
+        // the current directory only records a path, so check it here
+        let cwd = CURRENT_DIR_PATH.lock().clone();
+        let holds_cwd = self
+            .walk(&cwd, LookupFlags::empty())
+            .is_ok_and(|(loc, _)| Arc::ptr_eq(&loc.mount, &target));
+        let mut mounts = self.mounts.lock();
+        let nested = mounts.iter().any(|m| m.is_child_of(&target));
+        if nested && !flags.contains(UmountFlags::DETACH) {
+            return ax_err!(ResourceBusy);
+        }
+        let shared = mounts
+            .iter()
+            .any(|m| !Arc::ptr_eq(m, &target) && Arc::ptr_eq(&m.fs, &target.fs));
+        if (holds_cwd || (!shared && target.fs.is_busy()))
+            && !flags.intersects(UmountFlags::DETACH | UmountFlags::FORCE)
+        {
+            return ax_err!(ResourceBusy);
+        }
+        // remove the mount and all mounts below it
+        let mut removed = alloc::vec![target];
+        let mut i = 0;
+        while i < removed.len() {
+            let parent = removed[i].clone();
+            let (below, kept): (Vec<_>, Vec<_>) = core::mem::take(&mut *mounts)
+                .into_iter()
+                .partition(|m| Arc::ptr_eq(m, &parent) || m.is_child_of(&parent));
+            *mounts = kept;
+            removed.extend(below.into_iter().filter(|m| !Arc::ptr_eq(m, &parent)));
+            i += 1;
+        }
+        // filesystems still mounted elsewhere are kept
+        let removed: Vec<_> = removed
+            .into_iter()
+            .filter(|m| !mounts.iter().any(|other| Arc::ptr_eq(&other.fs, &m.fs)))
+            .collect();
+        drop(mounts);
+
+        let mut detached = DETACHED.lock();
+        for mnt in removed {
+            if detached.iter().any(|other| Arc::ptr_eq(&other.fs, &mnt.fs)) {
+                continue;
+            }
+            if !mnt.fs.is_busy() {
+                mnt.fs.umount()?;
+                continue;
+            }
+            if flags.contains(UmountFlags::FORCE) {
+                mnt.fs.umount()?;
+            }
+            detached.push(mnt);
+        }
+        drop(detached);
+        reap_detached();
+        Ok(())
     }
 
-    fn lookup_mounted_fs<F, T>(&self, path: &str, f: F) -> AxResult<T>
-    where
-        F: FnOnce(Arc<dyn VfsOps>, &str) -> AxResult<T>,
-    {
-        debug!("lookup at root: {}", path);
-        let path = path.trim_matches('/');
-        if let Some(rest) = path.strip_prefix("./") {
-            return self.lookup_mounted_fs(rest, f);
-        }
-
-        let mut idx = 0;
-        let mut max_len = 0;
-
-        // Find the filesystem that has the longest mounted path match
-        // TODO: more efficient, e.g. trie
-
-        for (i, mp) in self.mounts.iter().enumerate() {
-            // skip the first '/'
-            // two conditions
-            // 1. path == mp.path, e.g. dev
-            // 2. path == mp.path + '/', e.g. dev/
-            let prev = mp.path[1..].to_string() + "/";
-            if path.starts_with(&mp.path[1..]) {
-                if (path.len() == prev.len() - 1 || path.starts_with(&prev)) && prev.len() > max_len
-                {
-                    max_len = mp.path.len() - 1;
-                    idx = i;
-                }
+    /// Writes back all mounted filesystems, including the detached ones.
+    pub fn sync_all(&self) -> AxResult {
+        reap_detached();
+        let mut filesystems: Vec<Arc<dyn VfsOps>> = Vec::new();
+        let mounts = self.mounts.lock().clone();
+        let detached = DETACHED.lock().clone();
+        for mnt in mounts.iter().chain(detached.iter()) {
+            if !filesystems.iter().any(|fs| Arc::ptr_eq(fs, &mnt.fs)) {
+                filesystems.push(mnt.fs.clone());
             }
         }
-        if max_len == 0 {
-            f(self.main_fs.clone(), path) // not matched any mount point
-        } else {
-            f(self.mounts[idx].fs.clone(), &path[max_len..]) // matched at `idx`
+        for fs in filesystems {
+            fs.sync()?;
         }
+        Ok(())
+    }
+
+    /// Creates the hard link `new` to the node at `old`, not following a
+    /// symbolic link there. Both must be on the same mount.
+    fn hard_link(&self, old: &str, new: &str) -> AxResult {
+        let (src, node) = self.walk(old, LookupFlags::NOFOLLOW)?;
+        let node = node.ok_or(AxError::NotFound)?;
+        let (dst, existing) = self.walk(new, LookupFlags::NOFOLLOW | LookupFlags::CREATE)?;
+        if existing.is_some() {
+            return ax_err!(AlreadyExists);
+        }
+        let denied = VfsNodeFlags::IMMUTABLE | VfsNodeFlags::APPEND;
+        if node.get_attr()?.is_dir() || node.get_flags()?.intersects(denied) {
+            return ax_err!(OperationNotPermitted);
+        }
+        if !Arc::ptr_eq(&src.mount, &dst.mount) {
+            return ax_err!(CrossesDevices);
+        }
+        dst.mount
+            .fs
+            .root_dir()
+            .link(&dst.mount.fs_path(&dst.rel), &node)
+    }
+
+    /// Returns the topmost mount at `path`, if `path` is a mount point.
+    fn mount_at(&self, path: &str) -> Option<Arc<Mount>> {
+        let (loc, _) = self.walk(path, LookupFlags::empty()).ok()?;
+        loc.rel.is_empty().then_some(loc.mount)
+    }
+
+    /// Returns the mount table, in the order the filesystems were mounted.
+    pub fn mount_table(&self) -> Vec<MountInfo> {
+        self.mounts
+            .lock()
+            .iter()
+            .map(|m| MountInfo {
+                id: m.id,
+                parent_id: m.parent.as_ref().map_or(m.id, |p| p.id),
+                source: m.source.clone(),
+                target: m.path.clone(),
+                fs_type: m.fs_type.clone(),
+                options: m.options.lock().clone(),
+                root: format!("/{}", m.root),
+            })
+            .collect()
     }
 }
 
@@ -119,100 +457,307 @@ impl VfsNodeOps for RootDirectory {
         self.main_fs.root_dir().get_attr()
     }
 
+    fn get_flags(&self) -> VfsResult<VfsNodeFlags> {
+        self.main_fs.root_dir().get_flags()
+    }
+
+    fn set_flags(&self, flags: VfsNodeFlags) -> VfsResult {
+        self.main_fs.root_dir().set_flags(flags)
+    }
+
+    fn set_perm(&self, perm: VfsNodePerm) -> VfsResult {
+        self.main_fs.root_dir().set_perm(perm)
+    }
+
+    fn set_owner(&self, uid: Option<u32>, gid: Option<u32>) -> VfsResult {
+        self.main_fs.root_dir().set_owner(uid, gid)
+    }
+
+    fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) -> VfsResult {
+        self.main_fs.root_dir().set_times(atime, mtime)
+    }
+
+    fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        self.main_fs.root_dir().read_dir(start_idx, dirents)
+    }
+
+    fn read_dir_at(&self, pos: u64, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        self.main_fs.root_dir().read_dir_at(pos, dirents)
+    }
+
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
-        self.lookup_mounted_fs(path, |fs, rest_path| {
-            let dir = fs.root_dir();
-            dir.lookup(rest_path)
-        })
+        debug!("lookup at root: {}", path);
+        let (_, node) = self.walk(path, LookupFlags::empty())?;
+        node.ok_or(AxError::NotFound)
     }
 
     fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
-        self.lookup_mounted_fs(path, |fs, rest_path| {
-            if rest_path.is_empty() {
-                Ok(()) // already exists
-            } else {
-                fs.root_dir().create(rest_path, ty)
-            }
-        })
+        let (loc, node) = self.walk(path, LookupFlags::NOFOLLOW | LookupFlags::CREATE)?;
+        if node.is_some() {
+            Ok(()) // already exists
+        } else {
+            loc.mount
+                .fs
+                .root_dir()
+                .create(&loc.mount.fs_path(&loc.rel), ty)
+        }
+    }
+
+    fn symlink(&self, path: &str, target: &str) -> VfsResult {
+        let (loc, node) = self.walk(path, LookupFlags::NOFOLLOW | LookupFlags::CREATE)?;
+        if node.is_some() {
+            ax_err!(AlreadyExists)
+        } else {
+            loc.mount
+                .fs
+                .root_dir()
+                .symlink(&loc.mount.fs_path(&loc.rel), target)
+        }
+    }
+
+    fn mknod(&self, path: &str, ty: VfsNodeType, rdev: DeviceId) -> VfsResult {
+        let (loc, node) = self.walk(path, LookupFlags::NOFOLLOW | LookupFlags::CREATE)?;
+        if node.is_some() {
+            ax_err!(AlreadyExists)
+        } else {
+            loc.mount
+                .fs
+                .root_dir()
+                .mknod(&loc.mount.fs_path(&loc.rel), ty, rdev)
+        }
     }
 
     fn remove(&self, path: &str) -> VfsResult {
-        self.lookup_mounted_fs(path, |fs, rest_path| {
-            if rest_path.is_empty() {
-                ax_err!(PermissionDenied) // cannot remove mount points
-            } else {
-                fs.root_dir().remove(rest_path)
-            }
-        })
+        let (Location { mount, rel }, _) = self.walk(path, LookupFlags::NOFOLLOW)?;
+        if rel.is_empty() {
+            ax_err!(ResourceBusy) // cannot remove mount points
+        } else {
+            mount.fs.root_dir().remove(&mount.fs_path(&rel))
+        }
     }
 
     fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
-        self.lookup_mounted_fs(src_path, |fs, rest_path| {
-            if rest_path.is_empty() {
-                ax_err!(PermissionDenied) // cannot rename mount points
-            } else {
-                fs.root_dir().rename(rest_path, dst_path)
-            }
-        })
+        let (src, _) = self.walk(src_path, LookupFlags::NOFOLLOW)?;
+        let (dst, _) = self.walk(dst_path, LookupFlags::NOFOLLOW | LookupFlags::CREATE)?;
+        let (src_mnt, src_rel, dst_mnt, dst_rel) = (src.mount, src.rel, dst.mount, dst.rel);
+        if src_rel.is_empty() || dst_rel.is_empty() {
+            ax_err!(PermissionDenied) // cannot rename mount points
+        } else if !Arc::ptr_eq(&src_mnt, &dst_mnt) {
+            ax_err!(CrossesDevices)
+        } else {
+            src_mnt
+                .fs
+                .root_dir()
+                .rename(&src_mnt.fs_path(&src_rel), &dst_mnt.fs_path(&dst_rel))
+        }
+    }
+}
+
+/// Unmounts the detached filesystems that are no longer in use.
+fn reap_detached() {
+    let idle: Vec<_> = {
+        let mut detached = DETACHED.lock();
+        let (idle, busy): (Vec<_>, Vec<_>) = core::mem::take(&mut *detached)
+            .into_iter()
+            .partition(|mnt| !mnt.fs.is_busy());
+        *detached = busy;
+        idle
+    };
+    for mnt in idle {
+        if let Err(e) = mnt.fs.umount() {
+            warn!("failed to unmount detached {}: {:?}", mnt.path, e);
+        }
+    }
+}
+
+/// Returns the values of `root=`, `rootfstype=` and `overlayroot=` in the
+/// boot parameters.
+fn root_args(bootargs: &str) -> (Option<&str>, Option<&str>, Option<&str>) {
+    let mut root = None;
+    let mut fstype = None;
+    let mut overlayroot = None;
+    for arg in bootargs.split_whitespace() {
+        if let Some(dev) = arg.strip_prefix("root=") {
+            root = Some(dev.trim_start_matches("/dev/"));
+        } else if let Some(ty) = arg.strip_prefix("rootfstype=") {
+            fstype = Some(ty);
+        } else if let Some(upper) = arg.strip_prefix("overlayroot=") {
+            overlayroot = Some(upper);
+        }
+    }
+    (root, fstype, overlayroot)
+}
+
+/// Opens the root filesystem on the device named by `root`, or on the first
+/// disk or partition holding a supported filesystem (of type `fstype` if
+/// given). Returns the filesystem, its device and its type.
+#[cfg(not(feature = "myfs"))]
+fn root_fs(
+    devices: &[Arc<BlockDevNode>],
+    root: Option<&str>,
+    fstype: Option<&str>,
+) -> (Arc<dyn VfsOps>, String, String) {
+    let fstype = fstype.map(mounts::canonical_fs_type);
+    let (device, fs_type) = match root {
+        Some(name) => {
+            let device = devices
+                .iter()
+                .find(|dev| dev.name() == name)
+                .unwrap_or_else(|| panic!("root device {} not found", name));
+            (device.clone(), fstype.unwrap_or("auto"))
+        }
+        None => devices
+            .iter()
+            .find_map(|dev| {
+                let ty = mounts::probe(&(dev.clone() as VfsNodeRef)).ok()?;
+                let matched = fstype.map_or(true, |fstype| fstype == ty);
+                (matched && mounts::block_fs_supported(ty)).then(|| (dev.clone(), ty))
+            })
+            .expect("no root filesystem found"),
+    };
+    info!("  use {} ({}) as the root filesystem", device.name(), fs_type);
+    let source = format!("/dev/{}", device.name());
+    let (fs, fs_type) = mounts::new_fs(fs_type, || Ok(device as VfsNodeRef), "")
+        .expect("failed to mount the root filesystem");
+    (fs, source, fs_type)
+}
+
+/// Puts an overlay over the root filesystem `lower`, keeping the changes in
+/// a tmpfs if `upper` is `tmpfs`, or in the filesystem on the device named
+/// by `upper`, so that the root filesystem itself is never modified.
+#[cfg(all(feature = "overlayfs", not(feature = "myfs")))]
+fn overlay_root(
+    lower: Arc<dyn VfsOps>,
+    devices: &[Arc<BlockDevNode>],
+    upper: &str,
+) -> (Arc<dyn VfsOps>, String, String) {
+    let (upper_fs, _) = match upper {
+        "tmpfs" => mounts::new_fs("tmpfs", || ax_err!(NotFound), "mode=755"),
+        name => {
+            let name = name.trim_start_matches("/dev/");
+            let device = devices
+                .iter()
+                .find(|dev| dev.name() == name)
+                .unwrap_or_else(|| panic!("overlay device {} not found", name));
+            mounts::new_fs("auto", || Ok(device.clone() as VfsNodeRef), "")
+        }
     }
+    .expect("failed to mount the upper layer of the root overlay");
+    info!("  put an overlay on {} over the root filesystem", upper);
+    let fs = mounts::overlay_layers(lower, upper_fs).expect("failed to create the root overlay");
+    (fs, String::from("overlay"), String::from("overlay"))
 }
 
-pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
+/// Registers all disks as `vda`, `vdb`, ... and their partitions as `vda1`,
+/// `vda2`, ..., then mounts the root filesystem.
+///
+/// The root device and filesystem type are probed, or chosen by `root=` and
+/// `rootfstype=` in the boot parameters (`AX_BOOTARGS`). The other disks can
+/// be mounted later.
+///
+/// With `overlayroot=tmpfs` or `overlayroot=<device>`, the root filesystem
+/// is the lower layer of an overlay, so that it stays pristine.
+pub(crate) fn init_rootfs(disks: Vec<(String, crate::dev::Disk)>) {
+    let (root, fstype, overlayroot) = root_args(option_env!("AX_BOOTARGS").unwrap_or(""));
     cfg_if::cfg_if! {
         if #[cfg(feature = "myfs")] { // override the default filesystem
+            let _ = (fstype, overlayroot);
+            let mut disks = disks;
+            let idx = match root {
+                Some(name) => disks
+                    .iter()
+                    .position(|(n, _)| n == name)
+                    .unwrap_or_else(|| panic!("root device {} not found", name)),
+                None => 0,
+            };
+            let (name, disk) = disks.remove(idx);
+            for (name, disk) in disks {
+                BlockDevNode::register(name, disk);
+            }
             let main_fs = fs::myfs::new_myfs(disk);
-        } else if #[cfg(feature = "ext4fs")] {
-            static EXT4_FS: LazyInit<Arc<fs::ext4fs::Ext4FileSystem>> = LazyInit::new();
-            EXT4_FS.init_by(Arc::new(fs::ext4fs::Ext4FileSystem::new(disk)));
-            EXT4_FS.init();
-            let main_fs = EXT4_FS.clone();
-        } else if #[cfg(feature = "fatfs")] {
-            static FAT_FS: LazyInit<Arc<fs::fatfs::FatFileSystem>> = LazyInit::new();
-            FAT_FS.init_by(Arc::new(fs::fatfs::FatFileSystem::new(disk)));
-            FAT_FS.init();
-            let main_fs = FAT_FS.clone();
-        }
-    }
-
-    let mut root_dir = RootDirectory::new(main_fs);
-
-    // #[cfg(feature = "devfs")]
-    // root_dir
-    //     .mount("/dev", mounts::devfs())
-    //     .expect("failed to mount devfs at /dev");
-
-    // #[cfg(feature = "ramfs")]
-    // root_dir
-    //     .mount("/tmp", mounts::ramfs())
-    //     .expect("failed to mount ramfs at /tmp");
-
-    // #[cfg(feature = "ramfs")]
-    // root_dir
-    //     .mount("/var", mounts::ramfs())
-    //     .expect("failed to mount ramfs at /tmp");
-
-    // // Mount another ramfs as procfs
-    // #[cfg(feature = "procfs")]
-    // root_dir // should not fail
-    //     .mount("/proc", mounts::procfs().unwrap())
-    //     .expect("fail to mount procfs at /proc");
-
-    // // Mount another ramfs as sysfs
-    // #[cfg(feature = "sysfs")]
-    // root_dir // should not fail
-    //     .mount("/sys", mounts::sysfs().unwrap())
-    //     .expect("fail to mount sysfs at /sys");
+            let (source, fs_type) = (format!("/dev/{}", name), String::from("myfs"));
+        } else {
+            let devices: Vec<_> = disks
+                .into_iter()
+                .flat_map(|(name, disk)| BlockDevNode::register(name, disk))
+                .collect();
+            #[cfg(all(feature = "use-ramdisk", feature = "fatfs"))]
+            fs::fatfs::FatFileSystem::format(devices[0].clone()).expect("failed to format volume");
+            let (main_fs, source, fs_type) = root_fs(&devices, root, fstype);
+            #[cfg(feature = "overlayfs")]
+            let (main_fs, source, fs_type) = match overlayroot {
+                Some(upper) => overlay_root(main_fs, &devices, upper),
+                None => (main_fs, source, fs_type),
+            };
+            #[cfg(not(feature = "overlayfs"))]
+            if overlayroot.is_some() {
+                warn!("overlayfs is not enabled, overlayroot= is ignored");
+            }
+        }
+    }
+
+    let root_dir = RootDirectory::new(main_fs, &source, &fs_type);
+
+    #[cfg(feature = "devfs")]
+    if let Err(e) = root_dir.mount(
+        "/dev",
+        mounts::devfs(),
+        String::new(),
+        "devtmpfs",
+        "devtmpfs",
+        "",
+    ) {
+        warn!("failed to mount devtmpfs at /dev: {:?}", e);
+    }
+
+    #[cfg(feature = "devfs")]
+    if let Err(e) = root_dir.mount(
+        "/dev/pts",
+        mounts::devpts(),
+        String::new(),
+        "devpts",
+        "devpts",
+        "",
+    ) {
+        warn!("failed to mount devpts at /dev/pts: {:?}", e);
+    }
+
+    #[cfg(all(feature = "devfs", feature = "ramfs"))]
+    if let Err(e) = mounts::tmpfs("mode=1777")
+        .and_then(|fs| root_dir.mount("/dev/shm", fs, String::new(), "tmpfs", "tmpfs", "mode=1777"))
+    {
+        warn!("failed to mount tmpfs at /dev/shm: {:?}", e);
+    }
+
+    #[cfg(feature = "ramfs")]
+    if let Err(e) = mounts::tmpfs("mode=1777")
+        .and_then(|fs| root_dir.mount("/tmp", fs, String::new(), "tmpfs", "tmpfs", "mode=1777"))
+    {
+        warn!("failed to mount tmpfs at /tmp: {:?}", e);
+    }
+
+    #[cfg(feature = "procfs")]
+    if let Err(e) = root_dir.mount("/proc", mounts::procfs(), String::new(), "proc", "proc", "") {
+        warn!("failed to mount procfs at /proc: {:?}", e);
+    }
+
+    #[cfg(feature = "sysfs")]
+    if let Err(e) = root_dir.mount("/sys", mounts::sysfs(), String::new(), "sysfs", "sysfs", "") {
+        warn!("failed to mount sysfs at /sys: {:?}", e);
+    }
 
     ROOT_DIR.init_by(Arc::new(root_dir));
-    CURRENT_DIR.init_by(Mutex::new(ROOT_DIR.clone()));
     *CURRENT_DIR_PATH.lock() = "/".into();
 }
 
-fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
-    if path.starts_with('/') {
-        ROOT_DIR.clone()
-    } else {
-        dir.cloned().unwrap_or_else(|| CURRENT_DIR.lock().clone())
+/// Returns the directory to resolve `path` from, and the path relative to
+/// it. Paths not relative to `dir` are resolved from the root directory, so
+/// that they cross the mount points.
+fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> (VfsNodeRef, String) {
+    match dir {
+        _ if path.starts_with('/') => (ROOT_DIR.clone(), path.into()),
+        Some(dir) => (dir.clone(), path.into()),
+        None => (ROOT_DIR.clone(), CURRENT_DIR_PATH.lock().clone() + path),
     }
 }
 
@@ -229,7 +774,8 @@ pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRe
     if path.is_empty() {
         return ax_err!(NotFound);
     }
-    let node = parent_node_of(dir, path).lookup(path)?;
+    let (parent, rel) = parent_node_of(dir, path);
+    let node = parent.lookup(&rel)?;
     if path.ends_with('/') && !node.get_attr()?.is_dir() {
         ax_err!(NotADirectory)
     } else {
@@ -237,34 +783,178 @@ pub(crate) fn lookup(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRe
     }
 }
 
+/// Looks up `path` like [`lookup`], but returns a symbolic link in the last
+/// component itself rather than its target.
+fn lookup_nofollow(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+    match dir {
+        Some(_) if !path.starts_with('/') => lookup(dir, path),
+        _ => resolve_path(None, path, LookupFlags::NOFOLLOW)?
+            .node
+            .ok_or(AxError::NotFound),
+    }
+}
+
+/// Resolves `path` relative to the directory `dir` (an absolute path), or
+/// to the current directory.
+pub(crate) fn resolve_path(
+    dir: Option<&str>,
+    path: &str,
+    flags: LookupFlags,
+) -> AxResult<ResolvedPath> {
+    let start = if path.starts_with('/') {
+        ROOT_DIR.root_location()
+    } else {
+        let cwd = CURRENT_DIR_PATH.lock().clone();
+        ROOT_DIR
+            .walk(dir.unwrap_or(&cwd), LookupFlags::DIRECTORY)?
+            .0
+    };
+    let (loc, node) = namei::walk(&ROOT_DIR, start, path, flags)?;
+    Ok(ResolvedPath {
+        path: loc.path(),
+        node,
+    })
+}
+
+/// Reads the target of the symbolic link at `path`.
+pub(crate) fn read_link(dir: Option<&str>, path: &str) -> AxResult<String> {
+    let node = resolve_path(dir, path, LookupFlags::NOFOLLOW)?
+        .node
+        .ok_or(AxError::NotFound)?;
+    if !node.get_attr()?.file_type().is_symlink() {
+        return ax_err!(InvalidInput);
+    }
+    namei::read_link(&node)
+}
+
 pub(crate) fn create_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
     if path.is_empty() {
         return ax_err!(NotFound);
     } else if path.ends_with('/') {
         return ax_err!(NotADirectory);
     }
-    let parent = parent_node_of(dir, path);
-    parent.create(path, VfsNodeType::File)?;
-    parent.lookup(path)
+    let parent_dir = parent_dir_of(dir, path)?;
+    perm::may_create(&parent_dir)?;
+    let (parent, rel) = parent_node_of(dir, path);
+    parent.create(&rel, VfsNodeType::File)?;
+    let node = parent.lookup(&rel)?;
+    perm::init_owner(&parent_dir, &node)?;
+    if let Some(path) = notify_path(dir, path) {
+        inotify::notify_create(path, false);
+    }
+    Ok(node)
 }
 
 pub(crate) fn create_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
-    match lookup(dir, path) {
+    match lookup_nofollow(dir, path) {
         Ok(_) => ax_err!(AlreadyExists),
-        Err(AxError::NotFound) => parent_node_of(dir, path).create(path, VfsNodeType::Dir),
+        Err(AxError::NotFound) => {
+            let parent_dir = parent_dir_of(dir, path)?;
+            perm::may_create(&parent_dir)?;
+            let (parent, rel) = parent_node_of(dir, path);
+            parent.create(&rel, VfsNodeType::Dir)?;
+            perm::init_owner(&parent_dir, &parent.lookup(&rel)?)?;
+            if let Some(path) = notify_path(dir, path) {
+                inotify::notify_create(path, true);
+            }
+            Ok(())
+        }
         Err(e) => Err(e),
     }
 }
 
+/// Creates the symbolic link `path` pointing to `target`.
+pub(crate) fn symlink(dir: Option<&VfsNodeRef>, target: &str, path: &str) -> AxResult {
+    if path.is_empty() || target.is_empty() {
+        return ax_err!(NotFound);
+    }
+    let parent_dir = parent_dir_of(dir, path)?;
+    perm::may_create(&parent_dir)?;
+    let (parent, rel) = parent_node_of(dir, path);
+    parent.symlink(&rel, target)?;
+    perm::init_owner(&parent_dir, &lookup_nofollow(dir, path)?)?;
+    if let Some(path) = notify_path(dir, path) {
+        inotify::notify_create(path, false);
+    }
+    Ok(())
+}
+
+/// Creates the device node `path` of type `ty` referring to `rdev`.
+pub(crate) fn mknod(
+    dir: Option<&VfsNodeRef>,
+    path: &str,
+    ty: VfsNodeType,
+    rdev: DeviceId,
+) -> AxResult {
+    if path.is_empty() {
+        return ax_err!(NotFound);
+    } else if !(ty.is_char_device() || ty.is_block_device()) {
+        return ax_err!(InvalidInput);
+    } else if !perm::current_credentials().is_root() {
+        // only root may create device nodes, as with `CAP_MKNOD`
+        return ax_err!(OperationNotPermitted);
+    }
+    let parent_dir = parent_dir_of(dir, path)?;
+    perm::may_create(&parent_dir)?;
+    let (parent, rel) = parent_node_of(dir, path);
+    parent.mknod(&rel, ty, rdev)?;
+    perm::init_owner(&parent_dir, &lookup_nofollow(dir, path)?)?;
+    if let Some(path) = notify_path(dir, path) {
+        inotify::notify_create(path, false);
+    }
+    Ok(())
+}
+
+/// Returns `path` to name the file in the inotify events, `None` if it is
+/// relative to an opened directory.
+fn notify_path<'a>(dir: Option<&VfsNodeRef>, path: &'a str) -> Option<&'a str> {
+    (dir.is_none() || path.starts_with('/')).then_some(path)
+}
+
+/// Returns the directory that contains `path`.
+fn parent_dir_of(dir: Option<&VfsNodeRef>, path: &str) -> AxResult<VfsNodeRef> {
+    match path.trim_end_matches('/').rsplit_once('/') {
+        Some((parent, _)) if !parent.is_empty() => lookup(dir, parent),
+        Some(_) => Ok(ROOT_DIR.clone()),
+        None => match dir {
+            Some(dir) => Ok(dir.clone()),
+            None => lookup(None, &CURRENT_DIR_PATH.lock().clone()),
+        },
+    }
+}
+
+/// Returns the absolute path of the directory that contains `path`.
+fn parent_path(path: &str) -> AxResult<String> {
+    let path = absolute_path(path)?;
+    let parent = path.trim_end_matches('/').rsplit_once('/');
+    Ok(String::from(parent.map_or("", |(dir, _)| dir)))
+}
+
+/// Checks the node flags of `path` and its parent directory before removing
+/// or renaming it: neither may be immutable or append-only.
+fn check_unlink_flags(dir: Option<&VfsNodeRef>, path: &str, node: &VfsNodeRef) -> AxResult {
+    let parent = parent_dir_of(dir, path)?;
+    let denied = VfsNodeFlags::IMMUTABLE | VfsNodeFlags::APPEND;
+    if node.get_flags()?.intersects(denied) || parent.get_flags()?.intersects(denied) {
+        return ax_err!(OperationNotPermitted);
+    }
+    Ok(())
+}
+
 pub(crate) fn remove_file(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
-    let node = lookup(dir, path)?;
+    let node = lookup_nofollow(dir, path)?;
     let attr = node.get_attr()?;
     if attr.is_dir() {
         ax_err!(IsADirectory)
-    } else if !attr.perm().owner_writable() {
-        ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        perm::may_delete(&parent_dir_of(dir, path)?, &node)?;
+        check_unlink_flags(dir, path, &node)?;
+        let (parent, rel) = parent_node_of(dir, path);
+        parent.remove(&rel)?;
+        if let Some(path) = notify_path(dir, path) {
+            inotify::notify_delete(path, false);
+        }
+        Ok(())
     }
 }
 
@@ -282,53 +972,209 @@ pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
     {
         return ax_err!(InvalidInput);
     }
-    if ROOT_DIR.contains(&absolute_path(path)?) {
-        return ax_err!(PermissionDenied);
+    if ROOT_DIR.mount_at(&mount_point_path(path)?).is_some() {
+        return ax_err!(ResourceBusy); // cannot remove mount points
     }
 
-    let node = lookup(dir, path)?;
+    let node = lookup_nofollow(dir, path)?;
     let attr = node.get_attr()?;
     if !attr.is_dir() {
         ax_err!(NotADirectory)
-    } else if !attr.perm().owner_writable() {
-        ax_err!(PermissionDenied)
     } else {
-        parent_node_of(dir, path).remove(path)
+        perm::may_delete(&parent_dir_of(dir, path)?, &node)?;
+        check_unlink_flags(dir, path, &node)?;
+        let (parent, rel) = parent_node_of(dir, path);
+        parent.remove(&rel)?;
+        if let Some(path) = notify_path(dir, path) {
+            inotify::notify_delete(path, true);
+        }
+        Ok(())
     }
 }
 
-pub(crate) fn current_dir() -> AxResult<String> {
-    Ok(CURRENT_DIR_PATH.lock().clone())
+/// Converts `path` to the absolute form used as the key of a mount point,
+/// i.e. without the trailing slash.
+fn mount_point_path(path: &str) -> AxResult<String> {
+    let path = absolute_path(path)?;
+    match path.trim_end_matches('/') {
+        "" => Ok("/".into()),
+        p => Ok(p.into()),
+    }
 }
 
-pub(crate) fn set_current_dir(path: &str) -> AxResult {
-    let mut abs_path = absolute_path(path)?;
-    if !abs_path.ends_with('/') {
-        abs_path += "/";
+pub(crate) fn mount(
+    path: &str,
+    fs: Arc<dyn VfsOps>,
+    source: &str,
+    fs_type: &str,
+    options: &str,
+) -> AxResult {
+    ROOT_DIR.mount(
+        &mount_point_path(path)?,
+        fs,
+        String::new(),
+        source,
+        fs_type,
+        options,
+    )
+}
+
+pub(crate) fn bind_mount(src: &str, path: &str) -> AxResult {
+    ROOT_DIR.bind(&mount_point_path(src)?, &mount_point_path(path)?)
+}
+
+pub(crate) fn umount(path: &str, flags: UmountFlags) -> AxResult {
+    ROOT_DIR.umount(&mount_point_path(path)?, flags)
+}
+
+pub(crate) fn sync() -> AxResult {
+    crate::page_cache::sync_all()?;
+    ROOT_DIR.sync_all()?;
+    crate::dev::sync_all().map_err(|_| AxError::Io)
+}
+
+pub(crate) fn remount(path: &str, data: &str) -> AxResult {
+    let mnt = ROOT_DIR
+        .mount_at(&mount_point_path(path)?)
+        .ok_or(AxError::InvalidInput)?;
+    mnt.fs.remount(data)?;
+    *mnt.options.lock() = data.into();
+    Ok(())
+}
+
+pub(crate) fn mount_table() -> Vec<MountInfo> {
+    ROOT_DIR.mount_table()
+}
+
+/// Escapes the characters separating the fields of the mount tables, like
+/// Linux does in `/proc/mounts`.
+fn escape_mount_field(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            ' ' | '\t' | '\n' | '\\' => escaped += &format!("\\{:03o}", c as u32),
+            c => escaped.push(c),
+        }
     }
-    if abs_path == "/" {
-        *CURRENT_DIR.lock() = ROOT_DIR.clone();
-        *CURRENT_DIR_PATH.lock() = "/".into();
-        return Ok(());
+    escaped
+}
+
+/// Formats the mount table as `/proc/mounts`.
+pub(crate) fn proc_mounts() -> String {
+    let mut out = String::new();
+    for m in mount_table() {
+        out += &format!(
+            "{} {} {} {} 0 0\n",
+            escape_mount_field(&m.source),
+            escape_mount_field(&m.target),
+            m.fs_type,
+            mount_options(&m.options),
+        );
     }
+    out
+}
 
-    let node = lookup(None, &abs_path)?;
-    let attr = node.get_attr()?;
-    if !attr.is_dir() {
-        ax_err!(NotADirectory)
-    } else if !attr.perm().owner_executable() {
-        ax_err!(PermissionDenied)
+/// Formats the mount table as `/proc/self/mountinfo`. The device numbers
+/// are made up from the mount IDs.
+pub(crate) fn proc_mountinfo() -> String {
+    let mut out = String::new();
+    for m in mount_table() {
+        let options = mount_options(&m.options);
+        let access = if options.split(',').any(|o| o == "ro") {
+            "ro"
+        } else {
+            "rw"
+        };
+        out += &format!(
+            "{} {} 0:{} {} {} {} - {} {} {}\n",
+            m.id,
+            m.parent_id,
+            m.id,
+            escape_mount_field(&m.root),
+            escape_mount_field(&m.target),
+            access,
+            m.fs_type,
+            escape_mount_field(&m.source),
+            options,
+        );
+    }
+    out
+}
+
+/// Returns the options of a mount as shown in the mount tables, with `rw`
+/// first unless the mount is read-only.
+fn mount_options(options: &str) -> String {
+    let options: Vec<&str> = options.split(',').filter(|o| !o.is_empty()).collect();
+    if options.iter().any(|o| *o == "ro" || *o == "rw") {
+        options.join(",")
+    } else if options.is_empty() {
+        "rw".into()
     } else {
-        *CURRENT_DIR.lock() = node;
-        *CURRENT_DIR_PATH.lock() = abs_path;
-        Ok(())
+        format!("rw,{}", options.join(","))
+    }
+}
+
+pub(crate) fn current_dir() -> AxResult<String> {
+    Ok(CURRENT_DIR_PATH.lock().clone())
+}
+
+pub(crate) fn set_current_dir(path: &str) -> AxResult {
+    let resolved = resolve_path(None, path, LookupFlags::DIRECTORY)?;
+    perm::check(&resolved.node.ok_or(AxError::NotFound)?, Access::EXEC)?;
+    let mut path = resolved.path;
+    if !path.ends_with('/') {
+        path.push('/');
     }
+    *CURRENT_DIR_PATH.lock() = path;
+    Ok(())
+}
+
+pub(crate) fn hard_link(old: &str, new: &str) -> AxResult {
+    let cwd = CURRENT_DIR_PATH.lock().clone();
+    let absolute = |path: &str| {
+        if path.starts_with('/') {
+            String::from(path)
+        } else {
+            cwd.clone() + path
+        }
+    };
+    perm::may_create(&parent_dir_of(None, new)?)?;
+    ROOT_DIR.hard_link(&absolute(old), &absolute(new))?;
+    // the link count of the file changed
+    inotify::notify(old, InotifyMask::ATTRIB);
+    inotify::notify_create(new, false);
+    Ok(())
 }
 
 pub(crate) fn rename(old: &str, new: &str) -> AxResult {
-    if parent_node_of(None, new).lookup(new).is_ok() {
-        warn!("dst file already exist, now remove it");
-        remove_file(None, new)?;
+    let node = lookup_nofollow(None, old)?;
+    let old_dir = parent_dir_of(None, old)?;
+    let new_dir = parent_dir_of(None, new)?;
+    perm::may_delete(&old_dir, &node)?;
+    match lookup_nofollow(None, new) {
+        Ok(target) => perm::may_delete(&new_dir, &target)?,
+        Err(AxError::NotFound) => perm::may_create(&new_dir)?,
+        Err(e) => return Err(e),
+    }
+    // a directory moved to another parent has its `..` entry rewritten
+    if node.get_attr()?.is_dir() && parent_path(old)? != parent_path(new)? {
+        perm::check(&node, Access::WRITE)?;
+    }
+    check_unlink_flags(None, old, &node)?;
+    if new_dir.get_flags()?.contains(VfsNodeFlags::IMMUTABLE) {
+        return ax_err!(OperationNotPermitted);
+    }
+    let (parent, old_path) = parent_node_of(None, old);
+    let (_, new_path) = parent_node_of(None, new);
+    // filesystems that can't replace the target atomically refuse to
+    match parent.rename(&old_path, &new_path) {
+        Err(AxError::AlreadyExists) if lookup_nofollow(None, new).is_ok() => {
+            warn!("dst file already exist, now remove it");
+            remove_file(None, new)?;
+            parent.rename(&old_path, &new_path)?;
+        }
+        res => res?,
     }
-    parent_node_of(None, old).rename(old, new)
+    inotify::notify_rename(old, new, node.get_attr()?.is_dir());
+    Ok(())
 }
@@ -0,0 +1,27 @@
+//! Signals interrupting the waits of the filesystem.
+//!
+//! As with the credentials, the filesystem doesn't know about processes: the
+//! process module registers with [`register_signal_pending`] the function
+//! telling whether the calling task has a signal pending, and the waits can't
+//! be interrupted until it does.
+
+use axsync::Mutex;
+
+/// Returns whether the calling task has a signal pending.
+pub type SignalPendingFn = fn() -> bool;
+
+static SIGNAL_PENDING: Mutex<Option<SignalPendingFn>> = Mutex::new(None);
+
+/// Registers the function telling whether the calling task has a signal
+/// pending. A later registration replaces the earlier one.
+pub fn register_signal_pending(f: SignalPendingFn) {
+    *SIGNAL_PENDING.lock() = Some(f);
+}
+
+/// Returns whether the calling task has a signal pending, and should give up
+/// waiting with `EINTR`. Always false if no process module registered the
+/// function.
+pub fn signal_pending() -> bool {
+    let f = *SIGNAL_PENDING.lock();
+    f.is_some_and(|f| f())
+}
@@ -0,0 +1,24 @@
+//! The background task writing back dirty file pages and buffered disk
+//! writes.
+
+use core::time::Duration;
+
+/// How long dirty data may stay in memory before it is written back.
+const WRITEBACK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Spawns the write-back task.
+pub(crate) fn start() {
+    axtask::spawn(|| loop {
+        axtask::sleep(WRITEBACK_INTERVAL);
+        write_back();
+    });
+}
+
+/// Writes back the dirty pages of all files, which go to the disk write
+/// buffers, then the disk write buffers, in large contiguous writes.
+fn write_back() {
+    crate::page_cache::write_back_all();
+    if crate::dev::write_back_all().is_err() {
+        warn!("failed to write back disk buffers");
+    }
+}
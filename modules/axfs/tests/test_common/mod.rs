@@ -164,6 +164,251 @@ fn test_remove_file_dir() -> Result<()> {
     Ok(())
 }
 
+/// Reads the queued inotify events as `(wd, mask, name)`.
+#[cfg(feature = "ramfs")]
+fn read_inotify_events(inotify: &fs::Inotify) -> Vec<(i32, u32, String)> {
+    let mut buf = [0; 4096];
+    let len = inotify.read_events(&mut buf).unwrap_or(0);
+    let mut events = Vec::new();
+    let mut pos = 0;
+    while pos < len {
+        let field =
+            |off: usize| u32::from_ne_bytes(buf[pos + off..pos + off + 4].try_into().unwrap());
+        let name_len = field(12) as usize;
+        let name = &buf[pos + 16..pos + 16 + name_len];
+        let name = name.split(|&b| b == 0).next().unwrap();
+        events.push((
+            field(0) as i32,
+            field(4),
+            String::from_utf8_lossy(name).into(),
+        ));
+        pos += 16 + name_len;
+    }
+    events
+}
+
+#[cfg(feature = "ramfs")]
+fn test_inotify() -> Result<()> {
+    use fs::InotifyMask as M;
+    println!("test inotify:");
+    fs::mount("tmpfs", "/inotify", "tmpfs", "")?;
+    fs::create_dir("/inotify/watched")?;
+    let inotify = fs::Inotify::new();
+    let wd = inotify.add_watch("/inotify/watched", M::ALL_EVENTS)?;
+    assert_err!(inotify.read_events(&mut [0; 64]), WouldBlock);
+
+    fs::write("/inotify/watched/f.txt", "data")?;
+    let events = read_inotify_events(&inotify);
+    println!("events = {:?}", events);
+    let f = || String::from("f.txt");
+    assert_eq!(events[0], (wd, M::CREATE.bits(), f()));
+    assert_eq!(events[1], (wd, M::OPEN.bits(), f()));
+    assert_eq!(events[2], (wd, M::MODIFY.bits(), f()));
+    assert_eq!(events[3], (wd, M::CLOSE_WRITE.bits(), f()));
+
+    let file_wd = inotify.add_watch("/inotify/watched/f.txt", M::DELETE_SELF)?;
+    fs::remove_file("/inotify/watched/f.txt")?;
+    let events = read_inotify_events(&inotify);
+    assert_eq!(events[0], (wd, M::DELETE.bits(), f()));
+    assert_eq!(events[1], (file_wd, M::DELETE_SELF.bits(), String::new()));
+    assert_eq!(events[2], (file_wd, M::IGNORED.bits(), String::new()));
+
+    inotify.rm_watch(wd)?;
+    assert_err!(inotify.rm_watch(wd), InvalidInput);
+    fs::remove_dir("/inotify/watched")?;
+    let events = read_inotify_events(&inotify);
+    assert_eq!(events, [(wd, M::IGNORED.bits(), String::new())]);
+    fs::umount("/inotify", fs::UmountFlags::empty())?;
+    fs::remove_dir("/inotify")?;
+
+    println!("test_inotify() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "ramfs")]
+fn test_file_locks() -> Result<()> {
+    use fs::{LockOwner, LockType, RecordLock};
+    println!("test file locks:");
+    fs::mount("tmpfs", "/locks", "tmpfs", "")?;
+    fs::write("/locks/locked.txt", "data")?;
+    let f1 = File::open("/locks/locked.txt")?;
+    let f2 = File::open("/locks/locked.txt")?;
+    let (p1, p2) = (LockOwner::Process(1), LockOwner::Process(2));
+    let lock = |ty, start, end, owner| RecordLock {
+        ty,
+        start,
+        end,
+        owner,
+    };
+
+    // shared locks coexist, an exclusive one conflicts
+    f1.set_lock(&lock(LockType::Read, 0, 10, p1), false)?;
+    f2.set_lock(&lock(LockType::Read, 5, 15, p2), false)?;
+    let held = f2.test_lock(&lock(LockType::Write, 0, u64::MAX, p2))?;
+    assert_eq!(held, Some(lock(LockType::Read, 0, 10, p1)));
+    assert_err!(
+        f2.set_lock(&lock(LockType::Write, 0, 10, p2), false),
+        WouldBlock
+    );
+
+    // p2 waits for p1, so p1 waiting for p2 would deadlock
+    assert_err!(
+        f2.set_lock(&lock(LockType::Write, 0, 10, p2), true),
+        WouldBlock
+    );
+    assert_err!(
+        f1.set_lock(&lock(LockType::Write, 5, 15, p1), true),
+        Deadlock
+    );
+    fs::cancel_lock_wait(p2);
+
+    // unlocking part of a range splits it
+    f1.set_lock(&lock(LockType::Unlock, 2, 8, p1), false)?;
+    assert_eq!(f2.test_lock(&lock(LockType::Write, 2, 8, p2))?, None);
+    f1.release_locks(p1);
+    f2.set_lock(&lock(LockType::Write, 0, 5, p2), false)?;
+    fs::release_locks(p2);
+    assert_eq!(f1.test_lock(&lock(LockType::Write, 0, u64::MAX, p1))?, None);
+
+    // flock locks are separate from record locks
+    let (o1, o2) = (LockOwner::File(1), LockOwner::File(2));
+    f1.flock(o1, LockType::Write)?;
+    assert_err!(f2.flock(o2, LockType::Read), WouldBlock);
+    f1.flock(o1, LockType::Read)?;
+    f2.flock(o2, LockType::Read)?;
+    f1.release_locks(o1);
+    f2.release_locks(o2);
+
+    drop((f1, f2));
+    fs::remove_file("/locks/locked.txt")?;
+    fs::umount("/locks", fs::UmountFlags::empty())?;
+    fs::remove_dir("/locks")?;
+    println!("test_file_locks() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "overlayfs")]
+fn test_overlay() -> Result<()> {
+    println!("test overlay:");
+    fs::create_dir("/ovl_lower")?;
+    fs::create_dir("/ovl_upper")?;
+    fs::create_dir("/ovl")?;
+    fs::write("/ovl_lower/a.txt", "lower")?;
+    fs::write("/ovl_lower/b.txt", "lower")?;
+    fs::mount(
+        "",
+        "/ovl",
+        "overlay",
+        "lowerdir=/ovl_lower,upperdir=/ovl_upper",
+    )?;
+
+    // writes are copied up, the lower directory is left untouched
+    assert_eq!(fs::read_to_string("/ovl/a.txt")?, "lower");
+    fs::write("/ovl/a.txt", "upper")?;
+    assert_eq!(fs::read_to_string("/ovl/a.txt")?, "upper");
+    assert_eq!(fs::read_to_string("/ovl_upper/a.txt")?, "upper");
+    assert_eq!(fs::read_to_string("/ovl_lower/a.txt")?, "lower");
+
+    // removing a lower file leaves a whiteout in the upper directory
+    fs::remove_file("/ovl/b.txt")?;
+    assert_err!(fs::metadata("/ovl/b.txt"), NotFound);
+    assert!(fs::metadata("/ovl_upper/.wh.b.txt")?.is_file());
+    assert!(fs::metadata("/ovl_lower/b.txt")?.is_file());
+    let mut names = fs::read_dir("/ovl")?
+        .map(|e| e.unwrap().file_name())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["a.txt"]);
+    assert_err!(fs::write("/ovl/.wh.c.txt", "test"), InvalidInput);
+
+    fs::umount("/ovl", fs::UmountFlags::empty())?;
+    assert_eq!(fs::read_dir("/ovl")?.count(), 0);
+    for dir in ["/ovl_upper", "/ovl_lower"] {
+        let paths = fs::read_dir(dir)?
+            .map(|e| e.unwrap().path())
+            .collect::<Vec<_>>();
+        for path in paths {
+            fs::remove_file(&path)?;
+        }
+        fs::remove_dir(dir)?;
+    }
+    fs::remove_dir("/ovl")?;
+    println!("test_overlay() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "ramfs")]
+fn test_permissions() -> Result<()> {
+    use core::sync::atomic::{AtomicU32, Ordering};
+    static UID: AtomicU32 = AtomicU32::new(0);
+    fn credentials() -> fs::Credentials {
+        let uid = UID.load(Ordering::Relaxed);
+        fs::Credentials {
+            uid,
+            gid: uid,
+            groups: Vec::new(),
+        }
+    }
+    let set_user = |uid| UID.store(uid, Ordering::Relaxed);
+    println!("test permissions:");
+    fs::register_credentials(credentials);
+    // a sticky directory writable by everyone, as /tmp
+    fs::mount("tmpfs", "/ptmp", "tmpfs", "mode=1777")?;
+    fs::create_dir("/ptmp/perm")?;
+    fs::write("/ptmp/perm/secret", "root")?;
+    fs::set_permissions(
+        "/ptmp/perm/secret",
+        fs::Permissions::from_bits_truncate(0o600),
+    )?;
+
+    // other users can look at the file of root, but not open or remove it
+    set_user(1000);
+    assert_err!(File::open("/ptmp/perm/secret"), PermissionDenied);
+    assert_eq!(fs::metadata("/ptmp/perm/secret")?.uid(), 0);
+    assert_err!(fs::write("/ptmp/perm/new", "test"), PermissionDenied);
+    assert_err!(fs::remove_file("/ptmp/perm/secret"), PermissionDenied);
+    assert_err!(
+        fs::rename("/ptmp/perm/secret", "/ptmp/stolen"),
+        PermissionDenied
+    );
+    assert_err!(
+        fs::access("/ptmp/perm/secret", fs::Access::READ, &credentials()),
+        PermissionDenied
+    );
+
+    // new files belong to their creator, and only it can remove them from the
+    // sticky /ptmp
+    fs::write("/ptmp/mine", "test")?;
+    assert_eq!(fs::metadata("/ptmp/mine")?.uid(), 1000);
+    set_user(1001);
+    assert_err!(fs::remove_file("/ptmp/mine"), OperationNotPermitted);
+    assert_err!(
+        fs::rename("/ptmp/mine", "/ptmp/theirs"),
+        OperationNotPermitted
+    );
+    set_user(1000);
+    fs::rename("/ptmp/mine", "/ptmp/theirs")?;
+    fs::remove_file("/ptmp/theirs")?;
+
+    // root ignores the read and write bits, but not the execute ones
+    set_user(0);
+    assert_eq!(fs::read_to_string("/ptmp/perm/secret")?, "root");
+    assert_err!(fs::check_exec("/ptmp/perm/secret"), PermissionDenied);
+    fs::set_permissions(
+        "/ptmp/perm/secret",
+        fs::Permissions::from_bits_truncate(0o4700),
+    )?;
+    assert!(fs::check_exec("/ptmp/perm/secret")?
+        .permissions()
+        .contains(fs::Permissions::SET_UID));
+    fs::remove_file("/ptmp/perm/secret")?;
+    fs::remove_dir("/ptmp/perm")?;
+    fs::umount("/ptmp", fs::UmountFlags::empty())?;
+    fs::remove_dir("/ptmp")?;
+    println!("test_permissions() OK!");
+    Ok(())
+}
+
 fn test_devfs_ramfs() -> Result<()> {
     const N: usize = 32;
     let mut buf = [1; N];
@@ -224,7 +469,7 @@ fn test_devfs_ramfs() -> Result<()> {
     assert_err!(fs::write("/dev/stdout", "test"), PermissionDenied);
     assert_err!(fs::create_dir("/dev/test"), PermissionDenied);
     assert_err!(fs::remove_file("/dev/null"), PermissionDenied);
-    assert_err!(fs::remove_dir("./dev"), PermissionDenied);
+    assert_err!(fs::remove_dir("./dev"), ResourceBusy);
     assert_err!(fs::remove_dir("./dev/."), InvalidInput);
     assert_err!(fs::remove_dir("///dev//..//"), InvalidInput);
 
@@ -234,7 +479,7 @@ fn test_devfs_ramfs() -> Result<()> {
     assert_err!(fs::remove_file("./dev//../..//233//.///test.txt"), NotFound);
     assert_eq!(fs::remove_file("./dev//..//233//../233/./test.txt"), Ok(()));
     assert_eq!(fs::remove_dir("dev//foo/../foo/../.././/233"), Ok(()));
-    assert_err!(fs::remove_dir("very/../dev//"), PermissionDenied);
+    assert_err!(fs::remove_dir("very/../dev//"), ResourceBusy);
 
     // tests in /tmp
     assert_eq!(fs::metadata("tmp")?.file_type(), FileType::Dir);
@@ -252,11 +497,403 @@ fn test_devfs_ramfs() -> Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "ramfs")]
+fn test_mounts() -> Result<()> {
+    println!("test mounts:");
+    let flags = fs::UmountFlags::empty();
+    let count = fs::mounts().len();
+
+    // mounting over a non-empty directory hides its entries
+    fs::create_dir("/mnt_outer")?;
+    fs::write("/mnt_outer/hidden.txt", "outer")?;
+    fs::mount("tmpfs", "/mnt_outer", "tmpfs", "")?;
+    assert_err!(fs::metadata("/mnt_outer/hidden.txt"), NotFound);
+    fs::write("/mnt_outer/a.txt", "a")?;
+
+    // nested mount, whose mount point is created
+    fs::mount("tmpfs", "/mnt_outer/inner", "tmpfs", "")?;
+    fs::write("/mnt_outer/inner/b.txt", "b")?;
+    // `..` leaves a mount through the directory it is attached to
+    assert_eq!(fs::read_to_string("/mnt_outer/inner/../a.txt")?, "a");
+    assert_eq!(
+        fs::read_to_string("/mnt_outer/inner/../../mnt_outer/inner/b.txt")?,
+        "b"
+    );
+    fs::set_current_dir("/mnt_outer/inner")?;
+    assert_eq!(fs::read_to_string("../a.txt")?, "a");
+    // the current directory keeps the mount busy
+    assert_err!(fs::umount("/mnt_outer/inner", flags), ResourceBusy);
+    fs::set_current_dir("/")?;
+    // so does a mount below it
+    assert_err!(fs::umount("/mnt_outer", flags), ResourceBusy);
+    // a mount point cannot be removed while mounted
+    assert_err!(fs::remove_dir("/mnt_outer/inner"), ResourceBusy);
+
+    // stacked mount, hiding the one below until it is unmounted
+    fs::mount("tmpfs", "/mnt_outer/inner", "tmpfs", "")?;
+    assert_err!(fs::metadata("/mnt_outer/inner/b.txt"), NotFound);
+    fs::write("/mnt_outer/inner/c.txt", "c")?;
+    assert_eq!(fs::read_to_string("/mnt_outer/inner/../a.txt")?, "a");
+    fs::umount("/mnt_outer/inner", flags)?;
+    assert_eq!(fs::read_to_string("/mnt_outer/inner/b.txt")?, "b");
+    assert_err!(fs::metadata("/mnt_outer/inner/c.txt"), NotFound);
+
+    // bind mount of a directory of the nested mount
+    fs::create_dir("/mnt_outer/inner/sub")?;
+    fs::write("/mnt_outer/inner/sub/d.txt", "d")?;
+    fs::bind_mount("/mnt_outer/inner/sub", "/mnt_bind")?;
+    assert_eq!(fs::read_to_string("/mnt_bind/d.txt")?, "d");
+    fs::write("/mnt_bind/e.txt", "e")?;
+    assert_eq!(fs::read_to_string("/mnt_outer/inner/sub/e.txt")?, "e");
+    // `..` at its root goes to the parent of the mount point
+    assert!(fs::metadata("/mnt_bind/../mnt_outer")?.is_dir());
+    assert_err!(fs::metadata("/mnt_bind/../b.txt"), NotFound);
+
+    let mounts = fs::mounts();
+    assert_eq!(mounts.len(), count + 3);
+    let find = |target: &str| mounts.iter().find(|m| m.target == target).unwrap();
+    assert_eq!(find("/mnt_outer/inner").parent_id, find("/mnt_outer").id);
+    assert_eq!(find("/mnt_bind").root, "/sub");
+    assert_eq!(find("/mnt_bind").fs_type, "tmpfs");
+    assert!(fs::proc_mounts().contains("tmpfs /mnt_outer/inner tmpfs rw 0 0\n"));
+
+    // the filesystem stays reachable through the bind mount
+    fs::umount("/mnt_outer/inner", flags)?;
+    assert_eq!(fs::read_dir("/mnt_outer/inner")?.count(), 0);
+    assert_eq!(fs::read_to_string("/mnt_bind/d.txt")?, "d");
+    fs::umount("/mnt_bind", flags)?;
+    fs::umount("/mnt_outer", flags)?;
+    assert_eq!(fs::mounts().len(), count);
+    assert_eq!(fs::read_to_string("/mnt_outer/hidden.txt")?, "outer");
+
+    fs::remove_file("/mnt_outer/hidden.txt")?;
+    fs::remove_dir("/mnt_outer")?;
+    fs::remove_dir("/mnt_bind")?;
+    println!("test_mounts() OK!");
+    Ok(())
+}
+
+#[cfg(feature = "ramfs")]
+fn test_path_walk() -> Result<()> {
+    println!("test path walk:");
+    fs::mount("tmpfs", "/walk", "tmpfs", "")?;
+    fs::create_dir("/walk/dir")?;
+    fs::write("/walk/dir/f.txt", "f")?;
+    fs::symlink("dir", "/walk/rel")?;
+    fs::symlink("/walk/dir/f.txt", "/walk/abs")?;
+
+    assert_eq!(fs::read_to_string("/walk/rel/f.txt")?, "f");
+    assert_eq!(fs::read_to_string("/walk/abs")?, "f");
+    // `..` after a link is taken from where the link points to
+    assert_eq!(fs::read_to_string("/walk/rel/../dir/f.txt")?, "f");
+    let link = fs::resolve_path(None, "/walk/abs", fs::LookupFlags::NOFOLLOW)?;
+    assert_eq!(link.file_type(), Some(FileType::SymLink));
+    assert_eq!(fs::read_link(None, "/walk/abs")?, "/walk/dir/f.txt");
+    // opening: a link fails with NOFOLLOW, and the node reached is opened as is
+    let nofollow = fs::LookupFlags::NOFOLLOW | fs::LookupFlags::OPEN;
+    assert_err!(
+        fs::resolve_path(None, "/walk/abs", nofollow),
+        FilesystemLoop
+    );
+    let resolved = fs::resolve_path(None, "/walk/abs", fs::LookupFlags::OPEN)?;
+    let mut file = File::options().read(true).open_resolved(&resolved)?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    assert_eq!(contents, "f");
+    let missing = fs::resolve_path(None, "/walk/dir/new.txt", fs::LookupFlags::CREATE)?;
+    assert_err!(File::options().read(true).open_resolved(&missing), NotFound);
+    File::options()
+        .write(true)
+        .create_new(true)
+        .open_resolved(&missing)?;
+    assert!(fs::metadata("/walk/dir/new.txt")?.is_file());
+
+    // a trailing slash requires a directory, and follows a link to one
+    assert_err!(fs::metadata("/walk/dir/f.txt/"), NotADirectory);
+    assert_err!(File::open("/walk/dir/f.txt/"), NotADirectory);
+    assert_err!(fs::metadata("/walk/abs/"), NotADirectory);
+    assert_err!(fs::metadata("/walk/dir/f.txt/x"), NotADirectory);
+    assert!(fs::metadata("/walk/rel/")?.is_dir());
+    let dir = fs::resolve_path(None, "/walk/rel/", fs::LookupFlags::NOFOLLOW)?;
+    assert_eq!(dir.file_type(), Some(FileType::Dir));
+    assert_eq!(dir.path, "/walk/dir");
+
+    // loops, and chains longer than MAX_SYMLINKS
+    fs::symlink("self", "/walk/self")?;
+    fs::symlink("loop_b", "/walk/loop_a")?;
+    fs::symlink("loop_a", "/walk/loop_b")?;
+    assert_err!(fs::metadata("/walk/self"), FilesystemLoop);
+    assert_err!(fs::read_to_string("/walk/loop_a"), FilesystemLoop);
+    assert_err!(fs::metadata("/walk/loop_b/x"), FilesystemLoop);
+    fs::symlink("dir", "/walk/l0")?;
+    for i in 1..=fs::MAX_SYMLINKS {
+        fs::symlink(&format!("l{}", i - 1), &format!("/walk/l{}", i))?;
+    }
+    // following `l{n}` takes n + 1 links
+    let last = format!("/walk/l{}/f.txt", fs::MAX_SYMLINKS - 1);
+    assert_eq!(fs::read_to_string(&last)?, "f");
+    let last = format!("/walk/l{}/f.txt", fs::MAX_SYMLINKS);
+    assert_err!(fs::read_to_string(&last), FilesystemLoop);
+
+    fs::umount("/walk", fs::UmountFlags::empty())?;
+    fs::remove_dir("/walk")?;
+    println!("test_path_walk() OK!");
+    Ok(())
+}
+
+/// Reads a cached page as a user mapping of it would.
+#[cfg(all(feature = "ramfs", feature = "sysfs"))]
+fn read_mapped(page: &fs::CachedPage) -> Vec<u8> {
+    let vaddr = axhal::mem::phys_to_virt(page.start_paddr());
+    unsafe { core::slice::from_raw_parts(vaddr.as_ptr(), 4096) }.to_vec()
+}
+
+/// Writes a cached page at `offset` as a user mapping of it would.
+#[cfg(all(feature = "ramfs", feature = "sysfs"))]
+fn write_mapped(page: &fs::CachedPage, offset: usize, data: &[u8]) {
+    let vaddr = axhal::mem::phys_to_virt(page.start_paddr());
+    unsafe {
+        core::ptr::copy_nonoverlapping(data.as_ptr(), vaddr.as_mut_ptr().add(offset), data.len())
+    };
+    page.set_dirty();
+}
+
+#[cfg(all(feature = "ramfs", feature = "sysfs"))]
+fn test_page_cache() -> Result<()> {
+    const PAGE_SIZE: usize = 4096;
+    const MAX_PAGES: &str = "/sys/module/axfs/parameters/page_cache_max_pages";
+    const CACHED_PAGES: &str = "/sys/module/axfs/parameters/page_cache_pages";
+    let fname = "/tmp/page_cache.bin";
+    println!("test page cache {:?}:", fname);
+
+    // the page allocator is empty in host tests, so that nothing is cached
+    let heap_size = 256 * PAGE_SIZE;
+    let layout = std::alloc::Layout::from_size_align(heap_size, PAGE_SIZE).unwrap();
+    axalloc::global_init(unsafe { std::alloc::alloc(layout) } as usize, heap_size);
+
+    let mut file = File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(fname)?;
+    file.write_all(&[b'a'; 2 * PAGE_SIZE])?;
+    let cache = file.page_cache().unwrap();
+    let page = cache.get_page(0)?;
+    assert_eq!(read_mapped(&page), [b'a'; PAGE_SIZE]);
+
+    // writes to the file show through the mapping
+    file.seek(io::SeekFrom::Start(PAGE_SIZE as u64 - 2))?;
+    assert_eq!(file.write(b"bbbb")?, 4);
+    assert_eq!(&read_mapped(&page)[PAGE_SIZE - 2..], b"bb");
+    assert_eq!(&read_mapped(&cache.get_page(1)?)[..2], b"bb");
+
+    // and writes through the mapping show in reads of the file
+    write_mapped(&page, 0, b"cc");
+    assert!(page.is_dirty());
+    let mut buf = [0; 4];
+    file.rewind()?;
+    file.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"ccaa");
+
+    // truncating zeroes the tail of the last page, which stays mapped
+    file.set_len(100)?;
+    let data = read_mapped(&page);
+    assert_eq!(&data[..4], b"ccaa");
+    assert!(data[100..].iter().all(|&b| b == 0));
+    file.set_len(PAGE_SIZE as u64)?;
+    let mut data = Vec::new();
+    file.rewind()?;
+    assert_eq!(file.read_to_end(&mut data)?, PAGE_SIZE);
+    assert!(data[100..].iter().all(|&b| b == 0));
+
+    // the dirty page is written back when the cache is dropped
+    drop((page, cache, file));
+    let data = fs::read(fname)?;
+    assert_eq!(&data[..4], b"ccaa");
+    assert_eq!(data.len(), PAGE_SIZE);
+
+    let mut file = File::options().read(true).write(true).open(fname)?;
+    file.write_all(&[b'd'; 8 * PAGE_SIZE])?;
+    let cache = file.page_cache().unwrap();
+    let mapped = cache.get_page(0)?;
+    write_mapped(&cache.get_page(1)?, 0, b"e");
+
+    // reading the whole file keeps about the limit of pages cached
+    fs::write(MAX_PAGES, "4")?;
+    assert_eq!(fs::read_to_string(MAX_PAGES)?.trim(), "4");
+    let mut data = Vec::new();
+    file.rewind()?;
+    assert_eq!(file.read_to_end(&mut data)?, 8 * PAGE_SIZE);
+    assert_eq!(data[PAGE_SIZE], b'e');
+    assert_eq!(
+        data.iter().filter(|&&b| b == b'd').count(),
+        8 * PAGE_SIZE - 1
+    );
+
+    // only clean pages that are not mapped are dropped for the limit
+    fs::write(MAX_PAGES, "1")?;
+    let cached: usize = fs::read_to_string(CACHED_PAGES)?.trim().parse().unwrap();
+    assert!(cached <= 2);
+    assert!(std::sync::Arc::ptr_eq(&cache.get_page(0)?, &mapped));
+    assert!(cache.get_page(1)?.is_dirty());
+
+    fs::write(MAX_PAGES, "0")?;
+    drop((mapped, cache, file));
+    assert_eq!(fs::read(fname)?[PAGE_SIZE], b'e');
+    fs::remove_file(fname)?;
+    println!("test_page_cache() OK!");
+    Ok(())
+}
+
+/// Serves through `conn` a filesystem holding only the read-only file
+/// `hello.txt` of `contents`, until the connection is aborted. Returns the
+/// opcodes of the requests it has read.
+#[cfg(feature = "fuse")]
+fn fuse_daemon(conn: &fs::FuseConnection, contents: &[u8]) -> Vec<u32> {
+    const LOOKUP: u32 = 1;
+    const FORGET: u32 = 2;
+    const GETATTR: u32 = 3;
+    const OPEN: u32 = 14;
+    const READ: u32 = 15;
+    const RELEASE: u32 = 18;
+    const INIT: u32 = 26;
+    const OPENDIR: u32 = 27;
+    const RELEASEDIR: u32 = 29;
+    const ENOENT: i32 = 2;
+    const ENOSYS: i32 = 38;
+
+    let u32_at = |buf: &[u8], off: usize| u32::from_ne_bytes(buf[off..off + 4].try_into().unwrap());
+    let u64_at = |buf: &[u8], off: usize| u64::from_ne_bytes(buf[off..off + 8].try_into().unwrap());
+    // `struct fuse_attr`, after the `len` bytes of the reply before it
+    let attr = |len: usize, ino: u64, size: u64, mode: u32| {
+        let mut out = vec![0; len + 88];
+        out[len..len + 8].copy_from_slice(&ino.to_ne_bytes());
+        out[len + 8..len + 16].copy_from_slice(&size.to_ne_bytes());
+        out[len + 60..len + 64].copy_from_slice(&mode.to_ne_bytes());
+        out[len + 64..len + 68].copy_from_slice(&1u32.to_ne_bytes());
+        out
+    };
+    let file_size = contents.len() as u64;
+
+    let mut opcodes = Vec::new();
+    let mut buf = vec![0; 8192];
+    loop {
+        let len = match conn.read_request(&mut buf) {
+            Ok(len) => len,
+            // not mounted yet, or no request
+            Err(Error::OperationNotPermitted | Error::WouldBlock) => {
+                axtask::yield_now();
+                continue;
+            }
+            Err(_) => return opcodes,
+        };
+        // `struct fuse_in_header`
+        let (opcode, unique, nodeid) = (u32_at(&buf, 4), u64_at(&buf, 8), u64_at(&buf, 16));
+        let args = &buf[40..len];
+        opcodes.push(opcode);
+        let reply = match (opcode, nodeid) {
+            (FORGET, _) => continue,
+            (INIT, _) => {
+                let mut init = vec![0; 64];
+                init[0..4].copy_from_slice(&7u32.to_ne_bytes());
+                init[4..8].copy_from_slice(&31u32.to_ne_bytes());
+                Ok(init)
+            }
+            (LOOKUP, 1) if args == b"hello.txt\0" => {
+                let mut entry = attr(40, 2, file_size, 0o100444);
+                entry[0..8].copy_from_slice(&2u64.to_ne_bytes());
+                Ok(entry)
+            }
+            (LOOKUP, _) => Err(ENOENT),
+            (GETATTR, 1) => Ok(attr(16, 1, 0, 0o40555)),
+            (GETATTR, 2) => Ok(attr(16, 2, file_size, 0o100444)),
+            (OPEN | OPENDIR, _) => Ok(vec![0; 16]),
+            (READ, 2) => {
+                let start = (u64_at(args, 8) as usize).min(contents.len());
+                let end = (start + u32_at(args, 16) as usize).min(contents.len());
+                Ok(contents[start..end].to_vec())
+            }
+            (RELEASE | RELEASEDIR, _) => Ok(Vec::new()),
+            _ => Err(ENOSYS),
+        };
+        // `struct fuse_out_header`
+        let (error, payload) = reply.map_or_else(|errno| (-errno, Vec::new()), |p| (0, p));
+        let mut out = Vec::new();
+        out.extend_from_slice(&(16 + payload.len() as u32).to_ne_bytes());
+        out.extend_from_slice(&error.to_ne_bytes());
+        out.extend_from_slice(&unique.to_ne_bytes());
+        out.extend_from_slice(&payload);
+        assert_eq!(conn.write_reply(&out), Ok(out.len()));
+    }
+}
+
+#[cfg(feature = "fuse")]
+fn test_fuse() -> Result<()> {
+    use std::sync::{Arc, Mutex};
+    const CONTENTS: &str = "Hello, FUSE!\n";
+    println!("test fuse:");
+
+    // the daemon is a task answering the requests of the test through the
+    // connection
+    let conn = fs::FuseConnection::new();
+    let opcodes = Arc::new(Mutex::new(Vec::new()));
+    let daemon = {
+        let (conn, opcodes) = (conn.clone(), opcodes.clone());
+        axtask::spawn(move || *opcodes.lock().unwrap() = fuse_daemon(&conn, CONTENTS.as_bytes()))
+    };
+
+    fs::create_dir("/fuse")?;
+    let data = "fd=3,rootmode=40000,user_id=0,group_id=0";
+    fs::mount_fuse(&conn, "hello", "/fuse", "fuse.hello", data)?;
+    assert!(fs::metadata("/fuse")?.is_dir());
+    let metadata = fs::metadata("/fuse/hello.txt")?;
+    assert!(metadata.is_file());
+    assert_eq!(metadata.len(), CONTENTS.len() as u64);
+    assert_eq!(fs::read_to_string("/fuse/hello.txt")?, CONTENTS);
+    assert_err!(fs::metadata("/fuse/missing.txt"), NotFound);
+    // the connection is used by the mount
+    assert_err!(
+        fs::mount_fuse(&conn, "hello", "/fuse", "fuse.hello", data),
+        InvalidInput
+    );
+
+    // unmounting aborts the connection, which stops the daemon
+    fs::umount("/fuse", fs::UmountFlags::empty())?;
+    daemon.join();
+    assert_err!(conn.read_request(&mut [0; 8192]), NoSuchDevice);
+    let opcodes = opcodes.lock().unwrap();
+    println!("opcodes = {:?}", opcodes);
+    // INIT, LOOKUP, GETATTR and READ
+    for opcode in [26, 1, 3, 15] {
+        assert!(opcodes.contains(&opcode));
+    }
+
+    fs::remove_dir("/fuse")?;
+    println!("test_fuse() OK!");
+    Ok(())
+}
+
 pub fn test_all() {
     test_read_write_file().expect("test_read_write_file() failed");
     test_read_dir().expect("test_read_dir() failed");
     test_file_permission().expect("test_file_permission() failed");
     test_create_file_dir().expect("test_create_file_dir() failed");
     test_remove_file_dir().expect("test_remove_file_dir() failed");
+    #[cfg(feature = "ramfs")]
+    test_inotify().expect("test_inotify() failed");
+    #[cfg(feature = "ramfs")]
+    test_file_locks().expect("test_file_locks() failed");
+    #[cfg(feature = "overlayfs")]
+    test_overlay().expect("test_overlay() failed");
+    #[cfg(feature = "ramfs")]
+    test_permissions().expect("test_permissions() failed");
     test_devfs_ramfs().expect("test_devfs_ramfs() failed");
+    #[cfg(feature = "ramfs")]
+    test_mounts().expect("test_mounts() failed");
+    #[cfg(feature = "ramfs")]
+    test_path_walk().expect("test_path_walk() failed");
+    #[cfg(all(feature = "ramfs", feature = "sysfs"))]
+    test_page_cache().expect("test_page_cache() failed");
+    #[cfg(feature = "fuse")]
+    test_fuse().expect("test_fuse() failed");
 }
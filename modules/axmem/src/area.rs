@@ -1,8 +1,9 @@
-use alloc::vec::Vec;
+use alloc::{sync::Arc, vec::Vec};
 use axalloc::PhysPage;
 use axerrno::AxResult;
+use axfs::api::CachedPage;
 use axhal::{
-    mem::{virt_to_phys, VirtAddr, PAGE_SIZE_4K},
+    mem::{virt_to_phys, PhysAddr, VirtAddr, PAGE_SIZE_4K},
     paging::{MappingFlags, PageSize, PageTable},
 };
 use axio::{Seek, SeekFrom};
@@ -11,12 +12,29 @@ use riscv::asm::sfence_vma;
 
 use crate::MemBackend;
 
+/// A physical page mapped in a `MapArea`.
+pub enum AreaPage {
+    /// A page owned by the area.
+    Private(PhysPage),
+    /// A page of the file's page cache, mapped by all the shared mappings of the file.
+    Shared(Arc<CachedPage>),
+}
+
+impl AreaPage {
+    pub fn start_paddr(&self) -> PhysAddr {
+        match self {
+            Self::Private(page) => virt_to_phys(page.start_vaddr),
+            Self::Shared(page) => page.start_paddr(),
+        }
+    }
+}
+
 /// A continuous virtual area in user memory.
 ///
 /// NOTE: Cloning a `MapArea` needs allocating new phys pages and modifying a page table. So
 /// `Clone` trait won't implemented.
 pub struct MapArea {
-    pub pages: Vec<Option<PhysPage>>,
+    pub pages: Vec<Option<AreaPage>>,
     /// 起始虚拟地址
     pub vaddr: VirtAddr,
     pub flags: MappingFlags,
@@ -75,7 +93,10 @@ impl MapArea {
             )
             .unwrap();
         Ok(Self {
-            pages,
+            pages: pages
+                .into_iter()
+                .map(|page| page.map(AreaPage::Private))
+                .collect(),
             vaddr: start,
             flags,
             backend,
@@ -123,6 +144,37 @@ impl MapArea {
 
         debug!("page index {}", page_index);
 
+        // Shared file mapping: map the page in the page cache
+        if let Some(backend) = &mut self.backend {
+            if let Some(cache) = backend.page_cache().cloned() {
+                let offset = backend.offset() + (page_index * PAGE_SIZE_4K) as u64;
+                let page = match cache.get_page(offset / PAGE_SIZE_4K as u64) {
+                    Ok(page) => page,
+                    Err(e) => {
+                        warn!("Failed to load page from page cache: {:?}", e);
+                        return false;
+                    }
+                };
+                // Writes through the mapping are not tracked, a writable page is dirty at once.
+                if self.flags.contains(MappingFlags::WRITE) {
+                    page.set_dirty();
+                }
+                page_table
+                    .map_overwrite(
+                        addr.align_down_4k(),
+                        page.start_paddr(),
+                        PageSize::Size4K,
+                        self.flags,
+                    )
+                    .expect("Map in page fault handler failed");
+                unsafe {
+                    sfence_vma(0, addr.align_down_4k().into());
+                }
+                self.pages[page_index] = Some(AreaPage::Shared(page));
+                return true;
+            }
+        }
+
         // Allocate new page
         let mut page = PhysPage::alloc().expect("Error allocating new phys page for page fault");
 
@@ -160,30 +212,23 @@ impl MapArea {
         unsafe {
             sfence_vma(0, addr.align_down_4k().into());
         }
-        self.pages[page_index] = Some(page);
+        self.pages[page_index] = Some(AreaPage::Private(page));
         true
     }
 
-    /// Sync pages in index back to `self.backend` (if there is one).
-    ///
-    /// # Panics
-    ///
-    /// Panics if index is out of bounds.
-    pub fn sync_page_with_backend(&mut self, page_index: usize) {
-        if let Some(page) = &self.pages[page_index] {
-            if let Some(backend) = &mut self.backend {
-                if backend.writable() {
-                    let _ = backend
-                        .write_to_seek(
-                            SeekFrom::Start((page_index * PAGE_SIZE_4K) as u64),
-                            page.as_slice(),
-                        )
-                        .unwrap();
-                }
-            }
-        } else {
-            debug!("Tried to sync an unallocated page");
-        }
+    /// Write back the part of a shared file mapping overlapping [start, end) to the file, through
+    /// the page cache. Private mappings are never written back.
+    pub fn sync_with_backend(&mut self, start: VirtAddr, end: VirtAddr) -> AxResult {
+        let Some(backend) = &mut self.backend else {
+            return Ok(());
+        };
+        let Some(cache) = backend.page_cache().cloned() else {
+            return Ok(());
+        };
+        let offset = backend.offset();
+        let start = (start.max(self.vaddr) - self.vaddr.as_usize()).as_usize() as u64;
+        let end = (end.min(self.end_va()) - self.vaddr.as_usize()).as_usize() as u64;
+        cache.sync_range(offset + start, offset + end)
     }
 
     /// Deallocate some pages from the start of the area.
@@ -378,14 +423,21 @@ impl MapArea {
         self.pages.iter().all(|page| page.is_some())
     }
 
+    /// Whether this is a shared file mapping, whose pages are in the page cache.
+    pub fn is_shared(&self) -> bool {
+        self.backend
+            .as_ref()
+            .is_some_and(|backend| backend.page_cache().is_some())
+    }
+
     pub unsafe fn as_slice(&self) -> &[u8] {
         unsafe { core::slice::from_raw_parts(self.vaddr.as_ptr(), self.size()) }
     }
 
-    /// Fill `self` with `byte`.
+    /// Fill the private pages of `self` with `byte`.
     pub fn fill(&mut self, byte: u8) {
         self.pages.iter_mut().for_each(|page| {
-            if let Some(page) = page {
+            if let Some(AreaPage::Private(page)) = page {
                 page.fill(byte);
             }
         });
@@ -421,7 +473,7 @@ impl MapArea {
     /// This function will modify the page table as well.
     pub unsafe fn clone_alloc(&self, page_table: &mut PageTable) -> AxResult<Self> {
         // All the pages have been allocated. Allocate a contiguous area in phys memory.
-        if self.allocated() {
+        if self.allocated() && !self.is_shared() {
             MapArea::new_alloc(
                 self.vaddr,
                 self.pages.len(),
@@ -438,7 +490,15 @@ impl MapArea {
                 .map(|(idx, slot)| {
                     let vaddr = self.vaddr + (idx * PAGE_SIZE_4K);
                     match slot.as_ref() {
-                        Some(page) => {
+                        // Shared pages stay shared with the child.
+                        Some(AreaPage::Shared(page)) => {
+                            let _ = page_table
+                                .map(vaddr, page.start_paddr(), PageSize::Size4K, self.flags)
+                                .unwrap();
+
+                            Some(AreaPage::Shared(page.clone()))
+                        }
+                        Some(AreaPage::Private(page)) => {
                             let mut new_page = PhysPage::alloc().unwrap();
                             unsafe {
                                 copy_nonoverlapping(
@@ -457,7 +517,7 @@ impl MapArea {
                                 )
                                 .unwrap();
 
-                            Some(new_page)
+                            Some(AreaPage::Private(new_page))
                         }
                         None => {
                             let _ = page_table.map_fault(vaddr, PageSize::Size4K).unwrap();
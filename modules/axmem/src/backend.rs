@@ -1,18 +1,46 @@
-use alloc::boxed::Box;
-use axfs::api::{File, FileExt};
+use alloc::{boxed::Box, sync::Arc};
+use axfs::api::{File, FileExt, PageCache};
 use axio::{Read, Seek, SeekFrom};
 
 /// File backend for Lazy load `MapArea`. `file` should be a file holding a offset value. Normally,
 /// `MemBackend` won't share a file with other things, so we use a `Box` here.
 pub struct MemBackend {
     file: Box<dyn FileExt>,
+    /// The page cache of the file if this is a shared mapping (`MAP_SHARED`), whose pages are
+    /// mapped directly instead of copied.
+    cache: Option<Arc<PageCache>>,
 }
 
 impl MemBackend {
+    /// Create a backend for a private mapping, which copies the file data on page faults.
     pub fn new(mut file: Box<dyn FileExt>, offset: u64) -> Self {
         let _ = file.seek(SeekFrom::Start(offset)).unwrap();
 
-        Self { file }
+        Self { file, cache: None }
+    }
+
+    /// Create a backend for a shared mapping, which maps the pages of the file's page cache.
+    ///
+    /// Falls back to a private mapping if the file has no page cache, e.g. a device.
+    pub fn new_shared(file: Box<dyn FileExt>, offset: u64) -> Self {
+        let cache = file
+            .as_any()
+            .downcast_ref::<File>()
+            .and_then(File::page_cache);
+        Self {
+            cache,
+            ..Self::new(file, offset)
+        }
+    }
+
+    /// The page cache of the file if this is a shared mapping.
+    pub fn page_cache(&self) -> Option<&Arc<PageCache>> {
+        self.cache.as_ref()
+    }
+
+    /// The offset in the file where the mapping starts.
+    pub fn offset(&mut self) -> u64 {
+        self.file.seek(SeekFrom::Current(0)).unwrap()
     }
 
     pub fn clone_with_delta(&self, delta: i64) -> Self {
@@ -51,6 +79,7 @@ impl Clone for MemBackend {
 
         Self {
             file: Box::new(file),
+            cache: self.cache.clone(),
         }
     }
 }
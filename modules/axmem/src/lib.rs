@@ -2,7 +2,7 @@
 mod area;
 mod backend;
 mod shared;
-pub use area::MapArea;
+pub use area::{AreaPage, MapArea};
 use axerrno::{AxError, AxResult};
 pub use backend::MemBackend;
 
@@ -316,6 +316,11 @@ impl MemorySet {
             .unwrap_or_default()
     }
 
+    /// The user memory areas, in address order.
+    pub fn areas_mut(&mut self) -> impl Iterator<Item = &mut MapArea> {
+        self.owned_mem.values_mut()
+    }
+
     /// Allocate contiguous region. If no data, it will create a lazy load region.
     pub fn new_region(
         &mut self,
@@ -511,23 +516,15 @@ impl MemorySet {
         self.split_for_area(start, size);
     }
 
-    /// msync
-    pub fn msync(&mut self, start: VirtAddr, size: usize) {
+    /// msync. Write back the shared file mappings in [start, start + size) to the files.
+    pub fn msync(&mut self, start: VirtAddr, size: usize) -> AxResult {
         let end = start + size;
         for area in self.owned_mem.values_mut() {
-            if area.backend.is_none() {
-                continue;
-            }
             if area.overlap_with(start, end) {
-                for page_index in 0..area.pages.len() {
-                    let page_vaddr = area.vaddr + page_index * PAGE_SIZE_4K;
-
-                    if page_vaddr >= start && page_vaddr < end {
-                        area.sync_page_with_backend(page_index);
-                    }
-                }
+                area.sync_with_backend(start, end)?;
             }
         }
+        Ok(())
     }
 
     /// Edit the page table to update flags in given virt address segment. You need to flush TLB
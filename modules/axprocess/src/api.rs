@@ -7,6 +7,7 @@ use alloc::{
     vec::Vec,
 };
 use axerrno::{AxError, AxResult};
+use axfs::api::Metadata;
 use axhal::mem::VirtAddr;
 use axhal::paging::MappingFlags;
 use axhal::KERNEL_PROCESS_ID;
@@ -39,6 +40,15 @@ pub fn init_kernel_process() {
         &IDLE_TASK.current_ref_raw().get_unchecked()
     }));
     PID2PC.lock().insert(kernel_process.pid(), kernel_process);
+    axfs::api::register_credentials(fs_credentials);
+    #[cfg(feature = "signal")]
+    axfs::api::register_signal_pending(fs_signal_pending);
+    #[cfg(feature = "fs")]
+    {
+        crate::procfs::init();
+        crate::tty::register_devices();
+    }
+    crate::tty::init();
 }
 
 pub fn current_process() -> Arc<Process> {
@@ -49,6 +59,30 @@ pub fn current_process() -> Arc<Process> {
     current_process
 }
 
+/// 当前进程访问文件时的身份，不属于任何进程的任务以超级用户身份访问
+fn fs_credentials() -> axfs::api::Credentials {
+    let pid = current().get_process_id();
+    let process = PID2PC.lock().get(&pid).cloned();
+    match process {
+        Some(process) => process.cred.lock().fs_credentials(),
+        None => axfs::api::Credentials::root(),
+    }
+}
+
+/// 当前任务是否有待处理的信号，用于打断文件系统中的等待，如等待 FUSE 守护进程的应答
+#[cfg(feature = "signal")]
+fn fs_signal_pending() -> bool {
+    let curr = current();
+    let process = PID2PC.lock().get(&curr.get_process_id()).cloned();
+    process.is_some_and(|process| {
+        process
+            .signal_modules
+            .lock()
+            .get(&curr.id().as_u64())
+            .is_some_and(|module| module.signal_set.find_signal().is_some())
+    })
+}
+
 /// 退出当前任务
 pub fn exit_current_task(exit_code: i32) -> ! {
     let process = current_process();
@@ -105,11 +139,17 @@ pub fn exit_current_task(exit_code: i32) -> ! {
         }
         TID2TASK.lock().remove(&curr_id);
         process.set_exit_code(exit_code);
+        if process.sid() == process.pid() {
+            // 会话首进程退出，挂断控制终端
+            crate::tty::session_leader_exit(process.pid());
+        }
 
         process.set_zombie(true);
 
         process.tasks.lock().clear();
         process.fd_manager.fd_table.lock().clear();
+        // 释放进程持有的 POSIX 记录锁
+        axfs::api::release_locks(axfs::api::LockOwner::Process(process.pid()));
         #[cfg(feature = "signal")]
         process.signal_modules.lock().clear();
 
@@ -143,6 +183,16 @@ pub fn exit_current_task(exit_code: i32) -> ! {
     RUN_QUEUE.lock().exit_current(exit_code);
 }
 
+/// 检查当前进程能否执行程序 `name`，返回真正加载的程序文件的元数据
+///
+/// 与 [`load_app`] 一致，`.sh` 脚本由 busybox 解释执行，因此检查的是 busybox
+pub fn check_exec(name: &str) -> AxResult<Metadata> {
+    if name.ends_with(".sh") {
+        return axfs::api::check_exec("busybox");
+    }
+    axfs::api::check_exec(name)
+}
+
 /// 返回应用程序入口，用户栈底，用户堆底
 pub fn load_app(
     name: String,
@@ -207,6 +257,18 @@ pub fn handle_page_fault(addr: VirtAddr, flags: MappingFlags) {
     }
 }
 
+/// 子进程是否为 wait 的对象
+///
+/// pid 为 -1 时等待任意子进程，为 0 时等待同一进程组的子进程，小于 -1 时等待进程组 -pid 中的子进程
+fn is_wait_target(pid: isize, child: &Process, pgid: u64) -> bool {
+    match pid {
+        -1 => true,
+        0 => child.pgid() == pgid,
+        pid if pid < 0 => child.pgid() == (-pid) as u64,
+        pid => child.pid() == pid as u64,
+    }
+}
+
 /// 在当前进程找对应的子进程，并等待子进程结束
 /// 若找到了则返回对应的pid
 /// 否则返回一个状态
@@ -221,36 +283,21 @@ pub unsafe fn wait_pid(pid: isize, exit_code_ptr: *mut i32) -> Result<u64, WaitS
     let mut answer_id: u64 = 0;
     let mut answer_status = WaitStatus::NotExist;
     for (index, child) in curr_process.children.lock().iter().enumerate() {
-        if pid == -1 {
-            // 任意一个进程结束都可以的
-            answer_status = WaitStatus::Running;
-            if let Some(exit_code) = child.get_code_if_exit() {
-                answer_status = WaitStatus::Exited;
-                exit_task_id = index;
-                if !exit_code_ptr.is_null() {
-                    unsafe {
-                        // 因为没有切换页表，所以可以直接填写
-                        *exit_code_ptr = exit_code;
-                    }
+        if !is_wait_target(pid, child, curr_process.pgid()) {
+            continue;
+        }
+        // 找到了对应的进程
+        answer_status = WaitStatus::Running;
+        if let Some(exit_code) = child.get_code_if_exit() {
+            answer_status = WaitStatus::Exited;
+            exit_task_id = index;
+            if !exit_code_ptr.is_null() {
+                unsafe {
+                    // 因为没有切换页表，所以可以直接填写
+                    *exit_code_ptr = exit_code;
                 }
-                answer_id = child.pid();
-                break;
-            }
-        } else if child.pid() == pid as u64 {
-            // 找到了对应的进程
-            if let Some(exit_code) = child.get_code_if_exit() {
-                answer_status = WaitStatus::Exited;
-                exit_task_id = index;
-                if !exit_code_ptr.is_null() {
-                    unsafe {
-                        *exit_code_ptr = exit_code;
-                        // 用于WEXITSTATUS设置编码
-                    }
-                }
-                answer_id = child.pid();
-            } else {
-                answer_status = WaitStatus::Running;
             }
+            answer_id = child.pid();
             break;
         }
     }
@@ -262,6 +309,36 @@ pub unsafe fn wait_pid(pid: isize, exit_code_ptr: *mut i32) -> Result<u64, WaitS
     Err(answer_status)
 }
 
+/// 在当前进程找被停止（`stopped`）或者继续运行（`continued`）且尚未报告的子进程，
+/// 规则与 [`wait_pid`] 相同，找到时写入 wait 的状态码并返回其pid
+///
+/// # Safety
+///
+/// 保证传入的 ptr 是有效的
+pub unsafe fn wait_job_event(
+    pid: isize,
+    exit_code_ptr: *mut i32,
+    stopped: bool,
+    continued: bool,
+) -> Option<u64> {
+    let curr_process = current_process();
+    let children = curr_process.children.lock();
+    for child in children.iter() {
+        if !is_wait_target(pid, child, curr_process.pgid()) {
+            continue;
+        }
+        if let Some(status) = child.take_job_event(stopped, continued) {
+            if !exit_code_ptr.is_null() {
+                unsafe {
+                    *exit_code_ptr = status;
+                }
+            }
+            return Some(child.pid());
+        }
+    }
+    None
+}
+
 /// 以进程作为中转调用task的yield
 pub fn yield_now_task() {
     axtask::yield_now();
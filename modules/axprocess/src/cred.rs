@@ -0,0 +1,168 @@
+//! 进程的身份，即用户 id 与用户组 id
+//!
+//! 每个进程有实际、有效与保存的用户 id 和用户组 id，以及附属用户组。文件系统用有效 id
+//! 与附属组检查权限，access(2) 用实际 id 检查。执行设置了 set-user-ID 或 set-group-ID
+//! 位的程序时，有效 id 变为程序文件的所有者或用户组。
+//!
+//! 有效用户 id 为0的进程是超级用户，可以任意修改自己的 id。
+extern crate alloc;
+use alloc::vec::Vec;
+use axerrno::{AxError, AxResult};
+use axfs::api::{Metadata, Permissions};
+
+/// 附属用户组的最大数量，与 Linux 的 NGROUPS_MAX 相同
+pub const NGROUPS_MAX: usize = 65536;
+
+/// 进程的身份
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Credentials {
+    /// 实际用户 id
+    pub uid: u32,
+    /// 有效用户 id，检查权限时使用
+    pub euid: u32,
+    /// 保存的用户 id，非特权进程可以把有效用户 id 切换回它
+    pub suid: u32,
+    /// 实际用户组 id
+    pub gid: u32,
+    /// 有效用户组 id
+    pub egid: u32,
+    /// 保存的用户组 id
+    pub sgid: u32,
+    /// 附属用户组
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    /// 超级用户的身份，初始进程以此运行
+    pub const fn root() -> Self {
+        Self {
+            uid: 0,
+            euid: 0,
+            suid: 0,
+            gid: 0,
+            egid: 0,
+            sgid: 0,
+            groups: Vec::new(),
+        }
+    }
+
+    /// 是否有超级用户权限
+    pub fn is_privileged(&self) -> bool {
+        self.euid == 0
+    }
+
+    /// 文件系统检查权限时使用的身份，即有效 id
+    pub fn fs_credentials(&self) -> axfs::api::Credentials {
+        axfs::api::Credentials {
+            uid: self.euid,
+            gid: self.egid,
+            groups: self.groups.clone(),
+        }
+    }
+
+    /// access(2) 检查权限时使用的身份，即实际 id
+    pub fn real_credentials(&self) -> axfs::api::Credentials {
+        axfs::api::Credentials {
+            uid: self.uid,
+            gid: self.gid,
+            groups: self.groups.clone(),
+        }
+    }
+
+    /// setuid(2)：特权进程同时设置三个用户 id，非特权进程只能把有效用户 id
+    /// 设为实际或保存的用户 id
+    pub fn set_uid(&mut self, uid: u32) -> AxResult {
+        if self.is_privileged() {
+            self.uid = uid;
+            self.suid = uid;
+        } else if uid != self.uid && uid != self.suid {
+            return Err(AxError::OperationNotPermitted);
+        }
+        self.euid = uid;
+        Ok(())
+    }
+
+    /// setgid(2)，规则与 [`Self::set_uid`] 相同
+    pub fn set_gid(&mut self, gid: u32) -> AxResult {
+        if self.is_privileged() {
+            self.gid = gid;
+            self.sgid = gid;
+        } else if gid != self.gid && gid != self.sgid {
+            return Err(AxError::OperationNotPermitted);
+        }
+        self.egid = gid;
+        Ok(())
+    }
+
+    /// setresuid(2)：为 None 的 id 不变。非特权进程只能设为当前的三个用户 id 之一
+    pub fn set_resuid(
+        &mut self,
+        uid: Option<u32>,
+        euid: Option<u32>,
+        suid: Option<u32>,
+    ) -> AxResult {
+        let current = [self.uid, self.euid, self.suid];
+        if !self.is_privileged()
+            && [uid, euid, suid]
+                .into_iter()
+                .flatten()
+                .any(|id| !current.contains(&id))
+        {
+            return Err(AxError::OperationNotPermitted);
+        }
+        self.uid = uid.unwrap_or(self.uid);
+        self.euid = euid.unwrap_or(self.euid);
+        self.suid = suid.unwrap_or(self.suid);
+        Ok(())
+    }
+
+    /// setresgid(2)，规则与 [`Self::set_resuid`] 相同
+    pub fn set_resgid(
+        &mut self,
+        gid: Option<u32>,
+        egid: Option<u32>,
+        sgid: Option<u32>,
+    ) -> AxResult {
+        let current = [self.gid, self.egid, self.sgid];
+        if !self.is_privileged()
+            && [gid, egid, sgid]
+                .into_iter()
+                .flatten()
+                .any(|id| !current.contains(&id))
+        {
+            return Err(AxError::OperationNotPermitted);
+        }
+        self.gid = gid.unwrap_or(self.gid);
+        self.egid = egid.unwrap_or(self.egid);
+        self.sgid = sgid.unwrap_or(self.sgid);
+        Ok(())
+    }
+
+    /// setgroups(2)，只有特权进程可以设置附属用户组
+    pub fn set_groups(&mut self, groups: Vec<u32>) -> AxResult {
+        if !self.is_privileged() {
+            return Err(AxError::OperationNotPermitted);
+        }
+        if groups.len() > NGROUPS_MAX {
+            return Err(AxError::InvalidInput);
+        }
+        self.groups = groups;
+        Ok(())
+    }
+
+    /// 执行程序 `metadata` 后的身份
+    ///
+    /// set-user-ID 位使有效用户 id 变为文件所有者，set-group-ID 位（同时有用户组执行位时）
+    /// 使有效用户组 id 变为文件的用户组。保存的 id 随后更新为有效 id
+    pub fn exec(&mut self, metadata: &Metadata) {
+        let perm = metadata.permissions();
+        if perm.contains(Permissions::SET_UID) {
+            self.euid = metadata.uid();
+        }
+        if perm.contains(Permissions::SET_GID | Permissions::GROUP_EXEC) {
+            self.egid = metadata.gid();
+        }
+        self.suid = self.euid;
+        self.sgid = self.egid;
+    }
+}
@@ -4,13 +4,13 @@ use core::sync::atomic::{AtomicI32, AtomicU64};
 
 use alloc::string::String;
 use alloc::sync::Arc;
-use axfs::api::{FileIO, OpenFlags};
+use axfs::api::FileIO;
 use axlog::info;
 
 use alloc::vec::Vec;
 use axsync::Mutex;
 
-use crate::stdio::{Stdin, Stdout};
+use crate::tty::console_file;
 pub struct FdManager {
     /// 保存文件描述符的数组
     pub fd_table: Mutex<Vec<Option<Arc<dyn FileIO>>>>,
@@ -65,14 +65,10 @@ impl FdManager {
             }
         }
         if fd_table[0].is_none() {
-            fd_table[0] = Some(Arc::new(Stdin {
-                flags: Mutex::new(OpenFlags::empty()),
-            }));
+            fd_table[0] = Some(console_file());
         }
         if fd_table[1].is_none() {
-            fd_table[1] = Some(Arc::new(Stdout {
-                flags: Mutex::new(OpenFlags::empty()),
-            }));
+            fd_table[1] = Some(console_file());
         }
     }
 }
@@ -4,12 +4,15 @@ pub use api::*;
 mod process;
 pub use process::{Process, PID2PC, TID2TASK};
 
+pub mod cred;
 pub mod flags;
 pub mod futex;
 pub mod link;
 pub mod loader;
-mod stdio;
+pub mod tty;
 
 mod fd_manager;
+#[cfg(feature = "fs")]
+mod procfs;
 #[cfg(feature = "signal")]
 pub mod signal;
@@ -18,10 +18,7 @@ use axmem::MemorySet;
 use core::str::from_utf8;
 use xmas_elf::{program::SegmentData, ElfFile};
 
-use crate::{
-    link::{real_path, FilePath},
-    loader::user_stack::init_stack,
-};
+use crate::loader::user_stack::init_stack;
 
 /// A elf file wrapper.
 pub struct Loader<'a> {
@@ -74,9 +71,10 @@ impl<'a> Loader<'a> {
             {
                 panic!("ELF Interpreter is not supported without fs feature");
             }
-            let interp_path = FilePath::new(interp_path)?;
-            let real_interp_path = real_path(&(interp_path.path().to_string()));
-            let interp = axfs::api::read(real_interp_path.as_str())
+            // 解释器路径中可能含有符号链接，如 /lib/ld-musl-riscv64.so.1 -> libc.so
+            let interp_path =
+                axfs::api::resolve_path(None, interp_path, axfs::api::LookupFlags::empty())?.path;
+            let interp = axfs::api::read(interp_path.as_str())
                 .expect("Error reading Interpreter from fs");
             let loader = Loader::new(&interp);
             return loader.load(new_argv, envs, &mut memory_set);
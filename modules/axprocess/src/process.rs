@@ -5,7 +5,7 @@ use alloc::vec;
 use alloc::vec::Vec;
 use alloc::{collections::BTreeMap, string::String};
 use axerrno::{AxError, AxResult};
-use axfs::api::{FileIO, OpenFlags};
+use axfs::api::FileIO;
 use axhal::arch::{write_page_table_root, TrapFrame};
 use axhal::mem::{phys_to_virt, VirtAddr};
 use axhal::KERNEL_PROCESS_ID;
@@ -15,13 +15,14 @@ use axsync::Mutex;
 use axtask::{current, AxTaskRef, TaskId, TaskInner, RUN_QUEUE};
 use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
 
+use crate::cred::Credentials;
 use crate::fd_manager::FdManager;
 use crate::flags::CloneFlags;
 use crate::futex::FutexRobustList;
-use crate::load_app;
 #[cfg(feature = "signal")]
 use crate::signal::SignalModule;
-use crate::stdio::{Stderr, Stdin, Stdout};
+use crate::tty;
+use crate::{check_exec, load_app};
 pub static TID2TASK: Mutex<BTreeMap<u64, AxTaskRef>> = Mutex::new(BTreeMap::new());
 pub static PID2PC: Mutex<BTreeMap<u64, Arc<Process>>> = Mutex::new(BTreeMap::new());
 const FD_LIMIT_ORIGIN: usize = 1025;
@@ -32,6 +33,18 @@ pub struct Process {
     /// 父进程号
     pub parent: AtomicU64,
 
+    /// 进程组号
+    pgid: AtomicU64,
+
+    /// 会话号
+    sid: AtomicU64,
+
+    /// 被信号停止时为该信号，运行时为0
+    stop_signal: AtomicI32,
+
+    /// 尚未被 wait4 取走的停止或继续事件，为 wait 的状态码，没有时为0
+    job_event: AtomicI32,
+
     /// 子进程
     pub children: Mutex<Vec<Arc<Process>>>,
 
@@ -65,6 +78,18 @@ pub struct Process {
     /// 用来存储线程对共享变量的使用地址
     /// 具体使用交给了用户空间
     pub robust_list: Mutex<BTreeMap<u64, FutexRobustList>>,
+
+    /// 可执行文件的路径，即 /proc/<pid>/exe
+    pub exe: Mutex<String>,
+
+    /// 命令行参数，即 /proc/<pid>/cmdline
+    pub args: Mutex<Vec<String>>,
+
+    /// 环境变量，即 /proc/<pid>/environ
+    pub envs: Mutex<Vec<String>>,
+
+    /// 用户与用户组身份
+    pub cred: Mutex<Credentials>,
 }
 
 impl Process {
@@ -112,6 +137,22 @@ impl Process {
         self.heap_bottom.store(bottom, Ordering::Release)
     }
 
+    pub fn pgid(&self) -> u64 {
+        self.pgid.load(Ordering::Acquire)
+    }
+
+    pub fn set_pgid(&self, pgid: u64) {
+        self.pgid.store(pgid, Ordering::Release)
+    }
+
+    pub fn sid(&self) -> u64 {
+        self.sid.load(Ordering::Acquire)
+    }
+
+    pub fn set_sid(&self, sid: u64) {
+        self.sid.store(sid, Ordering::Release)
+    }
+
     /// 若进程运行完成，则获取其返回码
     /// 若正在运行（可能上锁或没有上锁），则返回None
     pub fn get_code_if_exit(&self) -> Option<i32> {
@@ -133,6 +174,10 @@ impl Process {
         Self {
             pid,
             parent: AtomicU64::new(parent),
+            pgid: AtomicU64::new(pid),
+            sid: AtomicU64::new(pid),
+            stop_signal: AtomicI32::new(0),
+            job_event: AtomicI32::new(0),
             children: Mutex::new(Vec::new()),
             tasks: Mutex::new(Vec::new()),
             is_zombie: AtomicBool::new(false),
@@ -144,8 +189,19 @@ impl Process {
             #[cfg(feature = "signal")]
             signal_modules: Mutex::new(BTreeMap::new()),
             robust_list: Mutex::new(BTreeMap::new()),
+            exe: Mutex::new(String::new()),
+            args: Mutex::new(Vec::new()),
+            envs: Mutex::new(Vec::new()),
+            cred: Mutex::new(Credentials::root()),
         }
     }
+
+    /// 记录当前运行的程序，供 procfs 使用
+    fn set_exec_info(&self, exe: String, args: Vec<String>, envs: Vec<String>) {
+        *self.exe.lock() = axfs::api::canonicalize(&exe).unwrap_or(exe);
+        *self.args.lock() = args;
+        *self.envs.lock() = envs;
+    }
     /// 根据给定参数创建一个新的进程，作为应用程序初始进程
     pub fn init(args: Vec<String>) -> AxResult<AxTaskRef> {
         let path = args[0].clone();
@@ -170,7 +226,7 @@ impl Process {
             "LD_LIBRARY_PATH=/lib/".into(),
         ];
         let (entry, user_stack_bottom, heap_bottom) =
-            if let Ok(ans) = load_app(path.clone(), args, envs, &mut memory_set) {
+            if let Ok(ans) = load_app(path.clone(), args.clone(), envs.clone(), &mut memory_set) {
                 ans
             } else {
                 error!("Failed to load app {}", path);
@@ -183,19 +239,16 @@ impl Process {
             heap_bottom.as_usize() as u64,
             vec![
                 // 标准输入
-                Some(Arc::new(Stdin {
-                    flags: Mutex::new(OpenFlags::empty()),
-                })),
+                Some(tty::console_file()),
                 // 标准输出
-                Some(Arc::new(Stdout {
-                    flags: Mutex::new(OpenFlags::empty()),
-                })),
+                Some(tty::console_file()),
                 // 标准错误
-                Some(Arc::new(Stderr {
-                    flags: Mutex::new(OpenFlags::empty()),
-                })),
+                Some(tty::console_file()),
             ],
         ));
+        // 新的应用程序是控制台所在会话的首进程，并且在前台运行
+        tty::console().attach(new_process.sid(), new_process.pgid());
+        new_process.set_exec_info(path.clone(), args, envs);
         let new_task = TaskInner::new(
             || {},
             path,
@@ -245,7 +298,10 @@ impl Process {
     /// 将当前进程替换为指定的用户程序
     /// args为传入的参数
     /// 任务的统计时间会被重置
+    ///
+    /// 没有执行权限时返回 PermissionDenied，此时原程序不受影响
     pub fn exec(&self, name: String, args: Vec<String>, envs: Vec<String>) -> AxResult<()> {
+        let metadata = check_exec(&name)?;
         // 首先要处理原先进程的资源
         // 处理分配的页帧
         // 之后加入额外的东西之后再处理其他的包括信号等因素
@@ -281,6 +337,7 @@ impl Process {
         } else {
             args
         };
+        self.set_exec_info(name.clone(), args.clone(), envs.clone());
         let (entry, user_stack_bottom, heap_bottom) =
             if let Ok(ans) = load_app(name.clone(), args, envs, &mut self.memory_set.lock()) {
                 ans
@@ -288,6 +345,8 @@ impl Process {
                 error!("Failed to load app {}", name);
                 return Err(AxError::NotFound);
             };
+        // 执行 set-user-ID 或 set-group-ID 程序时改变有效 id
+        self.cred.lock().exec(&metadata);
         // 切换了地址空间， 需要切换token
         let page_table_token = if self.pid == KERNEL_PROCESS_ID {
             0
@@ -499,6 +558,15 @@ impl Process {
                 self.get_heap_bottom(),
                 self.fd_manager.fd_table.lock().clone(),
             ));
+            new_process.set_exec_info(
+                self.exe.lock().clone(),
+                self.args.lock().clone(),
+                self.envs.lock().clone(),
+            );
+            // 子进程与父进程属于同一个进程组和会话，身份也相同
+            new_process.set_pgid(self.pgid());
+            new_process.set_sid(self.sid());
+            *new_process.cred.lock() = self.cred.lock().clone();
             // 记录该进程，防止被回收
             PID2PC.lock().insert(process_id, Arc::clone(&new_process));
             new_process.tasks.lock().push(Arc::clone(&new_task));
@@ -582,6 +650,50 @@ impl Process {
         self.fd_manager.cwd.lock().clone()
     }
 }
+/// 与作业控制相关的方法
+impl Process {
+    /// 是否被信号停止
+    pub fn is_stopped(&self) -> bool {
+        self.stop_signal.load(Ordering::Acquire) != 0
+    }
+
+    /// 因信号 `signum` 停止，并记录供 wait4 报告的事件
+    pub fn stop(&self, signum: i32) {
+        self.stop_signal.store(signum, Ordering::Release);
+        self.job_event
+            .store((signum << 8) | 0x7f, Ordering::Release);
+    }
+
+    /// 若被停止，则继续运行并记录供 wait4 报告的事件，返回是否确实被停止过
+    pub fn resume(&self) -> bool {
+        if self.stop_signal.swap(0, Ordering::AcqRel) == 0 {
+            return false;
+        }
+        self.job_event.store(0xffff, Ordering::Release);
+        true
+    }
+
+    /// 取走尚未报告的停止或继续事件，`stopped` 与 `continued` 指定要报告哪一种
+    pub fn take_job_event(&self, stopped: bool, continued: bool) -> Option<i32> {
+        let event = self.job_event.load(Ordering::Acquire);
+        let wanted = match event {
+            0 => false,
+            0xffff => continued,
+            _ => stopped,
+        };
+        if wanted
+            && self
+                .job_event
+                .compare_exchange(event, 0, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        {
+            Some(event)
+        } else {
+            None
+        }
+    }
+}
+
 #[cfg(feature = "signal")]
 /// 与信号相关的方法
 impl Process {
@@ -0,0 +1,339 @@
+//! procfs 中与进程相关的内容
+//!
+//! 包括 /proc/<pid> 目录、/proc/self、/proc/stat 与 /proc/loadavg，
+//! 均在读取时根据 PID2PC 中各进程的线程、文件描述符与地址空间现场生成
+extern crate alloc;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use axerrno::{AxError, AxResult};
+use axfs::api::{FileIOType, ProcDir, ProcFile, ProcSymlink};
+use axfs_vfs::{VfsNodeOps, VfsNodeRef};
+use axhal::mem::PAGE_SIZE_4K;
+use axhal::paging::MappingFlags;
+use axtask::{AxTaskRef, TaskState};
+
+use crate::current_process;
+use crate::process::{Process, PID2PC};
+
+/// 时间统计的单位，即 Linux 的 USER_HZ
+const USER_HZ: usize = 100;
+
+/// 将 procfs 的进程部分注册到文件系统中
+pub fn init() {
+    axfs::api::register_process_entries(process_entries);
+}
+
+/// procfs 根目录下与进程相关的项
+fn process_entries() -> Vec<(String, VfsNodeRef)> {
+    let mut entries = vec![
+        entry(
+            "self",
+            ProcSymlink::new(|| Ok(current_process().pid().to_string())),
+        ),
+        entry("stat", ProcFile::new(|| Ok(stat()))),
+        entry("loadavg", ProcFile::new(|| Ok(loadavg()))),
+    ];
+    // 先复制出进程号，生成目录时不持有 PID2PC 的锁
+    let pids: Vec<u64> = PID2PC.lock().keys().copied().collect();
+    entries.extend(
+        pids.into_iter()
+            .map(|pid| entry(&pid.to_string(), process_dir(pid))),
+    );
+    entries
+}
+
+fn entry(name: &str, node: impl VfsNodeOps + 'static) -> (String, VfsNodeRef) {
+    (name.into(), Arc::new(node))
+}
+
+/// 找到进程号为 pid 的进程，进程已退出时返回 NotFound
+fn process(pid: u64) -> AxResult<Arc<Process>> {
+    PID2PC.lock().get(&pid).cloned().ok_or(AxError::NotFound)
+}
+
+/// 生成进程内容的文件
+fn process_file(pid: u64, render: fn(&Process) -> String) -> ProcFile {
+    ProcFile::new(move || Ok(render(&*process(pid)?)))
+}
+
+/// /proc/<pid> 目录
+fn process_dir(pid: u64) -> ProcDir {
+    ProcDir::with_entries(vec![
+        entry("stat", process_file(pid, process_stat)),
+        entry("status", process_file(pid, process_status)),
+        entry("comm", process_file(pid, |p| comm(p) + "\n")),
+        entry(
+            "cmdline",
+            process_file(pid, |p| nul_separated(&p.args.lock())),
+        ),
+        entry(
+            "environ",
+            process_file(pid, |p| nul_separated(&p.envs.lock())),
+        ),
+        entry("maps", process_file(pid, process_maps)),
+        entry("mounts", process_file(pid, |_| axfs::api::proc_mounts())),
+        entry(
+            "mountinfo",
+            process_file(pid, |_| axfs::api::proc_mountinfo()),
+        ),
+        entry("fd", fd_dir(pid)),
+        entry("cwd", ProcSymlink::new(move || Ok(process(pid)?.get_cwd()))),
+        entry(
+            "exe",
+            ProcSymlink::new(move || {
+                let exe = process(pid)?.exe.lock().clone();
+                if exe.is_empty() {
+                    Err(AxError::NotFound)
+                } else {
+                    Ok(exe)
+                }
+            }),
+        ),
+    ])
+}
+
+/// /proc/<pid>/fd 目录，每个打开的文件描述符对应一个符号链接
+fn fd_dir(pid: u64) -> ProcDir {
+    ProcDir::new(move || {
+        let fds: Vec<usize> = match process(pid) {
+            Ok(process) => process
+                .fd_manager
+                .fd_table
+                .lock()
+                .iter()
+                .enumerate()
+                .filter_map(|(fd, file)| file.as_ref().map(|_| fd))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        fds.into_iter()
+            .map(|fd| {
+                let link = ProcSymlink::new(move || fd_target(pid, fd));
+                entry(&fd.to_string(), link)
+            })
+            .collect()
+    })
+}
+
+/// /proc/<pid>/fd/<fd> 指向的路径，没有路径的文件按 Linux 的格式表示
+fn fd_target(pid: u64, fd: usize) -> AxResult<String> {
+    let process = process(pid)?;
+    let file = process
+        .fd_manager
+        .fd_table
+        .lock()
+        .get(fd)
+        .cloned()
+        .flatten();
+    let file = file.ok_or(AxError::NotFound)?;
+    // 以对象地址作为匿名文件的编号
+    let ino = Arc::as_ptr(&file) as *const u8 as usize;
+    Ok(match file.get_type() {
+        FileIOType::FileDesc | FileIOType::DirDesc | FileIOType::Link | FileIOType::Tty => {
+            file.get_path()
+        }
+        FileIOType::Stdin | FileIOType::Stdout | FileIOType::Stderr => "/dev/tty".into(),
+        FileIOType::Pipe => format!("pipe:[{}]", ino),
+        FileIOType::Socket => format!("socket:[{}]", ino),
+        FileIOType::Other => format!("anon_inode:[{}]", ino),
+    })
+}
+
+/// 进程名，即可执行文件名，内核进程没有可执行文件
+fn comm(process: &Process) -> String {
+    let exe = process.exe.lock();
+    match exe.rsplit('/').next() {
+        Some(name) if !name.is_empty() => name.chars().take(15).collect(),
+        _ => "kernel".into(),
+    }
+}
+
+/// 以 '\0' 结尾拼接字符串，用于 cmdline 与 environ
+fn nul_separated(strings: &[String]) -> String {
+    strings.iter().map(|s| format!("{}\0", s)).collect()
+}
+
+/// 进程状态的字符表示：有线程在运行或就绪为 R，全部阻塞为 S
+fn state(process: &Process) -> char {
+    if process.get_zombie() {
+        return 'Z';
+    }
+    let tasks = process.tasks.lock();
+    if tasks
+        .iter()
+        .any(|task| matches!(task.state(), TaskState::Running | TaskState::Ready))
+    {
+        'R'
+    } else {
+        'S'
+    }
+}
+
+fn state_name(state: char) -> &'static str {
+    match state {
+        'R' => "R (running)",
+        'Z' => "Z (zombie)",
+        _ => "S (sleeping)",
+    }
+}
+
+/// 线程的 (用户态, 内核态) 时间，单位为 USER_HZ
+fn task_ticks(task: &AxTaskRef) -> (usize, usize) {
+    let (_, utime_us, _, stime_us) = task.time_stat_output();
+    let us_per_tick = 1_000_000 / USER_HZ;
+    (utime_us / us_per_tick, stime_us / us_per_tick)
+}
+
+/// 进程所有线程的 (用户态, 内核态) 时间，单位为 USER_HZ
+fn process_ticks(process: &Process) -> (usize, usize) {
+    process
+        .tasks
+        .lock()
+        .iter()
+        .map(task_ticks)
+        .fold((0, 0), |(u, s), (tu, ts)| (u + tu, s + ts))
+}
+
+/// 地址空间的 (虚拟内存大小, 已分配的页数)
+fn memory_usage(process: &Process) -> (usize, usize) {
+    let mut memory_set = process.memory_set.lock();
+    memory_set.areas_mut().fold((0, 0), |(size, pages), area| {
+        let allocated = area.pages.iter().filter(|page| page.is_some()).count();
+        (size + area.size(), pages + allocated)
+    })
+}
+
+/// /proc/<pid>/stat
+fn process_stat(process: &Process) -> String {
+    let pid = process.pid();
+    let (utime, stime) = process_ticks(process);
+    let (vsize, rss) = memory_usage(process);
+    let num_threads = process.tasks.lock().len();
+    format!(
+        "{pid} ({comm}) {state} {ppid} {pid} {pid} 0 -1 0 0 0 0 0 {utime} {stime} 0 0 20 0 \
+         {num_threads} 0 0 {vsize} {rss} {rsslim} 0 0 0 0 0 0 0 0 0 0 0 0 17 0 0 0 0 0 0 0 0 0 \
+         0 0 0 0 0\n",
+        comm = comm(process),
+        state = state(process),
+        ppid = process.get_parent(),
+        rsslim = usize::MAX,
+    )
+}
+
+/// /proc/<pid>/status
+fn process_status(process: &Process) -> String {
+    let pid = process.pid();
+    let (vsize, rss) = memory_usage(process);
+    let fd_size = process.fd_manager.fd_table.lock().len();
+    format!(
+        "Name:\t{}\nUmask:\t{:04o}\nState:\t{}\nTgid:\t{pid}\nPid:\t{pid}\nPPid:\t{}\n\
+         Uid:\t0\t0\t0\t0\nGid:\t0\t0\t0\t0\nFDSize:\t{}\nVmSize:\t{} kB\nVmRSS:\t{} kB\n\
+         Threads:\t{}\n",
+        comm(process),
+        process.fd_manager.get_mask(),
+        state_name(state(process)),
+        process.get_parent(),
+        fd_size,
+        vsize / 1024,
+        rss * PAGE_SIZE_4K / 1024,
+        process.tasks.lock().len(),
+    )
+}
+
+/// /proc/<pid>/maps，文件映射的路径暂不记录
+fn process_maps(process: &Process) -> String {
+    let heap = process.get_heap_bottom() as usize;
+    let mut memory_set = process.memory_set.lock();
+    memory_set
+        .areas_mut()
+        .map(|area| {
+            let start = area.vaddr.as_usize();
+            let end = area.end_va().as_usize();
+            let flag = |flag, c| if area.flags.contains(flag) { c } else { '-' };
+            let shared = if area.is_shared() { 's' } else { 'p' };
+            let offset = area.backend.as_mut().map_or(0, |backend| backend.offset());
+            let name = if (start..end).contains(&heap) {
+                "[heap]"
+            } else {
+                ""
+            };
+            let line = format!(
+                "{:08x}-{:08x} {}{}{}{} {:08x} 00:00 0",
+                start,
+                end,
+                flag(MappingFlags::READ, 'r'),
+                flag(MappingFlags::WRITE, 'w'),
+                flag(MappingFlags::EXECUTE, 'x'),
+                shared,
+                offset,
+            );
+            if name.is_empty() {
+                line + "\n"
+            } else {
+                format!("{:<72} {}\n", line, name)
+            }
+        })
+        .collect()
+}
+
+/// /proc/stat，CPU 时间按所有线程的时间统计，其余为空闲时间
+fn stat() -> String {
+    let processes: Vec<Arc<Process>> = PID2PC.lock().values().cloned().collect();
+    let (mut user, mut system) = (0, 0);
+    let (mut running, mut blocked) = (0, 0);
+    for process in &processes {
+        let (utime, stime) = process_ticks(process);
+        user += utime;
+        system += stime;
+        for task in process.tasks.lock().iter() {
+            match task.state() {
+                TaskState::Running | TaskState::Ready => running += 1,
+                TaskState::Blocked => blocked += 1,
+                TaskState::Exited => {}
+            }
+        }
+    }
+    let cpus = axconfig::SMP;
+    let uptime_ticks = axhal::time::current_time().as_millis() as usize / (1000 / USER_HZ);
+    let idle = (uptime_ticks * cpus).saturating_sub(user + system);
+    let cpu_line = |name: String, n: usize| {
+        format!(
+            "{} {} 0 {} {} 0 0 0 0 0 0\n",
+            name,
+            user / n,
+            system / n,
+            idle / n
+        )
+    };
+    let mut stat = cpu_line("cpu ".into(), 1);
+    for cpu in 0..cpus {
+        stat += &cpu_line(format!("cpu{}", cpu), cpus);
+    }
+    stat += &format!(
+        "intr 0\nctxt 0\nbtime 0\nprocesses {}\nprocs_running {}\nprocs_blocked {}\n",
+        processes.len(),
+        running,
+        blocked
+    );
+    stat
+}
+
+/// /proc/loadavg，不统计平均负载
+fn loadavg() -> String {
+    let processes: Vec<Arc<Process>> = PID2PC.lock().values().cloned().collect();
+    let mut running = 0;
+    let mut total = 0;
+    for process in &processes {
+        for task in process.tasks.lock().iter() {
+            total += 1;
+            if matches!(task.state(), TaskState::Running | TaskState::Ready) {
+                running += 1;
+            }
+        }
+    }
+    let last_pid = processes.iter().map(|p| p.pid()).max().unwrap_or(0);
+    format!("0.00 0.00 0.00 {}/{} {}\n", running, total, last_pid)
+}
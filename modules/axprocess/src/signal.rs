@@ -1,6 +1,7 @@
 //! 负责处理进程中与信号相关的内容
 extern crate alloc;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use axerrno::{AxError, AxResult};
 use axhal::{arch::TrapFrame, cpu::this_cpu_id, KERNEL_PROCESS_ID};
 use axlog::{info, warn};
@@ -12,7 +13,7 @@ use axsignal::{
     SignalHandler, SignalSet,
 };
 use axsync::Mutex;
-use axtask::{SignalCaller, TaskState, RUN_QUEUE};
+use axtask::{yield_now, SignalCaller, TaskState, RUN_QUEUE};
 
 pub struct SignalModule {
     pub sig_info: bool,
@@ -44,7 +45,7 @@ const USER_SIGNAL_PROTECT: usize = 512;
 
 use crate::{
     current_process, current_task, exit_current_task,
-    process::{PID2PC, TID2TASK},
+    process::{Process, PID2PC, TID2TASK},
 };
 
 /// 将保存的trap上下文填入内核栈中
@@ -98,6 +99,36 @@ fn terminate_process(signal: SignalNo) {
     }
 }
 
+/// 处理 Stop 类型的信号
+///
+/// 进程停止运行，直到收到 SIGCONT 或 SIGKILL
+fn stop_process(signal: SignalNo) {
+    let process = current_process();
+    warn!("Stop process: {}", process.pid());
+    process.stop(signal as i32);
+    notify_parent(&process);
+    while process.is_stopped() {
+        yield_now();
+    }
+    load_trap_for_signal();
+}
+
+/// 进程停止或继续运行时向父进程发送 SIGCHLD
+fn notify_parent(process: &Process) {
+    let parent = process.get_parent();
+    if parent != KERNEL_PROCESS_ID {
+        let _ = send_signal_to_process(parent as isize, SignalNo::SIGCHLD as isize);
+    }
+}
+
+/// 收到 SIGCONT 或 SIGKILL 时，让被停止的进程继续运行
+fn continue_if_stopped(process: &Process, signum: usize) {
+    let cont = signum == SignalNo::SIGCONT as usize;
+    if (cont || signum == SignalNo::SIGKILL as usize) && process.resume() && cont {
+        notify_parent(process);
+    }
+}
+
 /// 处理当前进程的信号
 ///
 /// 若返回值为真，代表需要进入处理信号，因此需要执行trap的返回
@@ -169,10 +200,11 @@ pub fn handle_signals() {
                 terminate_process(signal);
             }
             SignalDefault::Stop => {
-                unimplemented!();
+                stop_process(signal);
             }
             SignalDefault::Cont => {
-                unimplemented!();
+                // 在发送信号时已经继续运行，此时只需要恢复trap上下文
+                load_trap_for_signal();
             }
             SignalDefault::Core => {
                 terminate_process(signal);
@@ -182,7 +214,10 @@ pub fn handle_signals() {
     }
     let action = action.unwrap();
     if action.sa_handler == SIG_IGN {
-        // 忽略处理
+        // 忽略处理，此时相当于已经完成了处理，所以要把trap上下文清空
+        drop(signal_handler);
+        drop(signal_modules);
+        load_trap_for_signal();
         return;
     }
     // 此时需要调用信号处理函数，注意调用的方式是：
@@ -255,11 +290,10 @@ pub fn signal_return() -> isize {
 ///
 /// 默认发送到该进程下的主线程
 pub fn send_signal_to_process(pid: isize, signum: isize) -> AxResult<()> {
-    let mut pid2pc = PID2PC.lock();
-    if pid2pc.contains_key(&(pid as u64)) == false {
-        return Err(axerrno::AxError::NotFound);
-    }
-    let process = pid2pc.get_mut(&(pid as u64)).unwrap();
+    let process = match PID2PC.lock().get(&(pid as u64)) {
+        Some(process) => Arc::clone(process),
+        None => return Err(axerrno::AxError::NotFound),
+    };
     let mut now_id: Option<u64> = None;
     for task in process.tasks.lock().iter_mut() {
         if task.is_leader() {
@@ -278,9 +312,43 @@ pub fn send_signal_to_process(pid: isize, signum: isize) -> AxResult<()> {
             RUN_QUEUE.lock().unblock_task(main_task, false);
         }
     }
+    continue_if_stopped(&process, signum as usize);
+    Ok(())
+}
+
+/// 发送信号到进程组中的所有进程
+pub fn send_signal_to_group(pgid: u64, signum: isize) -> AxResult<()> {
+    let pids: Vec<u64> = PID2PC
+        .lock()
+        .values()
+        .filter(|process| process.pgid() == pgid && !process.get_zombie())
+        .map(|process| process.pid())
+        .collect();
+    if pids.is_empty() {
+        return Err(AxError::NotFound);
+    }
+    for pid in pids {
+        let _ = send_signal_to_process(pid as isize, signum);
+    }
     Ok(())
 }
 
+/// 当前线程是否屏蔽或者忽略了信号
+pub fn signal_ignored(signum: usize) -> bool {
+    let process = current_process();
+    let signal_modules = process.signal_modules.lock();
+    let Some(signal_module) = signal_modules.get(&current_task().id().as_u64()) else {
+        return true;
+    };
+    if signal_module.signal_set.mask & (1 << (signum - 1)) != 0 {
+        return true;
+    }
+    let signal_handler = signal_module.signal_handler.lock();
+    signal_handler
+        .get_action(signum)
+        .is_some_and(|action| action.sa_handler == SIG_IGN)
+}
+
 /// 发送信号到指定的线程
 pub fn send_signal_to_thread(tid: isize, signum: isize) -> AxResult<()> {
     let tid2task = TID2TASK.lock();
@@ -304,10 +372,12 @@ pub fn send_signal_to_thread(tid: isize, signum: isize) -> AxResult<()> {
     }
     let signal_module = signal_modules.get_mut(&(tid as u64)).unwrap();
     signal_module.signal_set.try_add_signal(signum as usize);
+    drop(signal_modules);
     // 如果这个时候对应的线程是处于休眠状态的，则唤醒之，进入信号处理阶段
     if task.state() == TaskState::Blocked {
         RUN_QUEUE.lock().unblock_task(task, false);
     }
+    continue_if_stopped(&process, signum as usize);
     Ok(())
 }
 
@@ -0,0 +1,459 @@
+//! 行规程：处理终端的输入，包括回显、行编辑与产生信号的控制字符
+//!
+//! 输入处理后的数据分段保存，规范模式下每段是完整的一行，一次读取最多读到行尾
+extern crate alloc;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use super::termios::*;
+
+/// 输入缓冲区的大小，与 Linux 的 `N_TTY_BUF_SIZE` 相同
+const BUF_SIZE: usize = 4096;
+
+/// 由控制字符产生的信号
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGTSTP: usize = 20;
+
+/// 行规程的状态
+pub struct LineDiscipline {
+    /// 终端属性
+    pub termios: Termios,
+    /// 可以被读取的数据，空的段代表文件结束
+    segments: VecDeque<Vec<u8>>,
+    /// 最后一段是否为非规范模式下的输入，此时新的输入直接追加到这一段
+    raw_tail: bool,
+    /// 规范模式下正在编辑的行
+    line: Vec<u8>,
+    /// 下一个字符是否按字面处理
+    literal_next: bool,
+}
+
+impl LineDiscipline {
+    pub fn new() -> Self {
+        Self {
+            termios: Termios::default(),
+            segments: VecDeque::new(),
+            raw_tail: false,
+            line: Vec::new(),
+            literal_next: false,
+        }
+    }
+
+    /// 可以被读取的字节数
+    pub fn available(&self) -> usize {
+        self.segments.iter().map(|s| s.len()).sum()
+    }
+
+    /// 是否有数据可读，规范模式下要求有完整的一行或者文件结束
+    pub fn readable(&self) -> bool {
+        if self.termios.canonical() {
+            !self.segments.is_empty()
+        } else {
+            self.available() > 0
+        }
+    }
+
+    /// 读取数据，规范模式下最多读到当前行的行尾
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let canonical = self.termios.canonical();
+        let mut count = 0;
+        while count < buf.len() {
+            let Some(front) = self.segments.front_mut() else {
+                break;
+            };
+            if front.is_empty() {
+                // 文件结束只在规范模式下有意义
+                self.segments.pop_front();
+                if canonical {
+                    break;
+                }
+                continue;
+            }
+            let len = front.len().min(buf.len() - count);
+            buf[count..count + len].copy_from_slice(&front[..len]);
+            front.drain(..len);
+            count += len;
+            if front.is_empty() {
+                self.segments.pop_front();
+                if canonical {
+                    break;
+                }
+            } else if canonical {
+                break;
+            }
+        }
+        if self.segments.is_empty() {
+            self.raw_tail = false;
+        }
+        count
+    }
+
+    /// 清空输入
+    pub fn flush_input(&mut self) {
+        self.segments.clear();
+        self.raw_tail = false;
+        self.line.clear();
+    }
+
+    /// 修改终端属性，离开规范模式时正在编辑的行变为可读
+    pub fn set_termios(&mut self, termios: Termios) {
+        let was_canonical = self.termios.canonical();
+        self.termios = termios;
+        if was_canonical && !termios.canonical() && !self.line.is_empty() {
+            let line = core::mem::take(&mut self.line);
+            self.push_raw(&line);
+        }
+    }
+
+    /// 处理一个输入的字符，需要回显的内容写入 `echo`，返回需要发送给前台进程组的信号
+    pub fn receive(&mut self, mut c: u8, echo: &mut Vec<u8>) -> Option<usize> {
+        let termios = self.termios;
+        let lflag = termios.c_lflag;
+        if core::mem::take(&mut self.literal_next) {
+            self.insert(c, echo);
+            return None;
+        }
+
+        let iflag = termios.c_iflag;
+        if iflag.contains(InputFlags::ISTRIP) {
+            c &= 0x7f;
+        }
+        if c == b'\r' {
+            if iflag.contains(InputFlags::IGNCR) {
+                return None;
+            }
+            if iflag.contains(InputFlags::ICRNL) {
+                c = b'\n';
+            }
+        } else if c == b'\n' && iflag.contains(InputFlags::INLCR) {
+            c = b'\r';
+        }
+
+        if lflag.contains(LocalFlags::ISIG) {
+            let signal = if termios.is_cc(c, VINTR) {
+                Some(SIGINT)
+            } else if termios.is_cc(c, VQUIT) {
+                Some(SIGQUIT)
+            } else if termios.is_cc(c, VSUSP) {
+                Some(SIGTSTP)
+            } else {
+                None
+            };
+            if signal.is_some() {
+                if !lflag.contains(LocalFlags::NOFLSH) {
+                    self.flush_input();
+                }
+                self.echo_char(c, echo);
+                return signal;
+            }
+        }
+
+        if !termios.canonical() {
+            self.insert(c, echo);
+            return None;
+        }
+
+        if lflag.contains(LocalFlags::IEXTEN) && termios.is_cc(c, VLNEXT) {
+            self.literal_next = true;
+            return None;
+        }
+
+        if termios.is_cc(c, VERASE) {
+            self.erase(echo);
+        } else if lflag.contains(LocalFlags::IEXTEN) && termios.is_cc(c, VWERASE) {
+            // 先擦除空白，再擦除一个单词
+            while self.line.last().is_some_and(|c| c.is_ascii_whitespace()) {
+                self.erase(echo);
+            }
+            while self.line.last().is_some_and(|c| !c.is_ascii_whitespace()) {
+                self.erase(echo);
+            }
+        } else if termios.is_cc(c, VKILL) {
+            if lflag.contains(LocalFlags::ECHOKE) {
+                while !self.line.is_empty() {
+                    self.erase(echo);
+                }
+            } else {
+                self.line.clear();
+                self.echo_char(c, echo);
+                if lflag.contains(LocalFlags::ECHO | LocalFlags::ECHOK) {
+                    self.echo_raw(b'\n', echo);
+                }
+            }
+        } else if termios.is_cc(c, VEOF) {
+            // 不加入 EOF 字符本身，空行即文件结束
+            let line = core::mem::take(&mut self.line);
+            self.commit(line);
+        } else if c == b'\n' || termios.is_cc(c, VEOL) || termios.is_cc(c, VEOL2) {
+            if lflag.contains(LocalFlags::ECHO)
+                || (c == b'\n' && lflag.contains(LocalFlags::ECHONL))
+            {
+                echo.push(c);
+            }
+            let mut line = core::mem::take(&mut self.line);
+            line.push(c);
+            self.commit(line);
+        } else {
+            self.insert(c, echo);
+        }
+        None
+    }
+
+    /// 加入一个普通字符
+    fn insert(&mut self, c: u8, echo: &mut Vec<u8>) {
+        if self.termios.canonical() {
+            // 留一个位置给行尾
+            if self.line.len() + 1 >= BUF_SIZE {
+                return;
+            }
+            self.line.push(c);
+        } else {
+            if self.available() >= BUF_SIZE {
+                return;
+            }
+            self.push_raw(&[c]);
+        }
+        self.echo_char(c, echo);
+    }
+
+    /// 将非规范模式下的输入追加到可读的数据中
+    fn push_raw(&mut self, data: &[u8]) {
+        match self.segments.back_mut() {
+            Some(back) if self.raw_tail => back.extend_from_slice(data),
+            _ => {
+                self.segments.push_back(data.to_vec());
+                self.raw_tail = true;
+            }
+        }
+    }
+
+    /// 提交规范模式下编辑完成的一行
+    fn commit(&mut self, line: Vec<u8>) {
+        self.segments.push_back(line);
+        self.raw_tail = false;
+    }
+
+    /// 擦除正在编辑的行的最后一个字符
+    fn erase(&mut self, echo: &mut Vec<u8>) {
+        let Some(c) = self.line.pop() else {
+            return;
+        };
+        let lflag = self.termios.c_lflag;
+        if !lflag.contains(LocalFlags::ECHO) {
+            return;
+        }
+        if lflag.contains(LocalFlags::ECHOE) {
+            // 以 `^X` 形式回显的控制字符占两列
+            let width = if Self::shown_as_caret(c) && lflag.contains(LocalFlags::ECHOCTL) {
+                2
+            } else {
+                1
+            };
+            for _ in 0..width {
+                echo.extend_from_slice(b"\x08 \x08");
+            }
+        } else {
+            self.echo_char(self.termios.c_cc[VERASE], echo);
+        }
+    }
+
+    /// 控制字符是否以 `^X` 的形式回显
+    fn shown_as_caret(c: u8) -> bool {
+        (c < b' ' && c != b'\t' && c != b'\n') || c == 0x7f
+    }
+
+    /// 按终端属性回显一个字符
+    fn echo_char(&self, c: u8, echo: &mut Vec<u8>) {
+        if self.termios.c_lflag.contains(LocalFlags::ECHO) {
+            self.echo_raw(c, echo);
+        }
+    }
+
+    /// 回显一个字符，不检查 ECHO
+    fn echo_raw(&self, c: u8, echo: &mut Vec<u8>) {
+        if Self::shown_as_caret(c) && self.termios.c_lflag.contains(LocalFlags::ECHOCTL) {
+            echo.push(b'^');
+            echo.push(c ^ 0x40);
+        } else {
+            echo.push(c);
+        }
+    }
+}
+
+/// 按终端属性处理输出的数据
+pub fn process_output(termios: &Termios, buf: &[u8], out: &mut Vec<u8>) {
+    let oflag = termios.c_oflag;
+    if !oflag.contains(OutputFlags::OPOST) {
+        out.extend_from_slice(buf);
+        return;
+    }
+    for &c in buf {
+        match c {
+            b'\n' if oflag.contains(OutputFlags::ONLCR) => out.extend_from_slice(b"\r\n"),
+            b'\r' if oflag.contains(OutputFlags::OCRNL) => out.push(b'\n'),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CTRL_C: u8 = 3;
+    const CTRL_D: u8 = 4;
+    const CTRL_U: u8 = 21;
+    const CTRL_V: u8 = 22;
+    const CTRL_W: u8 = 23;
+    const DEL: u8 = 127;
+
+    /// 依次输入 `input`，返回回显的内容与产生的信号
+    fn feed(ldisc: &mut LineDiscipline, input: &[u8]) -> (Vec<u8>, Vec<usize>) {
+        let mut echo = Vec::new();
+        let signals = input
+            .iter()
+            .filter_map(|&c| ldisc.receive(c, &mut echo))
+            .collect();
+        (echo, signals)
+    }
+
+    fn read_all(ldisc: &mut LineDiscipline) -> Vec<u8> {
+        let mut buf = [0u8; 64];
+        let len = ldisc.read(&mut buf);
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn test_canonical_line() {
+        let mut ldisc = LineDiscipline::new();
+        let (echo, _) = feed(&mut ldisc, b"ab");
+        assert_eq!(echo, b"ab");
+        // 没有完整的一行时不可读
+        assert!(!ldisc.readable());
+        // 输入的 CR 转换为 NL
+        feed(&mut ldisc, b"\rcd\n");
+        assert_eq!(ldisc.available(), 6);
+        // 一次最多读到行尾
+        assert_eq!(read_all(&mut ldisc), b"ab\n");
+        assert_eq!(read_all(&mut ldisc), b"cd\n");
+        assert!(!ldisc.readable());
+    }
+
+    #[test]
+    fn test_erase() {
+        let mut ldisc = LineDiscipline::new();
+        let (echo, _) = feed(&mut ldisc, &[b'a', b'b', DEL, b'c', 1, DEL, DEL, b'\n']);
+        // 以 `^A` 回显的控制字符擦除两列
+        assert_eq!(echo, b"ab\x08 \x08c^A\x08 \x08\x08 \x08\x08 \x08\n");
+        assert_eq!(read_all(&mut ldisc), b"a\n");
+
+        // 没有 ECHOE 时回显 ERASE 字符本身
+        ldisc.termios.c_lflag.remove(LocalFlags::ECHOE);
+        let (echo, _) = feed(&mut ldisc, &[b'x', DEL, b'\n']);
+        assert_eq!(echo, b"x^?\n");
+        assert_eq!(read_all(&mut ldisc), b"\n");
+
+        // 先擦除空白，再擦除一个单词
+        feed(&mut ldisc, b"one two  ");
+        feed(&mut ldisc, &[CTRL_W, b'\n']);
+        assert_eq!(read_all(&mut ldisc), b"one \n");
+    }
+
+    #[test]
+    fn test_kill() {
+        let mut ldisc = LineDiscipline::new();
+        let (echo, _) = feed(&mut ldisc, &[b'a', b'b', CTRL_U, b'c', b'\n']);
+        assert_eq!(echo, b"ab\x08 \x08\x08 \x08c\n");
+        assert_eq!(read_all(&mut ldisc), b"c\n");
+
+        // 没有 ECHOKE 时回显 KILL 字符并换行
+        ldisc.termios.c_lflag.remove(LocalFlags::ECHOKE);
+        let (echo, _) = feed(&mut ldisc, &[b'a', CTRL_U, b'b', b'\n']);
+        assert_eq!(echo, b"a^U\nb\n");
+        assert_eq!(read_all(&mut ldisc), b"b\n");
+    }
+
+    #[test]
+    fn test_eof() {
+        let mut ldisc = LineDiscipline::new();
+        // EOF 使当前行立即可读，不包括 EOF 字符本身
+        let (echo, _) = feed(&mut ldisc, &[b'a', b'b', CTRL_D]);
+        assert_eq!(echo, b"ab");
+        assert!(ldisc.readable());
+        assert_eq!(read_all(&mut ldisc), b"ab");
+        // 空行上的 EOF 使读取返回0
+        feed(&mut ldisc, &[CTRL_D, b'c', b'\n']);
+        assert!(ldisc.readable());
+        assert_eq!(read_all(&mut ldisc), b"");
+        assert_eq!(read_all(&mut ldisc), b"c\n");
+    }
+
+    #[test]
+    fn test_signals() {
+        let mut ldisc = LineDiscipline::new();
+        feed(&mut ldisc, b"done\nab");
+        let (echo, signals) = feed(&mut ldisc, &[CTRL_C, 28, 26]);
+        assert_eq!(signals, [SIGINT, SIGQUIT, SIGTSTP]);
+        assert_eq!(echo, b"^C^\\^Z");
+        // 产生信号时清空输入
+        assert_eq!(ldisc.available(), 0);
+        assert!(!ldisc.readable());
+
+        ldisc.termios.c_lflag.insert(LocalFlags::NOFLSH);
+        feed(&mut ldisc, b"ab\n");
+        assert_eq!(feed(&mut ldisc, &[CTRL_C]).1, [SIGINT]);
+        assert_eq!(read_all(&mut ldisc), b"ab\n");
+
+        // 按字面输入的控制字符不产生信号
+        let (_, signals) = feed(&mut ldisc, &[CTRL_V, CTRL_C, b'\n']);
+        assert!(signals.is_empty());
+        assert_eq!(read_all(&mut ldisc), [CTRL_C, b'\n']);
+
+        // 没有 ISIG 时作为普通字符
+        ldisc.termios.c_lflag.remove(LocalFlags::ISIG);
+        let (_, signals) = feed(&mut ldisc, &[CTRL_C, b'\n']);
+        assert!(signals.is_empty());
+        assert_eq!(read_all(&mut ldisc), [CTRL_C, b'\n']);
+    }
+
+    #[test]
+    fn test_raw_mode() {
+        let mut ldisc = LineDiscipline::new();
+        feed(&mut ldisc, b"ab");
+        // 离开规范模式时正在编辑的行变为可读
+        let mut termios = ldisc.termios;
+        termios
+            .c_lflag
+            .remove(LocalFlags::ICANON | LocalFlags::ECHO);
+        ldisc.set_termios(termios);
+        assert!(ldisc.readable());
+
+        // 编辑字符与 EOF 都作为普通字符，输入立即可读
+        let (echo, _) = feed(&mut ldisc, &[DEL, CTRL_U, CTRL_D, b'\n', b'c']);
+        assert!(echo.is_empty());
+        assert_eq!(ldisc.available(), 7);
+        let mut buf = [0u8; 4];
+        assert_eq!(ldisc.read(&mut buf), 4);
+        assert_eq!(buf, [b'a', b'b', DEL, CTRL_U]);
+        assert_eq!(read_all(&mut ldisc), [CTRL_D, b'\n', b'c']);
+        assert!(!ldisc.readable());
+
+        feed(&mut ldisc, b"xy");
+        ldisc.flush_input();
+        assert_eq!(ldisc.available(), 0);
+    }
+
+    #[test]
+    fn test_process_output() {
+        let mut termios = Termios::default();
+        let mut out = Vec::new();
+        process_output(&termios, b"a\nb\r", &mut out);
+        assert_eq!(out, b"a\r\nb\r");
+
+        termios.c_oflag.remove(OutputFlags::OPOST);
+        out.clear();
+        process_output(&termios, b"a\n", &mut out);
+        assert_eq!(out, b"a\n");
+    }
+}
@@ -0,0 +1,805 @@
+//! 终端子系统
+//!
+//! 控制台的输入经过行规程处理后提供给用户程序，支持规范模式与原始模式、完整的 termios，
+//! 以及前台进程组与作业控制：终端上输入的 `^C`、`^Z`、`^\` 会向前台进程组发送信号，
+//! 后台进程组读写终端时会收到 SIGTTIN、SIGTTOU
+extern crate alloc;
+mod ldisc;
+mod pty;
+pub mod termios;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use axerrno::{AxError, AxResult};
+use axfs::api::port::{
+    ConsoleWinSize, FileExt, FileIO, FileIOType, Kstat, OpenFlags, FIONREAD, TCFLSH, TCGETS,
+    TCSBRK, TCSETS, TCSETSF, TCSETSW, TCXONC, TIOCGPGRP, TIOCGSID, TIOCGWINSZ, TIOCNOTTY, TIOCOUTQ,
+    TIOCSCTTY, TIOCSPGRP, TIOCSWINSZ,
+};
+use axfs::api::DeviceId;
+use axhal::console::{getchar, write_bytes};
+use axio::{Read, Seek, SeekFrom, Write};
+use axlog::warn;
+use axsync::Mutex;
+use axtask::yield_now;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+use lazy_static::lazy_static;
+
+use crate::current_process;
+use crate::process::PID2PC;
+use ldisc::{process_output, LineDiscipline};
+use termios::{LocalFlags, Termios, VMIN, VTIME};
+
+/// 后台进程读终端时收到的信号
+const SIGTTIN: usize = 21;
+/// 后台进程写终端或修改终端属性时收到的信号
+const SIGTTOU: usize = 22;
+/// 控制终端挂断
+const SIGHUP: usize = 1;
+/// 让停止的进程继续运行
+const SIGCONT: usize = 18;
+/// 终端窗口大小改变
+const SIGWINCH: usize = 28;
+
+/// `/dev/tty`，即当前进程的控制终端
+pub const TTY_DEVICE: DeviceId = DeviceId::new(5, 0);
+/// `/dev/console`
+pub const CONSOLE_DEVICE: DeviceId = DeviceId::new(5, 1);
+/// `/dev/ttyS0`，即控制台所在的串口
+pub const SERIAL_DEVICE: DeviceId = DeviceId::new(4, 64);
+/// `/dev/ptmx`，打开时创建一对伪终端
+pub const PTMX_DEVICE: DeviceId = DeviceId::new(5, 2);
+
+/// 没有输入时，控制台输入任务的轮询间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// 终端的底层驱动，负责把数据输出到设备上
+pub trait TtyDriver: Send + Sync {
+    /// 输出经过处理的数据
+    fn write(&self, buf: &[u8]);
+
+    /// 还可以输出的字节数，为0时写终端的进程需要等待
+    fn write_room(&self) -> usize {
+        usize::MAX
+    }
+}
+
+/// 控制台驱动
+struct ConsoleDriver;
+
+impl TtyDriver for ConsoleDriver {
+    fn write(&self, buf: &[u8]) {
+        write_bytes(buf);
+    }
+}
+
+/// 一个终端
+pub struct Tty {
+    /// 设备名，即 `/dev` 下的文件名
+    name: String,
+    /// 设备号
+    rdev: DeviceId,
+    /// 底层驱动
+    driver: Box<dyn TtyDriver>,
+    /// 行规程
+    ldisc: Mutex<LineDiscipline>,
+    /// 窗口大小
+    winsize: Mutex<ConsoleWinSize>,
+    /// 前台进程组，为0时没有
+    fg_pgid: AtomicU64,
+    /// 以该终端为控制终端的会话，为0时没有
+    sid: AtomicU64,
+    /// 设备是否已经断开，如伪终端的主设备被关闭
+    disconnected: AtomicBool,
+    /// 打开该终端的文件个数
+    open_count: AtomicUsize,
+}
+
+impl Tty {
+    pub fn new(name: &str, rdev: DeviceId, driver: Box<dyn TtyDriver>) -> Self {
+        Self {
+            name: name.to_string(),
+            rdev,
+            driver,
+            ldisc: Mutex::new(LineDiscipline::new()),
+            winsize: Mutex::new(ConsoleWinSize {
+                ws_row: 24,
+                ws_col: 80,
+                ws_xpixel: 0,
+                ws_ypixel: 0,
+            }),
+            fg_pgid: AtomicU64::new(0),
+            sid: AtomicU64::new(0),
+            disconnected: AtomicBool::new(false),
+            open_count: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn rdev(&self) -> DeviceId {
+        self.rdev
+    }
+
+    /// 以该终端为控制终端的会话
+    pub fn sid(&self) -> u64 {
+        self.sid.load(Ordering::Acquire)
+    }
+
+    /// 前台进程组
+    pub fn fg_pgid(&self) -> u64 {
+        self.fg_pgid.load(Ordering::Acquire)
+    }
+
+    /// 成为会话 `sid` 的控制终端，前台进程组为 `pgid`
+    pub fn attach(&self, sid: u64, pgid: u64) {
+        self.sid.store(sid, Ordering::Release);
+        self.fg_pgid.store(pgid, Ordering::Release);
+    }
+
+    /// 与会话断开，向原来的前台进程组发送 SIGHUP 与 SIGCONT
+    pub fn hangup(&self) {
+        let fg = self.fg_pgid.swap(0, Ordering::AcqRel);
+        self.sid.store(0, Ordering::Release);
+        signal_group(fg, SIGHUP);
+        signal_group(fg, SIGCONT);
+    }
+
+    /// 设备断开，之后读终端返回文件结束，写终端返回 EIO
+    pub fn disconnect(&self) {
+        self.disconnected.store(true, Ordering::Release);
+        self.hangup();
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected.load(Ordering::Acquire)
+    }
+
+    /// 打开该终端的文件个数
+    pub fn open_count(&self) -> usize {
+        self.open_count.load(Ordering::Acquire)
+    }
+
+    /// 终端收到输入，经行规程处理后回显，并向前台进程组发送产生的信号
+    pub fn receive(&self, data: &[u8]) {
+        let mut echo = Vec::new();
+        let mut signals = Vec::new();
+        let termios = {
+            let mut ldisc = self.ldisc.lock();
+            for &c in data {
+                if let Some(signum) = ldisc.receive(c, &mut echo) {
+                    signals.push(signum);
+                }
+            }
+            ldisc.termios
+        };
+        if !echo.is_empty() {
+            let mut out = Vec::with_capacity(echo.len());
+            process_output(&termios, &echo, &mut out);
+            self.driver.write(&out);
+        }
+        for signum in signals {
+            signal_group(self.fg_pgid(), signum);
+        }
+    }
+
+    /// 是否有数据可读，断开后总是可读
+    pub fn readable(&self) -> bool {
+        self.is_disconnected() || self.ldisc.lock().readable()
+    }
+
+    /// 当前进程属于该终端的会话但不在前台时，向其进程组发送作业控制信号
+    ///
+    /// 进程忽略或屏蔽了该信号时，读终端返回 EIO，写终端与修改属性则照常进行
+    fn check_background(&self, signum: usize) -> AxResult<()> {
+        let process = current_process();
+        let fg = self.fg_pgid();
+        if process.sid() != self.sid() || fg == 0 || process.pgid() == fg {
+            return Ok(());
+        }
+        #[cfg(feature = "signal")]
+        {
+            if crate::signal::signal_ignored(signum) {
+                return if signum == SIGTTIN {
+                    Err(AxError::Io)
+                } else {
+                    Ok(())
+                };
+            }
+            signal_group(process.pgid(), signum);
+            Err(AxError::Interrupted)
+        }
+        #[cfg(not(feature = "signal"))]
+        {
+            let _ = signum;
+            Ok(())
+        }
+    }
+
+    /// 读取终端，规范模式下一次最多读取一行，非规范模式下按 VMIN 与 VTIME 等待
+    pub fn read(&self, buf: &mut [u8], nonblock: bool) -> AxResult<usize> {
+        self.check_background(SIGTTIN)?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // 非规范模式下的计时器，在收到新的数据时重新开始
+        let mut timer = axhal::time::current_time();
+        let mut last_available = 0;
+        loop {
+            let mut ldisc = self.ldisc.lock();
+            let termios = ldisc.termios;
+            let done = if termios.canonical() {
+                ldisc.readable()
+            } else {
+                let vmin = termios.c_cc[VMIN] as usize;
+                let vtime = termios.c_cc[VTIME] as u64;
+                let available = ldisc.available();
+                if available != last_available {
+                    last_available = available;
+                    timer = axhal::time::current_time();
+                }
+                let expired = vtime != 0
+                    && axhal::time::current_time() >= timer + Duration::from_millis(vtime * 100);
+                if vmin == 0 {
+                    available > 0 || vtime == 0 || expired
+                } else {
+                    available >= vmin.min(buf.len()) || (available > 0 && expired)
+                }
+            };
+            if done {
+                return Ok(ldisc.read(buf));
+            }
+            drop(ldisc);
+            if self.is_disconnected() {
+                return Ok(0);
+            }
+            if nonblock {
+                return Err(AxError::WouldBlock);
+            }
+            #[cfg(feature = "signal")]
+            if current_process().have_signals().is_some() {
+                return Err(AxError::Interrupted);
+            }
+            yield_now();
+        }
+    }
+
+    /// 写终端，数据按输出模式处理后交给驱动，驱动暂时不能输出时等待
+    ///
+    /// 已经写入部分数据后被打断时，返回写入的字节数
+    pub fn write(&self, buf: &[u8], nonblock: bool) -> AxResult<usize> {
+        let termios = self.ldisc.lock().termios;
+        if termios.c_lflag.contains(LocalFlags::TOSTOP) {
+            self.check_background(SIGTTOU)?;
+        }
+        let mut written = 0;
+        let mut out = Vec::new();
+        while written < buf.len() {
+            if self.is_disconnected() {
+                return if written > 0 {
+                    Ok(written)
+                } else {
+                    Err(AxError::Io)
+                };
+            }
+            let room = self.driver.write_room();
+            if room == 0 {
+                if nonblock {
+                    return if written > 0 {
+                        Ok(written)
+                    } else {
+                        Err(AxError::WouldBlock)
+                    };
+                }
+                #[cfg(feature = "signal")]
+                if current_process().have_signals().is_some() {
+                    return if written > 0 {
+                        Ok(written)
+                    } else {
+                        Err(AxError::Interrupted)
+                    };
+                }
+                yield_now();
+                continue;
+            }
+            let end = buf.len().min(written.saturating_add(room));
+            out.clear();
+            process_output(&termios, &buf[written..end], &mut out);
+            self.driver.write(&out);
+            written = end;
+        }
+        Ok(written)
+    }
+
+    /// 终端相关的 ioctl，`arg` 为指针时指向用户地址空间，使用前检查整个结构体
+    pub fn ioctl(&self, request: usize, arg: usize) -> AxResult<isize> {
+        match request {
+            TCGETS => {
+                let ptr = user_ptr::<Termios>(arg)?;
+                unsafe {
+                    *ptr = self.ldisc.lock().termios;
+                }
+                Ok(0)
+            }
+            TCSETS | TCSETSW | TCSETSF => {
+                self.check_background(SIGTTOU)?;
+                let termios = unsafe { *user_ptr::<Termios>(arg)? };
+                let mut ldisc = self.ldisc.lock();
+                if request == TCSETSF {
+                    ldisc.flush_input();
+                }
+                // 输出是同步完成的，TCSETSW 不需要等待
+                ldisc.set_termios(termios);
+                Ok(0)
+            }
+            TIOCGPGRP | TIOCGSID => {
+                if current_process().sid() != self.sid() {
+                    return Err(AxError::NotATty);
+                }
+                let value = if request == TIOCGPGRP {
+                    self.fg_pgid()
+                } else {
+                    self.sid()
+                };
+                let ptr = user_ptr::<i32>(arg)?;
+                unsafe {
+                    *ptr = value as i32;
+                }
+                Ok(0)
+            }
+            TIOCSPGRP => {
+                let process = current_process();
+                if process.sid() != self.sid() {
+                    return Err(AxError::NotATty);
+                }
+                self.check_background(SIGTTOU)?;
+                let pgid = unsafe { *user_ptr::<i32>(arg)? };
+                if pgid < 0 {
+                    return Err(AxError::InvalidInput);
+                }
+                let pgid = pgid as u64;
+                // 只能设置为同一会话中的进程组
+                if !PID2PC
+                    .lock()
+                    .values()
+                    .any(|p| p.pgid() == pgid && p.sid() == process.sid())
+                {
+                    return Err(AxError::OperationNotPermitted);
+                }
+                self.fg_pgid.store(pgid, Ordering::Release);
+                Ok(0)
+            }
+            TIOCGWINSZ => {
+                let ptr = user_ptr::<ConsoleWinSize>(arg)?;
+                unsafe {
+                    *ptr = *self.winsize.lock();
+                }
+                Ok(0)
+            }
+            TIOCSWINSZ => {
+                let new = unsafe { *user_ptr::<ConsoleWinSize>(arg)? };
+                let mut winsize = self.winsize.lock();
+                let changed = (
+                    winsize.ws_row,
+                    winsize.ws_col,
+                    winsize.ws_xpixel,
+                    winsize.ws_ypixel,
+                ) != (new.ws_row, new.ws_col, new.ws_xpixel, new.ws_ypixel);
+                *winsize = new;
+                drop(winsize);
+                if changed {
+                    signal_group(self.fg_pgid(), SIGWINCH);
+                }
+                Ok(0)
+            }
+            TIOCSCTTY => {
+                let process = current_process();
+                if self.sid() == process.sid() {
+                    return Ok(0);
+                }
+                // 只有没有控制终端的会话首进程可以获得控制终端，arg 为1时可以抢占其他会话的终端
+                if process.sid() != process.pid()
+                    || session_tty(process.sid()).is_some()
+                    || (self.sid() != 0 && arg != 1)
+                {
+                    return Err(AxError::OperationNotPermitted);
+                }
+                self.attach(process.sid(), process.pgid());
+                Ok(0)
+            }
+            TIOCNOTTY => {
+                let process = current_process();
+                if process.sid() != self.sid() {
+                    return Err(AxError::NotATty);
+                }
+                // 会话首进程放弃控制终端时，整个会话都失去控制终端
+                if process.sid() == process.pid() {
+                    self.hangup();
+                }
+                Ok(0)
+            }
+            FIONREAD => {
+                let ptr = user_ptr::<i32>(arg)?;
+                unsafe {
+                    *ptr = self.ldisc.lock().available() as i32;
+                }
+                Ok(0)
+            }
+            TCFLSH => match arg {
+                // TCIFLUSH 与 TCIOFLUSH，输出没有缓冲，不需要清空
+                0 | 2 => {
+                    self.ldisc.lock().flush_input();
+                    Ok(0)
+                }
+                1 => Ok(0),
+                _ => Err(AxError::InvalidInput),
+            },
+            // 输出是同步完成的，没有需要暂停、恢复或等待的内容
+            TCXONC | TCSBRK => Ok(0),
+            TIOCOUTQ => {
+                let ptr = user_ptr::<i32>(arg)?;
+                unsafe {
+                    *ptr = 0;
+                }
+                Ok(0)
+            }
+            _ => Err(AxError::Unsupported),
+        }
+    }
+}
+
+/// 检查用户地址 `arg` 处的整个 `T` 都可以访问，返回对应的指针
+fn user_ptr<T>(arg: usize) -> AxResult<*mut T> {
+    let ptr = arg as *mut T;
+    if arg.checked_add(core::mem::size_of::<T>()).is_none()
+        || current_process()
+            .manual_alloc_type_for_lazy(ptr as *const T)
+            .is_err()
+    {
+        return Err(AxError::BadAddress);
+    }
+    Ok(ptr)
+}
+
+/// 向进程组发送信号，`pgid` 为0时不发送
+fn signal_group(pgid: u64, signum: usize) {
+    if pgid == 0 {
+        return;
+    }
+    #[cfg(feature = "signal")]
+    let _ = crate::signal::send_signal_to_group(pgid, signum as isize);
+    #[cfg(not(feature = "signal"))]
+    let _ = signum;
+}
+
+lazy_static! {
+    /// 控制台终端
+    static ref CONSOLE: Arc<Tty> = Arc::new(Tty::new(
+        "console",
+        CONSOLE_DEVICE,
+        Box::new(ConsoleDriver)
+    ));
+}
+
+/// 已注册的终端
+static TTYS: Mutex<Vec<Arc<Tty>>> = Mutex::new(Vec::new());
+
+/// 控制台终端
+pub fn console() -> Arc<Tty> {
+    Arc::clone(&CONSOLE)
+}
+
+/// 注册一个终端，之后可以通过设备号打开
+pub fn register_tty(tty: Arc<Tty>) {
+    TTYS.lock().push(tty);
+}
+
+/// 注销设备号为 `rdev` 的终端
+pub fn unregister_tty(rdev: DeviceId) {
+    TTYS.lock().retain(|tty| tty.rdev() != rdev);
+}
+
+/// 根据设备号找到对应的终端
+pub(crate) fn find_tty(rdev: DeviceId) -> Option<Arc<Tty>> {
+    let rdev = if rdev == SERIAL_DEVICE {
+        CONSOLE_DEVICE
+    } else {
+        rdev
+    };
+    TTYS.lock().iter().find(|tty| tty.rdev() == rdev).cloned()
+}
+
+/// 会话 `sid` 的控制终端
+pub fn session_tty(sid: u64) -> Option<Arc<Tty>> {
+    TTYS.lock().iter().find(|tty| tty.sid() == sid).cloned()
+}
+
+/// 会话首进程退出时，其控制终端被挂断
+pub fn session_leader_exit(sid: u64) {
+    if let Some(tty) = session_tty(sid) {
+        tty.hangup();
+    }
+}
+
+/// 初始化终端子系统，启动读取控制台输入的任务
+pub fn init() {
+    register_tty(console());
+    axtask::spawn(|| {
+        let console = console();
+        let mut buf = Vec::new();
+        loop {
+            while let Some(c) = getchar() {
+                buf.push(c);
+            }
+            if buf.is_empty() {
+                axtask::sleep(POLL_INTERVAL);
+            } else {
+                console.receive(&buf);
+                buf.clear();
+            }
+        }
+    });
+}
+
+/// 打开设备号为 `rdev` 的终端，若不是终端则返回 None
+///
+/// 未指定 `O_NOCTTY` 时，没有控制终端的会话首进程打开一个不属于任何会话的终端后，
+/// 该终端成为其控制终端
+pub fn open(rdev: DeviceId, flags: OpenFlags) -> Option<AxResult<Arc<dyn FileIO>>> {
+    let process = current_process();
+    let tty = if rdev == TTY_DEVICE {
+        match session_tty(process.sid()) {
+            Some(tty) => tty,
+            None => return Some(Err(AxError::NoDeviceOrAddress)),
+        }
+    } else if rdev == PTMX_DEVICE {
+        return Some(pty::open_master(flags));
+    } else if rdev.major() == pty::PTS_MAJOR {
+        match pty::open_slave(rdev.minor())? {
+            Ok(tty) => tty,
+            Err(e) => return Some(Err(e)),
+        }
+    } else {
+        find_tty(rdev)?
+    };
+    if !flags.contains(OpenFlags::NOCTTY)
+        && process.sid() == process.pid()
+        && tty.sid() == 0
+        && session_tty(process.sid()).is_none()
+    {
+        tty.attach(process.sid(), process.pgid());
+    }
+    Some(Ok(Arc::new(TtyFile::new(tty, flags))))
+}
+
+/// 作为标准输入输出的控制台文件
+pub fn console_file() -> Arc<dyn FileIO> {
+    Arc::new(TtyFile::new(console(), OpenFlags::RDWR))
+}
+
+/// 打开的终端文件
+pub struct TtyFile {
+    tty: Arc<Tty>,
+    flags: Mutex<OpenFlags>,
+}
+
+impl TtyFile {
+    pub fn new(tty: Arc<Tty>, flags: OpenFlags) -> Self {
+        tty.open_count.fetch_add(1, Ordering::AcqRel);
+        Self {
+            tty,
+            flags: Mutex::new(flags),
+        }
+    }
+
+    /// 对应的终端
+    pub fn tty(&self) -> &Arc<Tty> {
+        &self.tty
+    }
+}
+
+impl Drop for TtyFile {
+    fn drop(&mut self) {
+        self.tty.open_count.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl Read for TtyFile {
+    fn read(&mut self, buf: &mut [u8]) -> AxResult<usize> {
+        FileIO::read(self, buf)
+    }
+}
+
+impl Write for TtyFile {
+    fn write(&mut self, buf: &[u8]) -> AxResult<usize> {
+        FileIO::write(self, buf)
+    }
+
+    fn flush(&mut self) -> AxResult {
+        Ok(())
+    }
+}
+
+impl Seek for TtyFile {
+    fn seek(&mut self, _pos: SeekFrom) -> AxResult<u64> {
+        Err(AxError::Unsupported) // 终端不能seek
+    }
+}
+
+impl FileExt for TtyFile {
+    fn readable(&self) -> bool {
+        self.flags.lock().readable()
+    }
+    fn writable(&self) -> bool {
+        self.flags.lock().writable()
+    }
+    fn executable(&self) -> bool {
+        false
+    }
+}
+
+impl FileIO for TtyFile {
+    fn read(&self, buf: &mut [u8]) -> AxResult<usize> {
+        let nonblock = self.flags.lock().contains(OpenFlags::NON_BLOCK);
+        self.tty.read(buf, nonblock)
+    }
+
+    fn write(&self, buf: &[u8]) -> AxResult<usize> {
+        let nonblock = self.flags.lock().contains(OpenFlags::NON_BLOCK);
+        self.tty.write(buf, nonblock)
+    }
+
+    fn flush(&self) -> AxResult {
+        Ok(())
+    }
+
+    fn readable(&self) -> bool {
+        self.flags.lock().readable()
+    }
+
+    fn writable(&self) -> bool {
+        self.flags.lock().writable()
+    }
+
+    fn executable(&self) -> bool {
+        false
+    }
+
+    fn get_type(&self) -> FileIOType {
+        FileIOType::Tty
+    }
+
+    fn get_path(&self) -> String {
+        format!("/dev/{}", self.tty.name())
+    }
+
+    fn get_stat(&self) -> AxResult<Kstat> {
+        Ok(Kstat {
+            // 字符设备，rw--w----
+            st_mode: 0o020620,
+            st_nlink: 1,
+            st_rdev: self.tty.rdev().raw(),
+            st_blksize: 1024,
+            ..Default::default()
+        })
+    }
+
+    fn ready_to_read(&self) -> bool {
+        self.tty.readable()
+    }
+
+    fn ready_to_write(&self) -> bool {
+        self.tty.is_disconnected() || self.tty.driver.write_room() > 0
+    }
+
+    fn is_hang_up(&self) -> bool {
+        self.tty.is_disconnected()
+    }
+
+    fn ioctl(&self, request: usize, arg: usize) -> AxResult<isize> {
+        self.tty.ioctl(request, arg)
+    }
+
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        // 访问模式不能修改
+        let mut old = self.flags.lock();
+        let mode = *old & (OpenFlags::WRONLY | OpenFlags::RDWR);
+        *old = (flags - OpenFlags::WRONLY - OpenFlags::RDWR) | mode;
+        true
+    }
+
+    fn get_status(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+
+    fn set_close_on_exec(&self, is_set: bool) -> bool {
+        if is_set {
+            // 设置close_on_exec位置
+            *self.flags.lock() |= OpenFlags::CLOEXEC;
+        } else {
+            *self.flags.lock() &= !OpenFlags::CLOEXEC;
+        }
+        true
+    }
+}
+
+/// 终端设备文件，出现在 `/dev` 与 `/dev/pts` 下
+///
+/// 用户程序打开时会被替换为 [`TtyFile`]，这里的读写只在内核直接访问设备文件时使用，
+/// `/dev/tty` 此时对应到控制台
+#[cfg(feature = "fs")]
+pub(crate) struct TtyNode {
+    /// 设备号
+    rdev: DeviceId,
+}
+
+#[cfg(feature = "fs")]
+impl TtyNode {
+    pub(crate) fn new(rdev: DeviceId) -> Self {
+        Self { rdev }
+    }
+
+    /// 设备号对应的终端
+    fn tty(&self) -> AxResult<Arc<Tty>> {
+        if self.rdev == TTY_DEVICE {
+            return Ok(console());
+        }
+        find_tty(self.rdev).ok_or(AxError::NoSuchDevice)
+    }
+}
+
+#[cfg(feature = "fs")]
+impl axfs_vfs::VfsNodeOps for TtyNode {
+    fn get_attr(&self) -> axfs_vfs::VfsResult<axfs_vfs::VfsNodeAttr> {
+        // 任何人都可以打开 ptmx 创建伪终端
+        let mode = if self.rdev == PTMX_DEVICE {
+            0o666
+        } else {
+            0o620
+        };
+        let mut attr = axfs_vfs::VfsNodeAttr::new(
+            axfs_vfs::VfsNodePerm::from_bits_truncate(mode),
+            axfs_vfs::VfsNodeType::CharDevice,
+            0,
+            0,
+        );
+        attr.set_rdev(self.rdev);
+        Ok(attr)
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> axfs_vfs::VfsResult<usize> {
+        self.tty()?.read(buf, false)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> axfs_vfs::VfsResult<usize> {
+        self.tty()?.write(buf, false)
+    }
+
+    fn truncate(&self, _size: u64) -> axfs_vfs::VfsResult {
+        Ok(())
+    }
+
+    axfs_vfs::impl_vfs_non_dir_default! {}
+}
+
+/// 将控制台与 ptmx 注册为设备，出现在 `/dev` 下，并在 devpts 中列出伪终端的从设备
+#[cfg(feature = "fs")]
+pub fn register_devices() {
+    for (name, rdev) in [
+        ("tty", TTY_DEVICE),
+        ("console", CONSOLE_DEVICE),
+        ("ttyS0", SERIAL_DEVICE),
+        ("ptmx", PTMX_DEVICE),
+    ] {
+        if let Err(e) = axfs::api::register_device(name, Arc::new(TtyNode::new(rdev))) {
+            warn!("failed to register /dev/{}: {:?}", name, e);
+        }
+    }
+    axfs::api::register_pts_entries(pty::pts_entries);
+}
@@ -0,0 +1,307 @@
+//! 伪终端
+//!
+//! 打开 `/dev/ptmx` 时创建一对伪终端：打开得到的文件是主设备，从设备是一个普通的终端，
+//! 出现在 `/dev/pts/<n>`，与控制台使用相同的行规程。写入主设备的数据作为从设备的输入，
+//! 从设备的输出（包括回显）从主设备读出。
+//!
+//! 从设备创建时被锁定，需要先通过 `TIOCSPTLCK`（即 `unlockpt`）解锁才能打开，
+//! `TIOCGPTN` 获取从设备的编号（即 `ptsname`）。主设备关闭时从设备断开并被删除。
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+#[cfg(feature = "fs")]
+use alloc::vec::Vec;
+use axerrno::{AxError, AxResult};
+use axfs::api::port::{
+    FileExt, FileIO, FileIOType, Kstat, OpenFlags, TIOCGPTLCK, TIOCGPTN, TIOCSPTLCK,
+};
+use axfs::api::DeviceId;
+use axio::{Read, Seek, SeekFrom, Write};
+use axsync::Mutex;
+use axtask::yield_now;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::{register_tty, unregister_tty, user_ptr, Tty, TtyDriver, PTMX_DEVICE};
+
+/// 从设备的主设备号，与 Linux 相同
+pub const PTS_MAJOR: u32 = 136;
+
+/// 伪终端的最大个数
+const MAX_PTYS: u32 = 256;
+
+/// 从设备输出缓冲区的大小，缓冲区满时写从设备的进程需要等待主设备读取
+const OUTPUT_BUF_SIZE: usize = 64 * 1024;
+
+/// 从设备的输出，等待从主设备读出
+struct PtyDriver {
+    output: Arc<Mutex<VecDeque<u8>>>,
+}
+
+impl TtyDriver for PtyDriver {
+    fn write(&self, buf: &[u8]) {
+        self.output.lock().extend(buf);
+    }
+
+    fn write_room(&self) -> usize {
+        OUTPUT_BUF_SIZE.saturating_sub(self.output.lock().len())
+    }
+}
+
+/// 一对伪终端
+struct Pty {
+    /// 从设备
+    slave: Arc<Tty>,
+    /// 从设备的输出
+    output: Arc<Mutex<VecDeque<u8>>>,
+    /// 从设备是否被锁定，锁定时不能打开
+    locked: AtomicBool,
+    /// 从设备是否被打开过，之后从设备全部关闭时读主设备返回 EIO
+    slave_opened: AtomicBool,
+}
+
+/// 已创建的伪终端，以从设备编号为键
+static PTYS: Mutex<BTreeMap<u32, Arc<Pty>>> = Mutex::new(BTreeMap::new());
+
+/// 创建一对伪终端，返回主设备的文件
+pub fn open_master(flags: OpenFlags) -> AxResult<Arc<dyn FileIO>> {
+    let mut ptys = PTYS.lock();
+    let index = (0..MAX_PTYS)
+        .find(|index| !ptys.contains_key(index))
+        .ok_or(AxError::StorageFull)?;
+    let output = Arc::new(Mutex::new(VecDeque::new()));
+    let slave = Arc::new(Tty::new(
+        &format!("pts/{}", index),
+        DeviceId::new(PTS_MAJOR, index),
+        Box::new(PtyDriver {
+            output: Arc::clone(&output),
+        }),
+    ));
+    let pty = Arc::new(Pty {
+        slave: Arc::clone(&slave),
+        output,
+        locked: AtomicBool::new(true),
+        slave_opened: AtomicBool::new(false),
+    });
+    ptys.insert(index, Arc::clone(&pty));
+    drop(ptys);
+    register_tty(slave);
+    Ok(Arc::new(PtyMaster {
+        index,
+        pty,
+        flags: Mutex::new(flags),
+    }))
+}
+
+/// 打开编号为 `index` 的从设备，不存在时返回 None，被锁定时返回 EIO
+pub fn open_slave(index: u32) -> Option<AxResult<Arc<Tty>>> {
+    let pty = PTYS.lock().get(&index).cloned()?;
+    if pty.locked.load(Ordering::Acquire) {
+        return Some(Err(AxError::Io));
+    }
+    pty.slave_opened.store(true, Ordering::Release);
+    Some(Ok(Arc::clone(&pty.slave)))
+}
+
+/// devpts 中列出的从设备
+#[cfg(feature = "fs")]
+pub fn pts_entries() -> Vec<(u32, axfs_vfs::VfsNodeRef)> {
+    PTYS.lock()
+        .keys()
+        .map(|&index| {
+            let node = super::TtyNode::new(DeviceId::new(PTS_MAJOR, index));
+            (index, Arc::new(node) as axfs_vfs::VfsNodeRef)
+        })
+        .collect()
+}
+
+/// 伪终端的主设备
+pub struct PtyMaster {
+    /// 从设备的编号
+    index: u32,
+    pty: Arc<Pty>,
+    flags: Mutex<OpenFlags>,
+}
+
+impl PtyMaster {
+    /// 从设备被打开过，并且已经全部关闭
+    fn slave_closed(&self) -> bool {
+        self.pty.slave_opened.load(Ordering::Acquire) && self.pty.slave.open_count() == 0
+    }
+}
+
+impl Drop for PtyMaster {
+    fn drop(&mut self) {
+        PTYS.lock().remove(&self.index);
+        unregister_tty(self.pty.slave.rdev());
+        self.pty.slave.disconnect();
+    }
+}
+
+impl Read for PtyMaster {
+    fn read(&mut self, buf: &mut [u8]) -> AxResult<usize> {
+        FileIO::read(self, buf)
+    }
+}
+
+impl Write for PtyMaster {
+    fn write(&mut self, buf: &[u8]) -> AxResult<usize> {
+        FileIO::write(self, buf)
+    }
+
+    fn flush(&mut self) -> AxResult {
+        Ok(())
+    }
+}
+
+impl Seek for PtyMaster {
+    fn seek(&mut self, _pos: SeekFrom) -> AxResult<u64> {
+        Err(AxError::Unsupported) // 终端不能seek
+    }
+}
+
+impl FileExt for PtyMaster {
+    fn readable(&self) -> bool {
+        self.flags.lock().readable()
+    }
+    fn writable(&self) -> bool {
+        self.flags.lock().writable()
+    }
+    fn executable(&self) -> bool {
+        false
+    }
+}
+
+impl FileIO for PtyMaster {
+    /// 读出从设备的输出
+    fn read(&self, buf: &mut [u8]) -> AxResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let mut output = self.pty.output.lock();
+            if !output.is_empty() {
+                let len = buf.len().min(output.len());
+                for (dst, src) in buf.iter_mut().zip(output.drain(..len)) {
+                    *dst = src;
+                }
+                return Ok(len);
+            }
+            drop(output);
+            if self.slave_closed() {
+                return Err(AxError::Io);
+            }
+            if self.flags.lock().contains(OpenFlags::NON_BLOCK) {
+                return Err(AxError::WouldBlock);
+            }
+            #[cfg(feature = "signal")]
+            if crate::current_process().have_signals().is_some() {
+                return Err(AxError::Interrupted);
+            }
+            yield_now();
+        }
+    }
+
+    /// 写入的数据作为从设备的输入
+    fn write(&self, buf: &[u8]) -> AxResult<usize> {
+        self.pty.slave.receive(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&self) -> AxResult {
+        Ok(())
+    }
+
+    fn readable(&self) -> bool {
+        self.flags.lock().readable()
+    }
+
+    fn writable(&self) -> bool {
+        self.flags.lock().writable()
+    }
+
+    fn executable(&self) -> bool {
+        false
+    }
+
+    fn get_type(&self) -> FileIOType {
+        FileIOType::Tty
+    }
+
+    fn get_path(&self) -> String {
+        "/dev/ptmx".into()
+    }
+
+    fn get_stat(&self) -> AxResult<Kstat> {
+        Ok(Kstat {
+            // 字符设备，rw-rw-rw-
+            st_mode: 0o020666,
+            st_nlink: 1,
+            st_rdev: PTMX_DEVICE.raw(),
+            st_blksize: 1024,
+            ..Default::default()
+        })
+    }
+
+    fn ready_to_read(&self) -> bool {
+        !self.pty.output.lock().is_empty() || self.slave_closed()
+    }
+
+    fn ready_to_write(&self) -> bool {
+        true
+    }
+
+    fn is_hang_up(&self) -> bool {
+        self.slave_closed()
+    }
+
+    fn ioctl(&self, request: usize, arg: usize) -> AxResult<isize> {
+        match request {
+            TIOCGPTN => {
+                let ptr = user_ptr::<u32>(arg)?;
+                unsafe {
+                    *ptr = self.index;
+                }
+                Ok(0)
+            }
+            TIOCSPTLCK => {
+                let lock = unsafe { *user_ptr::<i32>(arg)? } != 0;
+                self.pty.locked.store(lock, Ordering::Release);
+                Ok(0)
+            }
+            TIOCGPTLCK => {
+                let ptr = user_ptr::<i32>(arg)?;
+                unsafe {
+                    *ptr = self.pty.locked.load(Ordering::Acquire) as i32;
+                }
+                Ok(0)
+            }
+            // 其余的请求作用在从设备上，如窗口大小与终端属性
+            _ => self.pty.slave.ioctl(request, arg),
+        }
+    }
+
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        // 访问模式不能修改
+        let mut old = self.flags.lock();
+        let mode = *old & (OpenFlags::WRONLY | OpenFlags::RDWR);
+        *old = (flags - OpenFlags::WRONLY - OpenFlags::RDWR) | mode;
+        true
+    }
+
+    fn get_status(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+
+    fn set_close_on_exec(&self, is_set: bool) -> bool {
+        if is_set {
+            // 设置close_on_exec位置
+            *self.flags.lock() |= OpenFlags::CLOEXEC;
+        } else {
+            *self.flags.lock() &= !OpenFlags::CLOEXEC;
+        }
+        true
+    }
+}
@@ -0,0 +1,167 @@
+//! 终端属性，与 Linux 的 `struct termios`（`TCGETS`/`TCSETS` 所用的版本）布局相同
+use bitflags::bitflags;
+
+bitflags! {
+    /// 输入模式
+    #[repr(transparent)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct InputFlags: u32 {
+        const IGNBRK = 0o1;
+        const BRKINT = 0o2;
+        const IGNPAR = 0o4;
+        const PARMRK = 0o10;
+        const INPCK = 0o20;
+        /// 去掉输入的第8位
+        const ISTRIP = 0o40;
+        /// 将输入的 NL 转换为 CR
+        const INLCR = 0o100;
+        /// 忽略输入的 CR
+        const IGNCR = 0o200;
+        /// 将输入的 CR 转换为 NL
+        const ICRNL = 0o400;
+        const IUCLC = 0o1000;
+        const IXON = 0o2000;
+        const IXANY = 0o4000;
+        const IXOFF = 0o10000;
+        const IMAXBEL = 0o20000;
+        const IUTF8 = 0o40000;
+    }
+
+    /// 输出模式
+    #[repr(transparent)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct OutputFlags: u32 {
+        /// 对输出进行处理，以下标志只在设置了它时有效
+        const OPOST = 0o1;
+        const OLCUC = 0o2;
+        /// 将输出的 NL 转换为 CR NL
+        const ONLCR = 0o4;
+        /// 将输出的 CR 转换为 NL
+        const OCRNL = 0o10;
+        const ONOCR = 0o20;
+        const ONLRET = 0o40;
+    }
+
+    /// 控制模式，对控制台没有实际作用，只是保存下来
+    #[repr(transparent)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct ControlFlags: u32 {
+        const B38400 = 0o17;
+        const CS8 = 0o60;
+        const CSTOPB = 0o100;
+        const CREAD = 0o200;
+        const PARENB = 0o400;
+        const HUPCL = 0o2000;
+        const CLOCAL = 0o4000;
+    }
+
+    /// 本地模式，即行规程的行为
+    #[repr(transparent)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct LocalFlags: u32 {
+        /// 收到 INTR、QUIT、SUSP 时向前台进程组发送信号
+        const ISIG = 0o1;
+        /// 规范模式，按行读取并处理编辑字符
+        const ICANON = 0o2;
+        const XCASE = 0o4;
+        /// 回显输入
+        const ECHO = 0o10;
+        /// ERASE 擦除前一个字符
+        const ECHOE = 0o20;
+        /// KILL 擦除当前行
+        const ECHOK = 0o40;
+        /// 即使没有设置 ECHO 也回显 NL
+        const ECHONL = 0o100;
+        /// 产生信号时不清空输入
+        const NOFLSH = 0o200;
+        /// 后台进程写终端时发送 SIGTTOU
+        const TOSTOP = 0o400;
+        /// 以 `^X` 的形式回显控制字符
+        const ECHOCTL = 0o1000;
+        const ECHOPRT = 0o2000;
+        /// KILL 逐个擦除当前行的字符
+        const ECHOKE = 0o4000;
+        const FLUSHO = 0o10000;
+        const PENDIN = 0o40000;
+        const IEXTEN = 0o100000;
+    }
+}
+
+/// 控制字符的个数
+pub const NCCS: usize = 19;
+
+/// 中断，产生 SIGINT
+pub const VINTR: usize = 0;
+/// 退出，产生 SIGQUIT
+pub const VQUIT: usize = 1;
+/// 擦除前一个字符
+pub const VERASE: usize = 2;
+/// 擦除当前行
+pub const VKILL: usize = 3;
+/// 文件结束
+pub const VEOF: usize = 4;
+/// 非规范模式下读取的超时时间，单位为 0.1 秒
+pub const VTIME: usize = 5;
+/// 非规范模式下读取的最少字节数
+pub const VMIN: usize = 6;
+/// 挂起，产生 SIGTSTP
+pub const VSUSP: usize = 10;
+/// 额外的行结束符
+pub const VEOL: usize = 11;
+/// 擦除前一个单词
+pub const VWERASE: usize = 14;
+/// 下一个字符按字面处理
+pub const VLNEXT: usize = 15;
+/// 额外的行结束符
+pub const VEOL2: usize = 16;
+
+/// 终端属性
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Termios {
+    pub c_iflag: InputFlags,
+    pub c_oflag: OutputFlags,
+    pub c_cflag: ControlFlags,
+    pub c_lflag: LocalFlags,
+    pub c_line: u8,
+    pub c_cc: [u8; NCCS],
+}
+
+impl Default for Termios {
+    /// 与 Linux 的 `tty_std_termios` 相同
+    fn default() -> Self {
+        Self {
+            c_iflag: InputFlags::ICRNL | InputFlags::IXON,
+            c_oflag: OutputFlags::OPOST | OutputFlags::ONLCR,
+            c_cflag: ControlFlags::B38400
+                | ControlFlags::CS8
+                | ControlFlags::CREAD
+                | ControlFlags::HUPCL,
+            c_lflag: LocalFlags::ISIG
+                | LocalFlags::ICANON
+                | LocalFlags::ECHO
+                | LocalFlags::ECHOE
+                | LocalFlags::ECHOK
+                | LocalFlags::ECHOCTL
+                | LocalFlags::ECHOKE
+                | LocalFlags::IEXTEN,
+            c_line: 0,
+            // ^C ^\ DEL ^U ^D 0 1 0 ^Q ^S ^Z 0 ^R ^O ^W ^V 0
+            c_cc: [
+                3, 28, 127, 21, 4, 0, 1, 0, 17, 19, 26, 0, 18, 15, 23, 22, 0, 0, 0,
+            ],
+        }
+    }
+}
+
+impl Termios {
+    /// 是否为规范模式
+    pub fn canonical(&self) -> bool {
+        self.c_lflag.contains(LocalFlags::ICANON)
+    }
+
+    /// 字符 `c` 是否为控制字符 `index`，值为 0 的控制字符被禁用
+    pub fn is_cc(&self, c: u8, index: usize) -> bool {
+        self.c_cc[index] != 0 && self.c_cc[index] == c
+    }
+}
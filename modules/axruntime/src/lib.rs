@@ -22,6 +22,9 @@
 #[macro_use]
 extern crate axlog;
 
+#[cfg(feature = "fs")]
+extern crate alloc;
+
 #[cfg(all(target_os = "none", not(test)))]
 mod lang_items;
 
@@ -31,6 +34,9 @@ mod trap;
 #[cfg(feature = "smp")]
 mod mp;
 
+#[cfg(feature = "fs")]
+mod sysfs;
+
 #[cfg(feature = "smp")]
 pub use self::mp::rust_main_secondary;
 
@@ -167,6 +173,11 @@ pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
         #[allow(unused_variables)]
         let all_devices = axdriver::init_drivers();
 
+        #[cfg(feature = "fs")]
+        self::sysfs::add_devices(&all_devices);
+        #[cfg(all(feature = "fs", feature = "multitask"))]
+        self::sysfs::add_scheduler();
+
         #[cfg(feature = "fs")]
         axfs::init_filesystems(all_devices.block);
 
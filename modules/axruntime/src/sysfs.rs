@@ -0,0 +1,80 @@
+//! The sysfs entries of the devices probed by [`axdriver`] that are not
+//! block devices, and of the scheduler. The framebuffer is also published
+//! as `/dev/fb0`.
+
+use alloc::string::String;
+#[allow(unused_imports)]
+use axdriver::prelude::*;
+use axfs::api::{add_sysfs_node, SysAttr};
+
+/// A read-only attribute with a fixed value.
+#[allow(dead_code)]
+fn value(value: String) -> SysAttr {
+    SysAttr::read_only(move || value.clone())
+}
+
+fn add(path: &str, attr: SysAttr) {
+    if let Err(e) = add_sysfs_node(path, attr) {
+        warn!("failed to add {} to sysfs: {:?}", path, e);
+    }
+}
+
+/// Adds the devices in `all_devices` to sysfs, before they are handed to
+/// their subsystems.
+#[allow(unused_variables)]
+pub(crate) fn add_devices(all_devices: &axdriver::AllDevices) {
+    // the first one becomes `eth0`, the others are unused
+    #[cfg(feature = "net")]
+    if let Some(dev) = all_devices.net.iter().next() {
+        let mac = dev.mac_address().0;
+        let address = mac.map(|b| alloc::format!("{:02x}", b)).join(":");
+        add("class/net/eth0/address", value(address));
+        add("class/net/eth0/addr_len", value("6".into()));
+        add("class/net/eth0/mtu", value("1500".into()));
+        add("class/net/eth0/operstate", value("up".into()));
+        // ARPHRD_ETHER
+        add("class/net/eth0/type", value("1".into()));
+        add("class/net/eth0/ifindex", value("2".into()));
+        add(
+            "class/net/eth0/device/name",
+            value(dev.device_name().into()),
+        );
+    }
+
+    #[cfg(feature = "display")]
+    if let Some(dev) = all_devices.display.iter().next() {
+        let info = dev.info();
+        let size = alloc::format!("{},{}", info.width, info.height);
+        let bpp = info.fb_size * 8 / (info.width * info.height).max(1) as usize;
+        add("class/graphics/fb0/virtual_size", value(size));
+        add(
+            "class/graphics/fb0/bits_per_pixel",
+            value(alloc::format!("{}", bpp)),
+        );
+        add("class/graphics/fb0/name", value(dev.device_name().into()));
+        // the framebuffer stays mapped for the lifetime of the kernel
+        let fb = unsafe { axfs::axfs_devfs::FrameBufferDev::new(info.fb_base_vaddr, info.fb_size) };
+        if let Err(e) = axfs::api::register_device("fb0", alloc::sync::Arc::new(fb)) {
+            warn!("failed to register /dev/fb0: {:?}", e);
+        }
+    }
+}
+
+/// Adds the scheduler to the tunables. It is chosen when the kernel is
+/// built, so only the current one can be written.
+#[cfg(feature = "multitask")]
+pub(crate) fn add_scheduler() {
+    add(
+        "module/axtask/parameters/scheduler",
+        SysAttr::read_write(
+            || axtask::scheduler_name().into(),
+            |name| {
+                if name == axtask::scheduler_name() {
+                    Ok(())
+                } else {
+                    Err(axerrno::AxError::InvalidInput)
+                }
+            },
+        ),
+    );
+}
@@ -74,6 +74,11 @@ pub fn init_scheduler() {
     info!("  use {} scheduler.", Scheduler::scheduler_name());
 }
 
+/// Returns the name of the scheduler chosen when the kernel is built.
+pub fn scheduler_name() -> &'static str {
+    Scheduler::scheduler_name()
+}
+
 /// Initializes the task scheduler for secondary CPUs.
 pub fn init_scheduler_secondary() {
     crate::run_queue::init_secondary();
@@ -7,7 +7,7 @@ use alloc::vec::Vec;
 use axhal::arch::{flush_tlb, write_page_table_root};
 use axhal::KERNEL_PROCESS_ID;
 use axlog::info;
-use axprocess::link::{create_link, FilePath};
+use axprocess::link::FilePath;
 use axprocess::{wait_pid, yield_now_task, PID2PC};
 use axruntime::KERNEL_PAGE_TABLE;
 use axtask::{TaskId, EXITED_TASKS};
@@ -2,21 +2,27 @@ use syscall_utils::{normal_file_mode, StMode};
 extern crate alloc;
 use alloc::string::{String, ToString};
 use axerrno::{AxError, AxResult};
-use axfs::api::{self, FileIO, FileIOType, Kstat, OpenFlags};
+use axfs::api::{self, FileIO, FileIOType, Kstat, OpenFlags, ResolvedPath};
 use axio::SeekFrom;
 use axlog::debug;
+use axsync::Mutex;
 
 /// 目录描述符
 pub struct DirDesc {
     /// 目录
     pub dir_path: String,
+    /// getdents64下次开始读取的位置，由文件系统给出，不一定是目录项的下标
+    pub pos: Mutex<u64>,
 }
 
 /// 目录描述符的实现
 impl DirDesc {
     /// 创建一个新的目录描述符
     pub fn new(path: String) -> Self {
-        Self { dir_path: path }
+        Self {
+            dir_path: path,
+            pos: Mutex::new(0),
+        }
     }
 }
 
@@ -29,10 +35,21 @@ impl FileIO for DirDesc {
         Err(AxError::IsADirectory)
     }
     fn flush(&self) -> AxResult {
-        Err(AxError::IsADirectory)
+        match api::lookup(&self.dir_path)?.fsync() {
+            // 内存中的目录没有需要写回的数据
+            Err(AxError::IsADirectory) => Ok(()),
+            result => result,
+        }
     }
-    fn seek(&self, _: SeekFrom) -> AxResult<u64> {
-        Err(AxError::IsADirectory)
+    /// 目录的偏移量是不透明的位置，只支持设置为之前得到的位置或者查询当前位置
+    fn seek(&self, pos: SeekFrom) -> AxResult<u64> {
+        let mut now = self.pos.lock();
+        match pos {
+            SeekFrom::Start(pos) => *now = pos,
+            SeekFrom::Current(0) => {}
+            _ => return Err(AxError::InvalidInput),
+        }
+        Ok(*now)
     }
     fn get_type(&self) -> FileIOType {
         FileIOType::DirDesc
@@ -51,11 +68,16 @@ impl FileIO for DirDesc {
     }
 
     fn get_stat(&self) -> AxResult<Kstat> {
+        // 目录的链接数由文件系统给出，一般为 2 加上子目录的个数
+        let nlink = api::resolve_path(None, &self.dir_path, api::LookupFlags::DIRECTORY)
+            .ok()
+            .and_then(|resolved| resolved.node?.get_attr().ok())
+            .map_or(1, |attr| attr.nlink() as u32);
         let kstat = Kstat {
             st_dev: 1,
             st_ino: 0,
             st_mode: normal_file_mode(StMode::S_IFDIR).bits(),
-            st_nlink: 1,
+            st_nlink: nlink,
             st_uid: 0,
             st_gid: 0,
             st_rdev: 0,
@@ -78,8 +100,25 @@ impl FileIO for DirDesc {
 pub fn new_dir(dir_path: String, _flags: OpenFlags) -> AxResult<DirDesc> {
     debug!("Into function new_dir, dir_path: {}", dir_path);
     if !api::path_exists(dir_path.as_str()) {
-        // api::create_dir_all(dir_path.as_str())?;
+        // 创建时检查父目录的写权限，创建者总是可以打开新目录
         api::create_dir(dir_path.as_str())?;
+    } else {
+        // 与打开文件相同，经过权限钩子检查读权限
+        let cred = api::current_credentials();
+        api::access(dir_path.as_str(), api::Access::READ, &cred)?;
     }
     Ok(DirDesc::new(dir_path))
 }
+
+/// 打开路径解析时得到的目录，权限检查使用解析得到的结点而不是重新解析路径，目录不存在时创建
+pub fn open_dir(resolved: &ResolvedPath, flags: OpenFlags) -> AxResult<DirDesc> {
+    debug!("Into function open_dir, dir_path: {}", resolved.path);
+    match &resolved.node {
+        Some(node) => {
+            let cred = api::current_credentials();
+            api::permission(&cred, &node.get_attr()?, api::Access::READ)?;
+            Ok(DirDesc::new(resolved.path.clone()))
+        }
+        None => new_dir(resolved.path.clone(), flags),
+    }
+}
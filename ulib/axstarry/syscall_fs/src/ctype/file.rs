@@ -4,14 +4,22 @@ use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
-use axerrno::AxResult;
-use axfs::api::{File, FileIO, FileIOType, Kstat, OpenFlags};
+use axerrno::{AxError, AxResult};
+use axfs::api::{
+    Fiemap, FiemapExtent, File, FileFlags, FileIO, FileIOType, Kstat, LockOwner, LoopConfig,
+    LoopInfo64, OpenFlags, ResolvedPath, FIEMAP_FLAG_SYNC, FS_IOC_FIEMAP, FS_IOC_GETFLAGS, FS_IOC_SETFLAGS,
+    LOOP_CLR_FD, LOOP_CONFIGURE, LOOP_CTL_ADD, LOOP_CTL_GET_FREE, LOOP_CTL_REMOVE,
+    LOOP_DEVICE_COUNT, LOOP_GET_STATUS64, LOOP_SET_FD, LOOP_SET_STATUS64, LO_FLAGS_AUTOCLEAR,
+    LO_FLAGS_READ_ONLY, LO_NAME_SIZE, RTC_RD_TIME,
+};
+use axhal::mem::VirtAddr;
 use axio::{Read, Seek, SeekFrom, Write};
 use axlog::debug;
+use core::mem::size_of;
 
-use axprocess::link::get_link_count;
+use axprocess::current_process;
 use axsync::Mutex;
-use syscall_utils::{new_file, TimeSecs};
+use syscall_utils::{new_file, new_file_resolved, TimeSecs};
 use syscall_utils::{normal_file_mode, StMode};
 
 /// 文件描述符
@@ -96,10 +104,10 @@ impl FileIO for FileDesc {
             st_dev: 1,
             st_ino: 1,
             st_mode: normal_file_mode(StMode::S_IFREG).bits(),
-            st_nlink: get_link_count(&(self.path.as_str().to_string())) as u32,
-            st_uid: 0,
-            st_gid: 0,
-            st_rdev: 0,
+            st_nlink: metadata.nlink() as u32,
+            st_uid: metadata.uid(),
+            st_gid: metadata.gid(),
+            st_rdev: metadata.rdev().raw(),
             _pad0: 0,
             st_size: metadata.size(),
             st_blksize: 0,
@@ -160,6 +168,202 @@ impl FileIO for FileDesc {
         self.seek(SeekFrom::Start(now_pos)).unwrap();
         now_pos != len
     }
+
+    fn ioctl(&self, request: usize, arg1: usize) -> AxResult<isize> {
+        let mut file = self.file.lock();
+        if let Some(number) = file.loop_number() {
+            drop(file);
+            return loop_ioctl(number, request, arg1);
+        }
+        if file.is_loop_control() {
+            drop(file);
+            return loop_control_ioctl(request, arg1);
+        }
+        match request {
+            FS_IOC_GETFLAGS => {
+                let flags = file.flags()?;
+                let ptr = arg1 as *mut u32;
+                current_process()
+                    .manual_alloc_type_for_lazy(ptr)
+                    .map_err(|_| AxError::BadAddress)?;
+                unsafe { *ptr = flags.bits() };
+                Ok(0)
+            }
+            FS_IOC_SETFLAGS => {
+                let flags = unsafe { read_user(arg1 as *const u32) }
+                    .map(FileFlags::from_bits_retain)
+                    .map_err(|_| AxError::BadAddress)?;
+                // 与 Linux 的 CAP_LINUX_IMMUTABLE 一致，只有超级用户能设置或清除不可修改和只追加标志
+                let protected = FileFlags::IMMUTABLE | FileFlags::APPEND;
+                if (file.flags()? ^ flags).intersects(protected)
+                    && !current_process().cred.lock().is_privileged()
+                {
+                    return Err(AxError::OperationNotPermitted);
+                }
+                // 文件系统不支持时返回EINVAL，而不是被当作未实现的请求忽略
+                file.set_flags(flags).map_err(|e| match e {
+                    AxError::Unsupported => AxError::InvalidInput,
+                    e => e,
+                })
+            }
+            FS_IOC_FIEMAP => {
+                let ptr = arg1 as *mut Fiemap;
+                current_process()
+                    .manual_alloc_type_for_lazy(ptr as *const Fiemap)
+                    .map_err(|_| AxError::BadAddress)?;
+                let fiemap = unsafe { &mut *ptr };
+                if fiemap.fm_flags & !FIEMAP_FLAG_SYNC != 0 {
+                    // 返回支持的标志，供用户态判断
+                    fiemap.fm_flags &= FIEMAP_FLAG_SYNC;
+                    return Err(AxError::InvalidInput);
+                }
+                if fiemap.fm_flags & FIEMAP_FLAG_SYNC != 0 {
+                    // 先把缓存的数据写回，使返回的 extent 与磁盘上一致
+                    file.flush()?;
+                }
+                let extents = file
+                    .fiemap(fiemap.fm_start, fiemap.fm_length)
+                    .map_err(|e| match e {
+                        AxError::Unsupported => AxError::InvalidInput,
+                        e => e,
+                    })?;
+                if fiemap.fm_extent_count == 0 {
+                    // 只统计extent的数量
+                    fiemap.fm_mapped_extents = extents.len() as u32;
+                    return Ok(0);
+                }
+
+                let count = extents.len().min(fiemap.fm_extent_count as usize);
+                let start = arg1 + size_of::<Fiemap>();
+                // 区间的两端都包含在内
+                let end = (fiemap.fm_extent_count as usize)
+                    .checked_mul(size_of::<FiemapExtent>())
+                    .and_then(|len| start.checked_add(len - 1))
+                    .ok_or(AxError::BadAddress)?;
+                current_process()
+                    .manual_alloc_range_for_lazy(VirtAddr::from(start), VirtAddr::from(end))
+                    .map_err(|_| AxError::BadAddress)?;
+                let out = unsafe {
+                    core::slice::from_raw_parts_mut(start as *mut FiemapExtent, count)
+                };
+                for (out, extent) in out.iter_mut().zip(extents.iter()) {
+                    *out = FiemapExtent {
+                        fe_logical: extent.logical,
+                        fe_physical: extent.physical,
+                        fe_length: extent.length,
+                        fe_flags: extent.flags.bits(),
+                        ..Default::default()
+                    };
+                }
+                fiemap.fm_mapped_extents = count as u32;
+                Ok(0)
+            }
+            RTC_RD_TIME => {
+                let time = file.rtc_time().ok_or(AxError::Unsupported)?;
+                let ptr = arg1 as *mut axfs::axfs_devfs::RtcTime;
+                current_process()
+                    .manual_alloc_type_for_lazy(ptr)
+                    .map_err(|_| AxError::BadAddress)?;
+                unsafe { ptr.write(time) };
+                Ok(0)
+            }
+            _ => Err(AxError::Unsupported),
+        }
+    }
+}
+
+/// loop 设备 `/dev/loopN` 上的 ioctl
+fn loop_ioctl(number: usize, request: usize, arg1: usize) -> AxResult<isize> {
+    let result = match request {
+        LOOP_SET_FD => attach_loop(number, arg1),
+        LOOP_CLR_FD => axfs::api::loop_detach(number),
+        LOOP_CONFIGURE => {
+            let config = unsafe { read_user(arg1 as *const LoopConfig)? };
+            attach_loop(number, config.fd as usize)?;
+            set_loop_status(number, &config.info).map_err(|e| {
+                // 配置失败时不保留绑定
+                let _ = axfs::api::loop_detach(number);
+                e
+            })
+        }
+        LOOP_SET_STATUS64 => {
+            let info = unsafe { read_user(arg1 as *const LoopInfo64)? };
+            set_loop_status(number, &info)
+        }
+        LOOP_GET_STATUS64 => {
+            let status = axfs::api::loop_status(number)?;
+            let mut info = LoopInfo64 {
+                lo_offset: status.offset,
+                lo_sizelimit: status.size_limit,
+                lo_number: status.number as u32,
+                ..Default::default()
+            };
+            if status.read_only {
+                info.lo_flags |= LO_FLAGS_READ_ONLY;
+            }
+            if status.autoclear {
+                info.lo_flags |= LO_FLAGS_AUTOCLEAR;
+            }
+            // 保留结尾的 '\0'
+            let name = status.file_name.as_bytes();
+            let len = name.len().min(LO_NAME_SIZE - 1);
+            info.lo_file_name[..len].copy_from_slice(&name[..len]);
+            let out = arg1 as *mut LoopInfo64;
+            current_process().manual_alloc_type_for_lazy(out as *const LoopInfo64)?;
+            unsafe { out.write(info) };
+            Ok(())
+        }
+        // 其余 loop 请求不支持，返回EINVAL而不是假装成功
+        _ => Err(AxError::InvalidInput),
+    };
+    result.map(|_| 0)
+}
+
+/// `/dev/loop-control` 上的 ioctl，参数为设备编号
+fn loop_control_ioctl(request: usize, arg1: usize) -> AxResult<isize> {
+    match request {
+        LOOP_CTL_GET_FREE => axfs::api::loop_get_free().map(|number| number as isize),
+        // loop 设备数量固定，已有的设备视为已经存在，其余的无法创建
+        LOOP_CTL_ADD if arg1 < LOOP_DEVICE_COUNT => Err(AxError::AlreadyExists),
+        LOOP_CTL_ADD => Err(AxError::InvalidInput),
+        // 设备数量固定，不会真正删除，只检查设备是否空闲
+        LOOP_CTL_REMOVE if arg1 >= LOOP_DEVICE_COUNT => Err(AxError::NoSuchDevice),
+        LOOP_CTL_REMOVE if axfs::api::loop_status(arg1).is_ok() => Err(AxError::ResourceBusy),
+        LOOP_CTL_REMOVE => Ok(0),
+        _ => Err(AxError::InvalidInput),
+    }
+}
+
+/// 把 loop 设备 `number` 绑定到文件描述符 `fd` 对应的文件
+fn attach_loop(number: usize, fd: usize) -> AxResult {
+    let process = current_process();
+    let fd_table = process.fd_manager.fd_table.lock();
+    let file = fd_table
+        .get(fd)
+        .and_then(|file| file.clone())
+        .ok_or(AxError::InvalidInput)?;
+    drop(fd_table);
+    let file_desc = file
+        .as_any()
+        .downcast_ref::<FileDesc>()
+        .ok_or(AxError::InvalidInput)?;
+    let backing = file_desc.file.lock().clone();
+    backing.attach_loop(number, &file_desc.path)
+}
+
+fn set_loop_status(number: usize, info: &LoopInfo64) -> AxResult {
+    axfs::api::loop_set_status(
+        number,
+        info.lo_offset,
+        info.lo_sizelimit,
+        info.lo_flags & LO_FLAGS_AUTOCLEAR != 0,
+    )
+}
+
+/// 从用户地址读出一个结构体
+unsafe fn read_user<T: Copy>(ptr: *const T) -> AxResult<T> {
+    current_process().manual_alloc_type_for_lazy(ptr)?;
+    Ok(ptr.read())
 }
 
 impl FileDesc {
@@ -178,6 +382,24 @@ impl FileDesc {
             }),
         }
     }
+
+    /// 打开的文件作为 OFD 锁与 flock 锁的所有者，dup 与 fork 得到的文件描述符共享同一个
+    pub fn lock_owner(&self) -> LockOwner {
+        LockOwner::File(self as *const Self as usize)
+    }
+
+    /// 进程关闭文件描述符时，释放进程在该文件上持有的 POSIX 记录锁
+    pub fn release_process_locks(&self, pid: u64) {
+        self.file.lock().release_locks(LockOwner::Process(pid));
+    }
+}
+
+impl Drop for FileDesc {
+    /// 打开的文件的最后一个引用被释放时，释放其持有的 OFD 锁与 flock 锁
+    fn drop(&mut self) {
+        let owner = self.lock_owner();
+        self.file.lock().release_locks(owner);
+    }
 }
 
 /// 新建一个文件描述符
@@ -189,3 +411,11 @@ pub fn new_fd(path: String, flags: OpenFlags) -> AxResult<FileDesc> {
     let fd = FileDesc::new(path.as_str(), Arc::new(Mutex::new(file)), flags);
     Ok(fd)
 }
+
+/// 打开路径解析时得到的文件，不再按路径重新解析，避免两次解析之间路径被修改
+pub fn open_fd(resolved: &ResolvedPath, flags: OpenFlags) -> AxResult<FileDesc> {
+    debug!("Into function open_fd, path: {}", resolved.path);
+    let file = new_file_resolved(resolved, &flags)?;
+    let fd = FileDesc::new(resolved.path.as_str(), Arc::new(Mutex::new(file)), flags);
+    Ok(fd)
+}
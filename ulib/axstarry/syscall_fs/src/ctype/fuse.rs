@@ -0,0 +1,104 @@
+//! FUSE 设备文件
+//!
+//! 每次打开 `/dev/fuse` 都会新建一个 FUSE 连接。用户态守护进程把文件描述符通过
+//! `fd=` 挂载选项传给 mount(2)，之后从该文件读出文件系统的请求，并写回应答。
+//! 请求队列与应答的匹配由 axfs 维护，这里只负责阻塞读与文件状态。
+extern crate alloc;
+use alloc::sync::Arc;
+use axerrno::{AxError, AxResult};
+use axfs::api::{FileIO, FileIOType, FuseConnection, OpenFlags};
+use axprocess::yield_now_task;
+use axsync::Mutex;
+
+/// FUSE 设备文件
+pub struct FuseDevFile {
+    conn: Arc<FuseConnection>,
+    /// 只记录 O_NONBLOCK 与 O_CLOEXEC
+    flags: Mutex<OpenFlags>,
+}
+
+impl FuseDevFile {
+    /// 新建一个尚未挂载的 FUSE 连接
+    pub fn new(flags: OpenFlags) -> Self {
+        Self {
+            conn: FuseConnection::new(),
+            flags: Mutex::new(flags),
+        }
+    }
+
+    /// 获取对应的连接，用于挂载
+    pub fn conn(&self) -> Arc<FuseConnection> {
+        Arc::clone(&self.conn)
+    }
+}
+
+impl FileIO for FuseDevFile {
+    /// 读出一个请求，没有请求时阻塞，非阻塞模式下返回 EAGAIN
+    fn read(&self, buf: &mut [u8]) -> AxResult<usize> {
+        loop {
+            match self.conn.read_request(buf) {
+                Err(AxError::WouldBlock) => {}
+                res => return res,
+            }
+            if self.flags.lock().contains(OpenFlags::NON_BLOCK) {
+                return Err(AxError::WouldBlock);
+            }
+            #[cfg(feature = "signal")]
+            if axprocess::current_process().have_signals().is_some() {
+                return Err(AxError::Interrupted);
+            }
+            yield_now_task();
+        }
+    }
+
+    /// 写入一个应答，必须一次写入完整的应答
+    fn write(&self, buf: &[u8]) -> AxResult<usize> {
+        self.conn.write_reply(buf)
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn executable(&self) -> bool {
+        false
+    }
+
+    fn get_type(&self) -> FileIOType {
+        FileIOType::Other
+    }
+
+    fn ready_to_read(&self) -> bool {
+        self.conn.has_requests()
+    }
+
+    fn ready_to_write(&self) -> bool {
+        true
+    }
+
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        let mut old = self.flags.lock();
+        old.set(OpenFlags::NON_BLOCK, flags.contains(OpenFlags::NON_BLOCK));
+        true
+    }
+
+    fn get_status(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+
+    fn set_close_on_exec(&self, is_set: bool) -> bool {
+        self.flags.lock().set(OpenFlags::CLOEXEC, is_set);
+        true
+    }
+}
+
+impl Drop for FuseDevFile {
+    /// 守护进程关闭设备后断开连接，等待中与之后的请求都会失败
+    fn drop(&mut self) {
+        self.conn.abort();
+    }
+}
@@ -0,0 +1,104 @@
+//! inotify 文件
+//!
+//! 监视与事件队列由 axfs 维护，这里将其包装为文件描述符：读出的是 `struct inotify_event`
+//! 记录，有事件时文件可读，因此可以用 ppoll 与 epoll 等待事件。
+extern crate alloc;
+use alloc::sync::Arc;
+use axerrno::{AxError, AxResult};
+use axfs::api::{FileIO, FileIOType, Inotify, OpenFlags, FIONREAD};
+use axprocess::{current_process, yield_now_task};
+use axsync::Mutex;
+
+/// inotify 文件
+pub struct InotifyFile {
+    inotify: Arc<Inotify>,
+    /// 只记录 O_NONBLOCK 与 O_CLOEXEC
+    flags: Mutex<OpenFlags>,
+}
+
+impl InotifyFile {
+    /// 新建一个没有监视的 inotify 文件
+    pub fn new(flags: OpenFlags) -> Self {
+        Self {
+            inotify: Inotify::new(),
+            flags: Mutex::new(flags),
+        }
+    }
+
+    /// 获取对应的 inotify 实例，用于添加和删除监视
+    pub fn inotify(&self) -> Arc<Inotify> {
+        Arc::clone(&self.inotify)
+    }
+}
+
+impl FileIO for InotifyFile {
+    /// 读出队列中的事件，没有事件时阻塞，非阻塞模式下返回 EAGAIN
+    fn read(&self, buf: &mut [u8]) -> AxResult<usize> {
+        loop {
+            match self.inotify.read_events(buf) {
+                Err(AxError::WouldBlock) => {}
+                res => return res,
+            }
+            if self.flags.lock().contains(OpenFlags::NON_BLOCK) {
+                return Err(AxError::WouldBlock);
+            }
+            #[cfg(feature = "signal")]
+            if axprocess::current_process().have_signals().is_some() {
+                return Err(AxError::Interrupted);
+            }
+            yield_now_task();
+        }
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn executable(&self) -> bool {
+        false
+    }
+
+    fn get_type(&self) -> FileIOType {
+        FileIOType::FileDesc
+    }
+
+    fn ready_to_read(&self) -> bool {
+        self.inotify.has_events()
+    }
+
+    fn ioctl(&self, request: usize, arg: usize) -> AxResult<isize> {
+        match request {
+            // 队列中事件的总字节数
+            FIONREAD => {
+                let ptr = arg as *mut i32;
+                current_process()
+                    .manual_alloc_type_for_lazy(ptr as *const i32)
+                    .map_err(|_| AxError::BadAddress)?;
+                unsafe {
+                    *ptr = self.inotify.pending_bytes() as i32;
+                }
+                Ok(0)
+            }
+            _ => Err(AxError::Unsupported),
+        }
+    }
+
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        let mut old = self.flags.lock();
+        old.set(OpenFlags::NON_BLOCK, flags.contains(OpenFlags::NON_BLOCK));
+        true
+    }
+
+    fn get_status(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+
+    fn set_close_on_exec(&self, is_set: bool) -> bool {
+        self.flags.lock().set(OpenFlags::CLOEXEC, is_set);
+        true
+    }
+}
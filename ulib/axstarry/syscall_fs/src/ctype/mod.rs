@@ -2,6 +2,10 @@ pub mod dir;
 
 pub mod file;
 
+pub mod fuse;
+
+pub mod inotify;
+
 pub mod mount;
 
 pub mod pipe;
@@ -1,13 +1,17 @@
 extern crate alloc;
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
 use alloc::vec::Vec;
-use axfs::api::{lookup, path_exists, FileIO, Kstat, OpenFlags};
+use axerrno::{AxError, AxResult};
+use axfs::api::{lookup, FileIO, FuseConnection, Kstat, OpenFlags, UmountFlags};
+use bitflags::bitflags;
 use axlog::{debug, info};
+use axprocess::current_process;
 use axprocess::link::FilePath;
 use axsync::Mutex;
 use syscall_utils::{normal_file_mode, StMode, SyscallError};
 
-use super::{dir::new_dir, file::new_fd};
+use super::{dir::new_dir, file::new_fd, fuse::FuseDevFile};
 
 // use crate::{
 //     dir::new_dir,
@@ -17,28 +21,85 @@ use super::{dir::new_dir, file::new_fd};
 
 // use crate::link::{real_path};
 
+bitflags! {
+    /// mount(2) 的 flags 参数
+    #[derive(Clone, Copy, Debug)]
+    pub struct MountFlags: u32 {
+        /// 只读挂载
+        const MS_RDONLY = 1;
+        /// 忽略 setuid/setgid 位
+        const MS_NOSUID = 2;
+        /// 禁止访问设备文件
+        const MS_NODEV = 4;
+        /// 禁止执行程序
+        const MS_NOEXEC = 8;
+        /// 同步写入
+        const MS_SYNCHRONOUS = 16;
+        /// 修改已挂载文件系统的选项
+        const MS_REMOUNT = 32;
+        /// 不更新访问时间
+        const MS_NOATIME = 1024;
+        /// 不更新目录的访问时间
+        const MS_NODIRATIME = 2048;
+        /// 绑定挂载
+        const MS_BIND = 4096;
+        /// 按 relatime 策略更新访问时间
+        const MS_RELATIME = 1 << 21;
+        /// 每次访问都更新访问时间
+        const MS_STRICTATIME = 1 << 24;
+    }
+}
+
+/// 把 flags 中与文件系统相关的标志转换为挂载选项，再附加上用户传入的 `data`
+///
+/// `data` 中的选项在后，可以覆盖 flags 的设置。没有 `MS_RDONLY` 时为读写挂载，
+/// 因此 `MS_REMOUNT` 可以在只读与读写之间切换。
+pub fn mount_options(flags: MountFlags, data: &str) -> String {
+    let mut options = String::from(if flags.contains(MountFlags::MS_RDONLY) {
+        "ro"
+    } else {
+        "rw"
+    });
+    if flags.contains(MountFlags::MS_SYNCHRONOUS) {
+        options += ",sync";
+    }
+    if flags.contains(MountFlags::MS_NOATIME) {
+        options += ",noatime";
+    } else if flags.contains(MountFlags::MS_STRICTATIME) {
+        options += ",strictatime";
+    } else if flags.contains(MountFlags::MS_RELATIME) {
+        options += ",relatime";
+    }
+    if !data.is_empty() {
+        options += ",";
+        options += data;
+    }
+    options
+}
+
 /// 挂载的文件系统。
-/// 目前"挂载"的语义是，把一个文件当作文件系统读写
 pub struct MountedFs {
-    //pub inner: Arc<Mutex<FATFileSystem>>,
-    pub device: FilePath,
+    /// mount(2) 传入的来源，如 `/dev/vdb`、`tmpfs`，没有来源时为 `none`
+    pub source: String,
     pub mnt_dir: FilePath,
+    pub fs_type: String,
+    /// 是否真正挂载到了 VFS 中。未编译 FAT 支持时 vfat 只记录挂载信息
+    pub attached: bool,
 }
 
 impl MountedFs {
-    pub fn new(device: &FilePath, mnt_dir: &FilePath) -> Self {
-        assert!(
-            device.is_file() && mnt_dir.is_dir(),
-            "device must be a file and mnt_dir must be a dir"
-        );
+    pub fn new(source: &str, mnt_dir: &FilePath, fs_type: &str, attached: bool) -> Self {
+        assert!(mnt_dir.is_dir(), "mnt_dir must be a dir");
         Self {
-            device: device.clone(),
+            source: source.to_string(),
             mnt_dir: mnt_dir.clone(),
+            fs_type: fs_type.to_string(),
+            attached,
         }
     }
     #[allow(unused)]
-    pub fn device(&self) -> FilePath {
-        self.device.clone()
+    pub fn source(&self) -> &str {
+        &self.source
     }
 
     pub fn mnt_dir(&self) -> FilePath {
@@ -50,51 +111,147 @@ impl MountedFs {
 /// 注意启动时的文件系统不在这个 vec 里，它在 mod.rs 里。
 static MOUNTED: Mutex<Vec<MountedFs>> = Mutex::new(Vec::new());
 
-/// 挂载一个fatfs类型的设备
-pub fn mount_fat_fs(device_path: &FilePath, mount_path: &FilePath) -> bool {
-    // // device_path需要链接转换, mount_path不需要, 因为目前目录没有链接  // 暂时只有Open过的文件会加入到链接表，所以这里先不转换
-    // debug!("mounting {} to {}", device_path.path(), mount_path.path());
-    // if let Some(true_device_path) = real_path(device_path) {
-    if path_exists(mount_path.path()) {
-        MOUNTED.lock().push(MountedFs::new(device_path, mount_path));
-        info!("mounted {} to {}", device_path.path(), mount_path.path());
-        return true;
-    }
-    // }
+/// 把 `fs_type` 类型的文件系统挂载到 `mount_path`
+///
+/// `source` 为 mount(2) 传入的来源，`device_path` 为解析后的块设备或镜像文件路径，
+/// 只有 ext4、vfat 等基于磁盘的文件系统会用到。`options` 为逗号分隔的挂载选项。
+pub fn mount_fs(
+    source: &str,
+    device_path: Option<&FilePath>,
+    mount_path: &FilePath,
+    fs_type: &str,
+    options: &str,
+) -> AxResult {
+    let device = device_path.map_or("", |p| p.path());
+    let res = if fs_type == "fuse" || fs_type.starts_with("fuse.") {
+        fuse_connection(options).and_then(|conn| {
+            axfs::api::mount_fuse(&conn, source, mount_path.path(), fs_type, options)
+        })
+    } else {
+        axfs::api::mount(device, mount_path.path(), fs_type, options)
+    };
+    let attached = match res {
+        Ok(()) => true,
+        // 保留原来的行为：没有 FAT 支持时只记录挂载信息
+        Err(AxError::Unsupported) if fs_type == "vfat" => false,
+        Err(e) => {
+            info!(
+                "mount {} ({}) to {} failed: {:?}",
+                source,
+                fs_type,
+                mount_path.path(),
+                e
+            );
+            return Err(e);
+        }
+    };
+    MOUNTED
+        .lock()
+        .push(MountedFs::new(source, mount_path, fs_type, attached));
     info!(
-        "mount failed: {} to {}",
-        device_path.path(),
-        mount_path.path()
+        "mounted {} {} to {} ({})",
+        fs_type,
+        source,
+        mount_path.path(),
+        options
     );
-    false
+    Ok(())
 }
 
-/// 卸载一个fatfs类型的设备
-pub fn umount_fat_fs(mount_path: &FilePath) -> bool {
+/// 由挂载选项中的 `fd=` 找到守护进程打开的 `/dev/fuse` 文件，取得其 FUSE 连接
+fn fuse_connection(options: &str) -> AxResult<Arc<FuseConnection>> {
+    let fd = options
+        .split(',')
+        .find_map(|opt| opt.strip_prefix("fd="))
+        .and_then(|fd| fd.parse::<usize>().ok())
+        .ok_or(AxError::InvalidInput)?;
+    let process = current_process();
+    let fd_table = process.fd_manager.fd_table.lock();
+    let file = fd_table
+        .get(fd)
+        .and_then(|file| file.clone())
+        .ok_or(AxError::InvalidInput)?;
+    drop(fd_table);
+    let fuse_file = file
+        .as_any()
+        .downcast_ref::<FuseDevFile>()
+        .ok_or(AxError::InvalidInput)?;
+    Ok(fuse_file.conn())
+}
+
+/// 把目录 `source` 绑定挂载到 `mount_path`，即 `mount --bind`
+pub fn bind_fs(source: &FilePath, mount_path: &FilePath) -> AxResult {
+    if let Err(e) = axfs::api::bind_mount(source.path(), mount_path.path()) {
+        info!(
+            "bind {} to {} failed: {:?}",
+            source.path(),
+            mount_path.path(),
+            e
+        );
+        return Err(e);
+    }
+    info!("bound {} to {}", source.path(), mount_path.path());
+    Ok(())
+}
+
+/// 卸载挂载点 `mount_path` 最上层的文件系统，真正挂载的文件系统会从 VFS 中移除并释放其块设备
+///
+/// 同一目录上可以叠加多次挂载，此时卸载最后一次挂载的记录。绑定挂载等没有记录的挂载
+/// 直接交给 VFS 处理。文件系统仍在使用时返回 `ResourceBusy`，除非 `flags` 中指定了
+/// 强制或延迟卸载
+pub fn umount_fs(mount_path: &FilePath, flags: UmountFlags) -> AxResult {
     let mut mounted = MOUNTED.lock();
-    let mut i = 0;
-    while i < mounted.len() {
-        if mounted[i].mnt_dir().equal_to(mount_path) {
-            mounted.remove(i);
-            info!("umounted {}", mount_path.path());
-            return true;
-        }
-        i += 1;
+    let idx = mounted
+        .iter()
+        .rposition(|m| m.mnt_dir().equal_to(mount_path));
+    if let Some(i) = idx.filter(|&i| !mounted[i].attached) {
+        mounted.remove(i);
+        info!("umounted {}", mount_path.path());
+        return Ok(());
+    }
+    if let Err(e) = axfs::api::umount(mount_path.path(), flags) {
+        info!("umount failed: {}: {:?}", mount_path.path(), e);
+        return Err(e);
     }
-    info!("umount failed: {}", mount_path.path());
-    false
+    if let Some(i) = idx {
+        mounted.remove(i);
+    }
+    info!("umounted {}", mount_path.path());
+    Ok(())
 }
 
-/// 检查一个路径是否已经被挂载
-pub fn check_mounted(path: &FilePath) -> bool {
-    let mounted = MOUNTED.lock();
-    for m in mounted.iter() {
-        if path.start_with(&m.mnt_dir()) {
-            debug!("{} is mounted", path.path());
-            return true;
-        }
+/// tmpfs 中的文件和目录带有权限、所有者和时间戳，直接由节点的属性得到 stat
+///
+/// 不在 tmpfs 中的路径返回 None
+fn tmpfs_stat(path: &str) -> Option<Kstat> {
+    let node = lookup(path).ok()?;
+    let any = node.as_any();
+    if !any.is::<axfs::axfs_ramfs::DirNode>() && !any.is::<axfs::axfs_ramfs::FileNode>() {
+        return None;
     }
-    false
+    let attr = node.get_attr().ok()?;
+    let ty = if attr.is_dir() {
+        StMode::S_IFDIR
+    } else {
+        StMode::S_IFREG
+    };
+    Some(Kstat {
+        st_dev: 2,
+        st_mode: ty.bits() | attr.perm().bits() as u32,
+        st_nlink: attr.nlink() as u32,
+        st_uid: attr.uid(),
+        st_gid: attr.gid(),
+        st_size: attr.size(),
+        st_blksize: 4096,
+        st_blocks: attr.blocks(),
+        st_atime_sec: attr.atime().as_secs() as isize,
+        st_atime_nsec: attr.atime().subsec_nanos() as isize,
+        st_mtime_sec: attr.mtime().as_secs() as isize,
+        st_mtime_nsec: attr.mtime().subsec_nanos() as isize,
+        st_ctime_sec: attr.ctime().as_secs() as isize,
+        st_ctime_nsec: attr.ctime().subsec_nanos() as isize,
+        ..Kstat::default()
+    })
 }
 
 /// 根据给定的路径获取对应的文件stat
@@ -107,6 +264,9 @@ pub fn get_stat_in_fs(path: &FilePath) -> Result<Kstat, SyscallError> {
         || real_path.starts_with("/dev")
         || real_path.starts_with("/tmp")
     {
+        if let Some(stat) = tmpfs_stat(real_path) {
+            return Ok(stat);
+        }
         if path.is_dir() {
             ans.st_dev = 2;
             ans.st_mode = normal_file_mode(StMode::S_IFDIR).bits();
@@ -114,44 +274,33 @@ pub fn get_stat_in_fs(path: &FilePath) -> Result<Kstat, SyscallError> {
         }
         if let Ok(node) = lookup(path.path()) {
             let mut stat = Kstat::default();
-            stat.st_nlink = 1;
+            stat.st_nlink = node.get_attr().map_or(1, |attr| attr.nlink() as u32);
             // 先检查是否在vfs中存在对应文件
             // 判断是在哪个vfs中
             if node
                 .as_any()
                 .downcast_ref::<axfs::axfs_devfs::DirNode>()
                 .is_some()
-                || node
-                    .as_any()
-                    .downcast_ref::<axfs::axfs_ramfs::DirNode>()
-                    .is_some()
             {
                 stat.st_dev = 2;
                 stat.st_mode = normal_file_mode(StMode::S_IFDIR).bits();
                 return Ok(stat);
-            } else if node
-                .as_any()
-                .downcast_ref::<axfs::axfs_devfs::ZeroDev>()
-                .is_some()
-                || node
-                    .as_any()
-                    .downcast_ref::<axfs::axfs_devfs::NullDev>()
-                    .is_some()
-            {
-                stat.st_mode = normal_file_mode(StMode::S_IFCHR).bits();
-                return Ok(stat);
-            } else if node
-                .as_any()
-                .downcast_ref::<axfs::axfs_ramfs::FileNode>()
-                .is_some()
-            {
-                stat.st_mode = normal_file_mode(StMode::S_IFREG).bits();
-                stat.st_size = node.get_attr().unwrap().size();
+            } else if let Some(attr) = node.get_attr().ok().filter(|attr| {
+                attr.file_type().is_char_device() || attr.file_type().is_block_device()
+            }) {
+                // 字符设备与块设备（如loop设备），带上设备号
+                if attr.file_type().is_char_device() {
+                    stat.st_mode = normal_file_mode(StMode::S_IFCHR).bits();
+                } else {
+                    stat.st_mode = normal_file_mode(StMode::S_IFBLK).bits();
+                    stat.st_size = attr.size();
+                }
+                stat.st_rdev = attr.rdev().raw();
                 return Ok(stat);
             }
         }
     }
-    if !real_path.ends_with("/") && !real_path.ends_with("include") {
+    if !real_path.ends_with("/") {
         // 是文件
         return if let Ok(file) = new_fd(real_path.to_string(), 0.into()) {
             match file.get_stat() {
@@ -15,9 +15,15 @@ pub enum FsSyscallId {
     DUP = 23,
     DUP3 = 24,
     FCNTL64 = 25,
+    INOTIFY_INIT1 = 26,
+    INOTIFY_ADD_WATCH = 27,
+    INOTIFY_RM_WATCH = 28,
     IOCTL = 29,
+    FLOCK = 32,
+    MKNODAT = 33,
     MKDIRAT = 34,
     UNLINKAT = 35,
+    SYMLINKAT = 36,
     LINKAT = 37,
     UNMOUNT = 39,
     MOUNT = 40,
@@ -26,6 +32,7 @@ pub enum FsSyscallId {
     FACCESSAT = 48,
     CHDIR = 49,
     FCHMODAT = 53,
+    FCHOWNAT = 54,
     OPENAT = 56,
     CLOSE = 57,
     PIPE2 = 59,
@@ -1,19 +1,29 @@
 //! 对文件系统的管理，包括目录项的创建、文件权限设置等内容
-use axfs::api::{OpenFlags, Permissions};
-use axlog::{debug, error, info};
+extern crate alloc;
+use alloc::sync::Arc;
+use axerrno::AxError;
+use axfs::api::{
+    cancel_lock_wait, Access, DeviceId, FileIO, FileIOType, LockOwner, LockType, OpenFlags,
+    Permissions, RecordLock, LOOP_CLR_FD, LOOP_CTL_ADD, LOOP_CTL_GET_FREE, LOOP_CTL_REMOVE,
+    LOOP_SET_FD, TCFLSH, TCSBRK, TCXONC, TIOCSCTTY,
+};
+use axfs::fops::{DirEntry, Directory, FileType, OpenOptions};
+use axio::SeekFrom;
+use axlog::{debug, info};
 use core::{mem::transmute, ptr::copy_nonoverlapping};
 
 use axhal::mem::VirtAddr;
 use axprocess::{
     current_process,
-    link::{deal_with_path, FilePath, AT_FDCWD},
+    link::{resolve_path, FilePath, LookupFlags, AT_FDCWD, AT_SYMLINK_NOFOLLOW},
+    yield_now_task,
+};
+use syscall_utils::{
+    DirEnt, DirEntType, Fcntl64Cmd, Flock, StMode, SyscallError, SyscallResult, TimeSecs, F_RDLCK,
+    F_UNLCK, F_WRLCK, LOCK_EX, LOCK_NB, LOCK_SH, LOCK_UN,
 };
-use syscall_utils::{DirEnt, DirEntType, Fcntl64Cmd, SyscallError, SyscallResult, TimeSecs};
-
-use crate::{ctype::file::new_fd, FileDesc};
 
-extern crate alloc;
-use alloc::string::ToString;
+use crate::FileDesc;
 
 /// 功能：获取当前工作目录；
 /// 输入：
@@ -63,11 +73,7 @@ pub fn syscall_getcwd(buf: *mut u8, len: usize) -> SyscallResult {
 /// 返回值：成功执行，返回0。失败，返回-1。
 pub fn syscall_mkdirat(dir_fd: usize, path: *const u8, mode: u32) -> SyscallResult {
     // info!("signal module: {:?}", process_inner.signal_module.keys());
-    let path = if let Some(path) = deal_with_path(dir_fd, Some(path), true) {
-        path
-    } else {
-        return Err(SyscallError::EINVAL);
-    };
+    let path = resolve_path(dir_fd, path, LookupFlags::CREATE | LookupFlags::NOFOLLOW)?;
     debug!(
         "Into syscall_mkdirat. dirfd: {}, path: {:?}, mode: {}",
         dir_fd,
@@ -87,21 +93,45 @@ pub fn syscall_mkdirat(dir_fd: usize, path: *const u8, mode: u32) -> SyscallResu
     }
 }
 
+/// 33
+/// 创建设备文件，mode 的高位为文件类型，低位为权限
+/// dev 为 glibc 编码的设备号，打开时对应到同一设备号的已注册设备
+pub fn syscall_mknodat(dir_fd: usize, path: *const u8, mode: usize, dev: usize) -> SyscallResult {
+    let path = resolve_path(dir_fd, path, LookupFlags::CREATE | LookupFlags::NOFOLLOW)?;
+    debug!(
+        "Into syscall_mknodat. dirfd: {}, path: {:?}, mode: {:#o}, dev: {:#x}",
+        dir_fd,
+        path.path(),
+        mode,
+        dev
+    );
+    // 高4位为文件类型
+    let ty = match mode as u32 & 0o170000 {
+        m if m == StMode::S_IFCHR.bits() => FileType::CharDevice,
+        m if m == StMode::S_IFBLK.bits() => FileType::BlockDevice,
+        // 暂不支持创建普通文件、管道与套接字
+        _ => return Err(SyscallError::EPERM),
+    };
+    axfs::api::mknod(path.path(), ty, DeviceId::from_raw(dev as u64))?;
+    let perm = Permissions::from_bits_truncate(mode as u16 & 0o7777);
+    match axfs::api::set_permissions(path.path(), perm) {
+        // 不支持修改权限的文件系统仍然保留默认权限
+        Ok(()) | Err(AxError::Unsupported) => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
 /// 功能：切换工作目录；
 /// 输入：
 ///     - path：需要切换到的目录。
 /// 返回值：成功执行，返回0。失败，返回-1。
 pub fn syscall_chdir(path: *const u8) -> SyscallResult {
     // 从path中读取字符串
-    let path = if let Some(path) = deal_with_path(AT_FDCWD, Some(path), true) {
-        path
-    } else {
-        return Err(SyscallError::EINVAL);
-    };
+    let path = resolve_path(AT_FDCWD, path, LookupFlags::DIRECTORY)?;
     debug!("Into syscall_chdir. path: {:?}", path.path());
     match axfs::api::set_current_dir(path.path()) {
         Ok(_) => Ok(0),
-        Err(_) => Err(SyscallError::EINVAL),
+        Err(e) => Err(e.into()),
     }
 }
 
@@ -125,13 +155,15 @@ pub fn syscall_chdir(path: *const u8) -> SyscallResult {
 ///  2. d_off 和 d_reclen 同时存在的原因：
 ///       不同的dirent可以不按照顺序紧密排列
 pub fn syscall_getdents64(fd: usize, buf: *mut u8, len: usize) -> SyscallResult {
-    let path = if let Some(path) = deal_with_path(fd, None, true) {
-        path
-    } else {
-        return Err(SyscallError::EINVAL);
+    let process = current_process();
+    let file = match process.fd_manager.fd_table.lock().get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return Err(SyscallError::EBADF),
     };
+    if file.get_type() != FileIOType::DirDesc {
+        return Err(SyscallError::ENOTDIR);
+    }
 
-    let process = current_process();
     // 注意是否分配地址
     let start: VirtAddr = (buf as usize).into();
     let end = start + len;
@@ -139,46 +171,73 @@ pub fn syscall_getdents64(fd: usize, buf: *mut u8, len: usize) -> SyscallResult
         return Err(SyscallError::EFAULT);
     }
 
-    let entry_id_from = unsafe { (*(buf as *const DirEnt)).d_off };
-    if entry_id_from == -1 {
-        // 说明已经读完了
-        return Ok(0);
-    }
+    let mut opts = OpenOptions::new();
+    opts.read(true);
+    let mut dir = match Directory::open_dir(file.get_path().as_str(), &opts) {
+        Ok(dir) => dir,
+        Err(_) => return Err(SyscallError::ENOENT),
+    };
+    // 目录描述符的偏移量记录了上次读到的位置，由文件系统给出，不一定是目录项的下标
+    let pos = file.seek(SeekFrom::Current(0)).unwrap_or(0);
+    dir.seek(pos);
 
     let buf = unsafe { core::slice::from_raw_parts_mut(buf, len) };
-    let dir_iter = axfs::api::read_dir(path.path()).unwrap();
     let mut count = 0; // buf中已经写入的字节数
-
-    for (_, entry) in dir_iter.enumerate() {
-        let entry = entry.unwrap();
-        let mut name = entry.file_name();
-        name.push('\0');
-        let name = name.as_bytes();
-        let name_len = name.len();
-        let file_type = entry.file_type();
-        let entry_size = DirEnt::fixed_size() + name_len + 1;
-
-        // buf不够大，写不下新的entry
-        if count + entry_size > len {
-            debug!("buf not big enough");
-            return Ok(count as isize);
-        }
-        // 转换为DirEnt
-        let dirent: &mut DirEnt = unsafe { transmute(buf.as_mut_ptr().offset(count as isize)) };
-        // 设置定长部分
-        if file_type.is_dir() {
-            dirent.set_fixed_part(1, entry_size as i64, entry_size, DirEntType::DIR);
-        } else if file_type.is_file() {
-            dirent.set_fixed_part(1, entry_size as i64, entry_size, DirEntType::REG);
-        } else {
-            dirent.set_fixed_part(1, entry_size as i64, entry_size, DirEntType::UNKNOWN);
+    let mut pos = pos;
+    const EMPTY: DirEntry = DirEntry::default();
+    let mut entries = [EMPTY; 16];
+    'outer: loop {
+        let n = match dir.read_dir(&mut entries) {
+            Ok(n) => n,
+            Err(_) => return Err(SyscallError::EIO),
+        };
+        if n == 0 {
+            break;
         }
+        for entry in entries[..n].iter() {
+            let name = entry.name_as_bytes();
+            // 文件名以'\0'结尾，目录项长度按8字节对齐
+            let entry_size = (DirEnt::fixed_size() + name.len() + 1 + 7) & !7;
+
+            // buf不够大，写不下新的entry，下次从这一项继续
+            if count + entry_size > len {
+                debug!("buf not big enough");
+                if count == 0 {
+                    // 连一个目录项都放不下
+                    return Err(SyscallError::EINVAL);
+                }
+                break 'outer;
+            }
+            // 转换为DirEnt
+            let dirent: &mut DirEnt = unsafe { transmute(buf.as_mut_ptr().add(count)) };
+            let file_type = match entry.entry_type() {
+                FileType::Fifo => DirEntType::FIFO,
+                FileType::CharDevice => DirEntType::CHR,
+                FileType::Dir => DirEntType::DIR,
+                FileType::BlockDevice => DirEntType::BLK,
+                FileType::File => DirEntType::REG,
+                FileType::SymLink => DirEntType::LNK,
+                FileType::Socket => DirEntType::SOCK,
+            };
+            // 文件系统没有给出 inode 号时填 1，d_ino 为 0 的目录项会被 libc 跳过
+            let ino = match entry.ino() {
+                0 => 1,
+                ino => ino,
+            };
+            dirent.set_fixed_part(ino, entry.next_offset() as i64, entry_size, file_type);
 
-        // 写入文件名
-        unsafe { copy_nonoverlapping(name.as_ptr(), dirent.d_name.as_mut_ptr(), name_len) };
+            // 写入文件名
+            unsafe {
+                copy_nonoverlapping(name.as_ptr(), dirent.d_name.as_mut_ptr(), name.len());
+                *dirent.d_name.as_mut_ptr().add(name.len()) = 0;
+            }
 
-        count += entry_size;
+            count += entry_size;
+            pos = entry.next_offset();
+        }
     }
+
+    file.seek(SeekFrom::Start(pos)).ok();
     Ok(count as isize)
 }
 
@@ -204,8 +263,12 @@ pub fn syscall_renameat2(
     _new_path: *const u8,
     flags: usize,
 ) -> SyscallResult {
-    let old_path = deal_with_path(old_dirfd, Some(_old_path), false).unwrap();
-    let new_path = deal_with_path(new_dirfd, Some(_new_path), false).unwrap();
+    let old_path = resolve_path(old_dirfd, _old_path, LookupFlags::NOFOLLOW)?;
+    let new_path = resolve_path(
+        new_dirfd,
+        _new_path,
+        LookupFlags::NOFOLLOW | LookupFlags::CREATE,
+    )?;
 
     let proc_path = FilePath::new("/proc").unwrap();
     if old_path.start_with(&proc_path) || new_path.start_with(&proc_path) {
@@ -316,13 +379,190 @@ pub fn syscall_fcntl64(fd: usize, cmd: usize, arg: usize) -> SyscallResult {
                 Err(SyscallError::EINVAL)
             }
         }
+        Ok(
+            cmd @ (Fcntl64Cmd::F_GETLK
+            | Fcntl64Cmd::F_SETLK
+            | Fcntl64Cmd::F_SETLKW
+            | Fcntl64Cmd::F_OFD_GETLK
+            | Fcntl64Cmd::F_OFD_SETLK
+            | Fcntl64Cmd::F_OFD_SETLKW),
+        ) => {
+            // 等待锁时不能持有文件描述符表
+            drop(fd_table);
+            fcntl_lock(&file, cmd, arg as *mut Flock)
+        }
         _ => Err(SyscallError::EINVAL),
     }
 }
 
+/// 处理 fcntl 的记录锁命令
+///
+/// F_GETLK 等命令的锁属于进程，关闭该文件的任一文件描述符或进程退出时释放；
+/// F_OFD_GETLK 等命令的锁属于打开的文件，在打开的文件的所有文件描述符都关闭后释放
+fn fcntl_lock(file: &Arc<dyn FileIO>, cmd: Fcntl64Cmd, arg: *mut Flock) -> SyscallResult {
+    let process = current_process();
+    if process
+        .manual_alloc_type_for_lazy(arg as *const Flock)
+        .is_err()
+    {
+        return Err(SyscallError::EFAULT);
+    }
+    // 只有普通文件支持加锁
+    let Some(desc) = file.as_any().downcast_ref::<FileDesc>() else {
+        return Err(SyscallError::EINVAL);
+    };
+    let mut flock = unsafe { *arg };
+    let ofd = matches!(
+        cmd,
+        Fcntl64Cmd::F_OFD_GETLK | Fcntl64Cmd::F_OFD_SETLK | Fcntl64Cmd::F_OFD_SETLKW
+    );
+    let owner = if ofd {
+        // OFD 锁要求 l_pid 为 0
+        if flock.l_pid != 0 {
+            return Err(SyscallError::EINVAL);
+        }
+        desc.lock_owner()
+    } else {
+        LockOwner::Process(process.pid())
+    };
+    let ty = match flock.l_type {
+        F_RDLCK => LockType::Read,
+        F_WRLCK => LockType::Write,
+        F_UNLCK => LockType::Unlock,
+        _ => return Err(SyscallError::EINVAL),
+    };
+    let (start, end) = lock_range(desc, &flock)?;
+    let lock = RecordLock {
+        ty,
+        start,
+        end,
+        owner,
+    };
+    match cmd {
+        Fcntl64Cmd::F_GETLK | Fcntl64Cmd::F_OFD_GETLK => {
+            if ty == LockType::Unlock {
+                return Err(SyscallError::EINVAL);
+            }
+            let conflict = desc.file.lock().test_lock(&lock)?;
+            match conflict {
+                None => flock.l_type = F_UNLCK,
+                Some(held) => {
+                    flock.l_type = if held.ty == LockType::Write {
+                        F_WRLCK
+                    } else {
+                        F_RDLCK
+                    };
+                    flock.l_whence = 0;
+                    flock.l_start = held.start as i64;
+                    flock.l_len = if held.end == u64::MAX {
+                        0
+                    } else {
+                        (held.end - held.start) as i64
+                    };
+                    flock.l_pid = match held.owner {
+                        LockOwner::Process(pid) => pid as i32,
+                        LockOwner::File(_) => -1,
+                    };
+                }
+            }
+            unsafe { *arg = flock };
+            Ok(0)
+        }
+        _ => {
+            // 读锁要求文件以可读方式打开，写锁要求以可写方式打开
+            if (ty == LockType::Read && !desc.readable())
+                || (ty == LockType::Write && !desc.writable())
+            {
+                return Err(SyscallError::EBADF);
+            }
+            let wait = matches!(cmd, Fcntl64Cmd::F_SETLKW | Fcntl64Cmd::F_OFD_SETLKW);
+            loop {
+                let res = desc.file.lock().set_lock(&lock, wait);
+                match res {
+                    Err(AxError::WouldBlock) if wait => {}
+                    res => return res.map(|_| 0).map_err(SyscallError::from),
+                }
+                #[cfg(feature = "signal")]
+                if process.have_signals().is_some() {
+                    cancel_lock_wait(owner);
+                    return Err(SyscallError::EINTR);
+                }
+                yield_now_task();
+            }
+        }
+    }
+}
+
+/// 计算 flock 描述的锁的范围 [start, end)，end 为 u64::MAX 表示直到文件末尾
+fn lock_range(desc: &FileDesc, flock: &Flock) -> Result<(u64, u64), SyscallError> {
+    let base = match flock.l_whence {
+        // SEEK_SET
+        0 => 0,
+        // SEEK_CUR
+        1 => desc.file.lock().seek(SeekFrom::Current(0))? as i64,
+        // SEEK_END
+        2 => desc.file.lock().metadata()?.len() as i64,
+        _ => return Err(SyscallError::EINVAL),
+    };
+    let start = base
+        .checked_add(flock.l_start)
+        .ok_or(SyscallError::EOVERFLOW)?;
+    let (start, end) = if flock.l_len == 0 {
+        (start, None)
+    } else if flock.l_len > 0 {
+        let end = start
+            .checked_add(flock.l_len)
+            .ok_or(SyscallError::EOVERFLOW)?;
+        (start, Some(end))
+    } else {
+        // 负数长度表示 l_start 之前的范围，溢出时也在 0 之前
+        let begin = start
+            .checked_add(flock.l_len)
+            .ok_or(SyscallError::EINVAL)?;
+        (begin, Some(start))
+    };
+    if start < 0 {
+        return Err(SyscallError::EINVAL);
+    }
+    Ok((start as u64, end.map_or(u64::MAX, |end| end as u64)))
+}
+
+/// 32
+/// 对 fd 对应的整个文件加 BSD 风格的锁
+///
+/// 锁属于打开的文件，dup 与 fork 得到的文件描述符共享，在打开的文件的所有文件描述符都关闭后释放。
+/// operation 为 LOCK_SH、LOCK_EX 或 LOCK_UN，可以加上 LOCK_NB 表示有冲突时不等待
+pub fn syscall_flock(fd: usize, operation: usize) -> SyscallResult {
+    let process = current_process();
+    let file = match process.fd_manager.fd_table.lock().get(fd) {
+        Some(Some(file)) => Arc::clone(file),
+        _ => return Err(SyscallError::EBADF),
+    };
+    let Some(desc) = file.as_any().downcast_ref::<FileDesc>() else {
+        return Err(SyscallError::EINVAL);
+    };
+    let ty = match operation & !LOCK_NB {
+        LOCK_SH => LockType::Read,
+        LOCK_EX => LockType::Write,
+        LOCK_UN => LockType::Unlock,
+        _ => return Err(SyscallError::EINVAL),
+    };
+    loop {
+        let res = desc.file.lock().flock(desc.lock_owner(), ty);
+        match res {
+            Err(AxError::WouldBlock) if operation & LOCK_NB == 0 => {}
+            res => return res.map(|_| 0).map_err(SyscallError::from),
+        }
+        #[cfg(feature = "signal")]
+        if process.have_signals().is_some() {
+            return Err(SyscallError::EINTR);
+        }
+        yield_now_task();
+    }
+}
+
 /// 29
 /// 执行各种设备相关的控制功能
-/// todo: 未实现
 pub fn syscall_ioctl(fd: usize, request: usize, argp: *mut usize) -> SyscallResult {
     let process = current_process();
     let fd_table = process.fd_manager.fd_table.lock();
@@ -335,19 +575,41 @@ pub fn syscall_ioctl(fd: usize, request: usize, argp: *mut usize) -> SyscallResu
         debug!("fd {} is none", fd);
         return Err(SyscallError::EBADF);
     }
-    if process
-        .manual_alloc_for_lazy((argp as usize).into())
-        .is_err()
+    // 部分请求的参数是整数而不是地址
+    let arg_is_int = matches!(
+        request,
+        LOOP_SET_FD
+            | LOOP_CLR_FD
+            | LOOP_CTL_ADD
+            | LOOP_CTL_REMOVE
+            | LOOP_CTL_GET_FREE
+            | TIOCSCTTY
+            | TCFLSH
+            | TCXONC
+            | TCSBRK
+    );
+    if !arg_is_int
+        && process
+            .manual_alloc_for_lazy((argp as usize).into())
+            .is_err()
     {
         return Err(SyscallError::EFAULT); // 地址不合法
     }
 
     let file = fd_table[fd].clone().unwrap();
-    // if file.lock().ioctl(request, argp as usize).is_err() {
-    //     return -1;
-    // }
-    let _ = file.ioctl(request, argp as usize);
-    Ok(0)
+    drop(fd_table);
+    match file.ioctl(request, argp as usize) {
+        Ok(ret) => Ok(ret),
+        // 终端请求作用在不是终端的文件上
+        Err(AxError::Unsupported)
+            if file.get_type() != FileIOType::Tty && (0x5401..=0x5429).contains(&request) =>
+        {
+            Err(SyscallError::ENOTTY)
+        }
+        // 尚未支持的请求仍然假装成功，以兼容依赖这一行为的程序
+        Err(AxError::Unsupported) => Ok(0),
+        Err(e) => Err(e.into()),
+    }
 }
 
 /// 53
@@ -359,13 +621,40 @@ pub fn syscall_ioctl(fd: usize, request: usize, argp: *mut usize) -> SyscallResu
 /// path为绝对路径：
 ///     忽视dir_fd，直接根据path访问
 pub fn syscall_fchmodat(dir_fd: usize, path: *const u8, mode: usize) -> SyscallResult {
-    let file_path = deal_with_path(dir_fd, Some(path), false).unwrap();
-    axfs::api::metadata(file_path.path())
-        .map(|mut metadata| {
-            metadata.set_permissions(Permissions::from_bits_truncate(mode as u16));
-            Ok(0)
-        })
-        .unwrap_or_else(|_| Err(SyscallError::ENOENT))
+    let file_path = resolve_path(dir_fd, path, LookupFlags::empty())?;
+    let perm = Permissions::from_bits_truncate(mode as u16);
+    match axfs::api::set_permissions(file_path.path(), perm) {
+        Ok(()) => Ok(0),
+        // 不支持修改权限的文件系统仍然假装成功
+        Err(AxError::Unsupported) => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 54
+/// 修改文件的所有者和所属组
+/// owner 或 group 为 -1 时不修改对应的 ID
+/// flags 中含有 AT_SYMLINK_NOFOLLOW 时修改符号链接本身(lchown)
+pub fn syscall_fchownat(
+    dir_fd: usize,
+    path: *const u8,
+    owner: usize,
+    group: usize,
+    flags: usize,
+) -> SyscallResult {
+    let lookup_flags = if flags & AT_SYMLINK_NOFOLLOW != 0 {
+        LookupFlags::NOFOLLOW
+    } else {
+        LookupFlags::empty()
+    };
+    let file_path = resolve_path(dir_fd, path, lookup_flags)?;
+    let id = |id: usize| (id as u32 != u32::MAX).then_some(id as u32);
+    match axfs::api::set_owner(file_path.path(), id(owner), id(group), lookup_flags) {
+        Ok(()) => Ok(0),
+        // 不支持修改所有者的文件系统仍然假装成功
+        Err(AxError::Unsupported) => Ok(0),
+        Err(e) => Err(e.into()),
+    }
 }
 
 /// 48
@@ -379,38 +668,15 @@ pub fn syscall_fchmodat(dir_fd: usize, path: *const u8, mode: usize) -> SyscallR
 ///        respectively.
 /// 0: F_OK, 1: X_OK, 2: W_OK, 4: R_OK
 pub fn syscall_faccessat(dir_fd: usize, path: *const u8, mode: usize) -> SyscallResult {
-    // todo: 有问题，实际上需要考虑当前进程对应的用户UID和文件拥有者之间的关系
-    // 现在一律当作root用户处理
-    let file_path = deal_with_path(dir_fd, Some(path), false).unwrap();
-    axfs::api::metadata(file_path.path())
-        .map(|metadata| {
-            if mode == 0 {
-                //F_OK
-                // 文件存在返回0，不存在返回-1
-                if axfs::api::path_exists(file_path.path()) {
-                    Ok(0)
-                } else {
-                    Err(SyscallError::ENOENT)
-                }
-            } else {
-                // 逐位对比
-                let mut ret = true;
-                if mode & 1 != 0 {
-                    // X_OK
-                    ret &= metadata.permissions().contains(Permissions::OWNER_EXEC)
-                }
-                if mode & 2 != 0 {
-                    // W_OK
-                    ret &= metadata.permissions().contains(Permissions::OWNER_WRITE)
-                }
-                if mode & 4 != 0 {
-                    // R_OK
-                    ret &= metadata.permissions().contains(Permissions::OWNER_READ)
-                }
-                Ok(ret as isize - 1)
-            }
-        })
-        .unwrap_or_else(|_| Err(SyscallError::ENOENT))
+    let file_path = resolve_path(dir_fd, path, LookupFlags::empty())?;
+    if mode & !7 != 0 {
+        return Err(SyscallError::EINVAL);
+    }
+    // access 用实际用户 id 而不是有效用户 id 检查权限
+    let cred = current_process().cred.lock().real_credentials();
+    let mode = Access::from_bits_truncate(mode as u32);
+    axfs::api::access(file_path.path(), mode, &cred)?;
+    Ok(0)
 }
 
 /// 88
@@ -421,7 +687,7 @@ pub fn syscall_utimensat(
     dir_fd: usize,
     path: *const u8,
     times: *const TimeSecs,
-    _flags: usize,
+    flags: usize,
 ) -> SyscallResult {
     let process = current_process();
     // info!("dir_fd: {}, path: {}", dir_fd as usize, path as usize);
@@ -464,24 +730,34 @@ pub fn syscall_utimensat(
                 // }
                 fat_file.stat.lock().atime.set_as_utime(&new_atime);
                 fat_file.stat.lock().mtime.set_as_utime(&new_mtime);
+                let file = fat_file.file.lock();
+                match file.set_times(new_atime.to_utime(), new_mtime.to_utime()) {
+                    // 不支持修改时间戳的文件系统只记录在文件描述符中
+                    Ok(()) | Err(AxError::Unsupported) => {}
+                    Err(e) => return Err(e.into()),
+                }
             } else {
                 return Err(SyscallError::EPERM);
             }
         }
         Ok(0)
     } else {
-        let file_path = deal_with_path(dir_fd, Some(path), false).unwrap();
-        if !axfs::api::path_exists(file_path.path()) {
-            error!("Set time failed: file {} doesn't exist!", file_path.path());
-            if !axfs::api::path_exists(file_path.dir().unwrap()) {
-                return Err(SyscallError::ENOTDIR);
-            } else {
-                return Err(SyscallError::ENOENT);
-            }
+        let lookup_flags = if flags & AT_SYMLINK_NOFOLLOW != 0 {
+            LookupFlags::NOFOLLOW
+        } else {
+            LookupFlags::empty()
+        };
+        let file_path = resolve_path(dir_fd, path, lookup_flags)?;
+        match axfs::api::set_times(
+            file_path.path(),
+            new_atime.to_utime(),
+            new_mtime.to_utime(),
+            lookup_flags,
+        ) {
+            Ok(()) => Ok(0),
+            // 不支持修改时间戳的文件系统仍然假装成功
+            Err(AxError::Unsupported) => Ok(0),
+            Err(e) => Err(e.into()),
         }
-        let file = new_fd(file_path.path().to_string(), 0.into()).unwrap();
-        file.stat.lock().atime.set_as_utime(&new_atime);
-        file.stat.lock().mtime.set_as_utime(&new_mtime);
-        Ok(0)
     }
 }
@@ -0,0 +1,73 @@
+//! inotify 相关的系统调用，监视文件与目录的变化
+extern crate alloc;
+use alloc::sync::Arc;
+use axfs::api::{Inotify, InotifyMask, OpenFlags};
+use axprocess::{
+    current_process,
+    link::{resolve_path, LookupFlags, AT_FDCWD},
+};
+use syscall_utils::{SyscallError, SyscallResult};
+
+use crate::ctype::inotify::InotifyFile;
+
+/// inotify_init1 的参数，取值与 O_NONBLOCK 相同
+const IN_NONBLOCK: usize = 0x800;
+/// inotify_init1 的参数，取值与 O_CLOEXEC 相同
+const IN_CLOEXEC: usize = 0x80000;
+
+/// 26
+/// 创建一个 inotify 实例，返回对应的文件描述符
+///
+/// flags 可以包含 IN_NONBLOCK 与 IN_CLOEXEC
+pub fn syscall_inotify_init1(flags: usize) -> SyscallResult {
+    if flags & !(IN_NONBLOCK | IN_CLOEXEC) != 0 {
+        return Err(SyscallError::EINVAL);
+    }
+    let process = current_process();
+    let mut fd_table = process.fd_manager.fd_table.lock();
+    let fd = process
+        .alloc_fd(&mut fd_table)
+        .map_err(|_| SyscallError::EMFILE)?;
+    fd_table[fd] = Some(Arc::new(InotifyFile::new(OpenFlags::from(flags))));
+    Ok(fd as isize)
+}
+
+/// 27
+/// 监视 path 对应的文件，mask 为需要的事件与监视的选项。返回监视描述符
+///
+/// 再次监视同一个文件时返回相同的监视描述符，并替换(IN_MASK_ADD 时合并)其事件
+pub fn syscall_inotify_add_watch(fd: usize, path: *const u8, mask: u32) -> SyscallResult {
+    let inotify = find_inotify(fd)?;
+    let mask = InotifyMask::from_bits_truncate(mask);
+    let mut flags = LookupFlags::empty();
+    if mask.contains(InotifyMask::DONT_FOLLOW) {
+        flags |= LookupFlags::NOFOLLOW;
+    }
+    if mask.contains(InotifyMask::ONLYDIR) {
+        flags |= LookupFlags::DIRECTORY;
+    }
+    let file_path = resolve_path(AT_FDCWD, path, flags)?;
+    let wd = inotify.add_watch(file_path.path(), mask)?;
+    Ok(wd as isize)
+}
+
+/// 28
+/// 删除监视描述符 wd 对应的监视，之后会读到一个 IN_IGNORED 事件
+pub fn syscall_inotify_rm_watch(fd: usize, wd: i32) -> SyscallResult {
+    find_inotify(fd)?.rm_watch(wd)?;
+    Ok(0)
+}
+
+/// 获取 fd 对应的 inotify 实例，fd 不是 inotify 文件时返回 EINVAL
+fn find_inotify(fd: usize) -> Result<Arc<Inotify>, SyscallError> {
+    let process = current_process();
+    let fd_table = process.fd_manager.fd_table.lock();
+    match fd_table.get(fd) {
+        Some(Some(file)) => file
+            .as_any()
+            .downcast_ref::<InotifyFile>()
+            .map(InotifyFile::inotify)
+            .ok_or(SyscallError::EINVAL),
+        _ => Err(SyscallError::EBADF),
+    }
+}
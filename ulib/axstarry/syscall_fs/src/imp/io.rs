@@ -4,15 +4,16 @@ use alloc::string::ToString;
 use alloc::sync::Arc;
 use alloc::vec;
 use axerrno::AxError;
-use axfs::api::{FileIOType, OpenFlags};
+use axfs::api::{read_link, FileIO, FileIOType, OpenFlags, ResolvedPath};
 use axio::SeekFrom;
 use axlog::{debug, info};
 use axprocess::current_process;
-use axprocess::link::{create_link, deal_with_path, real_path};
+use axprocess::link::{resolve_node, resolve_path, LookupFlags};
 use syscall_utils::{IoVec, SyscallError, SyscallResult};
 
+use crate::ctype::fuse::FuseDevFile;
 use crate::ctype::pipe::make_pipe;
-use crate::ctype::{dir::new_dir, file::new_fd};
+use crate::ctype::{dir::open_dir, file::open_fd, file::FileDesc};
 /// 功能：从一个文件描述符中读取；
 /// 输入：
 ///     - fd：要读取文件的文件描述符。
@@ -76,6 +77,11 @@ pub fn syscall_read(fd: usize, buf: *mut u8, count: usize) -> SyscallResult {
     match file.read(buf) {
         Ok(len) => Ok(len as isize),
         Err(AxError::WouldBlock) => Err(SyscallError::EAGAIN),
+        // 被信号打断，或者后台进程读终端
+        Err(AxError::Interrupted) => Err(SyscallError::EINTR),
+        Err(AxError::Io) => Err(SyscallError::EIO),
+        // 缓冲区放不下一个 inotify 事件
+        Err(AxError::InvalidInput) => Err(SyscallError::EINVAL),
         Err(_) => Err(SyscallError::EPERM),
     }
 }
@@ -145,6 +151,8 @@ pub fn syscall_write(fd: usize, buf: *const u8, count: usize) -> SyscallResult {
         // socket with send half closed
         // TODO: send a SIGPIPE signal to the process
         Err(axerrno::AxError::ConnectionReset) => Err(SyscallError::EPIPE),
+        // 后台进程写终端
+        Err(AxError::Interrupted) => Err(SyscallError::EINTR),
         Err(_) => Err(SyscallError::EPERM),
     }
 }
@@ -278,6 +286,9 @@ pub fn syscall_dup3(fd: usize, new_fd: usize) -> SyscallResult {
     // }
     info!("dup3 fd {} to new fd {}", fd, new_fd);
     // 就算new_fd已经被打开了，也可以被重新替代掉
+    if new_fd != fd {
+        release_posix_locks(&fd_table[new_fd], process.pid());
+    }
     fd_table[new_fd] = fd_table[fd].clone();
     Ok(new_fd as isize)
 }
@@ -293,11 +304,23 @@ pub fn syscall_dup3(fd: usize, new_fd: usize) -> SyscallResult {
 /// 说明：如果打开的是一个目录，那么返回的文件描述符指向的是该目录的描述符。(后面会用到针对目录的文件描述符)
 /// flags: O_RDONLY: 0, O_WRONLY: 1, O_RDWR: 2, O_CREAT: 64, O_DIRECTORY: 65536
 pub fn syscall_openat(fd: usize, path: *const u8, flags: usize, _mode: u8) -> SyscallResult {
-    let force_dir = OpenFlags::from(flags).is_dir();
-    let path = if let Some(path) = deal_with_path(fd, Some(path), force_dir) {
-        path
-    } else {
-        return Err(SyscallError::EINVAL);
+    let open_flags = OpenFlags::from(flags);
+    let mut lookup_flags = LookupFlags::empty();
+    if open_flags.is_dir() {
+        lookup_flags |= LookupFlags::DIRECTORY;
+    }
+    if open_flags.contains(OpenFlags::CREATE) {
+        lookup_flags |= LookupFlags::CREATE;
+    }
+    // O_NOFOLLOW 时最后一个分量不能是符号链接，由路径解析检查并返回 ELOOP
+    if open_flags.contains(OpenFlags::NOFOLLOW) {
+        lookup_flags |= LookupFlags::NOFOLLOW | LookupFlags::OPEN;
+    }
+    // 打开解析得到的结点，而不是按解析出的路径再解析一次
+    let (path, node) = resolve_node(fd, path, lookup_flags)?;
+    let resolved = ResolvedPath {
+        path: path.path().to_string(),
+        node,
     };
     let process = current_process();
     let mut fd_table = process.fd_manager.fd_table.lock();
@@ -307,29 +330,48 @@ pub fn syscall_openat(fd: usize, path: *const u8, flags: usize, _mode: u8) -> Sy
         return Err(SyscallError::EMFILE);
     };
     debug!("allocated fd_num: {}", fd_num);
+    // 终端设备由终端子系统打开，以支持行规程与控制终端
+    if let Some(attr) = resolved.node.as_ref().and_then(|node| node.get_attr().ok()) {
+        if attr.file_type().is_char_device() {
+            if let Some(tty) = axprocess::tty::open(attr.rdev(), open_flags) {
+                fd_table[fd_num] = Some(tty?);
+                return Ok(fd_num as isize);
+            }
+            // 每次打开 /dev/fuse 都新建一个 FUSE 连接
+            if attr.rdev() == axfs::api::FUSE_DEVICE {
+                fd_table[fd_num] = Some(Arc::new(FuseDevFile::new(open_flags)));
+                return Ok(fd_num as isize);
+            }
+        }
+    }
     // 如果是DIR
     if path.is_dir() {
         debug!("open dir");
-        if let Ok(dir) = new_dir(path.path().to_string(), flags.into()) {
-            debug!("new dir_desc successfully allocated: {}", path.path());
-            fd_table[fd_num] = Some(Arc::new(dir));
-            Ok(fd_num as isize)
-        } else {
-            debug!("open dir failed");
-            Err(SyscallError::ENOENT)
+        match open_dir(&resolved, flags.into()) {
+            Ok(dir) => {
+                debug!("new dir_desc successfully allocated: {}", path.path());
+                fd_table[fd_num] = Some(Arc::new(dir));
+                Ok(fd_num as isize)
+            }
+            Err(e) => {
+                debug!("open dir failed: {:?}", e);
+                Err(SyscallError::from(e))
+            }
         }
     }
-    // 如果是FILE，注意若创建了新文件，需要添加链接
+    // 如果是FILE
     else {
         debug!("open file");
-        if let Ok(file) = new_fd(path.path().to_string(), flags.into()) {
-            debug!("new file_desc successfully allocated");
-            fd_table[fd_num] = Some(Arc::new(file));
-            let _ = create_link(&path, &path); // 不需要检查是否成功，因为如果成功，说明是新建的文件，如果失败，说明已经存在了
-            Ok(fd_num as isize)
-        } else {
-            debug!("open file failed");
-            Err(SyscallError::ENOENT)
+        match open_fd(&resolved, flags.into()) {
+            Ok(file) => {
+                debug!("new file_desc successfully allocated");
+                fd_table[fd_num] = Some(Arc::new(file));
+                Ok(fd_num as isize)
+            }
+            Err(e) => {
+                debug!("open file failed: {:?}", e);
+                Err(SyscallError::from(e))
+            }
         }
     }
 }
@@ -356,6 +398,7 @@ pub fn syscall_close(fd: usize) -> SyscallResult {
         return Err(SyscallError::EPERM);
     }
     // let file = process_inner.fd_manager.fd_table[fd].unwrap();
+    release_posix_locks(&fd_table[fd], process.pid());
     fd_table[fd] = None;
     // for i in 0..process_inner.fd_table.len() {
     //     if let Some(file) = process_inner.fd_table[i].as_ref() {
@@ -366,6 +409,16 @@ pub fn syscall_close(fd: usize) -> SyscallResult {
     Ok(0)
 }
 
+/// 关闭文件描述符时，释放进程在对应文件上持有的 POSIX 记录锁，即使还有其他文件描述符指向该文件
+fn release_posix_locks(file: &Option<Arc<dyn FileIO>>, pid: u64) {
+    if let Some(desc) = file
+        .as_ref()
+        .and_then(|file| file.as_any().downcast_ref::<FileDesc>())
+    {
+        desc.release_process_locks(pid);
+    }
+}
+
 /// 67
 /// pread64
 /// 从文件的指定位置读取数据，并且不改变文件的读写指针
@@ -471,28 +524,15 @@ pub fn syscall_readlinkat(
         }
     }
 
-    let path = deal_with_path(dir_fd, Some(path), false);
-    if path.is_none() {
-        return Err(SyscallError::ENOENT);
-    }
-    let path = path.unwrap();
-    if path.path() == "proc/self/exe" {
-        // 针对lmbench_all特判
-        let name = "/lmbench_all";
-        let len = bufsiz.min(name.len());
-        let slice = unsafe { core::slice::from_raw_parts_mut(buf, bufsiz) };
-        slice.copy_from_slice(&name.as_bytes()[..len]);
-        return Ok(len as isize);
-    }
-    if path.path().to_string() != real_path(&(path.path().to_string())) {
-        // 说明链接存在
-        let path = path.path();
-        let len = bufsiz.min(path.len());
-        let slice = unsafe { core::slice::from_raw_parts_mut(buf, len) };
-        slice.copy_from_slice(&path.as_bytes()[..len]);
-        return Ok(path.len() as isize);
-    }
-    Err(SyscallError::EINVAL)
+    let path = resolve_path(dir_fd, path, LookupFlags::NOFOLLOW)?;
+    let target = read_link(None, path.path())?;
+    if buf.is_null() {
+        return Ok(target.len() as isize);
+    }
+    let len = bufsiz.min(target.len());
+    let slice = unsafe { core::slice::from_raw_parts_mut(buf, len) };
+    slice.copy_from_slice(&target.as_bytes()[..len]);
+    Ok(len as isize)
 }
 /// 62
 /// 移动文件描述符的读写指针
@@ -505,10 +545,6 @@ pub fn syscall_lseek(fd: usize, offset: isize, whence: usize) -> SyscallResult {
     }
     let fd_table = process.fd_manager.fd_table.lock();
     if let Some(file) = fd_table[fd].as_ref() {
-        if file.get_type() == FileIOType::DirDesc {
-            debug!("fd is a dir");
-            return Err(SyscallError::EISDIR);
-        }
         let ans = if whence == 0 {
             // 即SEEK_SET
             file.seek(SeekFrom::Start(offset as u64))
@@ -532,9 +568,18 @@ pub fn syscall_lseek(fd: usize, offset: isize, whence: usize) -> SyscallResult {
     }
 }
 
+/// 81
+/// 把所有文件系统缓存的数据写回硬盘
+pub fn syscall_sync() -> SyscallResult {
+    if let Err(e) = axfs::api::sync() {
+        // sync 本身不会失败，只记录错误
+        info!("sync failed: {:?}", e);
+    }
+    Ok(0)
+}
+
 /// 82
-/// 写回硬盘
-#[allow(unused)]
+/// 把一个文件的数据和元数据写回硬盘
 pub fn syscall_fsync(fd: usize) -> SyscallResult {
     let process = current_process();
     if fd >= process.fd_manager.fd_table.lock().len() || fd < 3 {
@@ -543,8 +588,12 @@ pub fn syscall_fsync(fd: usize) -> SyscallResult {
     }
     let fd_table = process.fd_manager.fd_table.lock();
     if let Some(file) = fd_table[fd].clone() {
-        if file.flush().is_err() {}
-        Ok(0)
+        match file.flush() {
+            Ok(()) => Ok(0),
+            // 管道、socket 等不支持同步的文件
+            Err(AxError::Unsupported) | Err(AxError::InvalidInput) => Err(SyscallError::EINVAL),
+            Err(e) => Err(e.into()),
+        }
     } else {
         debug!("fd {} is none", fd);
         Err(SyscallError::EBADF)
@@ -4,7 +4,8 @@
 extern crate alloc;
 
 use axlog::debug;
-use axprocess::link::{create_link, deal_with_path, remove_link, FilePath};
+use axprocess::current_process;
+use axprocess::link::{raw_ptr_to_ref_str, resolve_path, FilePath, LookupFlags, AT_SYMLINK_FOLLOW};
 use syscall_utils::{SyscallError, SyscallResult};
 
 // Special value used to indicate openat should use the current working directory.
@@ -24,23 +25,56 @@ pub fn sys_linkat(
     old_path: *const u8,
     new_dir_fd: usize,
     new_path: *const u8,
-    _flags: usize,
+    flags: usize,
 ) -> SyscallResult {
-    let old_path = if let Some(path) = deal_with_path(old_dir_fd, Some(old_path), false) {
-        path
+    let old_flags = if flags & AT_SYMLINK_FOLLOW != 0 {
+        LookupFlags::empty()
     } else {
-        return Err(SyscallError::EINVAL);
-    };
-    let new_path = if let Some(path) = deal_with_path(new_dir_fd, Some(new_path), false) {
-        path
-    } else {
-        return Err(SyscallError::EINVAL);
+        LookupFlags::NOFOLLOW
     };
-    if create_link(&old_path, &new_path) {
-        Ok(0)
-    } else {
-        Err(SyscallError::EINVAL)
+    let old_path = resolve_path(old_dir_fd, old_path, old_flags)?;
+    let new_path = resolve_path(
+        new_dir_fd,
+        new_path,
+        LookupFlags::CREATE | LookupFlags::NOFOLLOW,
+    )?;
+    if let Err(e) = axfs::api::hard_link(old_path.path(), new_path.path()) {
+        debug!("link error: {:?}", e);
+        return Err(e.into());
     }
+    Ok(0)
+}
+
+/// 功能：创建符号链接；
+/// 输入：
+///     - target：符号链接指向的路径，原样保存，不要求存在。
+///     - new_dir_fd：符号链接所在的目录。
+///     - link_path：符号链接的名字。使用规则同linkat的new_path。
+/// 返回值：成功执行，返回0。失败，返回错误码。
+pub fn syscall_symlinkat(
+    target: *const u8,
+    new_dir_fd: usize,
+    link_path: *const u8,
+) -> SyscallResult {
+    let process = current_process();
+    if target.is_null()
+        || process
+            .manual_alloc_for_lazy((target as usize).into())
+            .is_err()
+    {
+        return Err(SyscallError::EFAULT);
+    }
+    let target = unsafe { raw_ptr_to_ref_str(target) };
+    let link_path = resolve_path(
+        new_dir_fd,
+        link_path,
+        LookupFlags::CREATE | LookupFlags::NOFOLLOW,
+    )?;
+    if let Err(e) = axfs::api::symlink(target, link_path.path()) {
+        debug!("symlink error: {:?}", e);
+        return Err(e.into());
+    }
+    Ok(0)
 }
 
 /// 功能：移除指定文件的链接(可用于删除文件)；
@@ -50,7 +84,7 @@ pub fn sys_linkat(
 ///     - flags：可设置为0或AT_REMOVEDIR。
 /// 返回值：成功执行，返回0。失败，返回-1。
 pub fn syscall_unlinkat(dir_fd: usize, path: *const u8, flags: usize) -> SyscallResult {
-    let path = deal_with_path(dir_fd, Some(path), false).unwrap();
+    let path = resolve_path(dir_fd, path, LookupFlags::NOFOLLOW)?;
 
     if path.start_with(&FilePath::new("/proc").unwrap()) {
         return Ok(-1);
@@ -58,9 +92,9 @@ pub fn syscall_unlinkat(dir_fd: usize, path: *const u8, flags: usize) -> Syscall
 
     // unlink file
     if flags == 0 {
-        if let None = remove_link(&path) {
-            debug!("unlink file error");
-            return Err(SyscallError::EINVAL);
+        if let Err(e) = axfs::api::remove_file(path.path()) {
+            debug!("unlink file error: {:?}", e);
+            return Err(e.into());
         }
     }
     // remove dir
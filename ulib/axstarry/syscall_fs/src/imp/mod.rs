@@ -2,6 +2,7 @@ extern crate alloc;
 
 mod ctl;
 mod epoll;
+mod inotify;
 mod io;
 mod link;
 mod mount;
@@ -9,6 +10,7 @@ mod poll;
 mod stat;
 pub use ctl::*;
 pub use epoll::*;
+pub use inotify::*;
 pub use io::*;
 pub use link::*;
 pub use mount::*;
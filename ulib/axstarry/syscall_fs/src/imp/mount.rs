@@ -1,11 +1,15 @@
 use axprocess::{
     current_process,
-    link::{deal_with_path, raw_ptr_to_ref_str, AT_FDCWD},
+    link::{raw_ptr_to_ref_str, resolve_path, LookupFlags, AT_FDCWD},
 };
+use axerrno::AxError;
+use axfs::api::UmountFlags;
 use syscall_utils::{SyscallError, SyscallResult};
 
-// use super::{deal_with_path, AT_FDCWD};
-use crate::ctype::mount::{check_mounted, mount_fat_fs, umount_fat_fs};
+/// umount2 的 UMOUNT_NOFOLLOW 标志：不跟随挂载点路径最后的符号链接
+const UMOUNT_NOFOLLOW: u32 = 8;
+
+use crate::ctype::mount::{bind_fs, mount_fs, mount_options, umount_fs, MountFlags};
 extern crate alloc;
 use alloc::string::ToString;
 use axlog::debug;
@@ -14,41 +18,89 @@ use axlog::debug;
 ///   - special: 挂载设备；
 ///   - dir: 挂载点；       经过实测，发现dir可以是绝对路径，也可以是相对路径，甚至可以是 . 或 ..
 ///   - fs_type: 挂载的文件系统类型；
-///   - flags: 挂载参数；
+///   - flags: 挂载参数，MS_REMOUNT 时修改已挂载文件系统的选项；
 ///   - data: 传递给文件系统的字符串参数，可为NULL；
 /// 返回值：成功返回0，失败返回-1
 pub fn syscall_mount(
     special: *const u8,
     dir: *const u8,
     fs_type: *const u8,
-    _flags: usize,
-    _data: *const u8,
+    flags: usize,
+    data: *const u8,
 ) -> SyscallResult {
-    let device_path = deal_with_path(AT_FDCWD, Some(special), false).unwrap();
-    // 这里dir必须以"/"结尾，但在shell中输入时，不需要以"/"结尾
-    let mount_path = deal_with_path(AT_FDCWD, Some(dir), true).unwrap();
+    // 挂载点不存在时会在下面被创建
+    let mount_path = resolve_path(AT_FDCWD, dir, LookupFlags::DIRECTORY | LookupFlags::CREATE)?;
+    let flags = MountFlags::from_bits_truncate(flags as u32);
 
     let process = current_process();
+    let mut data_str = "".to_string();
+    if !data.is_null() {
+        if process
+            .manual_alloc_for_lazy((data as usize).into())
+            .is_err()
+        {
+            return Err(SyscallError::EINVAL);
+        }
+        // data可以为NULL, 必须判断, 否则会panic, 发生LoadPageFault
+        data_str = unsafe { raw_ptr_to_ref_str(data) }.to_string();
+    }
+    let options = mount_options(flags, &data_str);
+
+    // 重新挂载时忽略 special 和 fs_type
+    if flags.contains(MountFlags::MS_REMOUNT) {
+        return match axfs::api::remount(mount_path.path(), &options) {
+            Ok(()) => Ok(0),
+            Err(e) => {
+                debug!("remount {} error: {:?}", mount_path.path(), e);
+                Err(e.into())
+            }
+        };
+    }
+
+    // 绑定挂载时忽略 fs_type 和 data
+    if flags.contains(MountFlags::MS_BIND) {
+        if special.is_null()
+            || process
+                .manual_alloc_for_lazy((special as usize).into())
+                .is_err()
+        {
+            return Err(SyscallError::EFAULT);
+        }
+        let source_path = resolve_path(AT_FDCWD, special, LookupFlags::DIRECTORY)?;
+        return match bind_fs(&source_path, &mount_path) {
+            Ok(()) => Ok(0),
+            Err(e) => {
+                debug!("bind mount error: {:?}", e);
+                Err(e.into())
+            }
+        };
+    }
+
     if process
         .manual_alloc_for_lazy((fs_type as usize).into())
         .is_err()
     {
         return Err(SyscallError::EINVAL);
     }
-
     let fs_type = unsafe { raw_ptr_to_ref_str(fs_type).to_string() };
-    let mut _data_str = "".to_string();
-    if !_data.is_null() {
+    // proc、tmpfs 等文件系统的来源可以为 NULL
+    let mut source = "none".to_string();
+    if !special.is_null() {
         if process
-            .manual_alloc_for_lazy((_data as usize).into())
+            .manual_alloc_for_lazy((special as usize).into())
             .is_err()
         {
-            return Err(SyscallError::EINVAL);
+            return Err(SyscallError::EFAULT);
         }
-        // data可以为NULL, 必须判断, 否则会panic, 发生LoadPageFault
-        _data_str = unsafe { raw_ptr_to_ref_str(_data) }.to_string();
+        source = unsafe { raw_ptr_to_ref_str(special) }.to_string();
     }
-    if device_path.is_dir() {
+    // 来源不是文件时(如 tmpfs 的 "none")，没有对应的设备路径
+    let device_path = if special.is_null() {
+        None
+    } else {
+        resolve_path(AT_FDCWD, special, LookupFlags::empty()).ok()
+    };
+    if device_path.as_ref().is_some_and(|p| p.is_dir()) {
         debug!("device_path should not be a dir");
         return Err(SyscallError::EPERM);
     }
@@ -65,49 +117,58 @@ pub fn syscall_mount(
         }
     }
 
-    if fs_type != "vfat" {
-        debug!("fs_type can only be vfat.");
-        return Err(SyscallError::EPERM);
-    }
-    // 检查挂载点路径是否存在
-    if !axfs::api::path_exists(mount_path.path()) {
-        debug!("mount path not exist");
-        return Err(SyscallError::EPERM);
-    }
-    // 查挂载点是否已经被挂载
-    if check_mounted(&mount_path) {
-        debug!("mount path includes mounted fs");
-        return Err(SyscallError::EPERM);
-    }
-    // 挂载
-    if !mount_fat_fs(&device_path, &mount_path) {
-        debug!("mount error");
-        return Err(SyscallError::EPERM);
+    // 挂载点下可以再挂载，同一目录也可以叠加挂载，后挂载的覆盖先挂载的
+    // 解析来源、选择文件系统类型并挂载
+    match mount_fs(
+        &source,
+        device_path.as_ref(),
+        &mount_path,
+        &fs_type,
+        &options,
+    ) {
+        Ok(()) => Ok(0),
+        Err(e) => {
+            debug!("mount {} error: {:?}", fs_type, e);
+            Err(e.into())
+        }
     }
-
-    Ok(0)
 }
 
 /// 功能：卸载文件系统；
 /// 输入：指定卸载目录，卸载参数；
 /// 返回值：成功返回0，失败返回-1；
 pub fn syscall_umount(dir: *const u8, flags: usize) -> SyscallResult {
-    let mount_path = deal_with_path(AT_FDCWD, Some(dir), true).unwrap();
-
-    if flags != 0 {
-        debug!("flags unimplemented");
-        return Err(SyscallError::EPERM);
+    let nofollow = flags as u32 & UMOUNT_NOFOLLOW != 0;
+    let lookup_flags = if nofollow {
+        LookupFlags::NOFOLLOW
+    } else {
+        LookupFlags::DIRECTORY
+    };
+    let mount_path = resolve_path(AT_FDCWD, dir, lookup_flags)?;
+    // UMOUNT_NOFOLLOW 时挂载点不能是符号链接
+    if nofollow && !mount_path.is_dir() {
+        debug!("mount path is not a dir");
+        return Err(SyscallError::EINVAL);
     }
 
+    let flags = flags as u32 & !UMOUNT_NOFOLLOW;
+    let Some(flags) = UmountFlags::from_bits(flags) else {
+        debug!("unsupported umount flags {:#x}", flags);
+        return Err(SyscallError::EINVAL);
+    };
+
     // 检查挂载点路径是否存在
     if !axfs::api::path_exists(mount_path.path()) {
         debug!("mount path not exist");
         return Err(SyscallError::EPERM);
     }
     // 从挂载点中删除
-    if !umount_fat_fs(&mount_path) {
-        debug!("umount error");
-        return Err(SyscallError::EPERM);
+    if let Err(e) = umount_fs(&mount_path, flags) {
+        debug!("umount error: {:?}", e);
+        return Err(match e {
+            AxError::ResourceBusy => SyscallError::EBUSY,
+            _ => SyscallError::EPERM,
+        });
     }
 
     Ok(0)
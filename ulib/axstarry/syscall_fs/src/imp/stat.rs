@@ -1,13 +1,13 @@
 //! 获取文件系统状态信息
 //!
 
-use axfs::api::{FileIOType, Kstat};
+use axfs::api::{read_link, FileIOType, Kstat};
 use axlog::{debug, error, info};
 use axprocess::{
     current_process,
-    link::{deal_with_path, FilePath, AT_FDCWD},
+    link::{resolve_path, FilePath, LookupFlags, AT_FDCWD, AT_SYMLINK_NOFOLLOW},
 };
-use syscall_utils::{get_fs_stat, FsStat, SyscallError, SyscallResult};
+use syscall_utils::{get_fs_stat, normal_file_mode, FsStat, StMode, SyscallError, SyscallResult};
 
 use crate::ctype::mount::get_stat_in_fs;
 
@@ -16,7 +16,7 @@ pub fn syscall_fstat(fd: usize, kst: *mut Kstat) -> SyscallResult {
     let process = current_process();
     let fd_table = process.fd_manager.fd_table.lock();
 
-    if fd >= fd_table.len() || fd < 3 {
+    if fd >= fd_table.len() {
         debug!("fd {} is out of range", fd);
         return Err(SyscallError::EPERM);
     }
@@ -25,7 +25,7 @@ pub fn syscall_fstat(fd: usize, kst: *mut Kstat) -> SyscallResult {
         return Err(SyscallError::EPERM);
     }
     let file = fd_table[fd].clone().unwrap();
-    if file.get_type() != FileIOType::FileDesc {
+    if !matches!(file.get_type(), FileIOType::FileDesc | FileIOType::Tty) {
         debug!("fd {} is not a file", fd);
         return Err(SyscallError::EPERM);
     }
@@ -45,9 +45,37 @@ pub fn syscall_fstat(fd: usize, kst: *mut Kstat) -> SyscallResult {
 }
 
 /// 获取文件状态信息，但是给出的是目录 fd 和相对路径。
-pub fn syscall_fstatat(dir_fd: usize, path: *const u8, kst: *mut Kstat) -> SyscallResult {
-    let file_path = deal_with_path(dir_fd, Some(path), false).unwrap();
+///
+/// flags 中含有 AT_SYMLINK_NOFOLLOW 时不跟随最后的符号链接，获取的是链接本身的信息(lstat)
+pub fn syscall_fstatat(
+    dir_fd: usize,
+    path: *const u8,
+    kst: *mut Kstat,
+    flags: usize,
+) -> SyscallResult {
+    let nofollow = flags & AT_SYMLINK_NOFOLLOW != 0;
+    let lookup_flags = if nofollow {
+        LookupFlags::NOFOLLOW
+    } else {
+        LookupFlags::empty()
+    };
+    let file_path = resolve_path(dir_fd, path, lookup_flags)?;
     info!("path : {}", file_path.path());
+    if nofollow {
+        if let Ok(target) = read_link(None, file_path.path()) {
+            let mut stat = Kstat::default();
+            stat.st_mode = normal_file_mode(StMode::S_IFLNK).bits();
+            stat.st_nlink = axfs::api::resolve_path(None, file_path.path(), lookup_flags)
+                .ok()
+                .and_then(|resolved| resolved.node?.get_attr().ok())
+                .map_or(1, |attr| attr.nlink() as u32);
+            stat.st_size = target.len() as u64;
+            unsafe {
+                *kst = stat;
+            }
+            return Ok(0);
+        }
+    }
     match get_stat_in_fs(&file_path) {
         Ok(stat) => unsafe {
             *kst = stat;
@@ -62,7 +90,7 @@ pub fn syscall_fstatat(dir_fd: usize, path: *const u8, kst: *mut Kstat) -> Sysca
 
 /// 获取文件系统的信息
 pub fn syscall_statfs(path: *const u8, stat: *mut FsStat) -> SyscallResult {
-    let file_path = deal_with_path(AT_FDCWD, Some(path), false).unwrap();
+    let file_path = resolve_path(AT_FDCWD, path, LookupFlags::empty())?;
     if file_path.equal_to(&FilePath::new("/").unwrap()) {
         // 目前只支持访问根目录文件系统的信息
         unsafe {
@@ -24,6 +24,7 @@ pub fn fs_syscall(syscall_id: fs_syscall_id::FsSyscallId, args: [usize; 6]) -> S
         PIPE2 => syscall_pipe2(args[0] as *mut u32, args[1]),
         DUP => syscall_dup(args[0]),
         DUP3 => syscall_dup3(args[0], args[1]),
+        MKNODAT => syscall_mknodat(args[0], args[1] as *const u8, args[2], args[3]),
         MKDIRAT => syscall_mkdirat(args[0], args[1] as *const u8, args[2] as u32),
         CHDIR => syscall_chdir(args[0] as *const u8),
         GETDENTS64 => syscall_getdents64(args[0], args[1] as *mut u8, args[2] as usize),
@@ -46,13 +47,20 @@ pub fn fs_syscall(syscall_id: fs_syscall_id::FsSyscallId, args: [usize; 6]) -> S
         READV => syscall_readv(args[0] as usize, args[1] as *mut IoVec, args[2] as usize),
         WRITEV => syscall_writev(args[0] as usize, args[1] as *const IoVec, args[2] as usize),
         FCNTL64 => syscall_fcntl64(args[0] as usize, args[1] as usize, args[2] as usize),
+        INOTIFY_INIT1 => syscall_inotify_init1(args[0]),
+        INOTIFY_ADD_WATCH => {
+            syscall_inotify_add_watch(args[0], args[1] as *const u8, args[2] as u32)
+        }
+        INOTIFY_RM_WATCH => syscall_inotify_rm_watch(args[0], args[1] as i32),
         FSTATAT => syscall_fstatat(
             args[0] as usize,
             args[1] as *const u8,
             args[2] as *mut Kstat,
+            args[3] as usize,
         ),
         STATFS => syscall_statfs(args[0] as *const u8, args[1] as *mut FsStat),
         FCHMODAT => syscall_fchmodat(args[0] as usize, args[1] as *const u8, args[2] as usize),
+        FCHOWNAT => syscall_fchownat(args[0], args[1] as *const u8, args[2], args[3], args[4]),
         FACCESSAT => syscall_faccessat(args[0] as usize, args[1] as *const u8, args[2] as usize),
         LSEEK => syscall_lseek(args[0] as usize, args[1] as isize, args[2] as usize),
         PREAD64 => syscall_pread64(
@@ -74,14 +82,14 @@ pub fn fs_syscall(syscall_id: fs_syscall_id::FsSyscallId, args: [usize; 6]) -> S
             args[2] as *mut usize,
             args[3] as usize,
         ),
-        FSYNC => Ok(0),
+        FSYNC => syscall_fsync(args[0]),
         FTRUNCATE64 => {
             syscall_ftruncate64(args[0] as usize, args[1] as usize)
             // 0
         }
         IOCTL => syscall_ioctl(args[0] as usize, args[1] as usize, args[2] as *mut usize),
-        // 不做处理即可
-        SYNC => Ok(0),
+        FLOCK => syscall_flock(args[0], args[1]),
+        SYNC => syscall_sync(),
         COPYFILERANGE => syscall_copyfilerange(
             args[0],
             args[1] as *mut usize,
@@ -98,6 +106,7 @@ pub fn fs_syscall(syscall_id: fs_syscall_id::FsSyscallId, args: [usize; 6]) -> S
             args[4],
         ),
         UNLINKAT => syscall_unlinkat(args[0], args[1] as *const u8, args[2] as usize),
+        SYMLINKAT => syscall_symlinkat(args[0] as *const u8, args[1], args[2] as *const u8),
         UTIMENSAT => syscall_utimensat(
             args[0],
             args[1] as *const u8,
@@ -75,6 +75,9 @@ pub fn syscall_mmap(
         if fd >= process.fd_manager.fd_table.lock().len() as i32 || fd < 0 {
             return Err(SyscallError::EINVAL);
         }
+        if offset % axhal::mem::PAGE_SIZE_4K != 0 {
+            return Err(SyscallError::EINVAL);
+        }
         let file = match &process.fd_manager.fd_table.lock()[fd as usize] {
             // 文件描述符表里面存的是文件描述符，这很合理罢
             Some(file) => alloc::boxed::Box::new(
@@ -89,7 +92,12 @@ pub fn syscall_mmap(
             None => return Err(SyscallError::EINVAL),
         };
 
-        let backend = MemBackend::new(file, offset as u64);
+        // 共享映射直接映射文件的页缓存，各进程看到的是同一份数据
+        let backend = if flags.contains(MMAPFlags::MAP_SHARED) {
+            MemBackend::new_shared(file, offset as u64)
+        } else {
+            MemBackend::new(file, offset as u64)
+        };
         process
             .memory_set
             .lock()
@@ -111,7 +119,9 @@ pub fn syscall_munmap(start: usize, len: usize) -> SyscallResult {
 
 pub fn syscall_msync(start: usize, len: usize) -> SyscallResult {
     let process = current_process();
-    process.memory_set.lock().msync(start.into(), len);
+    if process.memory_set.lock().msync(start.into(), len).is_err() {
+        return Err(SyscallError::EIO);
+    }
 
     Ok(0)
 }
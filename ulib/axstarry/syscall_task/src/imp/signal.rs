@@ -2,10 +2,13 @@
 //! 与信号处理相关的系统调用
 
 use axhal::cpu::this_cpu_id;
+use axhal::KERNEL_PROCESS_ID;
 use axlog::{debug, info};
-use axprocess::{current_process, current_task, yield_now_task};
+use axprocess::{current_process, current_task, yield_now_task, PID2PC};
 use axsignal::action::SigAction;
-use axsignal::signal_no::SignalNo;
+use axsignal::signal_no::{SignalNo, MAX_SIG_NUM};
+extern crate alloc;
+use alloc::vec::Vec;
 
 use syscall_utils::{SigMaskFlag, SyscallError, SyscallResult, SIGSET_SIZE_IN_BYTE};
 
@@ -163,17 +166,48 @@ pub fn syscall_sigprocmask(
 
 /// 向pid指定的进程发送信号
 ///
+/// pid 大于0时发送给该进程，为0时发送给当前进程组，为-1时发送给除内核进程外的所有进程，
+/// 小于-1时发送给进程组 -pid。signum 为0时只检查目标是否存在
+///
 /// 由于处理信号的单位在线程上，所以若进程中有多个线程，则会发送给主线程
 pub fn syscall_kill(pid: isize, signum: isize) -> SyscallResult {
-    if pid > 0 && signum > 0 {
-        // 不关心是否成功
-        let _ = axprocess::signal::send_signal_to_process(pid, signum);
-        Ok(0)
-    } else if pid == 0 {
-        Err(SyscallError::ESRCH)
-    } else {
-        Err(SyscallError::EINVAL)
+    if signum < 0 || signum as usize > MAX_SIG_NUM {
+        return Err(SyscallError::EINVAL);
     }
+    let targets: Vec<u64> = {
+        let pid2pc = PID2PC.lock();
+        let alive = pid2pc
+            .values()
+            .filter(|process| process.pid() != KERNEL_PROCESS_ID && !process.get_zombie());
+        match pid {
+            pid if pid > 0 => alive
+                .filter(|process| process.pid() == pid as u64)
+                .map(|process| process.pid())
+                .collect(),
+            -1 => alive.map(|process| process.pid()).collect(),
+            pid => {
+                let pgid = if pid == 0 {
+                    current_process().pgid()
+                } else {
+                    (-pid) as u64
+                };
+                alive
+                    .filter(|process| process.pgid() == pgid)
+                    .map(|process| process.pid())
+                    .collect()
+            }
+        }
+    };
+    if targets.is_empty() {
+        return Err(SyscallError::ESRCH);
+    }
+    if signum > 0 {
+        for pid in targets {
+            // 不关心是否成功
+            let _ = axprocess::signal::send_signal_to_process(pid as isize, signum);
+        }
+    }
+    Ok(0)
 }
 
 /// 向tid指定的线程发送信号
@@ -4,11 +4,12 @@ use core::time::Duration;
 use axconfig::TASK_STACK_SIZE;
 use axhal::time::current_time;
 use axprocess::{
+    cred::NGROUPS_MAX,
     current_process, current_task, exit_current_task,
     flags::{CloneFlags, WaitStatus},
     futex::clear_wait,
-    link::{deal_with_path, raw_ptr_to_ref_str, AT_FDCWD},
-    set_child_tid, sleep_now_task, wait_pid, yield_now_task, Process, PID2PC,
+    link::{raw_ptr_to_ref_str, resolve_path, LookupFlags, AT_FDCWD},
+    set_child_tid, sleep_now_task, wait_job_event, wait_pid, yield_now_task, Process, PID2PC,
 };
 
 // use axtask::{
@@ -16,7 +17,6 @@ use axprocess::{
 //     AxTaskRef,
 // };
 use axlog::info;
-use axtask::TaskId;
 use syscall_utils::{SyscallError, SyscallResult};
 extern crate alloc;
 use alloc::{string::ToString, sync::Arc, vec::Vec};
@@ -25,8 +25,6 @@ use syscall_utils::{RLimit, TimeSecs, WaitFlags, RLIMIT_AS, RLIMIT_NOFILE, RLIMI
 #[cfg(feature = "signal")]
 use axsignal::signal_no::SignalNo;
 
-#[cfg(feature = "signal")]
-use axprocess::signal::SignalModule;
 // pub static TEST_FILTER: Mutex<BTreeMap<String, usize>> = Mutex::new(BTreeMap::new());
 
 pub fn syscall_exit(exit_code: i32) -> ! {
@@ -80,11 +78,7 @@ pub fn syscall_exec(
     mut args: *const usize,
     mut envp: *const usize,
 ) -> SyscallResult {
-    let path = deal_with_path(AT_FDCWD, Some(path), false);
-    if path.is_none() {
-        return Err(SyscallError::EINVAL);
-    }
-    let path = path.unwrap();
+    let path = resolve_path(AT_FDCWD, path, LookupFlags::empty())?;
     if path.is_dir() {
         return Err(SyscallError::EISDIR);
     }
@@ -131,10 +125,14 @@ pub fn syscall_exec(
     // 清空futex信号列表
     clear_wait(curr_process.pid(), true);
     let argc = args_vec.len();
-    if curr_process.exec(path, args_vec, envs_vec).is_err() {
-        exit_current_task(0);
+    match curr_process.exec(path, args_vec, envs_vec) {
+        Ok(()) => Ok(argc as isize),
+        Err(e) => match SyscallError::from(e) {
+            // 没有执行权限时原程序不受影响，返回错误
+            SyscallError::EACCES => Err(SyscallError::EACCES),
+            _ => exit_current_task(0),
+        },
     }
-    Ok(argc as isize)
 }
 
 pub fn syscall_clone(
@@ -185,6 +183,16 @@ pub fn syscall_wait4(pid: isize, exit_code_ptr: *mut i32, option: WaitFlags) ->
                         return Err(SyscallError::EPERM);
                     }
                     WaitStatus::Running => {
+                        // 子进程被停止或者继续运行时也可以报告
+                        let stopped = option.contains(WaitFlags::WIMTRACED);
+                        let continued = option.contains(WaitFlags::WCONTINUED);
+                        if stopped || continued {
+                            if let Some(pid) =
+                                unsafe { wait_job_event(pid, exit_code_ptr, stopped, continued) }
+                            {
+                                return Ok(pid as isize);
+                            }
+                        }
                         if option.contains(WaitFlags::WNOHANG) {
                             // 不予等待，直接返回0
                             return Ok(0);
@@ -324,23 +332,133 @@ pub fn syscall_umask(new_mask: i32) -> SyscallResult {
     Ok(current_process().fd_manager.set_mask(new_mask) as isize)
 }
 
-/// 获取用户 id。在实现多用户权限前默认为最高权限
+/// 获取实际用户 id
 pub fn syscall_getuid() -> SyscallResult {
-    Ok(0)
+    Ok(current_process().cred.lock().uid as isize)
 }
 
-/// 获取有效用户 id，即相当于哪个用户的权限。在实现多用户权限前默认为最高权限
+/// 获取有效用户 id，即检查权限时使用的用户 id
 pub fn syscall_geteuid() -> SyscallResult {
-    Ok(0)
+    Ok(current_process().cred.lock().euid as isize)
 }
 
-/// 获取用户组 id。在实现多用户权限前默认为最高权限
+/// 获取实际用户组 id
 pub fn syscall_getgid() -> SyscallResult {
-    Ok(0)
+    Ok(current_process().cred.lock().gid as isize)
 }
 
-/// 获取有效用户组 id，即相当于哪个用户的权限。在实现多用户权限前默认为最高权限
+/// 获取有效用户组 id，即检查权限时使用的用户组 id
 pub fn syscall_getegid() -> SyscallResult {
+    Ok(current_process().cred.lock().egid as isize)
+}
+
+/// 把参数转换为 id，-1 表示不修改
+fn id_arg(id: usize) -> Option<u32> {
+    (id as u32 != u32::MAX).then_some(id as u32)
+}
+
+/// 146
+/// 设置用户 id，特权进程同时设置实际、有效与保存的用户 id
+pub fn syscall_setuid(uid: usize) -> SyscallResult {
+    current_process().cred.lock().set_uid(uid as u32)?;
+    Ok(0)
+}
+
+/// 144
+/// 设置用户组 id，规则与 setuid 相同
+pub fn syscall_setgid(gid: usize) -> SyscallResult {
+    current_process().cred.lock().set_gid(gid as u32)?;
+    Ok(0)
+}
+
+/// 147
+/// 分别设置实际、有效与保存的用户 id，为 -1 的不变
+pub fn syscall_setresuid(ruid: usize, euid: usize, suid: usize) -> SyscallResult {
+    current_process()
+        .cred
+        .lock()
+        .set_resuid(id_arg(ruid), id_arg(euid), id_arg(suid))?;
+    Ok(0)
+}
+
+/// 149
+/// 分别设置实际、有效与保存的用户组 id，为 -1 的不变
+pub fn syscall_setresgid(rgid: usize, egid: usize, sgid: usize) -> SyscallResult {
+    current_process()
+        .cred
+        .lock()
+        .set_resgid(id_arg(rgid), id_arg(egid), id_arg(sgid))?;
+    Ok(0)
+}
+
+/// 把三个 id 写入用户空间
+fn write_ids(ids: [u32; 3], ptrs: [*mut u32; 3]) -> SyscallResult {
+    let process = current_process();
+    for (id, ptr) in ids.into_iter().zip(ptrs) {
+        if ptr.is_null() || process.manual_alloc_type_for_lazy(ptr).is_err() {
+            return Err(SyscallError::EFAULT);
+        }
+        unsafe { *ptr = id };
+    }
+    Ok(0)
+}
+
+/// 148
+/// 获取实际、有效与保存的用户 id
+pub fn syscall_getresuid(ruid: *mut u32, euid: *mut u32, suid: *mut u32) -> SyscallResult {
+    let cred = current_process().cred.lock().clone();
+    write_ids([cred.uid, cred.euid, cred.suid], [ruid, euid, suid])
+}
+
+/// 150
+/// 获取实际、有效与保存的用户组 id
+pub fn syscall_getresgid(rgid: *mut u32, egid: *mut u32, sgid: *mut u32) -> SyscallResult {
+    let cred = current_process().cred.lock().clone();
+    write_ids([cred.gid, cred.egid, cred.sgid], [rgid, egid, sgid])
+}
+
+/// 158
+/// 获取附属用户组。size 为0时只返回数量，小于数量时返回 EINVAL
+pub fn syscall_getgroups(size: usize, list: *mut u32) -> SyscallResult {
+    let process = current_process();
+    let groups = process.cred.lock().groups.clone();
+    if size == 0 {
+        return Ok(groups.len() as isize);
+    }
+    if size < groups.len() {
+        return Err(SyscallError::EINVAL);
+    }
+    let end = unsafe { list.add(groups.len()) };
+    if process
+        .manual_alloc_range_for_lazy((list as usize).into(), (end as usize).into())
+        .is_err()
+    {
+        return Err(SyscallError::EFAULT);
+    }
+    unsafe { core::slice::from_raw_parts_mut(list, groups.len()) }.copy_from_slice(&groups);
+    Ok(groups.len() as isize)
+}
+
+/// 159
+/// 设置附属用户组，需要超级用户权限
+pub fn syscall_setgroups(size: usize, list: *const u32) -> SyscallResult {
+    if size > NGROUPS_MAX {
+        return Err(SyscallError::EINVAL);
+    }
+    let process = current_process();
+    let groups = if size == 0 {
+        Vec::new()
+    } else {
+        let end = unsafe { list.add(size) };
+        if process
+            .manual_alloc_range_for_lazy((list as usize).into(), (end as usize).into())
+            .is_err()
+        {
+            return Err(SyscallError::EFAULT);
+        }
+        unsafe { core::slice::from_raw_parts(list, size) }.to_vec()
+    };
+    process.cred.lock().set_groups(groups)?;
     Ok(0)
 }
 
@@ -348,42 +466,83 @@ pub fn syscall_gettid() -> SyscallResult {
     Ok(current_task().id().as_u64() as isize)
 }
 
+/// 创建一个新的会话，调用者成为新会话和新进程组的首进程，并且没有控制终端
+///
+/// 调用者已经是进程组首进程时返回 EPERM
 pub fn syscall_setsid() -> SyscallResult {
     let process = current_process();
-    let task = current_task();
-
-    let task_id = task.id().as_u64();
-
-    // 当前 process 已经是 process group leader
-    if process.pid() == task_id {
+    let pid = process.pid();
+    if process.pgid() == pid
+        || PID2PC
+            .lock()
+            .values()
+            .any(|p| p.pid() != pid && p.pgid() == pid)
+    {
         return Err(SyscallError::EPERM);
     }
+    process.set_sid(pid);
+    process.set_pgid(pid);
+    Ok(pid as isize)
+}
 
-    // 从当前 process 的 thread group 中移除 calling thread
-    process.tasks.lock().retain(|t| t.id().as_u64() != task_id);
-
-    // 新建 process group 并加入
-    let new_process = Process::new(
-        TaskId::new().as_u64(),
-        process.get_parent(),
-        process.memory_set.clone(),
-        process.get_heap_bottom(),
-        process.fd_manager.fd_table.lock().clone(),
-    );
-    #[cfg(feature = "signal")]
-    new_process
-        .signal_modules
-        .lock()
-        .insert(task_id, SignalModule::init_signal(None));
+/// 154
+/// 将进程 pid 加入进程组 pgid，pid 为0时指当前进程，pgid 为0时指与 pid 相同的进程组
+///
+/// 只能修改当前进程或其子进程，且新的进程组必须在同一会话中
+pub fn syscall_setpgid(pid: isize, pgid: isize) -> SyscallResult {
+    if pid < 0 || pgid < 0 {
+        return Err(SyscallError::EINVAL);
+    }
+    let curr_process = current_process();
+    let target = if pid == 0 || pid as u64 == curr_process.pid() {
+        Arc::clone(&curr_process)
+    } else {
+        match curr_process
+            .children
+            .lock()
+            .iter()
+            .find(|child| child.pid() == pid as u64 && !child.get_zombie())
+        {
+            Some(child) => Arc::clone(child),
+            None => return Err(SyscallError::ESRCH),
+        }
+    };
+    let pgid = if pgid == 0 { target.pid() } else { pgid as u64 };
+    // 会话首进程不能改变进程组，也不能移动到其他会话
+    if target.sid() == target.pid() || target.sid() != curr_process.sid() {
+        return Err(SyscallError::EPERM);
+    }
+    if pgid != target.pid()
+        && !PID2PC
+            .lock()
+            .values()
+            .any(|p| p.pgid() == pgid && p.sid() == target.sid())
+    {
+        return Err(SyscallError::EPERM);
+    }
+    target.set_pgid(pgid);
+    Ok(0)
+}
 
-    new_process.tasks.lock().push(task.as_task_ref().clone());
-    task.set_leader(true);
-    task.set_process_id(new_process.pid());
+/// 找到 pid 对应的进程，pid 为0时指当前进程
+fn find_process(pid: isize) -> Result<Arc<Process>, SyscallError> {
+    if pid == 0 {
+        return Ok(current_process());
+    }
+    match PID2PC.lock().get(&(pid as u64)) {
+        Some(process) if pid > 0 => Ok(Arc::clone(process)),
+        _ => Err(SyscallError::ESRCH),
+    }
+}
 
-    // 修改 PID2PC
-    PID2PC
-        .lock()
-        .insert(new_process.pid(), Arc::new(new_process));
+/// 155
+/// 获取进程 pid 所在的进程组，pid 为0时指当前进程
+pub fn syscall_getpgid(pid: isize) -> SyscallResult {
+    Ok(find_process(pid)?.pgid() as isize)
+}
 
-    Ok(task_id as isize)
+/// 156
+/// 获取进程 pid 所在的会话，pid 为0时指当前进程
+pub fn syscall_getsid(pid: isize) -> SyscallResult {
+    Ok(find_process(pid)?.sid() as isize)
 }
@@ -74,6 +74,22 @@ pub fn task_syscall(syscall_id: task_syscall_id::TaskSyscallId, args: [usize; 6]
         GETEUID => syscall_geteuid(),
         GETGID => syscall_getgid(),
         GETEGID => syscall_getegid(),
+        SETUID => syscall_setuid(args[0]),
+        SETGID => syscall_setgid(args[0]),
+        SETRESUID => syscall_setresuid(args[0], args[1], args[2]),
+        SETRESGID => syscall_setresgid(args[0], args[1], args[2]),
+        GETRESUID => syscall_getresuid(
+            args[0] as *mut u32,
+            args[1] as *mut u32,
+            args[2] as *mut u32,
+        ),
+        GETRESGID => syscall_getresgid(
+            args[0] as *mut u32,
+            args[1] as *mut u32,
+            args[2] as *mut u32,
+        ),
+        GETGROUPS => syscall_getgroups(args[0], args[1] as *mut u32),
+        SETGROUPS => syscall_setgroups(args[0], args[1] as *const u32),
         GETTID => syscall_gettid(),
         #[cfg(feature = "futex")]
         FUTEX => syscall_futex(
@@ -97,6 +113,9 @@ pub fn task_syscall(syscall_id: task_syscall_id::TaskSyscallId, args: [usize; 6]
             args[2] as *mut ITimerVal,
         ),
         GETTIMER => syscall_gettimer(args[0] as usize, args[1] as *mut ITimerVal),
+        SETPGID => syscall_setpgid(args[0] as isize, args[1] as isize),
+        GETPGID => syscall_getpgid(args[0] as isize),
+        GETSID => syscall_getsid(args[0] as isize),
         SETSID => syscall_setsid(),
         GETRUSAGE => syscall_getrusage(args[0] as i32, args[1] as *mut TimeVal),
         UMASK => syscall_umask(args[0] as i32),
@@ -23,7 +23,18 @@ pub enum TaskSyscallId {
     SCHED_GETSCHEDULER = 120,
     SCHED_SETAFFINITY = 122,
     SCHED_GETAFFINITY = 123,
+    SETGID = 144,
+    SETUID = 146,
+    SETRESUID = 147,
+    GETRESUID = 148,
+    SETRESGID = 149,
+    GETRESGID = 150,
+    SETPGID = 154,
+    GETPGID = 155,
+    GETSID = 156,
     SETSID = 157,
+    GETGROUPS = 158,
+    SETGROUPS = 159,
     GETRUSAGE = 165,
     UMASK = 166,
     PRCTL = 167,
@@ -4,6 +4,7 @@ use axhal::{
 };
 use bitflags::*;
 use core::panic;
+use core::time::Duration;
 pub const NSEC_PER_SEC: usize = 1_000_000_000;
 bitflags! {
     /// 指定 sys_wait4 的选项
@@ -102,6 +103,15 @@ impl TimeSecs {
             } // 设为指定时间
         }
     }
+
+    /// 转换为设置文件时间戳时使用的时间，UTIME_OMIT 表示不修改，返回 None
+    pub fn to_utime(&self) -> Option<Duration> {
+        match self.tv_nsec {
+            UTIME_NOW => Some(Duration::from_nanos(current_time_nanos())),
+            UTIME_OMIT => None,
+            _ => Some(Duration::new(self.tv_sec as u64, self.tv_nsec as u32)),
+        }
+    }
 }
 
 bitflags! {
@@ -274,11 +284,55 @@ numeric_enum_macro::numeric_enum! {
         F_GETFL = 3,
         /// 设置 flags 信息
         F_SETFL = 4,
+        /// 获取与给定记录锁冲突的锁
+        F_GETLK = 5,
+        /// 设置或释放进程持有的记录锁，有冲突时返回 EAGAIN
+        F_SETLK = 6,
+        /// 设置或释放进程持有的记录锁，有冲突时等待
+        F_SETLKW = 7,
+        /// 获取与给定记录锁冲突的锁，锁属于打开的文件
+        F_OFD_GETLK = 36,
+        /// 设置或释放打开的文件持有的记录锁，有冲突时返回 EAGAIN
+        F_OFD_SETLK = 37,
+        /// 设置或释放打开的文件持有的记录锁，有冲突时等待
+        F_OFD_SETLKW = 38,
         /// 复制 fd，然后设置 cloexec 信息，即 exec 成功时删除该 fd
         F_DUPFD_CLOEXEC = 1030,
     }
 }
 
+/// fcntl 的记录锁命令使用的结构体，即 `struct flock`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Flock {
+    /// 锁的类型：F_RDLCK、F_WRLCK 或 F_UNLCK
+    pub l_type: i16,
+    /// l_start 的起点：SEEK_SET、SEEK_CUR 或 SEEK_END
+    pub l_whence: i16,
+    /// 锁住的范围的起始位置
+    pub l_start: i64,
+    /// 锁住的范围的长度，0 表示直到文件末尾，负数表示 l_start 之前的范围
+    pub l_len: i64,
+    /// 持有冲突的锁的进程，锁属于打开的文件时为 -1
+    pub l_pid: i32,
+}
+/// 共享锁(读锁)
+pub const F_RDLCK: i16 = 0;
+/// 排他锁(写锁)
+pub const F_WRLCK: i16 = 1;
+/// 释放锁
+pub const F_UNLCK: i16 = 2;
+
+// flock 的操作
+/// 共享锁
+pub const LOCK_SH: usize = 1;
+/// 排他锁
+pub const LOCK_EX: usize = 2;
+/// 有冲突时不等待，返回 EWOULDBLOCK
+pub const LOCK_NB: usize = 4;
+/// 释放锁
+pub const LOCK_UN: usize = 8;
+
 /// syscall_info 用到的 结构体
 #[repr(C)]
 #[derive(Debug)]
@@ -373,7 +427,7 @@ numeric_enum_macro::numeric_enum! {
 pub struct DirEnt {
     /// 索引结点号
     pub d_ino: u64,
-    /// 到下一个dirent的偏移
+    /// 下一个dirent的位置，可用于lseek/seekdir
     pub d_off: i64,
     /// 当前dirent的长度
     pub d_reclen: u16,
@@ -410,9 +464,9 @@ impl DirEnt {
         8 + 8 + 2 + 1
     }
     /// 设置定长部分
-    pub fn set_fixed_part(&mut self, ino: u64, _off: i64, reclen: usize, type_: DirEntType) {
+    pub fn set_fixed_part(&mut self, ino: u64, off: i64, reclen: usize, type_: DirEntType) {
         self.d_ino = ino;
-        self.d_off = -1;
+        self.d_off = off;
         self.d_reclen = reclen as u16;
         self.d_type = type_ as u8;
     }
@@ -476,6 +530,10 @@ bitflags! {
         const S_IFDIR = 1 << 14;
         /// 是字符设备
         const S_IFCHR = 1 << 13;
+        /// 是块设备
+        const S_IFBLK = (1 << 14) | (1 << 13);
+        /// 是符号链接
+        const S_IFLNK = (1 << 15) | (1 << 13);
         /// 是否设置 uid/gid/sticky
         //const S_ISUID = 1 << 14;
         //const S_ISGID = 1 << 13;
@@ -1,14 +1,24 @@
 use axerrno::AxResult;
-use axfs::api::{File, OpenFlags};
+use axfs::api::{File, OpenFlags, OpenOptions, ResolvedPath};
 
 /// 若使用多次new file打开同名文件，那么不同new file之间读写指针不共享，但是修改的内容是共享的
 pub fn new_file(path: &str, flags: &OpenFlags) -> AxResult<File> {
+    open_options(flags).open(path)
+}
+
+/// 打开路径解析时得到的文件，不再按路径重新解析
+pub fn new_file_resolved(resolved: &ResolvedPath, flags: &OpenFlags) -> AxResult<File> {
+    open_options(flags).open_resolved(resolved)
+}
+
+/// 由打开标志得到打开文件的选项
+fn open_options(flags: &OpenFlags) -> OpenOptions {
     let mut file = File::options();
     file.read(flags.readable());
     file.write(flags.writable());
     file.create(flags.creatable());
     file.create_new(flags.new_creatable());
-    file.open(path)
+    file
 }
 /// 在完成一次系统调用之后，恢复全局目录
 pub fn init_current_dir() {